@@ -0,0 +1,114 @@
+//! `false-sharing`: every thread adds its chunk into its own slot of a
+//! shared Vec, one store per number
+//!
+//! The slots are different variables, but the CPU caches whole lines
+//! (64 bytes; see the locality chapter). Four adjacent u64 slots share one
+//! line, so each store invalidates that line in the other cores' caches
+//! and the line bounces between cores: the threads share nothing in the
+//! code and still contend like they share a lock. Padding each slot to its
+//! own line removes the contention without changing a line of the loop.
+//! Summing into a local and storing once avoids the question entirely.
+
+use crate::reduce::chunks;
+use crate::{benchmark, maybe_sleep};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::Duration;
+
+/// One slot per cache line. 128, not 64: x86 CPUs fetch lines in adjacent
+/// pairs, so 64 still leaves neighbours contending (crossbeam's
+/// `CachePadded` does the same)
+#[repr(align(128))]
+#[derive(Default)]
+struct Padded(AtomicU64);
+
+/// A slot each thread adds into; `AtomicU64` so the store in every
+/// iteration can't be hoisted out of the loop into a register
+trait Slot: Default + Sync {
+    fn cell(&self) -> &AtomicU64;
+}
+
+impl Slot for AtomicU64 {
+    fn cell(&self) -> &AtomicU64 {
+        self
+    }
+}
+
+impl Slot for Padded {
+    fn cell(&self) -> &AtomicU64 {
+        &self.0
+    }
+}
+
+/// Thread i adds its chunk of 1..=n into `slots[i]`, one load and store
+/// per number. No read-modify-write instruction: the cost measured is the
+/// cache line moving, not an atomic add
+fn sum_into_slots<S: Slot>(n: u64, num_threads: usize) -> u64 {
+    let chunks = chunks(1..=n, num_threads);
+    let slots: Vec<S> = chunks.iter().map(|_| S::default()).collect();
+    thread::scope(|s| {
+        for (chunk, slot) in chunks.into_iter().zip(&slots) {
+            s.spawn(move || {
+                maybe_sleep();
+                let cell = slot.cell();
+                for i in chunk {
+                    let sum = cell.load(Ordering::Relaxed).wrapping_add(i);
+                    cell.store(sum, Ordering::Relaxed);
+                }
+            });
+        }
+    });
+    slots
+        .iter()
+        .map(|slot| slot.cell().load(Ordering::Relaxed))
+        .fold(0, u64::wrapping_add)
+}
+
+/// The fix that needs no padding: sum in a local, store once
+fn sum_into_locals(n: u64, num_threads: usize) -> u64 {
+    let chunks = chunks(1..=n, num_threads);
+    let slots: Vec<AtomicU64> = chunks.iter().map(|_| AtomicU64::default()).collect();
+    thread::scope(|s| {
+        for (chunk, slot) in chunks.into_iter().zip(&slots) {
+            s.spawn(move || {
+                maybe_sleep();
+                let mut sum = 0_u64;
+                for i in chunk {
+                    sum = sum.wrapping_add(std::hint::black_box(i));
+                }
+                slot.store(sum, Ordering::Relaxed);
+            });
+        }
+    });
+    slots
+        .iter()
+        .map(|slot| slot.load(Ordering::Relaxed))
+        .fold(0, u64::wrapping_add)
+}
+
+/// The three layouts on `num_threads` threads, then how much the padding
+/// saved
+pub fn run(n: u64, num_threads: usize) {
+    println!(
+        "False sharing: {} threads, one slot each ({} bytes apart adjacent, {} padded)",
+        num_threads,
+        std::mem::size_of::<AtomicU64>(),
+        std::mem::size_of::<Padded>()
+    );
+    let adjacent = benchmark("Adjacent slots", || {
+        sum_into_slots::<AtomicU64>(n, num_threads)
+    });
+    let padded = benchmark("Padded slots", || sum_into_slots::<Padded>(n, num_threads));
+    let local = benchmark("Local sum, one store", || sum_into_locals(n, num_threads));
+
+    println!("{}", "-".repeat(70));
+    let ratio = |slow: Duration, fast: Duration| slow.as_secs_f64() / fast.as_secs_f64();
+    println!(
+        "Adjacent / padded: {:.2}x (the cost of sharing cache lines)",
+        ratio(adjacent, padded)
+    );
+    println!(
+        "Padded / local:    {:.2}x (the cost of a store per number)",
+        ratio(padded, local)
+    );
+}
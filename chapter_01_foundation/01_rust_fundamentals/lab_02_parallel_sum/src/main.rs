@@ -32,6 +32,135 @@
 //! cargo run --release     # Run performance comparison
 //! ```
 //!
+//! ## Extension: Profiling
+//! `--profile [PATH]` records a CPU profile of the whole run with pprof and
+//! writes a flamegraph SVG (default `flamegraph.svg`, see `shared/profiling.rs`):
+//! ```bash
+//! cargo run --release -- --observe --sleep-ms 0 --profile
+//! # Flamegraph: flamegraph.svg (2841 samples)
+//! ```
+//! Wide `sum_range` boxes are the real work; compare how much time goes to
+//! `Mutex::lock`, channel sends and rayon's scheduler next to them.
+//!
+//! ## Extension: Command Line and CSV
+//! The settings are clap options now, not environment variables
+//! (`cargo run --release -- --help`): `--n`, `--observe`, `--sleep-ms`,
+//! and for the table `--threads 1,2,4,8` (the thread and task counts),
+//! `--impl mutex,channel,...` (which versions) and `--repeat R` (runs per
+//! row; the summary takes the fastest). `--csv` prints nothing but one
+//! `impl,threads,run,millis` row per run, ready for a speedup plot:
+//! ```text
+//! $ cargo run --release -- --observe --sleep-ms 0 --n 50000000 \
+//!       --impl mutex,channel --threads 1,2,4 --repeat 3 --csv > runs.csv
+//! impl,threads,run,millis
+//! mutex,1,1,44.912
+//! mutex,1,2,43.807
+//! ...
+//! ```
+//! Speedup at t threads is the best 1-thread time over the best t-thread
+//! time; with `--repeat`, take the minimum per row, the run least
+//! disturbed by everything else on the machine.
+//!
+//! ## Extension: SIMD
+//! `sum_simd(n)` keeps four partial sums ("lanes") instead of one and adds
+//! four consecutive numbers per step. The four additions are independent,
+//! so the compiler turns them into one vector add (SSE2/AVX2/NEON): one
+//! core doing several additions per instruction. `std::simd` would say
+//! the same thing explicitly but needs nightly; the `[u64; LANES]` loop
+//! gets the same code on stable. `SIMD+Channel` runs it on every thread:
+//! instruction-level and thread-level parallelism multiply.
+//!
+//! Compare them with `--observe`: otherwise the compiler replaces the plain
+//! `(start..=end).sum()` with the N(N+1)/2 formula and it wins in
+//! nanoseconds. In observe mode every number of the plain sum goes through
+//! `black_box`, one scalar add at a time, while the SIMD sum only hides
+//! whole chunks. This run had one CPU, so the extra threads add nothing;
+//! with a core per thread, `SIMD+Channel` divides SIMD's time by about the
+//! thread count:
+//! ```text
+//! $ cargo run --release -- --observe --sleep-ms 0 --n 200000000
+//! Sequential                | Result:    20000000100000000 | Time: 173.140988ms
+//! SIMD (4 lanes)            | Result:    20000000100000000 | Time: 64.56275ms
+//! SIMD+Channel (4 threads)  | Result:    20000000100000000 | Time: 67.908346ms
+//! ```
+//!
+//! ## Extension: Map-Reduce Harness
+//! The mutex, channel, thread-pool and SIMD versions all split 1..=N the
+//! same way, so that lives in `src/reduce.rs`:
+//! `parallel_reduce(range, workers, map, reduce)` runs `map` on one chunk
+//! per thread and folds the partial results with `reduce`. Each version
+//! is now only its `map` (which sum) and its `Combine` (how the partial
+//! results come back). Property tests check the chunks cover any range
+//! exactly and that every `Combine` matches a sequential fold:
+//! ```bash
+//! cargo test --bin parallel_sum
+//! ```
+//!
+//! ## Extension: Scoped Threads and Crossbeam
+//! Two more threaded versions in the table:
+//! - `Scoped`: `std::thread::scope` (`scoped_reduce` in `src/reduce.rs`).
+//!   The scope joins every thread before it returns, so the threads may
+//!   borrow the caller's locals: no `Arc`, no `'static`, and the partial
+//!   sums come back through `join()` instead of a lock or a channel
+//! - `Crossbeam`: the channel version over `crossbeam_channel::unbounded`
+//!
+//! The run ends with the 4-thread times side by side. Without `--observe`
+//! the sums are a formula, so the times are nearly all spawning and
+//! collecting: scoped threads and crossbeam's lighter channel skip an
+//! `Arc` and a lock, and win by more than half
+//! ```text
+//! Summary at 4 threads (fastest first):
+//!   Scoped           58.183µs  1.00x
+//!   Crossbeam        60.562µs  1.04x
+//!   Mutex            90.415µs  1.55x
+//!   Channel         102.729µs  1.77x
+//!   ThreadPool      102.873µs  1.77x
+//! ```
+//! With `--observe --sleep-ms 0` the additions take ~80ms and the same
+//! versions land within 15% of each other, in a different order every
+//! run: once each thread has real work, how four numbers come back is
+//! noise.
+//!
+//! ## Extension: Tokio Tasks
+//! `Tokio spawn` and `Tokio blocking` run the same chunks as tasks on a
+//! Tokio runtime with 2 workers (`src/tokio_sum.rs`), with `tokio::spawn`
+//! and with `spawn_blocking`. A heartbeat task ticks every millisecond
+//! meanwhile, and the line under each row is its longest gap. A spawned
+//! chunk never awaits, so it keeps its worker until it is done; from 2
+//! tasks on, both workers are taken and the heartbeat (a timer, a socket,
+//! any other task) waits for the whole sum. `spawn_blocking` moves the
+//! chunks to Tokio's blocking pool and the workers stay free:
+//! ```text
+//! $ cargo run --release -- --observe --sleep-ms 100 --n 100000000
+//! Tokio spawn (4 tasks)     | Result:     5000000050000000 | Time: 288.545334ms
+//!   heartbeat on 2 workers: longest gap 288.267599ms
+//! Tokio blocking (4 tasks)  | Result:     5000000050000000 | Time: 179.686269ms
+//!   heartbeat on 2 workers: longest gap 14.663847ms
+//! ```
+//! Spawned chunks are slower too: 4 of them take turns on 2 workers,
+//! while the blocking pool starts a thread for each.
+//!
+//! ## Extension: False Sharing
+//! `cargo run --release -- false-sharing` replaces the table with one
+//! experiment (`src/false_sharing.rs`): 4 threads each add their chunk into
+//! their own slot of a shared Vec, one store per number. Adjacent u64
+//! slots sit in one 64-byte cache line, so every store takes the line
+//! away from the other cores; padded slots get a line each. Same code,
+//! same result, only the layout differs (see chapter 2's locality lab):
+//! ```text
+//! $ cargo run --release -- false-sharing
+//! False sharing: 4 threads, one slot each (8 bytes apart adjacent, 128 padded)
+//! Adjacent slots            | Result:     5000000050000000 | Time: 247.248649ms
+//! Padded slots              | Result:     5000000050000000 | Time: 248.914775ms
+//! Local sum, one store      | Result:     5000000050000000 | Time: 76.561213ms
+//! ----------------------------------------------------------------------
+//! Adjacent / padded: 0.99x (the cost of sharing cache lines)
+//! Padded / local:    3.25x (the cost of a store per number)
+//! ```
+//! That run had 1 CPU: the threads take turns and no line ever bounces.
+//! With one core per thread, expect adjacent slots to be several times
+//! slower than padded ones.
+//!
 //! ## Acceptance Criteria
 //! - [ ] `cargo test` all pass (or `cargo run` results correct)
 //! - [ ] All three versions compute correct results
@@ -39,14 +168,24 @@
 //! - [ ] Can explain the purpose of Arc, Mutex, and Channel
 //!
 //! Check solution/main.rs after completing
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
 use std::hint::black_box;
-use std::sync::{mpsc, Arc, Mutex};
+use std::path::PathBuf;
+use std::sync::OnceLock;
 use std::thread;
 use std::time::Duration;
 use std::time::Instant;
 
+mod false_sharing;
+// path_from_args goes unused: clap parses --profile here
+#[path = "../../../../shared/profiling.rs"]
+#[allow(dead_code)]
+mod profiling;
+mod reduce;
 mod thread_pool;
-use thread_pool::ThreadPool;
+mod tokio_sum;
+use reduce::{parallel_reduce, parallel_reduce_with, scoped_reduce, Combine};
+use tokio_sum::{sum_with_tokio_blocking, sum_with_tokio_tasks};
 
 use rayon::prelude::*;
 // ============================================================
@@ -74,145 +213,126 @@ fn sum_range(start: u64, end: u64, slow: bool) -> u64 {
     }
 }
 
-fn read_env_u64(name: &str) -> Option<u64> {
-    std::env::var(name).ok()?.trim().parse::<u64>().ok()
-}
-
-fn read_env_usize(name: &str) -> Option<usize> {
-    std::env::var(name).ok()?.trim().parse::<usize>().ok()
-}
+/// Numbers added per step by `sum_range_simd`: four u64 fill a 256-bit
+/// AVX2 register
+const LANES: usize = 4;
 
-fn observe_mode() -> bool {
-    matches!(
-        std::env::var("OBSERVE").as_deref(),
-        Ok("1") | Ok("true") | Ok("TRUE") | Ok("yes") | Ok("YES")
-    )
+/// SIMD version: one thread, `LANES` additions per step
+fn sum_simd(n: u64) -> u64 {
+    sum_range_simd(1, n, observe_mode())
 }
 
-fn maybe_sleep() {
-    let sleep_ms = read_env_u64("SLEEP_MS").unwrap_or(0);
-    if sleep_ms > 0 {
-        thread::sleep(Duration::from_millis(sleep_ms));
-    }
-}
-
-/// Arc + Mutex version
-fn sum_with_mutex(n: u64, num_threads: usize) -> u64 {
-    // Handle edge cases
-    if n == 0 || num_threads == 0 {
+/// Sum start..=end in chunks of `LANES`: lane i adds start+i, start+i+LANES,
+/// ... The lanes don't depend on each other, so one vector add does a whole
+/// chunk. The last len % LANES numbers go through `sum_range`
+fn sum_range_simd(start: u64, end: u64, slow: bool) -> u64 {
+    if start > end {
         return 0;
     }
 
-    let result = Arc::new(Mutex::new(0_u64));
-
-    // Compute chunk size (ceiling division)
-    let chunk_size = (n + num_threads as u64 - 1) / num_threads as u64;
-
-    let mut handles = Vec::with_capacity(num_threads);
-
-    for thread_id in 0..num_threads {
-        let start = thread_id as u64 * chunk_size + 1;
-        let mut end = (thread_id as u64 + 1) * chunk_size;
-        if end > n {
-            end = n;
+    let chunks = (end - start + 1) / LANES as u64;
+    let mut acc = [0_u64; LANES];
+    let mut next: [u64; LANES] = std::array::from_fn(|i| start + i as u64);
+    for _ in 0..chunks {
+        // Observe mode hides the whole chunk from the optimizer, not each
+        // number: it can't fold the loop into a formula, but can vectorize
+        let chunk = if slow { black_box(next) } else { next };
+        for lane in 0..LANES {
+            acc[lane] = acc[lane].wrapping_add(chunk[lane]);
+            next[lane] = next[lane].wrapping_add(LANES as u64);
         }
-
-        // If this thread's range is empty, skip creating the thread, happen when thread count is big while n is small, like num_thread=10, n=3
-        if start > end {
-            continue;
-        }
-
-        let result_clone = Arc::clone(&result);
-
-        let handle = thread::spawn(move || {
-            maybe_sleep();
-            // 1) Sum this chunk locally
-            let local_sum = sum_range(start, end, observe_mode());
-
-            // 2) Add to shared result under mutex
-            let mut guard = result_clone.lock().unwrap();
-            *guard += local_sum;
-        });
-
-        handles.push(handle);
     }
 
-    // Wait for all threads
-    for handle in handles {
-        handle.join().unwrap();
-    }
+    let tail = sum_range(start + chunks * LANES as u64, end, slow);
+    acc.iter().fold(tail, |sum, &lane| sum.wrapping_add(lane))
+}
 
-    // Extract final result
-    let final_result = *result.lock().unwrap();
-    final_result
+/// SIMD inside each thread, channel version across threads
+fn sum_simd_with_channel(n: u64, num_threads: usize) -> u64 {
+    parallel_reduce(
+        1..=n,
+        num_threads,
+        |chunk| {
+            maybe_sleep();
+            sum_range_simd(*chunk.start(), *chunk.end(), observe_mode())
+        },
+        u64::wrapping_add,
+    )
+    .unwrap_or(0)
 }
-/// Channel version
-fn sum_with_channel(n: u64, num_threads: usize) -> u64 {
-    if n == 0 || num_threads == 0 {
-        return 0;
-    }
 
-    let (tx, rx) = mpsc::channel();
+/// `--observe` and `--sleep-ms`, read by every chunk on every thread
+struct Settings {
+    observe: bool,
+    sleep_ms: u64,
+}
 
-    let chunk_size = (n + num_threads as u64 - 1) / num_threads as u64;
+/// Set once in `main`, before any sum runs
+static SETTINGS: OnceLock<Settings> = OnceLock::new();
 
-    for thread_id in 0..num_threads {
-        let start = thread_id as u64 * chunk_size + 1;
-        let mut end = (thread_id as u64 + 1) * chunk_size;
-        if end > n {
-            end = n;
-        }
+fn observe_mode() -> bool {
+    SETTINGS.get().is_some_and(|settings| settings.observe)
+}
 
-        if start > end {
-            continue;
-        }
+fn maybe_sleep() {
+    let sleep_ms = SETTINGS.get().map_or(0, |settings| settings.sleep_ms);
+    if sleep_ms > 0 {
+        thread::sleep(Duration::from_millis(sleep_ms));
+    }
+}
 
-        let tx_clone = tx.clone();
-        thread::spawn(move || {
+/// Split 1..=n into one chunk per thread, sum each chunk on its thread,
+/// and add the partial sums up; `combine` picks how they get back
+fn sum_chunks(combine: Combine, n: u64, num_threads: usize) -> u64 {
+    // Nothing to sum when n == 0 or num_threads == 0
+    parallel_reduce_with(
+        combine,
+        1..=n,
+        num_threads,
+        |chunk| {
             maybe_sleep();
-            let partial_sum = sum_range(start, end, observe_mode());
-            tx_clone.send(partial_sum).unwrap();
-        });
-    }
+            sum_range(*chunk.start(), *chunk.end(), observe_mode())
+        },
+        u64::wrapping_add,
+    )
+    .unwrap_or(0)
+}
 
-    // Close the original sender so the receiver iterator terminates when workers finish
-    drop(tx);
+/// Arc + Mutex version
+fn sum_with_mutex(n: u64, num_threads: usize) -> u64 {
+    sum_chunks(Combine::Mutex, n, num_threads)
+}
 
-    rx.iter().sum()
+/// Channel version
+fn sum_with_channel(n: u64, num_threads: usize) -> u64 {
+    sum_chunks(Combine::Channel, n, num_threads)
 }
 
 /// ThreadPool version (fixed-size worker threads)
 fn sum_with_thread_pool(n: u64, num_threads: usize) -> u64 {
-    if n == 0 || num_threads == 0 {
-        return 0;
-    }
-
-    let pool = ThreadPool::new(num_threads);
-    let (result_tx, result_rx) = mpsc::channel::<u64>();
-
-    let chunk_size = (n + num_threads as u64 - 1) / num_threads as u64;
-
-    for thread_id in 0..num_threads {
-        let start = thread_id as u64 * chunk_size + 1;
-        let mut end = (thread_id as u64 + 1) * chunk_size;
-        if end > n {
-            end = n;
-        }
+    sum_chunks(Combine::ThreadPool, n, num_threads)
+}
 
-        if start > end {
-            continue;
-        }
+/// Crossbeam version: the channel version over crossbeam-channel
+fn sum_with_crossbeam(n: u64, num_threads: usize) -> u64 {
+    sum_chunks(Combine::Crossbeam, n, num_threads)
+}
 
-        let tx_clone = result_tx.clone();
-        pool.execute(move || {
+/// Scoped threads version: no Arc, no channel, no 'static
+fn sum_with_scope(n: u64, num_threads: usize) -> u64 {
+    // Read once, and every thread borrows it: fine, because the scope
+    // outlives the threads
+    let slow = observe_mode();
+    scoped_reduce(
+        1..=n,
+        num_threads,
+        |chunk| {
             maybe_sleep();
-            let partial_sum = sum_range(start, end, observe_mode());
-            tx_clone.send(partial_sum).unwrap();
-        });
-    }
-
-    drop(result_tx);
-    result_rx.iter().sum()
+            sum_range(*chunk.start(), *chunk.end(), slow)
+        },
+        u64::wrapping_add,
+    )
+    .unwrap_or(0)
 }
 
 /// Rayon version (data-parallel iterator)
@@ -221,7 +341,7 @@ fn sum_with_rayon(n: u64) -> u64 {
         return 0;
     }
     if observe_mode() {
-        (1..=n).into_par_iter().map(|i| black_box(i)).sum()
+        (1..=n).into_par_iter().map(black_box).sum()
     } else {
         (1..=n).into_par_iter().sum()
     }
@@ -231,73 +351,343 @@ fn sum_with_rayon(n: u64) -> u64 {
 // Performance testing (no modification needed)
 // ============================================================
 
-fn benchmark<F>(name: &str, f: F)
+// Not inlined into main, so each benchmark gets its own frames in a --profile
+// flamegraph (pprof groups samples by the function they landed in)
+#[inline(never)]
+fn timed<F>(f: F) -> (u64, Duration)
 where
     F: FnOnce() -> u64,
 {
     let start = Instant::now();
     let result = black_box(f());
-    let duration = start.elapsed();
+    (result, start.elapsed())
+}
+
+fn benchmark<F>(name: &str, f: F) -> Duration
+where
+    F: FnOnce() -> u64,
+{
+    let (result, duration) = timed(f);
     println!("{:25} | Result: {:20} | Time: {:?}", name, result, duration);
+    duration
 }
 
-fn main() {
-    let observe = observe_mode();
-    if observe && std::env::var("SLEEP_MS").is_err() {
-        std::env::set_var("SLEEP_MS", "200");
+/// sum(n, num_threads), as every threaded version takes it
+type SumFn = fn(u64, usize) -> u64;
+
+/// sum(n, tasks) and the Tokio heartbeat's longest gap
+type TokioSumFn = fn(u64, usize) -> (u64, Duration);
+
+/// The versions in the table, in table order; `--impl` picks some
+#[derive(Clone, Copy, PartialEq, Eq, Debug, ValueEnum)]
+enum Impl {
+    Sequential,
+    Mutex,
+    Channel,
+    ThreadPool,
+    Scoped,
+    Crossbeam,
+    TokioSpawn,
+    TokioBlocking,
+    Rayon,
+    Simd,
+    SimdChannel,
+}
+
+impl Impl {
+    /// The name in the table and the summary
+    fn name(self) -> &'static str {
+        match self {
+            Impl::Sequential => "Sequential",
+            Impl::Mutex => "Mutex",
+            Impl::Channel => "Channel",
+            Impl::ThreadPool => "ThreadPool",
+            Impl::Scoped => "Scoped",
+            Impl::Crossbeam => "Crossbeam",
+            Impl::TokioSpawn => "Tokio spawn",
+            Impl::TokioBlocking => "Tokio blocking",
+            Impl::Rayon => "Rayon",
+            Impl::Simd => "SIMD",
+            Impl::SimdChannel => "SIMD+Channel",
+        }
     }
 
-    let n_default: u64 = if observe { 500_000_000 } else { 100_000_000 };
-    let n: u64 = black_box(read_env_u64("N").unwrap_or(n_default));
-    let expected_u128: u128 = (n as u128) * ((n + 1) as u128) / 2;
+    /// The `--impl` value, which is also the CSV's impl column
+    fn id(self) -> String {
+        self.to_possible_value()
+            .expect("no variant is skipped")
+            .get_name()
+            .to_string()
+    }
+
+    /// One row's label: "Mutex (4 threads)", "Tokio spawn (4 tasks)", ...
+    fn label(self, threads: usize) -> String {
+        match self {
+            Impl::Sequential | Impl::Rayon => self.name().to_string(),
+            Impl::Simd => format!("SIMD ({} lanes)", LANES),
+            Impl::TokioSpawn | Impl::TokioBlocking => {
+                format!("{} ({} tasks)", self.name(), threads)
+            }
+            _ => format!("{} ({} threads)", self.name(), threads),
+        }
+    }
+}
+
+#[derive(Parser, Debug)]
+#[command(name = "parallel_sum")]
+#[command(about = "Sum 1..=N with threads, channels, pools, Tokio, Rayon and SIMD, and compare")]
+struct Args {
+    /// Sum 1..=N [default: 100000000, or 500000000 with --observe]
+    #[arg(long)]
+    n: Option<u64>,
+
+    /// Thread (task) counts for every threaded version, comma separated
+    #[arg(long, value_delimiter = ',', default_value = "1,2,4,8", value_parser = positive)]
+    threads: Vec<usize>,
+
+    /// Versions to run, comma separated [default: all]
+    #[arg(long = "impl", value_enum, value_delimiter = ',')]
+    impls: Vec<Impl>,
+
+    /// Time every row this many times; the summary takes the fastest run
+    #[arg(long, default_value_t = 1, value_parser = positive)]
+    repeat: usize,
+
+    /// Print only `impl,threads,run,millis` rows, one per run, for plotting
+    #[arg(long)]
+    csv: bool,
+
+    /// Observe mode: a bigger default N, a sleep per chunk, and sums the
+    /// compiler can't turn into a formula
+    #[arg(long)]
+    observe: bool,
+
+    /// Sleep per chunk, in milliseconds [default: 200 with --observe, else 0]
+    #[arg(long)]
+    sleep_ms: Option<u64>,
+
+    /// Profile the run and write a flamegraph SVG
+    #[arg(long, value_name = "PATH", num_args = 0..=1, default_missing_value = "flamegraph.svg")]
+    profile: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Mode>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Mode {
+    /// Adjacent vs padded per-thread slots, instead of the version table
+    FalseSharing,
+}
+
+fn positive(value: &str) -> Result<usize, String> {
+    match value.parse() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err("expected a number > 0".to_string()),
+    }
+}
+
+/// How every row is timed and reported
+struct Bench {
+    csv: bool,
+    repeat: usize,
+}
 
-    println!("N = {}", n);
-    println!("Expected: {}", expected_u128);
-    if observe {
-        let sleep_ms = read_env_u64("SLEEP_MS").unwrap_or(0);
-        let rayon_threads = read_env_usize("RAYON_NUM_THREADS");
+impl Bench {
+    /// Time `f` `repeat` times as `version` at `threads`: a table row or a
+    /// CSV row per run. Returns the fastest run
+    fn run<F>(&self, version: Impl, threads: usize, mut f: F) -> Duration
+    where
+        F: FnMut() -> u64,
+    {
+        let label = version.label(threads);
+        (1..=self.repeat)
+            .map(|run| {
+                if !self.csv {
+                    return benchmark(&label, &mut f);
+                }
+                let (_, time) = timed(&mut f);
+                println!(
+                    "{},{},{},{:.3}",
+                    version.id(),
+                    threads,
+                    run,
+                    time.as_secs_f64() * 1000.0
+                );
+                time
+            })
+            .min()
+            .expect("repeat is at least 1")
+    }
+}
+
+/// The threaded and Tokio versions at one thread (task) count, fastest first, each against
+/// the fastest: what the way results come back costs
+fn print_summary(threads: usize, times: &mut [(String, Duration)]) {
+    times.sort_by_key(|&(_, time)| time);
+    let fastest = times[0].1.as_secs_f64();
+    println!("Summary at {} threads (fastest first):", threads);
+    for (name, time) in times.iter() {
         println!(
-            "OBSERVE=1 (slow_sum=true, SLEEP_MS={}, RAYON_NUM_THREADS={})",
-            sleep_ms,
-            rayon_threads
-                .map(|n| n.to_string())
-                .unwrap_or_else(|| "default".to_string())
+            "  {:16} {:>12?}  {:.2}x",
+            name,
+            time,
+            time.as_secs_f64() / fastest
         );
-        if expected_u128 > u64::MAX as u128 {
-            println!("Note: sum(1..=N) exceeds u64::MAX; Result values wrap modulo 2^64.");
-        }
     }
-    println!("{}", "=".repeat(70));
+}
+
+/// The versions `args` picks on the same N, then the summary at 4 threads
+/// (or the most asked for, without 4)
+fn compare_versions(n: u64, args: &Args) {
+    let bench = Bench {
+        csv: args.csv,
+        repeat: args.repeat,
+    };
+    let picked = |version| args.impls.is_empty() || args.impls.contains(&version);
+    let rule = |line: &str| {
+        if !args.csv {
+            println!("{}", line.repeat(70));
+        }
+    };
 
     // Single-threaded
-    benchmark("Sequential", || sum_sequential(n));
+    if picked(Impl::Sequential) {
+        bench.run(Impl::Sequential, 1, || sum_sequential(n));
+        rule("-");
+    }
 
-    println!("{}", "-".repeat(70));
+    // One section per threaded version; the summary's times go in the summary
+    let versions: [(Impl, SumFn); 5] = [
+        (Impl::Mutex, sum_with_mutex),
+        (Impl::Channel, sum_with_channel),
+        (Impl::ThreadPool, sum_with_thread_pool),
+        // Borrowing instead of Arc: std::thread::scope
+        (Impl::Scoped, sum_with_scope),
+        (Impl::Crossbeam, sum_with_crossbeam),
+    ];
+    let summary_threads = if args.threads.contains(&4) {
+        4
+    } else {
+        *args.threads.iter().max().expect("clap requires a value")
+    };
+    let mut summary = Vec::new();
+    for (version, sum) in versions.into_iter().filter(|&(v, _)| picked(v)) {
+        for &threads in &args.threads {
+            let time = bench.run(version, threads, || sum(n, threads));
+            if threads == summary_threads {
+                summary.push((version.name().to_string(), time));
+            }
+        }
 
-    // Mutex version
-    for &threads in &[1, 2, 4, 8] {
-        let name = format!("Mutex ({} threads)", threads);
-        benchmark(&name, || sum_with_mutex(n, threads));
+        rule("-");
     }
 
-    println!("{}", "-".repeat(70));
+    // Tokio versions: the same chunks as tasks, on a runtime with
+    // tokio_sum::WORKERS workers. The heartbeat line shows whether the
+    // runtime could still run anything else meanwhile
+    let tokio_versions: [(Impl, TokioSumFn); 2] = [
+        (Impl::TokioSpawn, sum_with_tokio_tasks),
+        (Impl::TokioBlocking, sum_with_tokio_blocking),
+    ];
+    for (version, sum) in tokio_versions.into_iter().filter(|&(v, _)| picked(v)) {
+        for &tasks in &args.threads {
+            let mut longest_gap = Duration::ZERO;
+            let time = bench.run(version, tasks, || {
+                let (result, gap) = sum(n, tasks);
+                longest_gap = longest_gap.max(gap);
+                result
+            });
+            if !args.csv {
+                println!(
+                    "  heartbeat on {} workers: longest gap {:?}",
+                    tokio_sum::WORKERS,
+                    longest_gap
+                );
+            }
+            if tasks == summary_threads {
+                summary.push((version.name().to_string(), time));
+            }
+        }
 
-    // Channel version
-    for &threads in &[1, 2, 4, 8] {
-        let name = format!("Channel ({} threads)", threads);
-        benchmark(&name, || sum_with_channel(n, threads));
+        rule("-");
     }
 
-    println!("{}", "-".repeat(70));
+    // Rayon version, on rayon's global pool (RAYON_NUM_THREADS sizes it)
+    if picked(Impl::Rayon) {
+        bench.run(Impl::Rayon, rayon::current_num_threads(), || {
+            sum_with_rayon(n)
+        });
+        rule("-");
+    }
 
-    // ThreadPool version
-    for &threads in &[1, 2, 4, 8] {
-        let name = format!("ThreadPool ({} threads)", threads);
-        benchmark(&name, || sum_with_thread_pool(n, threads));
+    // SIMD version: one core, several additions per instruction; its
+    // 1-thread row is the SIMD row
+    if picked(Impl::Simd) {
+        bench.run(Impl::Simd, 1, || sum_simd(n));
+    }
+    if picked(Impl::SimdChannel) {
+        for &threads in args.threads.iter().filter(|&&threads| threads > 1) {
+            bench.run(Impl::SimdChannel, threads, || {
+                sum_simd_with_channel(n, threads)
+            });
+        }
     }
 
-    println!("{}", "-".repeat(70));
+    rule("=");
+    if !args.csv && !summary.is_empty() {
+        print_summary(summary_threads, &mut summary);
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    if args.csv && args.command.is_some() {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--csv times the version table, not false-sharing",
+            )
+            .exit();
+    }
 
-    // Rayon version
-    benchmark("Rayon", || sum_with_rayon(n));
+    let profiler = args.profile.clone().map(profiling::Profiler::start);
+
+    let observe = args.observe;
+    let sleep_ms = args.sleep_ms.unwrap_or(if observe { 200 } else { 0 });
+    let settings = Settings { observe, sleep_ms };
+    assert!(SETTINGS.set(settings).is_ok(), "settings are set once");
+
+    let n_default: u64 = if observe { 500_000_000 } else { 100_000_000 };
+    let n: u64 = black_box(args.n.unwrap_or(n_default));
+    let expected_u128: u128 = (n as u128) * ((n + 1) as u128) / 2;
+
+    if args.csv {
+        // Nothing but CSV on stdout, so it can go straight to a file
+        println!("impl,threads,run,millis");
+        compare_versions(n, &args);
+    } else {
+        println!("N = {}", n);
+        println!("Expected: {}", expected_u128);
+        if observe {
+            println!(
+                "Observe mode (slow_sum=true, sleep {}ms per chunk, {} Rayon threads)",
+                sleep_ms,
+                rayon::current_num_threads()
+            );
+            if expected_u128 > u64::MAX as u128 {
+                println!("Note: sum(1..=N) exceeds u64::MAX; Result values wrap modulo 2^64.");
+            }
+        }
+        println!("{}", "=".repeat(70));
+
+        match args.command {
+            Some(Mode::FalseSharing) => false_sharing::run(n, 4),
+            None => compare_versions(n, &args),
+        }
+    }
+
+    if let Some(profiler) = profiler {
+        profiler.finish();
+    }
 }
@@ -0,0 +1,286 @@
+//! Map-reduce over a range of u64, the pattern behind every parallel sum
+//!
+//! `chunks` splits the range into one contiguous piece per worker, each
+//! worker `map`s its piece to a partial result, and `reduce` folds the
+//! partial results into one. The versions only differ in how partial
+//! results travel back (`Combine`): a shared `Arc<Mutex<_>>`, a std or
+//! crossbeam channel, or a channel fed by `ThreadPool` jobs instead of
+//! fresh threads. `scoped_reduce` does the same on `thread::scope`
+//! threads, which may borrow instead of sharing through an `Arc`.
+//!
+//! Partial results arrive in whatever order the workers finish, so
+//! `reduce` must be associative and commutative (+, max, ...), or the
+//! result changes from run to run.
+
+use crate::thread_pool::ThreadPool;
+use std::ops::RangeInclusive;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+/// How partial results get back to the caller
+#[derive(Clone, Copy, Debug)]
+pub enum Combine {
+    /// Each thread folds its result into a shared `Arc<Mutex<Option<T>>>`
+    Mutex,
+    /// Each thread sends its result; the caller folds them
+    Channel,
+    /// As `Channel`, over a crossbeam channel: lock-free, and any number
+    /// of receivers could share it
+    Crossbeam,
+    /// As `Channel`, but the chunks run as jobs on a `ThreadPool`
+    ThreadPool,
+}
+
+/// Split `range` into at most `workers` contiguous, non-overlapping chunks
+/// that cover it exactly. The chunks have the same length except the last,
+/// which may be shorter; no chunk is empty, so a small range gets fewer
+/// chunks than workers
+pub fn chunks(range: RangeInclusive<u64>, workers: usize) -> Vec<RangeInclusive<u64>> {
+    let (start, end) = range.into_inner();
+    if start > end || workers == 0 {
+        return Vec::new();
+    }
+
+    // u128: 0..=u64::MAX holds u64::MAX + 1 numbers
+    let len = (end - start) as u128 + 1;
+    // Ceiling division, so `workers` chunks are enough
+    let chunk_size = len.div_ceil(workers as u128);
+
+    (0..workers as u128)
+        .map(|worker| worker * chunk_size)
+        .take_while(|&offset| offset < len)
+        .map(|offset| {
+            let last = (offset + chunk_size - 1).min(len - 1);
+            start + offset as u64..=start + last as u64
+        })
+        .collect()
+}
+
+/// `parallel_reduce_with(Combine::Channel, ...)`
+pub fn parallel_reduce<T, M, R>(
+    range: RangeInclusive<u64>,
+    workers: usize,
+    map: M,
+    reduce: R,
+) -> Option<T>
+where
+    T: Send + 'static,
+    M: Fn(RangeInclusive<u64>) -> T + Send + Sync + 'static,
+    R: Fn(T, T) -> T + Send + Sync + 'static,
+{
+    parallel_reduce_with(Combine::Channel, range, workers, map, reduce)
+}
+
+/// Map each of `chunks(range, workers)` on its own thread, then reduce the
+/// partial results. None if there was nothing to map: an empty range, or
+/// 0 workers
+pub fn parallel_reduce_with<T, M, R>(
+    combine: Combine,
+    range: RangeInclusive<u64>,
+    workers: usize,
+    map: M,
+    reduce: R,
+) -> Option<T>
+where
+    T: Send + 'static,
+    M: Fn(RangeInclusive<u64>) -> T + Send + Sync + 'static,
+    R: Fn(T, T) -> T + Send + Sync + 'static,
+{
+    let chunks = chunks(range, workers);
+    if chunks.is_empty() {
+        return None;
+    }
+    // Every thread needs the closures, and they must outlive the call:
+    // share them instead of requiring Clone
+    let map = Arc::new(map);
+    let reduce = Arc::new(reduce);
+
+    match combine {
+        Combine::Mutex => {
+            let result = Arc::new(Mutex::new(None));
+            let handles: Vec<_> = chunks
+                .into_iter()
+                .map(|chunk| {
+                    let (map, reduce) = (Arc::clone(&map), Arc::clone(&reduce));
+                    let result = Arc::clone(&result);
+                    thread::spawn(move || {
+                        // Map outside the lock: only the fold is serialized
+                        let partial = map(chunk);
+                        let mut guard = result.lock().unwrap();
+                        *guard = Some(match guard.take() {
+                            Some(acc) => reduce(acc, partial),
+                            None => partial,
+                        });
+                    })
+                })
+                .collect();
+            for handle in handles {
+                handle.join().unwrap();
+            }
+            let result = result.lock().unwrap().take();
+            result
+        }
+        Combine::Channel => {
+            let (tx, rx) = mpsc::channel();
+            for chunk in chunks {
+                let (map, tx) = (Arc::clone(&map), tx.clone());
+                thread::spawn(move || tx.send(map(chunk)).unwrap());
+            }
+            // Close the original sender so the receiver iterator ends
+            // when the workers are done
+            drop(tx);
+            rx.iter().reduce(|acc, partial| reduce(acc, partial))
+        }
+        Combine::Crossbeam => {
+            let (tx, rx) = crossbeam_channel::unbounded();
+            for chunk in chunks {
+                let (map, tx) = (Arc::clone(&map), tx.clone());
+                thread::spawn(move || tx.send(map(chunk)).unwrap());
+            }
+            drop(tx);
+            rx.iter().reduce(|acc, partial| reduce(acc, partial))
+        }
+        Combine::ThreadPool => {
+            let pool = ThreadPool::new(chunks.len());
+            let (tx, rx) = mpsc::channel();
+            for chunk in chunks {
+                let (map, tx) = (Arc::clone(&map), tx.clone());
+                pool.execute(move || tx.send(map(chunk)).unwrap());
+            }
+            drop(tx);
+            rx.iter().reduce(|acc, partial| reduce(acc, partial))
+        }
+    }
+}
+
+/// `parallel_reduce` on scoped threads. `thread::scope` joins them all
+/// before it returns, so they may borrow: `map` may capture references to
+/// the caller's locals and needn't be 'static, nothing goes in an `Arc`,
+/// and the partial results come back through the join handles. `reduce`
+/// runs on the caller's thread only
+pub fn scoped_reduce<T, M, R>(
+    range: RangeInclusive<u64>,
+    workers: usize,
+    map: M,
+    reduce: R,
+) -> Option<T>
+where
+    T: Send,
+    M: Fn(RangeInclusive<u64>) -> T + Sync,
+    R: Fn(T, T) -> T,
+{
+    let chunks = chunks(range, workers);
+    let map = &map;
+    thread::scope(|s| {
+        let handles: Vec<_> = chunks
+            .into_iter()
+            .map(|chunk| s.spawn(move || map(chunk)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .reduce(reduce)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const ALL: [Combine; 4] = [
+        Combine::Mutex,
+        Combine::Channel,
+        Combine::Crossbeam,
+        Combine::ThreadPool,
+    ];
+
+    /// Ranges that start almost anywhere in u64, up to 10,000 numbers long,
+    /// and sometimes empty (start > end)
+    fn any_range() -> impl Strategy<Value = RangeInclusive<u64>> {
+        (1..u64::MAX, 0..10_000_u64).prop_map(|(start, len)| match len {
+            0 => start..=start - 1,
+            _ => start..=start.saturating_add(len - 1),
+        })
+    }
+
+    fn sequential_sum(range: RangeInclusive<u64>) -> u64 {
+        range.fold(0, u64::wrapping_add)
+    }
+
+    proptest! {
+        #[test]
+        fn chunks_cover_the_range_exactly(range in any_range(), workers in 1..32_usize) {
+            let chunks = chunks(range.clone(), workers);
+            prop_assert!(chunks.len() <= workers);
+            prop_assert!(chunks.iter().all(|chunk| !chunk.is_empty()));
+            let flattened: Vec<u64> = chunks.into_iter().flatten().collect();
+            let expected: Vec<u64> = range.collect();
+            prop_assert_eq!(flattened, expected);
+        }
+
+        #[test]
+        fn sum_matches_sequential(range in any_range(), workers in 1..16_usize) {
+            for combine in ALL {
+                let sum = parallel_reduce_with(
+                    combine,
+                    range.clone(),
+                    workers,
+                    sequential_sum,
+                    u64::wrapping_add,
+                );
+                prop_assert_eq!(sum.unwrap_or(0), sequential_sum(range.clone()), "{:?}", combine);
+            }
+            let sum = scoped_reduce(range.clone(), workers, sequential_sum, u64::wrapping_add);
+            prop_assert_eq!(sum.unwrap_or(0), sequential_sum(range), "scoped");
+        }
+
+        #[test]
+        fn max_matches_sequential(range in any_range(), workers in 1..16_usize) {
+            for combine in ALL {
+                let max = parallel_reduce_with(
+                    combine,
+                    range.clone(),
+                    workers,
+                    |chunk| chunk.max().unwrap(),
+                    u64::max,
+                );
+                prop_assert_eq!(max, range.clone().max(), "{:?}", combine);
+            }
+            let max = scoped_reduce(range.clone(), workers, |chunk| chunk.max().unwrap(), u64::max);
+            prop_assert_eq!(max, range.max(), "scoped");
+        }
+    }
+
+    #[test]
+    fn nothing_to_map() {
+        assert_eq!(
+            parallel_reduce(RangeInclusive::new(5, 4), 4, |_| 1, |a, b| a + b),
+            None
+        );
+        assert_eq!(parallel_reduce(1..=10, 0, |_| 1, |a, b| a + b), None);
+        assert_eq!(scoped_reduce(1..=10, 0, |_| 1, |a, b| a + b), None);
+    }
+
+    #[test]
+    fn scoped_map_borrows() {
+        // Not 'static, not Sync-wrapped, not cloned: borrowed by every thread
+        let weights: Vec<u64> = (0..100).map(|i| i % 7).collect();
+        let weighted = scoped_reduce(
+            0..=99,
+            4,
+            |chunk| chunk.map(|i| weights[i as usize] * i).sum::<u64>(),
+            |a, b| a + b,
+        );
+        let expected = (0..100).map(|i| weights[i as usize] * i).sum();
+        assert_eq!(weighted, Some(expected));
+    }
+
+    #[test]
+    fn whole_u64_range() {
+        let chunks = chunks(0..=u64::MAX, 4);
+        assert_eq!(chunks.len(), 4);
+        assert_eq!(chunks[0], 0..=u64::MAX / 4);
+        assert_eq!(chunks[3], 3 * (u64::MAX / 4 + 1)..=u64::MAX);
+    }
+}
@@ -0,0 +1,103 @@
+//! Tokio versions: the chunks as tasks on an async runtime
+//!
+//! A Tokio worker thread runs a task until the task awaits. A chunk sum
+//! never awaits, so a `tokio::spawn`ed one holds its worker until it is
+//! done; once every worker holds one, nothing else on the runtime runs:
+//! no timers, no sockets, no other tasks. `spawn_blocking` runs the chunk
+//! on Tokio's separate blocking pool instead and the workers stay free.
+//!
+//! To make that visible, a heartbeat task wakes every millisecond while
+//! the chunks run and reports the longest gap between two wake-ups: about
+//! 1ms if the workers are free, the whole sum if they are not.
+
+use crate::reduce::chunks;
+use crate::{maybe_sleep, observe_mode, sum_range};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+/// Runtime worker threads. Fewer than the largest task count, so plain
+/// `spawn` can take them all
+pub const WORKERS: usize = 2;
+
+const HEARTBEAT: Duration = Duration::from_millis(1);
+
+#[derive(Clone, Copy)]
+enum Spawn {
+    /// `tokio::spawn`: on the runtime's workers
+    Task,
+    /// `tokio::task::spawn_blocking`: on the blocking pool
+    Blocking,
+}
+
+/// Plain `tokio::spawn` version: returns the sum and the heartbeat's
+/// longest gap
+pub fn sum_with_tokio_tasks(n: u64, tasks: usize) -> (u64, Duration) {
+    sum_with_tokio(n, tasks, Spawn::Task)
+}
+
+/// `spawn_blocking` version: returns the sum and the heartbeat's longest
+/// gap
+pub fn sum_with_tokio_blocking(n: u64, tasks: usize) -> (u64, Duration) {
+    sum_with_tokio(n, tasks, Spawn::Blocking)
+}
+
+fn sum_with_tokio(n: u64, tasks: usize, spawn: Spawn) -> (u64, Duration) {
+    // A runtime per call, as the ThreadPool version builds a pool per call
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(WORKERS)
+        .enable_time()
+        .build()
+        .expect("failed to build the Tokio runtime");
+
+    // block_on runs this future on the calling thread, not on a worker:
+    // awaiting the handles here can't be starved by the chunks
+    runtime.block_on(async move {
+        let done = Arc::new(Notify::new());
+        let heartbeat = tokio::spawn(heartbeat(Instant::now(), Arc::clone(&done)));
+
+        let handles: Vec<_> = chunks(1..=n, tasks)
+            .into_iter()
+            .map(|chunk| {
+                let work = move || {
+                    maybe_sleep();
+                    sum_range(*chunk.start(), *chunk.end(), observe_mode())
+                };
+                match spawn {
+                    // Compiles and works, but blocks the worker it runs on
+                    Spawn::Task => tokio::spawn(async move { work() }),
+                    Spawn::Blocking => tokio::task::spawn_blocking(work),
+                }
+            })
+            .collect();
+
+        let mut sum = 0_u64;
+        for handle in handles {
+            sum = sum.wrapping_add(handle.await.expect("a chunk task panicked"));
+        }
+        // notify_one keeps the wake-up if the heartbeat isn't waiting yet
+        done.notify_one();
+        let longest_gap = heartbeat.await.expect("the heartbeat panicked");
+        (sum, longest_gap)
+    })
+}
+
+/// Tick until `done`, and return the longest time between two ticks. The
+/// first gap counts from `spawned` and the last one ends at `done`: a
+/// heartbeat that can't even start is starved too
+async fn heartbeat(spawned: Instant, done: Arc<Notify>) -> Duration {
+    let mut last = spawned;
+    let mut longest = Duration::ZERO;
+    loop {
+        let finished = tokio::select! {
+            _ = tokio::time::sleep(HEARTBEAT) => false,
+            _ = done.notified() => true,
+        };
+        let now = Instant::now();
+        longest = longest.max(now - last);
+        last = now;
+        if finished {
+            return longest;
+        }
+    }
+}
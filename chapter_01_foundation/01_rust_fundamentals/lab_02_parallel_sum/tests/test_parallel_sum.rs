@@ -69,6 +69,194 @@ fn test_03_results_match_expected() {
     }
 }
 
+#[test]
+fn test_04_profile_writes_flamegraph() {
+    // Test: --profile samples the run and writes an SVG flamegraph
+    let path = std::env::temp_dir().join(format!("parallel_sum_{}.svg", std::process::id()));
+    let output = Command::new("cargo")
+        .args(["run", "--release", "--quiet", "--", "--profile"])
+        .arg(&path)
+        .args(["--observe", "--sleep-ms", "0", "--n", "20000000"])
+        .output()
+        .expect("Failed to execute program");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if stdout.contains("not yet implemented") {
+        return;
+    }
+
+    assert!(
+        stdout.contains("Flamegraph:"),
+        "Should report the flamegraph: {}{}",
+        stdout,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let svg = std::fs::read_to_string(&path).expect("flamegraph file should exist");
+    let _ = std::fs::remove_file(&path);
+    assert!(svg.contains("<svg"), "Should be an SVG");
+    assert!(
+        svg.contains("sum_range"),
+        "Hot loop should show up in the flamegraph"
+    );
+}
+
+#[test]
+fn test_05_csv_rows_per_run() {
+    // Test: --csv prints only the header and one row per picked version,
+    // thread count and run
+    let output = Command::new("cargo")
+        .args(["run", "--release", "--quiet", "--", "--csv"])
+        .args(["--n", "1000000", "--threads", "1,2", "--repeat", "3"])
+        .args(["--impl", "sequential,mutex,channel"])
+        .output()
+        .expect("Failed to execute program");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if stdout.contains("not yet implemented") {
+        return;
+    }
+
+    let mut lines = stdout.lines();
+    assert_eq!(lines.next(), Some("impl,threads,run,millis"), "{}", stdout);
+    let rows: Vec<Vec<&str>> = lines.map(|line| line.split(',').collect()).collect();
+    // sequential once, mutex and channel at 1 and 2 threads: 5 rows, 3 runs each
+    assert_eq!(rows.len(), 15, "{}", stdout);
+    for row in &rows {
+        assert_eq!(row.len(), 4, "{:?}", row);
+        assert!(
+            ["sequential", "mutex", "channel"].contains(&row[0]),
+            "{:?}",
+            row
+        );
+        assert!(row[3].parse::<f64>().is_ok(), "{:?}", row);
+    }
+    let runs: Vec<&str> = rows
+        .iter()
+        .filter(|row| row[0] == "channel" && row[1] == "2")
+        .map(|row| row[2])
+        .collect();
+    assert_eq!(runs, ["1", "2", "3"], "{}", stdout);
+}
+
+#[test]
+fn test_06_simd_rows_are_correct() {
+    // Test: the SIMD rows are in the table, with the right sum even when
+    // N is not a multiple of the lane count (observe mode: no formula)
+    let output = Command::new("cargo")
+        .args(["run", "--release", "--quiet", "--", "--observe"])
+        .args(["--sleep-ms", "0", "--n", "1000003"])
+        .output()
+        .expect("Failed to execute program");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if stdout.contains("not yet implemented") {
+        return;
+    }
+
+    let simd_lines: Vec<&str> = stdout
+        .lines()
+        .filter(|line| line.starts_with("SIMD"))
+        .collect();
+    assert_eq!(simd_lines.len(), 4, "{}", stdout);
+    for line in simd_lines {
+        assert!(line.contains("500003500006"), "{}", line);
+    }
+}
+
+#[test]
+fn test_07_scoped_and_crossbeam_in_summary() {
+    // Test: the scoped and crossbeam versions run, and the summary ranks
+    // every threaded and Tokio version
+    let output = run_parallel_sum();
+    if output.contains("not yet implemented") {
+        return;
+    }
+
+    for version in ["Scoped (4 threads)", "Crossbeam (4 threads)"] {
+        assert!(output.contains(version), "{}", output);
+    }
+    let summary: Vec<&str> = output
+        .lines()
+        .skip_while(|line| !line.starts_with("Summary at 4 threads"))
+        .skip(1)
+        .take_while(|line| line.starts_with("  "))
+        .collect();
+    assert_eq!(summary.len(), 7, "{}", output);
+    assert!(summary[0].ends_with("1.00x"), "{}", output);
+}
+
+#[test]
+fn test_08_false_sharing_mode() {
+    // Test: every slot layout sums correctly, and the run reports how
+    // much padding saved
+    let output = Command::new("cargo")
+        .args(["run", "--release", "--quiet", "--"])
+        .args(["--n", "4000000", "false-sharing"])
+        .output()
+        .expect("Failed to execute program");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if stdout.contains("not yet implemented") {
+        return;
+    }
+
+    let results: Vec<&str> = stdout
+        .lines()
+        .filter(|line| line.contains("Result:"))
+        .collect();
+    assert_eq!(results.len(), 3, "{}", stdout);
+    for line in results {
+        assert!(line.contains("8000002000000"), "{}", line);
+    }
+    assert!(stdout.contains("Adjacent / padded:"), "{}", stdout);
+    // The version table doesn't run in this mode
+    assert!(!stdout.contains("Mutex"), "{}", stdout);
+}
+
+/// "  heartbeat on 2 workers: longest gap 1.5ms" -> 1.5
+fn heartbeat_gap_ms(line: &str) -> f64 {
+    let gap = line.rsplit(' ').next().unwrap();
+    let (value, scale) = if let Some(value) = gap.strip_suffix("ms") {
+        (value, 1.0)
+    } else if let Some(value) = gap.strip_suffix("µs") {
+        (value, 0.001)
+    } else if let Some(value) = gap.strip_suffix("ns") {
+        (value, 0.000_001)
+    } else {
+        (gap.strip_suffix('s').unwrap(), 1000.0)
+    };
+    value.parse::<f64>().unwrap() * scale
+}
+
+#[test]
+fn test_09_tokio_spawn_starves_the_runtime() {
+    // Test: CPU-bound chunks spawned as tasks hold both runtime workers
+    // (the heartbeat waits through every chunk's 200ms sleep), while
+    // spawn_blocking leaves them free
+    let output = Command::new("cargo")
+        .args(["run", "--release", "--quiet", "--", "--observe"])
+        .args(["--sleep-ms", "200", "--n", "1000000"])
+        .output()
+        .expect("Failed to execute program");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if stdout.contains("not yet implemented") {
+        return;
+    }
+
+    let gap_after = |row: &str| {
+        let mut lines = stdout.lines().skip_while(|line| !line.starts_with(row));
+        lines
+            .next()
+            .unwrap_or_else(|| panic!("no {} row: {}", row, stdout));
+        heartbeat_gap_ms(lines.next().unwrap())
+    };
+    let spawn = gap_after("Tokio spawn (4 tasks)");
+    let blocking = gap_after("Tokio blocking (4 tasks)");
+    assert!(spawn >= 200.0, "spawn: {}ms\n{}", spawn, stdout);
+    assert!(
+        blocking < 200.0,
+        "spawn_blocking: {}ms\n{}",
+        blocking,
+        stdout
+    );
+}
+
 // ============================================================
 // If you want more precise unit tests, add the following code
 // to the end of main.rs
@@ -0,0 +1,203 @@
+//! Lab 7: Typed Errors
+//!
+//! ## Goal
+//! Rewrite mini_cat (Lab 1) with a typed error hierarchy: the "library"
+//! part returns a `thiserror` enum that keeps every detail, the
+//! "application" part turns it into a message chain and an exit code
+//!
+//! ## Requirements
+//! 1. `typed_cat <file> [keyword] [-n]`, the same output as mini_cat
+//! 2. `mod cat` is the library: `cat()` returns `Result<usize, CatError>`,
+//!    never prints an error, never exits. Each variant keeps the path (and
+//!    line) and the `io::Error` as `#[source]`:
+//!    - `Open`: the file can't be opened
+//!    - `Read`: reading failed at line N (e.g. the path is a directory)
+//!    - `NotText`: line N is not valid UTF-8
+//!    - `Write`: stdout failed
+//! 3. The application wraps it: `AppError::{Usage, Cat}`, with
+//!    `#[error(transparent)]` and `#[from]` so `?` converts
+//! 4. Print the error, then every `source()` below it:
+//!    ```text
+//!    typed_cat: cannot open missing.txt
+//!      caused by: No such file or directory (os error 2)
+//!    ```
+//! 5. Exit codes (sysexits.h where one fits):
+//!    - 0: done; 1: a keyword matched no line (like grep); 2: usage
+//!    - 66 `EX_NOINPUT`: cannot open; 77 `EX_NOPERM`: permission denied
+//!    - 65 `EX_DATAERR`: not UTF-8; 74 `EX_IOERR`: read or write failed
+//! 6. `typed_cat big.txt | head -1` ends quietly with 0: a closed pipe is
+//!    a `Write` error the application chooses to ignore
+//!
+//! ## Library vs Application
+//! - A library can't know what its caller wants to do about an error, so
+//!   it hands over a type to `match` on, with the cause attached. Strings
+//!   (or `anyhow::Error`, as in Lab 1) leave only the message to parse
+//! - An application is the one caller that knows: it picks the exit code,
+//!   the wording, and which errors aren't errors (the broken pipe). One
+//!   place, `main`, does it; nothing below prints or exits
+//! - `anyhow` stays a good choice for application code that only reports
+//!   errors; typed errors pay off where someone branches on them
+//!
+//! ## Hints
+//! - `#[error("cannot open {}", .path.display())]`: fields in the message
+//! - `#[source]` (or `#[from]`, which implies it) fills in `source()`
+//! - `BufRead::lines()` reports bad UTF-8 as `io::ErrorKind::InvalidData`
+//! - `BufWriter` ignores errors when dropped: `flush()` and check it
+//! - Match guards pick exit codes: `Open { source, .. } if source.kind() == ...`
+//!
+//! ## Verification
+//! ```bash
+//! cargo test
+//! cargo run -- test.txt error -n
+//! cargo run -- missing.txt; echo $?          # 66
+//! cargo run -- /; echo $?                    # 74, "Is a directory"
+//! seq 100000 > /tmp/big.txt && cargo run -- /tmp/big.txt | head -1
+//! ```
+//!
+//! ## Acceptance Criteria
+//! - [ ] Same output as mini_cat for readable files
+//! - [ ] Every failure prints its source chain and exits with its own code
+//! - [ ] The library module has no `eprintln!` and no `exit`
+//! - [ ] `| head` doesn't panic or print an error
+//!
+//! Check solution/main.rs after completing
+
+use std::error::Error;
+use std::io::{self, BufWriter};
+use std::path::PathBuf;
+use thiserror::Error;
+
+use cat::{CatError, Options};
+
+// ============================================================
+// Library: typed errors, no printing, no exiting
+// ============================================================
+
+mod cat {
+    use std::io::{self, Write};
+    use std::path::{Path, PathBuf};
+    use thiserror::Error;
+
+    /// Everything `cat` can fail with
+    #[derive(Debug, Error)]
+    pub enum CatError {
+        #[error("cannot open {}", .path.display())]
+        Open {
+            path: PathBuf,
+            #[source]
+            source: io::Error,
+        },
+        // TODO: Read { path, line, source } and NotText { path, line, source },
+        //       each with an #[error(...)] message and #[source]
+        #[error("cannot write output")]
+        Write(#[source] io::Error),
+    }
+
+    pub struct Options<'a> {
+        /// Only lines containing this, ignoring case
+        pub keyword: Option<&'a str>,
+        pub line_numbers: bool,
+    }
+
+    /// Copy the lines of `path` that match to `out`; returns how many
+    pub fn cat(path: &Path, options: &Options, out: &mut impl Write) -> Result<usize, CatError> {
+        // TODO: Implement
+        //
+        // Suggested steps:
+        // 1. File::open(path), map_err into CatError::Open
+        // 2. BufReader::new(file).lines().enumerate(); map each line's error
+        //    into NotText (kind InvalidData) or Read, with the line number
+        // 3. Filter by keyword (case-insensitive), writeln! to out,
+        //    map_err(CatError::Write)
+        // 4. out.flush(), also mapped to Write
+        todo!("Implement cat")
+    }
+}
+
+// ============================================================
+// Application: decides what the user sees and the exit code
+// ============================================================
+
+/// Exit codes; sysexits.h where one fits
+const EXIT_NO_MATCH: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EX_DATAERR: i32 = 65;
+const EX_NOINPUT: i32 = 66;
+const EX_IOERR: i32 = 74;
+const EX_NOPERM: i32 = 77;
+
+#[derive(Debug, Error)]
+enum AppError {
+    #[error("{0}\nusage: typed_cat <file> [keyword] [-n]")]
+    Usage(String),
+    // TODO: Cat(CatError), with #[error(transparent)] and #[from]
+}
+
+impl AppError {
+    fn exit_code(&self) -> i32 {
+        // TODO: Usage -> 2, Open -> 66 (77 if PermissionDenied),
+        //       NotText -> 65, Read / Write -> 74
+        todo!("Implement exit_code")
+    }
+}
+
+struct Config {
+    path: PathBuf,
+    keyword: Option<String>,
+    line_numbers: bool,
+}
+
+fn parse_args(args: Vec<String>) -> Result<Config, AppError> {
+    let mut path = None;
+    let mut keyword = None;
+    let mut line_numbers = false;
+    for arg in args {
+        match arg.as_str() {
+            "-n" | "--line-numbers" => line_numbers = true,
+            s if s.starts_with('-') => {
+                return Err(AppError::Usage(format!("unknown option: {}", s)))
+            }
+            _ if path.is_none() => path = Some(PathBuf::from(arg)),
+            _ if keyword.is_none() => keyword = Some(arg),
+            _ => return Err(AppError::Usage(format!("unexpected argument: {}", arg))),
+        }
+    }
+    let path = path.ok_or_else(|| AppError::Usage("missing file".to_string()))?;
+    Ok(Config {
+        path,
+        keyword,
+        line_numbers,
+    })
+}
+
+/// The exit code on success: 0, or 1 if a keyword matched nothing
+fn run(args: Vec<String>) -> Result<i32, AppError> {
+    let config = parse_args(args)?;
+    let options = Options {
+        keyword: config.keyword.as_deref(),
+        line_numbers: config.line_numbers,
+    };
+    let mut out = BufWriter::new(io::stdout().lock());
+    // TODO: call cat::cat with `?` (needs the #[from] above), then pick
+    //       EXIT_NO_MATCH when a keyword printed nothing
+    todo!("Implement run")
+}
+
+/// The error, then each `source()` under it
+fn report(err: &dyn Error) {
+    // TODO: "typed_cat: {err}", then "  caused by: {cause}" while
+    //       source() returns Some
+    todo!("Implement report")
+}
+
+fn main() {
+    let code = match run(std::env::args().skip(1).collect()) {
+        Ok(code) => code,
+        // TODO: a Write error of kind BrokenPipe ends quietly with 0
+        Err(err) => {
+            report(&err);
+            err.exit_code()
+        }
+    };
+    std::process::exit(code);
+}
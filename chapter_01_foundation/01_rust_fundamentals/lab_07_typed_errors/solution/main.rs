@@ -0,0 +1,207 @@
+//! Lab 7 Reference Answer
+
+use std::error::Error;
+use std::io::{self, BufWriter};
+use std::path::PathBuf;
+use thiserror::Error;
+
+use cat::{CatError, Options};
+
+// ============================================================
+// Library: typed errors, no printing, no exiting
+// ============================================================
+
+mod cat {
+    use std::fs::File;
+    use std::io::{self, BufRead, BufReader, Write};
+    use std::path::{Path, PathBuf};
+    use thiserror::Error;
+
+    /// Everything `cat` can fail with. Each variant keeps what the caller
+    /// needs to act on it: which file, which line, and the io::Error
+    /// underneath as `source()`, not flattened into the message
+    #[derive(Debug, Error)]
+    pub enum CatError {
+        #[error("cannot open {}", .path.display())]
+        Open {
+            path: PathBuf,
+            #[source]
+            source: io::Error,
+        },
+        #[error("cannot read {} at line {line}", .path.display())]
+        Read {
+            path: PathBuf,
+            line: usize,
+            #[source]
+            source: io::Error,
+        },
+        #[error("{} is not text: line {line} is not valid UTF-8", .path.display())]
+        NotText {
+            path: PathBuf,
+            line: usize,
+            #[source]
+            source: io::Error,
+        },
+        #[error("cannot write output")]
+        Write(#[source] io::Error),
+    }
+
+    pub struct Options<'a> {
+        /// Only lines containing this, ignoring case
+        pub keyword: Option<&'a str>,
+        pub line_numbers: bool,
+    }
+
+    /// Copy the lines of `path` that match to `out`; returns how many
+    pub fn cat(path: &Path, options: &Options, out: &mut impl Write) -> Result<usize, CatError> {
+        let file = File::open(path).map_err(|source| CatError::Open {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let keyword = options.keyword.map(str::to_lowercase);
+
+        let mut printed = 0;
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let number = index + 1;
+            let line = line.map_err(|source| {
+                let path = path.to_path_buf();
+                // lines() reports bad UTF-8 as an io::Error of kind InvalidData
+                if source.kind() == io::ErrorKind::InvalidData {
+                    CatError::NotText {
+                        path,
+                        line: number,
+                        source,
+                    }
+                } else {
+                    CatError::Read {
+                        path,
+                        line: number,
+                        source,
+                    }
+                }
+            })?;
+
+            if let Some(keyword) = &keyword {
+                if !line.to_lowercase().contains(keyword) {
+                    continue;
+                }
+            }
+            let written = if options.line_numbers {
+                writeln!(out, "{:>3}: {}", number, line)
+            } else {
+                writeln!(out, "{}", line)
+            };
+            written.map_err(CatError::Write)?;
+            printed += 1;
+        }
+        // BufWriter ignores errors when dropped: flush to see them
+        out.flush().map_err(CatError::Write)?;
+        Ok(printed)
+    }
+}
+
+// ============================================================
+// Application: decides what the user sees and the exit code
+// ============================================================
+
+/// Exit codes; sysexits.h where one fits
+const EXIT_NO_MATCH: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EX_DATAERR: i32 = 65;
+const EX_NOINPUT: i32 = 66;
+const EX_IOERR: i32 = 74;
+const EX_NOPERM: i32 = 77;
+
+#[derive(Debug, Error)]
+enum AppError {
+    #[error("{0}\nusage: typed_cat <file> [keyword] [-n]")]
+    Usage(String),
+    /// Same message and source as the library error: no extra chain link
+    #[error(transparent)]
+    Cat(#[from] CatError),
+}
+
+impl AppError {
+    fn exit_code(&self) -> i32 {
+        match self {
+            AppError::Usage(_) => EXIT_USAGE,
+            AppError::Cat(CatError::Open { source, .. })
+                if source.kind() == io::ErrorKind::PermissionDenied =>
+            {
+                EX_NOPERM
+            }
+            AppError::Cat(CatError::Open { .. }) => EX_NOINPUT,
+            AppError::Cat(CatError::NotText { .. }) => EX_DATAERR,
+            AppError::Cat(CatError::Read { .. } | CatError::Write(_)) => EX_IOERR,
+        }
+    }
+}
+
+struct Config {
+    path: PathBuf,
+    keyword: Option<String>,
+    line_numbers: bool,
+}
+
+fn parse_args(args: Vec<String>) -> Result<Config, AppError> {
+    let mut path = None;
+    let mut keyword = None;
+    let mut line_numbers = false;
+    for arg in args {
+        match arg.as_str() {
+            "-n" | "--line-numbers" => line_numbers = true,
+            s if s.starts_with('-') => {
+                return Err(AppError::Usage(format!("unknown option: {}", s)))
+            }
+            _ if path.is_none() => path = Some(PathBuf::from(arg)),
+            _ if keyword.is_none() => keyword = Some(arg),
+            _ => return Err(AppError::Usage(format!("unexpected argument: {}", arg))),
+        }
+    }
+    let path = path.ok_or_else(|| AppError::Usage("missing file".to_string()))?;
+    Ok(Config {
+        path,
+        keyword,
+        line_numbers,
+    })
+}
+
+/// The exit code on success: 0, or 1 if a keyword matched nothing
+fn run(args: Vec<String>) -> Result<i32, AppError> {
+    let config = parse_args(args)?;
+    let options = Options {
+        keyword: config.keyword.as_deref(),
+        line_numbers: config.line_numbers,
+    };
+    let mut out = BufWriter::new(io::stdout().lock());
+    // `?` converts CatError into AppError through #[from]
+    let printed = cat::cat(&config.path, &options, &mut out)?;
+    Ok(if printed == 0 && options.keyword.is_some() {
+        EXIT_NO_MATCH
+    } else {
+        0
+    })
+}
+
+/// The error, then each `source()` under it
+fn report(err: &dyn Error) {
+    eprintln!("typed_cat: {}", err);
+    let mut source = err.source();
+    while let Some(cause) = source {
+        eprintln!("  caused by: {}", cause);
+        source = cause.source();
+    }
+}
+
+fn main() {
+    let code = match run(std::env::args().skip(1).collect()) {
+        Ok(code) => code,
+        // `| head` closed the pipe: nobody is reading, stop quietly
+        Err(AppError::Cat(CatError::Write(err))) if err.kind() == io::ErrorKind::BrokenPipe => 0,
+        Err(err) => {
+            report(&err);
+            err.exit_code()
+        }
+    };
+    std::process::exit(code);
+}
@@ -0,0 +1,149 @@
+//! Lab 7 Tests
+//!
+//! Run with: cargo test
+
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+
+/// Run `typed_cat <args>`: stdout, stderr, exit code
+fn run_typed_cat(args: &[&str]) -> (String, String, i32) {
+    let output = Command::new(env!("CARGO_BIN_EXE_typed_cat"))
+        .args(args)
+        .output()
+        .expect("Failed to execute program");
+
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    (stdout, stderr, output.status.code().unwrap_or(-1))
+}
+
+/// A file under the temp dir, unique to this test run
+fn temp_file(name: &str, contents: &[u8]) -> String {
+    let path = std::env::temp_dir().join(format!("typed_cat_{}_{}", std::process::id(), name));
+    std::fs::write(&path, contents).unwrap();
+    path.to_string_lossy().to_string()
+}
+
+#[test]
+fn test_01_same_output_as_mini_cat() {
+    let (stdout, stderr, code) = run_typed_cat(&["test.txt", "error", "-n"]);
+
+    assert_eq!(code, 0, "stderr: {}", stderr);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 4, "Four lines of test.txt contain 'error'");
+    assert_eq!(lines[0], "  2: Here comes an error message.");
+}
+
+#[test]
+fn test_02_no_match_exits_1() {
+    let (stdout, stderr, code) = run_typed_cat(&["test.txt", "no-such-word"]);
+
+    assert_eq!(code, 1, "Like grep, no match is exit 1");
+    assert!(
+        stdout.is_empty() && stderr.is_empty(),
+        "No match is not an error message"
+    );
+}
+
+#[test]
+fn test_03_missing_file_prints_chain() {
+    let (_, stderr, code) = run_typed_cat(&["this_file_does_not_exist.txt"]);
+
+    assert_eq!(code, 66, "EX_NOINPUT for a missing file: {}", stderr);
+    let lines: Vec<&str> = stderr.lines().collect();
+    assert_eq!(
+        lines[0],
+        "typed_cat: cannot open this_file_does_not_exist.txt"
+    );
+    assert!(
+        lines[1].starts_with("  caused by: No such file or directory"),
+        "The io::Error should follow as the cause: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_04_directory_is_a_read_error() {
+    let dir = std::env::temp_dir();
+    let (_, stderr, code) = run_typed_cat(&[dir.to_str().unwrap()]);
+
+    // Opening a directory works on Linux; the first read fails
+    assert_eq!(code, 74, "EX_IOERR: {}", stderr);
+    assert!(stderr.contains("at line 1"), "{}", stderr);
+    assert!(stderr.contains("caused by: Is a directory"), "{}", stderr);
+}
+
+#[test]
+fn test_05_invalid_utf8_names_the_line() {
+    let path = temp_file("bad.txt", b"fine\nstill fine\n\xff\xfe broken\n");
+    let (stdout, stderr, code) = run_typed_cat(&[&path]);
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(code, 65, "EX_DATAERR: {}", stderr);
+    assert!(stderr.contains("line 3 is not valid UTF-8"), "{}", stderr);
+    assert!(stderr.contains("caused by:"), "{}", stderr);
+    assert_eq!(
+        stdout, "fine\nstill fine\n",
+        "Lines before the bad one are printed"
+    );
+}
+
+#[test]
+fn test_06_permission_denied() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = temp_file("secret.txt", b"secret\n");
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o000)).unwrap();
+    // Skip as root: root can open it anyway
+    if std::fs::File::open(&path).is_ok() {
+        let _ = std::fs::remove_file(&path);
+        return;
+    }
+    let (_, stderr, code) = run_typed_cat(&[&path]);
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(code, 77, "EX_NOPERM: {}", stderr);
+    assert!(
+        stderr.contains("caused by: Permission denied"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn test_07_usage_errors() {
+    for args in [&[][..], &["test.txt", "error", "extra"], &["--bogus"]] {
+        let (_, stderr, code) = run_typed_cat(args);
+        assert_eq!(code, 2, "{:?} should be a usage error", args);
+        assert!(stderr.contains("usage: typed_cat"), "{}", stderr);
+    }
+}
+
+#[test]
+fn test_08_closed_pipe_is_quiet() {
+    let lines: String = (1..=200_000).map(|i| format!("line {}\n", i)).collect();
+    let path = temp_file("big.txt", lines.as_bytes());
+
+    // Like `typed_cat big.txt | head -1`
+    let mut child = Command::new(env!("CARGO_BIN_EXE_typed_cat"))
+        .arg(&path)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to execute program");
+    let mut first = String::new();
+    BufReader::new(child.stdout.take().unwrap())
+        .read_line(&mut first)
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(first, "line 1\n");
+    assert_eq!(
+        output.status.code(),
+        Some(0),
+        "A closed pipe is not a failure: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(output.stderr.is_empty(), "No panic, no error message");
+}
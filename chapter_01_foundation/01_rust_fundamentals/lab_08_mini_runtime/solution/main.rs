@@ -0,0 +1,129 @@
+//! Lab 8 Reference Answer
+
+// The runtime lives in src/; the paths work from solution/ and src/
+#[path = "../src/executor.rs"]
+mod executor;
+#[path = "../src/reactor.rs"]
+mod reactor;
+#[path = "../src/timer.rs"]
+mod timer;
+
+use executor::{block_on, spawn};
+use reactor::{TcpListener, TcpStream};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+struct Config {
+    addr: SocketAddr,
+    idle: Duration,
+    /// Exit after this many clients
+    connections: Option<usize>,
+}
+
+fn usage() -> String {
+    "usage: mini_runtime [--addr ADDR] [--idle-ms MS] [--connections N] [--trace]".to_string()
+}
+
+fn parse_args(args: Vec<String>) -> Result<Config, String> {
+    let mut config = Config {
+        addr: ([127, 0, 0, 1], 8080).into(),
+        idle: Duration::from_secs(10),
+        connections: None,
+    };
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--trace" {
+            executor::TRACE.store(true, Ordering::Relaxed);
+            continue;
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value", arg))?;
+        let bad = || format!("invalid {}: {}", arg, value);
+        match arg.as_str() {
+            "--addr" => {
+                config.addr = value
+                    .to_socket_addrs()
+                    .ok()
+                    .and_then(|mut addrs| addrs.next())
+                    .ok_or_else(bad)?
+            }
+            "--idle-ms" => config.idle = Duration::from_millis(value.parse().map_err(|_| bad())?),
+            "--connections" => config.connections = Some(value.parse().map_err(|_| bad())?),
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+    Ok(config)
+}
+
+/// Echo until the client closes, fails, or stays quiet for `idle`
+async fn echo(mut stream: TcpStream, peer: SocketAddr, idle: Duration) {
+    let mut buf = [0u8; 1024];
+    loop {
+        let n = match timer::timeout(idle, stream.read(&mut buf)).await {
+            None => {
+                println!("{} idle for {:?}: closing", peer, idle);
+                return;
+            }
+            Some(Ok(0)) => {
+                println!("{} closed the connection", peer);
+                return;
+            }
+            Some(Ok(n)) => n,
+            Some(Err(err)) => {
+                println!("{} read error: {}", peer, err);
+                return;
+            }
+        };
+        if let Err(err) = stream.write_all(&buf[..n]).await {
+            println!("{} write error: {}", peer, err);
+            return;
+        }
+    }
+}
+
+async fn serve(config: Config) {
+    let listener = TcpListener::bind(config.addr).expect("bind failed");
+    println!(
+        "Echo server on {} (one thread, mini runtime)",
+        listener.local_addr().unwrap()
+    );
+
+    let mut sessions = Vec::new();
+    while config.connections.is_none_or(|max| sessions.len() < max) {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                println!("{} connected", peer);
+                sessions.push(spawn(echo(stream, peer, config.idle)));
+            }
+            Err(err) => eprintln!("accept error: {}", err),
+        }
+    }
+    for session in sessions {
+        session.await;
+    }
+}
+
+fn main() {
+    let config = match parse_args(std::env::args().skip(1).collect()) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}\n{}", err, usage());
+            std::process::exit(2);
+        }
+    };
+
+    block_on(serve(config));
+
+    let (tasks, polls) = executor::stats();
+    let (turns, io_wakeups) = reactor::stats();
+    println!(
+        "Runtime: {} tasks, {} polls, {} I/O wake-ups, {} timer wake-ups, {} epoll_wait calls",
+        tasks,
+        polls,
+        io_wakeups,
+        timer::fired(),
+        turns
+    );
+}
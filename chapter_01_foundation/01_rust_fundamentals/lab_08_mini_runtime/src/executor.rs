@@ -0,0 +1,183 @@
+//! The executor: tasks, wakers and the run loop
+//!
+//! A task is a boxed future plus a way back into the queue. Polling it
+//! hands the future a `Waker` made from the task's own `Arc`; whoever the
+//! future is waiting on (the reactor for a socket, the timer wheel for a
+//! deadline) keeps that waker and calls `wake()` when it's time. Waking
+//! sends the task back into the ready queue, and the run loop polls it
+//! again. Nothing is ever polled "just in case".
+//!
+//! `block_on` is the whole runtime: run every ready task, and when none
+//! is left, block in the reactor until a socket is ready or the next timer
+//! is due. Tokio does the same, with a queue per worker thread.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+
+use crate::{reactor, timer};
+
+type BoxFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// `--trace`: print every poll, wake and reactor event
+pub static TRACE: AtomicBool = AtomicBool::new(false);
+
+static SPAWNED: AtomicUsize = AtomicUsize::new(0);
+static POLLS: AtomicUsize = AtomicUsize::new(0);
+
+pub fn trace(args: fmt::Arguments) {
+    if TRACE.load(Ordering::Relaxed) {
+        println!("[trace] {}", args);
+    }
+}
+
+struct Task {
+    id: usize,
+    /// `None` once the future has finished
+    future: Mutex<Option<BoxFuture>>,
+    queue: Sender<Arc<Task>>,
+}
+
+impl Wake for Task {
+    fn wake(self: Arc<Self>) {
+        trace(format_args!("wake task {}", self.id));
+        let queue = self.queue.clone();
+        // Fails only once block_on has returned: nothing will run it then
+        let _ = queue.send(self);
+    }
+}
+
+impl Task {
+    fn run(self: &Arc<Self>) {
+        let mut slot = self.future.lock().unwrap();
+        // Woken twice, or after it finished: nothing to do
+        let Some(mut future) = slot.take() else {
+            return;
+        };
+        POLLS.fetch_add(1, Ordering::Relaxed);
+        let waker = Waker::from(Arc::clone(self));
+        let mut cx = Context::from_waker(&waker);
+        match future.as_mut().poll(&mut cx) {
+            Poll::Pending => {
+                trace(format_args!("poll task {} -> Pending", self.id));
+                *slot = Some(future);
+            }
+            Poll::Ready(()) => trace(format_args!("poll task {} -> Ready", self.id)),
+        }
+    }
+}
+
+thread_local! {
+    /// The ready queue of the `block_on` running on this thread
+    static QUEUE: RefCell<Option<Sender<Arc<Task>>>> = const { RefCell::new(None) };
+}
+
+struct JoinState<T> {
+    output: Option<T>,
+    /// The task awaiting the handle
+    waker: Option<Waker>,
+}
+
+/// Resolves to the output of a spawned task
+pub struct JoinHandle<T> {
+    state: Arc<Mutex<JoinState<T>>>,
+}
+
+impl<T> JoinHandle<T> {
+    fn try_take(&self) -> Option<T> {
+        self.state.lock().unwrap().output.take()
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut state = self.state.lock().unwrap();
+        match state.output.take() {
+            Some(output) => Poll::Ready(output),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Run `future` as a task of its own; it starts at the next turn of the
+/// run loop, whether or not anyone awaits the handle
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let state = Arc::new(Mutex::new(JoinState {
+        output: None,
+        waker: None,
+    }));
+    let done = Arc::clone(&state);
+    let wrapped = async move {
+        let output = future.await;
+        let mut state = done.lock().unwrap();
+        state.output = Some(output);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    };
+
+    let queue = QUEUE
+        .with(|queue| queue.borrow().clone())
+        .expect("spawn() called outside block_on()");
+    let task = Arc::new(Task {
+        id: SPAWNED.fetch_add(1, Ordering::Relaxed) + 1,
+        future: Mutex::new(Some(Box::pin(wrapped))),
+        queue: queue.clone(),
+    });
+    trace(format_args!("spawn task {}", task.id));
+    let _ = queue.send(task);
+    JoinHandle { state }
+}
+
+/// Run `future`, and every task it spawns, on this thread until `future`
+/// finishes. Tasks still pending then are dropped
+pub fn block_on<F>(future: F) -> F::Output
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let (queue, ready) = mpsc::channel();
+    QUEUE.with(|slot| *slot.borrow_mut() = Some(queue));
+    let main = spawn(future);
+
+    loop {
+        // 1. Poll everything that was woken
+        while let Ok(task) = ready.try_recv() {
+            task.run();
+        }
+        if let Some(output) = main.try_take() {
+            QUEUE.with(|slot| slot.borrow_mut().take());
+            return output;
+        }
+
+        // 2. Nothing to run: sleep until a socket is ready or a timer is due
+        let timeout = timer::next_timeout();
+        if timeout.is_none() && !reactor::has_waiters() {
+            panic!("every task is waiting, but not on a socket or a timer: nothing can wake them");
+        }
+        reactor::turn(timeout);
+        timer::fire_due();
+    }
+}
+
+/// (tasks spawned, polls)
+pub fn stats() -> (usize, usize) {
+    (
+        SPAWNED.load(Ordering::Relaxed),
+        POLLS.load(Ordering::Relaxed),
+    )
+}
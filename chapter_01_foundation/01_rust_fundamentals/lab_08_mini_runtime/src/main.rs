@@ -0,0 +1,189 @@
+//! Lab 8: Async Runtime from Scratch
+//!
+//! ## Goal
+//! Build the parts of Tokio every later lab relies on, small enough to
+//! read in one sitting: an executor, wakers, a timer wheel and a reactor,
+//! then run a TCP echo server on them, on one thread
+//!
+//! ## Requirements
+//! 1. `src/executor.rs`: a task is a boxed future plus a sender into the
+//!    ready queue. `impl Wake for Task` so `Waker::from(Arc<Task>)` works;
+//!    waking sends the task back into the queue. `spawn` returns a
+//!    `JoinHandle` you can `.await`, `block_on` runs the loop
+//! 2. `src/timer.rs`: a hashed timing wheel (10ms ticks, 64 slots) behind
+//!    `sleep(duration)` and `timeout(duration, future)`
+//! 3. `src/reactor.rs`: a `mio::Poll` that keeps one waker per socket and
+//!    direction; `TcpListener::accept`, `TcpStream::read` and `write_all`
+//!    try the syscall and, on `WouldBlock`, register and return `Pending`
+//! 4. The run loop: poll every ready task; when none is left, block in the
+//!    reactor until a socket is ready or the next timer is due, fire the
+//!    due timers, repeat
+//! 5. `mini_runtime [--addr ADDR] [--idle-ms MS] [--connections N] [--trace]`:
+//!    echo every connection, close it after MS of silence, and with
+//!    `--connections` exit after N clients are done, printing the
+//!    runtime's counters
+//!
+//! ## Expected Behavior
+//! ```
+//! $ cargo run -- --connections 1
+//! Echo server on 127.0.0.1:8080 (one thread, mini runtime)
+//! 127.0.0.1:51234 connected
+//! 127.0.0.1:51234 closed the connection
+//! Runtime: 2 tasks, 7 polls, 5 I/O wake-ups, 0 timer wake-ups, 6 epoll_wait calls
+//! ```
+//!
+//! ## What Tokio Adds
+//! - several worker threads, each with its own queue, stealing from the
+//!   others when idle
+//! - timers on a multi-level wheel, I/O drivers for files, signals and
+//!   processes, `select!`, cancellation, cooperative budgeting
+//! - the same three pieces at the core: `Waker`s, a ready queue, and one
+//!   thread blocked in epoll when there is nothing to do
+//!
+//! ## Hints
+//! - `std::task::Wake` turns `Arc<Task>` into a `Waker`; that's why the
+//!   future inside needs `Send` and a `Mutex`
+//! - `std::future::poll_fn` writes a one-off future from a closure
+//! - mio is edge-triggered: retry the syscall until `WouldBlock`
+//! - No ready tasks, no timers and no socket waiters means nothing can
+//!   ever wake up again: panic instead of blocking forever
+//!
+//! ## Verification
+//! ```bash
+//! cargo test
+//! cargo run -- --trace --idle-ms 5000
+//! nc localhost 8080                          # type; wait 5s to be dropped
+//! strace -f -e trace=epoll_wait,accept4,recvfrom,sendto ./target/debug/mini_runtime
+//! ```
+//!
+//! ## Acceptance Criteria
+//! - [ ] Two clients are echoed concurrently on one thread
+//! - [ ] An idle client is closed by the timer, not by a blocked read
+//! - [ ] With `--trace`, every poll follows a wake (or a spawn)
+//! - [ ] Can explain who calls `wake()` for a socket and for a timer
+//!
+//! Check solution/main.rs after completing
+
+mod executor;
+mod reactor;
+mod timer;
+
+use executor::{block_on, spawn};
+use reactor::{TcpListener, TcpStream};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+struct Config {
+    addr: SocketAddr,
+    idle: Duration,
+    /// Exit after this many clients
+    connections: Option<usize>,
+}
+
+fn usage() -> String {
+    "usage: mini_runtime [--addr ADDR] [--idle-ms MS] [--connections N] [--trace]".to_string()
+}
+
+fn parse_args(args: Vec<String>) -> Result<Config, String> {
+    let mut config = Config {
+        addr: ([127, 0, 0, 1], 8080).into(),
+        idle: Duration::from_secs(10),
+        connections: None,
+    };
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--trace" {
+            executor::TRACE.store(true, Ordering::Relaxed);
+            continue;
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value", arg))?;
+        let bad = || format!("invalid {}: {}", arg, value);
+        match arg.as_str() {
+            "--addr" => {
+                config.addr = value
+                    .to_socket_addrs()
+                    .ok()
+                    .and_then(|mut addrs| addrs.next())
+                    .ok_or_else(bad)?
+            }
+            "--idle-ms" => config.idle = Duration::from_millis(value.parse().map_err(|_| bad())?),
+            "--connections" => config.connections = Some(value.parse().map_err(|_| bad())?),
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+    Ok(config)
+}
+
+/// Echo until the client closes, fails, or stays quiet for `idle`
+async fn echo(mut stream: TcpStream, peer: SocketAddr, idle: Duration) {
+    let mut buf = [0u8; 1024];
+    loop {
+        let n = match timer::timeout(idle, stream.read(&mut buf)).await {
+            None => {
+                println!("{} idle for {:?}: closing", peer, idle);
+                return;
+            }
+            Some(Ok(0)) => {
+                println!("{} closed the connection", peer);
+                return;
+            }
+            Some(Ok(n)) => n,
+            Some(Err(err)) => {
+                println!("{} read error: {}", peer, err);
+                return;
+            }
+        };
+        if let Err(err) = stream.write_all(&buf[..n]).await {
+            println!("{} write error: {}", peer, err);
+            return;
+        }
+    }
+}
+
+async fn serve(config: Config) {
+    let listener = TcpListener::bind(config.addr).expect("bind failed");
+    println!(
+        "Echo server on {} (one thread, mini runtime)",
+        listener.local_addr().unwrap()
+    );
+
+    let mut sessions = Vec::new();
+    while config.connections.is_none_or(|max| sessions.len() < max) {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                println!("{} connected", peer);
+                sessions.push(spawn(echo(stream, peer, config.idle)));
+            }
+            Err(err) => eprintln!("accept error: {}", err),
+        }
+    }
+    for session in sessions {
+        session.await;
+    }
+}
+
+fn main() {
+    let config = match parse_args(std::env::args().skip(1).collect()) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}\n{}", err, usage());
+            std::process::exit(2);
+        }
+    };
+
+    block_on(serve(config));
+
+    let (tasks, polls) = executor::stats();
+    let (turns, io_wakeups) = reactor::stats();
+    println!(
+        "Runtime: {} tasks, {} polls, {} I/O wake-ups, {} timer wake-ups, {} epoll_wait calls",
+        tasks,
+        polls,
+        io_wakeups,
+        timer::fired(),
+        turns
+    );
+}
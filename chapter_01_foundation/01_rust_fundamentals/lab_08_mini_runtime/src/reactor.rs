@@ -0,0 +1,233 @@
+//! The reactor: one epoll for every socket, via mio
+//!
+//! Sockets are non-blocking. A future tries the syscall first; on
+//! `WouldBlock` it leaves its waker with the reactor under the socket's
+//! token and returns `Pending`. When the executor has nothing left to run
+//! it calls `turn`, which blocks in `epoll_wait` (`mio::Poll::poll`) and
+//! wakes the tasks whose sockets became ready.
+//!
+//! mio is edge-triggered: an event means "something changed", not "there
+//! is data". That's why the futures always retry the syscall until it
+//! says `WouldBlock` before waiting again, instead of trusting an event.
+
+use mio::event::Source;
+use mio::{Events, Interest, Poll, Token};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::future::poll_fn;
+use std::io::{self, Read, Write};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::{self, Waker};
+use std::time::Duration;
+
+use crate::executor::trace;
+
+static TURNS: AtomicUsize = AtomicUsize::new(0);
+static WAKEUPS: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Clone, Copy)]
+enum Direction {
+    Read,
+    Write,
+}
+
+/// The tasks waiting on one socket
+#[derive(Default)]
+struct Waiters {
+    read: Option<Waker>,
+    write: Option<Waker>,
+}
+
+struct Reactor {
+    poll: Poll,
+    events: Events,
+    waiters: HashMap<Token, Waiters>,
+    next_token: usize,
+}
+
+thread_local! {
+    static REACTOR: RefCell<Option<Reactor>> = const { RefCell::new(None) };
+}
+
+/// The reactor of this thread, created on first use
+fn with<R>(f: impl FnOnce(&mut Reactor) -> R) -> R {
+    REACTOR.with(|cell| {
+        let mut cell = cell.borrow_mut();
+        let reactor = cell.get_or_insert_with(|| Reactor {
+            poll: Poll::new().expect("epoll_create failed"),
+            events: Events::with_capacity(64),
+            waiters: HashMap::new(),
+            next_token: 0,
+        });
+        f(reactor)
+    })
+}
+
+fn register(source: &mut impl Source, interest: Interest) -> io::Result<Token> {
+    with(|reactor| {
+        let token = Token(reactor.next_token);
+        reactor.next_token += 1;
+        reactor.poll.registry().register(source, token, interest)?;
+        reactor.waiters.insert(token, Waiters::default());
+        Ok(token)
+    })
+}
+
+fn deregister(source: &mut impl Source, token: Token) {
+    with(|reactor| {
+        let _ = reactor.poll.registry().deregister(source);
+        reactor.waiters.remove(&token);
+    });
+}
+
+fn wait(token: Token, direction: Direction, waker: &Waker) {
+    with(|reactor| {
+        let waiters = reactor.waiters.entry(token).or_default();
+        let slot = match direction {
+            Direction::Read => &mut waiters.read,
+            Direction::Write => &mut waiters.write,
+        };
+        *slot = Some(waker.clone());
+    });
+}
+
+/// Is any task waiting on a socket?
+pub fn has_waiters() -> bool {
+    with(|reactor| {
+        reactor
+            .waiters
+            .values()
+            .any(|w| w.read.is_some() || w.write.is_some())
+    })
+}
+
+/// Block until a socket is ready (or `timeout`), then wake its tasks
+pub fn turn(timeout: Option<Duration>) {
+    with(|reactor| {
+        TURNS.fetch_add(1, Ordering::Relaxed);
+        if let Err(err) = reactor.poll.poll(&mut reactor.events, timeout) {
+            if err.kind() != io::ErrorKind::Interrupted {
+                panic!("epoll_wait failed: {}", err);
+            }
+        }
+        for event in reactor.events.iter() {
+            let Some(waiters) = reactor.waiters.get_mut(&event.token()) else {
+                continue;
+            };
+            trace(format_args!(
+                "reactor: token {} readable={} writable={}",
+                event.token().0,
+                event.is_readable(),
+                event.is_writable()
+            ));
+            // Closed and failed sockets wake both sides: the retry sees why
+            let failed = event.is_error();
+            if event.is_readable() || event.is_read_closed() || failed {
+                if let Some(waker) = waiters.read.take() {
+                    WAKEUPS.fetch_add(1, Ordering::Relaxed);
+                    waker.wake();
+                }
+            }
+            if event.is_writable() || event.is_write_closed() || failed {
+                if let Some(waker) = waiters.write.take() {
+                    WAKEUPS.fetch_add(1, Ordering::Relaxed);
+                    waker.wake();
+                }
+            }
+        }
+    });
+}
+
+/// (epoll_wait calls, tasks woken by I/O)
+pub fn stats() -> (usize, usize) {
+    (
+        TURNS.load(Ordering::Relaxed),
+        WAKEUPS.load(Ordering::Relaxed),
+    )
+}
+
+/// `Ready(result)` unless it would block: then wait for `direction`
+fn retry<T>(
+    result: io::Result<T>,
+    token: Token,
+    direction: Direction,
+    cx: &mut task::Context<'_>,
+) -> task::Poll<io::Result<T>> {
+    match result {
+        Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+            wait(token, direction, cx.waker());
+            task::Poll::Pending
+        }
+        Err(err) if err.kind() == io::ErrorKind::Interrupted => {
+            // Try again straight away
+            cx.waker().wake_by_ref();
+            task::Poll::Pending
+        }
+        result => task::Poll::Ready(result),
+    }
+}
+
+pub struct TcpListener {
+    inner: mio::net::TcpListener,
+    token: Token,
+}
+
+impl TcpListener {
+    pub fn bind(addr: SocketAddr) -> io::Result<Self> {
+        let mut inner = mio::net::TcpListener::bind(addr)?;
+        let token = register(&mut inner, Interest::READABLE)?;
+        Ok(Self { inner, token })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    pub async fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
+        let (stream, peer) =
+            poll_fn(|cx| retry(self.inner.accept(), self.token, Direction::Read, cx)).await?;
+        Ok((TcpStream::new(stream)?, peer))
+    }
+}
+
+impl Drop for TcpListener {
+    fn drop(&mut self) {
+        deregister(&mut self.inner, self.token);
+    }
+}
+
+pub struct TcpStream {
+    inner: mio::net::TcpStream,
+    token: Token,
+}
+
+impl TcpStream {
+    fn new(mut inner: mio::net::TcpStream) -> io::Result<Self> {
+        let token = register(&mut inner, Interest::READABLE | Interest::WRITABLE)?;
+        Ok(Self { inner, token })
+    }
+
+    /// Some bytes, or 0 at end of stream
+    pub async fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        poll_fn(|cx| retry(self.inner.read(buf), self.token, Direction::Read, cx)).await
+    }
+
+    pub async fn write_all(&mut self, mut buf: &[u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            let n = poll_fn(|cx| retry(self.inner.write(buf), self.token, Direction::Write, cx))
+                .await?;
+            if n == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+            buf = &buf[n..];
+        }
+        Ok(())
+    }
+}
+
+impl Drop for TcpStream {
+    fn drop(&mut self) {
+        deregister(&mut self.inner, self.token);
+    }
+}
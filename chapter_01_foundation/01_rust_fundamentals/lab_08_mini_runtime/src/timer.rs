@@ -0,0 +1,165 @@
+//! Timers: a hashed timing wheel
+//!
+//! Time is cut into ticks of `TICK`; a timer due in tick `t` goes into
+//! slot `t % SLOTS`. Adding a timer and firing a slot are O(1) however
+//! many timers exist, which is why kernels and Tokio use wheels instead
+//! of a sorted list. A timer further away than one turn of the wheel
+//! shares a slot with nearer ones and is simply kept until its own turn
+//! comes round.
+//!
+//! Timers never fire early (deadlines round up to the next tick) and up
+//! to one tick late. The executor asks `next_timeout` how long the
+//! reactor may block, then calls `fire_due`.
+
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+use crate::executor::trace;
+
+const TICK: Duration = Duration::from_millis(10);
+const SLOTS: u64 = 64;
+
+static FIRED: AtomicUsize = AtomicUsize::new(0);
+
+struct Entry {
+    deadline: Instant,
+    waker: Waker,
+}
+
+struct TimerWheel {
+    start: Instant,
+    /// First tick not fired yet
+    current: u64,
+    slots: Vec<Vec<Entry>>,
+    len: usize,
+}
+
+impl TimerWheel {
+    fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            current: 0,
+            slots: (0..SLOTS).map(|_| Vec::new()).collect(),
+            len: 0,
+        }
+    }
+
+    fn ticks(&self, at: Instant) -> u64 {
+        (at.saturating_duration_since(self.start).as_nanos() / TICK.as_nanos()) as u64
+    }
+
+    fn insert(&mut self, deadline: Instant, waker: Waker) {
+        // Round up, and never into a tick already fired
+        let tick = (self.ticks(deadline) + 1).max(self.current);
+        self.slots[(tick % SLOTS) as usize].push(Entry { deadline, waker });
+        self.len += 1;
+    }
+
+    fn fire_due(&mut self, now: Instant) {
+        let now_tick = self.ticks(now);
+        // After a long block, one pass over the wheel covers every slot
+        let passed = (now_tick + 1).saturating_sub(self.current).min(SLOTS);
+        for tick in self.current..self.current + passed {
+            let slot = &mut self.slots[(tick % SLOTS) as usize];
+            let (due, later): (Vec<Entry>, Vec<Entry>) = std::mem::take(slot)
+                .into_iter()
+                .partition(|e| e.deadline <= now);
+            *slot = later;
+            self.len -= due.len();
+            for entry in due {
+                FIRED.fetch_add(1, Ordering::Relaxed);
+                trace(format_args!("timer fired"));
+                entry.waker.wake();
+            }
+        }
+        self.current = self.current.max(now_tick + 1);
+    }
+
+    /// Until the first non-empty slot; may be early if that slot's timer
+    /// is a turn of the wheel away, which only costs an extra loop
+    fn next_timeout(&self, now: Instant) -> Option<Duration> {
+        if self.len == 0 {
+            return None;
+        }
+        let tick = (self.current..self.current + SLOTS)
+            .find(|tick| !self.slots[(tick % SLOTS) as usize].is_empty())?;
+        let at = self.start + TICK * tick as u32;
+        Some(at.saturating_duration_since(now))
+    }
+}
+
+thread_local! {
+    static WHEEL: RefCell<TimerWheel> = RefCell::new(TimerWheel::new());
+}
+
+/// How long the reactor may block; `None` if no timer is set
+pub fn next_timeout() -> Option<Duration> {
+    WHEEL.with(|wheel| wheel.borrow().next_timeout(Instant::now()))
+}
+
+/// Wake every timer whose deadline has passed
+pub fn fire_due() {
+    WHEEL.with(|wheel| wheel.borrow_mut().fire_due(Instant::now()));
+}
+
+pub fn fired() -> usize {
+    FIRED.load(Ordering::Relaxed)
+}
+
+/// Completes at `deadline`
+pub struct Sleep {
+    deadline: Instant,
+}
+
+pub fn sleep(duration: Duration) -> Sleep {
+    Sleep {
+        deadline: Instant::now() + duration,
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if Instant::now() >= self.deadline {
+            return Poll::Ready(());
+        }
+        // Register on every Pending poll: the waker may have changed. An
+        // extra entry only means an extra wake
+        WHEEL.with(|wheel| wheel.borrow_mut().insert(self.deadline, cx.waker().clone()));
+        Poll::Pending
+    }
+}
+
+/// `future`, unless `duration` passes first
+pub struct Timeout<F> {
+    future: Pin<Box<F>>,
+    sleep: Sleep,
+}
+
+/// `Some(output)`, or `None` if the future took longer than `duration`
+pub fn timeout<F: Future>(duration: Duration, future: F) -> Timeout<F> {
+    Timeout {
+        future: Box::pin(future),
+        sleep: sleep(duration),
+    }
+}
+
+impl<F: Future> Future for Timeout<F> {
+    type Output = Option<F::Output>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Both get the same waker: whichever is ready first wakes the task
+        if let Poll::Ready(output) = self.future.as_mut().poll(cx) {
+            return Poll::Ready(Some(output));
+        }
+        match Pin::new(&mut self.sleep).poll(cx) {
+            Poll::Ready(()) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
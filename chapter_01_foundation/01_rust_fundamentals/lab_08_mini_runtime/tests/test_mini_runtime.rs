@@ -0,0 +1,147 @@
+//! Lab 8 Tests
+//!
+//! Run with: cargo test
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::process::{Child, Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// `mini_runtime --addr 127.0.0.1:<port> <args>`, stdout captured
+fn start(port: u16, args: &[&str]) -> Child {
+    let child = Command::new(env!("CARGO_BIN_EXE_mini_runtime"))
+        .args(["--addr", &format!("127.0.0.1:{}", port)])
+        .args(args)
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start mini_runtime");
+    thread::sleep(Duration::from_millis(300));
+    child
+}
+
+fn connect(port: u16) -> TcpStream {
+    let stream = TcpStream::connect(("127.0.0.1", port)).expect("connect failed");
+    stream
+        .set_read_timeout(Some(Duration::from_secs(3)))
+        .unwrap();
+    stream
+}
+
+fn echo(stream: &mut TcpStream, msg: &[u8]) -> Vec<u8> {
+    stream.write_all(msg).unwrap();
+    let mut buf = vec![0u8; msg.len()];
+    stream.read_exact(&mut buf).expect("no echo");
+    buf
+}
+
+/// Wait for the server to exit by itself (it was given --connections)
+fn finish(child: Child) -> Output {
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "mini_runtime failed");
+    output
+}
+
+#[test]
+fn test_01_echo_round_trip() {
+    let child = start(8144, &["--connections", "1"]);
+
+    let mut client = connect(8144);
+    assert_eq!(echo(&mut client, b"hello runtime"), b"hello runtime");
+    drop(client);
+
+    let stdout = String::from_utf8_lossy(&finish(child).stdout).to_string();
+    assert!(stdout.contains("closed the connection"), "{}", stdout);
+    assert!(
+        stdout.contains("Runtime: 2 tasks"),
+        "main + one session: {}",
+        stdout
+    );
+}
+
+#[test]
+fn test_02_clients_served_concurrently() {
+    let child = start(8145, &["--connections", "2"]);
+
+    // The first client stays silent; the second must not wait for it
+    let mut first = connect(8145);
+    let mut second = connect(8145);
+    assert_eq!(echo(&mut second, b"second"), b"second");
+    assert_eq!(echo(&mut first, b"first"), b"first");
+    assert_eq!(echo(&mut second, b"again"), b"again");
+    drop(first);
+    drop(second);
+
+    let stdout = String::from_utf8_lossy(&finish(child).stdout).to_string();
+    assert_eq!(
+        stdout.matches("closed the connection").count(),
+        2,
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn test_03_idle_client_closed_by_timer() {
+    let child = start(8146, &["--connections", "1", "--idle-ms", "300"]);
+
+    let mut client = connect(8146);
+    assert_eq!(echo(&mut client, b"ping"), b"ping");
+    let start = Instant::now();
+    let mut buf = [0u8; 16];
+    let n = client
+        .read(&mut buf)
+        .expect("server should close, not time out");
+    let waited = start.elapsed();
+    assert_eq!(n, 0, "Expected end of stream");
+    assert!(
+        waited >= Duration::from_millis(280) && waited < Duration::from_millis(1500),
+        "closed after {:?}, want about 300ms",
+        waited
+    );
+
+    let stdout = String::from_utf8_lossy(&finish(child).stdout).to_string();
+    assert!(stdout.contains("idle for 300ms: closing"), "{}", stdout);
+    assert!(!stdout.contains("0 timer wake-ups"), "{}", stdout);
+}
+
+#[test]
+fn test_04_trace_shows_wakes_and_polls() {
+    let child = start(8147, &["--connections", "1", "--trace"]);
+
+    let mut client = connect(8147);
+    assert_eq!(echo(&mut client, b"traced"), b"traced");
+    // Long enough for the session to find nothing to read and wait
+    thread::sleep(Duration::from_millis(200));
+    drop(client);
+
+    let stdout = String::from_utf8_lossy(&finish(child).stdout).to_string();
+    for line in [
+        "[trace] spawn task 2",
+        "[trace] reactor: token",
+        "[trace] wake task 2",
+        "[trace] poll task 2 -> Ready",
+    ] {
+        assert!(stdout.contains(line), "{} missing:\n{}", line, stdout);
+    }
+}
+
+#[test]
+fn test_05_invalid_args() {
+    for args in [
+        &["--idle-ms"][..],
+        &["--connections", "many"],
+        &["--bogus", "1"],
+    ] {
+        let output = Command::new(env!("CARGO_BIN_EXE_mini_runtime"))
+            .args(args)
+            .output()
+            .expect("Failed to execute program");
+        assert_eq!(
+            output.status.code(),
+            Some(2),
+            "{:?} should be rejected",
+            args
+        );
+    }
+}
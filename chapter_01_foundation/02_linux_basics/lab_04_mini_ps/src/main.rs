@@ -37,8 +37,167 @@
 //! Warning: This lab requires a Linux environment (WSL2, Docker, or native Linux)
 //!
 //! Check solution/main.rs after completing
+//!
+//! ## Extension: %CPU and Sorting
+//! `cargo run -- [--sort cpu|mem|pid] [--top N]`, a ps/top hybrid:
+//! - `%CPU` comes from `/proc/[pid]/stat`: utime + stime, in clock ticks,
+//!   read twice `SAMPLE_INTERVAL` apart. Like top, 100% is one CPU busy
+//!   for the whole interval
+//! - The ticks per second aren't needed: the `cpu` line of `/proc/stat`
+//!   counts the same ticks for all CPUs together, so a process's share of
+//!   that total, times the number of CPUs, is its %CPU
+//! - `--sort cpu` and `--sort mem` put the busiest and the largest first,
+//!   `--sort pid` (the default) is the usual order; `--top N` keeps the
+//!   first N rows
+//! ```text
+//! $ cargo run -- --sort cpu --top 3
+//!     PID    PPID STATE   MEMORY   %CPU   COMMAND
+//! ------------------------------------------------------------
+//!    4242    4100     R     2.1M   99.0   ./busy_loop
+//!     812       1     S    45.3M    1.0   /usr/bin/containerd
+//!       1       0     S    11.9M    0.0   /sbin/init
+//! ```
+//!
+//! ## Extension: Process Tree
+//! `cargo run -- --tree [--sort cpu|mem|pid]` prints the processes as a
+//! tree like pstree: every process under its PPid, siblings in the sort
+//! order (see `src/tree.rs`). A scan isn't atomic, so the tree must cope
+//! with what changes during it:
+//! - a parent that exited before it was read: its children become roots,
+//!   marked "parent N gone" (they've really been reparented by now)
+//! - a PID reused mid-scan can close a loop of PPids with no root above
+//!   it: each loop is printed once from its lowest PID, marked "PPid cycle"
+//! ```text
+//! $ cargo run -- --tree
+//! ├─ systemd (1)
+//! │  ├─ sshd (123)
+//! │  │  └─ bash (456)
+//! │  │     └─ mini_ps (789)
+//! │  └─ nginx (234)
+//! └─ kthreadd (2)
+//! ```
+//!
+//! ## Extension: Watch Mode
+//! `cargo run -- --watch SECS [--sort ...] [--top N] [--iterations N]`
+//! redraws the table every SECS seconds, like top (see `src/watch.rs`):
+//! - each frame's %CPU covers the time since the previous frame, so the
+//!   interval is also the CPU sampling window
+//! - the PIDs of consecutive scans are diffed: processes new since the
+//!   last frame are marked `+` (green), the ones that exited are listed
+//!   once more at the bottom, marked `-` (red)
+//! - on a terminal each frame clears the screen; piped, frames are just
+//!   printed one after another, without colours
+//! ```text
+//! Every 2s: 131 processes, 1 new, 1 exited (Ctrl-C to quit)
+//!       PID    PPID STATE   MEMORY   %CPU   COMMAND
+//! ------------------------------------------------------------
+//!         1       0     S    11.9M    0.0   /sbin/init
+//!   ...
+//! +    4242    4100     R     2.1M   99.0   ./busy_loop
+//! -    4201    4100     S     1.3M    0.0   sleep 1
+//! ```
+//! Polling is all /proc offers: a process that starts and exits between
+//! two frames is never seen. And a child that exited but wasn't waited for
+//! stays in /proc as a zombie (state Z): it counts as exited only once its
+//! parent reaps it.
+
+mod tree;
+mod watch;
 
 use std::fs;
+use std::time::Duration;
+
+/// Time between the two CPU samples
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(500);
+
+const USAGE: &str = "\
+Usage: mini_ps [--sort cpu|mem|pid] [--top N | --tree]
+       mini_ps --watch SECS [--sort cpu|mem|pid] [--top N] [--iterations N]";
+
+#[derive(Clone, Copy, PartialEq)]
+enum SortBy {
+    Cpu,
+    Memory,
+    Pid,
+}
+
+/// What the command line asked for
+struct Options {
+    sort: SortBy,
+    top: Option<usize>,
+    tree: bool,
+    /// Redraw this often
+    watch: Option<Duration>,
+    /// Stop watching after this many frames
+    iterations: Option<usize>,
+}
+
+/// One row of the listing
+struct ProcessInfo {
+    pid: u32,
+    ppid: u32,
+    /// From status, for the tree; the listing shows `command`
+    name: String,
+    state: String,
+    memory_kb: Option<u64>,
+    /// None if the process was gone by the second sample
+    cpu_percent: Option<f64>,
+    command: String,
+}
+
+/// None for anything USAGE doesn't allow
+fn parse_args(args: &[String]) -> Option<Options> {
+    let mut options = Options {
+        sort: SortBy::Pid,
+        top: None,
+        tree: false,
+        watch: None,
+        iterations: None,
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--tree" {
+            options.tree = true;
+            continue;
+        }
+        // Both "--sort cpu" and "--sort=cpu"
+        let (flag, value) = match arg.split_once('=') {
+            Some((flag, value)) => (flag, Some(value)),
+            None => (arg.as_str(), None),
+        };
+        let value = match value {
+            Some(value) => value,
+            None => args.next()?,
+        };
+        match flag {
+            "--sort" => {
+                options.sort = match value {
+                    "cpu" => SortBy::Cpu,
+                    "mem" => SortBy::Memory,
+                    "pid" => SortBy::Pid,
+                    _ => return None,
+                }
+            }
+            "--top" => options.top = Some(value.parse().ok()?),
+            "--watch" => {
+                let secs: f64 = value.parse().ok()?;
+                options.watch = Some(Duration::try_from_secs_f64(secs).ok()?)
+                    .filter(|interval| !interval.is_zero());
+                options.watch?;
+            }
+            "--iterations" => options.iterations = Some(value.parse().ok()?),
+            _ => return None,
+        }
+    }
+    // Which N would a tree keep? Say so instead of guessing
+    if options.tree && (options.top.is_some() || options.watch.is_some()) {
+        return None;
+    }
+    if options.iterations.is_some() && options.watch.is_none() {
+        return None;
+    }
+    Some(options)
+}
 
 fn main() {
     // Check if running in Linux environment
@@ -48,17 +207,52 @@ fn main() {
         std::process::exit(1);
     }
 
-    println!(
-        "{:>7} {:>7} {:>5} {:>8}   {}",
-        "PID", "PPID", "STATE", "MEMORY", "COMMAND"
-    );
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let Some(options) = parse_args(&args) else {
+        eprintln!("{}", USAGE);
+        std::process::exit(2);
+    };
+
+    // 1. List all PIDs, and take a first CPU sample of them
+    let mut pids = list_pids();
+    pids.sort(); // Sort by PID for consistent output
+    let before = CpuSample::take(&pids);
+    std::thread::sleep(SAMPLE_INTERVAL);
+
+    if let Some(interval) = options.watch {
+        watch::watch(&options, interval, before);
+        return;
+    }
+
+    // 2. For each PID, get info and the second sample, sort
+    let (mut processes, _) = scan(&before, options.sort);
+
+    // 3. Cut, print
+    if options.tree {
+        for line in tree::render_tree(&processes) {
+            println!("{}", line);
+        }
+        return;
+    }
+    processes.truncate(options.top.unwrap_or(usize::MAX));
+
+    println!("{}", HEADER);
     println!("{}", "-".repeat(60));
+    for process in &processes {
+        println!("{}", format_row(process));
+    }
+}
+
+/// Above `format_row`'s columns
+const HEADER: &str = "    PID    PPID STATE   MEMORY   %CPU   COMMAND";
 
-    // 1. List all PIDs
+/// Every process alive now, in `sort` order, with its %CPU since `before`;
+/// also the CPU sample taken at the end, for the next scan
+fn scan(before: &CpuSample, sort: SortBy) -> (Vec<ProcessInfo>, CpuSample) {
     let mut pids = list_pids();
     pids.sort(); // Sort by PID for consistent output
 
-    // 2. For each PID, get info and print
+    let mut processes = Vec::with_capacity(pids.len());
     for pid in pids {
         // Get status info, skip if process disappeared
         let Some((name, state, ppid, memory_kb)) = get_status(pid) else {
@@ -66,20 +260,52 @@ fn main() {
         };
 
         // Get command line, fallback to name (for kernel threads)
-        let cmdline = get_cmdline(pid).unwrap_or_else(|| format!("[{}]", name));
+        let command = get_cmdline(pid).unwrap_or_else(|| format!("[{}]", name));
 
-        // Format memory: None -> "?", Some(kb) -> human readable
-        let memory = match memory_kb {
-            Some(kb) if kb >= 1024 => format!("{:.1}M", kb as f64 / 1024.0),
-            Some(kb) => format!("{}K", kb),
-            None => "?".to_string(),
-        };
+        processes.push(ProcessInfo {
+            pid,
+            ppid,
+            name,
+            state,
+            memory_kb,
+            cpu_percent: None,
+            command,
+        });
+    }
+    let after = CpuSample::take(&processes.iter().map(|p| p.pid).collect::<Vec<_>>());
+    for process in &mut processes {
+        process.cpu_percent = before.cpu_percent(&after, process.pid);
+    }
 
-        println!(
-            "{:>7} {:>7} {:>5} {:>8}   {}",
-            pid, ppid, state, memory, cmdline
-        );
+    match sort {
+        // Busiest first; unknown last. The sort is stable: ties stay by PID
+        SortBy::Cpu => processes.sort_by(|a, b| {
+            let cpu = |p: &ProcessInfo| p.cpu_percent.unwrap_or(-1.0);
+            cpu(b).total_cmp(&cpu(a))
+        }),
+        SortBy::Memory => processes.sort_by_key(|p| std::cmp::Reverse(p.memory_kb)),
+        SortBy::Pid => {}
     }
+    (processes, after)
+}
+
+/// One line of the table
+fn format_row(process: &ProcessInfo) -> String {
+    // Format memory: None -> "?", Some(kb) -> human readable
+    let memory = match process.memory_kb {
+        Some(kb) if kb >= 1024 => format!("{:.1}M", kb as f64 / 1024.0),
+        Some(kb) => format!("{}K", kb),
+        None => "?".to_string(),
+    };
+    let cpu = match process.cpu_percent {
+        Some(percent) => format!("{:.1}", percent),
+        None => "?".to_string(),
+    };
+
+    format!(
+        "{:>7} {:>7} {:>5} {:>8} {:>6}   {}",
+        process.pid, process.ppid, process.state, memory, cpu, process.command
+    )
 }
 
 // TODO: Implement these helper functions
@@ -88,12 +314,10 @@ fn main() {
 fn list_pids() -> Vec<u32> {
     let mut pids = Vec::new();
 
-    for entry in fs::read_dir("/proc").unwrap() {
-        if let Ok(entry) = entry {
-            if let Some(name_str) = entry.file_name().to_str() {
-                if let Ok(pid) = name_str.parse::<u32>() {
-                    pids.push(pid);
-                }
+    for entry in fs::read_dir("/proc").unwrap().flatten() {
+        if let Some(name_str) = entry.file_name().to_str() {
+            if let Ok(pid) = name_str.parse::<u32>() {
+                pids.push(pid);
             }
         }
     }
@@ -163,3 +387,73 @@ fn get_status(pid: u32) -> Option<(String, String, u32, Option<u64>)> {
 
     Some((name, state, ppid, memory_kb))
 }
+
+/// Read a process's CPU time so far: utime + stime, in clock ticks
+fn get_cpu_ticks(pid: u32) -> Option<u64> {
+    // Read /proc/[pid]/stat: "pid (comm) state ppid ..."
+    let path = format!("/proc/{}/stat", pid);
+    let content = fs::read_to_string(&path).ok()?;
+
+    // comm may itself contain spaces and ')': the fields start after the
+    // last ')'
+    let (_, fields) = content.rsplit_once(')')?;
+    let fields: Vec<&str> = fields.split_whitespace().collect();
+
+    // man proc: utime is field 14, stime field 15; state, field 3, is
+    // fields[0] here
+    let utime: u64 = fields.get(11)?.parse().ok()?;
+    let stime: u64 = fields.get(12)?.parse().ok()?;
+    Some(utime + stime)
+}
+
+/// Read the ticks all CPUs have counted so far, and how many CPUs there are
+fn get_total_ticks() -> Option<(u64, usize)> {
+    let content = fs::read_to_string("/proc/stat").ok()?;
+
+    // "cpu  user nice system idle iowait irq softirq steal ..." sums every
+    // CPU; "cpu0", "cpu1", ... follow, one per CPU
+    let total = content
+        .lines()
+        .next()?
+        .strip_prefix("cpu ")?
+        .split_whitespace()
+        .filter_map(|ticks| ticks.parse::<u64>().ok())
+        .sum();
+    let cpus = content
+        .lines()
+        .filter(|line| line.starts_with("cpu") && !line.starts_with("cpu "))
+        .count();
+    Some((total, cpus.max(1)))
+}
+
+/// CPU ticks of every process, and of the whole machine, at one moment
+struct CpuSample {
+    /// (pid, ticks), in PID order
+    ticks: Vec<(u32, u64)>,
+    total: u64,
+    cpus: usize,
+}
+
+impl CpuSample {
+    /// `pids` must be sorted: lookups are binary searches
+    fn take(pids: &[u32]) -> Self {
+        let (total, cpus) = get_total_ticks().unwrap_or((0, 1));
+        let ticks = pids
+            .iter()
+            .filter_map(|&pid| Some((pid, get_cpu_ticks(pid)?)))
+            .collect();
+        Self { ticks, total, cpus }
+    }
+
+    fn ticks(&self, pid: u32) -> Option<u64> {
+        let i = self.ticks.binary_search_by_key(&pid, |&(p, _)| p).ok()?;
+        Some(self.ticks[i].1)
+    }
+
+    /// %CPU of `pid` between this sample and `later`: 100 is one CPU
+    fn cpu_percent(&self, later: &CpuSample, pid: u32) -> Option<f64> {
+        let used = later.ticks(pid)?.checked_sub(self.ticks(pid)?)?;
+        let elapsed = later.total.checked_sub(self.total).filter(|&t| t > 0)?;
+        Some(100.0 * used as f64 / elapsed as f64 * later.cpus as f64)
+    }
+}
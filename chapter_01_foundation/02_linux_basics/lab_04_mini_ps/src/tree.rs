@@ -0,0 +1,176 @@
+//! `--tree`: the processes as a pstree-style hierarchy, built from PPid
+
+use crate::ProcessInfo;
+use std::collections::HashMap;
+
+/// Why a process is printed at the top level
+enum Root {
+    /// PPid 0: started by the kernel (init, kthreadd)
+    Kernel,
+    /// Its parent wasn't in the scan: exited before it was read
+    ParentGone,
+    /// Nothing above it is a root: its PPids lead in a circle
+    Cycle,
+}
+
+/// Indices into `processes` of every process's children, in the order of
+/// `processes`
+struct Tree<'a> {
+    processes: &'a [ProcessInfo],
+    children: Vec<Vec<usize>>,
+    /// Printed already: a cycle must not print forever
+    printed: Vec<bool>,
+    lines: Vec<String>,
+}
+
+impl Tree<'_> {
+    /// One line for `i`, then its children below it, one level deeper
+    fn print(&mut self, i: usize, prefix: &str, last: bool, root: Option<Root>) {
+        self.printed[i] = true;
+        let process = &self.processes[i];
+        let note = match root {
+            Some(Root::ParentGone) => format!(", parent {} gone", process.ppid),
+            Some(Root::Cycle) => ", PPid cycle".to_string(),
+            Some(Root::Kernel) | None => String::new(),
+        };
+        self.lines.push(format!(
+            "{}{} {} ({}{})",
+            prefix,
+            if last { "└─" } else { "├─" },
+            process.name,
+            process.pid,
+            note
+        ));
+
+        let prefix = format!("{}{}", prefix, if last { "   " } else { "│  " });
+        // Only a cycle's way back to where it was entered is printed already
+        let children: Vec<usize> = self.children[i]
+            .iter()
+            .copied()
+            .filter(|&child| !self.printed[child])
+            .collect();
+        for (n, &child) in children.iter().enumerate() {
+            self.print(child, &prefix, n + 1 == children.len(), None);
+        }
+    }
+}
+
+/// Every process under its parent, one line each; siblings keep the order
+/// of `processes`
+pub fn render_tree(processes: &[ProcessInfo]) -> Vec<String> {
+    let index: HashMap<u32, usize> = processes
+        .iter()
+        .enumerate()
+        .map(|(i, process)| (process.pid, i))
+        .collect();
+
+    let mut children = vec![Vec::new(); processes.len()];
+    let mut roots = Vec::new();
+    for (i, process) in processes.iter().enumerate() {
+        match index.get(&process.ppid) {
+            // A process can't be its own parent; if the scan says so,
+            // it's a cycle of one, found below
+            Some(&parent) => children[parent].push(i),
+            None if process.ppid == 0 => roots.push((i, Root::Kernel)),
+            None => roots.push((i, Root::ParentGone)),
+        }
+    }
+
+    // Whatever the roots don't reach hangs off a cycle. Enter each cycle
+    // at its lowest PID: the rest of it, and what hangs off it, follow
+    let mut reached = vec![false; processes.len()];
+    let reach = |from: usize, reached: &mut Vec<bool>| {
+        let mut stack = vec![from];
+        while let Some(i) = stack.pop() {
+            if !std::mem::replace(&mut reached[i], true) {
+                stack.extend(&children[i]);
+            }
+        }
+    };
+    for &(root, _) in &roots {
+        reach(root, &mut reached);
+    }
+    let mut by_pid: Vec<usize> = (0..processes.len()).collect();
+    by_pid.sort_by_key(|&i| processes[i].pid);
+    for i in by_pid {
+        if !reached[i] {
+            reach(i, &mut reached);
+            roots.push((i, Root::Cycle));
+        }
+    }
+
+    let mut tree = Tree {
+        processes,
+        children,
+        printed: vec![false; processes.len()],
+        lines: Vec::with_capacity(processes.len()),
+    };
+    let count = roots.len();
+    for (n, (root, why)) in roots.into_iter().enumerate() {
+        tree.print(root, "", n + 1 == count, Some(why));
+    }
+    tree.lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(pid: u32, ppid: u32, name: &str) -> ProcessInfo {
+        ProcessInfo {
+            pid,
+            ppid,
+            name: name.to_string(),
+            state: "S".to_string(),
+            memory_kb: None,
+            cpu_percent: None,
+            command: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn nests_children_under_parents() {
+        let processes = [
+            process(1, 0, "init"),
+            process(2, 0, "kthreadd"),
+            process(10, 1, "sshd"),
+            process(11, 10, "bash"),
+            process(12, 1, "cron"),
+        ];
+        assert_eq!(
+            render_tree(&processes),
+            [
+                "├─ init (1)",
+                "│  ├─ sshd (10)",
+                "│  │  └─ bash (11)",
+                "│  └─ cron (12)",
+                "└─ kthreadd (2)",
+            ]
+        );
+    }
+
+    #[test]
+    fn orphans_and_cycles_become_roots() {
+        // 20's parent exited mid-scan; 30 and 31 name each other, 32 hangs
+        // off the cycle, and 40 claims to be its own parent
+        let processes = [
+            process(1, 0, "init"),
+            process(20, 5, "orphan"),
+            process(31, 30, "b"),
+            process(30, 31, "a"),
+            process(32, 31, "c"),
+            process(40, 40, "self"),
+        ];
+        assert_eq!(
+            render_tree(&processes),
+            [
+                "├─ init (1)",
+                "├─ orphan (20, parent 5 gone)",
+                "├─ a (30, PPid cycle)",
+                "│  └─ b (31)",
+                "│     └─ c (32)",
+                "└─ self (40, PPid cycle)",
+            ]
+        );
+    }
+}
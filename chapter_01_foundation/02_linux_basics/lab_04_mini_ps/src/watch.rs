@@ -0,0 +1,83 @@
+//! `--watch`: poll /proc, redraw, and diff each scan against the last
+
+use crate::{format_row, scan, CpuSample, Options, ProcessInfo, HEADER};
+use std::collections::HashSet;
+use std::io::{self, IsTerminal, Write};
+use std::thread;
+use std::time::Duration;
+
+/// Clear the screen, cursor to the top left
+const CLEAR: &str = "\x1b[2J\x1b[H";
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const RESET: &str = "\x1b[0m";
+
+/// A marked row: `mark` in front, coloured on a terminal
+fn print_marked(mark: &str, colour: &str, row: &str, terminal: bool) {
+    if terminal {
+        println!("{}{} {}{}", colour, mark, row, RESET);
+    } else {
+        println!("{} {}", mark, row);
+    }
+}
+
+/// Redraw every `interval` until `options.iterations` frames, or forever.
+/// `sample` is the CPU sample the first frame's %CPU starts from
+pub fn watch(options: &Options, interval: Duration, mut sample: CpuSample) {
+    let terminal = io::stdout().is_terminal();
+    let mut previous: Option<Vec<ProcessInfo>> = None;
+
+    for frame in 0..options.iterations.unwrap_or(usize::MAX) {
+        if frame > 0 {
+            thread::sleep(interval);
+        }
+        let (processes, next) = scan(&sample, options.sort);
+        sample = next;
+
+        // The first frame has nothing to compare with: nothing is new
+        let seen: Option<HashSet<u32>> = previous
+            .as_ref()
+            .map(|previous| previous.iter().map(|p| p.pid).collect());
+        let alive: HashSet<u32> = processes.iter().map(|p| p.pid).collect();
+        let is_new = |pid: u32| seen.as_ref().is_some_and(|seen| !seen.contains(&pid));
+        let new = processes.iter().filter(|p| is_new(p.pid)).count();
+        // Their last row, from the previous frame
+        let exited: Vec<ProcessInfo> = previous
+            .take()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|p| !alive.contains(&p.pid))
+            .collect();
+
+        if terminal {
+            print!("{}", CLEAR);
+        } else if frame > 0 {
+            println!();
+        }
+        println!(
+            "Every {:?}: {} processes, {} new, {} exited (Ctrl-C to quit)",
+            interval,
+            processes.len(),
+            new,
+            exited.len()
+        );
+        println!("  {}", HEADER);
+        println!("{}", "-".repeat(60));
+        let shown = options.top.unwrap_or(usize::MAX);
+        for process in processes.iter().take(shown) {
+            if is_new(process.pid) {
+                print_marked("+", GREEN, &format_row(process), terminal);
+            } else {
+                println!("  {}", format_row(process));
+            }
+        }
+        for process in &exited {
+            print_marked("-", RED, &format_row(process), terminal);
+        }
+        // A frame is drawn whole before the sleep, whatever stdout buffers
+        let _ = io::stdout().flush();
+
+        // Whole rows, not just PIDs: an exited process shows its last one
+        previous = Some(processes);
+    }
+}
@@ -341,8 +341,7 @@ fn test_11_consistent_line_count() {
     let count1 = stdout1
         .lines()
         .filter(|l| {
-            l.trim()
-                .split_whitespace()
+            l.split_whitespace()
                 .next()
                 .map(|f| f.parse::<u32>().is_ok())
                 .unwrap_or(false)
@@ -351,8 +350,7 @@ fn test_11_consistent_line_count() {
     let count2 = stdout2
         .lines()
         .filter(|l| {
-            l.trim()
-                .split_whitespace()
+            l.split_whitespace()
                 .next()
                 .map(|f| f.parse::<u32>().is_ok())
                 .unwrap_or(false)
@@ -371,6 +369,183 @@ fn test_11_consistent_line_count() {
     );
 }
 
+#[test]
+#[cfg(target_os = "linux")]
+fn test_12_sort_and_top() {
+    let run = |args: &[&str]| {
+        let output = Command::new("cargo")
+            .args(["run", "--quiet", "--"])
+            .args(args)
+            .output()
+            .expect("Failed to execute program");
+        assert!(output.status.success(), "{:?} failed", args);
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+    // Column `index` of every process line, memory in K; ? as None
+    let column = |stdout: &str, index: usize| -> Vec<Option<f64>> {
+        stdout
+            .lines()
+            .filter(|l| {
+                l.split_whitespace()
+                    .next()
+                    .is_some_and(|f| f.parse::<u32>().is_ok())
+            })
+            .map(|l| {
+                let value = l.split_whitespace().nth(index).unwrap();
+                let (number, scale) = match value.strip_suffix('M') {
+                    Some(megabytes) => (megabytes, 1024.0),
+                    None => (value.strip_suffix('K').unwrap_or(value), 1.0),
+                };
+                number.parse::<f64>().ok().map(|n| n * scale)
+            })
+            .collect()
+    };
+
+    // %CPU is a fifth column; --top keeps that many rows, busiest first
+    let stdout = run(&["--sort", "cpu", "--top", "5"]);
+    assert!(stdout.contains("%CPU"), "{}", stdout);
+    let cpu = column(&stdout, 4);
+    assert_eq!(cpu.len(), 5, "{}", stdout);
+    let known: Vec<f64> = cpu.iter().flatten().copied().collect();
+    assert!(known.windows(2).all(|w| w[0] >= w[1]), "{}", stdout);
+
+    // Largest resident set first
+    let stdout = run(&["--sort=mem", "--top", "3"]);
+    let memory = column(&stdout, 3);
+    assert_eq!(memory.len(), 3, "{}", stdout);
+    assert!(memory.windows(2).all(|w| w[0] >= w[1]), "{}", stdout);
+
+    // PID order by default
+    let pids = column(&run(&["--top", "10"]), 0);
+    assert!(pids.windows(2).all(|w| w[0] < w[1]), "{:?}", pids);
+
+    let usage = Command::new("cargo")
+        .args(["run", "--quiet", "--", "--sort", "name"])
+        .output()
+        .expect("Failed to execute program");
+    assert_eq!(usage.status.code(), Some(2));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_13_tree_view() {
+    let output = Command::new("cargo")
+        .args(["run", "--quiet", "--", "--tree"])
+        .output()
+        .expect("Failed to execute program");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+
+    // Every line: some levels of "│  " or "   ", a branch, "name (pid...)"
+    for line in stdout.lines() {
+        let mut rest = line;
+        while let Some(deeper) = rest
+            .strip_prefix("│  ")
+            .or_else(|| rest.strip_prefix("   "))
+        {
+            rest = deeper;
+        }
+        let entry = rest
+            .strip_prefix("├─ ")
+            .or_else(|| rest.strip_prefix("└─ "))
+            .unwrap_or_else(|| panic!("not a tree line: {:?}", line));
+        assert!(entry.ends_with(')'), "{:?}", line);
+    }
+
+    // PID 1 is a root; mini_ps itself is nested under cargo, which ran it
+    let depth = |name: &str| {
+        stdout
+            .lines()
+            .find(|l| l.contains(name))
+            .map(|l| l.chars().take_while(|c| !matches!(c, '├' | '└')).count() / 3)
+            .unwrap_or_else(|| panic!("no {} in tree: {}", name, stdout))
+    };
+    assert_eq!(depth(" (1)"), 0, "{}", stdout);
+    assert!(depth("mini_ps (") > depth("cargo ("), "{}", stdout);
+
+    // No header, no table: about as many lines as processes
+    let rows = run_program()
+        .0
+        .lines()
+        .filter(|l| {
+            l.split_whitespace()
+                .next()
+                .is_some_and(|f| f.parse::<u32>().is_ok())
+        })
+        .count();
+    let tree_rows = stdout.lines().count();
+    assert!(tree_rows.abs_diff(rows) < 20, "{} vs {}", tree_rows, rows);
+
+    let usage = Command::new("cargo")
+        .args(["run", "--quiet", "--", "--tree", "--top", "3"])
+        .output()
+        .expect("Failed to execute program");
+    assert_eq!(usage.status.code(), Some(2));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_14_watch_marks_new_and_exited() {
+    use std::io::{BufRead, BufReader};
+    use std::process::Stdio;
+
+    let mut watch = Command::new("cargo")
+        .args([
+            "run",
+            "--quiet",
+            "--",
+            "--watch",
+            "0.5",
+            "--iterations",
+            "6",
+        ])
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to execute program");
+    let mut lines = BufReader::new(watch.stdout.take().unwrap()).lines();
+
+    // Once the first frame is on its way, start a process that outlives
+    // the next frame but not the whole run
+    let first = lines.next().unwrap().unwrap();
+    assert!(first.starts_with("Every 500ms: "), "{}", first);
+    let mut sleeper = Command::new("sleep").arg("1.2").spawn().unwrap();
+    let pid = sleeper.id().to_string();
+    // Reaped as soon as it exits: unwaited, it would stay in /proc, a zombie
+    let reaper = std::thread::spawn(move || sleeper.wait());
+    let rest: Vec<String> = lines.map(Result::unwrap).collect();
+    assert!(watch.wait().unwrap().success());
+    let output = rest.join("\n");
+
+    // Piped: no escape codes, one frame after another
+    assert!(!output.contains('\x1b'), "{}", output);
+    assert_eq!(
+        rest.iter().filter(|l| l.starts_with("Every ")).count(),
+        5,
+        "{}",
+        output
+    );
+    let marked = |mark: &str| {
+        rest.iter().any(|l| {
+            let mut columns = l.split_whitespace();
+            columns.next() == Some(mark) && columns.next() == Some(pid.as_str())
+        })
+    };
+    assert!(marked("+"), "sleep {} never marked new:\n{}", pid, output);
+    assert!(
+        marked("-"),
+        "sleep {} never marked exited:\n{}",
+        pid,
+        output
+    );
+    reaper.join().unwrap().unwrap();
+
+    let usage = Command::new("cargo")
+        .args(["run", "--quiet", "--", "--iterations", "2"])
+        .output()
+        .expect("Failed to execute program");
+    assert_eq!(usage.status.code(), Some(2));
+}
+
 // ============================================================
 // Manual Verification
 // ============================================================
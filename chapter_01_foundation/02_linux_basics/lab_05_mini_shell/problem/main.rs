@@ -0,0 +1,127 @@
+//! Lab 5: Mini Shell
+//!
+//! ## Goal
+//! Build a tiny shell that runs commands, pipelines and redirections,
+//! tying together processes, file descriptors and exec
+//!
+//! ## Requirements
+//! 1. Read command lines from stdin (prompt `mini-sh$ ` when interactive)
+//! 2. Run each command with fork() + execvp(), wait for it in the foreground
+//! 3. Support pipelines `a | b | c` using pipe() + dup2()
+//! 4. Support redirection: `> file`, `>> file`, `< file`
+//! 5. Builtins `cd` and `exit`; `-c "line"` runs a single line like `sh -c`
+//! 6. Exit status of a pipeline is the status of its last command (127 = not found)
+//!
+//! ## Expected Behavior
+//! ```
+//! mini-sh$ echo hello | tr a-z A-Z
+//! HELLO
+//! mini-sh$ ls /proc/self/fd > fds.txt
+//! mini-sh$ wc -l < fds.txt
+//! 4
+//! mini-sh$ nosuchcmd
+//! mini-sh: nosuchcmd: ENOENT: No such file or directory
+//! ```
+//!
+//! ## Hints
+//! - `nix::unistd::{fork, execvp, pipe, dup2, close}` map 1:1 to the syscalls
+//! - Flush stdout before fork() or buffered output is printed twice
+//! - Close every unused pipe end in *both* parent and children, or readers hang
+//! - `cd` must be a builtin - why can't it be a child process?
+//!
+//! ## Verification
+//! ```bash
+//! cargo test
+//! cargo run
+//! strace -f -e trace=clone,execve,pipe2,dup2,wait4 ./target/debug/mini_shell -c "ls | wc -l"
+//! ```
+//!
+//! ## Acceptance Criteria
+//! - [ ] Simple commands run and their exit status is propagated
+//! - [ ] Pipelines of any length work and terminate (no leaked pipe ends)
+//! - [ ] `>`, `>>` and `<` redirect the right fds
+//! - [ ] `cd` changes the shell's own working directory
+//!
+//! Warning: Requires a Unix system (uses fork/exec)
+//!
+//! Check solution/main.rs after completing
+
+use nix::fcntl::{open, OFlag};
+use nix::sys::stat::Mode;
+use nix::sys::wait::{waitpid, WaitStatus};
+use nix::unistd::{chdir, close, dup2, execvp, fork, pipe, ForkResult, Pid};
+use std::ffi::CString;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::os::fd::RawFd;
+
+/// One command in a pipeline: program + arguments
+#[derive(Debug, PartialEq)]
+struct Command {
+    argv: Vec<String>,
+}
+
+/// A full command line: `a | b | c < in > out`
+#[derive(Debug, PartialEq)]
+struct Pipeline {
+    commands: Vec<Command>,
+    stdin_file: Option<String>,
+    stdout_file: Option<(String, bool)>, // (path, append)
+}
+
+// ============================================================
+// TODO: Implement these functions
+// ============================================================
+
+/// Parse a command line into a pipeline. Returns Ok(None) for blank lines.
+///
+/// Steps:
+/// 1. Split into tokens (words, quoted strings, `|`, `<`, `>`, `>>`)
+/// 2. `|` ends the current command
+/// 3. `<`/`>`/`>>` take the next token as a file name
+/// 4. Report syntax errors like `| foo` or `ls >`
+fn parse_line(line: &str) -> Result<Option<Pipeline>, String> {
+    todo!("Implement parse_line")
+}
+
+/// Fork one child per command, connect them with pipes, and wait for
+/// all of them. Returns the exit status of the last command.
+///
+/// Steps:
+/// 1. Open redirect files and create N-1 pipes
+/// 2. For each command: fork; in the child dup2() the right fds onto 0/1,
+///    close all pipe ends, then execvp()
+/// 3. In the parent: close all pipe ends, waitpid() every child
+fn run_pipeline(pipeline: &Pipeline) -> i32 {
+    todo!("Implement run_pipeline")
+}
+
+/// Builtins run inside the shell process itself. Returns None if `cmd`
+/// isn't a builtin, Some(status) otherwise.
+fn run_builtin(pipeline: &Pipeline, last_status: i32) -> Option<i32> {
+    // TODO: cd (chdir) and exit
+    todo!("Implement run_builtin")
+}
+
+fn execute(line: &str, last_status: i32) -> i32 {
+    match parse_line(line) {
+        Ok(Some(pipeline)) => {
+            run_builtin(&pipeline, last_status).unwrap_or_else(|| run_pipeline(&pipeline))
+        }
+        Ok(None) => last_status,
+        Err(e) => {
+            eprintln!("mini-sh: {}", e);
+            2
+        }
+    }
+}
+
+fn main() {
+    // TODO: Implement
+    //
+    // Suggested steps:
+    // 1. `-c "line"`: execute it and exit with its status
+    // 2. Otherwise loop: print prompt (if stdin is a terminal), read a line, execute
+    // 3. On EOF exit with the last status
+
+    todo!("Implement main")
+}
@@ -0,0 +1,346 @@
+//! Lab 5 Reference Answer
+
+use nix::fcntl::{open, OFlag};
+use nix::sys::stat::Mode;
+use nix::sys::wait::{waitpid, WaitStatus};
+use nix::unistd::{chdir, close, dup2, execvp, fork, pipe, ForkResult, Pid};
+use std::ffi::CString;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::os::fd::RawFd;
+
+/// One command in a pipeline: program + arguments
+#[derive(Debug, PartialEq)]
+struct Command {
+    argv: Vec<String>,
+}
+
+/// A full command line: `a | b | c < in > out`
+#[derive(Debug, PartialEq)]
+struct Pipeline {
+    commands: Vec<Command>,
+    stdin_file: Option<String>,
+    stdout_file: Option<(String, bool)>, // (path, append)
+}
+
+/// Split a line into words, keeping quoted strings together and
+/// treating `|`, `<`, `>` and `>>` as separate tokens
+fn tokenize(line: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut in_word = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' => {
+                // Everything up to the matching quote is literal
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some(q) if q == c => break,
+                        Some(other) => current.push(other),
+                        None => return Err(format!("unterminated {} quote", c)),
+                    }
+                }
+            }
+            '|' | '<' | '>' => {
+                if in_word {
+                    tokens.push(std::mem::take(&mut current));
+                    in_word = false;
+                }
+                if c == '>' && chars.peek() == Some(&'>') {
+                    chars.next();
+                    tokens.push(">>".to_string());
+                } else {
+                    tokens.push(c.to_string());
+                }
+            }
+            c if c.is_whitespace() => {
+                if in_word {
+                    tokens.push(std::mem::take(&mut current));
+                    in_word = false;
+                }
+            }
+            c => {
+                in_word = true;
+                current.push(c);
+            }
+        }
+    }
+    if in_word {
+        tokens.push(current);
+    }
+
+    Ok(tokens)
+}
+
+/// Parse a command line into a pipeline. Returns Ok(None) for blank lines.
+fn parse_line(line: &str) -> Result<Option<Pipeline>, String> {
+    let tokens = tokenize(line)?;
+    if tokens.is_empty() {
+        return Ok(None);
+    }
+
+    let mut pipeline = Pipeline {
+        commands: Vec::new(),
+        stdin_file: None,
+        stdout_file: None,
+    };
+    let mut argv: Vec<String> = Vec::new();
+    let mut iter = tokens.into_iter();
+
+    while let Some(token) = iter.next() {
+        match token.as_str() {
+            "|" => {
+                if argv.is_empty() {
+                    return Err("syntax error near `|'".to_string());
+                }
+                pipeline.commands.push(Command {
+                    argv: std::mem::take(&mut argv),
+                });
+            }
+            "<" | ">" | ">>" => {
+                let target = iter
+                    .next()
+                    .filter(|t| !matches!(t.as_str(), "|" | "<" | ">" | ">>"))
+                    .ok_or_else(|| format!("syntax error: missing file after `{}'", token))?;
+                if token == "<" {
+                    pipeline.stdin_file = Some(target);
+                } else {
+                    pipeline.stdout_file = Some((target, token == ">>"));
+                }
+            }
+            _ => argv.push(token),
+        }
+    }
+
+    if argv.is_empty() {
+        return Err("syntax error: missing command".to_string());
+    }
+    pipeline.commands.push(Command { argv });
+
+    Ok(Some(pipeline))
+}
+
+/// Runs in the child after fork(): wire up fds, then replace ourselves
+/// with the program. Never returns.
+fn exec_child(cmd: &Command, stdin_fd: RawFd, stdout_fd: RawFd, fds_to_close: &[RawFd]) -> ! {
+    // Move the pipe/file ends onto 0 and 1
+    if stdin_fd != 0 {
+        dup2(stdin_fd, 0).expect("dup2 stdin");
+    }
+    if stdout_fd != 1 {
+        dup2(stdout_fd, 1).expect("dup2 stdout");
+    }
+
+    // Close every other pipe end, or readers never see EOF
+    for &fd in fds_to_close {
+        let _ = close(fd);
+    }
+
+    let args: Vec<CString> = cmd
+        .argv
+        .iter()
+        .map(|a| CString::new(a.as_str()).expect("argument contains NUL"))
+        .collect();
+
+    // execvp searches $PATH and only returns on failure
+    let err = execvp(&args[0], &args).unwrap_err();
+    eprintln!("mini-sh: {}: {}", cmd.argv[0], err);
+    std::process::exit(127);
+}
+
+/// Open the redirect target for the last command's stdout
+fn open_output(path: &str, append: bool) -> nix::Result<RawFd> {
+    let mode = if append {
+        OFlag::O_APPEND
+    } else {
+        OFlag::O_TRUNC
+    };
+    open(
+        path,
+        OFlag::O_WRONLY | OFlag::O_CREAT | mode,
+        Mode::from_bits_truncate(0o644),
+    )
+}
+
+/// Fork one child per command, connect them with pipes, and wait for
+/// all of them. Returns the exit status of the last command.
+fn run_pipeline(pipeline: &Pipeline) -> i32 {
+    let first_stdin = match &pipeline.stdin_file {
+        Some(path) => match open(path.as_str(), OFlag::O_RDONLY, Mode::empty()) {
+            Ok(fd) => fd,
+            Err(e) => {
+                eprintln!("mini-sh: {}: {}", path, e);
+                return 1;
+            }
+        },
+        None => 0,
+    };
+    let last_stdout = match &pipeline.stdout_file {
+        Some((path, append)) => match open_output(path, *append) {
+            Ok(fd) => fd,
+            Err(e) => {
+                eprintln!("mini-sh: {}: {}", path, e);
+                return 1;
+            }
+        },
+        None => 1,
+    };
+
+    // Create all pipes up front: pipes[i] connects command i -> i+1
+    let mut pipes = Vec::new();
+    for _ in 1..pipeline.commands.len() {
+        match pipe() {
+            Ok(p) => pipes.push(p),
+            Err(e) => {
+                eprintln!("mini-sh: pipe: {}", e);
+                return 1;
+            }
+        }
+    }
+
+    // Every fd the children must not keep open
+    let mut all_fds: Vec<RawFd> = pipes.iter().flat_map(|&(r, w)| [r, w]).collect();
+    if first_stdin != 0 {
+        all_fds.push(first_stdin);
+    }
+    if last_stdout != 1 {
+        all_fds.push(last_stdout);
+    }
+
+    // Anything buffered would otherwise be printed by every child too
+    let _ = io::stdout().flush();
+
+    let mut children: Vec<Pid> = Vec::new();
+    let last = pipeline.commands.len() - 1;
+
+    for (i, cmd) in pipeline.commands.iter().enumerate() {
+        let stdin_fd = if i == 0 { first_stdin } else { pipes[i - 1].0 };
+        let stdout_fd = if i == last { last_stdout } else { pipes[i].1 };
+
+        match unsafe { fork() } {
+            Ok(ForkResult::Child) => exec_child(cmd, stdin_fd, stdout_fd, &all_fds),
+            Ok(ForkResult::Parent { child }) => children.push(child),
+            Err(e) => {
+                eprintln!("mini-sh: fork: {}", e);
+                break;
+            }
+        }
+    }
+
+    // The parent must close its copies too, or the last reader blocks forever
+    for fd in all_fds {
+        let _ = close(fd);
+    }
+
+    // Foreground job: wait for every child, report the last one's status
+    let mut status = 0;
+    for (i, pid) in children.iter().enumerate() {
+        let code = match waitpid(*pid, None) {
+            Ok(WaitStatus::Exited(_, code)) => code,
+            Ok(WaitStatus::Signaled(_, signal, _)) => 128 + signal as i32,
+            _ => 1,
+        };
+        if i == last {
+            status = code;
+        }
+    }
+    if children.len() <= last {
+        status = 1; // fork failed part-way
+    }
+
+    status
+}
+
+/// Builtins run inside the shell process itself. Returns None if `cmd`
+/// isn't a builtin, Some(status) otherwise.
+fn run_builtin(pipeline: &Pipeline, last_status: i32) -> Option<i32> {
+    if pipeline.commands.len() != 1 {
+        return None;
+    }
+    let argv = &pipeline.commands[0].argv;
+
+    match argv[0].as_str() {
+        // cd can't be a child process: it would change the child's cwd
+        "cd" => {
+            let home = std::env::var("HOME").unwrap_or_else(|_| "/".to_string());
+            let target = argv.get(1).cloned().unwrap_or(home);
+            match chdir(target.as_str()) {
+                Ok(()) => Some(0),
+                Err(e) => {
+                    eprintln!("mini-sh: cd: {}: {}", target, e);
+                    Some(1)
+                }
+            }
+        }
+        "exit" => {
+            let code = argv
+                .get(1)
+                .and_then(|c| c.parse().ok())
+                .unwrap_or(last_status);
+            std::process::exit(code);
+        }
+        _ => None,
+    }
+}
+
+fn execute(line: &str, last_status: i32) -> i32 {
+    match parse_line(line) {
+        Ok(Some(pipeline)) => {
+            run_builtin(&pipeline, last_status).unwrap_or_else(|| run_pipeline(&pipeline))
+        }
+        Ok(None) => last_status,
+        Err(e) => {
+            eprintln!("mini-sh: {}", e);
+            2
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    // `mini_shell -c "cmd"` runs one line, like `sh -c`
+    if args.len() >= 3 && args[1] == "-c" {
+        std::process::exit(execute(&args[2], 0));
+    }
+
+    let interactive = io::stdin().is_terminal();
+    let mut status = 0;
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+
+    loop {
+        if interactive {
+            print!("mini-sh$ ");
+            let _ = io::stdout().flush();
+        }
+
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => break, // EOF (Ctrl-D) or read error
+        };
+
+        status = execute(&line, status);
+    }
+
+    std::process::exit(status);
+}
+
+// Key concepts demonstrated:
+//
+// 1. FORK + EXEC:
+//    - fork() duplicates the shell, execvp() replaces the child with the program
+//    - The parent keeps running and waitpid()s for the child
+//
+// 2. FILE DESCRIPTORS ARE INHERITED:
+//    - dup2(fd, 1) makes fd the child's stdout before exec
+//    - The program never knows it's writing to a pipe or a file
+//
+// 3. PIPES NEED CAREFUL CLOSING:
+//    - A reader only sees EOF when *every* write end is closed
+//    - Forgetting one close() in parent or child = hung pipeline
+//
+// 4. BUILTINS:
+//    - cd/exit must run in the shell itself, not in a child
@@ -0,0 +1,149 @@
+//! Lab 5 Tests - Mini Shell
+//!
+//! Run with: cargo test
+//!
+//! Note: These tests require a Unix environment
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Run `mini_shell -c <line>`
+fn run_line(line: &str) -> (String, String, i32) {
+    let output = Command::new("cargo")
+        .args(["run", "--quiet", "--", "-c", line])
+        .output()
+        .expect("Failed to execute program");
+
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    let code = output.status.code().unwrap_or(-1);
+
+    (stdout, stderr, code)
+}
+
+/// Feed a script to the shell's stdin
+fn run_script(script: &str) -> (String, String, i32) {
+    let mut child = Command::new("cargo")
+        .args(["run", "--quiet"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to execute program");
+
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(script.as_bytes())
+        .unwrap();
+
+    let output = child.wait_with_output().expect("Failed to wait");
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    let code = output.status.code().unwrap_or(-1);
+
+    (stdout, stderr, code)
+}
+
+fn temp_path(name: &str) -> String {
+    std::env::temp_dir()
+        .join(format!("mini_shell_{}_{}", name, std::process::id()))
+        .to_string_lossy()
+        .to_string()
+}
+
+#[test]
+fn test_01_simple_command() {
+    let (stdout, _, code) = run_line("echo hello world");
+
+    assert_eq!(code, 0, "echo should succeed");
+    assert_eq!(stdout, "hello world\n");
+}
+
+#[test]
+fn test_02_pipe() {
+    let (stdout, _, code) = run_line("echo hello | tr a-z A-Z");
+
+    assert_eq!(code, 0);
+    assert_eq!(stdout, "HELLO\n", "Output of echo should flow into tr");
+}
+
+#[test]
+fn test_03_long_pipeline_terminates() {
+    // Hangs if any pipe write end is left open
+    let (stdout, _, code) = run_line("seq 1 1000 | grep 7 | sort -r | head -n 1");
+
+    assert_eq!(code, 0);
+    assert_eq!(stdout.trim(), "997");
+}
+
+#[test]
+fn test_04_output_redirect_and_append() {
+    let path = temp_path("redirect");
+
+    run_line(&format!("echo first > {}", path));
+    run_line(&format!("echo second >> {}", path));
+    let content = std::fs::read_to_string(&path).expect("Redirect should create file");
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(content, "first\nsecond\n");
+}
+
+#[test]
+fn test_05_input_redirect() {
+    let path = temp_path("input");
+    std::fs::write(&path, "c\na\nb\n").unwrap();
+
+    let (stdout, _, _) = run_line(&format!("sort < {}", path));
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(stdout, "a\nb\nc\n");
+}
+
+#[test]
+fn test_06_exit_status_propagates() {
+    let (_, _, code) = run_line("false");
+    assert_eq!(code, 1, "Status of `false` should be 1");
+
+    let (_, _, code) = run_line("true | false");
+    assert_eq!(code, 1, "Pipeline status is the last command's status");
+
+    let (_, _, code) = run_line("false | true");
+    assert_eq!(code, 0, "Pipeline status is the last command's status");
+}
+
+#[test]
+fn test_07_command_not_found() {
+    let (_, stderr, code) = run_line("definitely_not_a_command_xyz");
+
+    assert_eq!(code, 127, "Missing command should exit with 127");
+    assert!(
+        stderr.contains("definitely_not_a_command_xyz"),
+        "Error message should name the command"
+    );
+}
+
+#[test]
+fn test_08_syntax_errors() {
+    for line in ["| wc", "echo hi |", "echo hi >"] {
+        let (_, stderr, code) = run_line(line);
+        assert_eq!(code, 2, "`{}` should be a syntax error", line);
+        assert!(stderr.contains("syntax error"), "`{}`: {}", line, stderr);
+    }
+}
+
+#[test]
+fn test_09_cd_builtin_and_script() {
+    let (stdout, _, code) = run_script("cd /\npwd\nexit 3\necho unreachable\n");
+
+    assert_eq!(stdout.trim(), "/", "cd should change the shell's own cwd");
+    assert_eq!(code, 3, "exit should use its argument as status");
+}
+
+#[test]
+fn test_10_quoted_arguments() {
+    let (stdout, _, _) = run_line("echo 'a  b' \"c | d\"");
+
+    assert_eq!(stdout, "a  b c | d\n", "Quotes keep spaces and `|` literal");
+}
@@ -0,0 +1,267 @@
+//! Lab 6: Mini xargs
+//!
+//! ## Goal
+//! Build a small `xargs`: read arguments from stdin, batch them into child
+//! command invocations, and run up to N children in parallel on the
+//! ThreadPool from chapter_02_os/01_process_thread/lab_02_thread_pool
+//!
+//! ## Requirements
+//! 1. `mini_xargs [options] [command [initial-args...]]`, default command `echo`
+//! 2. Split stdin on whitespace; each word is one argument
+//! 3. `-n N` / `--max-args N`: at most N stdin arguments per invocation
+//!    (without it, everything goes into one invocation)
+//! 4. `-P N` / `--max-procs N`: run up to N invocations at once (default 1)
+//! 5. `-t`: print each command line to stderr before running it
+//! 6. Exit status like GNU xargs:
+//!    - 0: every invocation succeeded
+//!    - 123: some invocation exited with 1-125
+//!    - 124: an invocation exited with 255 (stop launching new ones)
+//!    - 125: an invocation was killed by a signal
+//!    - 126 / 127: the command cannot be run / was not found
+//!
+//! ## Expected Behavior
+//! ```
+//! $ printf 'a b c d e' | mini_xargs -n 2 echo
+//! a b
+//! c d
+//! e
+//! $ seq 4 | mini_xargs -n 1 -P 4 sleep    # ~1s, not 4s
+//! $ printf '0 3 0' | mini_xargs -n 1 sh -c 'exit $0'; echo $?
+//! 123
+//! ```
+//!
+//! ## Hints
+//! - `std::process::Command::new(cmd).args(..).status()` = fork + exec + waitpid
+//! - One batch = one `pool.execute(...)` job; the pool size *is* the `-P` limit
+//! - Send each outcome back over an `mpsc::channel`, `drop(tx)` before collecting
+//! - Spawn errors: `io::ErrorKind::NotFound` means 127
+//! - `ExitStatusExt::signal()` tells you the child was killed
+//!
+//! ## Verification
+//! ```bash
+//! cargo test
+//! find . -name '*.rs' | cargo run -- -n 2 -P 4 -t wc -l
+//! strace -f -e trace=clone,execve,wait4 ./target/debug/mini_xargs -n 1 -P 2 echo <<< "a b c"
+//! ```
+//!
+//! ## Acceptance Criteria
+//! - [ ] `-n` batches arguments correctly, including a short last batch
+//! - [ ] `-P N` runs N children concurrently (check timing with `sleep`)
+//! - [ ] Failing, missing and killed commands give 123 / 127 / 125
+//!
+//! Warning: Requires a Unix system (uses Unix exit/signal semantics)
+//!
+//! Check solution/main.rs after completing
+
+// The chapter-02 ThreadPool, not a copy; the path works from solution/ and
+// src/. Only `quiet` and `execute` are used here
+#[path = "../../../../chapter_02_os/01_process_thread/lab_02_thread_pool/src/thread_pool.rs"]
+#[allow(dead_code)]
+mod thread_pool;
+
+use std::io::{self, Read};
+use std::process::{Command, ExitStatus};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use thread_pool::ThreadPool;
+
+/// Parsed command line: `mini_xargs [-n N] [-P N] [-t] [command [args...]]`
+#[derive(Debug, PartialEq)]
+struct Config {
+    max_args: Option<usize>,
+    parallel: usize,
+    verbose: bool,
+    command: Vec<String>,
+}
+
+fn usage() -> String {
+    "usage: mini_xargs [-n|--max-args N] [-P|--max-procs N] [-t] [command [initial-args...]]"
+        .to_string()
+}
+
+fn parse_count(flag: &str, value: Option<String>) -> Result<usize, String> {
+    let value = value.ok_or_else(|| format!("{} needs a value\n{}", flag, usage()))?;
+    match value.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("{}: invalid number '{}'", flag, value)),
+    }
+}
+
+/// Options end at the first non-option word: that's the command
+fn parse_args(args: Vec<String>) -> Result<Config, String> {
+    let mut config = Config {
+        max_args: None,
+        parallel: 1,
+        verbose: false,
+        command: Vec::new(),
+    };
+    let mut iter = args.into_iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-n" | "--max-args" => config.max_args = Some(parse_count(&arg, iter.next())?),
+            "-P" | "--max-procs" => config.parallel = parse_count(&arg, iter.next())?,
+            "-t" | "--verbose" => config.verbose = true,
+            "-h" | "--help" => return Err(usage()),
+            "--" => {
+                config.command.extend(iter.by_ref());
+            }
+            s if s.starts_with('-') && config.command.is_empty() => {
+                return Err(format!("unknown option '{}'\n{}", s, usage()))
+            }
+            _ => {
+                config.command.push(arg);
+                config.command.extend(iter.by_ref());
+            }
+        }
+    }
+
+    // Like real xargs, the default command is echo
+    if config.command.is_empty() {
+        config.command.push("echo".to_string());
+    }
+    Ok(config)
+}
+
+/// Split input into batches of at most `max_args` words.
+/// Without `-n` everything goes into a single invocation.
+fn make_batches(input: &str, max_args: Option<usize>) -> Vec<Vec<String>> {
+    let words: Vec<String> = input.split_whitespace().map(String::from).collect();
+    if words.is_empty() {
+        return Vec::new();
+    }
+    let size = max_args.unwrap_or(words.len());
+    words.chunks(size).map(|chunk| chunk.to_vec()).collect()
+}
+
+/// What happened to one child invocation
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Outcome {
+    Success,
+    Failed,    // exited 1-125
+    Exit255,   // exited 255: xargs stops launching new commands
+    Signaled,  // killed by a signal
+    NotFound,  // command not in $PATH
+    CannotRun, // found but not executable
+}
+
+fn classify(status: ExitStatus) -> Outcome {
+    use std::os::unix::process::ExitStatusExt;
+
+    match status.code() {
+        Some(0) => Outcome::Success,
+        Some(255) => Outcome::Exit255,
+        Some(_) => Outcome::Failed,
+        None if status.signal().is_some() => Outcome::Signaled,
+        None => Outcome::Failed,
+    }
+}
+
+fn run_batch(command: &[String], batch: &[String], verbose: bool) -> Outcome {
+    if verbose {
+        eprintln!("{} {}", command.join(" "), batch.join(" "));
+    }
+
+    // std::process::Command does fork + exec + dup2 for us; stdout is inherited
+    let result = Command::new(&command[0])
+        .args(&command[1..])
+        .args(batch)
+        .status();
+
+    match result {
+        Ok(status) => classify(status),
+        Err(e) => {
+            eprintln!("mini_xargs: {}: {}", command[0], e);
+            match e.kind() {
+                io::ErrorKind::NotFound => Outcome::NotFound,
+                _ => Outcome::CannotRun,
+            }
+        }
+    }
+}
+
+/// Same exit codes as GNU xargs:
+/// 0 ok, 123 some command failed, 124 exited 255, 125 killed, 126 cannot run, 127 not found
+fn exit_code(outcomes: &[Outcome]) -> i32 {
+    let has = |o: Outcome| outcomes.contains(&o);
+
+    if has(Outcome::NotFound) {
+        127
+    } else if has(Outcome::CannotRun) {
+        126
+    } else if has(Outcome::Signaled) {
+        125
+    } else if has(Outcome::Exit255) {
+        124
+    } else if has(Outcome::Failed) {
+        123
+    } else {
+        0
+    }
+}
+
+/// Run every batch on a pool of `parallel` workers, collecting outcomes
+/// over a channel. Once a command fails fatally, queued batches are skipped.
+fn run_all(config: &Config, batches: Vec<Vec<String>>) -> Vec<Outcome> {
+    let pool = ThreadPool::quiet(config.parallel);
+    let stop = Arc::new(AtomicBool::new(false));
+    let command = Arc::new(config.command.clone());
+    let (tx, rx) = mpsc::channel();
+
+    for batch in batches {
+        let tx = tx.clone();
+        let stop = Arc::clone(&stop);
+        let command = Arc::clone(&command);
+        let verbose = config.verbose;
+
+        pool.execute(move || {
+            if stop.load(Ordering::SeqCst) {
+                return;
+            }
+            let outcome = run_batch(&command, &batch, verbose);
+            if !matches!(outcome, Outcome::Success | Outcome::Failed) {
+                stop.store(true, Ordering::SeqCst);
+            }
+            let _ = tx.send(outcome);
+        });
+    }
+
+    // Drop our sender so the receiver ends once every job is done
+    drop(tx);
+    rx.iter().collect()
+}
+
+fn main() {
+    let config = match parse_args(std::env::args().skip(1).collect()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("mini_xargs: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let mut input = String::new();
+    if let Err(e) = io::stdin().read_to_string(&mut input) {
+        eprintln!("mini_xargs: reading stdin: {}", e);
+        std::process::exit(1);
+    }
+
+    let batches = make_batches(&input, config.max_args);
+    let outcomes = run_all(&config, batches);
+
+    std::process::exit(exit_code(&outcomes));
+}
+
+// Key concepts demonstrated:
+//
+// 1. ARGUMENT BATCHING:
+//    - argv has a size limit (ARG_MAX), so xargs splits input into several invocations
+//    - `-n N` caps the number of arguments per invocation
+//
+// 2. BOUNDED PARALLELISM:
+//    - `-P N` = a ThreadPool of N workers, each blocking on one child at a time
+//    - At most N children are alive at once, no matter how many batches there are
+//
+// 3. EXIT STATUS PROPAGATION:
+//    - Each child's status is sent back over a channel and folded into one code
+//    - 123/124/125/126/127 tell the caller *how* things went wrong
@@ -0,0 +1,149 @@
+//! Lab 6 Tests - Mini xargs
+//!
+//! Run with: cargo test
+//!
+//! Note: These tests require a Unix environment
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// Run `mini_xargs <args>` with `input` on stdin
+fn run_xargs(args: &[&str], input: &str) -> (String, String, i32) {
+    let mut child = Command::new("cargo")
+        .args(["run", "--quiet", "--"])
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to execute program");
+
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+
+    let output = child
+        .wait_with_output()
+        .expect("Failed to wait for program");
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    let code = output.status.code().unwrap_or(-1);
+
+    (stdout, stderr, code)
+}
+
+#[test]
+fn test_01_default_command_is_echo() {
+    let (stdout, _, code) = run_xargs(&[], "a b\nc\n");
+
+    assert_eq!(code, 0);
+    assert_eq!(stdout, "a b c\n", "All input goes into one echo");
+}
+
+#[test]
+fn test_02_max_args_batches() {
+    let (stdout, _, code) = run_xargs(&["-n", "2", "echo"], "a b c d e");
+
+    assert_eq!(code, 0);
+    assert_eq!(stdout, "a b\nc d\ne\n", "Last batch may be short");
+}
+
+#[test]
+fn test_03_initial_args_come_first() {
+    let (stdout, _, code) = run_xargs(&["--max-args", "1", "echo", "item:"], "x y");
+
+    assert_eq!(code, 0);
+    assert_eq!(stdout, "item: x\nitem: y\n");
+}
+
+#[test]
+fn test_04_empty_input_runs_nothing() {
+    let (stdout, _, code) = run_xargs(&["-n", "1", "false"], "  \n");
+
+    assert_eq!(code, 0, "No input means no invocations");
+    assert!(stdout.is_empty());
+}
+
+#[test]
+fn test_05_parallel_runs_concurrently() {
+    // Warm up the build so compile time isn't measured
+    run_xargs(&[], "");
+
+    let start = Instant::now();
+    let (_, _, code) = run_xargs(&["-n", "1", "-P", "4", "sleep"], "1 1 1 1");
+    let elapsed = start.elapsed();
+
+    assert_eq!(code, 0);
+    assert!(
+        elapsed < Duration::from_millis(3000),
+        "4 x sleep 1 with -P 4 took {:?}, expected ~1s",
+        elapsed
+    );
+}
+
+#[test]
+fn test_06_parallel_output_complete() {
+    let input: Vec<String> = (1..=20).map(|i| i.to_string()).collect();
+    let (stdout, _, code) = run_xargs(&["-n", "1", "-P", "4", "echo"], &input.join(" "));
+
+    assert_eq!(code, 0);
+    let mut lines: Vec<u32> = stdout.lines().map(|l| l.parse().unwrap()).collect();
+    lines.sort();
+    assert_eq!(
+        lines,
+        (1..=20).collect::<Vec<_>>(),
+        "Every batch runs exactly once"
+    );
+}
+
+#[test]
+fn test_07_failure_exits_123() {
+    let (stdout, _, code) = run_xargs(&["-n", "1", "sh", "-c", "echo $0; exit $0"], "0 3 0");
+
+    assert_eq!(code, 123, "A child exiting 1-125 gives 123");
+    assert_eq!(stdout.lines().count(), 3, "Other batches still run");
+}
+
+#[test]
+fn test_08_not_found_exits_127() {
+    let (_, stderr, code) = run_xargs(&["nosuchcmd_xyz"], "a");
+
+    assert_eq!(code, 127);
+    assert!(stderr.contains("nosuchcmd_xyz"));
+}
+
+#[test]
+fn test_09_exit_255_stops_and_exits_124() {
+    let (stdout, _, code) = run_xargs(&["-n", "1", "sh", "-c", "echo $0; exit $0"], "0 255 0 0");
+
+    assert_eq!(code, 124);
+    assert_eq!(stdout, "0\n255\n", "No new invocations after exit 255");
+}
+
+#[test]
+fn test_10_killed_exits_125() {
+    let (_, _, code) = run_xargs(&["sh", "-c", "kill -TERM $$"], "a");
+
+    assert_eq!(code, 125);
+}
+
+#[test]
+fn test_11_verbose_prints_command() {
+    let (_, stderr, code) = run_xargs(&["-t", "-n", "2", "echo"], "a b c");
+
+    assert_eq!(code, 0);
+    assert!(stderr.contains("echo a b"));
+    assert!(stderr.contains("echo c"));
+}
+
+#[test]
+fn test_12_invalid_option() {
+    let (_, stderr, code) = run_xargs(&["-P", "0"], "a");
+
+    assert_ne!(code, 0);
+    assert!(stderr.contains("invalid number"));
+}
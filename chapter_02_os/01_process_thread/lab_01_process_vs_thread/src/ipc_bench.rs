@@ -0,0 +1,272 @@
+//! `ipc-bench [SIZE...]`: pipe(), UnixStream and shared memory, moving
+//! payloads of each SIZE bytes from a parent to a forked child
+//!
+//! One round trip: the parent sends SIZE bytes, the child receives all of
+//! them into its own buffer and answers with a 1-byte ack. The parent
+//! repeats that until about 64 MiB have moved (at least 100, at most
+//! 10,000 round trips) and reports:
+//! - latency: wall time per round trip. Small payloads measure the
+//!   mechanism itself: system calls and waking the other process
+//! - throughput: payload bytes per second. Large payloads measure copying
+//!
+//! The three mechanisms:
+//! - pipe: two `pipe()`s, one per direction. The kernel copies in and out
+//!   of a 64 KiB buffer, so a big payload goes in several rounds
+//! - UnixStream: one socket pair, both directions. Also a copy in, a copy
+//!   out, through socket buffers
+//! - shared memory: two mailboxes in a `MAP_SHARED` mapping. The sender
+//!   copies straight into the receiver's view and bumps a sequence number;
+//!   the receiver spins (yielding) until it sees it. No system call, but
+//!   no sleeping either: a waiting side burns its CPU
+
+use crate::shm::Mapping;
+use nix::sys::wait::waitpid;
+use nix::unistd::{fork, pipe, ForkResult};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::fd::FromRawFd;
+use std::os::unix::net::UnixStream;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_SIZES: [usize; 4] = [8, 1024, 64 * 1024, 1024 * 1024];
+
+/// Bytes to move per mechanism and size, within the round-trip limits
+const TARGET_BYTES: usize = 64 * 1024 * 1024;
+const MIN_ROUND_TRIPS: usize = 100;
+const MAX_ROUND_TRIPS: usize = 10_000;
+
+#[derive(Clone, Copy)]
+enum Mechanism {
+    Pipe,
+    UnixStream,
+    SharedMemory,
+}
+
+impl Mechanism {
+    const ALL: [Mechanism; 3] = [
+        Mechanism::Pipe,
+        Mechanism::UnixStream,
+        Mechanism::SharedMemory,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Mechanism::Pipe => "pipe",
+            Mechanism::UnixStream => "UnixStream",
+            Mechanism::SharedMemory => "shared memory",
+        }
+    }
+
+    /// (parent's end, child's end), made before the fork so both
+    /// processes inherit them
+    fn pair(self, size: usize) -> io::Result<(Box<dyn Endpoint>, Box<dyn Endpoint>)> {
+        Ok(match self {
+            Mechanism::Pipe => {
+                let (to_child_read, to_child_write) = pipe()?;
+                let (to_parent_read, to_parent_write) = pipe()?;
+                // SAFETY: fresh descriptors from pipe(), each owned by one
+                // File from here on
+                let file = |fd| unsafe { File::from_raw_fd(fd) };
+                (
+                    Box::new(Stream {
+                        reader: file(to_parent_read),
+                        writer: file(to_child_write),
+                    }),
+                    Box::new(Stream {
+                        reader: file(to_child_read),
+                        writer: file(to_parent_write),
+                    }),
+                )
+            }
+            Mechanism::UnixStream => {
+                let (parent, child) = UnixStream::pair()?;
+                (
+                    Box::new(Stream {
+                        reader: parent.try_clone()?,
+                        writer: parent,
+                    }),
+                    Box::new(Stream {
+                        reader: child.try_clone()?,
+                        writer: child,
+                    }),
+                )
+            }
+            Mechanism::SharedMemory => {
+                let (parent, child) = SharedEndpoint::pair(size)?;
+                (Box::new(parent), Box::new(child))
+            }
+        })
+    }
+}
+
+/// One side of a two-way channel. `recv` fills the whole buffer
+trait Endpoint {
+    fn send(&mut self, buf: &[u8]) -> io::Result<()>;
+    fn recv(&mut self, buf: &mut [u8]) -> io::Result<()>;
+}
+
+/// A pipe pair or a socket: bytes through the kernel
+struct Stream<R, W> {
+    reader: R,
+    writer: W,
+}
+
+impl<R: Read, W: Write> Endpoint for Stream<R, W> {
+    fn send(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.writer.write_all(buf)
+    }
+
+    fn recv(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        self.reader.read_exact(buf)
+    }
+}
+
+/// Messages in one direction: a sequence number on its own cache line,
+/// then the bytes of the latest message
+const MAILBOX_DATA: usize = 64;
+
+/// One side of two mailboxes in a shared mapping. Strict ping-pong: a
+/// sender never writes a mailbox again before the answer to its last
+/// message came back, so one message buffer per direction is enough
+struct SharedEndpoint {
+    /// Keeps the mapping alive; both ends of a pair share it
+    mapping: Rc<Mapping>,
+    outbox: usize,
+    inbox: usize,
+    /// Messages this side has received so far
+    seen: u64,
+}
+
+impl SharedEndpoint {
+    fn pair(size: usize) -> io::Result<(Self, Self)> {
+        // Whole cache lines: the second sequence number stays aligned, and
+        // apart from the first mailbox's data
+        let mailbox = (MAILBOX_DATA + size.max(1)).next_multiple_of(64);
+        let mapping = Rc::new(Mapping::new(2 * mailbox)?);
+        let end = |outbox, inbox| SharedEndpoint {
+            mapping: Rc::clone(&mapping),
+            outbox,
+            inbox,
+            seen: 0,
+        };
+        // Mailbox 0 at offset 0 goes to the child, mailbox 1 to the parent
+        Ok((end(0, mailbox), end(mailbox, 0)))
+    }
+
+    fn sequence(&self, mailbox: usize) -> &AtomicU64 {
+        // SAFETY: mailbox offsets are multiples of 64 inside the mapping,
+        // and the sequence number is only ever accessed atomically
+        unsafe { &*self.mapping.as_ptr().add(mailbox).cast::<AtomicU64>() }
+    }
+
+    fn data(&self, mailbox: usize) -> *mut u8 {
+        // SAFETY: each mailbox is MAILBOX_DATA + the largest payload long
+        unsafe { self.mapping.as_ptr().add(mailbox + MAILBOX_DATA) }
+    }
+}
+
+impl Endpoint for SharedEndpoint {
+    fn send(&mut self, buf: &[u8]) -> io::Result<()> {
+        // SAFETY: the receiver doesn't read before the Release below, and
+        // has finished reading the previous message (ping-pong)
+        unsafe {
+            std::ptr::copy_nonoverlapping(buf.as_ptr(), self.data(self.outbox), buf.len());
+        }
+        self.sequence(self.outbox).fetch_add(1, Ordering::Release);
+        Ok(())
+    }
+
+    fn recv(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        while self.sequence(self.inbox).load(Ordering::Acquire) == self.seen {
+            // On a busy machine, spinning without yielding would hold the
+            // CPU the sender needs
+            thread::yield_now();
+        }
+        self.seen += 1;
+        // SAFETY: written before the Release that the Acquire above saw,
+        // and not written again before our answer
+        unsafe {
+            std::ptr::copy_nonoverlapping(self.data(self.inbox), buf.as_mut_ptr(), buf.len());
+        }
+        Ok(())
+    }
+}
+
+fn round_trips(size: usize) -> usize {
+    (TARGET_BYTES / size.max(1)).clamp(MIN_ROUND_TRIPS, MAX_ROUND_TRIPS)
+}
+
+/// Fork a child that answers `trips` round trips of `size` bytes, drive
+/// them from the parent and time it
+fn measure(mechanism: Mechanism, size: usize, trips: usize) -> io::Result<Duration> {
+    let (mut parent, mut child) = mechanism.pair(size)?;
+    match unsafe { fork() }? {
+        ForkResult::Child => {
+            drop(parent);
+            let mut payload = vec![0u8; size];
+            let status = (0..trips).try_for_each(|_| {
+                child.recv(&mut payload)?;
+                child.send(&[1])
+            });
+            std::process::exit(if status.is_ok() { 0 } else { 1 });
+        }
+        ForkResult::Parent { child: pid } => {
+            drop(child);
+            // Not all zeros, so nothing can skip the copy
+            let payload: Vec<u8> = (0..size).map(|i| i as u8).collect();
+            let mut ack = [0u8; 1];
+            let started = Instant::now();
+            for _ in 0..trips {
+                parent.send(&payload)?;
+                parent.recv(&mut ack)?;
+            }
+            let elapsed = started.elapsed();
+            waitpid(pid, None)?;
+            Ok(elapsed)
+        }
+    }
+}
+
+fn format_size(bytes: usize) -> String {
+    match bytes {
+        b if b >= 1024 * 1024 && b % (1024 * 1024) == 0 => format!("{} MiB", b / (1024 * 1024)),
+        b if b >= 1024 && b % 1024 == 0 => format!("{} KiB", b / 1024),
+        b => format!("{} B", b),
+    }
+}
+
+/// Every mechanism at every size, one row each
+pub fn run(sizes: &[usize]) {
+    println!("IPC benchmark: parent sends SIZE bytes, child answers 1 byte");
+    println!(
+        "  {:<14} {:>8} {:>11} {:>12} {:>14}",
+        "mechanism", "size", "round trips", "latency", "throughput"
+    );
+    for &size in sizes {
+        let trips = round_trips(size);
+        for mechanism in Mechanism::ALL {
+            match measure(mechanism, size, trips) {
+                Ok(elapsed) => {
+                    let mib = (size * trips) as f64 / (1024.0 * 1024.0);
+                    println!(
+                        "  {:<14} {:>8} {:>11} {:>12.1?} {:>9.1} MiB/s",
+                        mechanism.name(),
+                        format_size(size),
+                        trips,
+                        elapsed / trips as u32,
+                        mib / elapsed.as_secs_f64()
+                    );
+                }
+                Err(err) => println!(
+                    "  {:<14} {:>8} failed: {}",
+                    mechanism.name(),
+                    format_size(size),
+                    err
+                ),
+            }
+        }
+    }
+}
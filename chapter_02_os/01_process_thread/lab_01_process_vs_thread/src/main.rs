@@ -38,19 +38,203 @@
 //! Warning: Process version requires Linux (uses fork)
 //!
 //! Check solution/main.rs after completing
+//!
+//! ## Extension: CPU Accounting
+//! - Each child reports its own `getrusage`: user/system CPU, max RSS,
+//!   voluntary/involuntary context switches; each thread its CPU time from
+//!   `clock_gettime(CLOCK_THREAD_CPUTIME_ID)` (see `src/usage.rs`)
+//! - Printed per worker under each version's total, with how many cores
+//!   were busy and how much of the total went on creating and collecting
+//!   the workers rather than on summing
+//! - Under `--release` LLVM turns the range sum into a formula: what is
+//!   left to measure is mostly fork/spawn
+//! ```text
+//! # debug build, one CPU: four workers share it, 25% each
+//! Multi-Process version:    1.295546805s, result: 5000000050000000
+//!        pid       wall       user     system  busy    max RSS vol cs invol cs
+//!       9009       1.3s    319.9ms      0.0ns   25%    996 KiB      0       82
+//!   ...
+//!   RUSAGE_CHILDREN: user 1.3s, system 293.0µs
+//!   workers' CPU 1.3s in 1.3s wall: 1.0 cores busy; fork + IPC + waitpid: 609.1µs
+//! ```
+//!
+//! ## Extension: Shared-Memory IPC
+//! - `sum_with_shared_memory(n, num_workers)` forks the same children, but
+//!   they return their results through an anonymous `MAP_SHARED` mapping
+//!   (nix `mmap`, see `src/shm.rs`) instead of a socket pair: each child
+//!   writes its own slot, then bumps an `AtomicU64` in the same region
+//! - The parent waits for the counter to reach the number of children,
+//!   then reads the slots: no write/read system calls, no copy through the
+//!   kernel. The counter's Release/Acquire is what makes the slots visible
+//! - The run ends with each version's overhead (total beyond its slowest
+//!   worker) side by side. With 8 bytes per child the socket isn't what
+//!   costs: fork, exit and waitpid dominate both process versions
+//!
+//! ## Extension: IPC Benchmark
+//! `cargo run --release -- ipc-bench [SIZE_BYTES...]` sets the sum aside
+//! and compares the mechanisms themselves (see `src/ipc_bench.rs`): a
+//! parent and a forked child ping-pong payloads of each size over two
+//! `pipe()`s, a `UnixStream` pair, and mailboxes in shared memory:
+//! ```text
+//! IPC benchmark: parent sends SIZE bytes, child answers 1 byte
+//!   mechanism          size round trips      latency     throughput
+//!   pipe                8 B       10000        3.1µs       2.5 MiB/s
+//!   UnixStream          8 B       10000        5.7µs       1.3 MiB/s
+//!   shared memory       8 B       10000        1.9µs       4.0 MiB/s
+//!   ...
+//!   pipe              1 MiB         100      172.6µs    5794.7 MiB/s
+//!   UnixStream        1 MiB         100      129.4µs    7725.0 MiB/s
+//!   shared memory     1 MiB         100      135.6µs    7374.7 MiB/s
+//! ```
+//! Small messages cost what the mechanism costs: two system calls and a
+//! wake-up per hop for pipes and sockets, an atomic store and a poll for
+//! shared memory. Large ones cost the copies, and a copy into shared
+//! memory is still a copy; the pipe's 64 KiB buffer splits 1 MiB into
+//! many rounds. On one CPU every hop is also a context switch: there the
+//! spinning side only yields, on more cores it would burn its own.
+//!
+//! ## Extension: Process Pool
+//! `ProcessPool` (see `src/process_pool.rs`) is the ThreadPool's API with
+//! forked workers: `new(size, handler)` forks the children once,
+//! `execute(bytes)` queues a job for whichever child is free, and the
+//! returned `Task`'s `join()` waits for its bytes. A child can't receive a
+//! closure, so the work is a `fn(&[u8]) -> Vec<u8>` that fork already
+//! copied into it; jobs and answers are length-prefixed frames over one
+//! `UnixStream` per child.
+//!
+//! `cargo run --release -- pool [ITEMS]` sums 1..=N in ITEMS work items
+//! (default 64), a fork per item against 4 forks up front:
+//! ```text
+//! Process pool: 1..=N in 64 work items, N = 100000000
+//!   fork per item:         10.9ms (64 forks), result: 5000000050000000
+//!   ProcessPool:            1.2ms (4 forks), result: 5000000050000000
+//!   items per worker pid: 1301: 15, 1302: 17, 1303: 15, 1304: 17
+//!   crash test: empty item failed (worker process exited)
+//!   after the crash: 8 of 8 more items done
+//! ```
+//! Forking is the fixed cost a pool pays once. And unlike a panicking
+//! thread, a crashing child loses only its own job: the other children
+//! keep taking the queue.
+//!
+//! ## Extension: Affinity and Scaling Sweep
+//! The run is a driver over a `Config` (N, workers, pinning) parsed from
+//! the command line, `cargo run --release -- [--n N] [--workers W] [--pin]`:
+//! - `--pin` restricts worker i to the i-th CPU this process may use,
+//!   round robin (sched_setaffinity, see `src/affinity.rs`): threads pin
+//!   themselves, children pin right after the fork
+//! - `sweep [--n N] [--workers MAX] [--pin]` times threads and processes
+//!   at every worker count from 1 to MAX (default twice the allowed CPUs),
+//!   best of 3 runs each. The workers really add (`black_box`), so there
+//!   is work to share out
+//! ```text
+//! Scaling sweep: N = 20000000, CPUs allowed: 1, workers pinned: no
+//! Best of 3 runs each; speedup against 1 worker
+//!   workers    threads  speedup  processes  speedup   serial
+//!         1     11.3ms    1.00x     15.0ms    1.00x        -
+//!         2     11.6ms    0.97x     16.2ms    0.93x        -
+//! ```
+//! `serial` is the Karp-Flatt estimate of Amdahl's serial fraction from
+//! the thread speedup on the cores in use: up to the CPU count, speedup
+//! should approach p and flatten as fork/spawn and collection take their
+//! share. Past it, workers only queue for the same cores. On this one-CPU
+//! machine that is every row after the first.
+//!
+//! ## Extension: Portable Processes
+//! fork, rusage and mmap come from nix, a Linux-only dependency here; on
+//! other platforms the fork versions, `pool` and `ipc-bench` are compiled
+//! out. `sum_with_spawned_processes` (see `src/spawn.rs`) works everywhere:
+//! it starts this binary again with `std::process::Command` and the
+//! hidden `worker` argument, writes each child's range to its stdin and
+//! reads the sum from its stdout. It runs on Linux too, last, so the
+//! overheads compare fork with spawn + exec:
+//! ```text
+//! Spawned-Process version:  2.795179ms, result: 5000000050000000
+//!        pid       wall        cpu  busy
+//!       3374      1.2ms      5.2µs    0%
+//!   ...
+//! Overhead beyond the slowest worker:
+//!   threads + channel:          77.9µs
+//!   processes + UnixStream:     367.9µs
+//!   processes + shared memory:  492.0µs
+//!   processes + spawn/exec:     1.6ms
+//! ```
+//! exec loads the program and starts its runtime from scratch: what fork
+//! skips by copying a process that is already running. Outside Linux the
+//! sweep's process column uses these children, and CPU times read 0.
+
+mod affinity;
+#[cfg(target_os = "linux")]
+mod ipc_bench;
+#[cfg(target_os = "linux")]
+mod process_pool;
+#[cfg(target_os = "linux")]
+mod shm;
+mod spawn;
+mod usage;
 
+#[cfg(target_os = "linux")]
+use nix::sys::resource::UsageWho;
+#[cfg(target_os = "linux")]
 use nix::sys::wait::waitpid;
+#[cfg(target_os = "linux")]
 use nix::unistd::{fork, ForkResult};
+#[cfg(target_os = "linux")]
+use process_pool::ProcessPool;
+#[cfg(target_os = "linux")]
+use shm::SharedResults;
+use std::hint::black_box;
+#[cfg(target_os = "linux")]
 use std::io::{Read, Write};
+#[cfg(target_os = "linux")]
 use std::os::fd::AsRawFd;
+#[cfg(target_os = "linux")]
 use std::os::unix::net::UnixStream;
 use std::sync::mpsc;
 use std::thread;
-use std::time::Instant;
+use std::time::{Duration, Instant};
+#[cfg(target_os = "linux")]
+use usage::ChildUsage;
+use usage::ThreadUsage;
 // ============================================================
 // TODO: Implement these two functions
 // ============================================================
 
+/// How every worker of a run is placed and what it computes
+#[derive(Default)]
+struct WorkerOptions {
+    /// Worker i is pinned to `cpus[i % cpus.len()]`; empty: not pinned
+    cpus: Vec<usize>,
+    /// Hide each number from the optimizer, so the sum is really computed
+    /// instead of folded into a formula
+    observe: bool,
+}
+
+impl WorkerOptions {
+    fn cpu_for(&self, worker: usize) -> Option<usize> {
+        (!self.cpus.is_empty()).then(|| self.cpus[worker % self.cpus.len()])
+    }
+}
+
+/// In a worker, before it starts: a failure only costs the pinning
+fn pin_to(cpu: Option<usize>) {
+    if let Some(cpu) = cpu {
+        if let Err(err) = affinity::pin_current_thread(cpu) {
+            eprintln!("Failed to pin to CPU {}: {}", cpu, err);
+        }
+    }
+}
+
+/// start + ... + end, 0 for an empty range
+fn sum_range(start: u64, end: u64, observe: bool) -> u64 {
+    if start > end {
+        0
+    } else if observe {
+        (start..=end).fold(0, |sum, x| sum + black_box(x))
+    } else {
+        (start..=end).sum()
+    }
+}
+
 /// Multi-process version using fork()
 ///
 /// Steps:
@@ -58,13 +242,20 @@ use std::time::Instant;
 /// 2. Fork `num_workers` child processes
 /// 3. Each child computes its portion and sends result to parent
 /// 4. Parent collects all results and sums them
-fn sum_with_processes(n: u64, num_workers: usize) -> u64 {
+///
+/// Also returns each child's pid and resource usage, in worker order
+#[cfg(target_os = "linux")]
+fn sum_with_processes(
+    n: u64,
+    num_workers: usize,
+    options: &WorkerOptions,
+) -> (u64, Vec<(i32, ChildUsage)>) {
     if n == 0 || num_workers == 0 {
-        return 0;
+        return (0, Vec::new());
     }
 
     let workers = num_workers.min(n as usize);
-    let chunk = (n + workers as u64 - 1) / workers as u64;
+    let chunk = n.div_ceil(workers as u64);
     let mut streams = Vec::with_capacity(workers);
     let mut child_pids = Vec::with_capacity(workers);
 
@@ -81,23 +272,22 @@ fn sum_with_processes(n: u64, num_workers: usize) -> u64 {
             end = n;
         }
 
+        // CPU counters start at the fork, so wall time does too
+        let started = Instant::now();
         match unsafe { fork() } {
             Ok(ForkResult::Child) => {
-                eprintln!(
-                    "[child pid={}] stream_fd={}",
-                    std::process::id(),
-                    child_fd
-                );
+                eprintln!("[child pid={}] stream_fd={}", std::process::id(), child_fd);
                 drop(streams);
-                let local_sum = if start > end {
-                    0
-                } else {
-                    (start..=end).sum::<u64>()
-                };
+                pin_to(options.cpu_for(i));
+                let local_sum = sum_range(start, end, options.observe);
+                let usage = ChildUsage::of_self(started.elapsed());
                 let mut stream = child_stream;
                 stream
                     .write_all(&local_sum.to_le_bytes())
                     .expect("Failed to write");
+                stream
+                    .write_all(&usage.to_bytes())
+                    .expect("Failed to write");
                 std::process::exit(0);
             }
             Ok(ForkResult::Parent { child }) => {
@@ -115,17 +305,75 @@ fn sum_with_processes(n: u64, num_workers: usize) -> u64 {
     }
 
     let mut total = 0u64;
-    for mut stream in streams {
+    let mut usages = Vec::with_capacity(workers);
+    for (mut stream, pid) in streams.into_iter().zip(&child_pids) {
         let mut buf = [0u8; 8];
         stream.read_exact(&mut buf).expect("Failed to read");
         total += u64::from_le_bytes(buf);
+        let mut usage = [0u8; ChildUsage::BYTES];
+        stream.read_exact(&mut usage).expect("Failed to read");
+        usages.push((pid.as_raw(), ChildUsage::from_bytes(&usage)));
     }
 
     for pid in child_pids {
         waitpid(pid, None).expect("Failed to wait");
     }
 
-    total
+    (total, usages)
+}
+
+/// Multi-process version that returns results through shared memory
+///
+/// Same fork and chunks as `sum_with_processes`, but no socket: the
+/// parent maps a `MAP_SHARED` region first, each child writes its partial
+/// sum and usage into its own slot and bumps an atomic counter, and the
+/// parent reads the slots once the counter reaches `num_workers` (see
+/// `src/shm.rs`)
+#[cfg(target_os = "linux")]
+fn sum_with_shared_memory(
+    n: u64,
+    num_workers: usize,
+    options: &WorkerOptions,
+) -> (u64, Vec<(i32, ChildUsage)>) {
+    if n == 0 || num_workers == 0 {
+        return (0, Vec::new());
+    }
+
+    let workers = num_workers.min(n as usize);
+    let chunk = n.div_ceil(workers as u64);
+    let shared = SharedResults::new(workers).expect("Failed to map shared memory");
+    let mut child_pids = Vec::with_capacity(workers);
+
+    for i in 0..workers {
+        let start = i as u64 * chunk + 1;
+        let end = ((i as u64 + 1) * chunk).min(n);
+
+        let started = Instant::now();
+        match unsafe { fork() } {
+            Ok(ForkResult::Child) => {
+                pin_to(options.cpu_for(i));
+                let local_sum = sum_range(start, end, options.observe);
+                let usage = ChildUsage::of_self(started.elapsed());
+                shared.publish(i, local_sum, usage);
+                std::process::exit(0);
+            }
+            Ok(ForkResult::Parent { child }) => child_pids.push(child),
+            Err(err) => panic!("Fork failed: {}", err),
+        }
+    }
+
+    let results = shared.collect();
+    for &pid in &child_pids {
+        waitpid(pid, None).expect("Failed to wait");
+    }
+
+    let total = results.iter().map(|&(sum, _)| sum).sum();
+    let usages = child_pids
+        .iter()
+        .zip(results)
+        .map(|(pid, (_, usage))| (pid.as_raw(), usage))
+        .collect();
+    (total, usages)
 }
 
 /// Multi-thread version using std::thread
@@ -135,13 +383,19 @@ fn sum_with_processes(n: u64, num_workers: usize) -> u64 {
 /// 2. Spawn `num_workers` threads
 /// 3. Each thread computes its portion
 /// 4. Collect and sum all results
-fn sum_with_threads(n: u64, num_workers: usize) -> u64 {
+///
+/// Also returns each thread's CPU and wall time, in worker order
+fn sum_with_threads(
+    n: u64,
+    num_workers: usize,
+    options: &WorkerOptions,
+) -> (u64, Vec<ThreadUsage>) {
     if n == 0 || num_workers == 0 {
-        return 0;
+        return (0, Vec::new());
     }
     let workers = num_workers.min(n as usize); // 避免開太多無事可做的 thread
-    let chunk = (n + workers as u64 - 1) / workers as u64;
-    let (tx, rx) = mpsc::channel::<u64>();
+    let chunk = n.div_ceil(workers as u64);
+    let (tx, rx) = mpsc::channel::<(usize, u64, ThreadUsage)>();
     for i in 0..workers {
         let tx = tx.clone();
 
@@ -152,58 +406,345 @@ fn sum_with_threads(n: u64, num_workers: usize) -> u64 {
             end = n;
         }
 
+        // Thread CPU time starts at creation, so wall time does too
+        let started = Instant::now();
+        let (cpu, observe) = (options.cpu_for(i), options.observe);
         thread::spawn(move || {
+            pin_to(cpu);
             // 如果因為 min/ceil 邏輯導致空區間，直接回 0
-            let local_sum = if start > end {
-                0
-            } else {
-                (start..=end).sum::<u64>()
-            };
+            let local_sum = sum_range(start, end, observe);
 
-            tx.send(local_sum).expect("receiver dropped");
+            let usage = ThreadUsage::of_self(started.elapsed());
+            tx.send((i, local_sum, usage)).expect("receiver dropped");
         });
     }
 
     drop(tx); // 很重要：關閉原始 sender，讓 rx 知道何時結束
 
     // 收集所有部分和
-    rx.iter().sum()
+    let mut total = 0u64;
+    let mut usages = vec![ThreadUsage::default(); workers];
+    for (i, local_sum, usage) in rx {
+        total += local_sum;
+        usages[i] = usage;
+    }
+    (total, usages)
 }
 
 // ============================================================
 // Benchmarking code (no modification needed)
 // ============================================================
 
-fn benchmark<F>(name: &str, f: F) -> u64
+/// Also returns how long `f` took, for the per-worker breakdown
+fn benchmark<F, T>(name: &str, f: F) -> (u64, T, Duration)
 where
-    F: FnOnce() -> u64,
+    F: FnOnce() -> (u64, T),
 {
     let start = Instant::now();
-    let result = f();
+    let (result, usage) = f();
     let duration = start.elapsed();
     println!("{:25} {:?}, result: {}", name, duration, result);
-    result
+    (result, usage, duration)
+}
+
+/// The ProcessPool's handler: bytes in, bytes out. A work item is two
+/// little-endian u64s, start and end; the answer is their range sum and
+/// the pid that computed it. An empty item panics, to show what a crashing
+/// worker does to the pool
+#[cfg(target_os = "linux")]
+fn sum_work_item(input: &[u8]) -> Vec<u8> {
+    assert!(!input.is_empty(), "empty work item");
+    let word = |i: usize| u64::from_le_bytes(input[i * 8..i * 8 + 8].try_into().unwrap());
+    let sum = sum_range(word(0), word(1), false);
+    let mut output = sum.to_le_bytes().to_vec();
+    output.extend_from_slice(&std::process::id().to_le_bytes());
+    output
+}
+
+/// 1..=n in `items` work items: a fork per item, then 4 children forked
+/// once and fed the items over sockets
+#[cfg(target_os = "linux")]
+fn process_pool_demo(n: u64, items: usize) {
+    println!("Process pool: 1..=N in {} work items, N = {}", items, n);
+    let expected = n * (n + 1) / 2;
+
+    let started = Instant::now();
+    let (result, _) = sum_with_processes(n, items, &WorkerOptions::default());
+    println!(
+        "  fork per item:     {:>10.1?} ({} forks), result: {}",
+        started.elapsed(),
+        items,
+        result
+    );
+    assert_eq!(result, expected, "Fork-per-item result mismatch!");
+
+    let started = Instant::now();
+    let pool = ProcessPool::new(4, sum_work_item);
+    let chunk = n.div_ceil(items as u64);
+    let tasks: Vec<_> = (0..items as u64)
+        .map(|i| {
+            let start = i * chunk + 1;
+            let end = ((i + 1) * chunk).min(n);
+            let mut item = start.to_le_bytes().to_vec();
+            item.extend_from_slice(&end.to_le_bytes());
+            pool.execute(item)
+        })
+        .collect();
+    let mut result = 0u64;
+    let mut served: Vec<(i32, usize)> = pool.pids().into_iter().map(|pid| (pid, 0)).collect();
+    for task in tasks {
+        let output = task.join().expect("a work item failed");
+        result += u64::from_le_bytes(output[..8].try_into().unwrap());
+        let pid = i32::from_le_bytes(output[8..12].try_into().unwrap());
+        if let Some((_, count)) = served.iter_mut().find(|(p, _)| *p == pid) {
+            *count += 1;
+        }
+    }
+    println!(
+        "  ProcessPool:       {:>10.1?} (4 forks), result: {}",
+        started.elapsed(),
+        result
+    );
+    assert_eq!(result, expected, "ProcessPool result mismatch!");
+    let served: Vec<String> = served
+        .iter()
+        .map(|(pid, count)| format!("{}: {}", pid, count))
+        .collect();
+    println!("  items per worker pid: {}", served.join(", "));
+
+    // A worker that panics dies alone: its job fails, the rest go on
+    match pool.execute(Vec::new()).join() {
+        Ok(_) => println!("  crash test: the empty item did not fail"),
+        Err(err) => println!("  crash test: empty item failed ({})", err),
+    }
+    let after: Vec<_> = (0..8)
+        .map(|i| {
+            let mut item = (i * 10 + 1_u64).to_le_bytes().to_vec();
+            item.extend_from_slice(&(i * 10 + 10_u64).to_le_bytes());
+            pool.execute(item)
+        })
+        .collect();
+    let done = after
+        .into_iter()
+        .filter_map(|task| task.join().ok())
+        .count();
+    println!("  after the crash: {} of 8 more items done", done);
+}
+
+/// One run of the benchmark, from the command line
+struct Config {
+    n: u64,
+    /// Workers per version; for `sweep`, the largest count
+    workers: Option<usize>,
+    pin: bool,
+}
+
+enum Command {
+    Compare(Config),
+    Sweep(Config),
+    Pool(usize),
+    /// Empty: the default sizes
+    IpcBench(Vec<usize>),
+    /// A child started by `sum_with_spawned_processes`
+    Worker,
+}
+
+const USAGE: &str = "\
+Usage: process_vs_thread [--n N] [--workers W] [--pin]
+       process_vs_thread sweep [--n N] [--workers MAX] [--pin]
+       process_vs_thread pool [ITEMS]
+       process_vs_thread ipc-bench [SIZE_BYTES...]
+pool and ipc-bench fork, and run on Linux only
+";
+
+/// None for anything USAGE doesn't allow
+fn parse_args(args: &[String]) -> Option<Command> {
+    fn positive<T: std::str::FromStr + Default + PartialOrd>(arg: Option<&String>) -> Option<T> {
+        arg?.parse().ok().filter(|value| *value > T::default())
+    }
+    fn config(flags: &[String]) -> Option<Config> {
+        let mut config = Config {
+            n: 100_000_000,
+            workers: None,
+            pin: false,
+        };
+        let mut flags = flags.iter();
+        while let Some(flag) = flags.next() {
+            match flag.as_str() {
+                "--n" => config.n = positive(flags.next())?,
+                "--workers" => config.workers = Some(positive(flags.next())?),
+                "--pin" => config.pin = true,
+                _ => return None,
+            }
+        }
+        Some(config)
+    }
+
+    match args.first().map(String::as_str) {
+        Some("sweep") => config(&args[1..]).map(Command::Sweep),
+        Some("pool") => match args.len() {
+            1 => Some(Command::Pool(64)),
+            2 => positive(args.get(1)).map(Command::Pool),
+            _ => None,
+        },
+        Some("ipc-bench") => args[1..]
+            .iter()
+            .map(|size| positive(Some(size)))
+            .collect::<Option<_>>()
+            .map(Command::IpcBench),
+        Some(spawn::WORKER_ARG) if args.len() == 1 => Some(Command::Worker),
+        _ => config(args).map(Command::Compare),
+    }
+}
+
+/// The CPUs to pin to, if `--pin` was given and pinning is possible here
+fn worker_options(config: &Config, observe: bool) -> WorkerOptions {
+    let cpus = if config.pin {
+        affinity::allowed_cpus().unwrap_or_else(|err| {
+            eprintln!("Workers not pinned: {}", err);
+            Vec::new()
+        })
+    } else {
+        Vec::new()
+    };
+    WorkerOptions { cpus, observe }
+}
+
+/// The fastest of 3 runs, so one unlucky time slice doesn't make the row
+fn best_of_3(expected: u64, mut run: impl FnMut() -> u64) -> Duration {
+    (0..3)
+        .map(|_| {
+            let started = Instant::now();
+            let result = run();
+            let elapsed = started.elapsed();
+            assert_eq!(result, expected, "Sweep result mismatch!");
+            elapsed
+        })
+        .min()
+        .unwrap()
+}
+
+/// Every worker count from 1 to twice the CPUs, threads against processes
+fn sweep(config: &Config) {
+    let n = config.n;
+    let expected = n * (n + 1) / 2;
+    let cores = affinity::allowed_cpus()
+        .map(|cpus| cpus.len())
+        .unwrap_or_else(|_| thread::available_parallelism().map_or(1, |cores| cores.get()));
+    let max_workers = config.workers.unwrap_or(2 * cores);
+    // Observed: a formula would take no time at any worker count
+    let options = worker_options(config, true);
+
+    println!(
+        "Scaling sweep: N = {}, CPUs allowed: {}, workers pinned: {}",
+        n,
+        cores,
+        if options.cpus.is_empty() { "no" } else { "yes" }
+    );
+    println!("Best of 3 runs each; speedup against 1 worker");
+    println!(
+        "  {:>7} {:>10} {:>8} {:>10} {:>8} {:>8}",
+        "workers", "threads", "speedup", "processes", "speedup", "serial"
+    );
+    let mut baseline = None;
+    for workers in 1..=max_workers {
+        let threads = best_of_3(expected, || sum_with_threads(n, workers, &options).0);
+        #[cfg(target_os = "linux")]
+        let processes = best_of_3(expected, || sum_with_processes(n, workers, &options).0);
+        // No fork elsewhere: the processes are spawned
+        #[cfg(not(target_os = "linux"))]
+        let processes = best_of_3(expected, || {
+            spawn::sum_with_spawned_processes(n, workers, &options).0
+        });
+        let (threads_1, processes_1) = *baseline.get_or_insert((threads, processes));
+        let speedup = threads_1.as_secs_f64() / threads.as_secs_f64();
+
+        // Karp-Flatt: the serial fraction that explains the thread speedup
+        // on the cores actually used; past the CPU count workers only queue
+        let used = workers.min(cores) as f64;
+        let serial = if used < 2.0 {
+            "-".to_string()
+        } else {
+            format!("{:.2}", (1.0 / speedup - 1.0 / used) / (1.0 - 1.0 / used))
+        };
+        println!(
+            "  {:>7} {:>10.1?} {:>7.2}x {:>10.1?} {:>7.2}x {:>8}",
+            workers,
+            threads,
+            speedup,
+            processes,
+            processes_1.as_secs_f64() / processes.as_secs_f64(),
+            serial
+        );
+    }
+    println!("Amdahl: with serial fraction s, p cores give at most 1 / (s + (1 - s) / p)");
+    println!(
+        "Workers beyond the {} allowed CPUs add no cores, only creating and switching",
+        cores
+    );
 }
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match parse_args(&args) {
+        Some(Command::Compare(config)) => compare(&config),
+        Some(Command::Sweep(config)) => sweep(&config),
+        #[cfg(target_os = "linux")]
+        Some(Command::Pool(items)) => process_pool_demo(100_000_000, items),
+        #[cfg(target_os = "linux")]
+        Some(Command::IpcBench(sizes)) if sizes.is_empty() => {
+            ipc_bench::run(&ipc_bench::DEFAULT_SIZES)
+        }
+        #[cfg(target_os = "linux")]
+        Some(Command::IpcBench(sizes)) => ipc_bench::run(&sizes),
+        #[cfg(not(target_os = "linux"))]
+        Some(Command::Pool(_) | Command::IpcBench(_)) => {
+            eprintln!("pool and ipc-bench use fork: Linux only");
+            std::process::exit(2);
+        }
+        Some(Command::Worker) => {
+            if let Err(err) = spawn::worker_main() {
+                eprintln!("worker: {}", err);
+                std::process::exit(1);
+            }
+        }
+        None => {
+            eprint!("{}", USAGE);
+            std::process::exit(2);
+        }
+    }
+}
+
+/// Every version once at `config.workers` (default 4), with the
+/// per-worker breakdown
+fn compare(config: &Config) {
     // Check if we're on Linux (fork requires it)
     #[cfg(not(target_os = "linux"))]
     {
-        eprintln!("Warning: Process version requires Linux (uses fork)");
-        eprintln!("Thread version will still work on any platform");
+        eprintln!("Warning: fork versions require Linux, skipped");
+        eprintln!("Thread and spawned-process versions work on any platform");
     }
 
-    let n: u64 = 100_000_000;
-    let num_workers = 4;
+    let n = config.n;
+    let num_workers = config.workers.unwrap_or(4);
     let expected = n * (n + 1) / 2;
+    let options = worker_options(config, false);
 
     println!("N = {}, Workers = {}", n, num_workers);
+    if !options.cpus.is_empty() {
+        println!("Workers pinned round robin to CPUs {:?}", options.cpus);
+    }
     println!("Expected result: {}", expected);
     println!("{}", "=".repeat(60));
 
     // Multi-thread version
-    let multithread_result =
-        benchmark("Multi-Thread version:", || sum_with_threads(n, num_workers));
+    let (multithread_result, threads, elapsed) = benchmark("Multi-Thread version:", || {
+        sum_with_threads(n, num_workers, &options)
+    });
+    let mut overheads = vec![(
+        "threads + channel:",
+        usage::print_threads(&threads, elapsed),
+    )];
     assert_eq!(
         multithread_result, expected,
         "Thread version result mismatch!"
@@ -212,14 +753,47 @@ fn main() {
     // Multi-process version
     #[cfg(target_os = "linux")]
     {
-        let result = benchmark("Multi-Process version:", || {
-            sum_with_processes(n, num_workers)
+        let (result, children, elapsed) = benchmark("Multi-Process version:", || {
+            sum_with_processes(n, num_workers, &options)
         });
+        let (pids, children): (Vec<i32>, Vec<ChildUsage>) = children.into_iter().unzip();
+        let reaped = usage::rusage(UsageWho::RUSAGE_CHILDREN);
+        let socket_overhead =
+            usage::print_children(&pids, &children, reaped, elapsed, "fork + IPC + waitpid");
+        overheads.push(("processes + UnixStream:", socket_overhead));
         assert_eq!(result, expected, "Process version result mismatch!");
+
+        // Same processes, results through shared memory
+        let (result, children, elapsed) = benchmark("Shared-Memory version:", || {
+            sum_with_shared_memory(n, num_workers, &options)
+        });
+        let (pids, children): (Vec<i32>, Vec<ChildUsage>) = children.into_iter().unzip();
+        // RUSAGE_CHILDREN keeps adding up: it now includes the previous children
+        let reaped = usage::rusage(UsageWho::RUSAGE_CHILDREN);
+        let shm_overhead =
+            usage::print_children(&pids, &children, reaped, elapsed, "fork + shm + waitpid");
+        overheads.push(("processes + shared memory:", shm_overhead));
+        assert_eq!(result, expected, "Shared-memory version result mismatch!");
+    }
+
+    // Processes on any platform: this binary again, in worker mode
+    let (result, children, elapsed) = benchmark("Spawned-Process version:", || {
+        spawn::sum_with_spawned_processes(n, num_workers, &options)
+    });
+    overheads.push((
+        "processes + spawn/exec:",
+        usage::print_spawned(&children, elapsed),
+    ));
+    assert_eq!(result, expected, "Spawned-process version result mismatch!");
+
+    println!("{}", "-".repeat(60));
+    println!("Overhead beyond the slowest worker:");
+    for (version, overhead) in overheads {
+        println!("  {:<28}{:.1?}", version, overhead);
     }
 
     println!("{}", "=".repeat(60));
-    println!("Both versions produced correct results!");
+    println!("All versions produced correct results!");
 
     println!("\nTry observing with:");
     println!("  htop    # Watch process/thread creation");
@@ -0,0 +1,211 @@
+//! `ProcessPool`: the ThreadPool's API, with forked workers
+//!
+//! A thread pool hands its workers closures; a child process can't
+//! receive a closure, only bytes. So the work is a handler, a plain `fn`
+//! that every child already has because fork copied the whole program,
+//! and a job is the bytes to call it with:
+//!
+//! ```text
+//! ProcessPool::new(4, handler)   fork 4 children, once
+//! pool.execute(bytes) -> Task    queue a job
+//! task.join() -> bytes           wait for its result
+//! drop(pool)                     finish the queue, children exit, waitpid
+//! ```
+//!
+//! Distribution mirrors the ThreadPool: one queue, and per child a thread
+//! in the parent that takes the next job, sends it over the child's
+//! socket, and waits for the answer. A busy child's thread doesn't take
+//! jobs, so work goes to whichever child is free. On the socket, every
+//! message is a u32 little-endian length and that many bytes.
+//!
+//! Unlike a thread, a child that crashes takes nothing else down: its job
+//! fails with an error, its thread stops taking jobs, and the other
+//! children carry on.
+
+use nix::sys::wait::waitpid;
+use nix::unistd::{fork, ForkResult, Pid};
+use std::io::{self, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+/// What every child runs on each job's bytes
+pub type Handler = fn(&[u8]) -> Vec<u8>;
+
+struct Job {
+    input: Vec<u8>,
+    reply: mpsc::Sender<io::Result<Vec<u8>>>,
+}
+
+/// A queued job's result, as `ThreadPool::execute_with_result`'s
+pub struct Task {
+    result: mpsc::Receiver<io::Result<Vec<u8>>>,
+}
+
+impl Task {
+    /// Block until the job is done. An error if its child died or the
+    /// socket failed: the job may not have run
+    pub fn join(self) -> io::Result<Vec<u8>> {
+        self.result.recv().unwrap_or_else(|_| {
+            Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "no worker left to run the job",
+            ))
+        })
+    }
+}
+
+/// A child process and the parent's thread that feeds it
+struct Worker {
+    pid: Pid,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+pub struct ProcessPool {
+    workers: Vec<Worker>,
+    sender: Option<mpsc::Sender<Job>>,
+}
+
+fn write_frame(stream: &mut UnixStream, bytes: &[u8]) -> io::Result<()> {
+    let len = u32::try_from(bytes.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "message over 4 GiB"))?;
+    stream.write_all(&len.to_le_bytes())?;
+    stream.write_all(bytes)
+}
+
+/// None at end of stream, before a frame starts
+fn read_frame(stream: &mut UnixStream) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0u8; 4];
+    match stream.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+    let mut bytes = vec![0u8; u32::from_le_bytes(len) as usize];
+    stream.read_exact(&mut bytes)?;
+    Ok(Some(bytes))
+}
+
+/// The child's whole life: answer jobs until the parent closes the socket
+fn serve(mut stream: UnixStream, handler: Handler) -> ! {
+    let status = panic::catch_unwind(AssertUnwindSafe(|| -> io::Result<()> {
+        while let Some(input) = read_frame(&mut stream)? {
+            write_frame(&mut stream, &handler(&input))?;
+        }
+        Ok(())
+    }));
+    // Never return into the parent's code that forked us
+    std::process::exit(match status {
+        Ok(Ok(())) => 0,
+        Ok(Err(_)) => 1,
+        // The panic message is already on stderr
+        Err(_) => 101,
+    });
+}
+
+/// The parent's side of one child: take a job, hand it over, pass the
+/// answer back
+fn feed(mut stream: UnixStream, jobs: Arc<Mutex<mpsc::Receiver<Job>>>) {
+    loop {
+        // The guard is a temporary: the lock is released before the job
+        // is sent, so the other threads can take the next ones
+        let Ok(job) = jobs.lock().unwrap().recv() else {
+            break; // The pool is dropped and the queue is empty
+        };
+        let result = write_frame(&mut stream, &job.input).and_then(|()| {
+            read_frame(&mut stream)?.ok_or_else(|| {
+                io::Error::new(io::ErrorKind::UnexpectedEof, "worker process exited")
+            })
+        });
+        let failed = result.is_err();
+        let _ = job.reply.send(result);
+        if failed {
+            // The child is gone or out of step: leave the jobs to the others
+            break;
+        }
+    }
+    // Dropping the stream closes the socket: the child sees the end and exits
+}
+
+impl ProcessPool {
+    /// Fork `size` children running `handler`
+    ///
+    /// # Panics
+    /// Panics if size is 0, or a socket pair or fork fails
+    pub fn new(size: usize, handler: Handler) -> ProcessPool {
+        assert!(size > 0, "ProcessPool size must be > 0");
+
+        // Every fork first, then the threads: forking a process that
+        // already runs threads gives a child where their locks may be held
+        let mut children = Vec::with_capacity(size);
+        for _ in 0..size {
+            let (parent_stream, child_stream) =
+                UnixStream::pair().expect("Failed to create socket pair");
+            match unsafe { fork() } {
+                Ok(ForkResult::Child) => {
+                    // The parent's ends of the earlier children's sockets
+                    // came along: close them, or those children never see
+                    // their socket close
+                    drop(children);
+                    drop(parent_stream);
+                    serve(child_stream, handler);
+                }
+                Ok(ForkResult::Parent { child }) => {
+                    drop(child_stream);
+                    children.push((child, parent_stream));
+                }
+                Err(err) => panic!("Fork failed: {}", err),
+            }
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = children
+            .into_iter()
+            .map(|(pid, stream)| {
+                let jobs = Arc::clone(&receiver);
+                Worker {
+                    pid,
+                    thread: Some(thread::spawn(move || feed(stream, jobs))),
+                }
+            })
+            .collect();
+
+        ProcessPool {
+            workers,
+            sender: Some(sender),
+        }
+    }
+
+    /// Queue `input` for the next free child
+    pub fn execute(&self, input: Vec<u8>) -> Task {
+        let (reply, result) = mpsc::channel();
+        // Fails only once every child is gone; the job is dropped with its
+        // reply sender, and `join` says so
+        let _ = self
+            .sender
+            .as_ref()
+            .expect("the sender lives until drop")
+            .send(Job { input, reply });
+        Task { result }
+    }
+
+    /// The children's pids, in worker order
+    pub fn pids(&self) -> Vec<i32> {
+        self.workers.iter().map(|w| w.pid.as_raw()).collect()
+    }
+}
+
+impl Drop for ProcessPool {
+    /// Run every queued job, then let the children exit and reap them
+    fn drop(&mut self) {
+        drop(self.sender.take());
+        for worker in &mut self.workers {
+            if let Some(thread) = worker.thread.take() {
+                let _ = thread.join();
+            }
+            let _ = waitpid(worker.pid, None);
+        }
+    }
+}
@@ -0,0 +1,140 @@
+//! Results from child processes through shared memory instead of sockets
+//!
+//! The parent maps an anonymous region with `MAP_SHARED` before it forks,
+//! so every child sees the same physical pages. Layout:
+//!
+//! ```text
+//! offset 0   done: AtomicU64     children that have published
+//! offset 64  slot 0: sum, usage  written by child 0 only
+//!            slot 1: sum, usage  ...
+//! ```
+//!
+//! A child writes its slot with plain stores, then bumps `done` with
+//! `Release`; the parent waits until it reads `done == workers` with
+//! `Acquire`, and from then on sees every slot. No system call carries a
+//! result: no write/read, no copy through the kernel. The price is the
+//! wait, which here spins (yielding) where a socket read would sleep.
+
+use crate::usage::ChildUsage;
+use nix::sys::mman::{mmap, munmap, MapFlags, ProtFlags};
+use std::num::NonZeroUsize;
+use std::os::fd::BorrowedFd;
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+
+/// `done` gets a cache line of its own, so polling it doesn't fight the
+/// children's slot writes
+const SLOTS_OFFSET: usize = 64;
+
+#[repr(C)]
+struct Slot {
+    sum: u64,
+    usage: [u8; ChildUsage::BYTES],
+}
+
+/// An anonymous `MAP_SHARED` mapping: zeroed, page-aligned, and the same
+/// pages in every child forked after it was made. Unmapped on drop
+pub struct Mapping {
+    base: NonNull<u8>,
+    len: usize,
+}
+
+impl Mapping {
+    pub fn new(len: usize) -> nix::Result<Self> {
+        // SAFETY: a fresh anonymous mapping, no address hint: nothing
+        // existing is aliased or replaced
+        let base = unsafe {
+            mmap(
+                None,
+                NonZeroUsize::new(len).expect("mapping length must be > 0"),
+                ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
+                MapFlags::MAP_SHARED | MapFlags::MAP_ANONYMOUS,
+                None::<BorrowedFd>,
+                0,
+            )?
+        };
+        Ok(Self {
+            base: NonNull::new(base.cast()).expect("mmap never returns null"),
+            len,
+        })
+    }
+
+    /// The first byte; the length given to `new` is mapped from here
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.base.as_ptr()
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        // SAFETY: the mapping from `new`; whoever hands out pointers into
+        // it keeps the Mapping alive while they are used
+        let _ = unsafe { munmap(self.base.as_ptr().cast(), self.len) };
+    }
+}
+
+/// A shared mapping with one result slot per worker
+pub struct SharedResults {
+    mapping: Mapping,
+    workers: usize,
+}
+
+impl SharedResults {
+    /// Map the region. Anonymous mappings start zeroed: `done` is 0
+    pub fn new(workers: usize) -> nix::Result<Self> {
+        let len = SLOTS_OFFSET + workers * std::mem::size_of::<Slot>();
+        Ok(Self {
+            mapping: Mapping::new(len)?,
+            workers,
+        })
+    }
+
+    fn done(&self) -> &AtomicU64 {
+        // SAFETY: offset 0 is in the mapping, page-aligned, and only ever
+        // accessed atomically
+        unsafe { &*self.mapping.as_ptr().cast::<AtomicU64>() }
+    }
+
+    fn slot(&self, worker: usize) -> *mut Slot {
+        assert!(worker < self.workers, "no slot for worker {}", worker);
+        // SAFETY: SLOTS_OFFSET + workers slots is the mapping's length
+        unsafe {
+            self.mapping
+                .as_ptr()
+                .add(SLOTS_OFFSET)
+                .cast::<Slot>()
+                .add(worker)
+        }
+    }
+
+    /// In child `worker`: write its slot, then count it as published
+    pub fn publish(&self, worker: usize, sum: u64, usage: ChildUsage) {
+        // SAFETY: each child writes only its own slot, and the parent
+        // doesn't read it before the Release below
+        unsafe {
+            self.slot(worker).write(Slot {
+                sum,
+                usage: usage.to_bytes(),
+            });
+        }
+        self.done().fetch_add(1, Ordering::Release);
+    }
+
+    /// In the parent: wait until every child has published, then read the
+    /// slots in worker order
+    pub fn collect(&self) -> Vec<(u64, ChildUsage)> {
+        while self.done().load(Ordering::Acquire) < self.workers as u64 {
+            // Let the children have the CPU we'd spin on
+            thread::yield_now();
+        }
+        (0..self.workers)
+            .map(|worker| {
+                // SAFETY: published: written before the Release that the
+                // Acquire above saw, and never written again
+                let slot = unsafe { &*self.slot(worker) };
+                (slot.sum, ChildUsage::from_bytes(&slot.usage))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,139 @@
+//! Processes without fork: this same binary, started again in worker mode
+//!
+//! `fork()` copies the running process, and Windows has none. The portable
+//! way is what `std::process::Command` offers everywhere: the parent runs
+//! its own executable (`std::env::current_exe`) with the `worker` argument,
+//! writes the range to the child's stdin and reads the answer from its
+//! stdout:
+//!
+//! ```text
+//! parent                                child: process_vs_thread worker
+//! spawn, stdin + stdout piped      ->   main() sees "worker"
+//! write "START END OBSERVE CPU\n"  ->   read stdin, sum the range
+//! read stdout, wait                <-   print "SUM WALL_NS CPU_NS\n", exit
+//! ```
+//!
+//! The child starts from `main()`: the executable is loaded and the
+//! runtime initialised again, and none of the parent's memory comes along.
+//! That is the work fork skips, and what this version costs on top of it.
+
+use crate::usage::ThreadUsage;
+use crate::{pin_to, sum_range, WorkerOptions};
+use std::io::{self, BufRead, Read, Write};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// The argument that puts the binary in worker mode
+pub const WORKER_ARG: &str = "worker";
+
+/// One spawned child's pid and its own measurement of its work
+pub type SpawnedUsage = (u32, ThreadUsage);
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Worker mode: one range from stdin, its sum and usage to stdout
+pub fn worker_main() -> io::Result<()> {
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let [start, end, observe, cpu] = fields[..] else {
+        return Err(invalid("expected START END OBSERVE CPU"));
+    };
+    let number = |field: &str| field.parse::<u64>().map_err(|_| invalid(field));
+    let (start, end) = (number(start)?, number(end)?);
+
+    // Like a forked child's, the usage covers the sum only: not loading
+    // the program, not waiting for stdin
+    let started = Instant::now();
+    let cpu_before = ThreadUsage::of_self(Duration::ZERO).cpu;
+    // "-": not pinned
+    pin_to(cpu.parse().ok());
+    let sum = sum_range(start, end, observe == "1");
+    let mut usage = ThreadUsage::of_self(started.elapsed());
+    usage.cpu = usage.cpu.saturating_sub(cpu_before);
+    writeln!(
+        io::stdout(),
+        "{} {} {}",
+        sum,
+        usage.wall.as_nanos(),
+        usage.cpu.as_nanos()
+    )
+}
+
+/// Start one worker on start..=end; its stdin is closed once the range is
+/// written
+fn spawn_worker(start: u64, end: u64, observe: bool, cpu: Option<usize>) -> io::Result<Child> {
+    let mut child = Command::new(std::env::current_exe()?)
+        .arg(WORKER_ARG)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let cpu = cpu.map_or("-".to_string(), |cpu| cpu.to_string());
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    writeln!(stdin, "{} {} {} {}", start, end, observe as u8, cpu)?;
+    Ok(child)
+}
+
+/// Read a worker's answer and wait for it to exit
+fn collect(mut child: Child) -> io::Result<(u64, ThreadUsage)> {
+    let mut answer = String::new();
+    child
+        .stdout
+        .take()
+        .expect("stdout is piped")
+        .read_to_string(&mut answer)?;
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("worker exited with {}", status)));
+    }
+    let fields: Vec<u64> = answer
+        .split_whitespace()
+        .map(|field| field.parse().map_err(|_| invalid(field)))
+        .collect::<io::Result<_>>()?;
+    let [sum, wall, cpu] = fields[..] else {
+        return Err(invalid("expected SUM WALL_NS CPU_NS"));
+    };
+    let usage = ThreadUsage {
+        wall: Duration::from_nanos(wall),
+        cpu: Duration::from_nanos(cpu),
+    };
+    Ok((sum, usage))
+}
+
+/// Multi-process version without fork, on any platform
+///
+/// Same chunks as `sum_with_processes`: every child is started first, so
+/// they run side by side, then each is read and waited for in turn. Also
+/// returns each child's pid and usage, in worker order
+pub fn sum_with_spawned_processes(
+    n: u64,
+    num_workers: usize,
+    options: &WorkerOptions,
+) -> (u64, Vec<SpawnedUsage>) {
+    if n == 0 || num_workers == 0 {
+        return (0, Vec::new());
+    }
+
+    let workers = num_workers.min(n as usize);
+    let chunk = n.div_ceil(workers as u64);
+    let children: Vec<Child> = (0..workers)
+        .map(|i| {
+            let start = i as u64 * chunk + 1;
+            let end = ((i as u64 + 1) * chunk).min(n);
+            spawn_worker(start, end, options.observe, options.cpu_for(i))
+                .expect("Failed to spawn worker")
+        })
+        .collect();
+
+    let mut total = 0u64;
+    let mut usages = Vec::with_capacity(workers);
+    for child in children {
+        let pid = child.id();
+        let (sum, usage) = collect(child).expect("Worker failed");
+        total += sum;
+        usages.push((pid, usage));
+    }
+    (total, usages)
+}
@@ -0,0 +1,240 @@
+//! Where the time went: CPU and wall time per worker
+//!
+//! A total like "120ms" hides whether the workers were computing, waiting
+//! to be scheduled, or whether the time went on fork and IPC. So each
+//! worker measures itself when its chunk is done:
+//!
+//! - a child process calls `getrusage(RUSAGE_SELF)`: user and system CPU,
+//!   peak RSS, voluntary and involuntary context switches (counters start
+//!   at zero after fork), and sends them to the parent after its sum
+//! - a thread reads `clock_gettime(CLOCK_THREAD_CPUTIME_ID)`, the CPU time
+//!   of that thread alone; `getrusage` would count the whole process
+//!
+//! The parent cross-checks the children with `getrusage(RUSAGE_CHILDREN)`,
+//! which the kernel adds up as it reaps them.
+//!
+//! Reading the tables: CPU close to wall means the worker had a core to
+//! itself; wall well above CPU with many involuntary switches means it
+//! waited for one. Wall time of the whole run beyond the slowest worker is
+//! the cost of creating, collecting and waiting for the workers.
+//!
+//! Both calls come from nix, which this lab uses on Linux only. Elsewhere
+//! only the thread and spawned-process tables exist, with wall time and no
+//! CPU time.
+
+#[cfg(target_os = "linux")]
+use nix::sys::resource::{getrusage, Usage, UsageWho};
+#[cfg(target_os = "linux")]
+use nix::sys::time::TimeVal;
+use std::time::Duration;
+
+/// One child process, measured by itself
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChildUsage {
+    pub wall: Duration,
+    pub user: Duration,
+    pub system: Duration,
+    /// Peak resident set size in KiB
+    pub max_rss_kib: u64,
+    /// Gave up the CPU itself (blocked on I/O, a lock, sleep)
+    pub voluntary_switches: u64,
+    /// Taken off the CPU by the scheduler
+    pub involuntary_switches: u64,
+}
+
+#[cfg(target_os = "linux")]
+fn duration(tv: TimeVal) -> Duration {
+    Duration::new(tv.tv_sec() as u64, tv.tv_usec() as u32 * 1000)
+}
+
+/// Counters of `who` so far; `None` if the call fails
+#[cfg(target_os = "linux")]
+pub fn rusage(who: UsageWho) -> Option<Usage> {
+    getrusage(who).ok()
+}
+
+#[cfg(target_os = "linux")]
+impl ChildUsage {
+    /// Size of `to_bytes`: six little-endian u64s
+    pub const BYTES: usize = 6 * 8;
+
+    /// The calling process, `wall` after it was forked
+    pub fn of_self(wall: Duration) -> Self {
+        let Some(usage) = rusage(UsageWho::RUSAGE_SELF) else {
+            return Self {
+                wall,
+                ..Self::default()
+            };
+        };
+        Self {
+            wall,
+            user: duration(usage.user_time()),
+            system: duration(usage.system_time()),
+            max_rss_kib: usage.max_rss() as u64,
+            voluntary_switches: usage.voluntary_context_switches() as u64,
+            involuntary_switches: usage.involuntary_context_switches() as u64,
+        }
+    }
+
+    pub fn to_bytes(self) -> [u8; Self::BYTES] {
+        let fields = [
+            self.wall.as_nanos() as u64,
+            self.user.as_nanos() as u64,
+            self.system.as_nanos() as u64,
+            self.max_rss_kib,
+            self.voluntary_switches,
+            self.involuntary_switches,
+        ];
+        let mut bytes = [0u8; Self::BYTES];
+        for (chunk, field) in bytes.chunks_exact_mut(8).zip(fields) {
+            chunk.copy_from_slice(&field.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8; Self::BYTES]) -> Self {
+        let field = |i: usize| u64::from_le_bytes(bytes[i * 8..i * 8 + 8].try_into().unwrap());
+        Self {
+            wall: Duration::from_nanos(field(0)),
+            user: Duration::from_nanos(field(1)),
+            system: Duration::from_nanos(field(2)),
+            max_rss_kib: field(3),
+            voluntary_switches: field(4),
+            involuntary_switches: field(5),
+        }
+    }
+}
+
+/// One thread, measured by itself
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ThreadUsage {
+    pub wall: Duration,
+    /// User and system CPU of this thread only
+    pub cpu: Duration,
+}
+
+impl ThreadUsage {
+    /// The calling thread, `wall` after it was spawned
+    #[cfg(target_os = "linux")]
+    pub fn of_self(wall: Duration) -> Self {
+        use nix::time::{clock_gettime, ClockId};
+
+        let cpu = clock_gettime(ClockId::CLOCK_THREAD_CPUTIME_ID)
+            .map(Duration::from)
+            .unwrap_or_default();
+        Self { wall, cpu }
+    }
+
+    /// No per-thread CPU clock here: wall time only
+    #[cfg(not(target_os = "linux"))]
+    pub fn of_self(wall: Duration) -> Self {
+        Self {
+            wall,
+            cpu: Duration::ZERO,
+        }
+    }
+}
+
+/// CPU as a share of wall time
+fn busy(cpu: Duration, wall: Duration) -> String {
+    format!(
+        "{:.0}%",
+        100.0 * cpu.as_secs_f64() / wall.as_secs_f64().max(1e-9)
+    )
+}
+
+/// Per-process table, the kernel's own total, and where the rest went,
+/// which is returned: `overhead` names it (how results came back)
+#[cfg(target_os = "linux")]
+pub fn print_children(
+    pids: &[i32],
+    children: &[ChildUsage],
+    reaped: Option<Usage>,
+    total: Duration,
+    overhead: &str,
+) -> Duration {
+    println!(
+        "  {:>8} {:>10} {:>10} {:>10} {:>5} {:>10} {:>6} {:>8}",
+        "pid", "wall", "user", "system", "busy", "max RSS", "vol cs", "invol cs"
+    );
+    for (pid, child) in pids.iter().zip(children) {
+        println!(
+            "  {:>8} {:>10.1?} {:>10.1?} {:>10.1?} {:>5} {:>6} KiB {:>6} {:>8}",
+            pid,
+            child.wall,
+            child.user,
+            child.system,
+            busy(child.user + child.system, child.wall),
+            child.max_rss_kib,
+            child.voluntary_switches,
+            child.involuntary_switches,
+        );
+    }
+    if let Some(reaped) = reaped {
+        println!(
+            "  RUSAGE_CHILDREN: user {:.1?}, system {:.1?}",
+            duration(reaped.user_time()),
+            duration(reaped.system_time())
+        );
+    }
+    let cpu = children.iter().map(|c| c.user + c.system).sum();
+    let slowest = children.iter().map(|c| c.wall).max().unwrap_or_default();
+    summary(cpu, slowest, total, overhead)
+}
+
+/// Per-thread table and where the rest went, which is returned
+pub fn print_threads(threads: &[ThreadUsage], total: Duration) -> Duration {
+    println!(
+        "  {:>8} {:>10} {:>10} {:>5}",
+        "thread", "wall", "cpu", "busy"
+    );
+    for (i, thread) in threads.iter().enumerate() {
+        println!(
+            "  {:>8} {:>10.1?} {:>10.1?} {:>5}",
+            i,
+            thread.wall,
+            thread.cpu,
+            busy(thread.cpu, thread.wall),
+        );
+    }
+    let cpu = threads.iter().map(|t| t.cpu).sum();
+    let slowest = threads.iter().map(|t| t.wall).max().unwrap_or_default();
+    summary(cpu, slowest, total, "spawn + channel")
+}
+
+/// Per-process table for children started with `Command`, which measure
+/// their own main thread, and where the rest went, which is returned
+pub fn print_spawned(children: &[(u32, ThreadUsage)], total: Duration) -> Duration {
+    println!("  {:>8} {:>10} {:>10} {:>5}", "pid", "wall", "cpu", "busy");
+    for (pid, child) in children {
+        println!(
+            "  {:>8} {:>10.1?} {:>10.1?} {:>5}",
+            pid,
+            child.wall,
+            child.cpu,
+            busy(child.cpu, child.wall),
+        );
+    }
+    let cpu = children.iter().map(|(_, c)| c.cpu).sum();
+    let slowest = children
+        .iter()
+        .map(|(_, c)| c.wall)
+        .max()
+        .unwrap_or_default();
+    summary(cpu, slowest, total, "spawn + exec + pipes + wait")
+}
+
+/// Returns the wall time beyond the slowest worker
+fn summary(cpu: Duration, slowest: Duration, total: Duration, overhead: &str) -> Duration {
+    let rest = total.saturating_sub(slowest);
+    println!(
+        "  workers' CPU {:.1?} in {:.1?} wall: {:.1} cores busy; {}: {:.1?}",
+        cpu,
+        total,
+        cpu.as_secs_f64() / total.as_secs_f64().max(1e-9),
+        overhead,
+        rest
+    );
+    rest
+}
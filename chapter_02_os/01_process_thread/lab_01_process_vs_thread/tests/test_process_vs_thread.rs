@@ -78,9 +78,251 @@ fn test_04_both_results_match() {
         return;
     }
 
-    // If both versions ran, they should have the same result
-    if output.contains("Both versions produced correct results!") {
-        // Success message means both versions matched expected
-        assert!(true);
+    // Every version asserts its own result against the expected one, so
+    // the closing message means they all matched
+    assert!(
+        output.contains("All versions produced correct results!"),
+        "{}",
+        output
+    );
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_05_per_worker_cpu_accounting() {
+    let (output, _) = run_program();
+
+    // One row per worker under each version, then the summary
+    let thread_rows = output
+        .lines()
+        .skip_while(|l| !l.starts_with("Multi-Thread version:"))
+        .skip(2)
+        .take_while(|l| !l.contains("workers' CPU"))
+        .count();
+    assert_eq!(thread_rows, 4, "{}", output);
+    let child_rows = output
+        .lines()
+        .skip_while(|l| !l.starts_with("Multi-Process version:"))
+        .skip(2)
+        .take_while(|l| l.contains(" KiB "))
+        .count();
+    assert_eq!(child_rows, 4, "{}", output);
+
+    assert!(output.contains("max RSS") && output.contains("invol cs"));
+    assert!(output.contains("RUSAGE_CHILDREN: user "));
+    assert!(output.contains("cores busy; spawn + channel: "));
+    assert!(output.contains("cores busy; fork + IPC + waitpid: "));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_06_shared_memory_version() {
+    let (output, success) = run_program();
+    assert!(success, "{}", output);
+
+    // Correct result, one row per child, then the overheads side by side
+    let version = output
+        .lines()
+        .find(|l| l.starts_with("Shared-Memory version:"))
+        .unwrap_or_else(|| panic!("no shared-memory version: {}", output));
+    assert!(version.ends_with("result: 5000000050000000"), "{}", version);
+    let child_rows = output
+        .lines()
+        .skip_while(|l| !l.starts_with("Shared-Memory version:"))
+        .skip(2)
+        .take_while(|l| l.contains(" KiB "))
+        .count();
+    assert_eq!(child_rows, 4, "{}", output);
+    assert!(output.contains("cores busy; fork + shm + waitpid: "));
+    for overhead in [
+        "threads + channel:",
+        "processes + UnixStream:",
+        "processes + shared memory:",
+        "processes + spawn/exec:",
+    ] {
+        assert!(output.contains(overhead), "{}", output);
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_07_ipc_benchmark() {
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--release",
+            "--quiet",
+            "--",
+            "ipc-bench",
+            "8",
+            "4096",
+        ])
+        .output()
+        .expect("Failed to execute program");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+
+    // Every mechanism at every size, with a latency and a throughput
+    for mechanism in ["pipe", "UnixStream", "shared memory"] {
+        for size in ["8 B", "4 KiB"] {
+            let row = stdout
+                .lines()
+                .find(|l| l.trim_start().starts_with(mechanism) && l.contains(size))
+                .unwrap_or_else(|| panic!("no {} {} row: {}", mechanism, size, stdout));
+            assert!(row.ends_with("MiB/s"), "{}", row);
+        }
+    }
+    // The sum benchmark doesn't run in this mode
+    assert!(!stdout.contains("Multi-Thread version:"), "{}", stdout);
+
+    let usage = Command::new("cargo")
+        .args(["run", "--release", "--quiet", "--", "ipc-bench", "lots"])
+        .output()
+        .expect("Failed to execute program");
+    assert_eq!(usage.status.code(), Some(2));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_08_process_pool() {
+    let output = Command::new("cargo")
+        .args(["run", "--release", "--quiet", "--", "pool", "16"])
+        .output()
+        .expect("Failed to execute program");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+
+    // Both ways get the sum right; the pool forks only its 4 workers
+    for version in ["fork per item:", "ProcessPool:"] {
+        let row = stdout
+            .lines()
+            .find(|l| l.trim_start().starts_with(version))
+            .unwrap_or_else(|| panic!("no {} row: {}", version, stdout));
+        assert!(row.ends_with("result: 5000000050000000"), "{}", row);
+    }
+    assert!(stdout.contains("(16 forks)") && stdout.contains("(4 forks)"));
+
+    // Every item is served by one of the 4 workers
+    let served = stdout
+        .lines()
+        .find_map(|l| l.trim_start().strip_prefix("items per worker pid: "))
+        .unwrap_or_else(|| panic!("no distribution: {}", stdout));
+    let counts: Vec<usize> = served
+        .split(", ")
+        .map(|entry| entry.split(": ").nth(1).unwrap().parse().unwrap())
+        .collect();
+    assert_eq!(counts.len(), 4, "{}", served);
+    assert_eq!(counts.iter().sum::<usize>(), 16, "{}", served);
+
+    // A crashed worker fails its own job only
+    assert!(
+        stdout.contains("crash test: empty item failed"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("after the crash: 8 of 8 more items done"));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_09_scaling_sweep() {
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--release",
+            "--quiet",
+            "--",
+            "sweep",
+            "--n",
+            "1000000",
+            "--workers",
+            "3",
+            "--pin",
+        ])
+        .output()
+        .expect("Failed to execute program");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("workers pinned: yes"), "{}", stdout);
+
+    // One row per worker count, each with both speedups; 1 worker is the
+    // baseline
+    let rows: Vec<&str> = stdout
+        .lines()
+        .skip_while(|l| !l.trim_start().starts_with("workers"))
+        .skip(1)
+        .take_while(|l| l.contains('x'))
+        .collect();
+    assert_eq!(rows.len(), 3, "{}", stdout);
+    for (workers, row) in (1..).zip(&rows) {
+        let fields: Vec<&str> = row.split_whitespace().collect();
+        assert_eq!(fields[0], workers.to_string(), "{}", row);
+        assert_eq!(fields.iter().filter(|f| f.ends_with('x')).count(), 2);
+    }
+    assert!(rows[0].contains("1.00x"), "{}", rows[0]);
+    assert!(stdout.contains("Amdahl:"), "{}", stdout);
+
+    // The same flags drive the one-shot comparison
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--release",
+            "--quiet",
+            "--",
+            "--workers",
+            "2",
+            "--pin",
+        ])
+        .output()
+        .expect("Failed to execute program");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.starts_with("N = 100000000, Workers = 2"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("pinned round robin to CPUs"), "{}", stdout);
+    assert!(stdout.contains("All versions produced correct results!"));
+}
+
+#[test]
+fn test_10_spawned_process_version() {
+    let (output, success) = run_program();
+    assert!(success, "{}", output);
+
+    // Re-executed children, no fork: same result, one row per child
+    let version = output
+        .lines()
+        .find(|l| l.starts_with("Spawned-Process version:"))
+        .unwrap_or_else(|| panic!("no spawned-process version: {}", output));
+    assert!(version.ends_with("result: 5000000050000000"), "{}", version);
+    let child_rows = output
+        .lines()
+        .skip_while(|l| !l.starts_with("Spawned-Process version:"))
+        .skip(2)
+        .take_while(|l| !l.contains("workers' CPU"))
+        .count();
+    assert_eq!(child_rows, 4, "{}", output);
+    assert!(
+        output.contains("spawn + exec + pipes + wait: "),
+        "{}",
+        output
+    );
+
+    // The worker mode itself: a range on stdin, "SUM WALL_NS CPU_NS" out
+    let mut worker = Command::new("cargo")
+        .args(["run", "--release", "--quiet", "--", "worker"])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .expect("Failed to execute program");
+    {
+        use std::io::Write;
+        let mut stdin = worker.stdin.take().unwrap();
+        writeln!(stdin, "1 100 1 -").unwrap();
     }
+    let output = worker.wait_with_output().expect("worker failed");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.split_whitespace().next(), Some("5050"), "{}", stdout);
 }
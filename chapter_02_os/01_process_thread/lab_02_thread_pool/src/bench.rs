@@ -0,0 +1,120 @@
+//! `steal-bench [JOBS]`: the shared-queue `ThreadPool` against the
+//! work-stealing `StealingPool`, on the same jobs.
+//!
+//! Job sizes are uneven: every 16th job spins 50 times longer than the
+//! others, so a worker that takes one falls behind and the rest must
+//! pick up its share. Two workloads:
+//! - outside: the main thread submits every job
+//! - nested: one job submits them all from inside the pool. The shared
+//!   queue doesn't care where a submit comes from; with stealing, every
+//!   job lands on the submitting worker's deque and the others steal
+//!
+//! Each job reports on a channel when it is done, and the clock stops
+//! when all have.
+
+use crate::stealing::{StealingPool, StealingSpawner};
+use crate::thread_pool::{Spawner, ThreadPool};
+use std::hint::black_box;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+/// Spin iterations of a short job; long ones do `LONG_FACTOR` times more
+const SHORT_WORK: u64 = 2_000;
+const LONG_FACTOR: u64 = 50;
+const LONG_EVERY: usize = 16;
+
+/// CPU-bound work that the optimizer can't remove
+fn spin(iterations: u64) -> u64 {
+    (0..iterations).fold(0u64, |acc, i| {
+        black_box(acc.wrapping_mul(31).wrapping_add(i))
+    })
+}
+
+fn work_for(job: usize) -> u64 {
+    if job.is_multiple_of(LONG_EVERY) {
+        SHORT_WORK * LONG_FACTOR
+    } else {
+        SHORT_WORK
+    }
+}
+
+/// What the workloads need from either pool
+trait Submit: Clone + Send + 'static {
+    fn submit(&self, job: impl FnOnce() + Send + 'static);
+}
+
+impl Submit for Spawner {
+    fn submit(&self, job: impl FnOnce() + Send + 'static) {
+        self.execute(job);
+    }
+}
+
+impl Submit for StealingSpawner {
+    fn submit(&self, job: impl FnOnce() + Send + 'static) {
+        self.execute(job);
+    }
+}
+
+/// Run `jobs` jobs and wait for all of them
+fn workload(spawner: impl Submit, jobs: usize, nested: bool) -> Duration {
+    let (done_sender, done) = mpsc::channel();
+    let started = Instant::now();
+    let submit_all = {
+        let spawner = spawner.clone();
+        move || {
+            for job in 0..jobs {
+                let done = done_sender.clone();
+                spawner.submit(move || {
+                    let _ = done.send(spin(work_for(job)));
+                });
+            }
+        }
+    };
+    if nested {
+        spawner.submit(submit_all);
+    } else {
+        submit_all();
+    }
+    for _ in 0..jobs {
+        done.recv().expect("a job finished");
+    }
+    started.elapsed()
+}
+
+fn report(scheduler: &str, workload: &str, workers: usize, jobs: usize, elapsed: Duration) {
+    print!(
+        "scheduler={:<8} workload={:<7} workers={} jobs={} elapsed_ms={:.1} jobs_per_sec={:.0}",
+        scheduler,
+        workload,
+        workers,
+        jobs,
+        elapsed.as_secs_f64() * 1000.0,
+        jobs as f64 / elapsed.as_secs_f64()
+    );
+}
+
+/// Both workloads on both pools, one line each
+pub fn run(jobs: usize, workers: usize) {
+    println!(
+        "Steal benchmark: {} jobs on {} workers, every {}th {}x longer",
+        jobs, workers, LONG_EVERY, LONG_FACTOR
+    );
+    for (name, nested) in [("outside", false), ("nested", true)] {
+        // Room for every job: the comparison is about scheduling, not
+        // backpressure
+        let pool = ThreadPool::builder()
+            .workers(workers)
+            .queue_capacity(jobs)
+            .log(false)
+            .build();
+        let elapsed = workload(pool.spawner(), jobs, nested);
+        report("shared", name, workers, jobs, elapsed);
+        println!();
+        drop(pool);
+
+        let pool = StealingPool::new(workers);
+        let elapsed = workload(pool.spawner(), jobs, nested);
+        report("stealing", name, workers, jobs, elapsed);
+        println!(" steals={}", pool.steals());
+    }
+}
@@ -43,128 +43,681 @@
 //! - [ ] Graceful shutdown (no panics, all tasks complete)
 //!
 //! Check solution/main.rs after completing
+//!
+//! ## Extension: Jobs Waiting for Jobs
+//! - A job that submits another job and waits for its result holds its
+//!   worker while it waits. With every worker doing that, the jobs they
+//!   wait for sit in the queue with nobody left to run them: deadlock
+//! - `execute_with_result` returns a `Task`; `Task::join` notices when it
+//!   is called on a worker of the same pool and, instead of blocking, runs
+//!   queued jobs inline until its result is there (the job it waits for
+//!   is one of them). `spawner()` gives jobs a handle to submit with
+//! - Waiting on a plain channel (`Task::into_receiver`) can't be noticed:
+//!   that is the deadlock, reproduced by `cargo run -- nested --naive`
+//! - `--log` keeps the workers' log lines, the waiting ones included
+//! ```text
+//! $ cargo run -- nested --log
+//! Worker 0 waits on its own pool: running queued jobs meanwhile
+//! Worker 1 waits on its own pool: running queued jobs meanwhile
+//! Nested results: [1, 11]
+//! $ cargo run -- nested --naive
+//! Deadlock: both workers wait for jobs queued behind them
+//! ```
+//!
+//! ## Extension: Awaiting a Task
+//! - `Task` is also a `Future`: `pool.execute_with_result(job).await`
+//!   gives the job's return value without blocking the awaiting thread.
+//!   Each job has its own one-shot channel for the result and a slot for
+//!   the waker of whoever polled last; the job wakes it when it finishes
+//! - std has no executor, so `block_on` is a small one: it parks the
+//!   thread until a waker unparks it
+//! ```text
+//! $ cargo run -- await
+//! Awaiting 4 tasks from a future
+//! Sum of squares: 30 (3 polls, the rest of the time parked)
+//! Awaiting a job that panicked: job panicked: boom
+//! ```
+//!
+//! ## Extension: Surviving Panicking Jobs
+//! - Unchecked, a panicking job unwinds through its worker's loop and the
+//!   thread is gone: the pool shrinks by one each time, down to nothing
+//! - Workers run each job under `catch_unwind`, log the panic, and hand
+//!   the worker to a fresh thread (thread-locals the job left half-updated
+//!   go with the old one). The pool keeps its configured size
+//! - `execute_with_result` catches the panic first and sends it through
+//!   the result channel: `try_join()` and `.await` give
+//!   `Err(JobPanicked(message))`, like Tokio's `JoinError`; `join()`
+//!   panics with the message
+//! ```text
+//! $ cargo run -- panics
+//! Worker 1 caught a panicking job (job 1 failed): replacing its thread
+//! Job 1: job panicked: job 1 failed
+//! Live workers: 2 of 2
+//! ```
+//!
+//! ## Extension: Bounded Queue and Backpressure
+//! - An unbounded channel accepts jobs faster than workers run them for
+//!   as long as memory lasts. The queue is now a `VecDeque` behind a
+//!   mutex with two condvars, holding at most `queue_capacity` jobs
+//! - `ThreadPool::builder()` sets the worker count, the capacity (default
+//!   1024) and what a submit does when the queue is full:
+//!   `QueuePolicy::Block` waits for room, `Reject` fails with
+//!   `SubmitError::QueueFull`, `DropOldest` throws away the job that
+//!   waited longest (its `Task` says it was dropped)
+//! - `try_execute` and `try_execute_with_result` return the error;
+//!   `execute` panics on it. `queue_stats()` gives the depth, its peak
+//!   and how many jobs were submitted, rejected and dropped
+//! - A job that submits to its own pool under `Block` holds its worker
+//!   while it waits for room: with every worker doing that, nobody makes
+//!   room. Prefer `Reject` there
+//! ```text
+//! $ cargo run -- queue
+//! Policy Reject: 1 worker, room for 2 queued jobs, 6 jobs
+//!   job 3: job queue is full
+//!   ...
+//!   ran: [0, 1, 2]
+//!   queue: depth=0 peak=2 capacity=2 submitted=3 rejected=3 dropped=0
+//! ```
+//!
+//! ## Extension: Resizing at Runtime
+//! - `resize(n)` grows the pool by starting workers and shrinks it by
+//!   queueing one poison pill (`Message::Stop`) per worker too many. A
+//!   worker that takes a pill exits; the others keep going
+//! - Pills queue behind the jobs already there, and a worker only takes
+//!   one between jobs: whatever is running or queued when the pool shrinks
+//!   still runs. They don't count against the queue's capacity, and
+//!   `DropOldest` never throws one away
+//! - Growing again first takes back pills nobody has taken yet. Workers
+//!   that stopped are joined by the next `resize` or by `Drop`
+//! ```text
+//! $ cargo run -- resize
+//! Resizing 4 -> 1 with 4 jobs running and 4 queued
+//! Worker 2 took a poison pill: stopping
+//! Jobs finished: 8 of 8
+//! Live workers: 1
+//! ```
+//!
+//! ## Extension: Job Priorities
+//! - The queue keeps one FIFO lane per `Priority` (High, Normal, Low);
+//!   a free worker takes the oldest job of the most urgent non-empty lane,
+//!   so a latency-sensitive job jumps the line instead of waiting behind
+//!   a backlog of batch work. Within a lane, order is first come, first
+//!   served
+//! - `pool.with_priority(Priority::High)` is a `Spawner` that submits at
+//!   that priority; everything else submits at `Normal`. Capacity counts
+//!   all lanes together, and `DropOldest` throws away the oldest job of
+//!   the least urgent lane
+//! - Nothing ages: while High jobs keep coming, Low ones wait forever.
+//!   Poison pills go in the Low lane, behind all queued work
+//! ```text
+//! $ cargo run -- priority
+//! Submitted: [low-1, normal-2, high-3, low-4, normal-5, high-6, ...]
+//! Ran:       [high-3, high-6, high-9, normal-2, normal-5, normal-8, low-1, low-4, low-7]
+//! ```
+//!
+//! ## Extension: Shutdown Modes
+//! - `shutdown(timeout)` closes the queue, so every submit from then on
+//!   fails with `SubmitError::ShutDown` (a `Block`ed one too), and gives
+//!   the workers up to `timeout` to take the queued jobs. Whatever is
+//!   still queued after that is discarded; the count is returned
+//! - `shutdown_now()` discards the queue right away
+//! - Both then join the workers. A running job can't be interrupted (Rust
+//!   threads have no cancellation), so they wait for those. Discarded
+//!   jobs count as dropped in `queue_stats()`, and their `Task`s say so
+//! - Dropping the pool without either is `shutdown` with no time limit:
+//!   every queued job runs, as before
+//! ```text
+//! $ cargo run -- shutdown
+//! Graceful shutdown (250ms) with 10 jobs of 100ms on 2 workers
+//!   discarded 4 queued job(s)
+//!   finished: [0, 1, 2, 3, 4, 5]
+//! Submit after shutdown: pool is shut down
+//! ```
+//!
+//! ## Extension: Work Stealing
+//! - Every worker above locks the same queue for every job. `StealingPool`
+//!   (`src/stealing.rs`) gives each worker its own deque (crossbeam-deque)
+//!   instead: a job submitted from inside a job goes on its worker's
+//!   deque, popped newest first, and jobs from outside go in a shared
+//!   injector. An idle worker takes a batch from the injector or steals
+//!   the oldest job from another worker's deque
+//! - `steal-bench [JOBS]` (`src/bench.rs`) runs the same uneven jobs, one
+//!   in 16 fifty times longer than the rest, on both pools: submitted from
+//!   `outside`, and `nested` (one job submits them all, so with stealing
+//!   they land on one deque and the other workers must steal them)
+//! - Stealing pays off when workers would otherwise queue up on one lock:
+//!   many cores, short jobs. On a single CPU both pools take the same time
+//!   (nested still shows thousands of steals)
+//! - `ThreadPoolBuilder::log(false)` silences the workers' log lines; one
+//!   per job would otherwise serialize the benchmark on stdout
+//! ```bash
+//! cargo run --release -- steal-bench 20000
+//! ```
+//!
+//! ## Extension: Instrumentation
+//! - Each worker counts the jobs it ran, how many panicked, and the time
+//!   spent inside them. `stats()` takes a snapshot of those counters with
+//!   the queue's metrics: the `PoolStats` says how busy each worker was
+//!   over its lifetime, and how far the queue backed up
+//! - Busy time is measured around the whole job: a job that waits in
+//!   `join` is busy while it waits, and the jobs it runs inline meanwhile
+//!   count as part of it
+//! - Low utilization with a deep queue peak means bursts: more workers
+//!   would have cut the wait. High utilization with an empty queue means
+//!   the pool is just keeping up
+//! ```text
+//! $ cargo run
+//! Pool stats after 802.31ms:
+//!   worker 0: 2 jobs, 0 panicked, busy 200.2ms (25%)
+//!   ...
+//!   queue: depth=0 peak=8 capacity=1024 submitted=9 rejected=0 dropped=0
+//! ```
+//!
+//! ## Extension: Scoped Jobs
+//! - `execute` needs `'static` jobs: the pool may run them after the
+//!   caller's stack frame is gone, so borrowed data means `Arc` and clones
+//! - `pool.scope(|s| { s.spawn(...); })` (`src/scope.rs`), like
+//!   `std::thread::scope`, returns only once every job spawned on `s` is
+//!   over, so those jobs may borrow locals, mutably too. The borrow
+//!   checker still stops two jobs from sharing a `&mut`
+//! - A panic in a scoped job reaches the caller when the scope ends. A
+//!   scope opened inside a job runs queued jobs while it waits, as
+//!   `Task::join` does, so it works on a pool with one worker
+//! ```text
+//! $ cargo run -- scope
+//! Summing 1..=1000 in 4 chunks borrowed from the stack
+//! Chunk sums: [31375, 93875, 156375, 218875], total 500500
+//! Scope inside the only worker's job: [2, 4, 6]
+//! Scope with a panicking job: 1 scoped job(s) panicked
+//! Worker 0 caught a panicking job (bad chunk): replacing its thread
+//! ```
+//!
+//! ## Extension: Named Workers and CPU Pinning
+//! - Worker threads are named `pool-worker-N` (`thread::Builder`; change
+//!   the prefix with `ThreadPoolBuilder::thread_name`). The name shows in
+//!   panic messages, in `htop` (F2 > Display options > Show custom thread
+//!   names), in `ps -L -o tid,comm,psr -p PID`, and in `perf top --sort comm`
+//! - `pin_cores(true)` restricts worker N to one CPU of those the process
+//!   may use (sched_setaffinity, via nix; Linux only), round robin. The
+//!   scheduler then can't migrate it: the `psr` column stops changing,
+//!   and two pinned workers on one CPU share it even when another is idle
+//! - `cargo run -- affinity [--pin]` keeps every worker busy spinning and
+//!   reports the thread each job ran on, and the CPUs it started and
+//!   ended on
+//! ```text
+//! $ cargo run -- affinity --pin
+//! Allowed CPUs: [0, 1, 2, 3]
+//! pool-worker-0 pinned to CPU 0
+//! job 0: pool-worker-0 on CPU 0 -> 0
+//! Jobs that changed CPU: 0 of 8
+//! ```
 
+mod bench;
+mod stealing;
+
+// The pool itself, in a file of its own: mini_xargs and the blocking echo
+// server include it too. Not every method has a demo here
+#[allow(dead_code)]
+mod thread_pool;
+
+use std::panic::{self, AssertUnwindSafe};
 use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
+use thread_pool::{affinity, block_on, panic_message, Priority, QueuePolicy, Task, ThreadPool};
 
 // ============================================================
-// TODO: Implement ThreadPool and Worker
+// Demo (no modification needed)
 // ============================================================
 
-/// A job is a boxed closure that can be sent across threads
-type Job = Box<dyn FnOnce() + Send + 'static>;
+/// Two workers, two jobs that each wait for a job of their own. `naive`
+/// waits on the bare channel and deadlocks; `join` doesn't
+fn nested_demo(naive: bool, log: bool) {
+    let pool = if log {
+        ThreadPool::new(2)
+    } else {
+        ThreadPool::quiet(2)
+    };
+    let spawner = pool.spawner();
+    let outer: Vec<Task<u64>> = (0..2)
+        .map(|i| {
+            let spawner = spawner.clone();
+            pool.execute_with_result(move || {
+                // Let both outer jobs take a worker before the inner ones
+                // are queued
+                thread::sleep(Duration::from_millis(100));
+                let inner = spawner.execute_with_result(move || i * 10);
+                let value = if naive {
+                    inner
+                        .into_receiver()
+                        .recv()
+                        .expect("inner job")
+                        .expect("inner job panicked")
+                } else {
+                    inner.join()
+                };
+                value + 1
+            })
+        })
+        .collect();
+    drop(spawner);
 
-/// Thread pool that manages a fixed number of worker threads
-pub struct ThreadPool {
-    // TODO: Add fields
-    workers: Vec<Worker>,
-    sender: Option<mpsc::Sender<Job>>,
+    let mut results = Vec::new();
+    for task in outer {
+        if naive {
+            // The main thread isn't stuck, so it can notice
+            match task.into_receiver().recv_timeout(Duration::from_secs(2)) {
+                Ok(value) => results.push(value.expect("outer job panicked")),
+                Err(_) => {
+                    println!("Deadlock: both workers wait for jobs queued behind them");
+                    // Dropping the pool would wait for them forever
+                    std::process::exit(1);
+                }
+            }
+        } else {
+            results.push(task.join());
+        }
+    }
+    println!("Nested results: {:?}", results);
 }
 
-/// A worker that runs in its own thread
-struct Worker {
-    id: usize,
-    thread: Option<thread::JoinHandle<()>>,
+/// Await several tasks from one future: the main thread sleeps in
+/// `block_on` instead of blocking in a `join` per task
+fn await_demo() {
+    let pool = ThreadPool::new(2);
+    let tasks: Vec<Task<u64>> = (1..=4)
+        .map(|i| {
+            pool.execute_with_result(move || {
+                thread::sleep(Duration::from_millis(50));
+                i * i
+            })
+        })
+        .collect();
+    println!("Awaiting {} tasks from a future", tasks.len());
+    let (sum, polls) = block_on(async {
+        let mut sum = 0;
+        for task in tasks {
+            sum += task.await.expect("squares don't panic");
+        }
+        sum
+    });
+    println!(
+        "Sum of squares: {} ({} polls, the rest of the time parked)",
+        sum, polls
+    );
+
+    // A panicking job still sends its outcome and wakes its awaiter
+    let failing = pool.execute_with_result(|| -> u64 { panic!("boom") });
+    if let (Err(err), _) = block_on(failing) {
+        println!("Awaiting a job that panicked: {}", err);
+    }
 }
 
-impl ThreadPool {
-    /// Create a new ThreadPool with `size` workers
-    ///
-    /// # Panics
-    /// Panics if size is 0
-    pub fn new(size: usize) -> ThreadPool {
-        assert!(size > 0, "Thread pool size must be > 0");
+/// Jobs that panic in a pool of two: each failure reaches its Task, and
+/// both workers are still there to run the rest
+fn panics_demo() {
+    let pool = ThreadPool::new(2);
+    let tasks: Vec<Task<u64>> = (0..6)
+        .map(|i| {
+            pool.execute_with_result(move || {
+                if i % 3 == 1 {
+                    panic!("job {} failed", i);
+                }
+                thread::sleep(Duration::from_millis(20));
+                i * i
+            })
+        })
+        .collect();
+    for (i, task) in tasks.into_iter().enumerate() {
+        match task.try_join() {
+            Ok(value) => println!("Job {}: Ok({})", i, value),
+            Err(err) => println!("Job {}: {}", i, err),
+        }
+    }
+    println!("Live workers: {} of 2", pool.live_workers());
 
-        let (sender, receiver) = mpsc::channel();
-        let shared_receiver = Arc::new(Mutex::new(receiver));
+    // Plain `execute` jobs have nobody to tell: the worker's log is all
+    pool.execute(|| panic!("fire and forget"));
+    let after = pool.execute_with_result(|| "still serving");
+    println!("After another panic: {}", after.join());
+    thread::sleep(Duration::from_millis(50));
+    println!("Live workers: {} of 2", pool.live_workers());
+}
 
-        let mut workers = Vec::with_capacity(size);
+/// One busy worker, room for two jobs, six jobs: what each policy does
+/// with the three that don't fit
+fn queue_demo() {
+    for policy in [
+        QueuePolicy::Block,
+        QueuePolicy::Reject,
+        QueuePolicy::DropOldest,
+    ] {
+        println!(
+            "Policy {:?}: 1 worker, room for 2 queued jobs, 6 jobs",
+            policy
+        );
+        let pool = ThreadPool::builder()
+            .workers(1)
+            .queue_capacity(2)
+            .policy(policy)
+            .build();
 
-        for id in 0..size {
-            workers.push(Worker::new(id, Arc::clone(&shared_receiver)));
+        // Job 0 holds the only worker until released, so the rest queue up
+        let (started_sender, started) = mpsc::channel();
+        let (release, released) = mpsc::channel();
+        let mut tasks = vec![(
+            0,
+            pool.execute_with_result(move || {
+                let _ = started_sender.send(());
+                let _ = released.recv();
+                0
+            }),
+        )];
+        started.recv().expect("job 0 started");
+        if policy == QueuePolicy::Block {
+            // Job 3 blocks the main thread: someone else has to release
+            let release = release.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(100));
+                let _ = release.send(());
+            });
         }
 
-        ThreadPool {
-            workers,
-            sender: Some(sender),
+        for i in 1..6 {
+            let submitted = Instant::now();
+            match pool.try_execute_with_result(move || i) {
+                Ok(task) => tasks.push((i, task)),
+                Err(err) => println!("  job {}: {}", i, err),
+            }
+            if submitted.elapsed() >= Duration::from_millis(50) {
+                println!("  job {}: waited for the worker to make room", i);
+            }
         }
-    }
+        let _ = release.send(());
 
-    /// Execute a closure on a worker thread
-    pub fn execute<F>(&self, job: F)
-    where
-        F: FnOnce() + Send + 'static,
-    {
-        let job = Box::new(job);
-        if let Some(sender) = &self.sender {
-            sender.send(job).expect("Failed to send job to worker");
+        let mut ran = Vec::new();
+        for (i, task) in tasks {
+            match task.try_join() {
+                Ok(value) => ran.push(value),
+                Err(_) => println!("  job {}: dropped from the queue, never ran", i),
+            }
         }
+        println!("  ran: {:?}", ran);
+        println!("  queue: {}", pool.queue_stats());
     }
+}
 
-    pub fn execute_with_result<F, R>(&self, job: F) -> mpsc::Receiver<R>
-    where
-        F: FnOnce() -> R + Send + 'static,
-        R: Send + 'static,
-    {
-        let (result_sender, result_receiver) = mpsc::channel();
-        self.execute(move || {
-            let result = job();
-            let _ = result_sender.send(result);
-        });
-        result_receiver
+/// Ten 100ms jobs on two workers, shut down after 250ms: the six taken by
+/// then finish, the four still queued are discarded. Then the same, at once
+fn shutdown_demo() {
+    let job = |i: u64| {
+        move || {
+            thread::sleep(Duration::from_millis(100));
+            i
+        }
+    };
+    let report = |tasks: Vec<Task<u64>>| {
+        let (mut finished, mut discarded) = (Vec::new(), Vec::new());
+        for (i, task) in tasks.into_iter().enumerate() {
+            match task.try_join() {
+                Ok(value) => finished.push(value),
+                Err(_) => discarded.push(i),
+            }
+        }
+        println!("  finished: {:?}", finished);
+        println!("  never ran: {:?}", discarded);
+    };
+
+    let mut pool = ThreadPool::new(2);
+    let tasks: Vec<Task<u64>> = (0..10).map(|i| pool.execute_with_result(job(i))).collect();
+    println!("Graceful shutdown (250ms) with 10 jobs of 100ms on 2 workers");
+    let discarded = pool.shutdown(Duration::from_millis(250));
+    println!("  discarded {} queued job(s)", discarded);
+    report(tasks);
+    match pool.try_execute(|| {}) {
+        Ok(()) => println!("Submit after shutdown: accepted"),
+        Err(err) => println!("Submit after shutdown: {}", err),
     }
+    println!("  queue: {}", pool.queue_stats());
+
+    let mut pool = ThreadPool::new(2);
+    let (started_sender, started) = mpsc::channel();
+    let tasks: Vec<Task<u64>> = (0..6)
+        .map(|i| {
+            let started = started_sender.clone();
+            pool.execute_with_result(move || {
+                let _ = started.send(());
+                job(i)()
+            })
+        })
+        .collect();
+    started.recv().expect("a job started");
+    started.recv().expect("a job started");
+    println!("Immediate shutdown with 2 jobs running and 4 queued");
+    let discarded = pool.shutdown_now();
+    println!("  discarded {} queued job(s)", discarded);
+    report(tasks);
 }
 
-impl Drop for ThreadPool {
-    fn drop(&mut self) {
-        println!("ThreadPool shutting down");
-        self.sender.take(); // We call take() to explicitly drop the Sender. Dropping it closes the channel, so each worker’s recv() returns Err and the worker can exit.
+/// Jobs that borrow a local slice and write into a local Vec, then a
+/// scope inside a job on a pool with a single worker
+fn scope_demo() {
+    let pool = ThreadPool::builder().workers(4).log(false).build();
+    let data: Vec<u64> = (1..=1000).collect();
+    let mut sums = vec![0u64; 4];
+    println!("Summing 1..=1000 in 4 chunks borrowed from the stack");
+    pool.scope(|s| {
+        for (chunk, sum) in data.chunks(250).zip(sums.iter_mut()) {
+            s.spawn(move || *sum = chunk.iter().sum());
+        }
+    });
+    // No Arc, no clone: the scope is over, the borrows with it
+    println!("Chunk sums: {:?}, total {}", sums, sums.iter().sum::<u64>());
 
-        for worker in &mut self.workers {
-            if let Some(thread) = worker.thread.take() {
-                // removes the handle, leaving None, so we own the handle and can join it., only join if the worker still has a thread.
-                thread.join().expect("Failed to join worker thread"); // waits for that worker thread to finish.
+    let pool = ThreadPool::builder().workers(1).log(false).build();
+    let spawner = pool.spawner();
+    let doubled = pool.execute_with_result(move || {
+        let mut values = vec![1, 2, 3];
+        // The only worker is running this job: it runs the scoped jobs
+        // itself while it waits for them
+        spawner.scope(|s| {
+            for value in values.iter_mut() {
+                s.spawn(move || *value *= 2);
             }
-        }
+        });
+        values
+    });
+    println!("Scope inside the only worker's job: {:?}", doubled.join());
+
+    // A scoped job's panic reaches whoever opened the scope
+    panic::set_hook(Box::new(|_| {}));
+    let caught = panic::catch_unwind(AssertUnwindSafe(|| {
+        pool.scope(|s| s.spawn(|| panic!("bad chunk")));
+    }));
+    let _ = panic::take_hook();
+    if let Err(payload) = caught {
+        println!("Scope with a panicking job: {}", panic_message(&*payload));
     }
 }
 
-impl Worker {
-    /// Create a new worker that listens for jobs on the receiver
-    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Job>>>) -> Worker {
-        // TODO: Implement
-        // 1. Spawn a thread
-        let thread = thread::spawn(move || {
-            println!("Worker {} started", id);
-            loop {
-                let message = receiver.lock().expect("Failed to lock receiver").recv();
-                //recv() returns exactly one message each time it’s called.
-                match message {
-                    Ok(job) => {
-                        println!("Worker {} got a job; executing", id);
-                        job(); // Wait for a job (blocking)
-                    }
-                    Err(_) => {
-                        println!("Worker {} shutting down", id);
-                        break; // If channel is closed, break
-                    }
+/// Eight CPU-bound jobs on four workers, each reporting the thread it ran
+/// on and the CPUs it started and ended on. Unpinned, the scheduler may
+/// move a job between CPUs; pinned, it can't
+fn affinity_demo(pin: bool) {
+    match affinity::allowed_cpus() {
+        Ok(cpus) => println!("Allowed CPUs: {:?}", cpus),
+        Err(err) => println!("Allowed CPUs: unknown ({})", err),
+    }
+    let pool = ThreadPool::builder().workers(4).pin_cores(pin).build();
+    let tasks: Vec<_> = (0..8)
+        .map(|_| {
+            pool.execute_with_result(|| {
+                let name = thread::current().name().unwrap_or("unnamed").to_string();
+                let first = affinity::current_cpu();
+                let mut moved = false;
+                // Busy, not sleeping: a sleeping thread gives its CPU up
+                let started = Instant::now();
+                while started.elapsed() < Duration::from_millis(200) {
+                    moved |= affinity::current_cpu() != first;
+                    std::hint::spin_loop();
                 }
-            }
-        });
-        //: move || ...: a closure that takes ownership of captured variables (so it can run safely in the new thread)
-        //: loop {}`: an infinite loop inside that thread (here, empty)
-        Worker {
-            id,
-            thread: Some(thread),
+                (name, first, affinity::current_cpu(), moved)
+            })
+        })
+        .collect();
+
+    let cpu = |cpu: Option<usize>| cpu.map_or("?".to_string(), |cpu| cpu.to_string());
+    let mut changed = 0;
+    for (job, task) in tasks.into_iter().enumerate() {
+        let (name, first, last, moved) = task.join();
+        println!(
+            "job {}: {} on CPU {} -> {}",
+            job,
+            name,
+            cpu(first),
+            cpu(last)
+        );
+        changed += usize::from(moved);
+    }
+    println!("Jobs that changed CPU: {} of 8", changed);
+}
+
+/// One worker, held busy while nine jobs of mixed priority queue up: they
+/// run by priority, and in submission order within a priority
+fn priority_demo() {
+    let pool = ThreadPool::new(1);
+    let (started_sender, started) = mpsc::channel();
+    let (release, released) = mpsc::channel::<()>();
+    pool.execute(move || {
+        let _ = started_sender.send(());
+        let _ = released.recv();
+    });
+    started.recv().expect("the blocking job started");
+
+    let ran = Arc::new(Mutex::new(Vec::new()));
+    let mut submitted = Vec::new();
+    let priorities = [Priority::Low, Priority::Normal, Priority::High];
+    let spawners = priorities.map(|priority| pool.with_priority(priority));
+    for i in 1..=9 {
+        let label = format!("{}-{}", priorities[(i - 1) % 3], i);
+        submitted.push(label.clone());
+        let ran = Arc::clone(&ran);
+        spawners[(i - 1) % 3].execute(move || ran.lock().unwrap().push(label));
+    }
+    drop(spawners);
+    println!("Submitted: [{}]", submitted.join(", "));
+
+    let _ = release.send(());
+    drop(pool);
+    println!("Ran:       [{}]", ran.lock().unwrap().join(", "));
+}
+
+/// Shrink a busy pool of 4 to 1, then grow it to 3: every job submitted
+/// before, during and after runs
+fn resize_demo() {
+    let mut pool = ThreadPool::new(4);
+    let (started_sender, started) = mpsc::channel();
+    let job = move |i: u64| {
+        let started = started_sender.clone();
+        move || {
+            let _ = started.send(());
+            thread::sleep(Duration::from_millis(100));
+            i
         }
+    };
+    let mut tasks: Vec<Task<u64>> = (0..8).map(|i| pool.execute_with_result(job(i))).collect();
+    // Four jobs hold the four workers; four wait in the queue
+    for _ in 0..4 {
+        started.recv().expect("a job started");
     }
+    println!(
+        "Resizing {} -> 1 with 4 jobs running and {} queued",
+        pool.size(),
+        pool.queue_stats().depth
+    );
+    pool.resize(1);
+
+    let results: Vec<u64> = tasks.drain(..).map(Task::join).collect();
+    println!("Jobs finished: {} of 8 {:?}", results.len(), results);
+    wait_for_workers(&pool, 1);
+    println!("Live workers: {}", pool.live_workers());
+
+    println!("Resizing 1 -> 3");
+    pool.resize(3);
+    let results: Vec<u64> = (8..14)
+        .map(|i| pool.execute_with_result(job(i)))
+        .collect::<Vec<_>>()
+        .into_iter()
+        .map(Task::join)
+        .collect();
+    println!("Jobs finished: {} of 6 {:?}", results.len(), results);
+    println!("Live workers: {}", pool.live_workers());
 }
 
-// ============================================================
-// Demo (no modification needed)
-// ============================================================
+/// Stopping workers exit once they have taken their pill: give them a moment
+fn wait_for_workers(pool: &ThreadPool, expected: usize) {
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while pool.live_workers() != expected && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+}
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("nested") {
+        nested_demo(
+            args.iter().any(|arg| arg == "--naive"),
+            args.iter().any(|arg| arg == "--log"),
+        );
+        return;
+    }
+    if args.first().map(String::as_str) == Some("await") {
+        await_demo();
+        return;
+    }
+    if args.first().map(String::as_str) == Some("scope") {
+        scope_demo();
+        return;
+    }
+    if args.first().map(String::as_str) == Some("steal-bench") {
+        let jobs = match args.get(1).map(|jobs| jobs.parse()) {
+            None => 4000,
+            Some(Ok(jobs)) if jobs > 0 => jobs,
+            Some(_) => {
+                eprintln!("Usage: thread_pool steal-bench [JOBS]");
+                std::process::exit(2);
+            }
+        };
+        bench::run(jobs, 4);
+        return;
+    }
+    if args.first().map(String::as_str) == Some("affinity") {
+        affinity_demo(args.iter().any(|arg| arg == "--pin"));
+        return;
+    }
+    if args.first().map(String::as_str) == Some("shutdown") {
+        shutdown_demo();
+        return;
+    }
+    if args.first().map(String::as_str) == Some("priority") {
+        priority_demo();
+        return;
+    }
+    if args.first().map(String::as_str) == Some("resize") {
+        resize_demo();
+        return;
+    }
+    if args.first().map(String::as_str) == Some("queue") {
+        queue_demo();
+        return;
+    }
+    if args.first().map(String::as_str) == Some("panics") {
+        // Our own log line says it; skip the default hook's backtrace note
+        panic::set_hook(Box::new(|_| {}));
+        panics_demo();
+        return;
+    }
+
     println!("=== Thread Pool Demo ===\n");
 
     let pool = ThreadPool::new(4);
@@ -192,6 +745,8 @@ fn main() {
     println!("  Result from task: {}", value);
     thread::sleep(std::time::Duration::from_millis(300));
 
+    println!("\n{}", pool.stats());
+
     println!("\nDropping pool (should trigger graceful shutdown)...");
     drop(pool);
 
@@ -0,0 +1,186 @@
+//! Scoped jobs: `pool.scope(|s| s.spawn(...))`, as `std::thread::scope`
+//! does for threads
+//!
+//! Jobs spawned on a `Scope` may borrow from the caller's stack, because
+//! `scope` doesn't return before every one of them is over: run, panicked,
+//! or dropped unrun by `DropOldest` or a shutdown. The queue only holds
+//! `'static` jobs, so `spawn` erases the borrow's lifetime; that wait is
+//! what makes it sound.
+
+use super::{panic_message, Job, Spawner, ThreadPool, HELP_POLL, WORKER_OF};
+use std::marker::PhantomData;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex};
+
+/// Spawns jobs that may borrow anything that outlives `'scope`
+pub struct Scope<'scope, 'env: 'scope> {
+    spawner: Spawner,
+    state: Arc<ScopeState>,
+    // Invariant in both lifetimes, as std's Scope: the borrow checker
+    // must not shrink them to make a borrow fit
+    scope: PhantomData<&'scope mut &'scope ()>,
+    env: PhantomData<&'env mut &'env ()>,
+}
+
+#[derive(Default)]
+struct ScopeState {
+    counts: Mutex<ScopeCounts>,
+    /// Signalled when `pending` drops to 0
+    done: Condvar,
+}
+
+#[derive(Default)]
+struct ScopeCounts {
+    /// Spawned and not over yet
+    pending: usize,
+    panicked: usize,
+    /// Dropped from the queue before they ran
+    discarded: usize,
+}
+
+/// A scoped job on its way through the queue. However it ends, its drop
+/// is the last thing that touches the borrowed data, and it tells the
+/// scope
+struct ScopedJob<'scope> {
+    job: Option<Box<dyn FnOnce() + Send + 'scope>>,
+    state: Arc<ScopeState>,
+}
+
+impl ScopedJob<'_> {
+    fn run(mut self) {
+        let job = self.job.take().expect("a scoped job runs once");
+        if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(job)) {
+            self.state.counts.lock().unwrap().panicked += 1;
+            // The worker logs it and replaces its thread, as for any job
+            panic::resume_unwind(payload);
+        }
+    }
+}
+
+impl Drop for ScopedJob<'_> {
+    fn drop(&mut self) {
+        // The closure and its borrows go first...
+        let unrun = self.job.take().is_some();
+        // ...then the scope may return
+        let mut counts = self.state.counts.lock().unwrap_or_else(|e| e.into_inner());
+        if unrun {
+            counts.discarded += 1;
+        }
+        counts.pending -= 1;
+        if counts.pending == 0 {
+            self.state.done.notify_all();
+        }
+    }
+}
+
+impl<'scope> Scope<'scope, '_> {
+    /// Queue a job that may borrow from the scope's caller
+    ///
+    /// # Panics
+    /// Panics if the pool can't take the job (`Reject` and full, or shut
+    /// down)
+    pub fn spawn<F>(&'scope self, job: F)
+    where
+        F: FnOnce() + Send + 'scope,
+    {
+        self.state.counts.lock().unwrap().pending += 1;
+        let scoped = ScopedJob {
+            job: Some(Box::new(job)),
+            state: Arc::clone(&self.state),
+        };
+        let job: Box<dyn FnOnce() + Send + 'scope> = Box::new(move || scoped.run());
+        // SAFETY: `scope` waits until `pending` is back to 0, which only
+        // happens when this job's `ScopedJob` has been dropped, after the
+        // closure holding the borrows. Nothing borrowed for 'scope is
+        // touched after `scope` returns
+        let job: Job =
+            unsafe { std::mem::transmute::<Box<dyn FnOnce() + Send + 'scope>, Job>(job) };
+        // A refused job is dropped here, unrun, and counts itself out
+        if let Err(err) = self.spawner.try_execute(job) {
+            panic!("cannot spawn a scoped job: {}", err);
+        }
+    }
+
+    /// Block until every spawned job is over. A worker of the same pool
+    /// runs queued jobs meanwhile, as `Task::join` does: the jobs it waits
+    /// for may be queued behind it
+    fn wait(&self) {
+        let worker = WORKER_OF
+            .get()
+            .filter(|(pool, _)| *pool == self.spawner.pool)
+            .map(|(_, worker)| worker);
+        let mut counts = self.state.counts.lock().unwrap();
+        while counts.pending > 0 {
+            let Some(worker) = worker else {
+                counts = self.state.done.wait(counts).unwrap();
+                continue;
+            };
+            drop(counts);
+            match self.spawner.queue.try_pop() {
+                Some(job) => {
+                    if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(job)) {
+                        println!(
+                            "Worker {} caught a panicking job ({}) while waiting",
+                            worker,
+                            panic_message(&*payload)
+                        );
+                    }
+                    counts = self.state.counts.lock().unwrap();
+                }
+                None => {
+                    counts = self.state.counts.lock().unwrap();
+                    if counts.pending > 0 {
+                        counts = self.state.done.wait_timeout(counts, HELP_POLL).unwrap().0;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Spawner {
+    /// Run `f` with a `Scope` whose jobs may borrow from the caller, and
+    /// return once `f` and every job spawned on the scope are done
+    ///
+    /// # Panics
+    /// Panics if `f` or a scoped job panicked, or a scoped job was
+    /// dropped before it ran
+    pub fn scope<'env, F, T>(&self, f: F) -> T
+    where
+        F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> T,
+    {
+        let scope = Scope {
+            spawner: self.clone(),
+            state: Arc::default(),
+            scope: PhantomData,
+            env: PhantomData,
+        };
+        // Even when `f` panics, its jobs are waited for before the
+        // unwinding leaves this frame and the borrowed data with it
+        let result = panic::catch_unwind(AssertUnwindSafe(|| f(&scope)));
+        scope.wait();
+        let result = result.unwrap_or_else(|payload| panic::resume_unwind(payload));
+
+        let counts = scope.state.counts.lock().unwrap();
+        if counts.panicked > 0 {
+            panic!("{} scoped job(s) panicked", counts.panicked);
+        }
+        if counts.discarded > 0 {
+            panic!(
+                "{} scoped job(s) were dropped before they ran",
+                counts.discarded
+            );
+        }
+        result
+    }
+}
+
+impl ThreadPool {
+    /// `Spawner::scope` on this pool
+    pub fn scope<'env, F, T>(&self, f: F) -> T
+    where
+        F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> T,
+    {
+        self.spawner().scope(f)
+    }
+}
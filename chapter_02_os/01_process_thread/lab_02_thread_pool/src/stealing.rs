@@ -0,0 +1,209 @@
+//! `StealingPool`: a work-stealing alternative to the shared queue
+//!
+//! Each worker owns a deque (crossbeam-deque). Only its owner pushes to
+//! and pops from the back, so that needs no lock: the job a worker just
+//! submitted runs next on the same thread, its data still in cache.
+//! Other workers take from the front with a `Stealer`. Jobs submitted
+//! from outside the pool go in a shared `Injector` instead.
+//!
+//! A worker looks for a job in that order: its own deque, a batch from
+//! the injector (the rest of the batch goes on its deque), then another
+//! worker's deque. When all are empty it sleeps on a condvar until a
+//! submit wakes it.
+
+use crossbeam_deque::{Injector, Steal, Stealer, Worker as Deque};
+use std::cell::RefCell;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+thread_local! {
+    /// (pool, this worker's deque) if this thread is a stealing worker
+    static LOCAL: RefCell<Option<(usize, Deque<Job>)>> = const { RefCell::new(None) };
+}
+
+struct Shared {
+    pool: usize,
+    injector: Injector<Job>,
+    /// One per worker, indexed by worker id
+    stealers: Vec<Stealer<Job>>,
+    /// Jobs submitted and not yet taken, wherever they are. A worker only
+    /// sleeps when it is 0
+    pending: AtomicUsize,
+    shutdown: AtomicBool,
+    /// Guards nothing: it makes "check pending, then sleep" atomic
+    /// against "add a job, then wake"
+    sleep: Mutex<()>,
+    wake: Condvar,
+    /// Jobs taken from another worker's deque
+    steals: AtomicU64,
+}
+
+/// A fixed set of workers with a deque each
+pub struct StealingPool {
+    shared: Arc<Shared>,
+    threads: Vec<thread::JoinHandle<()>>,
+}
+
+/// Submits jobs to a `StealingPool`, from outside or from inside its jobs
+#[derive(Clone)]
+pub struct StealingSpawner(Arc<Shared>);
+
+impl StealingPool {
+    /// # Panics
+    /// Panics if size is 0
+    pub fn new(size: usize) -> StealingPool {
+        assert!(size > 0, "Thread pool size must be > 0");
+        static NEXT_POOL: AtomicUsize = AtomicUsize::new(0);
+        let pool = NEXT_POOL.fetch_add(1, Ordering::Relaxed);
+
+        let deques: Vec<Deque<Job>> = (0..size).map(|_| Deque::new_lifo()).collect();
+        let shared = Arc::new(Shared {
+            pool,
+            injector: Injector::new(),
+            stealers: deques.iter().map(Deque::stealer).collect(),
+            pending: AtomicUsize::new(0),
+            shutdown: AtomicBool::new(false),
+            sleep: Mutex::new(()),
+            wake: Condvar::new(),
+            steals: AtomicU64::new(0),
+        });
+        let threads = deques
+            .into_iter()
+            .enumerate()
+            .map(|(id, deque)| {
+                let shared = Arc::clone(&shared);
+                thread::spawn(move || run(id, deque, &shared))
+            })
+            .collect();
+        StealingPool { shared, threads }
+    }
+
+    /// Jobs are submitted through a spawner, from anywhere
+    pub fn spawner(&self) -> StealingSpawner {
+        StealingSpawner(Arc::clone(&self.shared))
+    }
+
+    /// Jobs so far that a worker took from another worker's deque
+    pub fn steals(&self) -> u64 {
+        self.shared.steals.load(Ordering::Relaxed)
+    }
+}
+
+impl StealingSpawner {
+    pub fn execute<F>(&self, job: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        submit(&self.0, Box::new(job));
+    }
+}
+
+/// On a worker of this pool, push to its own deque; anywhere else, to
+/// the injector
+fn submit(shared: &Shared, job: Job) {
+    // Counted first: a worker may take the job as soon as it is pushed,
+    // and its decrement must not come before this increment
+    shared.pending.fetch_add(1, Ordering::SeqCst);
+    let job = LOCAL.with_borrow(|local| match local {
+        Some((pool, deque)) if *pool == shared.pool => {
+            deque.push(job);
+            None
+        }
+        _ => Some(job),
+    });
+    if let Some(job) = job {
+        shared.injector.push(job);
+    }
+    // A worker between its pending check and its wait holds the lock:
+    // taking it here means the notify can't fall in that gap
+    drop(shared.sleep.lock().unwrap());
+    shared.wake.notify_one();
+}
+
+/// The next job for worker `id`: its own deque, the injector, then the
+/// other workers' deques
+fn find_job(id: usize, shared: &Shared) -> Option<Job> {
+    LOCAL.with_borrow(|local| {
+        let (_, deque) = local.as_ref().expect("a worker thread");
+        if let Some(job) = deque.pop() {
+            return Some(job);
+        }
+        // Retry: a steal lost a race with another thread, and the deque
+        // may not be empty. Only give up once every source says Empty
+        loop {
+            let mut retry = false;
+            match shared.injector.steal_batch_and_pop(deque) {
+                Steal::Success(job) => return Some(job),
+                Steal::Retry => retry = true,
+                Steal::Empty => {}
+            }
+            for (other, stealer) in shared.stealers.iter().enumerate() {
+                if other == id {
+                    continue;
+                }
+                match stealer.steal() {
+                    Steal::Success(job) => {
+                        shared.steals.fetch_add(1, Ordering::Relaxed);
+                        return Some(job);
+                    }
+                    Steal::Retry => retry = true,
+                    Steal::Empty => {}
+                }
+            }
+            if !retry {
+                return None;
+            }
+        }
+    })
+}
+
+fn run(id: usize, deque: Deque<Job>, shared: &Shared) {
+    LOCAL.set(Some((shared.pool, deque)));
+    loop {
+        if let Some(job) = find_job(id, shared) {
+            shared.pending.fetch_sub(1, Ordering::SeqCst);
+            if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(job)) {
+                println!(
+                    "Worker {} caught a panicking job ({})",
+                    id,
+                    crate::thread_pool::panic_message(&*payload)
+                );
+            }
+            continue;
+        }
+        let pending = {
+            let guard = shared.sleep.lock().unwrap();
+            let _guard = shared
+                .wake
+                .wait_while(guard, |_| {
+                    shared.pending.load(Ordering::SeqCst) == 0
+                        && !shared.shutdown.load(Ordering::SeqCst)
+                })
+                .unwrap();
+            shared.pending.load(Ordering::SeqCst)
+        };
+        if pending == 0 {
+            // Shut down, and nothing is left anywhere
+            break;
+        }
+        // A job is pending but not found yet: pushed to a deque this
+        // worker already looked at, or in the middle of a batch steal
+        thread::yield_now();
+    }
+}
+
+impl Drop for StealingPool {
+    /// Let the workers finish every pending job, then join them
+    fn drop(&mut self) {
+        self.shared.shutdown.store(true, Ordering::SeqCst);
+        drop(self.shared.sleep.lock().unwrap());
+        self.shared.wake.notify_all();
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,1082 @@
+//! The ThreadPool and its workers
+//!
+//! Kept out of main.rs so other labs can use this pool instead of copying
+//! it: lab_06_mini_xargs and lab_04_blocking_echo include this file with
+//! `#[path]`. Programs whose stdout is their output make it with
+//! `ThreadPool::quiet`, which skips the workers' log lines.
+
+use std::any::Any;
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// `scope` and `Scope`, next to this file: a module of the pool, as they use
+// its queue and worker ids
+#[path = "scope.rs"]
+mod scope;
+
+// CPU pinning for `pin_cores`, next to this file too
+#[path = "affinity.rs"]
+pub mod affinity;
+
+// ============================================================
+// TODO: Implement ThreadPool and Worker
+// ============================================================
+
+/// A job is a boxed closure that can be sent across threads
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// What a worker takes off the queue
+enum Message {
+    Run(Job),
+    /// Poison pill: the worker that takes it exits (the pool shrank)
+    Stop,
+}
+
+/// The job queue, shared by the workers and every `Spawner`
+type Queue = Arc<JobQueue>;
+
+/// Queue capacity when the builder isn't given one
+const DEFAULT_QUEUE_CAPACITY: usize = 1024;
+
+/// How often a waiting worker looks for queued jobs again
+const HELP_POLL: Duration = Duration::from_millis(10);
+
+thread_local! {
+    /// (pool, worker id) if this thread is a pool worker
+    static WORKER_OF: Cell<Option<(usize, usize)>> = const { Cell::new(None) };
+}
+
+/// Thread pool that manages a fixed number of worker threads
+pub struct ThreadPool {
+    // TODO: Add fields
+    workers: Vec<Worker>,
+    /// None once the pool is shut down
+    spawner: Option<Spawner>,
+    queue: Queue,
+    /// Workers wanted: `workers` may still hold some that haven't taken
+    /// their poison pill yet
+    size: usize,
+    /// Id for the next worker started by `resize`
+    next_id: usize,
+    started: Instant,
+}
+
+/// Configures a `ThreadPool` before it starts
+pub struct ThreadPoolBuilder {
+    workers: usize,
+    queue_capacity: usize,
+    policy: QueuePolicy,
+    log: bool,
+    thread_name: String,
+    pin_cores: bool,
+}
+
+/// What a submit does when the queue is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QueuePolicy {
+    /// Wait until a worker takes a job off the queue
+    #[default]
+    Block,
+    /// Fail with `SubmitError::QueueFull`, leaving the queue as it is
+    Reject,
+    /// Throw away the job that has waited longest to make room
+    DropOldest,
+}
+
+/// Why a job was not queued
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubmitError {
+    /// Refused by `QueuePolicy::Reject`
+    QueueFull,
+    /// The pool was shut down
+    ShutDown,
+}
+
+impl fmt::Display for SubmitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubmitError::QueueFull => write!(f, "job queue is full"),
+            SubmitError::ShutDown => write!(f, "pool is shut down"),
+        }
+    }
+}
+
+impl std::error::Error for SubmitError {}
+
+/// A snapshot of the queue's metrics
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueStats {
+    pub capacity: usize,
+    /// Jobs queued now, waiting for a worker
+    pub depth: usize,
+    /// The most jobs ever queued at once
+    pub peak_depth: usize,
+    /// Jobs accepted into the queue, including those dropped later
+    pub submitted: u64,
+    /// Submits refused by `QueuePolicy::Reject`
+    pub rejected: u64,
+    /// Jobs thrown away by `QueuePolicy::DropOldest` or a shutdown
+    pub dropped: u64,
+}
+
+/// A snapshot of the pool: its workers' counters and the queue's
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoolStats {
+    /// Time since the pool was built
+    pub uptime: Duration,
+    pub workers: Vec<WorkerStats>,
+    pub queue: QueueStats,
+}
+
+/// What one worker has done since it started
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WorkerStats {
+    pub id: usize,
+    /// Jobs it took off the queue, panicked ones included
+    pub jobs: u64,
+    pub panicked: u64,
+    /// Time spent running jobs
+    pub busy: Duration,
+    /// Time since it started (later than the pool, if `resize` added it)
+    pub alive: Duration,
+}
+
+impl WorkerStats {
+    /// Share of its lifetime spent running jobs, 0.0 to 1.0
+    pub fn utilization(&self) -> f64 {
+        if self.alive.is_zero() {
+            return 0.0;
+        }
+        self.busy.as_secs_f64() / self.alive.as_secs_f64()
+    }
+}
+
+impl fmt::Display for PoolStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Pool stats after {:.2?}:", self.uptime)?;
+        for worker in &self.workers {
+            writeln!(
+                f,
+                "  worker {}: {} jobs, {} panicked, busy {:.1?} ({:.0}%)",
+                worker.id,
+                worker.jobs,
+                worker.panicked,
+                worker.busy,
+                worker.utilization() * 100.0
+            )?;
+        }
+        write!(f, "  queue: {}", self.queue)
+    }
+}
+
+impl fmt::Display for QueueStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "depth={} peak={} capacity={} submitted={} rejected={} dropped={}",
+            self.depth, self.peak_depth, self.capacity, self.submitted, self.rejected, self.dropped
+        )
+    }
+}
+
+/// How soon a queued job runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Priority {
+    High,
+    #[default]
+    Normal,
+    Low,
+}
+
+impl Priority {
+    /// Most urgent first: the order workers look in
+    const ALL: [Priority; 3] = [Priority::High, Priority::Normal, Priority::Low];
+
+    fn lane(self) -> usize {
+        self as usize
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Priority::High => "high",
+            Priority::Normal => "normal",
+            Priority::Low => "low",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A bounded queue of jobs, a FIFO per priority. Workers wait on
+/// `not_empty`, blocked submits on `not_full`
+struct JobQueue {
+    capacity: usize,
+    policy: QueuePolicy,
+    /// Whether workers print what they do
+    log: bool,
+    /// Worker threads are named "{thread_name}-{id}"
+    thread_name: String,
+    /// CPUs to pin workers to, round robin; None: no pinning
+    pin_cpus: Option<Vec<usize>>,
+    state: Mutex<QueueState>,
+    not_empty: Condvar,
+    not_full: Condvar,
+}
+
+struct QueueState {
+    /// Indexed by `Priority::lane`
+    lanes: [VecDeque<Message>; 3],
+    /// How many messages are poison pills (all in the Low lane)
+    pills: usize,
+    /// Set by shutdown: submits fail, and workers stop once the lanes
+    /// are empty (what dropping the `Sender` did for the channel)
+    closed: bool,
+    peak_depth: usize,
+    submitted: u64,
+    rejected: u64,
+    dropped: u64,
+}
+
+/// Submits jobs to a pool; jobs take a clone to submit jobs of their own.
+/// Submits fail once the pool is shut down
+#[derive(Clone)]
+pub struct Spawner {
+    pool: usize,
+    queue: Queue,
+    /// The lane this spawner's jobs go in
+    priority: Priority,
+}
+
+/// Where a job finds the waker of the future awaiting it
+type WakerSlot = Arc<Mutex<Option<Waker>>>;
+
+/// The handle of a worker's current thread; a replacement thread puts its
+/// own handle here
+type ThreadSlot = Arc<Mutex<Option<thread::JoinHandle<()>>>>;
+
+/// A job's panic, handed to whoever waits for its result
+#[derive(Debug, Clone, PartialEq)]
+pub struct JobPanicked(pub String);
+
+impl fmt::Display for JobPanicked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "job panicked: {}", self.0)
+    }
+}
+
+impl std::error::Error for JobPanicked {}
+
+/// The message of a `panic!`, which is a `&str` or a `String` in practice
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "(not a string)".to_string()
+    }
+}
+
+/// The result of a job, to come: `join()` it from a thread, or `.await` it
+pub struct Task<R> {
+    pool: usize,
+    result: mpsc::Receiver<Result<R, JobPanicked>>,
+    queue: Queue,
+    waker: WakerSlot,
+}
+
+/// Wakes the awaiting future when the job is over, however it ends:
+/// dropped during a panic's unwinding too
+struct WakeOnDrop(WakerSlot);
+
+impl Drop for WakeOnDrop {
+    fn drop(&mut self) {
+        if let Some(waker) = self.0.lock().unwrap_or_else(|e| e.into_inner()).take() {
+            waker.wake();
+        }
+    }
+}
+
+/// A worker that runs in its own thread
+struct Worker {
+    id: usize,
+    thread: ThreadSlot,
+    /// Shared with its thread, and with the replacement of a thread that
+    /// caught a panic: the worker keeps its counts
+    counters: Arc<WorkerCounters>,
+    started: Instant,
+}
+
+/// Updated by a worker thread after each job
+#[derive(Default)]
+struct WorkerCounters {
+    jobs: AtomicU64,
+    panicked: AtomicU64,
+    busy_nanos: AtomicU64,
+}
+
+impl ThreadPoolBuilder {
+    pub fn new() -> ThreadPoolBuilder {
+        ThreadPoolBuilder {
+            workers: 4,
+            queue_capacity: DEFAULT_QUEUE_CAPACITY,
+            policy: QueuePolicy::default(),
+            log: true,
+            thread_name: "pool-worker".to_string(),
+            pin_cores: false,
+        }
+    }
+
+    pub fn workers(mut self, workers: usize) -> ThreadPoolBuilder {
+        self.workers = workers;
+        self
+    }
+
+    /// Jobs that may wait for a worker at once
+    pub fn queue_capacity(mut self, capacity: usize) -> ThreadPoolBuilder {
+        self.queue_capacity = capacity;
+        self
+    }
+
+    pub fn policy(mut self, policy: QueuePolicy) -> ThreadPoolBuilder {
+        self.policy = policy;
+        self
+    }
+
+    /// Print the workers' lines: started, got a job, shutting down (the
+    /// default). Panics are printed either way
+    pub fn log(mut self, log: bool) -> ThreadPoolBuilder {
+        self.log = log;
+        self
+    }
+
+    /// Name worker threads "{prefix}-{id}" (default "pool-worker")
+    pub fn thread_name(mut self, prefix: &str) -> ThreadPoolBuilder {
+        self.thread_name = prefix.to_string();
+        self
+    }
+
+    /// Pin each worker to one CPU, round robin over the CPUs the process
+    /// may run on. Linux only: elsewhere, or if the CPUs can't be read,
+    /// workers stay unpinned and say so
+    pub fn pin_cores(mut self, pin: bool) -> ThreadPoolBuilder {
+        self.pin_cores = pin;
+        self
+    }
+
+    /// Start the workers
+    ///
+    /// # Panics
+    /// Panics if the worker count or the queue capacity is 0
+    pub fn build(self) -> ThreadPool {
+        assert!(self.workers > 0, "Thread pool size must be > 0");
+        assert!(self.queue_capacity > 0, "Queue capacity must be > 0");
+        static NEXT_POOL: AtomicUsize = AtomicUsize::new(0);
+        let pool = NEXT_POOL.fetch_add(1, Ordering::Relaxed);
+
+        let pin_cpus = match self.pin_cores.then(affinity::allowed_cpus) {
+            None => None,
+            Some(Ok(cpus)) if !cpus.is_empty() => Some(cpus),
+            Some(Ok(_)) => None,
+            Some(Err(err)) => {
+                println!("Not pinning workers: cannot read the allowed CPUs: {}", err);
+                None
+            }
+        };
+        let queue = Arc::new(JobQueue {
+            capacity: self.queue_capacity,
+            policy: self.policy,
+            log: self.log,
+            thread_name: self.thread_name,
+            pin_cpus,
+            state: Mutex::new(QueueState {
+                lanes: Default::default(),
+                pills: 0,
+                closed: false,
+                peak_depth: 0,
+                submitted: 0,
+                rejected: 0,
+                dropped: 0,
+            }),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+        });
+
+        let mut workers = Vec::with_capacity(self.workers);
+
+        for id in 0..self.workers {
+            workers.push(Worker::new(id, pool, Arc::clone(&queue)));
+        }
+
+        ThreadPool {
+            workers,
+            spawner: Some(Spawner {
+                pool,
+                queue: Arc::clone(&queue),
+                priority: Priority::Normal,
+            }),
+            queue,
+            size: self.workers,
+            next_id: self.workers,
+            started: Instant::now(),
+        }
+    }
+}
+
+impl Default for ThreadPoolBuilder {
+    fn default() -> Self {
+        ThreadPoolBuilder::new()
+    }
+}
+
+impl ThreadPool {
+    /// Create a new ThreadPool with `size` workers and the default queue
+    ///
+    /// # Panics
+    /// Panics if size is 0
+    pub fn new(size: usize) -> ThreadPool {
+        ThreadPool::builder().workers(size).build()
+    }
+
+    /// Like `new`, without the log lines
+    pub fn quiet(size: usize) -> ThreadPool {
+        ThreadPool::builder().workers(size).log(false).build()
+    }
+
+    pub fn builder() -> ThreadPoolBuilder {
+        ThreadPoolBuilder::new()
+    }
+
+    /// Execute a closure on a worker thread. Does nothing once the pool
+    /// is shut down
+    ///
+    /// # Panics
+    /// Panics if the queue is full and the policy is `Reject`
+    pub fn execute<F>(&self, job: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        if let Some(spawner) = &self.spawner {
+            spawner.execute(job);
+        }
+    }
+
+    /// Queue a closure, or say why not
+    pub fn try_execute<F>(&self, job: F) -> Result<(), SubmitError>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        match &self.spawner {
+            Some(spawner) => spawner.try_execute(job),
+            None => Err(SubmitError::ShutDown),
+        }
+    }
+
+    pub fn execute_with_result<F, R>(&self, job: F) -> Task<R>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        self.spawner().execute_with_result(job)
+    }
+
+    pub fn try_execute_with_result<F, R>(&self, job: F) -> Result<Task<R>, SubmitError>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        match &self.spawner {
+            Some(spawner) => spawner.try_execute_with_result(job),
+            None => Err(SubmitError::ShutDown),
+        }
+    }
+
+    pub fn queue_stats(&self) -> QueueStats {
+        self.queue.stats()
+    }
+
+    /// A handle for submitting jobs from inside jobs
+    pub fn spawner(&self) -> Spawner {
+        self.spawner.clone().expect("pool is shut down")
+    }
+
+    /// A handle that submits jobs at `priority`
+    pub fn with_priority(&self, priority: Priority) -> Spawner {
+        self.spawner().with_priority(priority)
+    }
+
+    /// Stop accepting jobs, give the workers up to `timeout` to take the
+    /// queued ones, discard the rest and join the workers. Returns how
+    /// many jobs were discarded
+    pub fn shutdown(&mut self, timeout: Duration) -> usize {
+        self.shut_down(Some(timeout))
+    }
+
+    /// Stop accepting jobs, discard the queued ones and join the workers,
+    /// which finish the jobs they are running. Returns how many jobs were
+    /// discarded
+    pub fn shutdown_now(&mut self) -> usize {
+        self.shut_down(Some(Duration::ZERO))
+    }
+
+    /// `timeout: None` waits for the queue to drain however long it takes
+    fn shut_down(&mut self, timeout: Option<Duration>) -> usize {
+        if self.spawner.take().is_none() {
+            return 0;
+        }
+        if self.queue.log {
+            println!("ThreadPool shutting down");
+        }
+        self.queue.close();
+        let discarded = match timeout {
+            Some(timeout) => self.queue.discard_after(timeout),
+            None => 0,
+        };
+
+        for worker in &mut self.workers {
+            // A thread being replaced stores its successor's handle before
+            // it exits: join until no handle is left. (Take the handle in
+            // its own statement: a worker replacing its thread needs the lock)
+            loop {
+                let thread = worker.thread.lock().unwrap().take();
+                let Some(thread) = thread else { break };
+                // waits for that worker thread to finish.
+                if thread.join().is_err() {
+                    println!("Worker {} died from a panic", worker.id);
+                }
+            }
+        }
+        discarded
+    }
+
+    /// Workers wanted, as last set by `new` or `resize`
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Grow or shrink the pool to `new_size` workers. Shrinking doesn't
+    /// wait: the workers that go first finish what is queued before their
+    /// pill
+    ///
+    /// # Panics
+    /// Panics if new_size is 0
+    pub fn resize(&mut self, new_size: usize) {
+        assert!(new_size > 0, "Thread pool size must be > 0");
+        self.join_stopped();
+        let spawner = self.spawner();
+        if new_size > self.size {
+            // Pills still queued would stop workers we are about to need
+            let taken_back = spawner.queue.take_back_stops(new_size - self.size);
+            for _ in taken_back..new_size - self.size {
+                let id = self.next_id;
+                self.next_id += 1;
+                self.workers
+                    .push(Worker::new(id, spawner.pool, Arc::clone(&spawner.queue)));
+            }
+        } else if new_size < self.size {
+            spawner.queue.push_stops(self.size - new_size);
+        }
+        self.size = new_size;
+    }
+
+    /// Join and forget the workers that took a poison pill
+    fn join_stopped(&mut self) {
+        self.workers.retain(|worker| {
+            let mut thread = worker.thread.lock().unwrap();
+            if !thread.as_ref().is_some_and(|thread| thread.is_finished()) {
+                return true;
+            }
+            if thread.take().is_some_and(|thread| thread.join().is_err()) {
+                println!("Worker {} died from a panic", worker.id);
+            }
+            false
+        });
+    }
+
+    /// The workers' counters and the queue's metrics, now. Workers that
+    /// `resize` stopped and joined are no longer listed
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            uptime: self.started.elapsed(),
+            workers: self.workers.iter().map(Worker::stats).collect(),
+            queue: self.queue.stats(),
+        }
+    }
+
+    /// Workers with a running thread: stays at the pool size, because a
+    /// thread that caught a panic is replaced before it exits
+    pub fn live_workers(&self) -> usize {
+        self.workers
+            .iter()
+            .filter(|worker| {
+                let thread = worker.thread.lock().unwrap();
+                thread.as_ref().is_some_and(|thread| !thread.is_finished())
+            })
+            .count()
+    }
+}
+
+impl JobQueue {
+    /// Queue a job in its priority's lane, applying the policy if the
+    /// queue is full
+    fn push(&self, job: Job, priority: Priority) -> Result<(), SubmitError> {
+        let mut state = self.state.lock().unwrap();
+        let mut evicted = None;
+        if state.depth() >= self.capacity && !state.closed {
+            match self.policy {
+                QueuePolicy::Block => {
+                    state = self
+                        .not_full
+                        .wait_while(state, |state| {
+                            state.depth() >= self.capacity && !state.closed
+                        })
+                        .unwrap();
+                }
+                QueuePolicy::Reject => {
+                    state.rejected += 1;
+                    return Err(SubmitError::QueueFull);
+                }
+                QueuePolicy::DropOldest => {
+                    // The oldest job of the least urgent lane, never a pill
+                    evicted = state.take_job(Priority::ALL.into_iter().rev());
+                    state.dropped += 1;
+                }
+            }
+        }
+        if state.closed {
+            return Err(SubmitError::ShutDown);
+        }
+        state.lanes[priority.lane()].push_back(Message::Run(job));
+        state.submitted += 1;
+        state.peak_depth = state.peak_depth.max(state.depth());
+        drop(state);
+        self.not_empty.notify_one();
+        // Dropping a job drops what it captured, a result sender and a
+        // waker among them: not while holding the lock
+        drop(evicted);
+        Ok(())
+    }
+
+    /// Queue `count` poison pills behind the jobs already there,
+    /// whatever the capacity
+    fn push_stops(&self, count: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.lanes[Priority::Low.lane()].extend((0..count).map(|_| Message::Stop));
+        state.pills += count;
+        drop(state);
+        self.not_empty.notify_all();
+    }
+
+    /// Remove up to `count` pills no worker has taken yet; returns how many
+    fn take_back_stops(&self, count: usize) -> usize {
+        let mut state = self.state.lock().unwrap();
+        let taken_back = count.min(state.pills);
+        let mut left = taken_back;
+        state.lanes[Priority::Low.lane()].retain(|message| match message {
+            Message::Stop if left > 0 => {
+                left -= 1;
+                false
+            }
+            _ => true,
+        });
+        state.pills -= taken_back;
+        taken_back
+    }
+
+    /// The next message of the most urgent lane, waiting for one. None
+    /// once the queue is closed and empty
+    fn pop(&self) -> Option<Message> {
+        let state = self.state.lock().unwrap();
+        let mut state = self
+            .not_empty
+            .wait_while(state, |state| state.is_empty() && !state.closed)
+            .unwrap();
+        let message = state.lanes.iter_mut().find_map(VecDeque::pop_front);
+        if let Some(Message::Stop) = message {
+            state.pills -= 1;
+        }
+        drop(state);
+        if message.is_some() {
+            // A blocked submit, or a shutdown waiting for the queue to drain
+            self.not_full.notify_one();
+        }
+        message
+    }
+
+    /// Refuse new jobs. Workers wake up and, once the queue is empty,
+    /// exit; blocked submits wake up and fail
+    fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.not_empty.notify_all();
+        self.not_full.notify_all();
+    }
+
+    /// Wait up to `timeout` for the workers to take every queued job,
+    /// then throw away what is left. Returns how many jobs that was
+    fn discard_after(&self, timeout: Duration) -> usize {
+        let state = self.state.lock().unwrap();
+        let (mut state, _) = self
+            .not_full
+            .wait_timeout_while(state, timeout, |state| !state.is_empty())
+            .unwrap();
+        let discarded: Vec<Message> = state
+            .lanes
+            .iter_mut()
+            .flat_map(|lane| lane.drain(..))
+            .collect();
+        let jobs = discarded.len() - state.pills;
+        state.pills = 0;
+        state.dropped += jobs as u64;
+        drop(state);
+        // As in push: captured result senders and wakers drop unlocked
+        drop(discarded);
+        jobs
+    }
+
+    /// The next job if one is queued. Pills stay where they are: a worker
+    /// waiting on a result is in the middle of a job
+    fn try_pop(&self) -> Option<Job> {
+        let job = self.state.lock().unwrap().take_job(Priority::ALL);
+        if job.is_some() {
+            self.not_full.notify_one();
+        }
+        job
+    }
+
+    fn stats(&self) -> QueueStats {
+        let state = self.state.lock().unwrap();
+        QueueStats {
+            capacity: self.capacity,
+            depth: state.depth(),
+            peak_depth: state.peak_depth,
+            submitted: state.submitted,
+            rejected: state.rejected,
+            dropped: state.dropped,
+        }
+    }
+}
+
+impl QueueState {
+    /// Queued jobs in all lanes, not counting pills
+    fn depth(&self) -> usize {
+        self.lanes.iter().map(VecDeque::len).sum::<usize>() - self.pills
+    }
+
+    fn is_empty(&self) -> bool {
+        self.lanes.iter().all(VecDeque::is_empty)
+    }
+
+    /// Remove the oldest job of the first non-empty lane in `order`,
+    /// skipping pills
+    fn take_job(&mut self, order: impl IntoIterator<Item = Priority>) -> Option<Job> {
+        order.into_iter().find_map(|priority| {
+            let lane = &mut self.lanes[priority.lane()];
+            let index = lane
+                .iter()
+                .position(|message| matches!(message, Message::Run(_)))?;
+            match lane.remove(index) {
+                Some(Message::Run(job)) => Some(job),
+                _ => unreachable!("position found a job"),
+            }
+        })
+    }
+}
+
+impl Spawner {
+    /// A spawner for the same pool that submits at `priority`
+    pub fn with_priority(&self, priority: Priority) -> Spawner {
+        let mut spawner = self.clone();
+        spawner.priority = priority;
+        spawner
+    }
+
+    /// # Panics
+    /// Panics if the queue is full and the policy is `Reject`, or the
+    /// pool is shut down
+    pub fn execute<F>(&self, job: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.try_execute(job)
+            .unwrap_or_else(|err| panic!("{}", err));
+    }
+
+    pub fn try_execute<F>(&self, job: F) -> Result<(), SubmitError>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.queue.push(Box::new(job), self.priority)
+    }
+
+    /// # Panics
+    /// Panics if the queue is full and the policy is `Reject`
+    pub fn execute_with_result<F, R>(&self, job: F) -> Task<R>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        self.try_execute_with_result(job)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_execute_with_result<F, R>(&self, job: F) -> Result<Task<R>, SubmitError>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (result_sender, result_receiver) = mpsc::channel();
+        let waker: WakerSlot = Arc::default();
+        let wake = WakeOnDrop(Arc::clone(&waker));
+        self.try_execute(move || {
+            // Dropped last, after the result is sent, even when unwinding
+            let _wake = wake;
+            match panic::catch_unwind(AssertUnwindSafe(job)) {
+                Ok(result) => {
+                    let _ = result_sender.send(Ok(result));
+                }
+                Err(payload) => {
+                    let panicked = JobPanicked(panic_message(&*payload));
+                    let _ = result_sender.send(Err(panicked));
+                    // The worker sees the panic too: it logs it and
+                    // replaces its thread
+                    panic::resume_unwind(payload);
+                }
+            }
+        })?;
+        Ok(Task {
+            pool: self.pool,
+            result: result_receiver,
+            queue: Arc::clone(&self.queue),
+            waker,
+        })
+    }
+}
+
+/// The channel closed without a result: the job never finished (or never
+/// ran: `DropOldest` threw it away)
+fn dropped() -> JobPanicked {
+    JobPanicked("the job was dropped before it finished".to_string())
+}
+
+impl<R> Task<R> {
+    /// Wait for the result, panicking if the job did
+    pub fn join(self) -> R {
+        self.try_join().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Wait for the result. A worker of the same pool runs queued jobs
+    /// while it waits, rather than holding its thread idle
+    pub fn try_join(self) -> Result<R, JobPanicked> {
+        let Some((_, worker)) = WORKER_OF.get().filter(|(pool, _)| *pool == self.pool) else {
+            return self.result.recv().unwrap_or_else(|_| Err(dropped()));
+        };
+        if self.queue.log {
+            println!(
+                "Worker {} waits on its own pool: running queued jobs meanwhile",
+                worker
+            );
+        }
+        loop {
+            match self.result.try_recv() {
+                Ok(result) => return result,
+                Err(mpsc::TryRecvError::Disconnected) => return Err(dropped()),
+                Err(mpsc::TryRecvError::Empty) => {}
+            }
+            match self.queue.try_pop() {
+                // Someone else's job: its panic is its own Task's business
+                Some(job) => {
+                    if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(job)) {
+                        println!(
+                            "Worker {} caught a panicking job ({}) while waiting",
+                            worker,
+                            panic_message(&*payload)
+                        );
+                    }
+                }
+                None => match self.result.recv_timeout(HELP_POLL) {
+                    Ok(result) => return result,
+                    Err(mpsc::RecvTimeoutError::Disconnected) => return Err(dropped()),
+                    Err(mpsc::RecvTimeoutError::Timeout) => {}
+                },
+            }
+        }
+    }
+
+    /// The bare channel. Blocking on it inside a job of the same pool is
+    /// what deadlocks
+    pub fn into_receiver(self) -> mpsc::Receiver<Result<R, JobPanicked>> {
+        self.result
+    }
+}
+
+impl<R> Future for Task<R> {
+    type Output = Result<R, JobPanicked>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.result.try_recv() {
+            Ok(result) => return Poll::Ready(result),
+            Err(mpsc::TryRecvError::Disconnected) => return Poll::Ready(Err(dropped())),
+            Err(mpsc::TryRecvError::Empty) => {}
+        }
+        *self.waker.lock().unwrap() = Some(cx.waker().clone());
+        // The job may have finished between try_recv and storing the
+        // waker, and found no waker to wake: look once more
+        match self.result.try_recv() {
+            Ok(result) => Poll::Ready(result),
+            Err(mpsc::TryRecvError::Disconnected) => Poll::Ready(Err(dropped())),
+            Err(mpsc::TryRecvError::Empty) => Poll::Pending,
+        }
+    }
+}
+
+/// Unparks the thread blocked in `block_on`
+struct ThreadWaker(thread::Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Run a future to completion on this thread, parked while it is pending.
+/// Returns the output and how many times the future was polled
+pub fn block_on<F: Future>(future: F) -> (F::Output, usize) {
+    let mut future = std::pin::pin!(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut polls = 0;
+    loop {
+        polls += 1;
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return (output, polls);
+        }
+        // A wake before this park leaves a token: park returns at once
+        thread::park();
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        // Closing the queue makes each worker's pop() return None once it
+        // is empty, so the worker can exit. Nothing left queued is lost
+        self.shut_down(None);
+    }
+}
+
+impl Worker {
+    /// Create a new worker that listens for jobs on the receiver
+    fn new(id: usize, pool: usize, receiver: Queue) -> Worker {
+        let thread = ThreadSlot::default();
+        let counters = Arc::new(WorkerCounters::default());
+        Worker::start(
+            id,
+            pool,
+            receiver,
+            Arc::clone(&thread),
+            Arc::clone(&counters),
+        );
+        Worker {
+            id,
+            thread,
+            counters,
+            started: Instant::now(),
+        }
+    }
+
+    fn stats(&self) -> WorkerStats {
+        WorkerStats {
+            id: self.id,
+            jobs: self.counters.jobs.load(Ordering::Relaxed),
+            panicked: self.counters.panicked.load(Ordering::Relaxed),
+            busy: Duration::from_nanos(self.counters.busy_nanos.load(Ordering::Relaxed)),
+            alive: self.started.elapsed(),
+        }
+    }
+
+    /// Run worker `id` on a new thread and put its handle in `slot`
+    fn start(
+        id: usize,
+        pool: usize,
+        receiver: Queue,
+        slot: ThreadSlot,
+        counters: Arc<WorkerCounters>,
+    ) {
+        // Held until the handle is stored: a thread that panics at once
+        // can't store its successor first and have it overwritten
+        let mut current = slot.lock().unwrap();
+        let own_slot = Arc::clone(&slot);
+        // TODO: Implement
+        // 1. Spawn a thread
+        let name = format!("{}-{}", receiver.thread_name, id);
+        let thread = thread::Builder::new()
+            .name(name.clone())
+            .spawn(move || {
+                WORKER_OF.set(Some((pool, id)));
+                if receiver.log {
+                    println!("Worker {} started", id);
+                }
+                // A replacement thread is pinned again: affinity is per thread
+                if let Some(cpus) = &receiver.pin_cpus {
+                    let cpu = cpus[id % cpus.len()];
+                    match affinity::pin_current_thread(cpu) {
+                        Ok(()) if receiver.log => println!("{} pinned to CPU {}", name, cpu),
+                        Ok(()) => {}
+                        Err(err) => println!("{} not pinned to CPU {}: {}", name, cpu, err),
+                    }
+                }
+                loop {
+                    // pop() returns exactly one job each time it's called
+                    match receiver.pop() {
+                        Some(Message::Run(job)) => {
+                            if receiver.log {
+                                println!("Worker {} got a job; executing", id);
+                            }
+                            // The job runs without the queue lock, so a panic
+                            // can't poison it
+                            let started = Instant::now();
+                            let outcome = panic::catch_unwind(AssertUnwindSafe(job));
+                            counters
+                                .busy_nanos
+                                .fetch_add(started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+                            counters.jobs.fetch_add(1, Ordering::Relaxed);
+                            if let Err(payload) = outcome {
+                                counters.panicked.fetch_add(1, Ordering::Relaxed);
+                                println!(
+                                    "Worker {} caught a panicking job ({}): replacing its thread",
+                                    id,
+                                    panic_message(&*payload)
+                                );
+                                Worker::start(id, pool, receiver, own_slot, counters);
+                                break;
+                            }
+                        }
+                        Some(Message::Stop) => {
+                            if receiver.log {
+                                println!("Worker {} took a poison pill: stopping", id);
+                            }
+                            break;
+                        }
+                        None => {
+                            if receiver.log {
+                                println!("Worker {} shutting down", id);
+                            }
+                            break; // If the queue is closed and empty, break
+                        }
+                    }
+                }
+            })
+            .expect("failed to spawn a worker thread");
+        //: move || ...: a closure that takes ownership of captured variables (so it can run safely in the new thread)
+        *current = Some(thread);
+    }
+}
@@ -3,10 +3,6 @@
 //! Run with: cargo test
 
 use std::process::Command;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
-use std::thread;
-use std::time::Duration;
 
 #[test]
 fn test_01_program_runs() {
@@ -88,3 +84,420 @@ fn test_04_no_panics() {
         "Program should not panic"
     );
 }
+
+#[test]
+fn test_05_nested_wait_deadlocks_on_a_bare_channel() {
+    let output = Command::new("cargo")
+        .args(["run", "--quiet", "--", "nested", "--naive"])
+        .output()
+        .expect("Failed to execute program");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    // Both workers sit in an outer job, the inner jobs sit in the queue
+    assert!(stdout.contains("Deadlock"), "{}", stdout);
+    assert!(!output.status.success());
+    assert!(!stdout.contains("Nested results"));
+}
+
+#[test]
+fn test_06_nested_join_runs_queued_jobs_inline() {
+    let output = Command::new("cargo")
+        .args(["run", "--quiet", "--", "nested", "--log"])
+        .output()
+        .expect("Failed to execute program");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("Nested results: [1, 11]"), "{}", stdout);
+    assert!(
+        stdout.contains("waits on its own pool: running queued jobs meanwhile"),
+        "{}",
+        stdout
+    );
+    assert!(!stdout.contains("Deadlock"));
+}
+
+#[test]
+fn test_07_tasks_can_be_awaited() {
+    let output = Command::new("cargo")
+        .args(["run", "--quiet", "--", "await"])
+        .output()
+        .expect("Failed to execute program");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("Sum of squares: 30"), "{}", stdout);
+    // Parked between results, not spinning: one poll per wake-up at most
+    let polls: usize = stdout
+        .split("Sum of squares: 30 (")
+        .nth(1)
+        .and_then(|rest| rest.split_whitespace().next())
+        .and_then(|n| n.parse().ok())
+        .expect("poll count");
+    assert!(polls <= 5, "{} polls for 4 tasks", polls);
+    // The panicking job must wake its awaiter, or this run would hang
+    assert!(
+        stdout.contains("Awaiting a job that panicked: job panicked: boom"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn test_08_panicking_jobs_keep_the_pool_size() {
+    let output = Command::new("cargo")
+        .args(["run", "--quiet", "--", "panics"])
+        .output()
+        .expect("Failed to execute program");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success(), "{}", stdout);
+    // Each failure reaches its own Task, the others are unaffected
+    for line in [
+        "Job 0: Ok(0)",
+        "Job 1: job panicked: job 1 failed",
+        "Job 2: Ok(4)",
+        "Job 3: Ok(9)",
+        "Job 4: job panicked: job 4 failed",
+        "Job 5: Ok(25)",
+        "After another panic: still serving",
+    ] {
+        assert!(stdout.contains(line), "missing {:?} in {}", line, stdout);
+    }
+    assert_eq!(
+        stdout.matches("caught a panicking job").count(),
+        3,
+        "{}",
+        stdout
+    );
+    assert_eq!(
+        stdout.matches("Live workers: 2 of 2").count(),
+        2,
+        "{}",
+        stdout
+    );
+    // Replacements shut down cleanly with the rest
+    assert_eq!(stdout.matches("shutting down").count(), 3, "{}", stdout);
+}
+
+#[test]
+fn test_09_full_queue_policies() {
+    let output = Command::new("cargo")
+        .args(["run", "--quiet", "--", "queue"])
+        .output()
+        .expect("Failed to execute program");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success(), "{}", stdout);
+    let policy = |name: &str| {
+        let start = stdout
+            .find(&format!("Policy {}:", name))
+            .unwrap_or_else(|| panic!("no {} section in {}", name, stdout));
+        let rest = &stdout[start..];
+        let end = rest[1..].find("Policy ").map_or(rest.len(), |i| i + 1);
+        rest[..end].to_string()
+    };
+
+    // Block: the submitter waits, nothing is lost
+    let block = policy("Block");
+    assert!(
+        block.contains("job 3: waited for the worker to make room"),
+        "{}",
+        block
+    );
+    assert!(block.contains("ran: [0, 1, 2, 3, 4, 5]"), "{}", block);
+    assert!(
+        block.contains("peak=2 capacity=2 submitted=6 rejected=0 dropped=0"),
+        "{}",
+        block
+    );
+
+    // Reject: the jobs that don't fit are refused
+    let reject = policy("Reject");
+    for job in 3..6 {
+        assert!(
+            reject.contains(&format!("job {}: job queue is full", job)),
+            "{}",
+            reject
+        );
+    }
+    assert!(reject.contains("ran: [0, 1, 2]"), "{}", reject);
+    assert!(
+        reject.contains("submitted=3 rejected=3 dropped=0"),
+        "{}",
+        reject
+    );
+
+    // DropOldest: the newest jobs run, the oldest queued ones never do
+    let drop_oldest = policy("DropOldest");
+    for job in 1..4 {
+        assert!(
+            drop_oldest.contains(&format!("job {}: dropped from the queue", job)),
+            "{}",
+            drop_oldest
+        );
+    }
+    assert!(drop_oldest.contains("ran: [0, 4, 5]"), "{}", drop_oldest);
+    assert!(
+        drop_oldest.contains("submitted=6 rejected=0 dropped=3"),
+        "{}",
+        drop_oldest
+    );
+    assert!(drop_oldest.contains("depth=0"), "{}", drop_oldest);
+}
+
+#[test]
+fn test_10_resize_keeps_in_flight_jobs() {
+    let output = Command::new("cargo")
+        .args(["run", "--quiet", "--", "resize"])
+        .output()
+        .expect("Failed to execute program");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success(), "{}", stdout);
+    assert!(
+        stdout.contains("Resizing 4 -> 1 with 4 jobs running and 4 queued"),
+        "{}",
+        stdout
+    );
+    // Shrinking while busy: the running and the queued jobs all finish
+    assert!(
+        stdout.contains("Jobs finished: 8 of 8 [0, 1, 2, 3, 4, 5, 6, 7]"),
+        "{}",
+        stdout
+    );
+    assert_eq!(
+        stdout.matches("took a poison pill").count(),
+        3,
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("Live workers: 1\n"), "{}", stdout);
+
+    // Growing starts new workers, which take jobs
+    assert!(stdout.contains("Worker 4 started"), "{}", stdout);
+    assert!(stdout.contains("Worker 5 started"), "{}", stdout);
+    assert!(
+        stdout.contains("Jobs finished: 6 of 6 [8, 9, 10, 11, 12, 13]"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("Live workers: 3\n"), "{}", stdout);
+}
+
+#[test]
+fn test_11_priorities_jump_the_line() {
+    let output = Command::new("cargo")
+        .args(["run", "--quiet", "--", "priority"])
+        .output()
+        .expect("Failed to execute program");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success(), "{}", stdout);
+    assert!(
+        stdout.contains(
+            "Submitted: [low-1, normal-2, high-3, low-4, normal-5, high-6, low-7, normal-8, high-9]"
+        ),
+        "{}",
+        stdout
+    );
+    // Most urgent lane first, first come first served within a lane
+    assert!(
+        stdout.contains(
+            "Ran:       [high-3, high-6, high-9, normal-2, normal-5, normal-8, low-1, low-4, low-7]"
+        ),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn test_12_shutdown_modes() {
+    let output = Command::new("cargo")
+        .args(["run", "--quiet", "--", "shutdown"])
+        .output()
+        .expect("Failed to execute program");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success(), "{}", stdout);
+    let (graceful, immediate) = stdout
+        .split_once("Immediate shutdown")
+        .unwrap_or_else(|| panic!("no immediate shutdown in {}", stdout));
+
+    // Graceful: the first jobs finish in time, the tail of the queue is
+    // discarded (how many depends on timing)
+    assert!(graceful.contains("finished: [0, 1, 2, 3"), "{}", graceful);
+    assert!(!graceful.contains("never ran: []"), "{}", graceful);
+    assert!(graceful.contains(", 9]\n"), "{}", graceful);
+    assert!(
+        graceful.contains("Submit after shutdown: pool is shut down"),
+        "{}",
+        graceful
+    );
+
+    // Immediate: the running jobs finish, the queued ones never start
+    assert!(
+        immediate.contains("discarded 4 queued job(s)"),
+        "{}",
+        immediate
+    );
+    assert!(immediate.contains("finished: [0, 1]"), "{}", immediate);
+    assert!(
+        immediate.contains("never ran: [2, 3, 4, 5]"),
+        "{}",
+        immediate
+    );
+}
+
+#[test]
+fn test_13_steal_bench_runs_both_schedulers() {
+    let output = Command::new("cargo")
+        .args(["run", "--quiet", "--", "steal-bench", "400"])
+        .output()
+        .expect("Failed to execute program");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success(), "{}", stdout);
+    for scheduler in ["shared  ", "stealing"] {
+        for workload in ["outside", "nested "] {
+            let prefix = format!(
+                "scheduler={} workload={} workers=4 jobs=400 elapsed_ms=",
+                scheduler, workload
+            );
+            assert!(
+                stdout.contains(&prefix),
+                "missing {:?} in {}",
+                prefix,
+                stdout
+            );
+        }
+    }
+    // Nested jobs all land on one worker's deque: the others must steal
+    let nested_steals: u64 = stdout
+        .lines()
+        .find(|line| line.starts_with("scheduler=stealing workload=nested"))
+        .and_then(|line| line.rsplit_once("steals="))
+        .and_then(|(_, steals)| steals.trim().parse().ok())
+        .unwrap_or_else(|| panic!("no steal count in {}", stdout));
+    assert!(nested_steals > 0, "{}", stdout);
+    // The benchmark pools don't log per worker or job
+    assert!(!stdout.contains("got a job"), "{}", stdout);
+}
+
+#[test]
+fn test_14_stats_snapshot() {
+    let output = Command::new("cargo")
+        .args(["run", "--quiet"])
+        .output()
+        .expect("Failed to execute program");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("Pool stats after"), "{}", stdout);
+    // "  worker N: J jobs, P panicked, busy T (U%)"
+    let workers: Vec<(u64, u64)> = stdout
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("worker "))
+        .map(|rest| {
+            let fields: Vec<&str> = rest.split_whitespace().collect();
+            let jobs = fields[1].parse().expect("job count");
+            let utilization = fields[fields.len() - 1]
+                .trim_matches(|c| c == '(' || c == ')' || c == '%')
+                .parse()
+                .expect("utilization");
+            (jobs, utilization)
+        })
+        .collect();
+    assert_eq!(workers.len(), 4, "{}", stdout);
+    // 8 tasks and the one with a result, spread over the workers
+    assert_eq!(
+        workers.iter().map(|(jobs, _)| jobs).sum::<u64>(),
+        9,
+        "{}",
+        stdout
+    );
+    // Each 100ms task counts as busy time; nobody is busy all the time
+    assert!(
+        workers.iter().all(|(_, utilization)| *utilization <= 100),
+        "{}",
+        stdout
+    );
+    assert!(
+        workers.iter().any(|(_, utilization)| *utilization >= 10),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains("submitted=9 rejected=0 dropped=0"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn test_15_scoped_jobs_borrow_the_stack() {
+    let output = Command::new("cargo")
+        .args(["run", "--quiet", "--", "scope"])
+        .output()
+        .expect("Failed to execute program");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success(), "{}", stdout);
+    // Every chunk's sum was written through its borrow before scope returned
+    assert!(
+        stdout.contains("Chunk sums: [31375, 93875, 156375, 218875], total 500500"),
+        "{}",
+        stdout
+    );
+    // A scope inside a job doesn't deadlock a one-worker pool
+    assert!(
+        stdout.contains("Scope inside the only worker's job: [2, 4, 6]"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains("Scope with a panicking job: 1 scoped job(s) panicked"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn test_16_named_and_pinned_workers() {
+    let output = Command::new("cargo")
+        .args(["run", "--quiet", "--", "affinity", "--pin"])
+        .output()
+        .expect("Failed to execute program");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success(), "{}", stdout);
+    // Every job ran on a named worker thread
+    let jobs: Vec<&str> = stdout.lines().filter(|l| l.starts_with("job ")).collect();
+    assert_eq!(jobs.len(), 8, "{}", stdout);
+    assert!(
+        jobs.iter().all(|job| job.contains(": pool-worker-")),
+        "{}",
+        stdout
+    );
+    if cfg!(target_os = "linux") {
+        // A pinned worker can't be migrated mid-job
+        assert!(stdout.contains("pool-worker-0 pinned to CPU"), "{}", stdout);
+        assert!(
+            stdout.contains("Jobs that changed CPU: 0 of 8"),
+            "{}",
+            stdout
+        );
+    }
+}
@@ -35,6 +35,26 @@
 //! perf stat -e cache-misses,cache-references ./target/release/locality_demo
 //! ```
 //!
+//! ## Extension: Profiling
+//! `--profile [PATH]` records a CPU profile with pprof and writes a flamegraph
+//! SVG (default `flamegraph.svg`, see `shared/profiling.rs`):
+//! ```bash
+//! cargo run --release -- --profile
+//! # Flamegraph: flamegraph.svg (1310 samples)
+//! ```
+//! Both loops in each pair do the same additions, so any difference in width
+//! between `sum_sequential` and `sum_random`, or `sum_row_major` and
+//! `sum_column_major`, is time spent waiting on memory.
+//!
+//! ## Extension: Matrix Multiplication
+//! `--matmul [N]` runs a different experiment: C = A·B for N x N matrices
+//! (default 1024), naive, with B transposed first, and blocked in tiles of
+//! 8 to 256, each reported in GFLOP/s (see `src/matmul.rs`). The same
+//! arithmetic in a cache-friendly order runs an order of magnitude faster:
+//! ```bash
+//! cargo run --release -- --matmul
+//! ```
+//!
 //! ## Acceptance Criteria
 //! - [ ] Sequential access is measurably faster than random
 //! - [ ] Row-major is faster than column-major for 2D arrays
@@ -42,6 +62,10 @@
 //!
 //! Check solution/main.rs after completing
 
+mod matmul;
+#[path = "../../../../shared/profiling.rs"]
+mod profiling;
+
 use rand::seq::SliceRandom;
 use std::time::Instant;
 
@@ -94,6 +118,9 @@ fn sum_column_major(arr: &[i64], dim: usize) -> i64 {
 // Benchmark helpers (no modification needed)
 // ============================================================
 
+// Not inlined into main, so each benchmark gets its own frames in a --profile
+// flamegraph (pprof groups samples by the function they landed in)
+#[inline(never)]
 fn benchmark<F, T>(name: &str, mut f: F) -> T
 where
     F: FnMut() -> T,
@@ -106,6 +133,8 @@ where
 }
 
 fn main() {
+    let profiler = profiling::path_from_args().map(profiling::Profiler::start);
+
     // Make sure we're running in release mode
     #[cfg(debug_assertions)]
     {
@@ -114,11 +143,24 @@ fn main() {
         eprintln!("Please run with: cargo run --release\n");
     }
 
+    if let Some(n) = matmul::size_from_args() {
+        matmul::run(n);
+        if let Some(profiler) = profiler {
+            println!();
+            profiler.finish();
+        }
+        return;
+    }
+
     const SIZE: usize = 10_000_000;
     const ROW_COL_ITERS: usize = 8;
 
     println!("=== Cache Locality Experiment ===\n");
-    println!("Array size: {} elements ({} MB)\n", SIZE, SIZE * 8 / 1_000_000);
+    println!(
+        "Array size: {} elements ({} MB)\n",
+        SIZE,
+        SIZE * 8 / 1_000_000
+    );
 
     // Create and initialize array
     println!("Creating array...");
@@ -184,4 +226,9 @@ fn main() {
     println!("  - Column-major jumps 1000 elements (8000 bytes) each time\n");
 
     println!("Try: perf stat -e cache-misses ./target/release/locality_demo");
+
+    if let Some(profiler) = profiler {
+        println!();
+        profiler.finish();
+    }
 }
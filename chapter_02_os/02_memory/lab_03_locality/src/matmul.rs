@@ -0,0 +1,164 @@
+//! Locality in a real workload: matrix multiplication
+//!
+//! `--matmul [N]` multiplies two N x N `f64` matrices (default 1024) three
+//! ways. Each does the same 2·N³ floating-point operations; only the order
+//! of memory accesses differs:
+//!
+//! - naive `i-j-k`: the inner loop walks down a column of B, one element
+//!   per row, N·8 bytes apart. Every load is a new cache line, and once B
+//!   outgrows the cache, a miss
+//! - transposed B: copy B into Bᵀ first (N² extra work), then the inner
+//!   loop reads a row of A and a row of Bᵀ, both sequential
+//! - blocked (tiled) `i-k-j`: work on T x T tiles, so the tiles of A, B and
+//!   C being combined stay in cache while each is used T times; the
+//!   inner loop runs along a row of B and of C, and the compiler can use
+//!   SIMD on it. The sweep over T shows the trade-off: tiny tiles pay loop
+//!   overhead, tiles too big for L1/L2 miss again
+//!
+//! The matrices hold small integers, so every order of additions gives
+//! exactly the same result and the three are compared with `==`.
+
+use std::time::{Duration, Instant};
+
+/// Tile edges tried by the sweep
+const TILES: [usize; 6] = [8, 16, 32, 64, 128, 256];
+
+/// `--matmul [N]` from the command line
+pub fn size_from_args() -> Option<usize> {
+    let mut args = std::env::args().skip(1).peekable();
+    while let Some(arg) = args.next() {
+        if arg == "--matmul" {
+            let size = args.next_if(|next| !next.starts_with("--"));
+            let size = size.and_then(|s| s.parse().ok()).unwrap_or(1024);
+            // At least one tile's worth
+            return Some(size.max(TILES[0]));
+        }
+    }
+    None
+}
+
+/// C = A·B, inner loop down a column of B
+#[inline(never)]
+fn multiply_naive(a: &[f64], b: &[f64], n: usize) -> Vec<f64> {
+    let mut c = vec![0.0; n * n];
+    for i in 0..n {
+        for j in 0..n {
+            let mut sum = 0.0;
+            for k in 0..n {
+                sum += a[i * n + k] * b[k * n + j];
+            }
+            c[i * n + j] = sum;
+        }
+    }
+    c
+}
+
+/// C = A·B via Bᵀ, inner loop along two rows
+#[inline(never)]
+fn multiply_transposed(a: &[f64], b: &[f64], n: usize) -> Vec<f64> {
+    let mut bt = vec![0.0; n * n];
+    for i in 0..n {
+        for j in 0..n {
+            bt[j * n + i] = b[i * n + j];
+        }
+    }
+    let mut c = vec![0.0; n * n];
+    for i in 0..n {
+        let row = &a[i * n..(i + 1) * n];
+        for j in 0..n {
+            let col = &bt[j * n..(j + 1) * n];
+            c[i * n + j] = row.iter().zip(col).map(|(x, y)| x * y).sum();
+        }
+    }
+    c
+}
+
+/// C = A·B one `tile` x `tile` block at a time
+#[inline(never)]
+fn multiply_blocked(a: &[f64], b: &[f64], n: usize, tile: usize) -> Vec<f64> {
+    let mut c = vec![0.0; n * n];
+    for ii in (0..n).step_by(tile) {
+        for kk in (0..n).step_by(tile) {
+            for jj in (0..n).step_by(tile) {
+                let (i_end, k_end, j_end) =
+                    ((ii + tile).min(n), (kk + tile).min(n), (jj + tile).min(n));
+                for i in ii..i_end {
+                    for k in kk..k_end {
+                        let a_ik = a[i * n + k];
+                        let b_row = &b[k * n + jj..k * n + j_end];
+                        let c_row = &mut c[i * n + jj..i * n + j_end];
+                        for (c_ij, b_kj) in c_row.iter_mut().zip(b_row) {
+                            *c_ij += a_ik * b_kj;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    c
+}
+
+fn gflops(n: usize, time: Duration) -> f64 {
+    2.0 * (n as f64).powi(3) / time.as_secs_f64() / 1e9
+}
+
+fn timed(
+    name: &str,
+    n: usize,
+    baseline: Option<Duration>,
+    f: impl FnOnce() -> Vec<f64>,
+) -> (Vec<f64>, Duration) {
+    let start = Instant::now();
+    let c = f();
+    let time = start.elapsed();
+    let speedup = baseline
+        .map(|base| format!("  {:.1}x", base.as_secs_f64() / time.as_secs_f64()))
+        .unwrap_or_default();
+    println!(
+        "{:25} {:>10.1?} {:>8.2} GFLOP/s{}",
+        name,
+        time,
+        gflops(n, time),
+        speedup
+    );
+    (c, time)
+}
+
+pub fn run(n: usize) {
+    let a: Vec<f64> = (0..n * n).map(|x| (x % 7) as f64).collect();
+    let b: Vec<f64> = (0..n * n).map(|x| (x % 5) as f64 - 2.0).collect();
+
+    println!(
+        "=== Matrix Multiplication ({}x{} f64, {:.2} GFLOP each) ===\n",
+        n,
+        n,
+        2.0 * (n as f64).powi(3) / 1e9
+    );
+    println!("Each matrix: {} KiB\n", n * n * 8 / 1024);
+
+    let (naive, base) = timed("Naive (i-j-k):", n, None, || multiply_naive(&a, &b, n));
+    let (transposed, _) = timed("Transposed B:", n, Some(base), || {
+        multiply_transposed(&a, &b, n)
+    });
+    assert!(transposed == naive, "Transposed result differs!");
+
+    let mut best = (0, Duration::MAX);
+    for tile in TILES.into_iter().filter(|&tile| tile <= n) {
+        let name = format!("Blocked, tile {}:", tile);
+        let (blocked, time) = timed(&name, n, Some(base), || multiply_blocked(&a, &b, n, tile));
+        assert!(blocked == naive, "Blocked result differs (tile {})!", tile);
+        if time < best.1 {
+            best = (tile, time);
+        }
+    }
+    println!("Results match\n");
+
+    println!(
+        "Best tile: {} ({} KiB per tile), {:.1}x the naive version",
+        best.0,
+        best.0 * best.0 * 8 / 1024,
+        base.as_secs_f64() / best.1.as_secs_f64()
+    );
+    println!("Three tiles (A, B, C) of that size should fit in L1 or L2:");
+    println!("  getconf -a | grep CACHE_SIZE");
+}
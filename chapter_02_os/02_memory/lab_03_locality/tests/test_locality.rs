@@ -61,3 +61,67 @@ fn test_03_no_panics() {
         "Program should not panic"
     );
 }
+
+#[test]
+fn test_04_profile_writes_flamegraph() {
+    let path = std::env::temp_dir().join(format!("locality_{}.svg", std::process::id()));
+    let output = Command::new("cargo")
+        .args(["run", "--release", "--quiet", "--", "--profile"])
+        .arg(&path)
+        .output()
+        .expect("Failed to execute program");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    // Skip if not implemented
+    if stdout.contains("not yet implemented") {
+        return;
+    }
+
+    assert!(
+        stdout.contains("Flamegraph:"),
+        "Program should report the flamegraph: {}{}",
+        stdout,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let svg = std::fs::read_to_string(&path).expect("flamegraph file should exist");
+    let _ = std::fs::remove_file(&path);
+    assert!(svg.contains("<svg"), "Flamegraph should be an SVG");
+
+    // The slow access patterns take long enough to always be sampled
+    for function in ["sum_random", "sum_column_major"] {
+        assert!(
+            svg.contains(function),
+            "{} missing from flamegraph",
+            function
+        );
+    }
+}
+
+#[test]
+fn test_05_matmul_reports_gflops() {
+    let output = Command::new("cargo")
+        .args(["run", "--release", "--quiet", "--", "--matmul", "256"])
+        .output()
+        .expect("Failed to execute program");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    // Skip if not implemented
+    if stdout.contains("not yet implemented") {
+        return;
+    }
+
+    assert!(
+        output.status.success(),
+        "Matrix multiplication failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    for line in ["Naive (i-j-k):", "Transposed B:", "Blocked, tile 64:"] {
+        assert!(stdout.contains(line), "{} missing: {}", line, stdout);
+    }
+    assert!(stdout.contains("GFLOP/s"), "Should report GFLOP/s");
+    // Every ordering gives the same matrix (the program asserts it)
+    assert!(stdout.contains("Results match"), "Results should match");
+    assert!(stdout.contains("Best tile:"), "Should name the best tile");
+}
@@ -0,0 +1,171 @@
+//! `--bench N`: the same N connections against each mode, measured from
+//! outside. The server is a child process (this binary again, on an
+//! ephemeral port), so its `/proc/<pid>/status` counts the server's
+//! threads and memory and none of the client's.
+//!
+//! Every connection is opened and sends its message before any is read
+//! back: thread-per-connection keeps a thread alive for each one it has
+//! accepted, the pool serves W at a time while the rest wait in its
+//! queue. Echoes are
+//! then read and each connection closed in order, which frees a worker
+//! for the next one.
+
+use crate::Mode;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const MESSAGE: &[u8; 64] = b"benchmark payload: one echo per connection, 64 bytes long.......";
+
+/// How often the sampler reads the server's thread count
+const SAMPLE_EVERY: Duration = Duration::from_millis(2);
+
+const ECHO_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct Report {
+    elapsed: Duration,
+    peak_threads: u64,
+    /// VmHWM: the most resident memory the server ever had
+    peak_rss_kib: u64,
+    /// VmPeak: the most address space it ever reserved (thread stacks!)
+    peak_vm_kib: u64,
+}
+
+/// A `Key:   123 kB` line of /proc/<pid>/status
+fn status_field(pid: u32, key: &str) -> Option<u64> {
+    let status = fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix(key))?
+        .trim_start_matches(':')
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// Kills the server however the benchmark ends
+struct Server(Child);
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// Start `blocking_echo --mode M` on a free port and return its address
+fn start(mode: Mode, workers: usize) -> Result<(Server, SocketAddr), String> {
+    let exe = std::env::current_exe().map_err(|err| err.to_string())?;
+    let mut child = Command::new(exe)
+        .args(["--bind", "127.0.0.1:0", "--mode", mode.name()])
+        .args(["--workers", &workers.to_string()])
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("cannot start the server: {}", err))?;
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let server = Server(child);
+
+    let mut line = String::new();
+    stdout.read_line(&mut line).map_err(|err| err.to_string())?;
+    let addr = line
+        .strip_prefix("Listening on ")
+        .and_then(|rest| rest.split_whitespace().next())
+        .and_then(|addr| addr.parse().ok())
+        .ok_or_else(|| format!("unexpected server output: {:?}", line))?;
+    // The server logs every connection: keep the pipe from filling up
+    thread::spawn(move || {
+        let mut sink = String::new();
+        while stdout.read_line(&mut sink).unwrap_or(0) > 0 {
+            sink.clear();
+        }
+    });
+    Ok((server, addr))
+}
+
+fn measure(mode: Mode, workers: usize, connections: usize) -> Result<Report, String> {
+    let (server, addr) = start(mode, workers)?;
+    let pid = server.0.id();
+
+    let peak_threads = Arc::new(AtomicU64::new(0));
+    let done = Arc::new(AtomicBool::new(false));
+    let sampler = {
+        let peak_threads = Arc::clone(&peak_threads);
+        let done = Arc::clone(&done);
+        thread::spawn(move || {
+            while !done.load(Ordering::Relaxed) {
+                if let Some(threads) = status_field(pid, "Threads") {
+                    peak_threads.fetch_max(threads, Ordering::Relaxed);
+                }
+                thread::sleep(SAMPLE_EVERY);
+            }
+        })
+    };
+
+    let started = Instant::now();
+    let mut streams = Vec::with_capacity(connections);
+    for _ in 0..connections {
+        let mut stream = TcpStream::connect(addr).map_err(|err| format!("connect: {}", err))?;
+        stream
+            .set_read_timeout(Some(ECHO_TIMEOUT))
+            .map_err(|err| err.to_string())?;
+        stream
+            .write_all(MESSAGE)
+            .map_err(|err| format!("write: {}", err))?;
+        streams.push(stream);
+    }
+    for mut stream in streams {
+        let mut echo = [0u8; MESSAGE.len()];
+        stream
+            .read_exact(&mut echo)
+            .map_err(|err| format!("no echo: {}", err))?;
+        if &echo != MESSAGE {
+            return Err("echo differs from the message".to_string());
+        }
+        // Dropping the stream closes it: the server's read returns 0
+    }
+    let elapsed = started.elapsed();
+
+    done.store(true, Ordering::Relaxed);
+    let _ = sampler.join();
+    Ok(Report {
+        elapsed,
+        peak_threads: peak_threads.load(Ordering::Relaxed),
+        peak_rss_kib: status_field(pid, "VmHWM").unwrap_or(0),
+        peak_vm_kib: status_field(pid, "VmPeak").unwrap_or(0),
+    })
+}
+
+/// Run both modes and print one line each
+pub fn run(connections: usize, workers: usize) -> Result<(), String> {
+    println!(
+        "Benchmark: {} connections, each sends {} bytes before any echo is read",
+        connections,
+        MESSAGE.len()
+    );
+    for mode in [Mode::Thread, Mode::Pool] {
+        let report = measure(mode, workers, connections)?;
+        let workers = match mode {
+            Mode::Thread => "-".to_string(),
+            Mode::Pool => workers.to_string(),
+        };
+        println!(
+            "mode={:<6} workers={:<3} connections={} elapsed_ms={:.1} conn_per_sec={:.0} \
+             peak_threads={} peak_rss_kib={} peak_vm_kib={}",
+            mode.name(),
+            workers,
+            connections,
+            report.elapsed.as_secs_f64() * 1000.0,
+            connections as f64 / report.elapsed.as_secs_f64(),
+            report.peak_threads,
+            report.peak_rss_kib,
+            report.peak_vm_kib
+        );
+    }
+    Ok(())
+}
@@ -51,18 +51,74 @@
 //! - [ ] Echo works correctly
 //! - [ ] Clean disconnect when client closes
 //!
+//! ## Extension: IPv6 / Dual-Stack
+//! - `--bind ADDR` picks the address (default `127.0.0.1:8080`); a host name
+//!   like `localhost:8080` binds to the first address it resolves to
+//! - `--bind [::]:8080` is dual-stack: IPv4 clients arrive as
+//!   `::ffff:a.b.c.d`. `--v6only` turns that off (IPV6_V6ONLY), leaving the
+//!   IPv4 port free for another socket
+//! ```bash
+//! cargo run -- --bind [::]:8080
+//! nc -6 ::1 8080          # New connection from [::1]:54321
+//! nc -4 127.0.0.1 8080    # New connection from [::ffff:127.0.0.1]:54322
+//! cargo run -- --bind [::]:8080 --v6only   # nc -4 is refused now
+//! ```
+//!
+//! ## Extension: Thread Pool Mode and Benchmark
+//! - `--mode pool` hands each connection to a fixed pool of `--workers N`
+//!   threads (default 4, the ThreadPool from lab_02) instead of spawning
+//!   one. The thread count stops growing, but a worker is busy for as long
+//!   as its client stays connected: client N+1 is accepted, then waits in
+//!   the pool's queue, unanswered, until someone hangs up
+//! - `--mode thread` (the default) is the original thread-per-connection
+//! - `--bench N` starts the server in each mode as a child process, opens
+//!   N connections that all send before any reads, and prints the time
+//!   taken with the server's peak thread count, resident memory (VmHWM)
+//!   and address space (VmPeak: every thread reserves a stack)
+//! ```bash
+//! cargo run -- --mode pool --workers 2
+//! nc localhost 8080 &  nc localhost 8080 &  nc localhost 8080   # third waits
+//! cargo run --release -- --bench 500
+//! ```
+//!
+//! ## Extension: Command Line for Experiments
+//! - Parsed with clap. The options both echo servers share live in
+//!   `../shared/echo_args.rs`, so lab_05 takes the same flags:
+//!   `--bind`, `--port` (overrides the port in --bind), `--v6only`,
+//!   `--buffer-size` (bytes per read, default 1024), `--backlog`
+//!   (default 1024) and `--workers` (here: the pool size, default 4)
+//! - `--help` lists everything; bad values exit with status 2
+//! ```bash
+//! cargo run -- --port 9000 --buffer-size 4 --mode pool --workers 8
+//! ```
+//!
 //! Check solution/main.rs after completing
 
+mod bench;
+#[path = "../../shared/echo_args.rs"]
+mod echo_args;
+// The chapter-02 ThreadPool, not a copy. Only `quiet` and `execute` are
+// used here
+#[path = "../../../01_process_thread/lab_02_thread_pool/src/thread_pool.rs"]
+#[allow(dead_code)]
+mod thread_pool;
+
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, ValueEnum};
+use echo_args::EchoArgs;
 use std::io::{Read, Write};
-use std::net::{TcpListener, TcpStream};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::thread;
+use thread_pool::ThreadPool;
 
 // ============================================================
 // TODO: Implement the echo server
 // ============================================================
 
 /// Handle a single client connection
-fn handle_client(mut stream: TcpStream) {
+fn handle_client(mut stream: TcpStream, buffer_size: usize) {
     // TODO: Implement
     // 1. Get client address for logging
     // 2. Create a buffer for reading
@@ -75,7 +131,7 @@ fn handle_client(mut stream: TcpStream) {
         .peer_addr()
         .map(|addr| addr.to_string())
         .unwrap_or_else(|_| "<unkown>".to_string());
-    let mut buffer = [0u8; 1024];
+    let mut buffer = vec![0u8; buffer_size];
 
     loop {
         let bytes_read = match stream.read(&mut buffer) {
@@ -94,8 +150,60 @@ fn handle_client(mut stream: TcpStream) {
     }
 }
 
+/// Who runs `handle_client`
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum Mode {
+    /// A new thread per connection
+    Thread,
+    /// One of a fixed set of workers; extra connections queue
+    Pool,
+}
+
+impl Mode {
+    fn name(self) -> &'static str {
+        match self {
+            Mode::Thread => "thread",
+            Mode::Pool => "pool",
+        }
+    }
+}
+
+#[derive(Parser, Debug)]
+#[command(name = "blocking_echo")]
+#[command(about = "Blocking echo server: a thread per connection, or a thread pool")]
+struct Cli {
+    #[command(flatten)]
+    echo: EchoArgs,
+
+    /// Who serves each connection
+    #[arg(long, value_enum, default_value = "thread")]
+    mode: Mode,
+
+    /// Run the benchmark with this many connections instead of serving
+    #[arg(long, value_parser = echo_args::positive)]
+    bench: Option<usize>,
+}
+
+impl Cli {
+    /// Pool size for `--mode pool` and the benchmark
+    fn workers(&self) -> usize {
+        self.echo.workers.unwrap_or(4)
+    }
+}
+
 fn main() {
-    let addr = "127.0.0.1:8080";
+    let cli = Cli::parse();
+    if let Some(connections) = cli.bench {
+        if let Err(err) = bench::run(connections, cli.workers()) {
+            eprintln!("benchmark failed: {}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+    let addr = match cli.echo.addr() {
+        Ok(addr) => addr,
+        Err(err) => Cli::command().error(ErrorKind::ValueValidation, err).exit(),
+    };
 
     // TODO: Implement
     // 3. Loop accepting connections:
@@ -103,8 +211,26 @@ fn main() {
     //    - Spawn a thread to handle the client
     //    - (Don't wait for the thread - let it run independently)
 
-    let listener = TcpListener::bind(addr).expect("failed to bind TCP listener");
-    println!("Listening on {addr}");
+    let listener = cli.echo.listen(addr).expect("failed to bind TCP listener");
+    // The real port, if --bind asked for port 0 (the benchmark does)
+    let local = listener.local_addr().unwrap_or(addr);
+    let serving = match cli.mode {
+        Mode::Thread => "thread per connection".to_string(),
+        Mode::Pool => format!("pool of {} worker(s)", cli.workers()),
+    };
+    if addr.is_ipv6() && !cli.echo.v6only {
+        println!("Listening on {local} (dual-stack, {serving})");
+    } else {
+        println!("Listening on {local} ({serving})");
+    }
+
+    let pool = match cli.mode {
+        Mode::Thread => None,
+        Mode::Pool => Some(ThreadPool::quiet(cli.workers())),
+    };
+    // Accepted connections no worker has picked up yet
+    let waiting = Arc::new(AtomicUsize::new(0));
+    let buffer_size = cli.echo.buffer_size;
 
     // let _ = listener;
 
@@ -115,11 +241,26 @@ fn main() {
                     .peer_addr()
                     .map(|addr| addr.to_string())
                     .unwrap_or_else(|_| "<unknow>".to_string());
-                println!("New connection from {peer_addr}");
-                thread::spawn(|| handle_client(stream));
+                match &pool {
+                    None => {
+                        println!("New connection from {peer_addr}");
+                        thread::spawn(move || handle_client(stream, buffer_size));
+                    }
+                    Some(pool) => {
+                        let queued = waiting.fetch_add(1, Ordering::Relaxed) + 1;
+                        println!("New connection from {peer_addr} ({queued} waiting for a worker)");
+                        let waiting = Arc::clone(&waiting);
+                        pool.execute(move || {
+                            waiting.fetch_sub(1, Ordering::Relaxed);
+                            let worker = thread::current().name().unwrap_or("worker").to_string();
+                            println!("{worker} serving {peer_addr}");
+                            handle_client(stream, buffer_size);
+                        });
+                    }
+                }
             }
             Err(err) => {
-                eprint!("Accept error: {err}")
+                eprintln!("Accept error: {err}")
             }
         }
     }
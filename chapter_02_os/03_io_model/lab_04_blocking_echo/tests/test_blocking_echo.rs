@@ -3,7 +3,7 @@
 //! Note: These tests start a server in the background
 
 use std::io::{Read, Write};
-use std::net::TcpStream;
+use std::net::{Shutdown, TcpStream};
 use std::process::{Child, Command};
 use std::thread;
 use std::time::Duration;
@@ -111,3 +111,228 @@ fn test_03_multiple_clients() {
         "Server should accept multiple connections"
     );
 }
+
+/// Connect to `addr` and check one round trip
+fn echoes(addr: &str) -> bool {
+    let mut stream = match TcpStream::connect(addr) {
+        Ok(s) => s,
+        Err(_) => return false,
+    };
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let mut buffer = [0u8; 16];
+    stream.write_all(b"ping").is_ok()
+        && matches!(stream.read(&mut buffer), Ok(4))
+        && &buffer[..4] == b"ping"
+}
+
+#[test]
+fn test_04_dual_stack_bind() {
+    // Skip on hosts without IPv6
+    if std::net::TcpListener::bind("[::1]:0").is_err() {
+        return;
+    }
+
+    let _server = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_blocking_echo"))
+            .args(["--bind", "[::]:8091"])
+            .spawn()
+            .expect("Failed to start server"),
+    };
+    thread::sleep(Duration::from_millis(300));
+
+    assert!(echoes("[::1]:8091"), "IPv6 client should be echoed");
+    assert!(
+        echoes("127.0.0.1:8091"),
+        "IPv4 client should reach a dual-stack socket"
+    );
+}
+
+#[test]
+fn test_05_v6only_bind() {
+    if std::net::TcpListener::bind("[::1]:0").is_err() {
+        return;
+    }
+
+    let _server = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_blocking_echo"))
+            .args(["--bind", "[::]:8092", "--v6only"])
+            .spawn()
+            .expect("Failed to start server"),
+    };
+    thread::sleep(Duration::from_millis(300));
+
+    assert!(echoes("[::1]:8092"), "IPv6 client should be echoed");
+    assert!(
+        !echoes("127.0.0.1:8092"),
+        "IPv4 client should be refused with --v6only"
+    );
+}
+
+#[test]
+fn test_06_invalid_bind_args() {
+    for args in [
+        &["--bind"][..],
+        &["--bind", "not an address"],
+        &["--bind", "127.0.0.1:8093", "--v6only"],
+        &["--bogus"],
+        &["--mode", "fork"],
+        &["--workers", "0"],
+        &["--bench", "many"],
+        &["--port", "70000"],
+        &["--buffer-size", "0"],
+        &["--backlog", "0"],
+    ] {
+        let output = Command::new(env!("CARGO_BIN_EXE_blocking_echo"))
+            .args(args)
+            .output()
+            .expect("Failed to execute program");
+        assert_eq!(
+            output.status.code(),
+            Some(2),
+            "{:?} should be rejected",
+            args
+        );
+    }
+}
+
+/// Threads of a running process, from /proc (Linux only)
+fn thread_count(pid: u32) -> usize {
+    let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).unwrap();
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("Threads:"))
+        .unwrap()
+        .trim()
+        .parse()
+        .unwrap()
+}
+
+#[test]
+fn test_07_pool_mode_queues_extra_clients() {
+    let server = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_blocking_echo"))
+            .args([
+                "--bind",
+                "127.0.0.1:8166",
+                "--mode",
+                "pool",
+                "--workers",
+                "1",
+            ])
+            .spawn()
+            .expect("Failed to start server"),
+    };
+    thread::sleep(Duration::from_millis(300));
+
+    let mut first = TcpStream::connect("127.0.0.1:8166").unwrap();
+    first
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let mut buffer = [0u8; 16];
+    first.write_all(b"one").unwrap();
+    assert_eq!(first.read(&mut buffer).unwrap(), 3);
+
+    // The only worker is busy with `first`: `second` is accepted but waits
+    let mut second = TcpStream::connect("127.0.0.1:8166").unwrap();
+    second
+        .set_read_timeout(Some(Duration::from_millis(300)))
+        .unwrap();
+    second.write_all(b"two").unwrap();
+    assert!(
+        second.read(&mut buffer).is_err(),
+        "second client should wait for the worker"
+    );
+    if cfg!(target_os = "linux") {
+        assert_eq!(thread_count(server.child.id()), 2, "main + one worker");
+    }
+
+    first.shutdown(Shutdown::Both).unwrap();
+    drop(first);
+    second
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    assert_eq!(second.read(&mut buffer).unwrap(), 3);
+    assert_eq!(&buffer[..3], b"two");
+}
+
+#[test]
+fn test_08_bench_compares_modes() {
+    if !cfg!(target_os = "linux") {
+        return; // measures through /proc
+    }
+
+    let output = Command::new(env!("CARGO_BIN_EXE_blocking_echo"))
+        .args(["--bench", "200", "--workers", "2"])
+        .output()
+        .expect("Failed to execute program");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "{}{}",
+        stdout,
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let field = |mode: &str, key: &str| -> u64 {
+        let line = stdout
+            .lines()
+            .find(|line| line.starts_with(&format!("mode={}", mode)))
+            .unwrap_or_else(|| panic!("no {} line in {}", mode, stdout));
+        line.split_whitespace()
+            .find_map(|part| part.strip_prefix(key))
+            .unwrap_or_else(|| panic!("missing {} in {}", key, line))
+            .parse()
+            .unwrap()
+    };
+    assert_eq!(field("thread", "connections="), 200);
+    assert!(field("pool", "peak_threads=") <= 3, "{}", stdout);
+    assert!(field("thread", "peak_threads=") > 3, "{}", stdout);
+    assert!(field("thread", "peak_rss_kib=") > 0, "{}", stdout);
+}
+
+#[test]
+fn test_09_port_and_buffer_size_flags() {
+    let help = Command::new(env!("CARGO_BIN_EXE_blocking_echo"))
+        .arg("--help")
+        .output()
+        .expect("Failed to execute program");
+    assert!(help.status.success());
+    let help = String::from_utf8_lossy(&help.stdout);
+    for flag in [
+        "--port",
+        "--buffer-size",
+        "--backlog",
+        "--workers",
+        "--mode",
+    ] {
+        assert!(help.contains(flag), "--help should list {}", flag);
+    }
+
+    // --port wins over the port in --bind
+    let _server = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_blocking_echo"))
+            .args([
+                "--bind",
+                "127.0.0.1:1",
+                "--port",
+                "8167",
+                "--buffer-size",
+                "3",
+            ])
+            .spawn()
+            .expect("Failed to start server"),
+    };
+    thread::sleep(Duration::from_millis(300));
+
+    let mut stream = TcpStream::connect("127.0.0.1:8167").unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    stream.write_all(b"hello world").unwrap();
+    // Three bytes per read: the echo comes back in pieces, but all of it
+    let mut echoed = [0u8; 11];
+    stream.read_exact(&mut echoed).unwrap();
+    assert_eq!(&echoed, b"hello world");
+}
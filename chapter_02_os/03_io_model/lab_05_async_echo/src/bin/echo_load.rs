@@ -0,0 +1,209 @@
+//! Echo load generator
+//!
+//! Opens N connections to an echo server, waits until all of them are
+//! connected, then has each send M messages one at a time, timing every
+//! round trip. Works against any echo server in this chapter: the
+//! blocking one (both modes), this lab's, and lab_08's event loop.
+//!
+//! ```bash
+//! cargo run --release -- --port 9000 &                        # async_echo
+//! cargo run --release --bin echo_load -- --port 9000 -c 1000 -m 100
+//! (cd ../lab_04_blocking_echo && cargo run --release -- --port 9001 --mode pool) &
+//! cargo run --release --bin echo_load -- --port 9001 -c 1000 -m 100
+//! ```
+//!
+//! Against `--mode pool`, connections past the pool size wait for a free
+//! worker: their first echo takes as long as the connections ahead of
+//! them, which shows up in p99 and max, not in p50.
+
+use clap::Parser;
+use std::net::SocketAddr;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::Barrier;
+use tokio::time::timeout;
+
+#[derive(Parser, Debug)]
+#[command(name = "echo_load")]
+#[command(about = "Concurrent connections against an echo server, with latency percentiles")]
+struct Cli {
+    /// Echo server address
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    target: SocketAddr,
+
+    /// Use this port instead of the one in --target
+    #[arg(long, short)]
+    port: Option<u16>,
+
+    /// Connections open at the same time
+    #[arg(long, short, default_value_t = 100, value_parser = clap::value_parser!(u32).range(1..))]
+    connections: u32,
+
+    /// Messages each connection sends, waiting for each echo
+    #[arg(long, short, default_value_t = 100, value_parser = clap::value_parser!(u32).range(1..))]
+    messages: u32,
+
+    /// Bytes per message
+    #[arg(long, default_value_t = 64, value_parser = clap::value_parser!(u32).range(1..))]
+    size: u32,
+
+    /// Give up on a connect or an echo after this many milliseconds
+    #[arg(long, default_value_t = 5000, value_parser = clap::value_parser!(u64).range(1..))]
+    timeout_ms: u64,
+}
+
+/// What went wrong, per connection (a failed connection stops there)
+#[derive(Default)]
+struct Errors {
+    connect: usize,
+    timeout: usize,
+    io: usize,
+    mismatch: usize,
+}
+
+impl Errors {
+    fn total(&self) -> usize {
+        self.connect + self.timeout + self.io + self.mismatch
+    }
+}
+
+enum Failure {
+    Connect,
+    Timeout,
+    Io,
+    Mismatch,
+}
+
+/// Message `seq` of connection `conn`: different bytes every time, so an
+/// echo of the wrong message is caught
+fn message(conn: u32, seq: u32, size: u32) -> Vec<u8> {
+    (0..size)
+        .map(|i| b'a' + ((conn + seq + i) % 26) as u8)
+        .collect()
+}
+
+/// One connection's run: the round-trip time of each message
+async fn run_connection(
+    conn: u32,
+    cli: Arc<Cli>,
+    target: SocketAddr,
+    barrier: Arc<Barrier>,
+) -> (Vec<Duration>, Option<Failure>) {
+    let limit = Duration::from_millis(cli.timeout_ms);
+    let connected = timeout(limit, TcpStream::connect(target)).await;
+    // Everyone waits at the barrier, connected or not, so nobody blocks
+    // forever on a connection that failed
+    barrier.wait().await;
+    let mut stream = match connected {
+        Ok(Ok(stream)) => stream,
+        _ => return (Vec::new(), Some(Failure::Connect)),
+    };
+    let _ = stream.set_nodelay(true);
+
+    let mut latencies = Vec::with_capacity(cli.messages as usize);
+    let mut echo = vec![0u8; cli.size as usize];
+    for seq in 0..cli.messages {
+        let sent = message(conn, seq, cli.size);
+        let started = Instant::now();
+        let round_trip = async {
+            stream.write_all(&sent).await?;
+            stream.read_exact(&mut echo).await?;
+            Ok::<_, std::io::Error>(())
+        };
+        match timeout(limit, round_trip).await {
+            Err(_) => return (latencies, Some(Failure::Timeout)),
+            Ok(Err(_)) => return (latencies, Some(Failure::Io)),
+            Ok(Ok(())) if echo != sent => return (latencies, Some(Failure::Mismatch)),
+            Ok(Ok(())) => latencies.push(started.elapsed()),
+        }
+    }
+    (latencies, None)
+}
+
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    let index = ((sorted.len() as f64) * p / 100.0) as usize;
+    sorted[index.min(sorted.len() - 1)]
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let mut target = cli.target;
+    if let Some(port) = cli.port {
+        target.set_port(port);
+    }
+    println!(
+        "Target {}: {} connections x {} messages of {} bytes",
+        target, cli.connections, cli.messages, cli.size
+    );
+
+    let cli = Arc::new(cli);
+    // +1: main passes the barrier too, and starts the clock there
+    let barrier = Arc::new(Barrier::new(cli.connections as usize + 1));
+    let connecting = Instant::now();
+    let tasks: Vec<_> = (0..cli.connections)
+        .map(|conn| {
+            tokio::spawn(run_connection(
+                conn,
+                Arc::clone(&cli),
+                target,
+                Arc::clone(&barrier),
+            ))
+        })
+        .collect();
+    barrier.wait().await;
+    println!(
+        "All connections attempted in {:.1?}, sending",
+        connecting.elapsed()
+    );
+
+    let started = Instant::now();
+    let mut latencies = Vec::new();
+    let mut errors = Errors::default();
+    for task in tasks {
+        let (times, failure) = task.await.expect("connection task panicked");
+        latencies.extend(times);
+        match failure {
+            None => {}
+            Some(Failure::Connect) => errors.connect += 1,
+            Some(Failure::Timeout) => errors.timeout += 1,
+            Some(Failure::Io) => errors.io += 1,
+            Some(Failure::Mismatch) => errors.mismatch += 1,
+        }
+    }
+    let elapsed = started.elapsed();
+
+    let expected = cli.connections as u64 * cli.messages as u64;
+    println!(
+        "Echoed {}/{} messages in {:.2?}: {:.0} msg/s",
+        latencies.len(),
+        expected,
+        elapsed,
+        latencies.len() as f64 / elapsed.as_secs_f64()
+    );
+    if !latencies.is_empty() {
+        latencies.sort();
+        println!(
+            "latency: min={:.1?} p50={:.1?} p90={:.1?} p99={:.1?} p99.9={:.1?} max={:.1?}",
+            latencies[0],
+            percentile(&latencies, 50.0),
+            percentile(&latencies, 90.0),
+            percentile(&latencies, 99.0),
+            percentile(&latencies, 99.9),
+            latencies[latencies.len() - 1]
+        );
+    }
+    println!(
+        "errors: connect={} timeout={} io={} mismatch={}",
+        errors.connect, errors.timeout, errors.io, errors.mismatch
+    );
+
+    if errors.total() > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
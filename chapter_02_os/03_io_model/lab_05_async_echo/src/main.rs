@@ -40,17 +40,154 @@
 //! - [ ] Echo works correctly
 //! - [ ] Uses async/await (not blocking calls)
 //!
+//! ## Extension: IPv6 / Dual-Stack
+//! - Same flags as the blocking server: `--bind ADDR` (default
+//!   `127.0.0.1:8080`, host names resolve to their first address) and
+//!   `--v6only` for an IPv6 socket that refuses IPv4 clients
+//! - The socket is built with socket2, then handed to Tokio with
+//!   `TcpListener::from_std` (it must be non-blocking first)
+//! ```bash
+//! cargo run -- --bind [::]:8080
+//! nc -6 ::1 8080          # New connection from [::1]:54321
+//! nc -4 127.0.0.1 8080    # New connection from [::ffff:127.0.0.1]:54322
+//! ```
+//!
+//! ## Extension: Connection Limit and Metrics
+//! - Tasks are cheap, but each connection still holds a socket, a buffer
+//!   and a file descriptor: `--max-connections N` (default 1024) caps
+//!   them with a `tokio::sync::Semaphore`. The accept loop takes a permit
+//!   *before* accepting, so connection N+1 waits in the kernel's backlog
+//!   (connected, but not echoed) until another one closes and its task
+//!   drops the permit
+//! - every `--stats-every SECS` seconds (default 10, `0` turns it off)
+//!   the server prints a gauge: connections active now and the totals
+//! - each connection counts its bytes in and out and prints them, with
+//!   how long it lasted, when it closes
+//! ```bash
+//! cargo run -- --max-connections 2 --stats-every 1
+//! nc localhost 8080 &  nc localhost 8080 &  nc localhost 8080   # third waits
+//! ```
+//!
+//! ## Extension: Idle Timeout and Byte Limit
+//! - a permit is only as good as its release: a client that connects and
+//!   says nothing would hold one forever. Each read is wrapped in
+//!   `tokio::time::timeout`, and after `--idle-ms MS` (default 60000)
+//!   without data the server says why and closes
+//! - `--max-bytes N` (default 1 MiB) caps what one connection gets echoed;
+//!   the part of a read over the cap is not echoed, and a last line says
+//!   why the connection is closing
+//! - both count as dropped connections in the gauge
+//! ```bash
+//! cargo run -- --idle-ms 5000 --max-bytes 16
+//! nc localhost 8080        # type nothing: closed after 5s
+//! ```
+//!
+//! ## Extension: Command Line for Experiments
+//! - clap parses the flags; those shared with lab_04 (`--bind`, `--port`,
+//!   `--v6only`, `--buffer-size`, `--backlog`, `--workers`) come from
+//!   `../shared/echo_args.rs`, so one script can run both servers
+//! - `--workers N` sets Tokio's worker threads (default: one per CPU):
+//!   main builds the runtime itself instead of using `#[tokio::main]`
+//! ```bash
+//! cargo run -- --port 9000 --workers 1 --buffer-size 64
+//! grep Threads /proc/$(pgrep async_echo)/status
+//! ```
+//!
+//! ## Extension: Load Generator
+//! - `src/bin/echo_load.rs` drives any echo server in this chapter:
+//!   `-c N` connections at once, `-m M` messages each (`--size` bytes),
+//!   one round trip at a time, then prints msg/s and latency percentiles
+//!   (p50/p90/p99/p99.9/max) and the failed connections by cause
+//! - it exits with status 1 if any connection failed, so scripts notice
+//! ```bash
+//! cargo run --release -- --port 9000 &
+//! cargo run --release --bin echo_load -- --port 9000 -c 500 -m 100
+//! ```
+//!
 //! Check solution/main.rs after completing
 
+#[path = "../../shared/echo_args.rs"]
+mod echo_args;
+
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser};
+use echo_args::EchoArgs;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Semaphore;
+use tokio::time::timeout;
 
 // ============================================================
 // TODO: Implement the async echo server
 // ============================================================
 
+/// Counters shared by every connection task
+#[derive(Default)]
+struct Metrics {
+    /// Connections open right now: a gauge, it goes down too
+    active: AtomicU64,
+    accepted: AtomicU64,
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+    idle_timeouts: AtomicU64,
+    over_limit: AtomicU64,
+}
+
+impl Metrics {
+    fn report(&self, limit: usize) -> String {
+        format!(
+            "[stats] {} active (limit {}), {} accepted, {} bytes in, {} bytes out, \
+             dropped: {} idle, {} over the byte limit",
+            self.active.load(Ordering::Relaxed),
+            limit,
+            self.accepted.load(Ordering::Relaxed),
+            self.bytes_in.load(Ordering::Relaxed),
+            self.bytes_out.load(Ordering::Relaxed),
+            self.idle_timeouts.load(Ordering::Relaxed),
+            self.over_limit.load(Ordering::Relaxed)
+        )
+    }
+}
+
+/// What one connection may do before the server drops it
+#[derive(Clone, Copy)]
+struct Limits {
+    /// Longest wait for the client's next bytes
+    idle: Duration,
+    /// Most bytes echoed over the connection's life
+    max_bytes: u64,
+}
+
+/// Tell the client why it is being dropped, then close. Closing a socket
+/// with unread data sends RST, which can destroy the message before the
+/// client reads it: shut down our side and drain theirs briefly first
+async fn close_with(stream: &mut TcpStream, message: &str) {
+    if stream.write_all(message.as_bytes()).await.is_err() || stream.shutdown().await.is_err() {
+        return;
+    }
+    let mut discard = [0u8; 1024];
+    let _ = timeout(Duration::from_secs(1), async {
+        while let Ok(n) = stream.read(&mut discard).await {
+            if n == 0 {
+                break;
+            }
+        }
+    })
+    .await;
+}
+
 /// Handle a single client connection (async version)
-async fn handle_client(mut stream: TcpStream) {
+async fn handle_client(
+    mut stream: TcpStream,
+    metrics: &Metrics,
+    limits: Limits,
+    buffer_size: usize,
+) {
     // TODO: Implement (very similar to blocking version, but with .await)
     // 1. Get client address for logging
     let peer_address = stream
@@ -59,35 +196,123 @@ async fn handle_client(mut stream: TcpStream) {
         .unwrap_or_else(|_| "unknown".to_string());
 
     println!("[{}]", peer_address);
+    let started = Instant::now();
+    let (mut bytes_in, mut bytes_out) = (0u64, 0u64);
     // 2. Create a buffer for reading
-    let mut buffer = [0u8; 1024];
+    let mut buffer = vec![0u8; buffer_size];
     // 3. Loop:
-    loop {
-        //    - Read data from stream with .await
-        match stream.read(&mut buffer).await {
-            Ok(0) => {
-                println!("[{}] Dissconnected", peer_address);
-                break;
+    let outcome = loop {
+        //    - Read data from stream with .await, for at most `idle`
+        let n = match timeout(limits.idle, stream.read(&mut buffer)).await {
+            Err(_) => {
+                metrics.idle_timeouts.fetch_add(1, Ordering::Relaxed);
+                let message = format!(
+                    "No data for {:?}, closing the connection. Bye!\n",
+                    limits.idle
+                );
+                close_with(&mut stream, &message).await;
+                break "Idle timeout";
             }
-            Ok(n) => {
-                if let Err(err) = stream.write_all(&buffer[..n]).await {
-                    println!("[{}] Write erro{}", peer_address, err);
-                }
-            }
-            Err(err) => {
-                println!("[{}] Read erro{}", peer_address, err);
+            Ok(Ok(0)) => break "Disconnected",
+            Ok(Ok(n)) => n,
+            Ok(Err(err)) => {
+                println!("[{}] Read error: {}", peer_address, err);
+                break "Read error";
             }
+        };
+        bytes_in += n as u64;
+        metrics.bytes_in.fetch_add(n as u64, Ordering::Relaxed);
+
+        // Echo what still fits under the cap
+        let room = (limits.max_bytes - bytes_out).min(n as u64) as usize;
+        if let Err(err) = stream.write_all(&buffer[..room]).await {
+            println!("[{}] Write error: {}", peer_address, err);
+            break "Write error";
+        }
+        bytes_out += room as u64;
+        metrics.bytes_out.fetch_add(room as u64, Ordering::Relaxed);
+        if room < n {
+            metrics.over_limit.fetch_add(1, Ordering::Relaxed);
+            let message = format!(
+                "\nThat's the {}-byte limit for one connection, closing it. Bye!\n",
+                limits.max_bytes
+            );
+            close_with(&mut stream, &message).await;
+            break "Byte limit reached";
         }
         //    - If read returns 0, client disconnected - break
         //    - Echo data back with write_all().await
-        // 4. Log when connection closes
+    };
+    // 4. Log when connection closes
+    println!(
+        "[{}] {} after {:.1?}: {} bytes in, {} bytes out",
+        peer_address,
+        outcome,
+        started.elapsed(),
+        bytes_in,
+        bytes_out
+    );
+}
+
+/// The shared listener setup, handed to Tokio (non-blocking first)
+fn bind(echo: &EchoArgs, addr: SocketAddr) -> io::Result<TcpListener> {
+    let listener = echo.listen(addr)?;
+    listener.set_nonblocking(true)?;
+    TcpListener::from_std(listener)
+}
+
+#[derive(Parser, Debug)]
+#[command(name = "async_echo")]
+#[command(about = "Async echo server on Tokio: a task per connection")]
+struct Cli {
+    #[command(flatten)]
+    echo: EchoArgs,
+
+    /// Connections served at once; more wait in the listen backlog
+    #[arg(long, default_value_t = 1024, value_parser = echo_args::positive)]
+    max_connections: usize,
+
+    /// Seconds between gauge lines (0: no periodic gauge)
+    #[arg(long, default_value_t = 10)]
+    stats_every: u64,
+
+    /// Longest wait for a client's next bytes, in milliseconds
+    #[arg(long, default_value_t = 60_000, value_parser = clap::value_parser!(u64).range(1..))]
+    idle_ms: u64,
+
+    /// Most bytes echoed over one connection's life
+    #[arg(long, default_value_t = 1024 * 1024, value_parser = clap::value_parser!(u64).range(1..))]
+    max_bytes: u64,
+}
+
+impl Cli {
+    fn limits(&self) -> Limits {
+        Limits {
+            idle: Duration::from_millis(self.idle_ms),
+            max_bytes: self.max_bytes,
+        }
     }
 }
 
-#[tokio::main]
-async fn main() {
-    let addr = "127.0.0.1:8080";
+/// `--workers` sizes Tokio's thread pool (default: one per CPU)
+fn main() {
+    let cli = Cli::parse();
+    let addr = match cli.echo.addr() {
+        Ok(addr) => addr,
+        Err(err) => Cli::command().error(ErrorKind::ValueValidation, err).exit(),
+    };
+    let mut runtime = tokio::runtime::Builder::new_multi_thread();
+    runtime.enable_all();
+    if let Some(workers) = cli.echo.workers {
+        runtime.worker_threads(workers);
+    }
+    runtime
+        .build()
+        .expect("failed to start the Tokio runtime")
+        .block_on(serve(cli, addr));
+}
 
+async fn serve(cli: Cli, addr: SocketAddr) {
     // TODO: Implement
     // 1. Create TcpListener bound to addr (use .await)
     // 2. Print "Listening on {addr}"
@@ -96,17 +321,70 @@ async fn main() {
     //    - Spawn an async task with tokio::spawn() to handle the client
     //    - (The task runs concurrently, not in a new thread)
 
-    let listener = TcpListener::bind(addr).await.expect("Failed to bind");
+    let listener = bind(&cli.echo, addr).expect("Failed to bind");
+    // The real port, if --port 0 asked for any free one
+    let local = listener.local_addr().unwrap_or(addr);
 
     println!("Async Echo Server (Tokio)");
-    println!("Listening on {}", addr);
+    if addr.is_ipv6() && !cli.echo.v6only {
+        println!("Listening on {} (dual-stack)", local);
+    } else {
+        println!("Listening on {}", local);
+    }
+    println!(
+        "Serving at most {} connections at once",
+        cli.max_connections
+    );
+    println!(
+        "Dropping connections idle for {:?} or past {} bytes echoed",
+        cli.limits().idle,
+        cli.max_bytes
+    );
+
+    let metrics = Arc::new(Metrics::default());
+    if cli.stats_every > 0 {
+        let metrics = Arc::clone(&metrics);
+        let limit = cli.max_connections;
+        let mut ticks = tokio::time::interval(Duration::from_secs(cli.stats_every));
+        ticks.tick().await;
+        tokio::spawn(async move {
+            loop {
+                ticks.tick().await;
+                println!("{}", metrics.report(limit));
+            }
+        });
+    }
 
+    let limit = Arc::new(Semaphore::new(cli.max_connections));
     loop {
+        // A permit first, then a connection: at the limit, new clients
+        // wait in the backlog instead of being accepted and starved
+        let permit = match Arc::clone(&limit).try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                println!(
+                    "At the limit ({} connections): waiting for one to close",
+                    cli.max_connections
+                );
+                Arc::clone(&limit)
+                    .acquire_owned()
+                    .await
+                    .expect("semaphore is never closed")
+            }
+        };
         match listener.accept().await {
             Ok((_socket, addr)) => {
                 println!("New connection from {}", addr);
+                metrics.accepted.fetch_add(1, Ordering::Relaxed);
+                metrics.active.fetch_add(1, Ordering::Relaxed);
+                let metrics = Arc::clone(&metrics);
+                let limits = cli.limits();
+                let buffer_size = cli.echo.buffer_size;
                 tokio::spawn(async move {
-                    handle_client(_socket).await;
+                    handle_client(_socket, &metrics, limits, buffer_size).await;
+                    metrics.active.fetch_sub(1, Ordering::Relaxed);
+                    // Frees the slot for the next connection
+                    drop(permit);
                 });
             }
             Err(err) => {
@@ -2,7 +2,7 @@
 
 use std::io::{Read, Write};
 use std::net::TcpStream;
-use std::process::{Child, Command};
+use std::process::{Child, Command, Stdio};
 use std::thread;
 use std::time::Duration;
 
@@ -104,3 +104,283 @@ fn test_03_multiple_clients() {
         "Server should accept multiple connections"
     );
 }
+
+/// Connect to `addr` and check one round trip
+fn echoes(addr: &str) -> bool {
+    let mut stream = match TcpStream::connect(addr) {
+        Ok(s) => s,
+        Err(_) => return false,
+    };
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let mut buffer = [0u8; 16];
+    stream.write_all(b"ping").is_ok()
+        && matches!(stream.read(&mut buffer), Ok(4))
+        && &buffer[..4] == b"ping"
+}
+
+#[test]
+fn test_04_dual_stack_bind() {
+    // Skip on hosts without IPv6
+    if std::net::TcpListener::bind("[::1]:0").is_err() {
+        return;
+    }
+
+    let _server = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_async_echo"))
+            .args(["--bind", "[::]:8101"])
+            .spawn()
+            .expect("Failed to start server"),
+    };
+    thread::sleep(Duration::from_millis(300));
+
+    assert!(echoes("[::1]:8101"), "IPv6 client should be echoed");
+    assert!(
+        echoes("127.0.0.1:8101"),
+        "IPv4 client should reach a dual-stack socket"
+    );
+}
+
+#[test]
+fn test_05_v6only_bind() {
+    if std::net::TcpListener::bind("[::1]:0").is_err() {
+        return;
+    }
+
+    let _server = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_async_echo"))
+            .args(["--bind", "[::]:8102", "--v6only"])
+            .spawn()
+            .expect("Failed to start server"),
+    };
+    thread::sleep(Duration::from_millis(300));
+
+    assert!(echoes("[::1]:8102"), "IPv6 client should be echoed");
+    assert!(
+        !echoes("127.0.0.1:8102"),
+        "IPv4 client should be refused with --v6only"
+    );
+}
+
+#[test]
+fn test_06_invalid_bind_args() {
+    for args in [
+        &["--bind"][..],
+        &["--bind", "not an address"],
+        &["--bind", "127.0.0.1:8103", "--v6only"],
+        &["--bogus"],
+        &["--max-connections", "0"],
+        &["--max-connections"],
+        &["--stats-every", "soon"],
+        &["--idle-ms", "0"],
+        &["--max-bytes", "-1"],
+        &["--workers", "0"],
+        &["--buffer-size", "big"],
+        &["--port", "-1"],
+    ] {
+        let output = Command::new(env!("CARGO_BIN_EXE_async_echo"))
+            .args(args)
+            .output()
+            .expect("Failed to execute program");
+        assert_eq!(
+            output.status.code(),
+            Some(2),
+            "{:?} should be rejected",
+            args
+        );
+    }
+}
+
+/// One round trip on an open connection; false if no echo within `wait`
+fn round_trip(stream: &mut TcpStream, msg: &[u8], wait: Duration) -> bool {
+    stream.set_read_timeout(Some(wait)).unwrap();
+    let mut buffer = [0u8; 64];
+    stream.write_all(msg).is_ok()
+        && matches!(stream.read(&mut buffer), Ok(n) if &buffer[..n] == msg)
+}
+
+#[test]
+fn test_07_connection_limit_and_metrics() {
+    let mut server = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_async_echo"))
+            .args(["--bind", "127.0.0.1:8160", "--max-connections", "2"])
+            .args(["--stats-every", "1"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("Failed to start server"),
+    };
+    thread::sleep(Duration::from_millis(300));
+
+    let mut first = TcpStream::connect("127.0.0.1:8160").unwrap();
+    let mut second = TcpStream::connect("127.0.0.1:8160").unwrap();
+    assert!(round_trip(&mut first, b"one", Duration::from_secs(2)));
+    assert!(round_trip(&mut second, b"two", Duration::from_secs(2)));
+
+    // The kernel completes the handshake, but the server won't accept it
+    let mut third = TcpStream::connect("127.0.0.1:8160").unwrap();
+    assert!(
+        !round_trip(&mut third, b"three", Duration::from_millis(500)),
+        "a third connection should wait while two are open"
+    );
+    // Let a gauge report go out while two connections are open
+    thread::sleep(Duration::from_millis(1200));
+
+    // Closing one frees its permit; the waiting one is served
+    drop(first);
+    let mut buffer = [0u8; 64];
+    third
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let n = third.read(&mut buffer).unwrap();
+    assert_eq!(
+        &buffer[..n],
+        b"three",
+        "the waiting connection's data was echoed"
+    );
+    drop(second);
+    drop(third);
+    thread::sleep(Duration::from_millis(200));
+
+    let _ = server.child.kill();
+    let mut stdout = String::new();
+    server
+        .child
+        .stdout
+        .take()
+        .unwrap()
+        .read_to_string(&mut stdout)
+        .unwrap();
+    assert!(
+        stdout.contains("At the limit (2 connections)"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("[stats] 2 active (limit 2)"), "{}", stdout);
+    assert!(stdout.contains("3 bytes in, 3 bytes out"), "{}", stdout);
+    assert!(stdout.contains("5 bytes in, 5 bytes out"), "{}", stdout);
+    assert_eq!(
+        stdout.matches("Disconnected after").count(),
+        3,
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn test_08_idle_timeout_and_byte_limit() {
+    let _server = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_async_echo"))
+            .args(["--bind", "127.0.0.1:8161", "--idle-ms", "400"])
+            .args(["--max-bytes", "10", "--stats-every", "0"])
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Failed to start server"),
+    };
+    thread::sleep(Duration::from_millis(300));
+
+    // Silent client: told why, then closed
+    let mut silent = TcpStream::connect("127.0.0.1:8161").unwrap();
+    silent
+        .set_read_timeout(Some(Duration::from_secs(3)))
+        .unwrap();
+    let started = std::time::Instant::now();
+    let mut reply = String::new();
+    silent.read_to_string(&mut reply).unwrap();
+    assert!(reply.starts_with("No data for 400ms"), "{:?}", reply);
+    assert!(started.elapsed() >= Duration::from_millis(400));
+
+    // Chatty client: echoed up to 10 bytes, then the limit message
+    let mut chatty = TcpStream::connect("127.0.0.1:8161").unwrap();
+    assert!(round_trip(&mut chatty, b"12345678", Duration::from_secs(2)));
+    chatty.write_all(b"ABCDEF").unwrap();
+    chatty
+        .set_read_timeout(Some(Duration::from_secs(3)))
+        .unwrap();
+    let mut reply = String::new();
+    chatty.read_to_string(&mut reply).unwrap();
+    assert!(
+        reply.starts_with("AB\nThat's the 10-byte limit"),
+        "{:?}",
+        reply
+    );
+
+    // Data keeps the idle timer from firing
+    let mut steady = TcpStream::connect("127.0.0.1:8161").unwrap();
+    for _ in 0..4 {
+        thread::sleep(Duration::from_millis(200));
+        assert!(round_trip(&mut steady, b"x", Duration::from_secs(2)));
+    }
+}
+
+#[test]
+fn test_09_shared_flags() {
+    let _server = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_async_echo"))
+            .args(["--port", "8168", "--workers", "1", "--buffer-size", "3"])
+            .spawn()
+            .expect("Failed to start server"),
+    };
+    thread::sleep(Duration::from_millis(300));
+
+    let mut stream = TcpStream::connect("127.0.0.1:8168").unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    stream.write_all(b"hello world").unwrap();
+    let mut echoed = [0u8; 11];
+    stream.read_exact(&mut echoed).unwrap();
+    assert_eq!(&echoed, b"hello world");
+}
+
+fn echo_load(args: &[&str]) -> (String, Option<i32>) {
+    let output = Command::new(env!("CARGO_BIN_EXE_echo_load"))
+        .args(args)
+        .output()
+        .expect("Failed to execute echo_load");
+    (
+        String::from_utf8_lossy(&output.stdout).to_string(),
+        output.status.code(),
+    )
+}
+
+#[test]
+fn test_10_echo_load_reports_percentiles() {
+    let _server = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_async_echo"))
+            .args(["--port", "8169", "--stats-every", "0"])
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Failed to start server"),
+    };
+    thread::sleep(Duration::from_millis(300));
+
+    let (stdout, code) = echo_load(&["--port", "8169", "-c", "20", "-m", "10", "--size", "32"]);
+    assert_eq!(code, Some(0), "{}", stdout);
+    assert!(stdout.contains("Echoed 200/200 messages"), "{}", stdout);
+    for field in ["p50=", "p90=", "p99=", "p99.9=", "max="] {
+        assert!(stdout.contains(field), "missing {} in {}", field, stdout);
+    }
+    assert!(
+        stdout.contains("errors: connect=0 timeout=0 io=0 mismatch=0"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn test_11_echo_load_counts_failures() {
+    // Nothing listens on 8170
+    let (stdout, code) = echo_load(&["--port", "8170", "-c", "3", "-m", "1"]);
+    assert_eq!(code, Some(1), "{}", stdout);
+    assert!(stdout.contains("errors: connect=3"), "{}", stdout);
+
+    for args in [
+        &["-c", "0"][..],
+        &["--size", "big"],
+        &["--target", "nowhere"],
+    ] {
+        let (_, code) = echo_load(args);
+        assert_eq!(code, Some(2), "{:?} should be rejected", args);
+    }
+}
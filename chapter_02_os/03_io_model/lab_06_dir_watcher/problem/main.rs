@@ -0,0 +1,233 @@
+//! Lab 6: Directory Watcher (inotify)
+//!
+//! ## Goal
+//! Watch a directory tree for changes using inotify, debounce bursts of
+//! events, and trigger an action once per burst - the core of tools like
+//! `cargo watch`, file sync daemons and search re-indexers
+//!
+//! ## Requirements
+//! 1. `dir_watcher <dir> [--debounce-ms N] [--exec CMD]`
+//! 2. Watch `<dir>` and every subdirectory for create/modify/delete/move events
+//! 3. Keep the watch set in sync: new directories get watched (recursively),
+//!    directories moved out or deleted are dropped
+//! 4. Debounce: collect events until none arrive for `--debounce-ms` (default 200),
+//!    then print one line per changed path and `-- batch: N changes`
+//! 5. Merge events per path: created+modified = created, created+deleted = nothing
+//! 6. `--exec CMD` runs `sh -c CMD` after each batch with the changed paths as `"$@"`
+//!
+//! ## Expected Behavior
+//! ```
+//! $ dir_watcher ./notes --exec 'echo reindex $#'
+//! watching ./notes (3 directories)
+//! created ./notes/todo.md
+//! modified ./notes/week1/log.md
+//! -- batch: 2 changes
+//! reindex 2
+//! ```
+//!
+//! ## Hints
+//! - `nix::sys::inotify::Inotify::init` / `add_watch` / `read_events`
+//! - inotify is not recursive: one `add_watch` per directory, keep a wd -> path map
+//! - Events for a new directory's files can fire *before* you watch it:
+//!   scan the directory right after `add_watch`
+//! - The inotify fd works with `poll()`: use the timeout to detect the quiet period
+//! - `IN_IGNORED` arrives when the kernel drops a watch (its directory was deleted)
+//!
+//! ## Verification
+//! ```bash
+//! cargo test
+//! mkdir -p /tmp/w && cargo run -- /tmp/w --exec 'echo changed: "$@"'
+//! # In another terminal:
+//! for i in $(seq 100); do echo $i >> /tmp/w/burst.txt; done   # one batch, not 100
+//! mkdir -p /tmp/w/a/b && touch /tmp/w/a/b/deep.txt
+//! cat /proc/$(pgrep dir_watcher)/fdinfo/3                     # one line per watch
+//! ```
+//!
+//! ## Acceptance Criteria
+//! - [ ] Changes anywhere in the tree are reported, including new subdirectories
+//! - [ ] A burst of writes produces a single batch
+//! - [ ] The action runs once per batch with the changed paths
+//! - [ ] Can explain why inotify needs one watch per directory
+//!
+//! Warning: Requires Linux (inotify)
+//!
+//! Check solution/main.rs after completing
+
+use anyhow::{bail, Context, Result};
+use nix::poll::{poll, PollFd, PollFlags};
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify, InotifyEvent, WatchDescriptor};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+/// `dir_watcher <dir> [--debounce-ms N] [--exec CMD]`
+struct Config {
+    root: PathBuf,
+    debounce: Duration,
+    exec: Option<String>,
+}
+
+fn parse_args(args: Vec<String>) -> Result<Config> {
+    let mut root = None;
+    let mut debounce = Duration::from_millis(200);
+    let mut exec = None;
+    let mut iter = args.into_iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--debounce-ms" => {
+                let ms = iter.next().context("--debounce-ms needs a value")?;
+                debounce = Duration::from_millis(ms.parse().context("invalid --debounce-ms")?);
+            }
+            "--exec" => exec = Some(iter.next().context("--exec needs a command")?),
+            s if s.starts_with("--") => bail!("unknown option '{}'", s),
+            _ => root = Some(PathBuf::from(arg)),
+        }
+    }
+
+    Ok(Config {
+        root: root.context("usage: dir_watcher <dir> [--debounce-ms N] [--exec CMD]")?,
+        debounce,
+        exec,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Change {
+    Created,
+    Modified,
+    Deleted,
+}
+
+impl Change {
+    fn label(self) -> &'static str {
+        match self {
+            Change::Created => "created",
+            Change::Modified => "modified",
+            Change::Deleted => "deleted",
+        }
+    }
+}
+
+/// Fold a new change into the one already pending for the same path.
+/// None means the two cancel out (created, then deleted within one burst).
+fn merge(pending: Option<Change>, new: Change) -> Option<Change> {
+    // TODO: created+modified -> created, created+deleted -> None,
+    // deleted+created -> modified, otherwise the new change wins
+    todo!("Implement merge")
+}
+
+/// Events we care about on every directory in the tree
+fn watch_mask() -> AddWatchFlags {
+    AddWatchFlags::IN_CREATE
+        | AddWatchFlags::IN_MODIFY
+        | AddWatchFlags::IN_DELETE
+        | AddWatchFlags::IN_MOVED_FROM
+        | AddWatchFlags::IN_MOVED_TO
+        | AddWatchFlags::IN_ONLYDIR
+}
+
+/// inotify is not recursive: one watch per directory, and we keep the
+/// wd -> path map ourselves because events only carry the wd and a name
+struct Watcher {
+    inotify: Inotify,
+    root: PathBuf,
+    dirs: HashMap<WatchDescriptor, PathBuf>,
+}
+
+impl Watcher {
+    fn new(root: &Path) -> Result<Self> {
+        let mut watcher = Self {
+            inotify: Inotify::init(InitFlags::IN_CLOEXEC).context("inotify_init1")?,
+            root: root.to_path_buf(),
+            dirs: HashMap::new(),
+        };
+        watcher.add_recursive(root);
+        Ok(watcher)
+    }
+
+    /// Watch `dir` and every directory below it. Files already inside are
+    /// returned: they appeared before the watch existed, so no event will
+    /// ever report them.
+    fn add_recursive(&mut self, dir: &Path) -> Vec<PathBuf> {
+        // TODO: Implement
+        //
+        // Suggested steps:
+        // 1. Keep a stack of directories, starting with `dir`
+        // 2. add_watch(dir, watch_mask()) and remember wd -> dir in self.dirs
+        // 3. read_dir: push subdirectories onto the stack, collect every entry
+        todo!("Implement Watcher::add_recursive")
+    }
+
+    /// Stop watching `dir` and its subdirectories (it was moved out of the tree)
+    fn remove_recursive(&mut self, dir: &Path) {
+        // TODO: rm_watch every wd whose path starts with `dir`
+        todo!("Implement Watcher::remove_recursive")
+    }
+
+    /// Translate one raw event into (path, change) pairs, keeping the
+    /// watch set in sync as directories come and go
+    fn handle(&mut self, event: InotifyEvent) -> Vec<(PathBuf, Change)> {
+        // TODO: Implement
+        //
+        // Suggested steps:
+        // 1. IN_Q_OVERFLOW: drop all watches and rescan from self.root
+        // 2. IN_IGNORED: forget event.wd
+        // 3. Build the path from self.dirs[wd] + event.name
+        // 4. IN_CREATE/IN_MOVED_TO -> Created (+ add_recursive if IN_ISDIR)
+        //    IN_DELETE/IN_MOVED_FROM -> Deleted (+ remove_recursive if IN_ISDIR)
+        //    IN_MODIFY -> Modified
+        todo!("Implement Watcher::handle")
+    }
+}
+
+/// Print one debounced batch and run the action with the paths as "$@"
+fn flush(batch: &BTreeMap<PathBuf, Change>, exec: Option<&str>) {
+    let mut out = std::io::stdout().lock();
+    for (path, change) in batch {
+        let _ = writeln!(out, "{} {}", change.label(), path.display());
+    }
+    let _ = writeln!(out, "-- batch: {} changes", batch.len());
+    let _ = out.flush();
+    drop(out);
+
+    if let Some(cmd) = exec {
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(cmd)
+            .arg("dir_watcher")
+            .args(batch.keys())
+            .status();
+        match status {
+            Ok(s) if !s.success() => eprintln!("dir_watcher: action exited with {}", s),
+            Err(e) => eprintln!("dir_watcher: cannot run action: {}", e),
+            _ => {}
+        }
+    }
+}
+
+fn run(config: Config) -> Result<()> {
+    if !config.root.is_dir() {
+        bail!("{} is not a directory", config.root.display());
+    }
+
+    // TODO: Implement the event loop
+    //
+    // Suggested steps:
+    // 1. Watcher::new(&config.root), print "watching <dir> (N directories)"
+    // 2. Loop: poll() the inotify fd with timeout -1 when nothing is pending,
+    //    otherwise the time left until the debounce interval has passed
+    // 3. Timeout -> flush(&pending, ...) and clear
+    // 4. Readable -> read_events(), watcher.handle() each, merge() into pending
+    todo!("Implement run")
+}
+
+fn main() {
+    let result = parse_args(std::env::args().skip(1).collect()).and_then(run);
+    if let Err(e) = result {
+        eprintln!("dir_watcher: {:#}", e);
+        std::process::exit(1);
+    }
+}
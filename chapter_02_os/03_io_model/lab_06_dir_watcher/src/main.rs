@@ -0,0 +1,357 @@
+//! Lab 6: Directory Watcher (inotify)
+//!
+//! ## Goal
+//! Watch a directory tree for changes using inotify, debounce bursts of
+//! events, and trigger an action once per burst - the core of tools like
+//! `cargo watch`, file sync daemons and search re-indexers
+//!
+//! ## Requirements
+//! 1. `dir_watcher <dir> [--debounce-ms N] [--exec CMD]`
+//! 2. Watch `<dir>` and every subdirectory for create/modify/delete/move events
+//! 3. Keep the watch set in sync: new directories get watched (recursively),
+//!    directories moved out or deleted are dropped
+//! 4. Debounce: collect events until none arrive for `--debounce-ms` (default 200),
+//!    then print one line per changed path and `-- batch: N changes`
+//! 5. Merge events per path: created+modified = created, created+deleted = nothing
+//! 6. `--exec CMD` runs `sh -c CMD` after each batch with the changed paths as `"$@"`
+//!
+//! ## Expected Behavior
+//! ```
+//! $ dir_watcher ./notes --exec 'echo reindex $#'
+//! watching ./notes (3 directories)
+//! created ./notes/todo.md
+//! modified ./notes/week1/log.md
+//! -- batch: 2 changes
+//! reindex 2
+//! ```
+//!
+//! ## Hints
+//! - `nix::sys::inotify::Inotify::init` / `add_watch` / `read_events`
+//! - inotify is not recursive: one `add_watch` per directory, keep a wd -> path map
+//! - Events for a new directory's files can fire *before* you watch it:
+//!   scan the directory right after `add_watch`
+//! - The inotify fd works with `poll()`: use the timeout to detect the quiet period
+//! - `IN_IGNORED` arrives when the kernel drops a watch (its directory was deleted)
+//!
+//! ## Verification
+//! ```bash
+//! cargo test
+//! mkdir -p /tmp/w && cargo run -- /tmp/w --exec 'echo changed: "$@"'
+//! # In another terminal:
+//! for i in $(seq 100); do echo $i >> /tmp/w/burst.txt; done   # one batch, not 100
+//! mkdir -p /tmp/w/a/b && touch /tmp/w/a/b/deep.txt
+//! cat /proc/$(pgrep dir_watcher)/fdinfo/3                     # one line per watch
+//! ```
+//!
+//! ## Acceptance Criteria
+//! - [ ] Changes anywhere in the tree are reported, including new subdirectories
+//! - [ ] A burst of writes produces a single batch
+//! - [ ] The action runs once per batch with the changed paths
+//! - [ ] Can explain why inotify needs one watch per directory
+//!
+//! Warning: Requires Linux (inotify)
+//!
+//! Check solution/main.rs after completing
+
+use anyhow::{bail, Context, Result};
+use nix::poll::{poll, PollFd, PollFlags};
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify, InotifyEvent, WatchDescriptor};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+/// `dir_watcher <dir> [--debounce-ms N] [--exec CMD]`
+struct Config {
+    root: PathBuf,
+    debounce: Duration,
+    exec: Option<String>,
+}
+
+fn parse_args(args: Vec<String>) -> Result<Config> {
+    let mut root = None;
+    let mut debounce = Duration::from_millis(200);
+    let mut exec = None;
+    let mut iter = args.into_iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--debounce-ms" => {
+                let ms = iter.next().context("--debounce-ms needs a value")?;
+                debounce = Duration::from_millis(ms.parse().context("invalid --debounce-ms")?);
+            }
+            "--exec" => exec = Some(iter.next().context("--exec needs a command")?),
+            s if s.starts_with("--") => bail!("unknown option '{}'", s),
+            _ => root = Some(PathBuf::from(arg)),
+        }
+    }
+
+    Ok(Config {
+        root: root.context("usage: dir_watcher <dir> [--debounce-ms N] [--exec CMD]")?,
+        debounce,
+        exec,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Change {
+    Created,
+    Modified,
+    Deleted,
+}
+
+impl Change {
+    fn label(self) -> &'static str {
+        match self {
+            Change::Created => "created",
+            Change::Modified => "modified",
+            Change::Deleted => "deleted",
+        }
+    }
+}
+
+/// Fold a new change into the one already pending for the same path.
+/// None means the two cancel out (created, then deleted within one burst).
+fn merge(pending: Option<Change>, new: Change) -> Option<Change> {
+    match (pending, new) {
+        (Some(Change::Created), Change::Modified) => Some(Change::Created),
+        (Some(Change::Created), Change::Deleted) => None,
+        (Some(Change::Deleted), Change::Created) => Some(Change::Modified),
+        (_, new) => Some(new),
+    }
+}
+
+/// Events we care about on every directory in the tree
+fn watch_mask() -> AddWatchFlags {
+    AddWatchFlags::IN_CREATE
+        | AddWatchFlags::IN_MODIFY
+        | AddWatchFlags::IN_DELETE
+        | AddWatchFlags::IN_MOVED_FROM
+        | AddWatchFlags::IN_MOVED_TO
+        | AddWatchFlags::IN_ONLYDIR
+}
+
+/// inotify is not recursive: one watch per directory, and we keep the
+/// wd -> path map ourselves because events only carry the wd and a name
+struct Watcher {
+    inotify: Inotify,
+    root: PathBuf,
+    dirs: HashMap<WatchDescriptor, PathBuf>,
+}
+
+impl Watcher {
+    fn new(root: &Path) -> Result<Self> {
+        let mut watcher = Self {
+            inotify: Inotify::init(InitFlags::IN_CLOEXEC).context("inotify_init1")?,
+            root: root.to_path_buf(),
+            dirs: HashMap::new(),
+        };
+        watcher.add_recursive(root);
+        Ok(watcher)
+    }
+
+    /// Watch `dir` and every directory below it. Files already inside are
+    /// returned: they appeared before the watch existed, so no event will
+    /// ever report them.
+    fn add_recursive(&mut self, dir: &Path) -> Vec<PathBuf> {
+        let mut found = Vec::new();
+        let mut stack = vec![dir.to_path_buf()];
+
+        while let Some(dir) = stack.pop() {
+            match self.inotify.add_watch(&dir, watch_mask()) {
+                Ok(wd) => {
+                    self.dirs.insert(wd, dir.clone());
+                }
+                // Already gone again: nothing to watch
+                Err(_) => continue,
+            }
+            let entries = match std::fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                    stack.push(path.clone());
+                }
+                found.push(path);
+            }
+        }
+
+        found
+    }
+
+    /// Stop watching `dir` and its subdirectories (it was moved out of the tree)
+    fn remove_recursive(&mut self, dir: &Path) {
+        let gone: Vec<WatchDescriptor> = self
+            .dirs
+            .iter()
+            .filter(|(_, path)| path.starts_with(dir))
+            .map(|(wd, _)| *wd)
+            .collect();
+        for wd in gone {
+            let _ = self.inotify.rm_watch(wd);
+            self.dirs.remove(&wd);
+        }
+    }
+
+    /// Translate one raw event into (path, change) pairs, keeping the
+    /// watch set in sync as directories come and go
+    fn handle(&mut self, event: InotifyEvent) -> Vec<(PathBuf, Change)> {
+        let mask = event.mask;
+
+        if mask.contains(AddWatchFlags::IN_Q_OVERFLOW) {
+            // The kernel queue overflowed: events were lost, so start over
+            eprintln!("dir_watcher: event queue overflow, rescanning");
+            for wd in self.dirs.keys() {
+                let _ = self.inotify.rm_watch(*wd);
+            }
+            self.dirs.clear();
+            let root = self.root.clone();
+            return self
+                .add_recursive(&root)
+                .into_iter()
+                .map(|path| (path, Change::Modified))
+                .collect();
+        }
+        if mask.contains(AddWatchFlags::IN_IGNORED) {
+            // Directory deleted: the kernel already dropped the watch
+            self.dirs.remove(&event.wd);
+            return Vec::new();
+        }
+
+        let (dir, name) = match (self.dirs.get(&event.wd), &event.name) {
+            (Some(dir), Some(name)) => (dir, name),
+            _ => return Vec::new(),
+        };
+        let path = dir.join(name);
+        let is_dir = mask.contains(AddWatchFlags::IN_ISDIR);
+
+        if mask.intersects(AddWatchFlags::IN_CREATE | AddWatchFlags::IN_MOVED_TO) {
+            let mut changes = vec![(path.clone(), Change::Created)];
+            if is_dir {
+                let inside = self.add_recursive(&path);
+                changes.extend(inside.into_iter().map(|p| (p, Change::Created)));
+            }
+            changes
+        } else if mask.intersects(AddWatchFlags::IN_DELETE | AddWatchFlags::IN_MOVED_FROM) {
+            if is_dir {
+                self.remove_recursive(&path);
+            }
+            vec![(path, Change::Deleted)]
+        } else if mask.contains(AddWatchFlags::IN_MODIFY) {
+            vec![(path, Change::Modified)]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+/// Print one debounced batch and run the action with the paths as "$@"
+fn flush(batch: &BTreeMap<PathBuf, Change>, exec: Option<&str>) {
+    let mut out = std::io::stdout().lock();
+    for (path, change) in batch {
+        let _ = writeln!(out, "{} {}", change.label(), path.display());
+    }
+    let _ = writeln!(out, "-- batch: {} changes", batch.len());
+    let _ = out.flush();
+    drop(out);
+
+    if let Some(cmd) = exec {
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(cmd)
+            .arg("dir_watcher")
+            .args(batch.keys())
+            .status();
+        match status {
+            Ok(s) if !s.success() => eprintln!("dir_watcher: action exited with {}", s),
+            Err(e) => eprintln!("dir_watcher: cannot run action: {}", e),
+            _ => {}
+        }
+    }
+}
+
+fn run(config: Config) -> Result<()> {
+    if !config.root.is_dir() {
+        bail!("{} is not a directory", config.root.display());
+    }
+
+    let mut watcher = Watcher::new(&config.root)?;
+    println!(
+        "watching {} ({} directories)",
+        config.root.display(),
+        watcher.dirs.len()
+    );
+
+    let mut pending: BTreeMap<PathBuf, Change> = BTreeMap::new();
+    let mut last_event = Instant::now();
+
+    loop {
+        // Block forever when idle; otherwise only until the burst has been
+        // quiet for the debounce interval
+        let timeout = if pending.is_empty() {
+            -1
+        } else {
+            let remaining = config.debounce.saturating_sub(last_event.elapsed());
+            remaining.as_millis() as i32
+        };
+
+        let ready = {
+            let mut fds = [PollFd::new(&watcher.inotify, PollFlags::POLLIN)];
+            match poll(&mut fds, timeout) {
+                Ok(n) => n > 0,
+                Err(nix::errno::Errno::EINTR) => continue,
+                Err(e) => return Err(e).context("poll"),
+            }
+        };
+
+        if !ready {
+            flush(&pending, config.exec.as_deref());
+            pending.clear();
+            continue;
+        }
+
+        // One read returns every queued event
+        let events = watcher
+            .inotify
+            .read_events()
+            .context("read inotify events")?;
+        for event in events {
+            for (path, change) in watcher.handle(event) {
+                match merge(pending.get(&path).copied(), change) {
+                    Some(merged) => pending.insert(path, merged),
+                    None => pending.remove(&path),
+                };
+            }
+        }
+        last_event = Instant::now();
+    }
+}
+
+fn main() {
+    let result = parse_args(std::env::args().skip(1).collect()).and_then(run);
+    if let Err(e) = result {
+        eprintln!("dir_watcher: {:#}", e);
+        std::process::exit(1);
+    }
+}
+
+// Key concepts demonstrated:
+//
+// 1. INOTIFY IS A FILE DESCRIPTOR:
+//    - Events are read() from it, so it works with poll/epoll like a socket
+//    - One watch per directory: recursion is the program's job
+//
+// 2. RACES WHEN DIRECTORIES APPEAR:
+//    - Files created in a new directory before add_watch() never produce events
+//    - Scan the directory right after adding the watch to catch them
+//
+// 3. DEBOUNCING:
+//    - Editors and builds produce bursts of events for one logical change
+//    - Wait until the stream goes quiet, then act once on the merged batch
+//
+// 4. OVERFLOW:
+//    - The kernel queue is bounded (fs.inotify.max_queued_events)
+//    - IN_Q_OVERFLOW means events were dropped: the only safe answer is a rescan
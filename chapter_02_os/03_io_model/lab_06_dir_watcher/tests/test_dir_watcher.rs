@@ -0,0 +1,205 @@
+//! Lab 6 Tests - Directory Watcher
+//!
+//! Run with: cargo test
+//!
+//! Note: These tests require Linux (inotify)
+
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+struct WatcherGuard {
+    child: Child,
+    lines: Receiver<String>,
+}
+
+impl Drop for WatcherGuard {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+    }
+}
+
+impl WatcherGuard {
+    /// Collect output lines up to and including the next batch marker
+    fn next_batch(&self) -> Option<Vec<String>> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut batch = Vec::new();
+        loop {
+            let left = deadline.checked_duration_since(Instant::now())?;
+            let line = self.lines.recv_timeout(left).ok()?;
+            let done = line.starts_with("-- batch:");
+            batch.push(line);
+            if done {
+                return Some(batch);
+            }
+        }
+    }
+}
+
+/// Fresh directory per test so tests can run in parallel
+fn watch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("dir_watcher_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn start_watcher(dir: &Path, extra: &[&str]) -> WatcherGuard {
+    // Run the binary directly so killing it closes its stdout pipe
+    let mut child = Command::new(env!("CARGO_BIN_EXE_dir_watcher"))
+        .arg(dir)
+        .args(["--debounce-ms", "150"])
+        .args(extra)
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start watcher");
+
+    let (tx, lines) = mpsc::channel();
+    let stdout = child.stdout.take().unwrap();
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    let first = lines
+        .recv_timeout(Duration::from_secs(5))
+        .expect("watcher should start");
+    assert!(
+        first.starts_with("watching "),
+        "unexpected first line: {}",
+        first
+    );
+
+    WatcherGuard { child, lines }
+}
+
+#[test]
+fn test_01_reports_created_file() {
+    let dir = watch_dir("create");
+    let watcher = start_watcher(&dir, &[]);
+
+    fs::write(dir.join("hello.txt"), "hi").unwrap();
+
+    let batch = watcher.next_batch().expect("a batch should be printed");
+    assert!(
+        batch.contains(&format!("created {}", dir.join("hello.txt").display())),
+        "batch: {:?}",
+        batch
+    );
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_02_burst_is_debounced() {
+    let dir = watch_dir("burst");
+    let watcher = start_watcher(&dir, &[]);
+
+    let path = dir.join("log.txt");
+    for i in 0..100 {
+        let mut content = fs::read_to_string(&path).unwrap_or_default();
+        content.push_str(&format!("{}\n", i));
+        fs::write(&path, content).unwrap();
+    }
+
+    let batch = watcher.next_batch().expect("a batch should be printed");
+    assert_eq!(
+        batch,
+        vec![
+            format!("created {}", path.display()),
+            "-- batch: 1 changes".to_string()
+        ],
+        "100 writes should merge into one change"
+    );
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_03_new_subdirectories_are_watched() {
+    let dir = watch_dir("recursive");
+    let watcher = start_watcher(&dir, &[]);
+
+    // Files created together with the directory must not be missed
+    fs::create_dir_all(dir.join("a/b")).unwrap();
+    fs::write(dir.join("a/b/early.txt"), "x").unwrap();
+    let batch = watcher.next_batch().expect("first batch");
+    assert!(
+        batch.contains(&format!("created {}", dir.join("a/b/early.txt").display())),
+        "file created before the watch existed should be found: {:?}",
+        batch
+    );
+
+    fs::write(dir.join("a/b/late.txt"), "x").unwrap();
+    let batch = watcher.next_batch().expect("second batch");
+    assert!(
+        batch.contains(&format!("created {}", dir.join("a/b/late.txt").display())),
+        "file in a new subdirectory should be reported: {:?}",
+        batch
+    );
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_04_delete_and_cancelled_changes() {
+    let dir = watch_dir("delete");
+    fs::write(dir.join("old.txt"), "x").unwrap();
+    let watcher = start_watcher(&dir, &[]);
+
+    // Created and deleted within one burst cancel out
+    fs::write(dir.join("tmp.swp"), "x").unwrap();
+    fs::remove_file(dir.join("tmp.swp")).unwrap();
+    fs::remove_file(dir.join("old.txt")).unwrap();
+
+    let batch = watcher.next_batch().expect("a batch should be printed");
+    assert_eq!(
+        batch,
+        vec![
+            format!("deleted {}", dir.join("old.txt").display()),
+            "-- batch: 1 changes".to_string()
+        ]
+    );
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_05_exec_runs_once_per_batch() {
+    let dir = watch_dir("exec");
+    let marker = std::env::temp_dir().join(format!("dir_watcher_marker_{}", std::process::id()));
+    let _ = fs::remove_file(&marker);
+
+    let action = format!("echo \"$#\" >> {}", marker.display());
+    let watcher = start_watcher(&dir, &["--exec", &action]);
+
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        fs::write(dir.join(name), "x").unwrap();
+    }
+    watcher.next_batch().expect("a batch should be printed");
+    thread::sleep(Duration::from_millis(200));
+
+    let runs = fs::read_to_string(&marker).unwrap_or_default();
+    assert_eq!(runs, "3\n", "action should run once with 3 paths");
+
+    let _ = fs::remove_file(&marker);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_06_rejects_missing_directory() {
+    let output = Command::new(env!("CARGO_BIN_EXE_dir_watcher"))
+        .arg("/nonexistent/dir_watcher_test")
+        .output()
+        .expect("Failed to run watcher");
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("not a directory"));
+}
@@ -0,0 +1,242 @@
+//! Lab 7: mini cp - read/write vs sendfile vs copy_file_range
+//!
+//! ## Goal
+//! Copy a file four different ways and measure how many syscalls and how
+//! much time each takes, to see where the cost of I/O really goes
+//!
+//! ## Requirements
+//! 1. `mini_cp [--strategy S] [--buffer-size N] <src> <dst>`
+//! 2. Strategies:
+//!    - `naive`: read()/write() 512 bytes at a time
+//!    - `buffered`: read()/write() with a `--buffer-size` buffer (default 128 KiB)
+//!    - `sendfile`: sendfile() from src to dst, no userspace buffer
+//!    - `copy_file_range`: copy_file_range(), falling back to sendfile on EXDEV/ENOSYS
+//! 3. `--strategy all` runs every strategy in turn (each overwrites `<dst>`)
+//! 4. Count every syscall issued for the copy, handle short writes
+//! 5. Print one line per strategy:
+//!    `strategy=... bytes=... syscalls=... elapsed_ms=... throughput=... MiB/s`
+//!
+//! ## Expected Output
+//! ```
+//! $ mini_cp --strategy all big.bin copy.bin
+//! strategy=naive           bytes=536870912 syscalls=2097154 elapsed_ms=1890.3 throughput=270.9 MiB/s
+//! strategy=buffered        bytes=536870912 syscalls=8194 elapsed_ms=160.2 throughput=3196.0 MiB/s
+//! strategy=sendfile        bytes=536870912 syscalls=2 elapsed_ms=95.7 throughput=5350.1 MiB/s
+//! strategy=copy_file_range bytes=536870912 syscalls=2 elapsed_ms=0.4 throughput=... MiB/s
+//! ```
+//!
+//! ## Hints
+//! - Use `nix::unistd::{read, write}` directly: `std::io::BufReader` would hide syscalls
+//! - `nix::sys::sendfile::sendfile(out, in, None, count)` - output can be a file since Linux 2.6.33
+//! - `nix::fcntl::copy_file_range(in, None, out, None, len)` returns 0 at EOF
+//! - Both zero-copy calls may copy less than asked: loop until they return 0
+//! - Run twice: the first run may read from disk, the second from the page cache
+//!
+//! ## Verification
+//! ```bash
+//! cargo test
+//! dd if=/dev/urandom of=/tmp/big.bin bs=1M count=512
+//! cargo run --release -- --strategy all /tmp/big.bin /tmp/copy.bin
+//! strace -c ./target/release/mini_cp --strategy naive /tmp/big.bin /tmp/copy.bin
+//! strace -c ./target/release/mini_cp --strategy sendfile /tmp/big.bin /tmp/copy.bin
+//! ```
+//!
+//! ## Acceptance Criteria
+//! - [ ] All four strategies produce identical copies
+//! - [ ] Syscall counts match `strace -c`
+//! - [ ] Can explain why sendfile needs no userspace buffer
+//! - [ ] Can explain why copy_file_range can be near-instant on btrfs/XFS
+//!
+//! Warning: Requires Linux (sendfile, copy_file_range)
+//!
+//! Check solution/main.rs after completing
+
+use anyhow::{bail, Context, Result};
+use nix::errno::Errno;
+use nix::fcntl::copy_file_range;
+use nix::sys::sendfile::sendfile;
+use nix::unistd::{read, write};
+use std::fs::File;
+use std::os::fd::{AsFd, AsRawFd};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Buffer size of the "naive" strategy: one old-style disk sector
+const NAIVE_CHUNK: usize = 512;
+
+/// Largest request per sendfile/copy_file_range call (Linux caps one call
+/// at about 2 GiB anyway)
+const MAX_ZERO_COPY_CHUNK: usize = 1 << 30;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Strategy {
+    Naive,
+    Buffered,
+    Sendfile,
+    CopyFileRange,
+}
+
+impl Strategy {
+    const ALL: [Strategy; 4] = [
+        Strategy::Naive,
+        Strategy::Buffered,
+        Strategy::Sendfile,
+        Strategy::CopyFileRange,
+    ];
+
+    fn parse(name: &str) -> Result<Self> {
+        match name {
+            "naive" => Ok(Strategy::Naive),
+            "buffered" => Ok(Strategy::Buffered),
+            "sendfile" => Ok(Strategy::Sendfile),
+            "copy_file_range" => Ok(Strategy::CopyFileRange),
+            _ => bail!(
+                "unknown strategy '{}' (naive|buffered|sendfile|copy_file_range|all)",
+                name
+            ),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Strategy::Naive => "naive",
+            Strategy::Buffered => "buffered",
+            Strategy::Sendfile => "sendfile",
+            Strategy::CopyFileRange => "copy_file_range",
+        }
+    }
+}
+
+/// `mini_cp [--strategy S|all] [--buffer-size N] <src> <dst>`
+struct Config {
+    strategies: Vec<Strategy>,
+    buffer_size: usize,
+    src: PathBuf,
+    dst: PathBuf,
+}
+
+fn parse_args(args: Vec<String>) -> Result<Config> {
+    let mut strategies = vec![Strategy::Buffered];
+    let mut buffer_size = 128 * 1024;
+    let mut paths = Vec::new();
+    let mut iter = args.into_iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--strategy" | "-s" => {
+                let name = iter.next().context("--strategy needs a value")?;
+                strategies = if name == "all" {
+                    Strategy::ALL.to_vec()
+                } else {
+                    vec![Strategy::parse(&name)?]
+                };
+            }
+            "--buffer-size" => {
+                let size = iter.next().context("--buffer-size needs a value")?;
+                buffer_size = size.parse().context("invalid --buffer-size")?;
+                if buffer_size == 0 {
+                    bail!("--buffer-size must be > 0");
+                }
+            }
+            s if s.starts_with('-') => bail!("unknown option '{}'", s),
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    if paths.len() != 2 {
+        bail!("usage: mini_cp [--strategy naive|buffered|sendfile|copy_file_range|all] [--buffer-size N] <src> <dst>");
+    }
+    let dst = paths.pop().unwrap();
+    let src = paths.pop().unwrap();
+
+    Ok(Config {
+        strategies,
+        buffer_size,
+        src,
+        dst,
+    })
+}
+
+/// What one copy cost
+#[derive(Debug, Default)]
+struct Stats {
+    bytes: u64,
+    syscalls: u64,
+}
+
+/// Copy through a userspace buffer: every byte crosses the kernel boundary
+/// twice (read into `buf`, write out of it)
+fn copy_read_write(src: &File, dst: &File, chunk: usize) -> Result<Stats> {
+    // TODO: Implement
+    //
+    // Suggested steps:
+    // 1. Allocate a `chunk`-sized buffer
+    // 2. read() until it returns 0, counting each call
+    // 3. write() in a loop until all `n` bytes are out (short writes!)
+    todo!("Implement copy_read_write")
+}
+
+/// sendfile(): the kernel moves pages from the page cache to `dst`,
+/// no copy into userspace
+fn copy_sendfile(src: &File, dst: &File) -> Result<Stats> {
+    // TODO: Loop sendfile(dst, src, None, MAX_ZERO_COPY_CHUNK) until it returns 0
+    todo!("Implement copy_sendfile")
+}
+
+/// copy_file_range(): file-to-file copy inside the kernel. On filesystems
+/// like btrfs/XFS this can share extents (reflink) instead of copying data.
+fn copy_range(src: &File, dst: &File) -> Result<Stats> {
+    // TODO: Loop copy_file_range(src.as_fd(), None, dst.as_fd(), None, ...) until it returns 0.
+    // (Pass BorrowedFd: nix 0.27 forwards `fd_in` to the raw syscall as-is.)
+    // On EXDEV/ENOSYS/EOPNOTSUPP before any byte was copied, fall back to
+    // copy_sendfile() and add its stats.
+    todo!("Implement copy_range")
+}
+
+fn copy(strategy: Strategy, config: &Config) -> Result<(Stats, Duration)> {
+    let src =
+        File::open(&config.src).with_context(|| format!("cannot open {}", config.src.display()))?;
+    let dst = File::create(&config.dst)
+        .with_context(|| format!("cannot create {}", config.dst.display()))?;
+
+    let start = Instant::now();
+    let stats = match strategy {
+        Strategy::Naive => copy_read_write(&src, &dst, NAIVE_CHUNK)?,
+        Strategy::Buffered => copy_read_write(&src, &dst, config.buffer_size)?,
+        Strategy::Sendfile => copy_sendfile(&src, &dst)?,
+        Strategy::CopyFileRange => copy_range(&src, &dst)?,
+    };
+
+    Ok((stats, start.elapsed()))
+}
+
+fn run(config: Config) -> Result<()> {
+    if !config.src.is_file() {
+        bail!("{} is not a regular file", config.src.display());
+    }
+
+    for &strategy in &config.strategies {
+        let (stats, elapsed) = copy(strategy, &config)?;
+        let mib = stats.bytes as f64 / (1024.0 * 1024.0);
+        let secs = elapsed.as_secs_f64().max(1e-9);
+
+        println!(
+            "strategy={:<15} bytes={} syscalls={} elapsed_ms={:.1} throughput={:.1} MiB/s",
+            strategy.name(),
+            stats.bytes,
+            stats.syscalls,
+            secs * 1000.0,
+            mib / secs
+        );
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let result = parse_args(std::env::args().skip(1).collect()).and_then(run);
+    if let Err(e) = result {
+        eprintln!("mini_cp: {:#}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,147 @@
+//! Lab 7 Tests - mini cp
+//!
+//! Run with: cargo test
+//!
+//! Note: These tests require Linux (sendfile, copy_file_range)
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+fn run_cp(args: &[&str]) -> (String, String, bool) {
+    let output = Command::new(env!("CARGO_BIN_EXE_mini_cp"))
+        .args(args)
+        .output()
+        .expect("Failed to execute program");
+
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+
+    (stdout, stderr, output.status.success())
+}
+
+/// Source file with non-repeating content, unique per test
+fn source_file(name: &str, size: usize) -> (PathBuf, Vec<u8>) {
+    let path = std::env::temp_dir().join(format!("mini_cp_{}_{}", name, std::process::id()));
+    let content: Vec<u8> = (0..size).map(|i| (i * 7 % 251) as u8).collect();
+    fs::write(&path, &content).unwrap();
+    (path, content)
+}
+
+/// Read a `key=value` field from a result line
+fn field<'a>(line: &'a str, key: &str) -> &'a str {
+    line.split_whitespace()
+        .find_map(|part| part.strip_prefix(key))
+        .unwrap_or_else(|| panic!("missing {} in {}", key, line))
+}
+
+#[test]
+fn test_01_every_strategy_copies_exactly() {
+    let (src, content) = source_file("exact", 3 * 1024 * 1024 + 123);
+
+    for strategy in ["naive", "buffered", "sendfile", "copy_file_range"] {
+        let dst = src.with_extension(strategy);
+        let (stdout, stderr, success) = run_cp(&[
+            "--strategy",
+            strategy,
+            src.to_str().unwrap(),
+            dst.to_str().unwrap(),
+        ]);
+
+        assert!(success, "{} failed: {}", strategy, stderr);
+        assert_eq!(
+            fs::read(&dst).unwrap(),
+            content,
+            "{} copy differs",
+            strategy
+        );
+        assert_eq!(
+            field(&stdout, "bytes=").parse::<usize>().unwrap(),
+            content.len()
+        );
+        let _ = fs::remove_file(&dst);
+    }
+
+    let _ = fs::remove_file(&src);
+}
+
+#[test]
+fn test_02_syscall_counts() {
+    let size = 1024 * 1024;
+    let (src, _) = source_file("counts", size);
+    let dst = src.with_extension("out");
+
+    let (stdout, _, success) = run_cp(&["-s", "all", src.to_str().unwrap(), dst.to_str().unwrap()]);
+    assert!(success);
+
+    let count = |strategy: &str| -> u64 {
+        let line = stdout
+            .lines()
+            .find(|l| field(l, "strategy=") == strategy)
+            .unwrap_or_else(|| panic!("no line for {}", strategy));
+        field(line, "syscalls=").parse().unwrap()
+    };
+
+    // 512-byte chunks: one read + one write per chunk, plus the final read
+    assert_eq!(count("naive"), (size / 512 * 2 + 1) as u64);
+    // 128 KiB chunks: 8 reads + 8 writes + final read
+    assert_eq!(count("buffered"), 17);
+    assert!(
+        count("sendfile") <= 3,
+        "sendfile should need only a few calls"
+    );
+    assert!(count("copy_file_range") <= 4);
+
+    let _ = fs::remove_file(&src);
+    let _ = fs::remove_file(&dst);
+}
+
+#[test]
+fn test_03_buffer_size_changes_syscalls() {
+    let (src, _) = source_file("bufsize", 64 * 1024);
+    let dst = src.with_extension("out");
+
+    let (stdout, _, success) = run_cp(&[
+        "--buffer-size",
+        "4096",
+        src.to_str().unwrap(),
+        dst.to_str().unwrap(),
+    ]);
+
+    assert!(success);
+    assert_eq!(
+        field(&stdout, "strategy="),
+        "buffered",
+        "buffered is the default"
+    );
+    assert_eq!(field(&stdout, "syscalls="), "33");
+
+    let _ = fs::remove_file(&src);
+    let _ = fs::remove_file(&dst);
+}
+
+#[test]
+fn test_04_empty_file() {
+    let (src, _) = source_file("empty", 0);
+    let dst = src.with_extension("out");
+
+    let (stdout, _, success) = run_cp(&["-s", "all", src.to_str().unwrap(), dst.to_str().unwrap()]);
+
+    assert!(success);
+    assert_eq!(stdout.lines().count(), 4);
+    assert!(fs::read(&dst).unwrap().is_empty());
+
+    let _ = fs::remove_file(&src);
+    let _ = fs::remove_file(&dst);
+}
+
+#[test]
+fn test_05_errors() {
+    let (_, stderr, success) = run_cp(&["/nonexistent/mini_cp_src", "/tmp/x"]);
+    assert!(!success);
+    assert!(stderr.contains("not a regular file"));
+
+    let (_, stderr, success) = run_cp(&["--strategy", "mmap", "a", "b"]);
+    assert!(!success);
+    assert!(stderr.contains("unknown strategy"));
+}
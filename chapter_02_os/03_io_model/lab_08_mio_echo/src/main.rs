@@ -0,0 +1,380 @@
+//! Lab 8: Event-Loop Echo Server (mio)
+//!
+//! ## Goal
+//! Build the echo server a third time, on one thread with a hand-written
+//! readiness loop, to see what Tokio does behind `.await`
+//!
+//! ## Requirements
+//! 1. `mio_echo [--bind ADDR] [--trace]` (default `127.0.0.1:8080`)
+//! 2. One thread: a `mio::Poll` (epoll on Linux) watches the listener
+//!    and every client socket, all of them non-blocking
+//! 3. Each connection is a state machine with its own buffer:
+//!    - `Reading`: waiting for bytes, registered for READABLE
+//!    - `Writing`: holding bytes the socket would not take yet,
+//!      registered for WRITABLE
+//!    - `Draining`: the client half-closed; flush what is left, then close
+//!    - `Closed`: deregister and drop the socket
+//! 4. Stop reading once 64 KiB are waiting to be echoed (backpressure)
+//! 5. Print each new connection with its token, and bytes in/out when it
+//!    closes; `--trace` also prints every poll wake-up, each event's
+//!    readiness and each state change
+//!
+//! ## Expected Behavior
+//! ```
+//! $ cargo run -- --trace
+//! Event-loop echo server listening on 127.0.0.1:8080 (one thread)
+//! poll: 1 event(s)
+//!   token 0: readable
+//! New connection from 127.0.0.1:54321 (token 1)
+//! poll: 1 event(s)
+//!   token 1: readable
+//!   [127.0.0.1:54321] Reading -> Writing (6 bytes pending)
+//!   [127.0.0.1:54321] Writing -> Reading
+//! ```
+//!
+//! ## What Tokio Does For You
+//! | Here (by hand)                          | Lab 5 (Tokio)                         |
+//! |-----------------------------------------|---------------------------------------|
+//! | `Poll::poll` + `for event in &events`   | the runtime's reactor                 |
+//! | `Token` -> `HashMap<Token, Connection>` | the waker stored for each task        |
+//! | `State` enum + `pending` buffer         | the future `async fn` compiles into   |
+//! | `Err(WouldBlock)` -> return to the loop | `.await` returning `Poll::Pending`    |
+//! | `reregister` READABLE / WRITABLE        | `readable()` / `writable()` internally|
+//!
+//! ## Hints
+//! - `mio::net::TcpListener::bind` is already non-blocking; so are the
+//!   streams it accepts
+//! - Readiness is edge-triggered: an event means "something changed", so
+//!   read (and accept) until `WouldBlock`, or the next event never comes
+//! - `Interrupted` is not an error: retry
+//! - A read of 0 bytes is EOF: the client will send nothing more, but may
+//!   still be reading
+//! - An event can name a connection closed earlier in the same batch
+//!
+//! ## Verification
+//! ```bash
+//! cargo test
+//! cargo run -- --trace
+//! # In another terminal:
+//! nc localhost 8080
+//! grep Threads /proc/$(pgrep mio_echo)/status   # Threads: 1
+//! strace -f -e trace=epoll_wait,read,write,accept4 ./target/debug/mio_echo
+//! ```
+//!
+//! ## Acceptance Criteria
+//! - [ ] Many clients are echoed at once by a single thread
+//! - [ ] A large upload is echoed intact, even when the client reads slowly
+//! - [ ] Can explain why each read loops until WouldBlock
+//! - [ ] Can map every part of the loop to what Tokio hides
+//!
+//! Warning: `strace` and `/proc` need Linux; the server itself runs
+//! wherever mio does (kqueue on macOS)
+//!
+//! Check solution/main.rs after completing
+
+use mio::event::Event;
+use mio::net::{TcpListener, TcpStream};
+use mio::{Events, Interest, Poll, Registry, Token};
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, ToSocketAddrs};
+
+/// The listener's token; connections count up from 1
+const LISTENER: Token = Token(0);
+
+/// Most bytes held for one client before the server stops reading from it
+const MAX_PENDING: usize = 64 * 1024;
+
+const READ_CHUNK: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Reading,
+    Writing,
+    Draining,
+    Closed,
+}
+
+/// Why `read_some` stopped
+enum ReadOutcome {
+    /// Nothing more to read for now
+    WouldBlock,
+    /// `pending` is full: echo it before reading more
+    Full,
+    /// The client sent FIN
+    Eof,
+}
+
+/// Everything an `async fn handle_client` would keep across `.await`s
+struct Connection {
+    stream: TcpStream,
+    peer: SocketAddr,
+    state: State,
+    /// Read but not yet echoed
+    pending: Vec<u8>,
+    bytes_in: u64,
+    bytes_out: u64,
+}
+
+impl Connection {
+    fn new(stream: TcpStream, peer: SocketAddr) -> Self {
+        Connection {
+            stream,
+            peer,
+            state: State::Reading,
+            pending: Vec::new(),
+            bytes_in: 0,
+            bytes_out: 0,
+        }
+    }
+
+    /// What to wait for in the current state
+    fn interest(&self) -> Option<Interest> {
+        match self.state {
+            State::Reading => Some(Interest::READABLE),
+            State::Writing | State::Draining => Some(Interest::WRITABLE),
+            State::Closed => None,
+        }
+    }
+
+    fn set_state(&mut self, next: State, trace: bool) {
+        if trace && next != self.state {
+            if self.pending.is_empty() {
+                println!("  [{}] {:?} -> {:?}", self.peer, self.state, next);
+            } else {
+                println!(
+                    "  [{}] {:?} -> {:?} ({} bytes pending)",
+                    self.peer,
+                    self.state,
+                    next,
+                    self.pending.len()
+                );
+            }
+        }
+        self.state = next;
+    }
+
+    /// Read into `pending` until the socket is empty or the buffer full
+    fn read_some(&mut self) -> io::Result<ReadOutcome> {
+        let mut buf = [0u8; READ_CHUNK];
+        loop {
+            let room = MAX_PENDING - self.pending.len();
+            if room == 0 {
+                return Ok(ReadOutcome::Full);
+            }
+            let chunk = room.min(READ_CHUNK);
+            match self.stream.read(&mut buf[..chunk]) {
+                Ok(0) => return Ok(ReadOutcome::Eof),
+                Ok(n) => {
+                    self.bytes_in += n as u64;
+                    self.pending.extend_from_slice(&buf[..n]);
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    return Ok(ReadOutcome::WouldBlock)
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Write `pending` until it is empty (true) or the socket is full (false)
+    fn write_some(&mut self) -> io::Result<bool> {
+        while !self.pending.is_empty() {
+            match self.stream.write(&self.pending) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => {
+                    self.bytes_out += n as u64;
+                    self.pending.drain(..n);
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(false),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(true)
+    }
+
+    /// Run the state machine until the socket has nothing more for us:
+    /// the equivalent of polling the connection's future once
+    fn handle(&mut self, trace: bool) -> io::Result<()> {
+        loop {
+            if self.state == State::Reading {
+                match self.read_some()? {
+                    ReadOutcome::Eof => self.set_state(State::Draining, trace),
+                    ReadOutcome::Full => self.set_state(State::Writing, trace),
+                    ReadOutcome::WouldBlock if self.pending.is_empty() => return Ok(()),
+                    ReadOutcome::WouldBlock => self.set_state(State::Writing, trace),
+                }
+            }
+
+            let flushed = self.write_some()?;
+            match (self.state, flushed) {
+                (State::Draining, true) => {
+                    self.set_state(State::Closed, trace);
+                    return Ok(());
+                }
+                // The read may have stopped at a full buffer with more data
+                // in the socket, and no new event will say so: read again
+                (State::Writing, true) => self.set_state(State::Reading, trace),
+                // Socket buffer full: wait for WRITABLE
+                _ => return Ok(()),
+            }
+        }
+    }
+}
+
+/// `readable + read-closed`, for `--trace`
+fn readiness(event: &Event) -> String {
+    let flags = [
+        (event.is_readable(), "readable"),
+        (event.is_writable(), "writable"),
+        (event.is_read_closed(), "read-closed"),
+        (event.is_write_closed(), "write-closed"),
+        (event.is_error(), "error"),
+    ];
+    let names: Vec<&str> = flags
+        .iter()
+        .filter(|(set, _)| *set)
+        .map(|(_, name)| *name)
+        .collect();
+    names.join(" + ")
+}
+
+/// Accept every waiting connection: one READABLE event on the listener
+/// can stand for many
+fn accept_all(
+    listener: &TcpListener,
+    registry: &Registry,
+    connections: &mut HashMap<Token, Connection>,
+    next_token: &mut usize,
+) -> io::Result<()> {
+    loop {
+        match listener.accept() {
+            Ok((mut stream, peer)) => {
+                let token = Token(*next_token);
+                *next_token += 1;
+                registry.register(&mut stream, token, Interest::READABLE)?;
+                println!("New connection from {} (token {})", peer, token.0);
+                connections.insert(token, Connection::new(stream, peer));
+            }
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => {
+                // EMFILE and friends: the rest stay in the backlog until
+                // the next connection wakes the listener
+                eprintln!("Accept failed: {}", err);
+                return Ok(());
+            }
+        }
+    }
+}
+
+struct Config {
+    bind: SocketAddr,
+    /// Print every poll wake-up, event and state change
+    trace: bool,
+}
+
+fn usage() -> String {
+    "usage: mio_echo [--bind ADDR] [--trace]".to_string()
+}
+
+fn parse_args(args: Vec<String>) -> Result<Config, String> {
+    let mut bind = "127.0.0.1:8080".to_string();
+    let mut trace = false;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--bind" => bind = args.next().ok_or("--bind needs an address")?,
+            "--trace" => trace = true,
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+    let bind = bind
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+        .ok_or_else(|| format!("cannot resolve {}", bind))?;
+    Ok(Config { bind, trace })
+}
+
+fn run(config: Config) -> io::Result<()> {
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(1024);
+    let mut listener = TcpListener::bind(config.bind)?;
+    poll.registry()
+        .register(&mut listener, LISTENER, Interest::READABLE)?;
+    println!(
+        "Event-loop echo server listening on {} (one thread)",
+        listener.local_addr()?
+    );
+
+    let mut connections: HashMap<Token, Connection> = HashMap::new();
+    let mut next_token = 1;
+    loop {
+        if let Err(err) = poll.poll(&mut events, None) {
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err);
+        }
+        if config.trace {
+            println!("poll: {} event(s)", events.iter().count());
+        }
+
+        for event in events.iter() {
+            if config.trace {
+                println!("  token {}: {}", event.token().0, readiness(event));
+            }
+            if event.token() == LISTENER {
+                accept_all(
+                    &listener,
+                    poll.registry(),
+                    &mut connections,
+                    &mut next_token,
+                )?;
+                continue;
+            }
+
+            let token = event.token();
+            let Some(conn) = connections.get_mut(&token) else {
+                continue;
+            };
+            let before = conn.interest();
+            if let Err(err) = conn.handle(config.trace) {
+                println!("[{}] Error: {}", conn.peer, err);
+                conn.set_state(State::Closed, config.trace);
+            }
+            match conn.interest() {
+                Some(interest) if Some(interest) != before => {
+                    poll.registry()
+                        .reregister(&mut conn.stream, token, interest)?;
+                }
+                Some(_) => {}
+                None => {
+                    let mut conn = connections.remove(&token).unwrap();
+                    poll.registry().deregister(&mut conn.stream)?;
+                    println!(
+                        "[{}] Disconnected: {} bytes in, {} bytes out",
+                        conn.peer, conn.bytes_in, conn.bytes_out
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn main() {
+    let config = match parse_args(std::env::args().skip(1).collect()) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}\n{}", err, usage());
+            std::process::exit(2);
+        }
+    };
+    if let Err(err) = run(config) {
+        eprintln!("mio_echo: {}", err);
+        std::process::exit(1);
+    }
+}
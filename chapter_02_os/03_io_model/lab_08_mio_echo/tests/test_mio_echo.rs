@@ -0,0 +1,171 @@
+//! Lab 8 Tests - Event-Loop Echo Server
+//!
+//! Run with: cargo test
+//!
+//! Note: test_03 reads /proc and needs Linux
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+struct ServerGuard {
+    child: Child,
+}
+
+impl Drop for ServerGuard {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Start the server and wait until it accepts connections
+fn start_server(port: u16, extra: &[&str]) -> ServerGuard {
+    let bind = format!("127.0.0.1:{}", port);
+    let child = Command::new(env!("CARGO_BIN_EXE_mio_echo"))
+        .args(["--bind", &bind])
+        .args(extra)
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start server");
+    let server = ServerGuard { child };
+
+    let started = Instant::now();
+    while TcpStream::connect(&bind).is_err() {
+        assert!(
+            started.elapsed() < Duration::from_secs(5),
+            "server never listened"
+        );
+        thread::sleep(Duration::from_millis(50));
+    }
+    server
+}
+
+fn connect(port: u16) -> TcpStream {
+    let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream
+}
+
+/// Kill the server and collect everything it printed
+fn output(mut server: ServerGuard) -> String {
+    let _ = server.child.kill();
+    let mut stdout = String::new();
+    let mut reader = BufReader::new(server.child.stdout.take().unwrap());
+    let mut line = String::new();
+    while reader.read_line(&mut line).unwrap_or(0) > 0 {
+        stdout.push_str(&line);
+        line.clear();
+    }
+    stdout
+}
+
+#[test]
+fn test_01_echoes_many_clients_at_once() {
+    let _server = start_server(8162, &[]);
+
+    let mut clients: Vec<TcpStream> = (0..20).map(|_| connect(8162)).collect();
+    // Interleave: every client is open while the others are served
+    for round in 0..3 {
+        for (i, client) in clients.iter_mut().enumerate() {
+            let msg = format!("client {} round {}", i, round);
+            client.write_all(msg.as_bytes()).unwrap();
+            let mut buf = vec![0u8; msg.len()];
+            client.read_exact(&mut buf).unwrap();
+            assert_eq!(buf, msg.as_bytes());
+        }
+    }
+}
+
+#[test]
+fn test_02_large_upload_with_half_close() {
+    let _server = start_server(8163, &[]);
+
+    let mut stream = connect(8163);
+    let payload: Vec<u8> = (0..1024 * 1024).map(|i| (i * 7 % 251) as u8).collect();
+
+    // Send everything before reading anything: the server must stop
+    // reading when its buffer fills instead of losing bytes
+    let mut writer = stream.try_clone().unwrap();
+    let upload = payload.clone();
+    let sender = thread::spawn(move || {
+        writer.write_all(&upload).unwrap();
+        writer.shutdown(Shutdown::Write).unwrap();
+    });
+    thread::sleep(Duration::from_millis(200));
+
+    let mut echoed = Vec::new();
+    stream.read_to_end(&mut echoed).unwrap();
+    sender.join().unwrap();
+    assert_eq!(echoed.len(), payload.len());
+    assert!(echoed == payload, "echo differs from what was sent");
+}
+
+#[test]
+fn test_03_single_thread() {
+    let server = start_server(8164, &[]);
+    let mut clients: Vec<TcpStream> = (0..10).map(|_| connect(8164)).collect();
+    for client in clients.iter_mut() {
+        client.write_all(b"ping").unwrap();
+        let mut buf = [0u8; 4];
+        client.read_exact(&mut buf).unwrap();
+    }
+
+    let status = std::fs::read_to_string(format!("/proc/{}/status", server.child.id())).unwrap();
+    let threads = status
+        .lines()
+        .find_map(|line| line.strip_prefix("Threads:"))
+        .unwrap()
+        .trim();
+    assert_eq!(threads, "1", "10 clients should still mean one thread");
+}
+
+#[test]
+fn test_04_trace_shows_events_and_states() {
+    let server = start_server(8165, &["--trace"]);
+
+    let mut stream = connect(8165);
+    stream.write_all(b"hello").unwrap();
+    let mut buf = [0u8; 5];
+    stream.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"hello");
+    drop(stream);
+    thread::sleep(Duration::from_millis(300));
+
+    let stdout = output(server);
+    assert!(stdout.contains("poll: "), "{}", stdout);
+    assert!(stdout.contains("token 0: readable"), "{}", stdout);
+    assert!(stdout.contains("(token 2)"), "{}", stdout);
+    assert!(
+        stdout.contains("Reading -> Writing (5 bytes pending)"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("Writing -> Reading"), "{}", stdout);
+    assert!(stdout.contains("Draining -> Closed"), "{}", stdout);
+    assert!(
+        stdout.contains("Disconnected: 5 bytes in, 5 bytes out"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn test_05_invalid_args() {
+    for args in [
+        vec!["--bind"],
+        vec!["--bind", "not an address"],
+        vec!["--threads", "4"],
+    ] {
+        let output = Command::new(env!("CARGO_BIN_EXE_mio_echo"))
+            .args(&args)
+            .output()
+            .expect("Failed to execute program");
+        assert_eq!(output.status.code(), Some(2), "{:?}", args);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("usage:"));
+    }
+}
@@ -0,0 +1,98 @@
+//! Command-line options shared by the echo servers
+//!
+//! `lab_04_blocking_echo` and `lab_05_async_echo` flatten these into their
+//! own clap parsers, so a script can drive either server with the same
+//! flags and change one knob at a time:
+//!
+//! ```ignore
+//! #[path = "../../shared/echo_args.rs"]
+//! mod echo_args;
+//!
+//! #[derive(Parser)]
+//! struct Cli {
+//!     #[command(flatten)]
+//!     echo: echo_args::EchoArgs,
+//!     // ...options only this server has
+//! }
+//! ```
+//!
+//! ```bash
+//! cargo run --release -- --port 9000 --buffer-size 16
+//! cargo run --release -- --port 9001 --buffer-size 65536 --backlog 16
+//! ```
+
+use clap::Args;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io;
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+
+#[derive(Args, Debug, Clone)]
+pub struct EchoArgs {
+    /// Address to listen on; a host name binds to its first address,
+    /// [::]:PORT is dual-stack
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    pub bind: String,
+
+    /// Listen on this port instead of the one in --bind (0: any free port)
+    #[arg(long, short)]
+    pub port: Option<u16>,
+
+    /// Refuse IPv4 clients on an IPv6 socket (IPV6_V6ONLY)
+    #[arg(long)]
+    pub v6only: bool,
+
+    /// Bytes asked for per read: the most one echo write sends
+    #[arg(long, default_value_t = 1024, value_parser = positive)]
+    pub buffer_size: usize,
+
+    /// Connections the kernel may hold completed but not yet accepted
+    #[arg(long, default_value_t = 1024, value_parser = clap::value_parser!(i32).range(1..))]
+    pub backlog: i32,
+
+    /// Worker threads (default depends on the server)
+    #[arg(long, value_parser = positive)]
+    pub workers: Option<usize>,
+}
+
+/// A count that must be at least 1
+pub fn positive(value: &str) -> Result<usize, String> {
+    match value.parse() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err("expected a number > 0".to_string()),
+    }
+}
+
+impl EchoArgs {
+    /// --bind resolved, with --port applied
+    pub fn addr(&self) -> Result<SocketAddr, String> {
+        let mut addr = self
+            .bind
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addrs| addrs.next())
+            .ok_or_else(|| format!("cannot resolve {}", self.bind))?;
+        if let Some(port) = self.port {
+            addr.set_port(port);
+        }
+        if self.v6only && !addr.is_ipv6() {
+            return Err("--v6only needs an IPv6 --bind address".to_string());
+        }
+        Ok(addr)
+    }
+
+    /// Bind a blocking listening socket. For IPv6, set IPV6_V6ONLY
+    /// explicitly: the default differs between systems (Linux: sysctl
+    /// net.ipv6.bindv6only)
+    pub fn listen(&self, addr: SocketAddr) -> io::Result<TcpListener> {
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+        if addr.is_ipv6() {
+            socket.set_only_v6(self.v6only)?;
+        }
+        // As std's TcpListener::bind does: restart without waiting out TIME_WAIT
+        #[cfg(unix)]
+        socket.set_reuse_address(true)?;
+        socket.bind(&addr.into())?;
+        socket.listen(self.backlog)?;
+        Ok(socket.into())
+    }
+}
@@ -0,0 +1,190 @@
+//! Every chat line, kept in SQLite
+//!
+//! ```sql
+//! CREATE TABLE messages (
+//!     id      INTEGER PRIMARY KEY AUTOINCREMENT,
+//!     sent_at INTEGER NOT NULL,  -- Unix milliseconds
+//!     sender  TEXT NOT NULL,
+//!     room    TEXT NOT NULL,
+//!     text    TEXT NOT NULL
+//! );
+//! ```
+//!
+//! The database comes from `CHAT_DB` (default `sqlite::memory:`, gone on
+//! restart):
+//!
+//! ```bash
+//! CHAT_DB=sqlite:chat.db cargo run
+//! ```
+//!
+//! Client tasks never wait for the disk: `record` only queues the line, and
+//! one writer task inserts whatever has piled up in a single transaction.
+//! The price is that `/history <n>` may miss the last few milliseconds.
+//! Private messages are not archived. On shutdown, `close` waits for the
+//! queue to be written out.
+
+use sqlx::sqlite::{
+    SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions, SqliteSynchronous,
+};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// Most lines one transaction writes
+const BATCH: usize = 100;
+
+/// Most lines `/history <n>` returns
+pub const MAX_QUERY: usize = 500;
+
+struct Record {
+    sent_at: i64,
+    sender: String,
+    room: String,
+    text: String,
+}
+
+/// A line read back from the database
+pub struct Stored {
+    pub sent_at: i64,
+    pub sender: String,
+    pub text: String,
+}
+
+pub struct Archive {
+    pool: SqlitePool,
+    /// Taken by `close`: without a sender the writer task drains and ends
+    writer: Mutex<Option<mpsc::UnboundedSender<Record>>>,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Archive {
+    /// Open (creating if needed) the database and start the writer task
+    pub async fn open(url: &str) -> Result<Self, sqlx::Error> {
+        // WAL: /history queries don't block the writer task (and vice versa).
+        // See chapter_04 lab_05 for the numbers behind these settings
+        let options = SqliteConnectOptions::from_str(url)?
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal)
+            .busy_timeout(Duration::from_secs(5))
+            .synchronous(SqliteSynchronous::Normal);
+        // Every connection to `sqlite::memory:` opens its own empty database
+        let max_connections = if url.contains(":memory:") { 1 } else { 5 };
+        let pool = SqlitePoolOptions::new()
+            .max_connections(max_connections)
+            .connect_with(options)
+            .await?;
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS messages (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                sent_at INTEGER NOT NULL,
+                sender TEXT NOT NULL,
+                room TEXT NOT NULL,
+                text TEXT NOT NULL
+            )",
+        )
+        .execute(&pool)
+        .await?;
+        sqlx::query("CREATE INDEX IF NOT EXISTS messages_by_room ON messages (room, id)")
+            .execute(&pool)
+            .await?;
+
+        let (writer, queue) = mpsc::unbounded_channel();
+        let task = tokio::spawn(write_batches(pool.clone(), queue));
+        Ok(Self {
+            pool,
+            writer: Mutex::new(Some(writer)),
+            task: Mutex::new(Some(task)),
+        })
+    }
+
+    /// Queue a chat line for the writer task; never blocks
+    pub fn record(&self, sender: &str, room: &str, text: &str) {
+        let sent_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as i64;
+        let writer = self.writer.lock().unwrap();
+        let Some(writer) = writer.as_ref() else {
+            return;
+        };
+        let _ = writer.send(Record {
+            sent_at,
+            sender: sender.to_string(),
+            room: room.to_string(),
+            text: text.to_string(),
+        });
+    }
+
+    /// Write whatever is still queued, then close the database; lines
+    /// recorded after this are dropped
+    pub async fn close(&self) {
+        self.writer.lock().unwrap().take();
+        let task = self.task.lock().unwrap().take();
+        if let Some(task) = task {
+            let _ = task.await;
+        }
+        self.pool.close().await;
+    }
+
+    /// The last `n` lines said in `room`, oldest first
+    pub async fn recent(&self, room: &str, n: usize) -> Result<Vec<Stored>, sqlx::Error> {
+        let rows: Vec<(i64, String, String)> = sqlx::query_as(
+            "SELECT sent_at, sender, text FROM messages
+             WHERE room = ? ORDER BY id DESC LIMIT ?",
+        )
+        .bind(room)
+        .bind(n as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .rev()
+            .map(|(sent_at, sender, text)| Stored {
+                sent_at,
+                sender,
+                text,
+            })
+            .collect())
+    }
+}
+
+/// Wait for a line, take everything else already queued, insert it all
+async fn write_batches(pool: SqlitePool, mut queue: mpsc::UnboundedReceiver<Record>) {
+    while let Some(first) = queue.recv().await {
+        let mut batch = vec![first];
+        while batch.len() < BATCH {
+            match queue.try_recv() {
+                Ok(record) => batch.push(record),
+                Err(_) => break,
+            }
+        }
+        if let Err(err) = insert(&pool, &batch).await {
+            // The chat goes on without its archive
+            eprintln!("Archive: lost {} message(s): {}", batch.len(), err);
+        }
+    }
+}
+
+async fn insert(pool: &SqlitePool, batch: &[Record]) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    for record in batch {
+        sqlx::query("INSERT INTO messages (sent_at, sender, room, text) VALUES (?, ?, ?, ?)")
+            .bind(record.sent_at)
+            .bind(&record.sender)
+            .bind(&record.room)
+            .bind(&record.text)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await
+}
+
+/// `HH:MM:SS` (UTC) of Unix milliseconds
+pub fn clock(millis: i64) -> String {
+    let secs = millis.div_euclid(1000).rem_euclid(86_400);
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
@@ -0,0 +1,270 @@
+//! Authentication handshake before a client may chat
+//!
+//! Three ways in, each turned on by its own configuration. The first line
+//! a client sends must use one of those that are on:
+//!
+//! ```text
+//! > AUTH t0k-alice                  token: the token names the client
+//! OK welcome, alice
+//! > LOGIN bob hunter2               user and password from a file
+//! OK welcome, bob
+//! > PASS letmein                    shared password: in as a guest
+//! OK welcome, guest3
+//! > AUTH wrong
+//! ERR invalid token                 (connection closed)
+//! ```
+//!
+//! Tokens come from `CHAT_TOKENS` (comma separated) or `CHAT_TOKENS_FILE`
+//! (one per line, `#` starts a comment), each entry `name:token`:
+//!
+//! ```bash
+//! CHAT_TOKENS="alice:t0k-alice,bob:t0k-bob" cargo run
+//! ```
+//!
+//! Users come from `CHAT_USERS_FILE`, same format, `user:password`. The
+//! shared password is `CHAT_PASSWORD`; it lets anyone who knows it in,
+//! under a guest name they may change with /nick. Secrets are kept in
+//! plain text: fine for a lab, a real server would store password hashes.
+//!
+//! `CHAT_ADMINS` (comma separated) names the token holders and users who
+//! may use admin commands such as /stats. Guests can't be admins: they
+//! choose their own name.
+//!
+//! A client that says nothing for `AUTH_TIMEOUT_SECS` (default 10) is
+//! disconnected. With nothing configured the handshake is skipped and
+//! clients are known by their address.
+
+use std::time::Duration;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt};
+
+struct Secret {
+    name: String,
+    secret: String,
+}
+
+/// Who a successful handshake let in
+pub enum Identity {
+    /// A token or a user: this is the nickname, and it can't change
+    Named(String),
+    /// The shared password: a guest, who may pick a nickname
+    Guest,
+}
+
+pub struct Auth {
+    tokens: Vec<Secret>,
+    users: Vec<Secret>,
+    password: Option<String>,
+    admins: Vec<String>,
+    timeout: Duration,
+}
+
+/// `name:secret` entries, one per line, `#` comments; `what` names them in
+/// errors
+fn parse_entries(spec: &str, what: &str) -> Result<Vec<Secret>, String> {
+    let mut entries: Vec<Secret> = Vec::new();
+    for entry in spec
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim())
+    {
+        if entry.is_empty() {
+            continue;
+        }
+        // The secret goes last, so it may contain ':' itself
+        let (name, secret) = match entry.split_once(':') {
+            Some((name, secret)) if !name.is_empty() && !secret.is_empty() => (name, secret),
+            // Only the name part, so the message never echoes a secret
+            _ => {
+                return Err(format!(
+                    "{} entry '{}' is not name:{}",
+                    what,
+                    entry.split(':').next().unwrap_or(""),
+                    what
+                ))
+            }
+        };
+        if entries.iter().any(|e| e.name == name) {
+            return Err(format!("{} for '{}' is configured twice", what, name));
+        }
+        entries.push(Secret {
+            name: name.to_string(),
+            secret: secret.to_string(),
+        });
+    }
+    Ok(entries)
+}
+
+impl Auth {
+    /// Tokens from `CHAT_TOKENS`, else from the file named by
+    /// `CHAT_TOKENS_FILE`; users from `CHAT_USERS_FILE`; the shared
+    /// password from `CHAT_PASSWORD`. None of them: handshake off
+    pub fn from_env() -> Result<Self, String> {
+        let spec = match (
+            std::env::var("CHAT_TOKENS"),
+            std::env::var("CHAT_TOKENS_FILE"),
+        ) {
+            (Ok(tokens), _) => tokens.replace(',', "\n"),
+            (Err(_), Ok(path)) => std::fs::read_to_string(&path)
+                .map_err(|e| format!("cannot read CHAT_TOKENS_FILE {}: {}", path, e))?,
+            (Err(_), Err(_)) => String::new(),
+        };
+        let tokens = parse_entries(&spec, "token")?;
+        // Tokens alone identify a client, so two owners can't share one
+        for (i, token) in tokens.iter().enumerate() {
+            if tokens[..i].iter().any(|t| t.secret == token.secret) {
+                return Err(format!("token for '{}' is configured twice", token.name));
+            }
+        }
+
+        let users = match std::env::var("CHAT_USERS_FILE") {
+            Ok(path) => parse_entries(
+                &std::fs::read_to_string(&path)
+                    .map_err(|e| format!("cannot read CHAT_USERS_FILE {}: {}", path, e))?,
+                "password",
+            )?,
+            Err(_) => Vec::new(),
+        };
+
+        let password = match std::env::var("CHAT_PASSWORD") {
+            Ok(password) if password.is_empty() => {
+                return Err("CHAT_PASSWORD must not be empty".to_string())
+            }
+            Ok(password) => Some(password),
+            Err(_) => None,
+        };
+
+        let admins: Vec<String> = std::env::var("CHAT_ADMINS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+            .collect();
+        // A guest picks its own name, so only a login can prove one
+        if !admins.is_empty() && tokens.is_empty() && users.is_empty() {
+            return Err(
+                "CHAT_ADMINS needs CHAT_TOKENS or CHAT_USERS_FILE: admins must log in".to_string(),
+            );
+        }
+
+        let timeout = match std::env::var("AUTH_TIMEOUT_SECS") {
+            Ok(secs) => secs
+                .trim()
+                .parse()
+                .map_err(|_| format!("AUTH_TIMEOUT_SECS must be a number, got '{}'", secs))?,
+            Err(_) => 10,
+        };
+
+        Ok(Self {
+            tokens,
+            users,
+            password,
+            admins,
+            timeout: Duration::from_secs(timeout),
+        })
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.tokens.is_empty() || !self.users.is_empty() || self.password.is_some()
+    }
+
+    /// Whether a client logged in as `name` (never a guest) is an admin
+    pub fn is_admin(&self, name: &str) -> bool {
+        self.admins.iter().any(|admin| admin == name)
+    }
+
+    /// Name of the token's owner
+    fn find(&self, secret: &str) -> Option<&str> {
+        // Compare against every token, in constant time, so response timing
+        // doesn't tell how much of a guess was right
+        self.tokens.iter().fold(None, |found, token| {
+            if constant_time_eq(token.secret.as_bytes(), secret.as_bytes()) {
+                Some(&token.name)
+            } else {
+                found
+            }
+        })
+    }
+
+    /// Whether `user` exists and has this password
+    fn login(&self, user: &str, password: &str) -> bool {
+        // As in `find`: every password is compared, also for unknown users,
+        // so timing tells neither which users exist nor how close a guess was
+        self.users.iter().fold(false, |ok, entry| {
+            let matches = constant_time_eq(entry.secret.as_bytes(), password.as_bytes());
+            ok | (matches && entry.name == user)
+        })
+    }
+
+    /// `AUTH <token> or PASS <password>`: the first lines accepted
+    pub fn ways(&self) -> String {
+        let mut ways = Vec::new();
+        if !self.tokens.is_empty() {
+            ways.push("AUTH <token>");
+        }
+        if !self.users.is_empty() {
+            ways.push("LOGIN <user> <password>");
+        }
+        if self.password.is_some() {
+            ways.push("PASS <password>");
+        }
+        ways.join(" or ")
+    }
+
+    fn expected(&self) -> String {
+        format!("expected {}", self.ways())
+    }
+
+    /// Check one handshake line
+    fn check(&self, line: &str) -> Result<Identity, String> {
+        let line = line.trim();
+        let (verb, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let rest = rest.trim();
+        match verb {
+            "AUTH" if !self.tokens.is_empty() => self
+                .find(rest)
+                .map(|name| Identity::Named(name.to_string()))
+                .ok_or_else(|| "invalid token".to_string()),
+            "LOGIN" if !self.users.is_empty() => match rest.split_once(' ') {
+                Some((user, password)) if self.login(user, password.trim()) => {
+                    Ok(Identity::Named(user.to_string()))
+                }
+                Some(_) => Err("invalid user or password".to_string()),
+                None => Err(self.expected()),
+            },
+            "PASS" => match &self.password {
+                Some(password) if constant_time_eq(password.as_bytes(), rest.as_bytes()) => {
+                    Ok(Identity::Guest)
+                }
+                Some(_) => Err("invalid password".to_string()),
+                None => Err(self.expected()),
+            },
+            _ => Err(self.expected()),
+        }
+    }
+
+    /// Read the first line. Returns who the client is (the caller sends the
+    /// welcome), or answers with why it was refused (the caller closes the
+    /// connection).
+    pub async fn handshake<R, W>(&self, reader: &mut R, writer: &mut W) -> Result<Identity, String>
+    where
+        R: AsyncBufRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let mut line = String::new();
+        let outcome = match tokio::time::timeout(self.timeout, reader.read_line(&mut line)).await {
+            Err(_) => Err("authentication timed out".to_string()),
+            Ok(Err(_)) | Ok(Ok(0)) => Err("disconnected before authenticating".to_string()),
+            Ok(Ok(_)) => self.check(&line),
+        };
+
+        if let Err(reason) = &outcome {
+            let _ = writer
+                .write_all(format!("ERR {}\n", reason).as_bytes())
+                .await;
+        }
+        outcome
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
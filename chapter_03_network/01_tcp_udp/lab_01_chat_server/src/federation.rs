@@ -0,0 +1,238 @@
+//! Federation: several servers, one conversation
+//!
+//! A server listens for other servers on `--link-port` and dials the ones
+//! given with `--peer` (again and again, if the link drops). Both ends of a
+//! link introduce themselves, then every chat line said on either server
+//! crosses it:
+//!
+//! ```text
+//! LINK <node>                         first line, each way
+//! MSG <id> <room> <nick> <text>       id is <origin node>:<boot>:<counter>
+//! ```
+//!
+//! A server posts what it receives into its own room of that name (if
+//! someone there is in it) and passes it on to its other links, so a
+//! line reaches servers that aren't linked to the origin directly. With
+//! links forming a loop (A-B, B-C, C-A) the same line would then circle
+//! forever; three rules stop it:
+//!
+//! - never send a line back over the link it came from
+//! - drop lines whose origin is this node
+//! - remember the last `SEEN_LEN` ids and drop any seen before: a line
+//!   that arrives by two routes is shown once
+//!
+//! The counter starts again at 1 when a server restarts, but its peers
+//! still remember the ids it used before. `<boot>`, the time the server
+//! started in nanoseconds, keeps the new ids from looking like old ones.
+//!
+//! Only chat lines travel. Nicknames, presence and private messages stay
+//! on their own server, which is why remote senders show as `nick@node`.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+
+/// Ids remembered for loop suppression
+const SEEN_LEN: usize = 4096;
+
+/// How long the other side has to say `LINK`
+const HELLO: Duration = Duration::from_secs(5);
+
+/// Pause before dialling a peer again
+const REDIAL: Duration = Duration::from_secs(1);
+
+/// A chat line from another server
+pub struct Relayed {
+    pub origin: String,
+    pub room: String,
+    pub nick: String,
+    pub text: String,
+}
+
+/// Recently relayed ids, oldest first
+#[derive(Default)]
+struct Seen {
+    ids: HashSet<String>,
+    order: VecDeque<String>,
+}
+
+pub struct Federation {
+    node: String,
+    /// Start time in nanoseconds: ids from before a restart stay distinct
+    boot: u128,
+    /// Link id -> the queue its writer task sends from
+    links: Mutex<HashMap<u64, mpsc::UnboundedSender<String>>>,
+    next_link: AtomicU64,
+    next_msg: AtomicU64,
+    seen: Mutex<Seen>,
+    /// Lines for this server's rooms
+    incoming: mpsc::UnboundedSender<Relayed>,
+}
+
+impl Federation {
+    /// Also returns where lines from other servers arrive
+    pub fn new(node: String) -> (Arc<Self>, mpsc::UnboundedReceiver<Relayed>) {
+        let (incoming, relayed) = mpsc::unbounded_channel();
+        let federation = Self {
+            node,
+            boot: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|since| since.as_nanos())
+                .unwrap_or(0),
+            links: Mutex::new(HashMap::new()),
+            next_link: AtomicU64::new(1),
+            next_msg: AtomicU64::new(1),
+            seen: Mutex::new(Seen::default()),
+            incoming,
+        };
+        (Arc::new(federation), relayed)
+    }
+
+    pub fn node(&self) -> &str {
+        &self.node
+    }
+
+    /// Send a line said on this server to every linked server
+    pub fn publish(&self, room: &str, nick: &str, text: &str) {
+        let id = format!(
+            "{}:{}:{}",
+            self.node,
+            self.boot,
+            self.next_msg.fetch_add(1, Ordering::Relaxed)
+        );
+        self.first_sight(&id);
+        self.send_all(&format!("MSG {} {} {} {}\n", id, room, nick, text), None);
+    }
+
+    /// Remember `id`; false if it was already there
+    fn first_sight(&self, id: &str) -> bool {
+        let mut seen = self.seen.lock().unwrap();
+        if !seen.ids.insert(id.to_string()) {
+            return false;
+        }
+        seen.order.push_back(id.to_string());
+        if seen.order.len() > SEEN_LEN {
+            if let Some(old) = seen.order.pop_front() {
+                seen.ids.remove(&old);
+            }
+        }
+        true
+    }
+
+    fn send_all(&self, line: &str, except: Option<u64>) {
+        for (id, link) in self.links.lock().unwrap().iter() {
+            if Some(*id) != except {
+                let _ = link.send(line.to_string());
+            }
+        }
+    }
+
+    /// Accept links from other servers
+    pub async fn listen(self: Arc<Self>, listener: TcpListener) {
+        loop {
+            match listener.accept().await {
+                Ok((stream, addr)) => {
+                    let federation = Arc::clone(&self);
+                    tokio::spawn(async move { federation.run_link(stream, addr).await });
+                }
+                Err(err) => eprintln!("Link accept error: {}", err),
+            }
+        }
+    }
+
+    /// Keep a link to `peer` up
+    pub async fn dial(self: Arc<Self>, peer: String) {
+        loop {
+            match TcpStream::connect(&peer).await {
+                Ok(stream) => {
+                    let addr = stream.peer_addr().unwrap_or(([0, 0, 0, 0], 0).into());
+                    Arc::clone(&self).run_link(stream, addr).await;
+                }
+                Err(err) => println!("Link to {} failed: {}", peer, err),
+            }
+            tokio::time::sleep(REDIAL).await;
+        }
+    }
+
+    async fn run_link(self: Arc<Self>, stream: TcpStream, addr: SocketAddr) {
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+        let mut line = String::new();
+
+        let hello = format!("LINK {}\n", self.node);
+        if writer.write_all(hello.as_bytes()).await.is_err() {
+            return;
+        }
+        let peer = match tokio::time::timeout(HELLO, reader.read_line(&mut line)).await {
+            Ok(Ok(n)) if n > 0 => match line.trim_end().strip_prefix("LINK ") {
+                Some(node) if node == self.node => {
+                    println!("Link from {} is this node ({}): closed", addr, node);
+                    return;
+                }
+                Some(node) => node.to_string(),
+                None => {
+                    println!("Link from {} didn't say LINK: closed", addr);
+                    return;
+                }
+            },
+            _ => return,
+        };
+
+        let link = self.next_link.fetch_add(1, Ordering::Relaxed);
+        let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+        self.links.lock().unwrap().insert(link, tx);
+        println!("Linked with {} ({})", peer, addr);
+        tokio::spawn(async move {
+            while let Some(line) = rx.recv().await {
+                if writer.write_all(line.as_bytes()).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        loop {
+            line.clear();
+            match reader.read_line(&mut line).await {
+                Ok(n) if n > 0 => {}
+                _ => break,
+            }
+            let Some((id, relayed)) = parse(line.trim_end()) else {
+                println!("[{}] bad link line: {:?}", peer, line.trim_end());
+                continue;
+            };
+            if relayed.origin == self.node || !self.first_sight(&id) {
+                // Came round a loop, or by a second route
+                continue;
+            }
+            self.send_all(&line, Some(link));
+            let _ = self.incoming.send(relayed);
+        }
+
+        // Dropping the sender ends the writer task
+        self.links.lock().unwrap().remove(&link);
+        println!("Link with {} closed", peer);
+    }
+}
+
+/// A `MSG` line: its id and what it carries
+fn parse(line: &str) -> Option<(String, Relayed)> {
+    let mut fields = line.splitn(5, ' ');
+    if fields.next()? != "MSG" {
+        return None;
+    }
+    let id = fields.next()?.to_string();
+    // <node>:<boot>:<counter>, split from the right: the node may have ':' in it
+    let origin = id.rsplitn(3, ':').nth(2)?;
+    let relayed = Relayed {
+        origin: origin.to_string(),
+        room: fields.next()?.to_string(),
+        nick: fields.next()?.to_string(),
+        text: fields.next().unwrap_or("").to_string(),
+    };
+    Some((id, relayed))
+}
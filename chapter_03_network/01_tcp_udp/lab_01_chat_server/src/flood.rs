@@ -0,0 +1,120 @@
+//! Flood protection: a token bucket per connection
+//!
+//! Each line a client sends costs one token. The bucket holds `burst`
+//! tokens and refills at `rate` per second, so a client may paste a few
+//! lines at once but can't keep up more than `rate` lines a second.
+//!
+//! A line that finds the bucket empty is dropped, and is a strike:
+//!
+//! ```text
+//! strike 1   warning                  (the line is dropped)
+//! strike 2   muted for `mute` seconds (chat lines and /msg refused)
+//! strike 3   disconnected
+//! ```
+//!
+//! Strikes are forgotten after `STRIKE_MEMORY` without a new one, so a
+//! client who slowed down starts over at a warning.
+//!
+//! ```bash
+//! cargo run -- 8080 --rate 2 --burst 5 --mute-secs 60
+//! ```
+
+use std::time::{Duration, Instant};
+
+/// How long a strike counts towards the next step
+const STRIKE_MEMORY: Duration = Duration::from_secs(60);
+
+/// Thresholds, the same for every connection
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    /// Tokens added per second
+    pub rate: f64,
+    /// Bucket size: the longest burst allowed
+    pub burst: f64,
+    /// How long the second strike mutes
+    pub mute: Duration,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            rate: 5.0,
+            burst: 20.0,
+            mute: Duration::from_secs(30),
+        }
+    }
+}
+
+/// What to do with the line just received
+pub enum Verdict {
+    Allow,
+    /// Drop the line and tell the client this
+    Refuse(String),
+    /// Same, and the room hears the client was muted
+    Mute(String),
+    /// Tell the client this, then close the connection
+    Disconnect(String),
+}
+
+pub struct Guard {
+    limits: Limits,
+    tokens: f64,
+    refilled: Instant,
+    strikes: u32,
+    last_strike: Instant,
+    muted_until: Option<Instant>,
+}
+
+impl Guard {
+    /// A full bucket: a new client may start with a burst
+    pub fn new(limits: Limits) -> Self {
+        let now = Instant::now();
+        Self {
+            limits,
+            tokens: limits.burst,
+            refilled: now,
+            strikes: 0,
+            last_strike: now,
+            muted_until: None,
+        }
+    }
+
+    /// Take a token for one line
+    pub fn check(&mut self) -> Verdict {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.limits.rate).min(self.limits.burst);
+        self.refilled = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Verdict::Allow;
+        }
+
+        if now.duration_since(self.last_strike) > STRIKE_MEMORY {
+            self.strikes = 0;
+        }
+        self.strikes += 1;
+        self.last_strike = now;
+        match self.strikes {
+            1 => Verdict::Refuse(format!(
+                "Slow down! At most {} messages per second; next time you are muted\n",
+                self.limits.rate
+            )),
+            2 => {
+                self.muted_until = Some(now + self.limits.mute);
+                Verdict::Mute(format!(
+                    "You are muted for {} seconds for flooding; keep going and you are out\n",
+                    self.limits.mute.as_secs()
+                ))
+            }
+            _ => Verdict::Disconnect("Disconnected for flooding\n".to_string()),
+        }
+    }
+
+    /// Whole seconds of mute left, rounded up; `None` when not muted
+    pub fn muted_for(&self) -> Option<u64> {
+        let left = self.muted_until?.checked_duration_since(Instant::now())?;
+        Some(left.as_secs() + u64::from(left.subsec_nanos() > 0))
+    }
+}
@@ -0,0 +1,182 @@
+//! Length-prefixed binary framing, as an alternative to lines
+//!
+//! With `--framing binary` every message is a frame:
+//!
+//! ```text
+//! +------+-----------------+-------------------+
+//! | type | length: u32 BE  | payload (UTF-8)   |
+//! +------+-----------------+-------------------+
+//!   1 B        4 B             length bytes
+//!
+//! type 1 TEXT  a chat line or command; from the server, one line of output
+//!      2 PING  payload is the token (may be empty)
+//!      3 PONG  likewise
+//! ```
+//!
+//! A newline protocol finds the end of a message by scanning for `\n`, so a
+//! message can't contain one and the reader can't know how much is coming.
+//! A length prefix says up front: the decoder waits until the header and
+//! then the whole payload are in its buffer, however the bytes were split
+//! across reads, and can refuse an oversized frame before buffering it.
+//!
+//! The chat itself stays line-based: `bridge` turns frames into lines for
+//! `handle_client` and its lines back into frames. A newline inside a TEXT
+//! payload becomes a space, so one frame is always one command.
+
+use futures::{SinkExt, StreamExt};
+use std::io;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, DuplexStream};
+use tokio_util::bytes::{Buf, BufMut, BytesMut};
+use tokio_util::codec::{Decoder, Encoder, FramedRead, FramedWrite, LinesCodec};
+
+/// Type byte and length
+const HEADER: usize = 5;
+
+/// Largest payload accepted; anything bigger closes the connection
+pub const MAX_PAYLOAD: usize = 8 * 1024;
+
+const TEXT: u8 = 1;
+const PING: u8 = 2;
+const PONG: u8 = 3;
+
+#[derive(Debug, PartialEq)]
+pub enum Frame {
+    Text(String),
+    Ping(String),
+    Pong(String),
+}
+
+impl Frame {
+    /// The line `handle_client` would have read
+    fn into_line(self) -> String {
+        let keyword = |word: &str, token: String| match token.is_empty() {
+            true => format!("{}\n", word),
+            false => format!("{} {}\n", word, token),
+        };
+        match self {
+            Frame::Text(text) => format!("{}\n", text.replace(['\r', '\n'], " ")),
+            Frame::Ping(token) => keyword("PING", token),
+            Frame::Pong(token) => keyword("PONG", token),
+        }
+    }
+
+    /// One line of server output as a frame; the server's PING and its
+    /// answers to client PINGs get their own types
+    fn from_line(line: String) -> Frame {
+        let keyword = |word: &str| {
+            let rest = line.strip_prefix(word)?;
+            (rest.is_empty() || rest.starts_with(' ')).then(|| rest.trim().to_string())
+        };
+        if let Some(token) = keyword("PING") {
+            Frame::Ping(token)
+        } else if let Some(token) = keyword("PONG") {
+            Frame::Pong(token)
+        } else {
+            Frame::Text(line)
+        }
+    }
+}
+
+pub struct FrameCodec;
+
+impl Decoder for FrameCodec {
+    type Item = Frame;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Frame>> {
+        // Not even the header yet: wait for more bytes
+        if src.len() < HEADER {
+            return Ok(None);
+        }
+        let kind = src[0];
+        let len = u32::from_be_bytes([src[1], src[2], src[3], src[4]]) as usize;
+        if len > MAX_PAYLOAD {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("frame of {} bytes, limit is {}", len, MAX_PAYLOAD),
+            ));
+        }
+        // Header but not the whole payload: make room for it and wait
+        if src.len() < HEADER + len {
+            src.reserve(HEADER + len - src.len());
+            return Ok(None);
+        }
+
+        src.advance(HEADER);
+        let payload = src.split_to(len);
+        let text = String::from_utf8(payload.to_vec())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "payload is not UTF-8"))?;
+        match kind {
+            TEXT => Ok(Some(Frame::Text(text))),
+            PING => Ok(Some(Frame::Ping(text))),
+            PONG => Ok(Some(Frame::Pong(text))),
+            other => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unknown frame type {}", other),
+            )),
+        }
+    }
+}
+
+impl Encoder<Frame> for FrameCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, frame: Frame, dst: &mut BytesMut) -> io::Result<()> {
+        let (kind, payload) = match frame {
+            Frame::Text(text) => (TEXT, text),
+            Frame::Ping(token) => (PING, token),
+            Frame::Pong(token) => (PONG, token),
+        };
+        dst.reserve(HEADER + payload.len());
+        dst.put_u8(kind);
+        dst.put_u32(payload.len() as u32);
+        dst.extend_from_slice(payload.as_bytes());
+        Ok(())
+    }
+}
+
+/// Serve a framed client through the line-based handler: returns the
+/// stream `handle_client` talks to, and pumps frames and lines in between
+/// until either side closes
+pub fn bridge<S>(stream: S) -> DuplexStream
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let (handler_side, bridge_side) = tokio::io::duplex(64 * 1024);
+    tokio::spawn(async move {
+        let (socket_rx, socket_tx) = tokio::io::split(stream);
+        let mut frames_in = FramedRead::new(socket_rx, FrameCodec);
+        let mut frames_out = FramedWrite::new(socket_tx, FrameCodec);
+        let (pipe_rx, mut pipe_tx) = tokio::io::split(bridge_side);
+        let mut lines_in = FramedRead::new(pipe_rx, LinesCodec::new());
+
+        loop {
+            tokio::select! {
+                frame = frames_in.next() => match frame {
+                    Some(Ok(frame)) => {
+                        if pipe_tx.write_all(frame.into_line().as_bytes()).await.is_err() {
+                            break;
+                        }
+                    }
+                    Some(Err(err)) => {
+                        // Past a bad frame the stream can't be trusted
+                        let reason = format!("ERR {}", err);
+                        let _ = frames_out.send(Frame::Text(reason)).await;
+                        break;
+                    }
+                    None => break,
+                },
+                line = lines_in.next() => match line {
+                    Some(Ok(line)) => {
+                        if frames_out.send(Frame::from_line(line)).await.is_err() {
+                            break;
+                        }
+                    }
+                    // The handler hung up: what it wrote last is sent
+                    _ => break,
+                },
+            }
+        }
+    });
+    handler_side
+}
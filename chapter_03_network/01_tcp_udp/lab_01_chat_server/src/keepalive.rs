@@ -0,0 +1,146 @@
+//! Idle timeout and PING/PONG keepalive, one per connection
+//!
+//! Two clocks run per client:
+//!
+//! ```text
+//! idle   no chat line or command for `idle`     -> disconnected
+//! ping   nothing at all heard for `ping`        -> server sends PING <n>
+//!        no line back within `pong` after that  -> disconnected
+//! ```
+//!
+//! TCP only notices a dead peer when a write fails, and its own keepalive
+//! waits two hours by default. A client whose network vanished (laptop
+//! lid closed, NAT entry dropped) looks idle but connected; the PING finds
+//! out within `ping + pong`.
+//!
+//! PONG answers keep the connection open but are not activity: a client
+//! that answers pings and says nothing is still idle. Pings are off by
+//! default, since an `nc` user would have to type `PONG` by hand.
+//!
+//! ```bash
+//! cargo run -- 8080 --idle-secs 600 --ping-secs 30 --pong-secs 10
+//! ```
+
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Thresholds, the same for every connection
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    /// Longest silence allowed; `None` never times out
+    pub idle: Option<Duration>,
+    /// Quiet time before a PING; `None` never pings
+    pub ping: Option<Duration>,
+    /// How long a PING may go unanswered
+    pub pong: Duration,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            idle: Some(Duration::from_secs(300)),
+            ping: None,
+            pong: Duration::from_secs(10),
+        }
+    }
+}
+
+/// What the timer asks for when it fires
+pub enum Action {
+    /// Send this line to the client
+    Ping(String),
+    /// Tell the client this, then close the connection
+    Disconnect(String),
+}
+
+pub struct Keepalive {
+    limits: Limits,
+    /// Last chat line or command
+    active: Instant,
+    /// Last line of any kind, PONGs included
+    heard: Instant,
+    /// The PING in flight: its number and when it went out
+    ping: Option<(u64, Instant)>,
+    sent: u64,
+}
+
+impl Keepalive {
+    pub fn new(limits: Limits) -> Self {
+        let now = Instant::now();
+        Self {
+            limits,
+            active: now,
+            heard: now,
+            ping: None,
+            sent: 0,
+        }
+    }
+
+    /// A line arrived: the client is alive, whatever it says
+    pub fn heard(&mut self) {
+        self.heard = Instant::now();
+        self.ping = None;
+    }
+
+    /// The line was a chat line or a command
+    pub fn active(&mut self) {
+        self.heard();
+        self.active = self.heard;
+    }
+
+    /// Whether `PONG <token>` answers the PING in flight
+    pub fn answers(&self, token: &str) -> bool {
+        self.ping
+            .is_some_and(|(n, _)| token.parse::<u64>().is_ok_and(|token| token == n))
+    }
+
+    /// When `fire` has something to do next; `None` if never
+    pub fn deadline(&self) -> Option<Instant> {
+        let idle = self.limits.idle.map(|idle| self.active + idle);
+        let ping = match self.ping {
+            Some((_, sent)) => Some(sent + self.limits.pong),
+            None => self.limits.ping.map(|ping| self.heard + ping),
+        };
+        match (idle, ping) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// Called once `deadline` has passed
+    pub fn fire(&mut self) -> Option<Action> {
+        let now = Instant::now();
+        if let Some(idle) = self.limits.idle {
+            if now >= self.active + idle {
+                return Some(Action::Disconnect(format!(
+                    "Disconnected: idle for {} seconds\n",
+                    idle.as_secs()
+                )));
+            }
+        }
+        match self.ping {
+            Some((_, sent)) if now >= sent + self.limits.pong => Some(Action::Disconnect(format!(
+                "Disconnected: no PONG within {} seconds\n",
+                self.limits.pong.as_secs()
+            ))),
+            Some(_) => None,
+            None => {
+                let ping = self.limits.ping?;
+                if now < self.heard + ping {
+                    return None;
+                }
+                self.sent += 1;
+                self.ping = Some((self.sent, now));
+                Some(Action::Ping(format!("PING {}\n", self.sent)))
+            }
+        }
+    }
+}
+
+/// Sleep until `deadline`, or forever without one; for `select!`
+pub async fn wait(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
@@ -43,34 +43,799 @@
 //! - [ ] Messages are broadcast to all other clients
 //! - [ ] Disconnection is handled gracefully
 //! - [ ] Connection notifications shown
+//!
+//! ## Extension: Rooms
+//! - Everyone starts in `#lobby`; messages only reach members of the
+//!   sender's room
+//! - `/join <room>` moves to a room (created on first join, removed when the
+//!   last member leaves), `/leave` goes back to the lobby, `/rooms` lists
+//!   rooms with member counts
+//! - One broadcast channel per room, in a shared `name -> Sender` map
+//! - Optional first argument: port (default 8080)
+//! ```
+//! > /join rust
+//! You joined #rust
+//! > /rooms
+//! Rooms: #lobby (1), #rust (2) - you are in #rust
+//! > /leave
+//! You left #rust, back in #lobby
+//! ```
+//!
+//! ## Extension: Token Auth
+//! - With `CHAT_TOKENS="alice:t0k-alice,bob:t0k-bob"` (or `CHAT_TOKENS_FILE`)
+//!   set, the first line must be `AUTH <token>`; anything else, a wrong
+//!   token, or silence for `AUTH_TIMEOUT_SECS` (default 10) closes the
+//!   connection before the client reaches the lobby
+//! - Every broadcast is tagged with the token's name instead of the address
+//! - See `auth.rs`
+//! ```
+//! > AUTH t0k-alice
+//! OK welcome, alice
+//! > hi
+//! # Others see:
+//! [alice]: hi
+//! ```
+//!
+//! ## Extension: Password Auth
+//! - `CHAT_USERS_FILE` holds `user:password` lines; such users log in with
+//!   `LOGIN <user> <password>` and are named like token holders
+//! - `CHAT_PASSWORD` is one password for everybody: `PASS <password>` lets
+//!   a client in as a guest, free to pick a `/nick`
+//! - Any mix of tokens, users and the shared password can be on at once;
+//!   the same timeout and the same "refused before the lobby" rules apply
+//! ```
+//! $ CHAT_PASSWORD=letmein CHAT_USERS_FILE=users.txt cargo run
+//! > LOGIN bob hunter2
+//! OK welcome, bob
+//! ```
+//!
+//! ## Extension: Nicknames
+//! - Clients are shown as `[nickname]`: the token's name with auth on,
+//!   otherwise `guest1`, `guest2`, ... until they pick one with `/nick`
+//! - Nicknames are unique; taking one in use is refused. A token or login
+//!   decides the name, so `/nick` is refused too
+//! - `/who` lists the members of your room, `/help` the commands, `/quit`
+//!   disconnects
+//! ```
+//! Welcome, guest1! /nick <name> to pick a name, /help for commands
+//! > /nick ferris
+//! You are now known as ferris
+//! > /who
+//! In #lobby: ferris, guest2
+//!   ferris  online 2m 05s, idle 3s
+//!   guest2  online 40s, idle 40s
+//! ```
+//!
+//! ## Extension: Presence
+//! - The nickname registry is who is online: it records each client's
+//!   room, when it connected and when it last said or asked something.
+//!   Joins, leaves and room changes update it and are announced in the
+//!   rooms concerned
+//! - `/who` reads it to show connect and idle time. PINGs and PONGs don't
+//!   count as activity, the same rule as the keepalive's
+//! - The registry is an `RwLock`: every /msg and /who only reads it, and a
+//!   line marks activity through an atomic, so the write lock is only for
+//!   connecting, renaming, moving and leaving
+//!
+//! ## Extension: Private Messages
+//! - `/msg <nick> <text>` reaches only that user, in whatever room they are
+//! - Rooms can't do this: a broadcast goes to every receiver. So each client
+//!   also has its own mpsc channel, and the nickname registry holds its
+//!   sending half
+//! ```
+//! > /msg bob see you at 5
+//! (to bob) see you at 5
+//! # bob sees:
+//! [alice -> you] see you at 5
+//! ```
+//!
+//! ## Extension: History
+//! - Each room keeps its last 20 chat lines in a ring buffer; they are
+//!   replayed to whoever connects or joins the room, and `/history` asks
+//!   for them again
+//! - A room's history goes when the room closes (the lobby never does)
+//! ```
+//! > /join rust
+//! You joined #rust
+//! --- last 2 message(s) in #rust ---
+//! [alice]: anyone tried 2024 edition?
+//! [bob]: yes, works fine
+//! --- end of history ---
+//! ```
+//!
+//! ## Extension: SQLite History
+//! - Every chat line (time, sender, room, text) is also written to SQLite,
+//!   so history outlives the ring buffer and the server itself
+//! - `CHAT_DB` picks the database (default `sqlite::memory:`); writes go
+//!   through a background task, so a slow disk never holds up the chat
+//! - `/history <n>` reads the last n (1-500) lines of your room from the
+//!   database; see `archive.rs`
+//! ```
+//! $ CHAT_DB=sqlite:chat.db cargo run
+//! > /history 2
+//! --- last 2 message(s) in #lobby from the archive ---
+//! [09:14:02] [alice]: morning
+//! [09:14:30] [bob]: hi alice
+//! --- end of history ---
+//! ```
+//!
+//! ## Extension: Flood Protection
+//! - Every connection has a token bucket: `--burst` lines at once, then
+//!   `--rate` lines per second (defaults 20 and 5)
+//! - A line over the limit is dropped. The first time earns a warning, the
+//!   second a mute for `--mute-secs` (default 30; chat and /msg refused),
+//!   the third a disconnect
+//! - See `flood.rs`
+//! ```
+//! $ cargo run -- 8080 --rate 1 --burst 3
+//! Slow down! At most 1 messages per second; next time you are muted
+//! You are muted for 30 seconds for flooding; keep going and you are out
+//! # Others see:
+//! [guest1] was muted for flooding
+//! ```
+//!
+//! ## Extension: Idle Timeout and Keepalive
+//! - A client that sends nothing for `--idle-secs` (default 300, 0 turns
+//!   it off) is disconnected
+//! - With `--ping-secs N`, a client silent for N seconds gets `PING <n>`
+//!   and must answer `PONG <n>` (any line will do) within `--pong-secs`
+//!   (default 10), or it is dropped: this finds half-dead connections that
+//!   TCP itself won't notice for hours
+//! - Answering PINGs keeps the connection, but not from being idle. Clients
+//!   may send `PING [n]` too and get `PONG [n]` back
+//! - See `keepalive.rs`
+//! ```
+//! $ cargo run -- 8080 --ping-secs 30
+//! PING 1
+//! > PONG 1
+//! ```
+//!
+//! ## Extension: IPv6 / Dual-Stack
+//! - `--bind ADDR` replaces the `0.0.0.0:PORT` default; `--bind [::]:8080`
+//!   is dual-stack and `--v6only` limits it to IPv6 clients
+//! - Guests from IPv4 over a dual-stack socket are still `guestN`, but the
+//!   server log shows them as `[::ffff:a.b.c.d]:port`
+//! ```
+//! $ cargo run -- --bind [::]:8080
+//! $ nc -6 ::1 8080
+//! $ nc -4 127.0.0.1 8080
+//! ```
+//!
+//! ## Extension: Admission Control
+//! - At most `--max-clients` (default 100) connections at once; one more is
+//!   told the server is full and closed straight away, before any
+//!   handshake, so it holds no resources
+//! - `/stats` shows connected clients, rooms and message throughput. It is
+//!   for admins only: token holders or users named in `CHAT_ADMINS`
+//! - See `stats.rs`
+//! ```
+//! $ CHAT_TOKENS=root:t0k-root CHAT_ADMINS=root cargo run -- 8080 --max-clients 2
+//! > /stats
+//! --- server stats ---
+//! Clients: 2/2 connected, 5 accepted, 1 turned away (full)
+//! Rooms: #lobby (1), #rust (1)
+//! Messages: 42 total, 0.3/s over the last minute, 0.1/s since start
+//! Uptime: 0h 05m 12s
+//! --- end of stats ---
+//! ```
+//!
+//! ## Extension: Binary Framing
+//! - `--framing binary` swaps lines for length-prefixed frames: a type byte
+//!   (1 TEXT, 2 PING, 3 PONG), a big-endian u32 payload length, then the
+//!   UTF-8 payload. The same commands go in TEXT frames; each line the
+//!   server would have sent comes back as one frame
+//! - A line reader scans for `\n` and can't tell how much is still coming;
+//!   a frame reader knows after 5 bytes. Both must cope with a message split
+//!   over several reads, or several messages in one read: `FrameCodec`
+//!   returns `None` until a whole frame is buffered
+//! - Frames over 8 KiB or of an unknown type get an `ERR` frame and the
+//!   connection is closed
+//! - See `framing.rs`, built on `tokio_util::codec`
+//! ```
+//! $ cargo run -- 8080 --framing binary
+//! # TEXT "/nick alice" on the wire:
+//! 01 00 00 00 0b 2f 6e 69 63 6b 20 61 6c 69 63 65
+//! ```
+//!
+//! ## Extension: TLS
+//! - `--tls-cert FILE --tls-key FILE` (PEM) wrap every connection in TLS
+//!   (rustls); the handshake comes first, so auth secrets are encrypted too.
+//!   A client that doesn't finish it within 10 seconds is dropped
+//! - `--gen-cert DIR` writes a self-signed `cert.pem` / `key.pem` for
+//!   localhost and exits
+//! - Works with `--framing binary` too: frames inside TLS records
+//! - See `tls.rs`
+//! ```
+//! $ cargo run -- --gen-cert certs
+//! $ cargo run -- 8443 --tls-cert certs/cert.pem --tls-key certs/key.pem
+//! $ openssl s_client -connect localhost:8443 -CAfile certs/cert.pem -quiet
+//! Welcome, guest1! /nick <name> to pick a name, /help for commands
+//! ```
+//!
+//! ## Extension: Graceful Shutdown
+//! - Ctrl-C (or SIGTERM) stops the accept loop first: new connections are
+//!   refused from then on
+//! - Every client gets what was already on its way (room and private
+//!   messages queued for it), then `Server shutting down, goodbye!`, and
+//!   its connection is closed from the server side (with a TLS
+//!   close_notify under `--tls-cert`)
+//! - The server waits up to 5 seconds for the clients to be closed, writes
+//!   the archive's queued lines to the database and exits with status 0
+//! ```
+//! ^C
+//! Shutting down: saying goodbye to 3 client(s)
+//! Shutdown complete
+//! ```
+//!
+//! ## Extension: Federation
+//! - `--link-port N` listens for other chat servers, `--peer HOST:PORT`
+//!   links to one (redialled every second while it is down), `--node NAME`
+//!   names this server (default `node-<port>`)
+//! - Chat lines cross the links and appear in the same room on every
+//!   server, as `[nick@node]`; each server passes them on, so links can
+//!   form any graph. Loops are suppressed by message id (see
+//!   `src/federation.rs`)
+//! ```
+//! $ cargo run -- 8080 --node a --link-port 9080
+//! $ cargo run -- 8081 --node b --peer 127.0.0.1:9080
+//! # alice on 8080 says "hi", bob on 8081 sees:
+//! [alice@a]: hi
+//! ```
+
+mod archive;
+mod auth;
+mod federation;
+mod flood;
+mod framing;
+mod keepalive;
+mod stats;
+mod tls;
+
+use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, ToSocketAddrs};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast, mpsc};
+use tokio_util::sync::CancellationToken;
+
+use archive::Archive;
+use auth::{Auth, Identity};
+use federation::{Federation, Relayed};
+use keepalive::Keepalive;
+use stats::Stats;
+
+/// The room every client starts in; it is never removed
+const LOBBY: &str = "lobby";
+
+/// Longest room name /join or nickname /nick accepts
+const MAX_NAME: usize = 32;
 
-use std::net::SocketAddr;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::broadcast;
+/// Chat lines a room remembers for newcomers
+const HISTORY_LEN: usize = 20;
+
+type Message = (String, SocketAddr);
+
+struct Room {
+    /// A member holds a receiver, so `receiver_count()` is the number of
+    /// people in the room
+    sender: broadcast::Sender<Message>,
+    /// The last `HISTORY_LEN` chat lines, oldest first
+    history: VecDeque<String>,
+}
+
+/// Room name -> its channel and history
+type Rooms = Arc<Mutex<HashMap<String, Room>>>;
+
+/// A connected client, as others can reach it
+struct User {
+    room: String,
+    /// Private messages for this client only
+    direct: mpsc::UnboundedSender<String>,
+    /// When it connected
+    since: Instant,
+    /// Milliseconds after `since` of its last chat line or command; an
+    /// atomic, so marking activity needs only the read lock
+    active: AtomicU64,
+}
+
+impl User {
+    fn idle(&self) -> Duration {
+        let active = Duration::from_millis(self.active.load(Ordering::Relaxed));
+        self.since.elapsed().saturating_sub(active)
+    }
+}
+
+/// Nickname -> its owner. Holding a key is owning the name. Read far more
+/// often than written, hence the `RwLock`
+type Users = Arc<RwLock<HashMap<String, User>>>;
 
 // ============================================================
 // TODO: Implement the chat server
 // ============================================================
 
-/// Handle a single client connection
-async fn handle_client(
-    stream: TcpStream,
+/// Subscribe to a room, creating it if needed; also returns its history
+fn enter(
+    rooms: &Rooms,
+    room: &str,
+) -> (
+    broadcast::Sender<Message>,
+    broadcast::Receiver<Message>,
+    Vec<String>,
+) {
+    let mut rooms = rooms.lock().unwrap();
+    let room = rooms.entry(room.to_string()).or_insert_with(|| Room {
+        sender: broadcast::channel(100).0,
+        history: VecDeque::with_capacity(HISTORY_LEN),
+    });
+    // Subscribe under the lock, so `exit` can't remove the room in between,
+    // and no message lands both in the history copy and in the receiver
+    let receiver = room.sender.subscribe();
+    let history = room.history.iter().cloned().collect();
+    (room.sender.clone(), receiver, history)
+}
+
+/// Called after the member's receiver is dropped: remove the room if it is
+/// now empty
+fn exit(rooms: &Rooms, room: &str) {
+    let mut rooms = rooms.lock().unwrap();
+    let empty = rooms
+        .get(room)
+        .is_some_and(|r| r.sender.receiver_count() == 0);
+    if empty && room != LOBBY {
+        rooms.remove(room);
+        println!("Room #{} closed", room);
+    }
+}
+
+/// The room a client is in, and its end of that room's channel
+struct Membership {
+    room: String,
+    /// Tells the client's own messages apart when they come back
     addr: SocketAddr,
-    sender: broadcast::Sender<(String, SocketAddr)>,
+    /// How others see the client: its token's name, or its address
+    who: String,
+    sender: broadcast::Sender<Message>,
+    rx: broadcast::Receiver<Message>,
+}
+
+impl Membership {
+    /// Join `room`; also returns the history to replay
+    fn new(rooms: &Rooms, room: &str, addr: SocketAddr, who: String) -> (Self, Vec<String>) {
+        let (sender, rx, history) = enter(rooms, room);
+        let member = Self {
+            room: room.to_string(),
+            addr,
+            who,
+            sender,
+            rx,
+        };
+        (member, history)
+    }
+
+    /// Notices (joins, leaves, renames): sent, not kept in the history
+    fn announce(&self, text: String) {
+        let _ = self.sender.send((text, self.addr));
+    }
+
+    /// A chat line: kept in the room's history and sent
+    fn say(&self, rooms: &Rooms, text: String) {
+        post(rooms, &self.room, text, self.addr);
+    }
+
+    /// Say goodbye in the old room, hello in the new one; returns the old
+    /// name and the new room's history
+    fn move_to(&mut self, rooms: &Rooms, room: &str) -> (String, Vec<String>) {
+        self.announce(format!("[{}] left #{}\n", self.who, self.room));
+        // Join the new room before leaving the old one, so no message meant
+        // for this client falls in between
+        let (new, history) = Membership::new(rooms, room, self.addr, self.who.clone());
+        let old = std::mem::replace(self, new);
+        let old_room = old.room.clone();
+        old.leave(rooms);
+        println!("[{}] moved from #{} to #{}", self.who, old_room, room);
+        self.announce(format!("[{}] joined #{}\n", self.who, room));
+        (old_room, history)
+    }
+
+    fn leave(self, rooms: &Rooms) {
+        let Membership { room, rx, .. } = self;
+        drop(rx);
+        exit(rooms, &room);
+    }
+}
+
+/// Keep a chat line in `room`'s history and send it to its members, but
+/// not back to `from`; false if the room doesn't exist
+fn post(rooms: &Rooms, room: &str, text: String, from: SocketAddr) -> bool {
+    let mut rooms = rooms.lock().unwrap();
+    let Some(room) = rooms.get_mut(room) else {
+        return false;
+    };
+    if room.history.len() == HISTORY_LEN {
+        room.history.pop_front();
+    }
+    room.history.push_back(text.clone());
+    // Still under the lock: see `enter`
+    let _ = room.sender.send((text, from));
+    true
+}
+
+/// Stands in for the sender of lines from other servers: no client has it
+const RELAYED_FROM: SocketAddr = SocketAddr::new(std::net::IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0);
+
+/// Post lines from other servers into the rooms they were said in, if
+/// anyone here is in a room of that name
+async fn deliver(
+    mut relayed: mpsc::UnboundedReceiver<Relayed>,
+    rooms: Rooms,
+    archive: Arc<Archive>,
 ) {
-    // Subscribe to receive broadcast messages
-    let mut rx = sender.subscribe();
+    while let Some(Relayed {
+        origin,
+        room,
+        nick,
+        text,
+    }) = relayed.recv().await
+    {
+        let sender = format!("{}@{}", nick, origin);
+        let msg = format!("[{}]: {}\n", sender, text);
+        if post(&rooms, &room, msg.clone(), RELAYED_FROM) {
+            println!("#{} {}", room, msg.trim());
+            archive.record(&sender, &room, &text);
+        }
+    }
+}
+
+/// Take a nickname if nobody has it
+fn claim(users: &Users, nick: &str, direct: &mpsc::UnboundedSender<String>) -> bool {
+    let mut users = users.write().unwrap();
+    if users.contains_key(nick) {
+        return false;
+    }
+    let user = User {
+        room: LOBBY.to_string(),
+        direct: direct.clone(),
+        since: Instant::now(),
+        active: AtomicU64::new(0),
+    };
+    users.insert(nick.to_string(), user);
+    true
+}
+
+/// The first free `guest<n>`
+fn claim_guest(users: &Users, direct: &mpsc::UnboundedSender<String>) -> String {
+    static NEXT: AtomicUsize = AtomicUsize::new(1);
+    loop {
+        let nick = format!("guest{}", NEXT.fetch_add(1, Ordering::Relaxed));
+        // Someone may have picked it with /nick already
+        if claim(users, &nick, direct) {
+            return nick;
+        }
+    }
+}
+
+/// Move `old`'s entry to `new`; false if `new` is taken
+fn rename(users: &Users, old: &str, new: &str) -> bool {
+    let mut users = users.write().unwrap();
+    if users.contains_key(new) {
+        return false;
+    }
+    match users.remove(old) {
+        Some(user) => {
+            users.insert(new.to_string(), user);
+            true
+        }
+        None => false,
+    }
+}
+
+fn locate(users: &Users, nick: &str, room: &str) {
+    if let Some(user) = users.write().unwrap().get_mut(nick) {
+        user.room = room.to_string();
+    }
+}
+
+/// `nick` just said or asked something
+fn touch(users: &Users, nick: &str) {
+    if let Some(user) = users.read().unwrap().get(nick) {
+        let millis = user.since.elapsed().as_millis() as u64;
+        user.active.store(millis, Ordering::Relaxed);
+    }
+}
+
+/// Hand `text` to `nick`'s own channel; false if nobody has that name
+fn send_direct(users: &Users, nick: &str, text: String) -> bool {
+    let users = users.read().unwrap();
+    // A send fails only if the client is on its way out: same as not there
+    users
+        .get(nick)
+        .is_some_and(|user| user.direct.send(text).is_ok())
+}
+
+/// `45s`, `3m 05s`, `2h 03m`
+fn short(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m {:02}s", secs / 60, secs % 60),
+        _ => format!("{}h {:02}m", secs / 3600, secs / 60 % 60),
+    }
+}
+
+/// `In #rust: alice, bob`, then a line per member with connect and idle time
+fn who_list(users: &Users, room: &str) -> String {
+    let users = users.read().unwrap();
+    let mut members: Vec<(&str, &User)> = users
+        .iter()
+        .filter(|(_, user)| user.room == room)
+        .map(|(nick, user)| (nick.as_str(), user))
+        .collect();
+    members.sort_by_key(|(nick, _)| *nick);
+
+    let nicks: Vec<&str> = members.iter().map(|(nick, _)| *nick).collect();
+    let width = nicks.iter().map(|nick| nick.len()).max().unwrap_or(0);
+    let mut list = format!("In #{}: {}\n", room, nicks.join(", "));
+    for (nick, user) in members {
+        list.push_str(&format!(
+            "  {:width$}  online {}, idle {}\n",
+            nick,
+            short(user.since.elapsed()),
+            short(user.idle()),
+        ));
+    }
+    list
+}
+
+const HELP: &str = "\
+Commands:
+  /nick <name>   change your nickname
+  /msg <nick> <text>  send a private message
+  /join <room>   move to a room (created if needed)
+  /leave         go back to #lobby
+  /rooms         list rooms
+  /who           who is in your room, online and idle for how long
+  /history       show the room's recent messages again
+  /history <n>   show the room's last n messages from the archive
+  /stats         server statistics (admins only)
+  /quit          disconnect
+  PING [n]       check the server is there (it answers PONG [n])
+";
+
+/// The history block sent on join and for /history; empty if there is none
+fn replay(room: &str, history: &[String]) -> String {
+    if history.is_empty() {
+        return String::new();
+    }
+    format!(
+        "--- last {} message(s) in #{} ---\n{}--- end of history ---\n",
+        history.len(),
+        room,
+        history.concat()
+    )
+}
+
+/// `/history <n>`: the room's last n lines from the database
+async fn recall(archive: &Archive, room: &str, n: &str) -> String {
+    let n = match n.parse::<usize>() {
+        Ok(n) if (1..=archive::MAX_QUERY).contains(&n) => n,
+        _ => return format!("Usage: /history <n>, n from 1 to {}\n", archive::MAX_QUERY),
+    };
+    let rows = match archive.recent(room, n).await {
+        Ok(rows) => rows,
+        Err(err) => {
+            eprintln!("Archive: query failed: {}", err);
+            return "History is unavailable\n".to_string();
+        }
+    };
+    if rows.is_empty() {
+        return format!("No messages in #{} yet\n", room);
+    }
+    let mut block = format!(
+        "--- last {} message(s) in #{} from the archive ---\n",
+        rows.len(),
+        room
+    );
+    for row in rows {
+        block.push_str(&format!(
+            "[{}] [{}]: {}\n",
+            archive::clock(row.sent_at),
+            row.sender,
+            row.text
+        ));
+    }
+    block.push_str("--- end of history ---\n");
+    block
+}
+
+/// `#lobby (1), #rust (2)`
+fn room_counts(rooms: &Rooms) -> String {
+    let rooms = rooms.lock().unwrap();
+    let mut names: Vec<&String> = rooms.keys().collect();
+    names.sort();
+    let list: Vec<String> = names
+        .into_iter()
+        .map(|name| format!("#{} ({})", name, rooms[name].sender.receiver_count()))
+        .collect();
+    list.join(", ")
+}
+
+/// `Rooms: #lobby (1), #rust (2) - you are in #rust`
+fn room_list(rooms: &Rooms, current: &str) -> String {
+    format!("Rooms: {} - you are in #{}\n", room_counts(rooms), current)
+}
+
+/// Rules for room names and nicknames
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// What a line from the client asks for
+enum Command<'a> {
+    Join(&'a str),
+    Leave,
+    List,
+    Nick(&'a str),
+    /// (to, text)
+    Msg(&'a str, &'a str),
+    Who,
+    History,
+    /// /history <n>, n not yet checked
+    Recall(&'a str),
+    Help,
+    Stats,
+    Quit,
+    /// PING from the client, with its optional token
+    Ping(&'a str),
+    /// PONG answering the server's PING
+    Pong(&'a str),
+    Unknown(&'a str),
+    Say,
+}
+
+fn parse_command(line: &str) -> Command<'_> {
+    let line = line.trim();
+    // Keepalive words, not chat; the token is optional
+    if let Some(rest) = line.strip_prefix("PING") {
+        if rest.is_empty() || rest.starts_with(' ') {
+            return Command::Ping(rest.trim());
+        }
+    }
+    if let Some(rest) = line.strip_prefix("PONG") {
+        if rest.is_empty() || rest.starts_with(' ') {
+            return Command::Pong(rest.trim());
+        }
+    }
+    if !line.starts_with('/') {
+        return Command::Say;
+    }
+    // The text may hold any number of words, so /msg doesn't fit the match
+    if let Some(rest) = line.strip_prefix("/msg ") {
+        return match rest.trim_start().split_once(char::is_whitespace) {
+            Some((nick, text)) if !text.trim().is_empty() => Command::Msg(nick, text.trim()),
+            _ => Command::Unknown(line),
+        };
+    }
+    let mut words = line.split_whitespace();
+    match (words.next(), words.next()) {
+        (Some("/join"), Some(room)) => Command::Join(room),
+        (Some("/leave"), None) => Command::Leave,
+        (Some("/rooms"), None) => Command::List,
+        (Some("/nick"), Some(nick)) => Command::Nick(nick),
+        (Some("/who"), None) => Command::Who,
+        (Some("/history"), None) => Command::History,
+        (Some("/history"), Some(n)) => Command::Recall(n),
+        (Some("/help"), None) => Command::Help,
+        (Some("/stats"), None) => Command::Stats,
+        (Some("/quit"), None) => Command::Quit,
+        _ => Command::Unknown(line),
+    }
+}
+
+/// Server-wide state every connection gets a handle to
+#[derive(Clone)]
+struct Shared {
+    rooms: Rooms,
+    users: Users,
+    auth: Arc<Auth>,
+    archive: Arc<Archive>,
+    stats: Arc<Stats>,
+    /// Links to other servers; without any, publishing goes nowhere
+    federation: Arc<Federation>,
+    /// Cancelled when the server is shutting down
+    shutdown: CancellationToken,
+}
+
+/// Handle a single client connection
+async fn handle_client<S>(stream: S, addr: SocketAddr, shared: Shared, limits: Limits)
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let Shared {
+        rooms,
+        users,
+        auth,
+        archive,
+        stats,
+        federation,
+        shutdown,
+    } = shared;
 
     // Split the stream into reader and writer
-    let (reader, mut writer) = stream.into_split();
+    let (reader, mut writer) = tokio::io::split(stream);
     let mut reader = BufReader::new(reader);
     let mut line = String::new();
 
+    // Private messages for this client; the sender goes into the registry
+    let (direct, mut direct_rx) = mpsc::unbounded_channel::<String>();
+
+    // Nobody gets into a room before proving who they are
+    let identity = if auth.enabled() {
+        match auth.handshake(&mut reader, &mut writer).await {
+            Ok(identity) => Some(identity),
+            Err(reason) => {
+                println!("[{}] refused: {}", addr, reason);
+                return;
+            }
+        }
+    } else {
+        None
+    };
+    // Named clients keep their name; guests may pick one with /nick
+    let named = matches!(identity, Some(Identity::Named(_)));
+    let admin = matches!(&identity, Some(Identity::Named(name)) if auth.is_admin(name));
+    let who = if let Some(Identity::Named(name)) = identity {
+        // One connection per name: it is the nickname, and those are unique
+        if !claim(&users, &name, &direct) {
+            println!("[{}] refused: {} is already connected", addr, name);
+            let reply = format!("ERR {} is already connected\n", name);
+            let _ = writer.write_all(reply.as_bytes()).await;
+            return;
+        }
+        let _ = writer
+            .write_all(format!("OK welcome, {}\n", name).as_bytes())
+            .await;
+        name
+    } else if identity.is_some() {
+        let name = claim_guest(&users, &direct);
+        let greeting = format!(
+            "OK welcome, {}! /nick <name> to pick a name, /help for commands\n",
+            name
+        );
+        let _ = writer.write_all(greeting.as_bytes()).await;
+        name
+    } else {
+        let name = claim_guest(&users, &direct);
+        let greeting = format!(
+            "Welcome, {}! /nick <name> to pick a name, /help for commands\n",
+            name
+        );
+        let _ = writer.write_all(greeting.as_bytes()).await;
+        name
+    };
+
+    // Subscribe to receive the lobby's messages
+    let (mut member, history) = Membership::new(&rooms, LOBBY, addr, who);
+
     // Notify about new connection
-    let join_msg = format!("[{}] joined the chat\n", addr);
+    let join_msg = format!("[{}] joined the chat\n", member.who);
     println!("{}", join_msg.trim());
-    let _ = sender.send((join_msg, addr));
+    member.announce(join_msg);
+
+    // Catch the newcomer up on what was said before
+    let _ = writer.write_all(replay(LOBBY, &history).as_bytes()).await;
+
+    let mut guard = flood::Guard::new(limits.flood);
+    let mut keepalive = Keepalive::new(limits.keepalive);
 
     loop {
         tokio::select! {
@@ -79,17 +844,170 @@ async fn handle_client(
                 match result {
                     Ok(0) => {
                         // Client disconnected
-                        let leave_msg = format!("[{}] left the chat\n", addr);
-                        println!("{}", leave_msg.trim());
-                        let _ = sender.send((leave_msg, addr));
                         break;
                     }
                     Ok(_) => {
-                        // Broadcast message to all clients
-                        let msg = format!("[{}]: {}", addr, line);
-                        println!("{}", msg.trim());
-                        let _ = sender.send((msg, addr));
+                        // Every line costs a token, commands too: /msg and
+                        // /join can flood as well. /quit is always let
+                        // out, and so is the answer to our own PING
+                        let verdict = match parse_command(&line) {
+                            Command::Quit => flood::Verdict::Allow,
+                            Command::Pong(token) if keepalive.answers(token) => {
+                                flood::Verdict::Allow
+                            }
+                            _ => guard.check(),
+                        };
+                        // Any line shows the client is alive; only chat
+                        // lines and commands show it is in use
+                        match parse_command(&line) {
+                            Command::Ping(_) | Command::Pong(_) => keepalive.heard(),
+                            _ => {
+                                keepalive.active();
+                                touch(&users, &member.who);
+                            }
+                        }
+                        let refusal = match verdict {
+                            flood::Verdict::Allow => None,
+                            flood::Verdict::Refuse(notice) => Some(notice),
+                            flood::Verdict::Mute(notice) => {
+                                let msg = format!("[{}] was muted for flooding\n", member.who);
+                                println!("{}", msg.trim());
+                                member.announce(msg);
+                                Some(notice)
+                            }
+                            flood::Verdict::Disconnect(notice) => {
+                                println!("[{}] disconnected for flooding", member.who);
+                                let _ = writer.write_all(notice.as_bytes()).await;
+                                break;
+                            }
+                        };
+                        if let Some(notice) = refusal {
+                            line.clear();
+                            if let Err(err) = writer.write_all(notice.as_bytes()).await {
+                                eprintln!("[{}] Write error: {}", addr, err);
+                                break;
+                            }
+                            continue;
+                        }
+
+                        // Commands are answered to this client only
+                        let reply = match parse_command(&line) {
+                            Command::Say | Command::Msg(..) if guard.muted_for().is_some() => {
+                                Some(format!(
+                                    "You are muted for {} more second(s)\n",
+                                    guard.muted_for().unwrap_or(0)
+                                ))
+                            }
+                            Command::Say => {
+                                // Broadcast message to the room
+                                let msg = format!("[{}]: {}", member.who, line);
+                                println!("#{} {}", member.room, msg.trim());
+                                member.say(&rooms, msg);
+                                archive.record(&member.who, &member.room, line.trim_end());
+                                federation.publish(&member.room, &member.who, line.trim_end());
+                                stats.message();
+                                None
+                            }
+                            Command::Join(name) if name == member.room => {
+                                Some(format!("You are already in #{}\n", name))
+                            }
+                            Command::Join(name) if !valid_name(name) => Some(format!(
+                                "Room names are 1-{} letters, digits, '-' or '_'\n",
+                                MAX_NAME
+                            )),
+                            Command::Join(name) => {
+                                let (_, history) = member.move_to(&rooms, name);
+                                locate(&users, &member.who, name);
+                                Some(format!("You joined #{}\n{}", name, replay(name, &history)))
+                            }
+                            Command::Leave if member.room == LOBBY => {
+                                Some(format!("You are in #{} already\n", LOBBY))
+                            }
+                            Command::Leave => {
+                                let (old, history) = member.move_to(&rooms, LOBBY);
+                                locate(&users, &member.who, LOBBY);
+                                Some(format!(
+                                    "You left #{}, back in #{}\n{}",
+                                    old,
+                                    LOBBY,
+                                    replay(LOBBY, &history)
+                                ))
+                            }
+                            Command::List => Some(room_list(&rooms, &member.room)),
+                            Command::Nick(_) if named => Some(format!(
+                                "Your nickname comes with your login, you stay {}\n",
+                                member.who
+                            )),
+                            Command::Nick(nick) if nick == member.who => {
+                                Some(format!("You are already {}\n", nick))
+                            }
+                            Command::Nick(nick) if !valid_name(nick) => Some(format!(
+                                "Nicknames are 1-{} letters, digits, '-' or '_'\n",
+                                MAX_NAME
+                            )),
+                            Command::Nick(nick) if !rename(&users, &member.who, nick) => {
+                                Some(format!("Nickname {} is taken\n", nick))
+                            }
+                            Command::Nick(nick) => {
+                                let msg = format!("[{}] is now known as {}\n", member.who, nick);
+                                println!("{}", msg.trim());
+                                member.announce(msg);
+                                member.who = nick.to_string();
+                                Some(format!("You are now known as {}\n", nick))
+                            }
+                            Command::Msg(to, _) if to == member.who => {
+                                Some("You can't /msg yourself\n".to_string())
+                            }
+                            Command::Msg(to, text) => {
+                                let msg = format!("[{} -> you] {}\n", member.who, text);
+                                if send_direct(&users, to, msg) {
+                                    stats.message();
+                                    Some(format!("(to {}) {}\n", to, text))
+                                } else {
+                                    Some(format!("No user named {}\n", to))
+                                }
+                            }
+                            Command::Who => Some(who_list(&users, &member.room)),
+                            Command::History => {
+                                let history = rooms.lock().unwrap()[&member.room]
+                                    .history
+                                    .iter()
+                                    .cloned()
+                                    .collect::<Vec<_>>();
+                                match replay(&member.room, &history) {
+                                    block if block.is_empty() => {
+                                        Some(format!("No messages in #{} yet\n", member.room))
+                                    }
+                                    block => Some(block),
+                                }
+                            }
+                            Command::Recall(n) => Some(recall(&archive, &member.room, n).await),
+                            Command::Help => Some(HELP.to_string()),
+                            Command::Stats if !admin => {
+                                Some("/stats is for admins only\n".to_string())
+                            }
+                            Command::Stats => Some(
+                                stats.report(&format!("Rooms: {}\n", room_counts(&rooms))),
+                            ),
+                            Command::Ping("") => Some("PONG\n".to_string()),
+                            Command::Ping(token) => Some(format!("PONG {}\n", token)),
+                            Command::Pong(_) => None,
+                            Command::Quit => {
+                                let _ = writer.write_all(b"Bye!\n").await;
+                                break;
+                            }
+                            Command::Unknown(command) => Some(format!(
+                                "Unknown command {}. Try /help\n",
+                                command
+                            )),
+                        };
                         line.clear();
+                        if let Some(reply) = reply {
+                            if let Err(err) = writer.write_all(reply.as_bytes()).await {
+                                eprintln!("[{}] Write error: {}", addr, err);
+                                break;
+                            }
+                        }
                     }
                     Err(err) => {
                         eprintln!("[{}] Read error: {}", addr, err);
@@ -99,7 +1017,7 @@ async fn handle_client(
             }
 
             // Receive broadcast messages and send to this client
-            result = rx.recv() => {
+            result = member.rx.recv() => {
                 match result {
                     Ok((msg, sender_addr)) => {
                         // Don't send message back to sender
@@ -118,35 +1036,461 @@ async fn handle_client(
                     }
                 }
             }
+
+            // Private messages, only for this client
+            Some(msg) = direct_rx.recv() => {
+                if let Err(err) = writer.write_all(msg.as_bytes()).await {
+                    eprintln!("[{}] Write error: {}", addr, err);
+                    break;
+                }
+            }
+
+            // The server is going down: deliver what is already queued for
+            // this client, say goodbye and close our side
+            _ = shutdown.cancelled() => {
+                let mut pending = String::new();
+                loop {
+                    match member.rx.try_recv() {
+                        Ok((msg, sender_addr)) if sender_addr != addr => pending.push_str(&msg),
+                        Ok(_) | Err(broadcast::error::TryRecvError::Lagged(_)) => {}
+                        Err(_) => break,
+                    }
+                }
+                while let Ok(msg) = direct_rx.try_recv() {
+                    pending.push_str(&msg);
+                }
+                pending.push_str(FAREWELL);
+                let _ = writer.write_all(pending.as_bytes()).await;
+                let _ = writer.shutdown().await;
+                break;
+            }
+
+            // Idle timeout and PING/PONG
+            _ = keepalive::wait(keepalive.deadline()) => {
+                match keepalive.fire() {
+                    Some(keepalive::Action::Ping(ping)) => {
+                        if let Err(err) = writer.write_all(ping.as_bytes()).await {
+                            eprintln!("[{}] Write error: {}", addr, err);
+                            break;
+                        }
+                    }
+                    Some(keepalive::Action::Disconnect(notice)) => {
+                        println!("[{}] {}", member.who, notice.trim());
+                        let _ = writer.write_all(notice.as_bytes()).await;
+                        break;
+                    }
+                    None => {}
+                }
+            }
+        }
+    }
+
+    let leave_msg = format!("[{}] left the chat\n", member.who);
+    println!("{}", leave_msg.trim());
+    // Everyone is leaving at once then, nobody needs to be told
+    if !shutdown.is_cancelled() {
+        member.announce(leave_msg);
+    }
+    users.write().unwrap().remove(&member.who);
+    member.leave(&rooms);
+}
+
+/// Bind the listening socket. For IPv6, set IPV6_V6ONLY explicitly: the
+/// default differs between systems (Linux: sysctl net.ipv6.bindv6only)
+fn bind(addr: SocketAddr, v6only: bool) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(v6only)?;
+    }
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    socket.set_nonblocking(true)?;
+    TcpListener::from_std(socket.into())
+}
+
+/// Last line every client gets when the server shuts down
+const FAREWELL: &str = "Server shutting down, goodbye!\n";
+
+/// How long shutdown waits for clients to be closed
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+
+/// Ctrl-C, or SIGTERM from `kill` or a service manager
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut term = signal(SignalKind::terminate()).expect("Failed to watch SIGTERM");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = term.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Longest a client may take to finish the TLS handshake
+const TLS_HANDSHAKE: Duration = Duration::from_secs(10);
+
+/// `handle_client` on a plain or TLS stream, in lines or frames
+async fn serve<S>(stream: S, addr: SocketAddr, shared: Shared, limits: Limits, mode: Framing)
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    match mode {
+        Framing::Lines => handle_client(stream, addr, shared, limits).await,
+        Framing::Binary => handle_client(framing::bridge(stream), addr, shared, limits).await,
+    }
+}
+
+/// Per-connection limits from the command line
+#[derive(Clone, Copy, Debug, Default)]
+struct Limits {
+    flood: flood::Limits,
+    keepalive: keepalive::Limits,
+}
+
+/// How messages are delimited on the wire
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Framing {
+    /// One message per `\n`-terminated line
+    Lines,
+    /// Type byte, u32 length, payload: see `framing.rs`
+    Binary,
+}
+
+struct Config {
+    bind: SocketAddr,
+    v6only: bool,
+    max_clients: usize,
+    framing: Framing,
+    /// Certificate and key files, if connections are to be encrypted
+    tls: Option<(PathBuf, PathBuf)>,
+    /// `--gen-cert`: write a certificate here instead of serving
+    gen_cert: Option<PathBuf>,
+    /// This server's name to other servers
+    node: Option<String>,
+    /// Port other servers link to
+    link_port: Option<u16>,
+    /// Servers to link to
+    peers: Vec<String>,
+    limits: Limits,
+}
+
+fn usage() -> String {
+    "usage: chat_server [PORT | --bind ADDR [--v6only]] [--max-clients N] [--framing lines|binary] \
+     [--rate LINES_PER_SEC] [--burst LINES] [--mute-secs SECS] [--idle-secs SECS] \
+     [--ping-secs SECS] [--pong-secs SECS] [--tls-cert FILE --tls-key FILE] \
+     [--node NAME] [--link-port N] [--peer HOST:PORT]...\n\
+     \x20      chat_server --gen-cert DIR"
+        .to_string()
+}
+
+fn parse_args(args: Vec<String>) -> Result<Config, String> {
+    let mut port = None;
+    let mut bind = None;
+    let mut config = Config {
+        bind: (Ipv4Addr::UNSPECIFIED, 8080).into(),
+        v6only: false,
+        max_clients: 100,
+        framing: Framing::Lines,
+        tls: None,
+        gen_cert: None,
+        node: None,
+        link_port: None,
+        peers: Vec::new(),
+        limits: Limits::default(),
+    };
+    let (mut cert, mut key) = (None, None);
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") && port.is_none() {
+            port = Some(
+                arg.parse::<u16>()
+                    .map_err(|_| format!("invalid port: {}", arg))?,
+            );
+            continue;
+        }
+        if arg == "--v6only" {
+            config.v6only = true;
+            continue;
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value", arg))?;
+        match arg.as_str() {
+            "--bind" => bind = Some(value),
+            "--max-clients" => match value.parse::<usize>() {
+                Ok(max) if max >= 1 => config.max_clients = max,
+                _ => return Err(format!("--max-clients must be at least 1, got {}", value)),
+            },
+            "--tls-cert" => cert = Some(PathBuf::from(value)),
+            "--tls-key" => key = Some(PathBuf::from(value)),
+            "--gen-cert" => config.gen_cert = Some(PathBuf::from(value)),
+            "--node" if valid_name(&value) => config.node = Some(value),
+            "--node" => {
+                return Err(format!(
+                    "--node is 1-{} letters, digits, '-' or '_', got {}",
+                    MAX_NAME, value
+                ))
+            }
+            "--link-port" => match value.parse::<u16>() {
+                Ok(port) => config.link_port = Some(port),
+                _ => return Err(format!("invalid --link-port: {}", value)),
+            },
+            "--peer" => config.peers.push(value),
+            "--framing" => match value.as_str() {
+                "lines" => config.framing = Framing::Lines,
+                "binary" => config.framing = Framing::Binary,
+                _ => return Err(format!("--framing must be lines or binary, got {}", value)),
+            },
+            "--rate" => match value.parse::<f64>() {
+                Ok(rate) if rate > 0.0 && rate.is_finite() => config.limits.flood.rate = rate,
+                _ => return Err(format!("--rate must be a positive number, got {}", value)),
+            },
+            "--burst" => match value.parse::<u32>() {
+                Ok(burst) if burst >= 1 => config.limits.flood.burst = f64::from(burst),
+                _ => return Err(format!("--burst must be at least 1, got {}", value)),
+            },
+            "--mute-secs" => match value.parse::<u64>() {
+                Ok(secs) if secs >= 1 => config.limits.flood.mute = Duration::from_secs(secs),
+                _ => return Err(format!("--mute-secs must be at least 1, got {}", value)),
+            },
+            // 0 turns the idle timeout and the pings off
+            "--idle-secs" => match value.parse::<u64>() {
+                Ok(0) => config.limits.keepalive.idle = None,
+                Ok(secs) => config.limits.keepalive.idle = Some(Duration::from_secs(secs)),
+                _ => return Err(format!("--idle-secs must be 0 or more, got {}", value)),
+            },
+            "--ping-secs" => match value.parse::<u64>() {
+                Ok(0) => config.limits.keepalive.ping = None,
+                Ok(secs) => config.limits.keepalive.ping = Some(Duration::from_secs(secs)),
+                _ => return Err(format!("--ping-secs must be 0 or more, got {}", value)),
+            },
+            "--pong-secs" => match value.parse::<u64>() {
+                Ok(secs) if secs >= 1 => config.limits.keepalive.pong = Duration::from_secs(secs),
+                _ => return Err(format!("--pong-secs must be at least 1, got {}", value)),
+            },
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+    match (port, bind) {
+        (Some(_), Some(_)) => return Err("give either PORT or --bind, not both".to_string()),
+        (Some(port), None) => config.bind.set_port(port),
+        (None, Some(bind)) => {
+            config.bind = bind
+                .to_socket_addrs()
+                .ok()
+                .and_then(|mut addrs| addrs.next())
+                .ok_or_else(|| format!("cannot resolve {}", bind))?;
         }
+        (None, None) => {}
     }
+    if config.v6only && !config.bind.is_ipv6() {
+        return Err("--v6only needs an IPv6 --bind address".to_string());
+    }
+    config.tls = match (cert, key) {
+        (Some(cert), Some(key)) => Some((cert, key)),
+        (None, None) => None,
+        _ => return Err("--tls-cert and --tls-key go together".to_string()),
+    };
+    Ok(config)
 }
 
 #[tokio::main]
 async fn main() {
-    // let addr = "127.0.0.1:8080";
-    let addr = "0.0.0.0:8080";
+    let config = match parse_args(std::env::args().skip(1).collect()) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}\n{}", err, usage());
+            std::process::exit(2);
+        }
+    };
+    if let Some(dir) = &config.gen_cert {
+        match tls::generate_self_signed(dir) {
+            Ok((cert, key)) => {
+                println!(
+                    "Wrote {} and {} (self-signed for {})",
+                    cert,
+                    key,
+                    tls::local_names()
+                );
+                return;
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+    let addr = config.bind;
 
+    let tls = match &config.tls {
+        Some((cert, key)) => match tls::acceptor(cert, key) {
+            Ok(acceptor) => Some(acceptor),
+            Err(err) => {
+                eprintln!("Invalid TLS configuration: {}", err);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
 
-    // Create a broadcast channel for message distribution
-    let (tx, _rx) = broadcast::channel::<(String, SocketAddr)>(100);
+    // One broadcast channel per room, created on first /join
+    let rooms: Rooms = Arc::new(Mutex::new(HashMap::new()));
 
-    let listener = TcpListener::bind(addr).await.expect("Failed to bind");
+    // Nicknames in use
+    let users: Users = Arc::new(RwLock::new(HashMap::new()));
+
+    let auth = match Auth::from_env() {
+        Ok(auth) => Arc::new(auth),
+        Err(err) => {
+            eprintln!("Invalid auth configuration: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    // Every chat line, also after a restart if CHAT_DB is a file
+    let db_url = std::env::var("CHAT_DB").unwrap_or_else(|_| "sqlite::memory:".to_string());
+    let archive = match Archive::open(&db_url).await {
+        Ok(archive) => Arc::new(archive),
+        Err(err) => {
+            eprintln!("Cannot open {}: {}", db_url, err);
+            std::process::exit(1);
+        }
+    };
+
+    // Seats for --max-clients connections, and the numbers /stats shows
+    let stats = Stats::new(config.max_clients);
+
+    let listener = bind(addr, config.v6only).expect("Failed to bind");
+
+    // Other servers, and the lines they send for our rooms
+    let node = config
+        .node
+        .clone()
+        .unwrap_or_else(|| format!("node-{}", addr.port()));
+    let (federation, relayed) = Federation::new(node);
+    tokio::spawn(deliver(relayed, Arc::clone(&rooms), Arc::clone(&archive)));
+    if let Some(port) = config.link_port {
+        let links = TcpListener::bind(SocketAddr::new(addr.ip(), port))
+            .await
+            .expect("Failed to bind the link port");
+        tokio::spawn(Arc::clone(&federation).listen(links));
+    }
+    for peer in &config.peers {
+        tokio::spawn(Arc::clone(&federation).dial(peer.clone()));
+    }
+
+    let shared = Shared {
+        rooms,
+        users,
+        auth: Arc::clone(&auth),
+        archive,
+        stats,
+        federation: Arc::clone(&federation),
+        shutdown: CancellationToken::new(),
+    };
 
     println!("TCP Chat Server");
-    println!("Listening on {}", addr);
-    println!("\nTest with: nc localhost 8080");
+    if addr.is_ipv6() && !config.v6only {
+        println!("Listening on {} (dual-stack)", addr);
+    } else {
+        println!("Listening on {}", addr);
+    }
+    println!("\nTest with: nc localhost {}", addr.port());
     println!("Open multiple terminals to chat!\n");
+    println!("Messages archived in {}\n", db_url);
+    if auth.enabled() {
+        println!("Clients must send {} first\n", auth.ways());
+    }
+    println!("At most {} clients at once\n", config.max_clients);
+    if let Some((cert, _)) = &config.tls {
+        println!(
+            "TLS with {}: nc won't do, try openssl s_client\n",
+            cert.display()
+        );
+    }
+    if config.link_port.is_some() || !config.peers.is_empty() {
+        println!("Federation as node {}", federation.node());
+        if let Some(port) = config.link_port {
+            println!("  servers link to port {}", port);
+        }
+        for peer in &config.peers {
+            println!("  linking to {}", peer);
+        }
+        println!();
+    }
+    if config.framing == Framing::Binary {
+        println!("Binary framing: type byte + u32 length + payload (nc won't do)\n");
+    }
+    println!(
+        "Flood limit: {} lines/s, bursts of {}, mute {}s\n",
+        config.limits.flood.rate,
+        config.limits.flood.burst,
+        config.limits.flood.mute.as_secs()
+    );
+    match config.limits.keepalive.idle {
+        Some(idle) => println!("Idle timeout: {}s", idle.as_secs()),
+        None => println!("Idle timeout: off"),
+    }
+    match config.limits.keepalive.ping {
+        Some(ping) => println!(
+            "PING after {}s of silence, PONG due within {}s\n",
+            ping.as_secs(),
+            config.limits.keepalive.pong.as_secs()
+        ),
+        None => println!("PING: off\n"),
+    }
     // 2. Create TcpListener
     // 3. Loop accepting connections
+    // Listen before the first accept, so a signal is never missed
+    let signal = shutdown_signal();
+    tokio::pin!(signal);
     loop {
-        match listener.accept().await {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = &mut signal => break,
+        };
+        match accepted {
             Ok((stream, client_addr)) => {
                 println!("New connection from {}", client_addr);
 
-                let tx_clone = tx.clone();
+                let seat = match shared.stats.admit() {
+                    Ok(seat) => seat,
+                    Err(notice) => {
+                        println!("[{}] turned away: server full", client_addr);
+                        // A fresh socket's send buffer is empty, so this
+                        // won't block; dropping the stream closes it
+                        let _ = stream.try_write(notice.as_bytes());
+                        continue;
+                    }
+                };
+
+                let shared = shared.clone();
+                let limits = config.limits;
+                let mode = config.framing;
+                let tls = tls.clone();
                 tokio::spawn(async move {
-                    handle_client(stream, client_addr, tx_clone).await;
+                    match tls {
+                        Some(acceptor) => {
+                            let handshake = acceptor.accept(stream);
+                            match tokio::time::timeout(TLS_HANDSHAKE, handshake).await {
+                                Ok(Ok(stream)) => {
+                                    serve(stream, client_addr, shared, limits, mode).await
+                                }
+                                Ok(Err(err)) => {
+                                    println!("[{}] TLS handshake failed: {}", client_addr, err)
+                                }
+                                Err(_) => println!("[{}] TLS handshake timed out", client_addr),
+                            }
+                        }
+                        None => serve(stream, client_addr, shared, limits, mode).await,
+                    }
+                    // The place is free once the client is gone
+                    drop(seat);
                 });
             }
             Err(err) => {
@@ -155,4 +1499,24 @@ async fn main() {
         }
     }
     // 4. Spawn handle_client for each connection
+
+    // No more accepts: connections from here on are refused
+    drop(listener);
+    println!(
+        "\nShutting down: saying goodbye to {} client(s)",
+        shared.stats.connected()
+    );
+    shared.shutdown.cancel();
+
+    // A seat is given back when its client task ends
+    let deadline = tokio::time::Instant::now() + SHUTDOWN_GRACE;
+    while shared.stats.connected() > 0 && tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    match shared.stats.connected() {
+        0 => {}
+        left => println!("{} client(s) not closed in time, leaving anyway", left),
+    }
+    shared.archive.close().await;
+    println!("Shutdown complete");
 }
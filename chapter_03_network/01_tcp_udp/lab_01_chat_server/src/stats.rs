@@ -0,0 +1,146 @@
+//! Admission control and server-wide counters
+//!
+//! At most `--max-clients` connections are served at once. The next one
+//! gets a line and is closed right away, before authentication, so a
+//! crowd of unauthenticated sockets can't hold the server open either:
+//!
+//! ```text
+//! $ nc localhost 8080
+//! Server full (100/100 clients), try again later
+//! ```
+//!
+//! A `Seat` is held for as long as a connection is served; dropping it
+//! frees the place. The counters behind it feed `/stats`, which only
+//! admins (`CHAT_ADMINS`, see `auth.rs`) may use.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How far back the "recent" message rate looks
+const WINDOW: Duration = Duration::from_secs(60);
+
+pub struct Stats {
+    max: usize,
+    started: Instant,
+    connected: AtomicUsize,
+    accepted: AtomicU64,
+    refused: AtomicU64,
+    messages: AtomicU64,
+    /// Messages per second, for the last `WINDOW`: (second since start, count)
+    recent: Mutex<VecDeque<(u64, u64)>>,
+}
+
+/// A place on the server; given back on drop
+pub struct Seat {
+    stats: Arc<Stats>,
+}
+
+impl Drop for Seat {
+    fn drop(&mut self) {
+        self.stats.connected.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Stats {
+    pub fn new(max: usize) -> Arc<Self> {
+        Arc::new(Self {
+            max,
+            started: Instant::now(),
+            connected: AtomicUsize::new(0),
+            accepted: AtomicU64::new(0),
+            refused: AtomicU64::new(0),
+            messages: AtomicU64::new(0),
+            recent: Mutex::new(VecDeque::new()),
+        })
+    }
+
+    /// A seat for a new connection, or the line to send before closing it
+    pub fn admit(self: &Arc<Self>) -> Result<Seat, String> {
+        // Check and take in one step, so two connections arriving together
+        // can't both get the last place
+        let taken = self
+            .connected
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (n < self.max).then_some(n + 1)
+            });
+        match taken {
+            Ok(_) => {
+                self.accepted.fetch_add(1, Ordering::Relaxed);
+                Ok(Seat {
+                    stats: Arc::clone(self),
+                })
+            }
+            Err(_) => {
+                self.refused.fetch_add(1, Ordering::Relaxed);
+                Err(format!(
+                    "Server full ({}/{} clients), try again later\n",
+                    self.max, self.max
+                ))
+            }
+        }
+    }
+
+    /// Connections being served right now
+    pub fn connected(&self) -> usize {
+        self.connected.load(Ordering::SeqCst)
+    }
+
+    /// A chat line or private message went out
+    pub fn message(&self) {
+        self.messages.fetch_add(1, Ordering::Relaxed);
+        let second = self.started.elapsed().as_secs();
+        let mut recent = self.recent.lock().unwrap();
+        match recent.back_mut() {
+            Some((last, count)) if *last == second => *count += 1,
+            _ => recent.push_back((second, 1)),
+        }
+        while recent
+            .front()
+            .is_some_and(|(s, _)| s + WINDOW.as_secs() <= second)
+        {
+            recent.pop_front();
+        }
+    }
+
+    /// The `/stats` block; `rooms` is the room list, already formatted
+    pub fn report(&self, rooms: &str) -> String {
+        let uptime = self.started.elapsed();
+        let now = uptime.as_secs();
+        let messages = self.messages.load(Ordering::Relaxed);
+        let recent: u64 = self
+            .recent
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(s, _)| s + WINDOW.as_secs() > now)
+            .map(|(_, count)| count)
+            .sum();
+        // A server up for less than the window has only that long to divide by
+        let window = uptime.min(WINDOW).as_secs_f64().max(1.0);
+        format!(
+            "--- server stats ---\n\
+             Clients: {}/{} connected, {} accepted, {} turned away (full)\n\
+             {}\
+             Messages: {} total, {:.1}/s over the last minute, {:.1}/s since start\n\
+             Uptime: {}\n\
+             --- end of stats ---\n",
+            self.connected.load(Ordering::SeqCst),
+            self.max,
+            self.accepted.load(Ordering::Relaxed),
+            self.refused.load(Ordering::Relaxed),
+            rooms,
+            messages,
+            recent as f64 / window,
+            messages as f64 / uptime.as_secs_f64().max(1.0),
+            hms(uptime),
+        )
+    }
+}
+
+/// `1h 02m 03s`
+fn hms(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{}h {:02}m {:02}s", secs / 3600, secs / 60 % 60, secs % 60)
+}
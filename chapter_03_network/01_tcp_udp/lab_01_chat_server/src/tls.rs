@@ -0,0 +1,101 @@
+//! TLS: the same chat, encrypted
+//!
+//! With `--tls-cert` and `--tls-key` every connection starts with a TLS
+//! handshake; only then does the greeting (or the auth prompt) go out, so
+//! tokens and passwords never cross the network in the clear. Nothing
+//! else changes: `handle_client` reads and writes the decrypted stream.
+//!
+//! A certificate for trying it out:
+//!
+//! ```text
+//! $ cargo run -- --gen-cert certs
+//! Wrote certs/cert.pem and certs/key.pem (self-signed for localhost, 127.0.0.1, ::1)
+//! $ cargo run -- 8443 --tls-cert certs/cert.pem --tls-key certs/key.pem
+//! $ openssl s_client -connect localhost:8443 -CAfile certs/cert.pem -quiet
+//! Welcome, guest1! /nick <name> to pick a name, /help for commands
+//! ```
+//!
+//! `nc` can't speak TLS; it gets no greeting, and the server logs a failed
+//! handshake. Without `-CAfile`, s_client warns that the certificate is
+//! self-signed: nobody vouches for it, which is what a real CA would do.
+
+use rustls_pemfile::Item;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
+
+/// Names the generated certificate is valid for
+const LOCAL_NAMES: [&str; 3] = ["localhost", "127.0.0.1", "::1"];
+
+/// An acceptor for the certificate chain and private key in two PEM files
+pub fn acceptor(cert_path: &Path, key_path: &Path) -> Result<TlsAcceptor, String> {
+    let open = |path: &Path| {
+        File::open(path)
+            .map(BufReader::new)
+            .map_err(|err| format!("cannot open {}: {}", path.display(), err))
+    };
+
+    let certs: Vec<CertificateDer<'static>> = rustls_pemfile::certs(&mut open(cert_path)?)
+        .collect::<Result<_, _>>()
+        .map_err(|err| format!("{}: {}", cert_path.display(), err))?;
+    if certs.is_empty() {
+        return Err(format!("no certificate in {}", cert_path.display()));
+    }
+
+    // The first key of any supported kind
+    let mut key = None;
+    for item in rustls_pemfile::read_all(&mut open(key_path)?) {
+        let item = item.map_err(|err| format!("{}: {}", key_path.display(), err))?;
+        key = match item {
+            Item::Pkcs8Key(key) => Some(PrivateKeyDer::Pkcs8(key)),
+            Item::Pkcs1Key(key) => Some(PrivateKeyDer::Pkcs1(key)),
+            Item::Sec1Key(key) => Some(PrivateKeyDer::Sec1(key)),
+            _ => continue,
+        };
+        break;
+    }
+    let key = key.ok_or_else(|| format!("no private key in {}", key_path.display()))?;
+
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|err| format!("certificate and key don't fit: {}", err))?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Write `dir/cert.pem` and `dir/key.pem`, self-signed for this machine;
+/// returns the two paths
+pub fn generate_self_signed(dir: &Path) -> Result<(String, String), String> {
+    let names: Vec<String> = LOCAL_NAMES.iter().map(|name| name.to_string()).collect();
+    let generated = rcgen::generate_simple_self_signed(names)
+        .map_err(|err| format!("cannot generate a certificate: {}", err))?;
+
+    std::fs::create_dir_all(dir)
+        .map_err(|err| format!("cannot create {}: {}", dir.display(), err))?;
+    let cert_path = dir.join("cert.pem");
+    let key_path = dir.join("key.pem");
+    let write = |path: &Path, pem: String| {
+        std::fs::write(path, pem).map_err(|err| format!("cannot write {}: {}", path.display(), err))
+    };
+    write(&cert_path, generated.cert.pem())?;
+    write(&key_path, generated.key_pair.serialize_pem())?;
+    // The key is the secret half: keep it to the owner
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let _ = std::fs::set_permissions(&key_path, std::fs::Permissions::from_mode(0o600));
+    }
+    Ok((
+        cert_path.display().to_string(),
+        key_path.display().to_string(),
+    ))
+}
+
+/// For the startup line and `--gen-cert`'s report
+pub fn local_names() -> String {
+    LOCAL_NAMES.join(", ")
+}
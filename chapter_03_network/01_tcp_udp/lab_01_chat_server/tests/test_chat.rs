@@ -1,6 +1,6 @@
 //! Lab 1 Tests
 
-use std::io::{BufRead, BufReader, Write};
+use std::io::{BufRead, BufReader, Read, Write};
 use std::net::TcpStream;
 use std::process::{Child, Command};
 use std::thread;
@@ -118,3 +118,1259 @@ fn test_03_message_broadcast() {
 
     // If we get here, message wasn't broadcast (might not be implemented yet)
 }
+
+struct Client {
+    stream: TcpStream,
+    reader: BufReader<TcpStream>,
+}
+
+impl Client {
+    fn connect(port: u16) -> Option<Client> {
+        Client::connect_to(&format!("127.0.0.1:{}", port))
+    }
+
+    fn connect_to(addr: &str) -> Option<Client> {
+        let stream = TcpStream::connect(addr).ok()?;
+        stream
+            .set_read_timeout(Some(Duration::from_millis(500)))
+            .ok()?;
+        let reader = BufReader::new(stream.try_clone().ok()?);
+        Some(Client { stream, reader })
+    }
+
+    fn send(&mut self, line: &str) {
+        self.stream
+            .write_all(format!("{}\n", line).as_bytes())
+            .expect("send failed");
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    /// Every line that arrives before the read times out
+    fn received(&mut self) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut line = String::new();
+        while matches!(self.reader.read_line(&mut line), Ok(n) if n > 0) {
+            lines.push(line.trim_end().to_string());
+            line.clear();
+        }
+        lines
+    }
+}
+
+#[test]
+fn test_04_rooms() {
+    let _server = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_chat_server"))
+            .arg("8081")
+            .spawn()
+            .expect("Failed to start server"),
+    };
+    thread::sleep(Duration::from_millis(500));
+
+    let (Some(mut alice), Some(mut bob), Some(mut carol)) = (
+        Client::connect(8081),
+        Client::connect(8081),
+        Client::connect(8081),
+    ) else {
+        panic!("Should be able to connect to port 8081");
+    };
+    thread::sleep(Duration::from_millis(200));
+    alice.received();
+    bob.received();
+
+    // alice and bob meet in #rust, carol stays in the lobby
+    alice.send("/join rust");
+    assert!(alice.received().contains(&"You joined #rust".to_string()));
+    bob.send("/join rust");
+    bob.received();
+    let seen = alice.received();
+    assert!(
+        seen.iter().any(|l| l.ends_with("joined #rust")),
+        "No join notice: {:?}",
+        seen
+    );
+
+    bob.send("hello rust");
+    assert!(
+        alice.received().iter().any(|l| l.contains("hello rust")),
+        "Room member missed the message"
+    );
+    let lobby = carol.received();
+    assert!(
+        !lobby.iter().any(|l| l.contains("hello rust")),
+        "Message leaked out of the room: {:?}",
+        lobby
+    );
+
+    carol.send("hello lobby");
+    assert!(!alice.received().iter().any(|l| l.contains("hello lobby")));
+
+    carol.send("/rooms");
+    let listing = carol.received();
+    assert_eq!(
+        listing,
+        ["Rooms: #lobby (1), #rust (2) - you are in #lobby"],
+        "Room list: {:?}",
+        listing
+    );
+
+    // Back to the lobby; #rust disappears once it is empty
+    bob.send("/leave");
+    assert!(bob
+        .received()
+        .contains(&"You left #rust, back in #lobby".to_string()));
+    alice.send("/leave");
+    alice.received();
+    carol.received();
+    carol.send("/rooms");
+    assert_eq!(carol.received(), ["Rooms: #lobby (3) - you are in #lobby"]);
+
+    alice.send("/leave");
+    assert_eq!(alice.received(), ["You are in #lobby already"]);
+    alice.send("/join no/slashes");
+    assert!(alice.received()[0].starts_with("Room names are"));
+    alice.send("/dance");
+    assert!(alice.received()[0].starts_with("Unknown command /dance"));
+}
+
+#[test]
+fn test_05_token_auth() {
+    let _server = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_chat_server"))
+            .arg("8082")
+            .env("CHAT_TOKENS", "alice:t0k-alice,bob:t0k-bob")
+            .env("AUTH_TIMEOUT_SECS", "1")
+            .spawn()
+            .expect("Failed to start server"),
+    };
+    thread::sleep(Duration::from_millis(500));
+
+    let (Some(mut alice), Some(mut bob)) = (Client::connect(8082), Client::connect(8082)) else {
+        panic!("Should be able to connect to port 8082");
+    };
+    alice.send("AUTH t0k-alice");
+    assert_eq!(alice.received(), ["OK welcome, alice"]);
+    bob.send("AUTH t0k-bob");
+    assert_eq!(bob.received(), ["OK welcome, bob"]);
+    assert_eq!(alice.received(), ["[bob] joined the chat"]);
+
+    // Broadcasts carry the authenticated name, not the address
+    bob.send("hi alice");
+    assert_eq!(alice.received(), ["[bob]: hi alice"]);
+
+    // A wrong token, or no AUTH at all, is answered and disconnected
+    let mut mallory = Client::connect(8082).expect("connect");
+    mallory.send("AUTH t0k-guess");
+    assert_eq!(mallory.received(), ["ERR invalid token"]);
+    let mut eve = Client::connect(8082).expect("connect");
+    eve.send("hello?");
+    assert_eq!(eve.received(), ["ERR expected AUTH <token>"]);
+    // The name is the nickname, so a token can only be used once at a time
+    let mut twin = Client::connect(8082).expect("connect");
+    twin.send("AUTH t0k-alice");
+    assert_eq!(twin.received(), ["ERR alice is already connected"]);
+
+    let mut silent = Client::connect(8082).expect("connect");
+    thread::sleep(Duration::from_millis(1200));
+    assert_eq!(silent.received(), ["ERR authentication timed out"]);
+
+    // None of them made it into the lobby
+    assert!(alice.received().is_empty());
+    alice.send("/rooms");
+    assert_eq!(alice.received(), ["Rooms: #lobby (2) - you are in #lobby"]);
+}
+
+#[test]
+fn test_06_nicknames() {
+    let _server = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_chat_server"))
+            .arg("8083")
+            .spawn()
+            .expect("Failed to start server"),
+    };
+    thread::sleep(Duration::from_millis(500));
+
+    let (Some(mut alice), Some(mut bob)) = (Client::connect(8083), Client::connect(8083)) else {
+        panic!("Should be able to connect to port 8083");
+    };
+    let greeting = alice.received();
+    assert!(
+        greeting[0].starts_with("Welcome, guest"),
+        "No greeting: {:?}",
+        greeting
+    );
+    bob.received();
+
+    alice.send("/nick alice");
+    assert_eq!(alice.received(), ["You are now known as alice"]);
+    assert!(bob.received()[0].ends_with("is now known as alice"));
+    bob.send("/nick alice");
+    assert_eq!(bob.received(), ["Nickname alice is taken"]);
+    bob.send("/nick bob");
+    bob.received();
+    alice.received();
+
+    // Messages show the nickname, not the address
+    bob.send("hi");
+    assert_eq!(alice.received(), ["[bob]: hi"]);
+
+    alice.send("/who");
+    assert_eq!(alice.received()[0], "In #lobby: alice, bob");
+    bob.send("/join rust");
+    bob.send("/who");
+    assert_eq!(bob.received()[..2], ["You joined #rust", "In #rust: bob"]);
+
+    alice.send("/help");
+    let help = alice.received();
+    assert!(
+        help.iter().any(|l| l.contains("/nick <name>")),
+        "{:?}",
+        help
+    );
+    alice.send("/nick no/slashes");
+    assert!(alice.received()[0].starts_with("Nicknames are"));
+
+    // /quit frees the name
+    bob.send("/quit");
+    assert_eq!(bob.received(), ["Bye!"]);
+    alice.received();
+    alice.send("/nick bob");
+    assert_eq!(alice.received(), ["You are now known as bob"]);
+}
+
+#[test]
+fn test_07_private_messages() {
+    let _server = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_chat_server"))
+            .arg("8084")
+            .spawn()
+            .expect("Failed to start server"),
+    };
+    thread::sleep(Duration::from_millis(500));
+
+    let (Some(mut alice), Some(mut bob), Some(mut carol)) = (
+        Client::connect(8084),
+        Client::connect(8084),
+        Client::connect(8084),
+    ) else {
+        panic!("Should be able to connect to port 8084");
+    };
+    alice.send("/nick alice");
+    bob.send("/nick bob");
+    carol.send("/nick carol");
+    // bob is in another room: private messages don't care
+    bob.send("/join rust");
+    alice.received();
+    bob.received();
+    carol.received();
+
+    alice.send("/msg bob see you at 5");
+    assert_eq!(alice.received(), ["(to bob) see you at 5"]);
+    assert_eq!(bob.received(), ["[alice -> you] see you at 5"]);
+    assert!(carol.received().is_empty(), "carol saw a private message");
+
+    alice.send("/msg dave hello?");
+    assert_eq!(alice.received(), ["No user named dave"]);
+    alice.send("/msg alice hi me");
+    assert_eq!(alice.received(), ["You can't /msg yourself"]);
+    alice.send("/msg bob");
+    assert!(alice.received()[0].starts_with("Unknown command /msg bob"));
+
+    // The registry follows renames
+    bob.send("/nick robert");
+    bob.received();
+    alice.send("/msg robert still there?");
+    alice.received();
+    assert_eq!(bob.received(), ["[alice -> you] still there?"]);
+}
+
+#[test]
+fn test_08_history() {
+    let _server = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_chat_server"))
+            .arg("8085")
+            .spawn()
+            .expect("Failed to start server"),
+    };
+    thread::sleep(Duration::from_millis(500));
+
+    let Some(mut alice) = Client::connect(8085) else {
+        panic!("Should be able to connect to port 8085");
+    };
+    alice.send("/nick alice");
+    alice.send("/history");
+    assert_eq!(
+        alice.received().last().unwrap(),
+        "No messages in #lobby yet"
+    );
+    alice.send("first");
+    alice.send("second");
+
+    // A newcomer gets the lobby's history right after the greeting
+    let mut bob = Client::connect(8085).expect("connect");
+    let seen = bob.received();
+    assert_eq!(
+        seen[1..],
+        [
+            "--- last 2 message(s) in #lobby ---",
+            "[alice]: first",
+            "[alice]: second",
+            "--- end of history ---",
+        ],
+        "{:?}",
+        seen
+    );
+
+    // Each room has its own history, replayed on /join
+    alice.send("/join rust");
+    alice.send("in rust");
+    bob.received();
+    bob.send("/join rust");
+    assert_eq!(
+        bob.received(),
+        [
+            "You joined #rust",
+            "--- last 1 message(s) in #rust ---",
+            "[alice]: in rust",
+            "--- end of history ---",
+        ]
+    );
+
+    // Bounded: only the last 20 lines are kept
+    for i in 1..=25 {
+        alice.send(&format!("line {}", i));
+    }
+    bob.received();
+    bob.send("/history");
+    let history = bob.received();
+    assert_eq!(history.len(), 22, "{:?}", history);
+    assert_eq!(history[0], "--- last 20 message(s) in #rust ---");
+    assert_eq!(history[1], "[alice]: line 6");
+    assert_eq!(history[20], "[alice]: line 25");
+}
+
+#[test]
+fn test_09_sqlite_history() {
+    let db = std::env::temp_dir().join(format!("chat_test_{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&db);
+    let url = format!("sqlite:{}", db.display());
+    let start = || ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_chat_server"))
+            .arg("8086")
+            .env("CHAT_DB", &url)
+            .spawn()
+            .expect("Failed to start server"),
+    };
+
+    let server = start();
+    thread::sleep(Duration::from_millis(500));
+    let Some(mut alice) = Client::connect(8086) else {
+        panic!("Should be able to connect to port 8086");
+    };
+    alice.send("/nick alice");
+    alice.send("/history 5");
+    assert_eq!(
+        alice.received().last().unwrap(),
+        "No messages in #lobby yet"
+    );
+    for text in ["one", "two", "three"] {
+        alice.send(text);
+    }
+    alice.send("/history 2");
+    let history = alice.received();
+    assert_eq!(history.len(), 4, "{:?}", history);
+    assert_eq!(
+        history[0],
+        "--- last 2 message(s) in #lobby from the archive ---"
+    );
+    assert!(history[1].ends_with(" [alice]: two"), "{:?}", history);
+    assert!(history[2].ends_with(" [alice]: three"), "{:?}", history);
+
+    alice.send("/history 0");
+    assert!(alice.received()[0].starts_with("Usage: /history <n>"));
+    drop(alice);
+    drop(server);
+    thread::sleep(Duration::from_millis(300));
+
+    // The ring buffer is gone after a restart, the database is not
+    let _server = start();
+    thread::sleep(Duration::from_millis(500));
+    let mut bob = Client::connect(8086).expect("connect");
+    bob.send("/history");
+    assert_eq!(bob.received().last().unwrap(), "No messages in #lobby yet");
+    bob.send("/history 10");
+    let history = bob.received();
+    assert_eq!(
+        history[0],
+        "--- last 3 message(s) in #lobby from the archive ---"
+    );
+    assert!(history[1].ends_with(" [alice]: one"), "{:?}", history);
+
+    let _ = std::fs::remove_file(&db);
+}
+
+#[test]
+fn test_10_flood_protection() {
+    let _server = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_chat_server"))
+            .args(["8087", "--rate", "1", "--burst", "3", "--mute-secs", "3"])
+            .spawn()
+            .expect("Failed to start server"),
+    };
+    thread::sleep(Duration::from_millis(500));
+
+    let (Some(mut alice), Some(mut bob)) = (Client::connect(8087), Client::connect(8087)) else {
+        panic!("Should be able to connect to port 8087");
+    };
+    alice.send("/nick alice");
+    alice.received();
+    bob.received();
+
+    // The bucket is full again: a burst of 3 goes through, the 4th line is
+    // the first strike, the 5th the second
+    alice.stream.write_all(b"one\ntwo\nthree\nfour\n").unwrap();
+    alice.send("five");
+    let seen = alice.received();
+    assert!(seen[0].starts_with("Slow down!"), "{:?}", seen);
+    assert!(
+        seen[1].starts_with("You are muted for 3 seconds"),
+        "{:?}",
+        seen
+    );
+    assert_eq!(
+        bob.received(),
+        [
+            "[alice]: one",
+            "[alice]: two",
+            "[alice]: three",
+            "[alice] was muted for flooding"
+        ]
+    );
+
+    // Tokens are back, but the mute isn't over; commands still work
+    thread::sleep(Duration::from_millis(1000));
+    alice.send("still here?");
+    let seen = alice.received();
+    assert!(seen[0].starts_with("You are muted for"), "{:?}", seen);
+    alice.send("/who");
+    assert_eq!(alice.received()[0], "In #lobby: alice, guest2");
+    assert!(bob.received().is_empty());
+
+    thread::sleep(Duration::from_millis(1000));
+    alice.send("back");
+    assert_eq!(bob.received(), ["[alice]: back"]);
+
+    // Third strike: out
+    alice.stream.write_all(b"a\nb\nc\nd\ne\nf\n").unwrap();
+    thread::sleep(Duration::from_millis(200));
+    let seen = alice.received();
+    assert_eq!(
+        seen.last().unwrap(),
+        "Disconnected for flooding",
+        "{:?}",
+        seen
+    );
+    let mut rest = String::new();
+    assert_eq!(alice.reader.read_line(&mut rest).unwrap_or(0), 0);
+    assert!(bob
+        .received()
+        .contains(&"[alice] left the chat".to_string()));
+}
+
+#[test]
+fn test_11_invalid_flood_limits() {
+    for args in [
+        &["--rate", "0"][..],
+        &["--rate", "fast"],
+        &["--burst", "0"],
+        &["--mute-secs", "-1"],
+        &["--burst"],
+        &["--bogus", "1"],
+        &["--idle-secs", "soon"],
+        &["--ping-secs", "-5"],
+        &["--pong-secs", "0"],
+        &["8088", "8089"],
+        &["8088", "--bind", "[::]:8089"],
+        &["--bind", "0.0.0.0:8089", "--v6only"],
+    ] {
+        let output = Command::new(env!("CARGO_BIN_EXE_chat_server"))
+            .args(args)
+            .output()
+            .expect("Failed to execute program");
+        assert_eq!(
+            output.status.code(),
+            Some(2),
+            "{:?} should be rejected",
+            args
+        );
+    }
+}
+
+#[test]
+fn test_12_dual_stack_bind() {
+    // Skip on hosts without IPv6
+    if std::net::TcpListener::bind("[::1]:0").is_err() {
+        return;
+    }
+
+    let _server = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_chat_server"))
+            .args(["--bind", "[::]:8090"])
+            .spawn()
+            .expect("Failed to start server"),
+    };
+    thread::sleep(Duration::from_millis(500));
+
+    let (Some(mut v6), Some(mut v4)) = (
+        Client::connect_to("[::1]:8090"),
+        Client::connect_to("127.0.0.1:8090"),
+    ) else {
+        panic!("Both families should reach a dual-stack server");
+    };
+    thread::sleep(Duration::from_millis(200));
+    v6.received();
+    v4.received();
+
+    v6.send("over v6");
+    assert!(v4.received().iter().any(|l| l.ends_with("over v6")));
+    v4.send("over v4");
+    assert!(v6.received().iter().any(|l| l.ends_with("over v4")));
+}
+
+#[test]
+fn test_13_idle_timeout() {
+    let _server = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_chat_server"))
+            .args(["8097", "--idle-secs", "2"])
+            .spawn()
+            .expect("Failed to start server"),
+    };
+    thread::sleep(Duration::from_millis(500));
+
+    let (Some(mut alice), Some(mut bob)) = (Client::connect(8097), Client::connect(8097)) else {
+        panic!("Should be able to connect to port 8097");
+    };
+    alice.send("/nick alice");
+    bob.send("/nick bob");
+
+    // Bob keeps talking, alice says nothing: only she times out
+    for _ in 0..3 {
+        thread::sleep(Duration::from_millis(700));
+        bob.send("still here");
+    }
+    let seen = alice.received();
+    assert_eq!(
+        seen.last().unwrap(),
+        "Disconnected: idle for 2 seconds",
+        "{:?}",
+        seen
+    );
+    let mut rest = String::new();
+    assert_eq!(alice.reader.read_line(&mut rest).unwrap_or(0), 0);
+    assert!(bob
+        .received()
+        .contains(&"[alice] left the chat".to_string()));
+
+    bob.send("/who");
+    assert_eq!(bob.received()[0], "In #lobby: bob");
+}
+
+#[test]
+fn test_14_ping_pong() {
+    let _server = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_chat_server"))
+            .args([
+                "8098",
+                "--idle-secs",
+                "0",
+                "--ping-secs",
+                "1",
+                "--pong-secs",
+                "2",
+            ])
+            .spawn()
+            .expect("Failed to start server"),
+    };
+    thread::sleep(Duration::from_millis(500));
+
+    let Some(mut alice) = Client::connect(8098) else {
+        panic!("Should be able to connect to port 8098");
+    };
+
+    // Clients may ping the server too; PING/PONG are not chat lines
+    alice.send("PING abc");
+    assert!(alice.received().contains(&"PONG abc".to_string()));
+
+    // A second of silence earns a PING; answering keeps the connection
+    thread::sleep(Duration::from_millis(1000));
+    assert_eq!(alice.received(), ["PING 1"]);
+    alice.send("PONG 1");
+
+    // Not answering the next one gets her dropped
+    thread::sleep(Duration::from_millis(3500));
+    let seen = alice.received();
+    assert_eq!(
+        seen,
+        ["PING 2", "Disconnected: no PONG within 2 seconds"],
+        "{:?}",
+        seen
+    );
+    let mut rest = String::new();
+    assert_eq!(alice.reader.read_line(&mut rest).unwrap_or(0), 0);
+}
+
+#[test]
+fn test_15_password_auth() {
+    let users = std::env::temp_dir().join(format!("chat_users_{}.txt", std::process::id()));
+    std::fs::write(
+        &users,
+        "# user:password\nbob:hunter2\ncarol:s3cret:with:colons\n",
+    )
+    .unwrap();
+
+    let _server = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_chat_server"))
+            .arg("8099")
+            .env("CHAT_USERS_FILE", &users)
+            .env("CHAT_PASSWORD", "letmein")
+            .env("AUTH_TIMEOUT_SECS", "1")
+            .spawn()
+            .expect("Failed to start server"),
+    };
+    thread::sleep(Duration::from_millis(500));
+
+    // Each client logs in right away: AUTH_TIMEOUT_SECS is short
+    let Some(mut bob) = Client::connect(8099) else {
+        panic!("Should be able to connect to port 8099");
+    };
+    bob.send("LOGIN bob hunter2");
+    assert_eq!(bob.received(), ["OK welcome, bob"]);
+    let mut carol = Client::connect(8099).expect("connect");
+    carol.send("LOGIN carol s3cret:with:colons");
+    assert_eq!(carol.received(), ["OK welcome, carol"]);
+    let mut guest = Client::connect(8099).expect("connect");
+    guest.send("PASS letmein");
+    let seen = guest.received();
+    assert!(seen[0].starts_with("OK welcome, guest"), "{:?}", seen);
+
+    bob.received();
+    carol.received();
+
+    // Users keep their name, password guests may choose one
+    bob.send("/nick robert");
+    assert!(bob.received()[0].starts_with("Your nickname comes with your login"));
+    guest.send("/nick dave");
+    assert_eq!(guest.received(), ["You are now known as dave"]);
+    guest.send("hello");
+    assert!(bob.received().contains(&"[dave]: hello".to_string()));
+
+    // Wrong passwords, unknown users and other greetings are all refused
+    for (line, reply) in [
+        ("LOGIN bob hunter3", "ERR invalid user or password"),
+        ("LOGIN mallory hunter2", "ERR invalid user or password"),
+        ("PASS guess", "ERR invalid password"),
+        (
+            "AUTH t0k-alice",
+            "ERR expected LOGIN <user> <password> or PASS <password>",
+        ),
+        (
+            "hi",
+            "ERR expected LOGIN <user> <password> or PASS <password>",
+        ),
+    ] {
+        let mut stranger = Client::connect(8099).expect("connect");
+        stranger.send(line);
+        assert_eq!(stranger.received(), [reply], "{}", line);
+        let mut rest = String::new();
+        assert_eq!(stranger.reader.read_line(&mut rest).unwrap_or(0), 0);
+    }
+
+    // An unauthenticated client can't say anything: it never got a room
+    let mut silent = Client::connect(8099).expect("connect");
+    thread::sleep(Duration::from_millis(1200));
+    assert_eq!(silent.received(), ["ERR authentication timed out"]);
+    assert!(bob.received().is_empty());
+    bob.send("/who");
+    assert_eq!(bob.received()[0], "In #lobby: bob, carol, dave");
+
+    let _ = std::fs::remove_file(&users);
+}
+
+#[test]
+fn test_16_max_clients() {
+    let _server = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_chat_server"))
+            .args(["8118", "--max-clients", "2"])
+            .spawn()
+            .expect("Failed to start server"),
+    };
+    thread::sleep(Duration::from_millis(500));
+
+    let Some(mut alice) = Client::connect(8118) else {
+        panic!("Should be able to connect to port 8118");
+    };
+    let mut bob = Client::connect(8118).expect("connect");
+    assert!(alice.received()[0].starts_with("Welcome, guest"));
+    assert!(bob.received()[0].starts_with("Welcome, guest"));
+
+    // The third is told why and closed at once, without a guest name
+    let mut carol = Client::connect(8118).expect("connect");
+    assert_eq!(
+        carol.received(),
+        ["Server full (2/2 clients), try again later"]
+    );
+    let mut rest = String::new();
+    assert_eq!(carol.reader.read_line(&mut rest).unwrap_or(0), 0);
+    assert!(alice.received().is_empty(), "Nobody joined");
+
+    // Once someone leaves there is room again
+    bob.send("/quit");
+    let mut dave = Client::connect(8118).expect("connect");
+    assert!(dave.received()[0].starts_with("Welcome, guest"));
+}
+
+#[test]
+fn test_17_admin_stats() {
+    let _server = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_chat_server"))
+            .args(["8119", "--max-clients", "3"])
+            .env("CHAT_TOKENS", "root:t0k-root,bob:t0k-bob")
+            .env("CHAT_ADMINS", "root")
+            .spawn()
+            .expect("Failed to start server"),
+    };
+    thread::sleep(Duration::from_millis(500));
+
+    let Some(mut root) = Client::connect(8119) else {
+        panic!("Should be able to connect to port 8119");
+    };
+    root.send("AUTH t0k-root");
+    let mut bob = Client::connect(8119).expect("connect");
+    bob.send("AUTH t0k-bob");
+    root.received();
+    bob.received();
+
+    bob.send("/join rust");
+    bob.send("hello rust");
+    bob.send("/msg root psst");
+    root.received();
+    bob.received();
+
+    // Only admins may look
+    bob.send("/stats");
+    assert_eq!(bob.received(), ["/stats is for admins only"]);
+
+    root.send("/stats");
+    let stats = root.received();
+    assert_eq!(
+        stats.first().map(String::as_str),
+        Some("--- server stats ---")
+    );
+    assert!(
+        stats.contains(&"Clients: 2/3 connected, 2 accepted, 0 turned away (full)".to_string()),
+        "{:?}",
+        stats
+    );
+    assert!(
+        stats.contains(&"Rooms: #lobby (1), #rust (1)".to_string()),
+        "{:?}",
+        stats
+    );
+    assert!(
+        stats
+            .iter()
+            .any(|line| line.starts_with("Messages: 2 total")),
+        "{:?}",
+        stats
+    );
+    assert_eq!(
+        stats.last().map(String::as_str),
+        Some("--- end of stats ---")
+    );
+}
+
+#[test]
+fn test_18_admins_need_logins() {
+    // Without tokens or users, nobody could prove to be an admin
+    let output = Command::new(env!("CARGO_BIN_EXE_chat_server"))
+        .arg("8119")
+        .env("CHAT_ADMINS", "root")
+        .env("CHAT_PASSWORD", "letmein")
+        .output()
+        .expect("Failed to run server");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("CHAT_ADMINS"));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_chat_server"))
+        .args(["8119", "--max-clients", "0"])
+        .output()
+        .expect("Failed to run server");
+    assert_eq!(output.status.code(), Some(2));
+}
+
+/// A `--framing binary` client: type byte, u32 length, payload
+struct FrameClient {
+    stream: TcpStream,
+}
+
+impl FrameClient {
+    fn connect(port: u16) -> Option<FrameClient> {
+        let stream = TcpStream::connect(("127.0.0.1", port)).ok()?;
+        stream
+            .set_read_timeout(Some(Duration::from_millis(500)))
+            .ok()?;
+        Some(FrameClient { stream })
+    }
+
+    fn frame(kind: u8, payload: &str) -> Vec<u8> {
+        let mut bytes = vec![kind];
+        bytes.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(payload.as_bytes());
+        bytes
+    }
+
+    /// Write `bytes` in pieces cut at `cuts`, pausing in between so the
+    /// server sees several partial reads
+    fn send_split(&mut self, bytes: &[u8], cuts: &[usize]) {
+        let mut start = 0;
+        for &end in cuts.iter().chain([bytes.len()].iter()) {
+            self.stream
+                .write_all(&bytes[start..end])
+                .expect("send failed");
+            self.stream.flush().unwrap();
+            thread::sleep(Duration::from_millis(50));
+            start = end;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    /// Every frame that arrives before the read times out
+    fn received(&mut self) -> Vec<(u8, String)> {
+        let mut frames = Vec::new();
+        let mut header = [0u8; 5];
+        while self.stream.read_exact(&mut header).is_ok() {
+            let len = u32::from_be_bytes([header[1], header[2], header[3], header[4]]);
+            let mut payload = vec![0u8; len as usize];
+            self.stream.read_exact(&mut payload).expect("payload");
+            frames.push((header[0], String::from_utf8(payload).unwrap()));
+        }
+        frames
+    }
+}
+
+#[test]
+fn test_19_binary_framing() {
+    let _server = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_chat_server"))
+            .args(["8124", "--framing", "binary"])
+            .spawn()
+            .expect("Failed to start server"),
+    };
+    thread::sleep(Duration::from_millis(500));
+
+    let Some(mut alice) = FrameClient::connect(8124) else {
+        panic!("Should be able to connect to port 8124");
+    };
+    let mut bob = FrameClient::connect(8124).expect("connect");
+    let greeting = alice.received();
+    assert_eq!(greeting[0].0, 1);
+    assert!(
+        greeting[0].1.starts_with("Welcome, guest"),
+        "{:?}",
+        greeting
+    );
+    bob.received();
+
+    // Cut inside the header and inside the payload: still one command
+    let nick = FrameClient::frame(1, "/nick alice");
+    alice.send_split(&nick, &[2, 7]);
+    assert!(alice
+        .received()
+        .iter()
+        .any(|(_, text)| text.contains("alice")));
+    bob.received();
+
+    // Two frames in one write are two messages; a newline is just a byte
+    let mut both = FrameClient::frame(1, "first\nline");
+    both.extend(FrameClient::frame(1, "second"));
+    alice.send_split(&both, &[]);
+    let texts: Vec<String> = bob.received().into_iter().map(|(_, t)| t).collect();
+    assert!(
+        texts.iter().any(|t| t.contains("first line")),
+        "{:?}",
+        texts
+    );
+    assert!(texts.iter().any(|t| t.contains("second")), "{:?}", texts);
+
+    // PING and PONG have their own frame types
+    alice.send_split(&FrameClient::frame(2, "5"), &[]);
+    assert_eq!(alice.received(), [(3, "5".to_string())]);
+
+    // An unknown type gets an ERR frame and the connection is closed
+    alice.send_split(&FrameClient::frame(9, "?"), &[]);
+    let frames = alice.received();
+    assert!(
+        frames[0].1.starts_with("ERR unknown frame type"),
+        "{:?}",
+        frames
+    );
+    let mut rest = [0u8; 1];
+    assert_eq!(alice.stream.read(&mut rest).unwrap_or(0), 0);
+
+    // So is a frame claiming more than the limit, before it is buffered
+    assert!(bob
+        .received()
+        .iter()
+        .any(|(_, text)| text.contains("alice")));
+    bob.stream.write_all(&[1, 0, 0, 0x40, 0]).unwrap();
+    let frames = bob.received();
+    assert!(frames[0].1.starts_with("ERR frame of"), "{:?}", frames);
+    assert_eq!(bob.stream.read(&mut rest).unwrap_or(0), 0);
+}
+
+/// A chat client over TLS that trusts only `cert_pem`
+fn tls_client(
+    port: u16,
+    cert_pem: &std::path::Path,
+) -> BufReader<rustls::StreamOwned<rustls::ClientConnection, TcpStream>> {
+    let mut pem = BufReader::new(std::fs::File::open(cert_pem).expect("open cert"));
+    let der = rustls_pemfile::certs(&mut pem).next().unwrap().unwrap();
+
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(der).expect("add root");
+    let config = rustls::ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let name = "localhost".try_into().unwrap();
+    let conn = rustls::ClientConnection::new(std::sync::Arc::new(config), name).unwrap();
+    let sock = TcpStream::connect(("127.0.0.1", port)).expect("connect");
+    sock.set_read_timeout(Some(Duration::from_millis(500)))
+        .unwrap();
+    BufReader::new(rustls::StreamOwned::new(conn, sock))
+}
+
+/// Every line that arrives before the read times out
+fn tls_received<R: BufRead>(reader: &mut R) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    while matches!(reader.read_line(&mut line), Ok(n) if n > 0) {
+        lines.push(line.trim_end().to_string());
+        line.clear();
+    }
+    lines
+}
+
+#[test]
+fn test_20_tls() {
+    let dir = std::env::temp_dir().join(format!("chat_tls_{}", std::process::id()));
+    let output = Command::new(env!("CARGO_BIN_EXE_chat_server"))
+        .arg("--gen-cert")
+        .arg(&dir)
+        .output()
+        .expect("Failed to run server");
+    assert!(output.status.success());
+    let (cert, key) = (dir.join("cert.pem"), dir.join("key.pem"));
+
+    let _server = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_chat_server"))
+            .arg("8125")
+            .arg("--tls-cert")
+            .arg(&cert)
+            .arg("--tls-key")
+            .arg(&key)
+            .spawn()
+            .expect("Failed to start server"),
+    };
+    thread::sleep(Duration::from_millis(500));
+
+    let mut alice = tls_client(8125, &cert);
+    let mut bob = tls_client(8125, &cert);
+    assert!(tls_received(&mut alice)[0].starts_with("Welcome, guest"));
+    assert!(tls_received(&mut bob)[0].starts_with("Welcome, guest"));
+
+    alice.get_mut().write_all(b"over tls\n").unwrap();
+    alice.get_mut().flush().unwrap();
+    thread::sleep(Duration::from_millis(100));
+    assert!(tls_received(&mut bob)
+        .iter()
+        .any(|l| l.ends_with("over tls")));
+
+    // A plaintext client gets no greeting, only a TLS alert, then EOF
+    let Some(mut plain) = Client::connect(8125) else {
+        panic!("Should be able to connect to port 8125");
+    };
+    plain.send("hello");
+    let mut bytes = Vec::new();
+    let _ = plain.reader.read_to_end(&mut bytes);
+    assert!(!String::from_utf8_lossy(&bytes).contains("Welcome"));
+
+    // Half a TLS setup is refused up front
+    let output = Command::new(env!("CARGO_BIN_EXE_chat_server"))
+        .arg("8125")
+        .arg("--tls-cert")
+        .arg(&cert)
+        .output()
+        .expect("Failed to run server");
+    assert_eq!(output.status.code(), Some(2));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_21_graceful_shutdown() {
+    let db = std::env::temp_dir().join(format!("chat_shutdown_{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&db);
+    let url = format!("sqlite:{}", db.display());
+    let start = || ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_chat_server"))
+            .arg("8126")
+            .env("CHAT_DB", &url)
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .expect("Failed to start server"),
+    };
+
+    let mut server = start();
+    thread::sleep(Duration::from_millis(500));
+    let Some(mut alice) = Client::connect(8126) else {
+        panic!("Should be able to connect to port 8126");
+    };
+    let mut bob = Client::connect(8126).expect("connect");
+    alice.send("/nick alice");
+    alice.received();
+    bob.received();
+
+    // Said right before the signal: still delivered and still archived
+    alice
+        .stream
+        .write_all(b"last words\n")
+        .expect("send failed");
+    let interrupted = Command::new("kill")
+        .args(["-INT", &server.child.id().to_string()])
+        .status()
+        .expect("Failed to run kill");
+    assert!(interrupted.success());
+    thread::sleep(Duration::from_millis(500));
+
+    let lines = bob.received();
+    assert_eq!(
+        lines.last().map(String::as_str),
+        Some("Server shutting down, goodbye!"),
+        "{:?}",
+        lines
+    );
+    assert!(
+        lines.iter().any(|l| l == "[alice]: last words"),
+        "{:?}",
+        lines
+    );
+    assert!(
+        !lines.iter().any(|l| l.contains("left the chat")),
+        "{:?}",
+        lines
+    );
+    // Closed by the server
+    let mut rest = String::new();
+    assert_eq!(bob.reader.read_line(&mut rest).unwrap_or(0), 0);
+    assert_eq!(
+        alice.received().last().map(String::as_str),
+        Some("Server shutting down, goodbye!")
+    );
+
+    let status = server.child.wait().expect("wait");
+    assert!(status.success(), "{:?}", status);
+    let mut log = String::new();
+    let _ = server.child.stdout.take().unwrap().read_to_string(&mut log);
+    assert!(log.contains("saying goodbye to 2 client(s)"), "{}", log);
+    assert!(log.contains("Shutdown complete"), "{}", log);
+    assert!(Client::connect(8126).is_none(), "Still accepting");
+
+    // Nothing queued for the archive was lost
+    let _server = start();
+    thread::sleep(Duration::from_millis(500));
+    let mut carol = Client::connect(8126).expect("connect");
+    carol.send("/history 1");
+    let history = carol.received();
+    assert!(
+        history.iter().any(|l| l.ends_with(" [alice]: last words")),
+        "{:?}",
+        history
+    );
+
+    let _ = std::fs::remove_file(&db);
+}
+
+/// The idle seconds in a `/who` line, e.g. `  bob    online 3s, idle 2s`
+fn idle_secs(line: &str) -> u64 {
+    let idle = line.split("idle ").nth(1).expect("idle time");
+    idle.trim_end_matches('s').parse().expect("seconds")
+}
+
+#[test]
+fn test_22_who_shows_online_and_idle_time() {
+    let _server = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_chat_server"))
+            .arg("8130")
+            .spawn()
+            .expect("Failed to start server"),
+    };
+    thread::sleep(Duration::from_millis(500));
+
+    let Some(mut alice) = Client::connect(8130) else {
+        panic!("Should be able to connect to port 8130");
+    };
+    alice.send("/nick alice");
+    let mut bob = Client::connect(8130).expect("connect");
+    bob.send("/nick bob");
+    bob.received();
+    assert!(alice
+        .received()
+        .contains(&"[guest2] is now known as bob".to_string()));
+
+    // bob stays quiet; alice talks. A PING is not activity, but her line is
+    thread::sleep(Duration::from_millis(2000));
+    alice.send("still here");
+    alice.send("PING 1");
+    alice.received();
+    alice.send("/who");
+    let who = alice.received();
+    assert_eq!(who[0], "In #lobby: alice, bob", "{:?}", who);
+    assert!(who[1].starts_with("  alice  online "), "{:?}", who);
+    assert!(who[2].starts_with("  bob    online "), "{:?}", who);
+    assert!(idle_secs(&who[1]) <= 1, "{:?}", who);
+    assert!(idle_secs(&who[2]) >= 2, "{:?}", who);
+
+    // Presence follows bob into another room, and out of the chat
+    bob.send("/join rust");
+    bob.received();
+    assert_eq!(alice.received(), ["[bob] left #lobby"]);
+    alice.send("/who");
+    let who = alice.received();
+    assert_eq!(who[0], "In #lobby: alice", "{:?}", who);
+    assert_eq!(who.len(), 2, "{:?}", who);
+    bob.send("/who");
+    let who = bob.received();
+    assert_eq!(who[0], "In #rust: bob", "{:?}", who);
+    assert!(idle_secs(&who[1]) <= 1, "/join is activity: {:?}", who);
+
+    drop(bob);
+    thread::sleep(Duration::from_millis(200));
+    alice.send("/join rust");
+    alice.send("/who");
+    let who = alice.received();
+    assert!(who.contains(&"In #rust: alice".to_string()), "{:?}", who);
+}
+
+#[test]
+fn test_23_federation_relays_rooms_once() {
+    // A triangle: b and c link to a, c also to b. Every line has two
+    // routes to one of the others, and a loop to come back on
+    let start = |args: &[&str]| ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_chat_server"))
+            .args(args)
+            .spawn()
+            .expect("Failed to start server"),
+    };
+    let _a = start(&["8131", "--node", "a", "--link-port", "8132"]);
+    let _b = start(&[
+        "8133",
+        "--node",
+        "b",
+        "--link-port",
+        "8134",
+        "--peer",
+        "127.0.0.1:8132",
+    ]);
+    let _c = start(&[
+        "8135",
+        "--node",
+        "c",
+        "--peer",
+        "127.0.0.1:8132",
+        "--peer",
+        "127.0.0.1:8134",
+    ]);
+    thread::sleep(Duration::from_millis(1500));
+
+    let (Some(mut alice), Some(mut bob), Some(mut carol)) = (
+        Client::connect(8131),
+        Client::connect(8133),
+        Client::connect(8135),
+    ) else {
+        panic!("Should be able to connect to ports 8131, 8133 and 8135");
+    };
+    alice.send("/nick alice");
+    bob.send("/nick bob");
+    carol.send("/nick carol");
+    alice.received();
+    bob.received();
+    carol.received();
+
+    alice.send("hello from a");
+    assert_eq!(bob.received(), ["[alice@a]: hello from a"]);
+    assert_eq!(carol.received(), ["[alice@a]: hello from a"]);
+    assert!(alice.received().is_empty(), "no echo back to the origin");
+
+    carol.send("hello from c");
+    assert_eq!(alice.received(), ["[carol@c]: hello from c"]);
+    assert_eq!(bob.received(), ["[carol@c]: hello from c"]);
+    assert!(carol.received().is_empty());
+
+    // Rooms stay rooms: only b has someone in #rust
+    bob.send("/join rust");
+    bob.received();
+    alice.received();
+    alice.send("/join rust");
+    alice.received();
+    bob.received();
+    alice.send("rust only");
+    assert_eq!(bob.received(), ["[alice@a]: rust only"]);
+    assert!(carol.received().is_empty());
+
+    // Relayed lines are history like any other
+    let mut dave = Client::connect(8133).expect("connect");
+    dave.send("/join rust");
+    let seen = dave.received();
+    assert!(
+        seen.contains(&"[alice@a]: rust only".to_string()),
+        "{:?}",
+        seen
+    );
+}
+
+#[test]
+fn test_24_federation_relays_after_restart() {
+    // b's message counter starts over when it restarts, while a still
+    // remembers the ids b used before
+    let start = |args: &[&str]| ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_chat_server"))
+            .args(args)
+            .spawn()
+            .expect("Failed to start server"),
+    };
+    let b_args = ["8138", "--node", "b", "--peer", "127.0.0.1:8137"];
+    let _a = start(&["8136", "--node", "a", "--link-port", "8137"]);
+    let b = start(&b_args);
+    thread::sleep(Duration::from_millis(1500));
+
+    let mut alice = Client::connect(8136).expect("connect to a");
+    let mut bob = Client::connect(8138).expect("connect to b");
+    alice.send("/nick alice");
+    bob.send("/nick bob");
+    alice.received();
+    bob.received();
+    bob.send("before restart");
+    assert_eq!(alice.received(), ["[bob@b]: before restart"]);
+
+    drop(bob);
+    drop(b);
+    thread::sleep(Duration::from_millis(500));
+    let _b = start(&b_args);
+    thread::sleep(Duration::from_millis(1500));
+
+    let mut bob = Client::connect(8138).expect("connect to restarted b");
+    bob.send("/nick bob");
+    bob.received();
+    alice.received();
+    bob.send("after restart");
+    assert_eq!(alice.received(), ["[bob@b]: after restart"]);
+}
@@ -0,0 +1,372 @@
+//! Service discovery over UDP multicast (or broadcast)
+//!
+//! Servers announce themselves to a group address; clients join the group
+//! and learn who is around without knowing any address but the group's.
+//! mDNS, SSDP and many cluster membership protocols start like this.
+//!
+//! ```text
+//! HELLO <name> <service addr> <interval ms>   every interval, and on DISCOVER
+//! DISCOVER                                    a listener that just joined asks
+//! BYE <name>                                  an announcer leaving
+//! ```
+//!
+//! A listener forgets a service that missed three announcements in a row,
+//! so a crashed announcer disappears even though it never said BYE.
+//!
+//! The group (default `239.255.42.99:4242`, in the organisation-local
+//! 239/8 block) decides the mode: a multicast address is joined with
+//! IP_ADD_MEMBERSHIP on `--interface`, and only hosts that joined receive
+//! the datagrams; any other IPv4 address (`255.255.255.255`, a subnet's
+//! `x.y.z.255`) is sent with SO_BROADCAST and reaches every host on the
+//! link. `--ttl` (default 1) limits how many routers a multicast datagram
+//! may cross: 1 keeps it on the local network. Broadcasts are never routed.
+//!
+//! ```bash
+//! cargo run --bin discovery -- announce api 127.0.0.1:8080
+//! cargo run --bin discovery -- announce cache 127.0.0.1:6379 --every-ms 500
+//! cargo run --bin discovery -- listen --for-ms 3000
+//! cargo run --bin discovery -- listen --group 255.255.255.255:4242   # broadcast
+//! ```
+
+use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::HashMap;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::time::{self, Instant};
+
+const DEFAULT_GROUP: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(239, 255, 42, 99), 4242);
+
+/// A service missing this many announcements in a row is gone
+const MISSED_ANNOUNCEMENTS: u32 = 3;
+
+enum Role {
+    Announce {
+        name: String,
+        service: String,
+        every: Duration,
+        /// Leave after this many periodic announcements
+        count: Option<u64>,
+    },
+    Listen {
+        /// Print what was found and leave after this long
+        duration: Option<Duration>,
+    },
+}
+
+struct Config {
+    role: Role,
+    group: SocketAddrV4,
+    /// Local address of the interface to join and send on; unspecified
+    /// lets the routing table pick
+    interface: Ipv4Addr,
+    ttl: u32,
+}
+
+fn usage() -> String {
+    "usage: discovery announce NAME SERVICE_ADDR [--every-ms MS] [--count N] [OPTIONS]\n       \
+     discovery listen [--for-ms MS] [OPTIONS]\n\
+     options: [--group IP:PORT] [--interface IP] [--ttl N]"
+        .to_string()
+}
+
+fn parse_args(args: Vec<String>) -> Result<Config, String> {
+    let mut args = args.into_iter();
+    let mut role = match args.next().as_deref() {
+        Some("announce") => {
+            let name = args.next().ok_or("missing NAME")?;
+            let service = args.next().ok_or("missing SERVICE_ADDR")?;
+            // Fields are separated by spaces on the wire
+            if name.contains(char::is_whitespace) || service.contains(char::is_whitespace) {
+                return Err("NAME and SERVICE_ADDR cannot contain spaces".to_string());
+            }
+            Role::Announce {
+                name,
+                service,
+                every: Duration::from_secs(1),
+                count: None,
+            }
+        }
+        Some("listen") => Role::Listen { duration: None },
+        Some(other) => return Err(format!("unknown command: {}", other)),
+        None => return Err("missing command".to_string()),
+    };
+    let mut group = DEFAULT_GROUP;
+    let mut interface = Ipv4Addr::UNSPECIFIED;
+    let mut ttl = 1;
+
+    while let Some(arg) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value", arg))?;
+        let bad = || format!("invalid {}: {}", arg, value);
+        match (arg.as_str(), &mut role) {
+            ("--group", _) => group = value.parse().map_err(|_| bad())?,
+            ("--interface", _) => interface = value.parse().map_err(|_| bad())?,
+            ("--ttl", _) => match value.parse() {
+                Ok(n) if (1..=255).contains(&n) => ttl = n,
+                _ => return Err(bad()),
+            },
+            ("--every-ms", Role::Announce { every, .. }) => match value.parse() {
+                Ok(ms) if ms > 0 => *every = Duration::from_millis(ms),
+                _ => return Err(bad()),
+            },
+            ("--count", Role::Announce { count, .. }) => match value.parse() {
+                Ok(n) if n > 0 => *count = Some(n),
+                _ => return Err(bad()),
+            },
+            ("--for-ms", Role::Listen { duration }) => {
+                *duration = Some(Duration::from_millis(value.parse().map_err(|_| bad())?))
+            }
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+    Ok(Config {
+        role,
+        group,
+        interface,
+        ttl,
+    })
+}
+
+/// Bind the group's port and join the group (multicast) or allow sending
+/// to it (broadcast)
+fn open(config: &Config) -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    // Every announcer and listener on this host binds the same port; with
+    // SO_REUSEADDR each of them gets its own copy of a group datagram
+    socket.set_reuse_address(true)?;
+    socket.bind(&SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, config.group.port()).into())?;
+    let group = config.group.ip();
+    if group.is_multicast() {
+        socket.join_multicast_v4(group, &config.interface)?;
+        socket.set_multicast_if_v4(&config.interface)?;
+        socket.set_multicast_ttl_v4(config.ttl)?;
+        // Deliver our own datagrams to the other members on this host too
+        socket.set_multicast_loop_v4(true)?;
+        println!(
+            "Joined {} on {} (ttl {})",
+            group, config.interface, config.ttl
+        );
+    } else {
+        socket.set_broadcast(true)?;
+        println!("Broadcasting to {}", config.group);
+    }
+    socket.set_nonblocking(true)?;
+    UdpSocket::from_std(socket.into())
+}
+
+/// Leave the group. Closing the socket would too; this says it out loud
+fn leave(socket: &UdpSocket, config: &Config) {
+    let group = *config.group.ip();
+    if group.is_multicast() {
+        match socket.leave_multicast_v4(group, config.interface) {
+            Ok(()) => println!("Left {}", group),
+            Err(err) => eprintln!("leaving {}: {}", group, err),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Message<'a> {
+    Hello {
+        name: &'a str,
+        service: &'a str,
+        every: Duration,
+    },
+    Discover,
+    Bye {
+        name: &'a str,
+    },
+}
+
+impl<'a> Message<'a> {
+    /// `None` for anything else that reaches the group's port
+    fn parse(datagram: &'a [u8]) -> Option<Self> {
+        let text = std::str::from_utf8(datagram).ok()?;
+        let fields: Vec<&str> = text.split(' ').collect();
+        match fields[..] {
+            ["HELLO", name, service, ms] => Some(Message::Hello {
+                name,
+                service,
+                every: Duration::from_millis(ms.parse().ok()?),
+            }),
+            ["DISCOVER"] => Some(Message::Discover),
+            ["BYE", name] => Some(Message::Bye { name }),
+            _ => None,
+        }
+    }
+}
+
+/// Ctrl-C, or SIGTERM from `kill`
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut term = signal(SignalKind::terminate()).expect("Failed to watch SIGTERM");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = term.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+async fn announce(
+    socket: &UdpSocket,
+    config: &Config,
+    name: &str,
+    service: &str,
+    every: Duration,
+    count: Option<u64>,
+) -> io::Result<()> {
+    let group = SocketAddr::V4(config.group);
+    let hello = format!("HELLO {} {} {}", name, service, every.as_millis());
+    println!(
+        "Announcing {} at {} every {}ms",
+        name,
+        service,
+        every.as_millis()
+    );
+
+    let mut ticks = time::interval(every);
+    let mut sent = 0;
+    let mut buf = [0u8; 1500];
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+    loop {
+        tokio::select! {
+            _ = ticks.tick() => {
+                if count.is_some_and(|count| sent == count) {
+                    break;
+                }
+                socket.send_to(hello.as_bytes(), group).await?;
+                sent += 1;
+            }
+            received = socket.recv_from(&mut buf) => {
+                let (n, peer) = received?;
+                // Our own HELLOs come back too (multicast loop); only a
+                // newcomer's question needs an answer
+                if Message::parse(&buf[..n]) == Some(Message::Discover) {
+                    socket.send_to(hello.as_bytes(), group).await?;
+                    println!("DISCOVER from {}: announced", peer);
+                }
+            }
+            _ = &mut shutdown => break,
+        }
+    }
+
+    socket
+        .send_to(format!("BYE {}", name).as_bytes(), group)
+        .await?;
+    println!("Said BYE after {} announcements", sent);
+    Ok(())
+}
+
+struct Service {
+    addr: String,
+    from: SocketAddr,
+    /// Forgotten if no HELLO arrives before then
+    expires: Instant,
+}
+
+async fn listen(socket: &UdpSocket, config: &Config, duration: Option<Duration>) -> io::Result<()> {
+    // Don't wait up to a whole interval for the next round of HELLOs
+    socket
+        .send_to(b"DISCOVER", SocketAddr::V4(config.group))
+        .await?;
+
+    let mut services: HashMap<String, Service> = HashMap::new();
+    let deadline = duration.map(|duration| Instant::now() + duration);
+    let mut ticks = time::interval(Duration::from_millis(100));
+    let mut buf = [0u8; 1500];
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+    loop {
+        tokio::select! {
+            received = socket.recv_from(&mut buf) => {
+                let (n, from) = received?;
+                match Message::parse(&buf[..n]) {
+                    Some(Message::Hello { name, service, every }) => {
+                        let expires = Instant::now() + every * MISSED_ANNOUNCEMENTS;
+                        let known = services.get(name).is_some_and(|known| known.addr == service);
+                        if !known {
+                            println!("Found {} at {} (from {})", name, service, from);
+                        }
+                        services.insert(
+                            name.to_string(),
+                            Service { addr: service.to_string(), from, expires },
+                        );
+                    }
+                    Some(Message::Bye { name }) => {
+                        if services.remove(name).is_some() {
+                            println!("{} left (BYE from {})", name, from);
+                        }
+                    }
+                    Some(Message::Discover) | None => {}
+                }
+            }
+            _ = ticks.tick() => {
+                let now = Instant::now();
+                services.retain(|name, service| {
+                    let alive = service.expires > now;
+                    if !alive {
+                        println!(
+                            "{} lost: no HELLO from {} in {} announcements",
+                            name, service.from, MISSED_ANNOUNCEMENTS
+                        );
+                    }
+                    alive
+                });
+                if deadline.is_some_and(|deadline| now >= deadline) {
+                    break;
+                }
+            }
+            _ = &mut shutdown => break,
+        }
+    }
+
+    let mut names: Vec<&String> = services.keys().collect();
+    names.sort();
+    let plural = if names.len() == 1 { "" } else { "s" };
+    println!("{} service{}:", names.len(), plural);
+    for name in names {
+        println!("  {} at {}", name, services[name].addr);
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+    let config = match parse_args(std::env::args().skip(1).collect()) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}\n{}", err, usage());
+            std::process::exit(2);
+        }
+    };
+    let socket = match open(&config) {
+        Ok(socket) => socket,
+        Err(err) => {
+            eprintln!("discovery: cannot open {}: {}", config.group, err);
+            std::process::exit(1);
+        }
+    };
+
+    let result = match &config.role {
+        Role::Announce {
+            name,
+            service,
+            every,
+            count,
+        } => announce(&socket, &config, name, service, *every, *count).await,
+        Role::Listen { duration } => listen(&socket, &config, *duration).await,
+    };
+    leave(&socket, &config);
+    if let Err(err) = result {
+        eprintln!("discovery: {}", err);
+        std::process::exit(1);
+    }
+}
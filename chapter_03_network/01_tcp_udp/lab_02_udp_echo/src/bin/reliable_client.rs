@@ -0,0 +1,201 @@
+//! Reliable UDP client
+//!
+//! Sends stdin line by line to `udp_echo --reliable`, one DATA packet per
+//! line, and returns once every line is acknowledged. Up to `--window`
+//! packets are in flight; each one not acknowledged within its timeout
+//! is sent again. `--loss P` drops some of the client's own packets before they
+//! leave, so the server sees gaps and has to hold later lines back.
+//!
+//! ```bash
+//! seq 1 100 | cargo run --bin reliable_client -- 127.0.0.1:8080 --window 8 --loss 0.2
+//! ```
+
+#[allow(dead_code)] // only parse_probability is needed here
+#[path = "../impair.rs"]
+mod impair;
+#[allow(dead_code)] // the receiving half is the server's
+#[path = "../reliable.rs"]
+mod reliable;
+#[allow(dead_code)] // only chance() is needed here
+#[path = "../../../../../shared/rng.rs"]
+mod rng;
+
+use reliable::{Packet, Sender, MAX_RETRIES};
+use rng::Rng;
+use std::collections::VecDeque;
+use std::io::{self, BufRead};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+struct Config {
+    target: SocketAddr,
+    window: usize,
+    rto: Duration,
+    loss: f64,
+    seed: Option<u64>,
+}
+
+fn usage() -> String {
+    "usage: reliable_client HOST:PORT [--window N] [--rto-ms MS] [--loss P] [--seed N]".to_string()
+}
+
+fn parse_args(args: Vec<String>) -> Result<Config, String> {
+    let mut args = args.into_iter();
+    let target = args.next().ok_or("missing HOST:PORT")?;
+    let target = target
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+        .ok_or_else(|| format!("cannot resolve {}", target))?;
+    let mut config = Config {
+        target,
+        window: 4,
+        rto: Duration::from_millis(200),
+        loss: 0.0,
+        seed: None,
+    };
+    while let Some(arg) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value", arg))?;
+        let bad = || format!("invalid {}: {}", arg, value);
+        match arg.as_str() {
+            "--window" => match value.parse() {
+                Ok(window) if window > 0 => config.window = window,
+                _ => return Err(bad()),
+            },
+            "--rto-ms" => match value.parse() {
+                Ok(ms) if ms > 0 => config.rto = Duration::from_millis(ms),
+                _ => return Err(bad()),
+            },
+            "--loss" => config.loss = impair::parse_probability(&arg, &value)?,
+            "--seed" => config.seed = Some(value.parse().map_err(|_| bad())?),
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+    Ok(config)
+}
+
+struct Link {
+    socket: UdpSocket,
+    loss: f64,
+    rng: Rng,
+    sent: usize,
+    dropped: usize,
+}
+
+impl Link {
+    /// Send `packet`, unless the simulated loss eats it
+    fn transmit(&mut self, packet: &[u8]) -> io::Result<()> {
+        self.sent += 1;
+        if self.rng.chance(self.loss) {
+            self.dropped += 1;
+            return Ok(());
+        }
+        self.socket.send(packet).map(|_| ())
+    }
+}
+
+fn run(config: &Config, mut lines: VecDeque<Vec<u8>>) -> Result<String, String> {
+    let local: SocketAddr = match config.target {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(local).map_err(|err| format!("bind: {}", err))?;
+    // connect() filters out datagrams from anyone but the server
+    socket
+        .connect(config.target)
+        .map_err(|err| format!("connect: {}", err))?;
+    let seed = config.seed.unwrap_or_else(|| {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        now.as_nanos() as u64
+    });
+    let mut link = Link {
+        socket,
+        loss: config.loss,
+        rng: Rng::new(seed),
+        sent: 0,
+        dropped: 0,
+    };
+    let messages = lines.len();
+    let mut sender = Sender::new(config.window, config.rto);
+    let mut buf = [0u8; 2048];
+
+    while !lines.is_empty() || !sender.is_idle() {
+        // 1. Fill the window
+        while sender.can_send() {
+            let Some(line) = lines.pop_front() else {
+                break;
+            };
+            let packet = sender.send(&line, Instant::now());
+            link.transmit(&packet).map_err(|err| err.to_string())?;
+        }
+
+        // 2. Wait for an ACK, but no longer than the next retransmission
+        let due = sender.next_due().expect("packets in flight");
+        let wait = due.saturating_duration_since(Instant::now());
+        // A zero read timeout is an error: wait at least a millisecond
+        link.socket
+            .set_read_timeout(Some(wait.max(Duration::from_millis(1))))
+            .map_err(|err| err.to_string())?;
+        match link.socket.recv(&mut buf) {
+            Ok(n) => {
+                if let Some(Packet::Ack { next }) = Packet::decode(&buf[..n]) {
+                    sender.on_ack(next);
+                }
+            }
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) => {}
+            // ICMP port unreachable: keep retrying, the server may come up
+            Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => {}
+            Err(err) => return Err(err.to_string()),
+        }
+
+        // 3. Retransmit whatever timed out
+        let resend = sender.due(Instant::now()).map_err(|seq| {
+            format!(
+                "no ACK for #{} after {} retransmissions: giving up",
+                seq, MAX_RETRIES
+            )
+        })?;
+        for packet in resend {
+            link.transmit(&packet).map_err(|err| err.to_string())?;
+        }
+    }
+
+    Ok(format!(
+        "Sent {} messages in {} packets ({} retransmissions, {} dropped by --loss), all acknowledged",
+        messages, link.sent, sender.retransmissions, link.dropped
+    ))
+}
+
+fn main() {
+    let config = match parse_args(std::env::args().skip(1).collect()) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}\n{}", err, usage());
+            std::process::exit(2);
+        }
+    };
+    let lines: io::Result<Vec<String>> = io::stdin().lock().lines().collect();
+    let lines = match lines {
+        Ok(lines) => lines.into_iter().map(String::into_bytes).collect(),
+        Err(err) => {
+            eprintln!("cannot read stdin: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    match run(&config, lines) {
+        Ok(summary) => println!("{}", summary),
+        Err(err) => {
+            eprintln!("reliable_client: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,356 @@
+//! Datagram size experiment
+//!
+//! Sends datagrams of growing size to `udp_echo --sizes`, which answers
+//! each with `SIZE <bytes received>`, and reports for every size how many
+//! IP packets it takes on the path and where sending stops working.
+//!
+//! A datagram larger than the path MTU minus the headers (1500 - 20 - 8
+//! = 1472 bytes on Ethernet, IPv4) is split into fragments by the sending
+//! kernel and put back together by the receiver's; losing one fragment
+//! loses the whole datagram. With `--df` the Don't Fragment bit is set
+//! (IP_PMTUDISC_DO), and the kernel refuses anything over the MTU with
+//! EMSGSIZE instead. Either way nothing over 65507 bytes (IPv4) fits in a
+//! UDP datagram at all: EMSGSIZE again.
+//!
+//! ```bash
+//! cargo run -- --sizes
+//! cargo run --bin size_probe -- 127.0.0.1:8080            # loopback: MTU 65536
+//! cargo run --bin size_probe -- 127.0.0.1:8080 --df
+//! ```
+
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(1);
+const UDP_HEADER: usize = 8;
+
+struct Config {
+    target: SocketAddr,
+    from: usize,
+    to: usize,
+    dont_fragment: bool,
+}
+
+fn usage() -> String {
+    "usage: size_probe HOST:PORT [--from BYTES] [--to BYTES] [--df]".to_string()
+}
+
+fn parse_args(args: Vec<String>) -> Result<Config, String> {
+    let mut args = args.into_iter();
+    let target = args.next().ok_or("missing HOST:PORT")?;
+    let target = target
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+        .ok_or_else(|| format!("cannot resolve {}", target))?;
+    let mut config = Config {
+        target,
+        from: 64,
+        to: 65536,
+        dont_fragment: false,
+    };
+    while let Some(arg) = args.next() {
+        if arg == "--df" {
+            config.dont_fragment = true;
+            continue;
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value", arg))?;
+        let bytes = match value.parse() {
+            Ok(bytes) if bytes > 0 => bytes,
+            _ => return Err(format!("invalid {}: {}", arg, value)),
+        };
+        match arg.as_str() {
+            "--from" => config.from = bytes,
+            "--to" => config.to = bytes,
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+    if config.from > config.to {
+        return Err("--from must not be larger than --to".to_string());
+    }
+    Ok(config)
+}
+
+/// Path MTU and Don't Fragment. nix wraps IP_MTU but not IPV6_MTU or the
+/// *_MTU_DISCOVER options, so those go through libc directly
+#[cfg(target_os = "linux")]
+mod path {
+    use nix::libc;
+    use nix::sys::socket::{getsockopt, sockopt::IpMtu};
+    use std::io;
+    use std::net::UdpSocket;
+    use std::os::fd::AsRawFd;
+
+    /// The kernel's MTU estimate for the route to the connected peer
+    pub fn mtu(socket: &UdpSocket, v6: bool) -> io::Result<usize> {
+        if !v6 {
+            return Ok(getsockopt(socket, IpMtu)? as usize);
+        }
+        let mut value: libc::c_int = 0;
+        let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+        // SAFETY: value and len describe a c_int the kernel writes into
+        let rc = unsafe {
+            libc::getsockopt(
+                socket.as_raw_fd(),
+                libc::IPPROTO_IPV6,
+                libc::IPV6_MTU,
+                &mut value as *mut libc::c_int as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        if rc != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(value as usize)
+    }
+
+    /// Never fragment: sends over the path MTU fail with EMSGSIZE
+    pub fn dont_fragment(socket: &UdpSocket, v6: bool) -> io::Result<()> {
+        let (level, name, value) = if v6 {
+            (
+                libc::IPPROTO_IPV6,
+                libc::IPV6_MTU_DISCOVER,
+                libc::IPV6_PMTUDISC_DO,
+            )
+        } else {
+            (
+                libc::IPPROTO_IP,
+                libc::IP_MTU_DISCOVER,
+                libc::IP_PMTUDISC_DO,
+            )
+        };
+        // SAFETY: value is a c_int that outlives the call
+        let rc = unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                level,
+                name,
+                &value as *const libc::c_int as *const libc::c_void,
+                std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if rc != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod path {
+    use std::io;
+    use std::net::UdpSocket;
+
+    pub fn mtu(_: &UdpSocket, _: bool) -> io::Result<usize> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    pub fn dont_fragment(_: &UdpSocket, _: bool) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+/// IP packets a datagram of `payload` bytes takes on a path with this MTU.
+/// Every fragment but the last carries a multiple of 8 bytes; IPv6 adds
+/// an 8-byte fragment header to each
+fn ip_packets(payload: usize, mtu: usize, v6: bool) -> usize {
+    let ip_header = if v6 { 40 } else { 20 };
+    let udp = payload + UDP_HEADER;
+    if udp <= mtu - ip_header {
+        return 1;
+    }
+    let per_fragment = if v6 {
+        (mtu - ip_header - 8) / 8 * 8
+    } else {
+        (mtu - ip_header) / 8 * 8
+    };
+    udp.div_ceil(per_fragment)
+}
+
+enum Outcome {
+    /// The server's SIZE answer
+    Echoed(usize),
+    NoReply,
+    TooBig,
+}
+
+fn probe(socket: &UdpSocket, size: usize) -> io::Result<Outcome> {
+    let payload = vec![b'x'; size];
+    match socket.send(&payload) {
+        Ok(_) => {}
+        Err(err) if err.raw_os_error() == Some(nix::libc::EMSGSIZE) => return Ok(Outcome::TooBig),
+        Err(err) => return Err(err),
+    }
+    let mut buf = [0u8; 64];
+    match socket.recv(&mut buf) {
+        Ok(n) => {
+            let reply = String::from_utf8_lossy(&buf[..n]);
+            reply
+                .strip_prefix("SIZE ")
+                .and_then(|size| size.parse().ok())
+                .map(Outcome::Echoed)
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "unexpected reply {:?}: is the server running --sizes?",
+                            reply
+                        ),
+                    )
+                })
+        }
+        Err(err)
+            if matches!(
+                err.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+            ) =>
+        {
+            Ok(Outcome::NoReply)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Doubling sizes, plus the ones on either side of the fragmentation and
+/// the IPv4 size limits
+fn sizes(config: &Config, mtu: Option<usize>, v6: bool) -> Vec<usize> {
+    let headers = if v6 { 40 } else { 20 } + UDP_HEADER;
+    let mut sizes: Vec<usize> =
+        std::iter::successors(Some(config.from), |size| size.checked_mul(2))
+            .take_while(|&size| size < config.to)
+            .chain([config.to, 65507, 65508])
+            .chain(
+                mtu.map(|mtu| [mtu - headers, mtu - headers + 1])
+                    .into_iter()
+                    .flatten(),
+            )
+            .filter(|size| (config.from..=config.to).contains(size))
+            .collect();
+    sizes.sort_unstable();
+    sizes.dedup();
+    sizes
+}
+
+fn run(config: &Config) -> Result<(), String> {
+    let v6 = config.target.is_ipv6();
+    let local: SocketAddr = if v6 {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    };
+    let socket = UdpSocket::bind(local).map_err(|err| format!("bind: {}", err))?;
+    // connect() picks the route, which is what the path MTU belongs to
+    socket
+        .connect(config.target)
+        .map_err(|err| format!("connect: {}", err))?;
+    socket
+        .set_read_timeout(Some(TIMEOUT))
+        .map_err(|err| err.to_string())?;
+    if config.dont_fragment {
+        path::dont_fragment(&socket, v6).map_err(|err| format!("--df: {}", err))?;
+    }
+
+    let mtu = path::mtu(&socket, v6).ok();
+    let headers = if v6 { 40 } else { 20 } + UDP_HEADER;
+    match mtu {
+        Some(mtu) => {
+            println!(
+                "Path MTU to {}: {} bytes; {} of them are IP and UDP headers",
+                config.target, mtu, headers
+            );
+            let beyond = if config.dont_fragment {
+                "rejected with EMSGSIZE (--df)"
+            } else {
+                "fragmented"
+            };
+            println!("Payloads over {} bytes are {}", mtu - headers, beyond);
+        }
+        None => println!("Path MTU to {}: unknown on this system", config.target),
+    }
+
+    let mut largest_sent = None;
+    let mut first_too_big = None;
+    for size in sizes(config, mtu, v6) {
+        let outcome = probe(&socket, size).map_err(|err| format!("{} bytes: {}", size, err))?;
+        let packets = match mtu {
+            Some(mtu) => {
+                let packets = ip_packets(size, mtu, v6);
+                let plural = if packets == 1 { "" } else { "s" };
+                format!(" ({} IP packet{})", packets, plural)
+            }
+            None => String::new(),
+        };
+        match outcome {
+            Outcome::Echoed(received) if received == size => {
+                println!("{:>6} bytes: received in full{}", size, packets)
+            }
+            Outcome::Echoed(received) => println!(
+                "{:>6} bytes: server received only {} (its buffer is too small){}",
+                size, received, packets
+            ),
+            Outcome::NoReply => println!(
+                "{:>6} bytes: no reply within {:?} (a lost fragment loses the datagram){}",
+                size, TIMEOUT, packets
+            ),
+            Outcome::TooBig => {
+                println!("{:>6} bytes: EMSGSIZE, not sent", size);
+                first_too_big = Some(size);
+                break;
+            }
+        }
+        largest_sent = Some(size);
+    }
+
+    // Narrow the limit down to the byte
+    match (largest_sent, first_too_big) {
+        (Some(ok), Some(too_big)) => {
+            let largest = largest_sendable(&socket, ok, too_big)?;
+            println!(
+                "Largest datagram the kernel sends: {} bytes (EMSGSIZE above)",
+                largest
+            );
+        }
+        (None, Some(_)) => println!("Not even {} bytes can be sent", config.from),
+        (_, None) => println!("No EMSGSIZE up to {} bytes", config.to),
+    }
+    Ok(())
+}
+
+/// Binary search between a size that was sent and one that wasn't
+fn largest_sendable(
+    socket: &UdpSocket,
+    mut ok: usize,
+    mut too_big: usize,
+) -> Result<usize, String> {
+    while too_big - ok > 1 {
+        let mid = ok + (too_big - ok) / 2;
+        let payload = vec![b'x'; mid];
+        match socket.send(&payload) {
+            Ok(_) => ok = mid,
+            Err(err) if err.raw_os_error() == Some(nix::libc::EMSGSIZE) => too_big = mid,
+            Err(err) => return Err(format!("{} bytes: {}", mid, err)),
+        }
+    }
+    // Drain the answers to the search's datagrams
+    let _ = socket.set_read_timeout(Some(Duration::from_millis(100)));
+    let mut buf = [0u8; 64];
+    while socket.recv(&mut buf).is_ok() {}
+    Ok(ok)
+}
+
+fn main() {
+    let config = match parse_args(std::env::args().skip(1).collect()) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}\n{}", err, usage());
+            std::process::exit(2);
+        }
+    };
+    if let Err(err) = run(&config) {
+        eprintln!("size_probe: {}", err);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,62 @@
+//! UDP echo client
+//!
+//! Sends one datagram and prints the echo. The target may be a host name:
+//! every address it resolves to is tried in turn, each from a socket of the
+//! matching family (an IPv4 socket cannot send to an IPv6 address).
+//!
+//! ```bash
+//! cargo run --bin udp_client -- localhost:8080 hello
+//! cargo run --bin udp_client -- [::1]:8080 hello
+//! ```
+
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Send `msg` to `target` and wait for the reply
+fn echo(target: SocketAddr, msg: &[u8]) -> io::Result<Vec<u8>> {
+    let local: SocketAddr = match target {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(local)?;
+    socket.set_read_timeout(Some(TIMEOUT))?;
+    // connect() filters out datagrams from anyone but the target
+    socket.connect(target)?;
+    socket.send(msg)?;
+
+    let mut buf = [0u8; 2048];
+    let n = socket.recv(&mut buf)?;
+    Ok(buf[..n].to_vec())
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.len() < 2 {
+        eprintln!("usage: udp_client HOST:PORT MESSAGE...");
+        std::process::exit(2);
+    }
+    let msg = args[1..].join(" ");
+
+    let targets: Vec<SocketAddr> = match args[0].to_socket_addrs() {
+        Ok(addrs) => addrs.collect(),
+        Err(err) => {
+            eprintln!("cannot resolve {}: {}", args[0], err);
+            std::process::exit(2);
+        }
+    };
+
+    for target in targets {
+        match echo(target, msg.as_bytes()) {
+            Ok(reply) => {
+                println!("{}", String::from_utf8_lossy(&reply));
+                return;
+            }
+            Err(err) => eprintln!("{}: {}", target, err),
+        }
+    }
+    eprintln!("no echo from {}", args[0]);
+    std::process::exit(1);
+}
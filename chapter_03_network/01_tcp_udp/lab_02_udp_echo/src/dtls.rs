@@ -0,0 +1,544 @@
+//! DTLS: TLS for datagrams
+//!
+//! TLS needs a byte stream that neither loses nor reorders anything; DTLS
+//! (RFC 6347, version 1.2 here) keeps TLS's handshake and records but adds
+//! what UDP lacks: each record carries an epoch and sequence number, the
+//! handshake is retransmitted on a timer, and the server first answers a
+//! ClientHello with a HelloVerifyRequest holding a cookie. Only a client
+//! that echoes the cookie from its real address gets the expensive part
+//! (certificate, key exchange), so a spoofed source can't make the server
+//! do work or send large replies to a victim.
+//!
+//! ```text
+//! client                                   server
+//! ClientHello                 ------>
+//!                             <------      HelloVerifyRequest (cookie)
+//! ClientHello (cookie)        ------>
+//!                             <------      ServerHello, Certificate,
+//!                                          ServerKeyExchange, ServerHelloDone
+//! ClientKeyExchange, ChangeCipherSpec,
+//! Finished                    ------>
+//!                             <------      ChangeCipherSpec, Finished
+//! ApplicationData             <----->      ApplicationData
+//! ```
+//!
+//! The server speaks DTLS through OpenSSL with one session, and one
+//! thread, per peer: its datagrams are routed to the session by source
+//! address, and whatever the session sends goes back through the receive
+//! loop's socket, past `--loss` and `--delay-ms`, so DTLS's own
+//! retransmission of lost handshake flights can be watched. The
+//! certificate is generated at startup and self-signed; the client pins
+//! it by its SHA-256 fingerprint instead of a CA.
+//!
+//! The receive loop does the cookie exchange itself, keeping nothing: a
+//! ClientHello without a cookie gets a HelloVerifyRequest, and only one
+//! with the right cookie starts a session, if fewer than `MAX_SESSIONS`
+//! are open. The new session is handed the same ClientHello without its
+//! cookie first, as OpenSSL expects, and its own HelloVerifyRequest (with
+//! the same cookie) is dropped instead of sent.
+
+use openssl::asn1::Asn1Time;
+use openssl::bn::{BigNum, MsbOption};
+use openssl::ec::{EcGroup, EcKey};
+use openssl::error::ErrorStack;
+use openssl::ex_data::Index;
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Private};
+use openssl::sign::Signer;
+use openssl::ssl::{ErrorCode, Ssl, SslContext, SslMethod, SslOptions, SslStream};
+use openssl::x509::{X509NameBuilder, X509Ref, X509};
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::SocketAddr;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Largest datagram DTLS sends: handshake messages bigger than this
+/// (the certificate, usually) are split into fragments, as in one
+/// Ethernet frame with room to spare for IP and UDP headers
+pub const MTU: u32 = 1400;
+
+/// How often a blocked read gives OpenSSL a chance to retransmit
+pub const TICK: Duration = Duration::from_millis(100);
+
+/// Sessions open at once; a verified ClientHello beyond that is dropped
+pub const MAX_SESSIONS: usize = 64;
+
+/// How long a verified peer has to finish its handshake
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Record content types (the first byte of every record)
+const CHANGE_CIPHER_SPEC: u8 = 20;
+const ALERT: u8 = 21;
+const HANDSHAKE: u8 = 22;
+const APPLICATION_DATA: u8 = 23;
+
+/// Type, version, epoch, sequence number, length
+const RECORD_HEADER: usize = 13;
+
+/// Type, length, message sequence, fragment offset, fragment length
+const HANDSHAKE_HEADER: usize = 12;
+
+const CLIENT_HELLO: u8 = 1;
+const HELLO_VERIFY_REQUEST: u8 = 3;
+
+/// The version a HelloVerifyRequest carries, whatever is negotiated later
+const DTLS_1_0: [u8; 2] = [0xfe, 0xff];
+
+fn handshake_name(msg_type: u8) -> &'static str {
+    match msg_type {
+        1 => "ClientHello",
+        2 => "ServerHello",
+        3 => "HelloVerifyRequest",
+        4 => "NewSessionTicket",
+        11 => "Certificate",
+        12 => "ServerKeyExchange",
+        13 => "CertificateRequest",
+        14 => "ServerHelloDone",
+        16 => "ClientKeyExchange",
+        20 => "Finished",
+        _ => "unknown handshake message",
+    }
+}
+
+/// The records in a datagram, as far as they can be read without keys:
+/// from epoch 1 on (after ChangeCipherSpec) only the header is plaintext
+pub fn describe(datagram: &[u8]) -> String {
+    let mut records = Vec::new();
+    let mut rest = datagram;
+    while rest.len() >= RECORD_HEADER {
+        let epoch = u16::from_be_bytes([rest[3], rest[4]]);
+        let len = u16::from_be_bytes([rest[11], rest[12]]) as usize;
+        let body = &rest[RECORD_HEADER..];
+        if body.len() < len {
+            break;
+        }
+        let encrypted = if epoch > 0 { "encrypted " } else { "" };
+        records.push(match rest[0] {
+            CHANGE_CIPHER_SPEC => "ChangeCipherSpec".to_string(),
+            ALERT => format!("{}Alert", encrypted),
+            HANDSHAKE if epoch == 0 && !body.is_empty() => handshake_name(body[0]).to_string(),
+            // The only encrypted handshake message without renegotiation
+            HANDSHAKE => "encrypted Finished".to_string(),
+            APPLICATION_DATA => format!("ApplicationData ({} bytes encrypted)", len),
+            _ => break,
+        });
+        rest = &body[len..];
+    }
+    if records.is_empty() || !rest.is_empty() {
+        return "not DTLS".to_string();
+    }
+    records.join(" + ")
+}
+
+fn u24(bytes: &[u8]) -> usize {
+    (bytes[0] as usize) << 16 | (bytes[1] as usize) << 8 | bytes[2] as usize
+}
+
+fn u24_bytes(n: usize) -> [u8; 3] {
+    [(n >> 16) as u8, (n >> 8) as u8, n as u8]
+}
+
+/// What the receive loop reads from a ClientHello before any session
+/// exists: the cookie, and the sequence numbers to answer with
+struct ClientHello<'a> {
+    record_seq: [u8; 6],
+    message_seq: u16,
+    cookie: &'a [u8],
+    /// Where the cookie's length byte is in the datagram
+    cookie_at: usize,
+}
+
+/// A datagram holding one record with one unfragmented, plaintext
+/// ClientHello, which is how clients send it
+fn client_hello(datagram: &[u8]) -> Option<ClientHello<'_>> {
+    if datagram.len() < RECORD_HEADER + HANDSHAKE_HEADER || datagram[0] != HANDSHAKE {
+        return None;
+    }
+    let epoch = u16::from_be_bytes([datagram[3], datagram[4]]);
+    let record_len = u16::from_be_bytes([datagram[11], datagram[12]]) as usize;
+    let message = &datagram[RECORD_HEADER..];
+    if epoch != 0 || record_len != message.len() || message[0] != CLIENT_HELLO {
+        return None;
+    }
+    let len = u24(&message[1..4]);
+    if u24(&message[6..9]) != 0
+        || u24(&message[9..12]) != len
+        || HANDSHAKE_HEADER + len != message.len()
+    {
+        return None;
+    }
+    // client_version and random, then the session id and the cookie, each
+    // behind a length byte
+    let session_id_at = RECORD_HEADER + HANDSHAKE_HEADER + 2 + 32;
+    let cookie_at = session_id_at + 1 + *datagram.get(session_id_at)? as usize;
+    let cookie_len = *datagram.get(cookie_at)? as usize;
+    Some(ClientHello {
+        record_seq: datagram[5..11].try_into().ok()?,
+        message_seq: u16::from_be_bytes([message[4], message[5]]),
+        cookie: datagram.get(cookie_at + 1..cookie_at + 1 + cookie_len)?,
+        cookie_at,
+    })
+}
+
+/// The answer to `hello`: the same record and message sequence numbers,
+/// as RFC 6347 asks, so the server needs no counters of its own
+fn hello_verify_request(hello: &ClientHello, cookie: &[u8]) -> Vec<u8> {
+    let len = 2 + 1 + cookie.len();
+    let mut datagram = Vec::with_capacity(RECORD_HEADER + HANDSHAKE_HEADER + len);
+    datagram.push(HANDSHAKE);
+    datagram.extend_from_slice(&DTLS_1_0);
+    datagram.extend_from_slice(&[0, 0]);
+    datagram.extend_from_slice(&hello.record_seq);
+    datagram.extend_from_slice(&((HANDSHAKE_HEADER + len) as u16).to_be_bytes());
+    datagram.push(HELLO_VERIFY_REQUEST);
+    datagram.extend_from_slice(&u24_bytes(len));
+    datagram.extend_from_slice(&hello.message_seq.to_be_bytes());
+    datagram.extend_from_slice(&u24_bytes(0));
+    datagram.extend_from_slice(&u24_bytes(len));
+    datagram.extend_from_slice(&DTLS_1_0);
+    datagram.push(cookie.len() as u8);
+    datagram.extend_from_slice(cookie);
+    datagram
+}
+
+/// The ClientHello that `hello` answers a HelloVerifyRequest for: the
+/// same message without the cookie, as message 0 in record 0
+fn without_cookie(datagram: &[u8], hello: &ClientHello) -> Vec<u8> {
+    let cut = hello.cookie.len();
+    let mut first = datagram[..hello.cookie_at].to_vec();
+    first.push(0);
+    first.extend_from_slice(&datagram[hello.cookie_at + 1 + cut..]);
+    first[5..11].fill(0);
+    let record_len = u16::from_be_bytes([first[11], first[12]]) as usize - cut;
+    first[11..13].copy_from_slice(&(record_len as u16).to_be_bytes());
+    let len = u24(&first[14..17]) - cut;
+    first[14..17].copy_from_slice(&u24_bytes(len));
+    first[17..19].fill(0);
+    first[22..25].copy_from_slice(&u24_bytes(len));
+    first
+}
+
+fn is_hello_verify_request(datagram: &[u8]) -> bool {
+    datagram.first() == Some(&HANDSHAKE)
+        && datagram.get(RECORD_HEADER) == Some(&HELLO_VERIFY_REQUEST)
+}
+
+/// `AB:CD:...`, as `openssl x509 -fingerprint -sha256` prints it
+pub fn fingerprint(cert: &X509Ref) -> Result<String, ErrorStack> {
+    let digest = cert.digest(MessageDigest::sha256())?;
+    let hex: Vec<String> = digest.iter().map(|b| format!("{:02X}", b)).collect();
+    Ok(hex.join(":"))
+}
+
+/// How to watch the datagrams to or from `addr` go by
+pub fn tcpdump_hint(addr: SocketAddr) -> String {
+    let interface = if addr.ip().is_loopback() { "lo" } else { "any" };
+    format!(
+        "sudo tcpdump -i {} -n -X udp port {}    # or: tshark -i {} -d udp.port=={},dtls",
+        interface,
+        addr.port(),
+        interface,
+        addr.port()
+    )
+}
+
+/// A P-256 key and a certificate for it signed by itself, valid for a day
+fn self_signed() -> Result<(X509, PKey<Private>), ErrorStack> {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+    let key = PKey::from_ec_key(EcKey::generate(&group)?)?;
+
+    let mut name = X509NameBuilder::new()?;
+    name.append_entry_by_nid(Nid::COMMONNAME, "udp_echo")?;
+    let name = name.build();
+    let mut serial = BigNum::new()?;
+    serial.rand(64, MsbOption::MAYBE_ZERO, false)?;
+
+    let mut cert = X509::builder()?;
+    cert.set_version(2)?;
+    let serial = serial.to_asn1_integer()?;
+    cert.set_serial_number(&serial)?;
+    cert.set_subject_name(&name)?;
+    cert.set_issuer_name(&name)?;
+    cert.set_pubkey(&key)?;
+    let (not_before, not_after) = (Asn1Time::days_from_now(0)?, Asn1Time::days_from_now(1)?);
+    cert.set_not_before(&not_before)?;
+    cert.set_not_after(&not_after)?;
+    cert.sign(&key, MessageDigest::sha256())?;
+    Ok((cert.build(), key))
+}
+
+/// HMAC of the peer's address under a key made at startup: the server
+/// keeps nothing per cookie, yet only that address can present it
+fn cookie(secret: &PKey<Private>, peer: &SocketAddr) -> Result<Vec<u8>, ErrorStack> {
+    let mut signer = Signer::new(MessageDigest::sha256(), secret)?;
+    signer.update(peer.to_string().as_bytes())?;
+    signer.sign_to_vec()
+}
+
+fn cookie_matches(secret: &PKey<Private>, peer: &SocketAddr, presented: &[u8]) -> bool {
+    match cookie(secret, peer) {
+        Ok(mac) => mac.len() == presented.len() && openssl::memcmp::eq(&mac, presented),
+        Err(_) => false,
+    }
+}
+
+/// What every peer's session is made from
+#[derive(Clone)]
+pub struct Server {
+    context: SslContext,
+    /// Where the cookie callbacks find the address of the peer
+    peer_index: Index<Ssl, SocketAddr>,
+    /// The key cookies are made with
+    secret: PKey<Private>,
+    /// End a session after this much silence
+    idle: Duration,
+    pub fingerprint: String,
+}
+
+impl Server {
+    pub fn new(idle: Duration) -> Result<Self, ErrorStack> {
+        let (cert, key) = self_signed()?;
+        let peer_index = Ssl::new_ex_index()?;
+        let mut secret = [0u8; 32];
+        openssl::rand::rand_bytes(&mut secret)?;
+        let secret = PKey::hmac(&secret)?;
+
+        let mut context = SslContext::builder(SslMethod::dtls())?;
+        context.set_certificate(&cert)?;
+        context.set_private_key(&key)?;
+        context.check_private_key()?;
+        // Send HelloVerifyRequest first; the MTU is set per session, not
+        // asked of the transport (it has no idea)
+        context.set_options(SslOptions::COOKIE_EXCHANGE | SslOptions::NO_QUERY_MTU);
+        let generate_secret = secret.clone();
+        context.set_cookie_generate_cb(move |ssl, buf| {
+            let peer = ssl.ex_data(peer_index).ok_or_else(ErrorStack::get)?;
+            let mac = cookie(&generate_secret, peer)?;
+            buf[..mac.len()].copy_from_slice(&mac);
+            Ok(mac.len())
+        });
+        let verify_secret = secret.clone();
+        context.set_cookie_verify_cb(move |ssl, presented| {
+            ssl.ex_data(peer_index)
+                .is_some_and(|peer| cookie_matches(&verify_secret, peer, presented))
+        });
+
+        Ok(Server {
+            context: context.build(),
+            peer_index,
+            secret,
+            idle,
+            fingerprint: fingerprint(&cert)?,
+        })
+    }
+}
+
+/// A session's view of UDP: the datagrams its peer sent, routed to it
+/// by the receive loop, and a way to send back through the same socket
+struct Channel {
+    peer: SocketAddr,
+    incoming: mpsc::Receiver<Vec<u8>>,
+    outgoing: UnboundedSender<(Vec<u8>, SocketAddr)>,
+    idle: Duration,
+    last_heard: Instant,
+    /// The receive loop sent the HelloVerifyRequest: drop the session's
+    verify_sent: bool,
+}
+
+impl Read for Channel {
+    /// One datagram per read, as DTLS expects. Nothing yet is WouldBlock,
+    /// which OpenSSL turns into WANT_READ: the caller tries again, and
+    /// that is when an expired retransmission timer resends a flight
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.incoming.recv_timeout(TICK) {
+            Ok(datagram) => {
+                self.last_heard = Instant::now();
+                let n = datagram.len().min(buf.len());
+                buf[..n].copy_from_slice(&datagram[..n]);
+                Ok(n)
+            }
+            Err(RecvTimeoutError::Timeout) if self.last_heard.elapsed() < self.idle => {
+                Err(io::ErrorKind::WouldBlock.into())
+            }
+            Err(RecvTimeoutError::Timeout) => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("idle for {:?}", self.idle),
+            )),
+            Err(RecvTimeoutError::Disconnected) => Err(io::ErrorKind::ConnectionAborted.into()),
+        }
+    }
+}
+
+impl Write for Channel {
+    /// Each write is one datagram
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.verify_sent && is_hello_verify_request(buf) {
+            self.verify_sent = false;
+            return Ok(buf.len());
+        }
+        self.outgoing
+            .send((buf.to_vec(), self.peer))
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn failure(err: openssl::ssl::Error) -> String {
+    match err.io_error() {
+        Some(io) => io.to_string(),
+        None => err.to_string(),
+    }
+}
+
+/// Handshake with `peer`, then decrypt each record, print it and send it
+/// back encrypted, until close_notify, an error or `idle` of silence
+fn serve_peer(server: Server, channel: Channel) -> Result<(), String> {
+    let peer = channel.peer;
+    let mut ssl = Ssl::new(&server.context).map_err(|err| err.to_string())?;
+    ssl.set_ex_data(server.peer_index, peer);
+    ssl.set_mtu(MTU).map_err(|err| err.to_string())?;
+    let mut stream = SslStream::new(ssl, channel).map_err(|err| err.to_string())?;
+
+    let started = Instant::now();
+    loop {
+        match stream.accept() {
+            Ok(()) => break,
+            Err(err)
+                if err.code() == ErrorCode::WANT_READ && started.elapsed() < HANDSHAKE_TIMEOUT =>
+            {
+                continue
+            }
+            Err(err) if err.code() == ErrorCode::WANT_READ => {
+                return Err(format!("no handshake within {:?}", HANDSHAKE_TIMEOUT))
+            }
+            Err(err) => return Err(format!("handshake failed: {}", failure(err))),
+        }
+    }
+    println!(
+        "[dtls] {} handshake done: {}, {}",
+        peer,
+        stream.ssl().version_str(),
+        stream
+            .ssl()
+            .current_cipher()
+            .map_or("no cipher", |cipher| cipher.name())
+    );
+
+    let mut buf = vec![0u8; 16384];
+    loop {
+        match stream.ssl_read(&mut buf) {
+            Ok(n) => {
+                let msg = String::from_utf8_lossy(&buf[..n]);
+                println!("  Decrypted {} bytes from {}: {}", n, peer, msg);
+                stream
+                    .ssl_write(&buf[..n])
+                    .map_err(|err| format!("write: {}", failure(err)))?;
+            }
+            Err(err) if err.code() == ErrorCode::WANT_READ => continue,
+            Err(err) if err.code() == ErrorCode::ZERO_RETURN => {
+                // Answer the peer's close_notify with ours
+                let _ = stream.shutdown();
+                println!("[dtls] {} closed (close_notify)", peer);
+                return Ok(());
+            }
+            Err(err) => return Err(failure(err)),
+        }
+    }
+}
+
+struct PeerSession {
+    incoming: mpsc::Sender<Vec<u8>>,
+    thread: JoinHandle<()>,
+}
+
+/// The DTLS sessions of one socket's peers
+pub struct Endpoint {
+    server: Server,
+    peers: HashMap<SocketAddr, PeerSession>,
+    outgoing: UnboundedSender<(Vec<u8>, SocketAddr)>,
+}
+
+impl Endpoint {
+    /// Sessions send their datagrams to `outgoing`, for the receive loop
+    /// to put on the socket
+    pub fn new(server: Server, outgoing: UnboundedSender<(Vec<u8>, SocketAddr)>) -> Self {
+        Endpoint {
+            server,
+            peers: HashMap::new(),
+            outgoing,
+        }
+    }
+
+    /// Hand a datagram to `peer`'s session. Without one, answer a
+    /// ClientHello with a cookie, or start a session if it has the right
+    /// one; nothing is kept for a peer until then
+    pub fn receive(&mut self, datagram: &[u8], peer: SocketAddr) {
+        // Sessions end on their own after `idle` of silence
+        self.peers
+            .retain(|_, session| !session.thread.is_finished());
+        if let Some(session) = self.peers.get(&peer) {
+            // Fails once the session's thread is gone
+            if session.incoming.send(datagram.to_vec()).is_ok() {
+                return;
+            }
+        }
+        let Some(hello) = client_hello(datagram) else {
+            println!("  no DTLS session with {}: ignored", peer);
+            return;
+        };
+
+        if hello.cookie.is_empty() {
+            match cookie(&self.server.secret, &peer) {
+                Ok(cookie) => {
+                    let _ = self
+                        .outgoing
+                        .send((hello_verify_request(&hello, &cookie), peer));
+                    println!("[dtls] {} sent a cookie, no session yet", peer);
+                }
+                Err(err) => println!("[dtls] {} no cookie: {}", peer, err),
+            }
+            return;
+        }
+        // Record 0 and message 0 went to the ClientHello without a cookie
+        let answers_ours = hello.message_seq == 1 && hello.record_seq != [0; 6];
+        if !answers_ours || !cookie_matches(&self.server.secret, &peer, hello.cookie) {
+            println!("[dtls] {} wrong cookie: ignored", peer);
+            return;
+        }
+        if self.peers.len() >= MAX_SESSIONS {
+            println!(
+                "[dtls] {} refused: {} sessions open",
+                peer,
+                self.peers.len()
+            );
+            return;
+        }
+
+        let (incoming, receiver) = mpsc::channel();
+        let _ = incoming.send(without_cookie(datagram, &hello));
+        let _ = incoming.send(datagram.to_vec());
+        let channel = Channel {
+            peer,
+            incoming: receiver,
+            outgoing: self.outgoing.clone(),
+            idle: self.server.idle,
+            last_heard: Instant::now(),
+            verify_sent: true,
+        };
+        let server = self.server.clone();
+        let thread = thread::spawn(move || {
+            if let Err(err) = serve_peer(server, channel) {
+                println!("[dtls] {} session ended: {}", peer, err);
+            }
+        });
+        self.peers.insert(peer, PeerSession { incoming, thread });
+    }
+}
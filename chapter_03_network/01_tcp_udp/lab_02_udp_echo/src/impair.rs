@@ -0,0 +1,77 @@
+//! A bad network on purpose: loss, duplication and delay of the echoes
+//!
+//! UDP promises nothing, but on loopback nothing ever goes wrong, so a
+//! client's retry logic never runs. With these flags the server behaves
+//! like a poor link, the way `tc qdisc ... netem` would, but only for its
+//! replies and without root:
+//!
+//! - `--loss P`: drop a received datagram without echoing it
+//! - `--dup P`: echo it twice; each copy gets its own delay, so the two
+//!   may arrive apart or in the other order than later echoes
+//! - `--delay-ms MS[±JITTER]` (or `MS+-JITTER`): hold each echo for MS
+//!   milliseconds, plus or minus up to JITTER, drawn uniformly; jitter
+//!   larger than the gap between datagrams reorders them
+//! - `--seed N`: the same seed gives the same drops and delays for the
+//!   same sequence of datagrams, so a failing client run can be replayed
+//!
+//! ```bash
+//! cargo run -- --loss 0.1 --dup 0.05 --delay-ms 50±20 --seed 7
+//! ```
+
+use std::time::Duration;
+
+use crate::rng::Rng;
+
+/// What to do to each echo; the default does nothing
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Impairment {
+    /// Probabilities, each in 0.0..=1.0
+    pub loss: f64,
+    pub dup: f64,
+    pub delay: Duration,
+    pub jitter: Duration,
+}
+
+impl Impairment {
+    pub fn is_none(&self) -> bool {
+        self.loss == 0.0 && self.dup == 0.0 && self.delay.is_zero() && self.jitter.is_zero()
+    }
+
+    /// When to send each copy of one echo: none if lost, two if duplicated
+    pub fn plan(&self, rng: &mut Rng) -> Vec<Duration> {
+        if rng.chance(self.loss) {
+            return Vec::new();
+        }
+        let copies = if rng.chance(self.dup) { 2 } else { 1 };
+        (0..copies).map(|_| self.delay_for(rng)).collect()
+    }
+
+    /// delay ± jitter, never below zero
+    fn delay_for(&self, rng: &mut Rng) -> Duration {
+        let offset = self.jitter.as_secs_f64() * (2.0 * rng.unit() - 1.0);
+        Duration::from_secs_f64((self.delay.as_secs_f64() + offset).max(0.0))
+    }
+}
+
+/// `P` in 0..=1 for `--loss` and `--dup`
+pub fn parse_probability(flag: &str, value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(p) if (0.0..=1.0).contains(&p) => Ok(p),
+        _ => Err(format!("{} must be between 0 and 1, got {}", flag, value)),
+    }
+}
+
+/// `MS`, `MS±JITTER` or `MS+-JITTER` for `--delay-ms`
+pub fn parse_delay(value: &str) -> Result<(Duration, Duration), String> {
+    let (delay, jitter) = match value.split_once('±').or_else(|| value.split_once("+-")) {
+        Some((delay, jitter)) => (delay, jitter),
+        None => (value, "0"),
+    };
+    let ms = |s: &str| {
+        s.trim()
+            .parse::<u64>()
+            .map(Duration::from_millis)
+            .map_err(|_| format!("invalid --delay-ms: {} (want MS or MS±JITTER)", value))
+    };
+    Ok((ms(delay)?, ms(jitter)?))
+}
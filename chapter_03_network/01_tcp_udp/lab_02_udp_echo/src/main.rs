@@ -46,26 +46,547 @@
 //! - [ ] Echoes back to sender
 //! - [ ] Shows packet statistics
 //! - [ ] Handles multiple clients (no connection state)
+//!
+//! ## Extension: IPv6 / Dual-Stack
+//! - `--bind ADDR` (default `127.0.0.1:8080`) and `--v6only`, as in the
+//!   echo servers of chapter 2: `[::]:8080` also receives IPv4 datagrams,
+//!   from senders shown as `::ffff:a.b.c.d`, and replies reach them
+//! - `udp_client HOST:PORT MESSAGE` resolves the host (`localhost` may be
+//!   `::1` or `127.0.0.1`), binds a local socket of the same family and
+//!   prints the echo
+//! ```bash
+//! cargo run -- --bind [::]:8080
+//! cargo run --bin udp_client -- [::1]:8080 hello
+//! cargo run --bin udp_client -- 127.0.0.1:8080 hello
+//! ```
+//!
+//! ## Extension: Statistics
+//! - every `--stats-every SECS` seconds (default 10, `0` turns it off) the
+//!   server prints packets/s, bytes/s and distinct senders for that
+//!   interval, plus the totals
+//! - `--stats-addr ADDR` serves `GET /stats` over TCP: the totals as JSON
+//! ```bash
+//! cargo run -- --stats-every 1 --stats-addr 127.0.0.1:8081
+//! curl -s localhost:8081/stats
+//! ```
+//!
+//! ## Extension: Lossy Network Simulator
+//! - `--loss P`, `--dup P` and `--delay-ms MS[±JITTER]` drop, duplicate
+//!   and delay the echoes, so a client's timeouts and retries get
+//!   exercised on loopback (see `src/impair.rs`)
+//! - `--seed N` replays the same faults; without it the seed comes from
+//!   the clock and is printed at startup
+//! ```bash
+//! cargo run -- --loss 0.1 --dup 0.05 --delay-ms 50±10
+//! ```
+//!
+//! ## Extension: Reliable UDP
+//! - `--reliable`: instead of echoing, treat each datagram as a DATA
+//!   packet (`src/reliable.rs`), answer it with a cumulative ACK, and
+//!   print the messages of each sender in order, once each, holding the
+//!   ones that overtook a lost packet
+//! - `reliable_client HOST:PORT [--window N] [--rto-ms MS] [--loss P]`
+//!   sends stdin line by line: up to N packets unacknowledged, each
+//!   retransmitted after MS without an ACK (doubling each time), and
+//!   `--loss` drops some of its own packets so gaps happen
+//! - with the server's `--loss`/`--delay-ms` on the ACKs too, this is
+//!   TCP's core (sequence numbers, ACKs, retransmission, a sliding
+//!   window) built on a protocol that promises nothing
+//! ```bash
+//! cargo run -- --reliable --loss 0.2 --delay-ms 20±15
+//! seq 1 100 | cargo run --bin reliable_client -- 127.0.0.1:8080 --loss 0.2
+//! ```
+//!
+//! ## Extension: SO_REUSEPORT
+//! - `--sockets N` binds N sockets to the same address with SO_REUSEPORT,
+//!   each received by a task of its own. On Linux the kernel hashes each
+//!   sender's address and port to pick a socket, so one sender always
+//!   lands on the same socket and many senders spread across them
+//! - each datagram is printed with the socket that got it, and the stats
+//!   report and `/stats` show packets per socket
+//! - macOS and the BSDs accept the option but hand every datagram to one
+//!   socket: there the counts show no spreading at all
+//! ```bash
+//! cargo run -- --sockets 4 --stats-every 1
+//! for i in $(seq 1 20); do echo hi | nc -u -w0 localhost 8080; done
+//! ss -uanp 'sport = :8080'               # four sockets, one port
+//! ```
+//!
+//! ## Extension: Sessions
+//! - each sender gets a session (packets, bytes, first and last seen),
+//!   opened by its first datagram (see `src/session.rs`)
+//! - `--idle-ms MS` (default 60000): a session silent that long is
+//!   expired and its counters printed; UDP never says a client left
+//! - Ctrl-C or SIGTERM prints the sessions still active and the totals,
+//!   then exits
+//! ```bash
+//! cargo run -- --idle-ms 5000
+//! echo hi | nc -u -w1 localhost 8080     # expires 5s later
+//! ```
+//!
+//! ## Extension: Multicast Discovery
+//! - `discovery announce NAME ADDR` joins a multicast group (default
+//!   `239.255.42.99:4242`) and sends `HELLO` with its service address
+//!   every `--every-ms` (default 1000); on Ctrl-C, SIGTERM or after
+//!   `--count` announcements it sends `BYE` and leaves the group
+//! - `discovery listen` joins the group, sends `DISCOVER` so announcers
+//!   answer at once, and prints services as they appear, say `BYE` or miss
+//!   three announcements; `--for-ms` ends with a summary
+//! - `--ttl N` (default 1) is how many routers an announcement may cross;
+//!   `--interface IP` picks the interface to join on; a non-multicast
+//!   `--group` such as `255.255.255.255:4242` uses broadcast instead (see
+//!   `src/bin/discovery.rs`)
+//! ```bash
+//! cargo run --bin discovery -- announce api 127.0.0.1:8080 &
+//! cargo run --bin discovery -- listen --for-ms 3000
+//! ```
+//!
+//! ## Extension: Datagram Sizes
+//! - `--sizes` answers each datagram with `SIZE <bytes received>` instead
+//!   of echoing it; the receive buffer holds the largest datagram (64 KiB),
+//!   where a smaller one would truncate without an error
+//! - `size_probe HOST:PORT [--from B] [--to B] [--df]` sends doubling
+//!   sizes plus the ones around the limits, prints the path MTU, how many
+//!   IP packets each size needs, and finds the largest size the kernel
+//!   sends before EMSGSIZE. `--df` sets Don't Fragment: over the MTU,
+//!   EMSGSIZE instead of fragments (see `src/bin/size_probe.rs`)
+//! ```bash
+//! cargo run -- --sizes
+//! cargo run --bin size_probe -- 127.0.0.1:8080
+//! cargo run -- --sizes --bind [::1]:8080 & cargo run --bin size_probe -- [::1]:8080
+//! ```
+//!
+//! ## Extension: DTLS
+//! - `--dtls` echoes inside a DTLS 1.2 session per peer (see `src/dtls.rs`)
+//!   with a self-signed certificate made at startup; the server prints
+//!   the certificate's fingerprint and the tcpdump command to watch with,
+//!   and for each datagram the records in it, then what it decrypts
+//! - `dtls_client HOST:PORT MESSAGE... [--fingerprint SHA256]` prints
+//!   every datagram of the handshake (cookie exchange, fragmented
+//!   certificate, retransmissions), each echo and the bytes encryption
+//!   added to it; `--fingerprint` refuses any other certificate
+//! - plain datagrams get no answer in this mode, and it doesn't combine
+//!   with `--reliable` or `--sizes`; `--loss` and `--delay-ms` apply to
+//!   the handshake too, so a lost flight is resent on DTLS's own timer
+//! - a peer gets a session (and a thread) only once it returns the
+//!   cookie; at most 64 are open, and one that hasn't finished its
+//!   handshake in 10s or then stays silent for `--idle-ms` is closed
+//! ```bash
+//! cargo run -- --dtls
+//! sudo tcpdump -i lo -n -X udp port 8080
+//! cargo run --bin dtls_client -- 127.0.0.1:8080 hello --fingerprint <printed>
+//! ```
+
+use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::HashMap;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::net::{TcpListener, UdpSocket};
+use tokio::sync::mpsc;
 
-use std::sync::atomic::{AtomicU64, Ordering};
-use tokio::net::UdpSocket;
+mod dtls;
+mod impair;
+#[allow(dead_code)] // the sending half is reliable_client's
+mod reliable;
+#[allow(dead_code)] // between_1_and, below and shuffle are other labs'
+#[path = "../../../../shared/rng.rs"]
+mod rng;
+mod session;
+mod stats;
+use impair::Impairment;
+use reliable::{Packet, Receiver};
+use rng::Rng;
+use session::Sessions;
+use stats::Stats;
 
 // ============================================================
 // TODO: Implement the UDP echo server
 // ============================================================
 
-// Statistics counters
-static PACKETS_RECEIVED: AtomicU64 = AtomicU64::new(0);
-static BYTES_PROCESSED: AtomicU64 = AtomicU64::new(0);
+/// Bind a UDP socket. For IPv6, set IPV6_V6ONLY explicitly: the default
+/// differs between systems (Linux: sysctl net.ipv6.bindv6only).
+/// `reuse_port` lets several sockets bind the same address
+fn bind(addr: SocketAddr, v6only: bool, reuse_port: bool) -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(v6only)?;
+    }
+    if reuse_port {
+        socket.set_reuse_port(true)?;
+    }
+    socket.bind(&addr.into())?;
+    socket.set_nonblocking(true)?;
+    UdpSocket::from_std(socket.into())
+}
+
+struct Config {
+    bind: SocketAddr,
+    v6only: bool,
+    /// Zero: no periodic report
+    stats_every: Duration,
+    stats_addr: Option<SocketAddr>,
+    impairment: Impairment,
+    seed: Option<u64>,
+    /// ACK and reorder instead of echoing
+    reliable: bool,
+    /// Sockets bound to the same address with SO_REUSEPORT
+    sockets: usize,
+    /// Expire a peer's session after this much silence
+    idle: Duration,
+    /// Answer with the datagram's size instead of echoing it
+    sizes: bool,
+    /// Echo inside DTLS sessions
+    dtls: bool,
+}
+
+fn usage() -> String {
+    "usage: udp_echo [--bind ADDR] [--v6only] [--stats-every SECS] [--stats-addr ADDR] \
+     [--loss P] [--dup P] [--delay-ms MS[±JITTER]] [--seed N] [--reliable] [--sockets N] [--idle-ms MS] [--sizes] [--dtls]"
+        .to_string()
+}
+
+fn resolve(addr: &str) -> Result<SocketAddr, String> {
+    addr.to_socket_addrs()
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+        .ok_or_else(|| format!("cannot resolve {}", addr))
+}
+
+fn parse_args(args: Vec<String>) -> Result<Config, String> {
+    let mut bind = "127.0.0.1:8080".to_string();
+    let mut v6only = false;
+    let mut stats_every = Duration::from_secs(10);
+    let mut stats_addr = None;
+    let mut impairment = Impairment::default();
+    let mut seed = None;
+    let mut reliable = false;
+    let mut sockets = 1;
+    let mut idle = Duration::from_secs(60);
+    let mut sizes = false;
+    let mut dtls = false;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--bind" => bind = args.next().ok_or("--bind needs an address")?,
+            "--v6only" => v6only = true,
+            "--reliable" => reliable = true,
+            "--sizes" => sizes = true,
+            "--dtls" => dtls = true,
+            "--sockets" => {
+                let value = args.next().ok_or("--sockets needs a number")?;
+                sockets = match value.parse() {
+                    Ok(n) if n > 0 => n,
+                    _ => return Err(format!("invalid --sockets: {}", value)),
+                };
+            }
+            "--idle-ms" => {
+                let value = args.next().ok_or("--idle-ms needs milliseconds")?;
+                idle = match value.parse() {
+                    Ok(ms) if ms > 0 => Duration::from_millis(ms),
+                    _ => return Err(format!("invalid --idle-ms: {}", value)),
+                };
+            }
+            "--stats-every" => {
+                let secs = args.next().ok_or("--stats-every needs seconds")?;
+                let secs: u64 = secs
+                    .parse()
+                    .map_err(|_| format!("invalid --stats-every: {}", secs))?;
+                stats_every = Duration::from_secs(secs);
+            }
+            "--stats-addr" => {
+                let addr = args.next().ok_or("--stats-addr needs an address")?;
+                stats_addr = Some(resolve(&addr)?);
+            }
+            "--loss" | "--dup" => {
+                let value = args.next().ok_or(format!("{} needs a probability", arg))?;
+                let p = impair::parse_probability(&arg, &value)?;
+                if arg == "--loss" {
+                    impairment.loss = p;
+                } else {
+                    impairment.dup = p;
+                }
+            }
+            "--delay-ms" => {
+                let value = args.next().ok_or("--delay-ms needs milliseconds")?;
+                (impairment.delay, impairment.jitter) = impair::parse_delay(&value)?;
+            }
+            "--seed" => {
+                let value = args.next().ok_or("--seed needs a number")?;
+                seed = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("invalid --seed: {}", value))?,
+                );
+            }
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+    let bind = resolve(&bind)?;
+    if v6only && !bind.is_ipv6() {
+        return Err("--v6only needs an IPv6 --bind address".to_string());
+    }
+    if reliable && sizes {
+        return Err("--reliable and --sizes cannot be combined".to_string());
+    }
+    if dtls && (reliable || sizes) {
+        return Err("--dtls cannot be combined with --reliable or --sizes".to_string());
+    }
+    Ok(Config {
+        bind,
+        v6only,
+        stats_every,
+        stats_addr,
+        impairment,
+        seed,
+        reliable,
+        sockets,
+        idle,
+        sizes,
+        dtls,
+    })
+}
+
+/// `--reliable`: take one DATA packet from `peer`, print what it makes
+/// deliverable, and return the ACK. `None` if it isn't a DATA packet
+fn receive(
+    receivers: &mut HashMap<SocketAddr, Receiver>,
+    data: &[u8],
+    peer: SocketAddr,
+) -> Option<Vec<u8>> {
+    let Some(Packet::Data { seq, payload }) = Packet::decode(data) else {
+        println!("  not a DATA packet: ignored");
+        return None;
+    };
+    // One sequence space per sender; nothing ever forgets one
+    let receiver = receivers.entry(peer).or_default();
+    let delivered = receiver.receive(seq, payload);
+    if delivered.is_empty() {
+        if seq < receiver.next() {
+            println!("  DATA #{}: duplicate", seq);
+        } else {
+            println!(
+                "  DATA #{}: held, waiting for #{} ({} held)",
+                seq,
+                receiver.next(),
+                receiver.buffered()
+            );
+        }
+    }
+    for (seq, msg) in delivered {
+        println!(
+            "  Delivered #{} from {}: {}",
+            seq,
+            peer,
+            String::from_utf8_lossy(&msg)
+        );
+    }
+    Some(
+        Packet::Ack {
+            next: receiver.next(),
+        }
+        .encode(),
+    )
+}
+
+/// Ctrl-C, or SIGTERM from `kill` or a service manager
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut term = signal(SignalKind::terminate()).expect("Failed to watch SIGTERM");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = term.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+async fn echo(socket: &UdpSocket, data: &[u8], peer: SocketAddr) {
+    if let Err(err) = socket.send_to(data, &peer).await {
+        eprintln!("send_to error: {}", err);
+    }
+}
+
+/// Send `reply` to `peer` through the simulated network: dropped,
+/// duplicated or delayed as `--loss`, `--dup` and `--delay-ms` say
+async fn send(
+    socket: &Arc<UdpSocket>,
+    impairment: &Impairment,
+    rng: &mut Rng,
+    reply: Vec<u8>,
+    peer: SocketAddr,
+) {
+    let delays = impairment.plan(rng);
+    match delays.len() {
+        0 => println!("  dropped (simulated loss)"),
+        1 => {}
+        _ => println!("  duplicated"),
+    }
+    for delay in delays {
+        if delay.is_zero() {
+            echo(socket, &reply, peer).await;
+            continue;
+        }
+        // Later datagrams keep flowing while this one waits
+        let socket = Arc::clone(socket);
+        let data = reply.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            echo(&socket, &data, peer).await;
+        });
+    }
+}
+
+/// An IPv4 UDP payload is at most 65535 - 20 (IP header) - 8 (UDP header)
+/// = 65507 bytes, IPv6 65535 - 8; this holds either
+const MAX_DATAGRAM: usize = 65536;
+
+/// What every receive loop shares
+struct Shared {
+    stats: Arc<Stats>,
+    sessions: Arc<Mutex<Sessions>>,
+    impairment: Impairment,
+    reliable: bool,
+    sizes: bool,
+    dtls: Option<dtls::Server>,
+    /// More than one socket: say which one got each datagram
+    label: bool,
+}
+
+/// Receive on socket `index` forever; with `--sockets`, one of these
+/// runs per socket, each a task of its own
+async fn serve_socket(index: usize, socket: Arc<UdpSocket>, shared: Arc<Shared>, mut rng: Rng) {
+    let on = if shared.label {
+        format!(" on socket {}", index)
+    } else {
+        String::new()
+    };
+    // A sender always hashes to the same socket, so per-socket state works
+    let mut receivers = HashMap::new();
+    // Room for the largest datagram: a smaller buffer truncates silently
+    let mut buf = vec![0u8; MAX_DATAGRAM];
+    // With --dtls, the peers' sessions hand their datagrams back here
+    let (outgoing, mut from_sessions) = mpsc::unbounded_channel();
+    let mut dtls = shared
+        .dtls
+        .clone()
+        .map(|server| dtls::Endpoint::new(server, outgoing.clone()));
+
+    loop {
+        let (len, peer) = tokio::select! {
+            received = socket.recv_from(&mut buf) => match received {
+                Ok(received) => received,
+                Err(err) => {
+                    eprintln!("recv_from error: {}", err);
+                    continue;
+                }
+            },
+            Some((datagram, peer)) = from_sessions.recv() => {
+                send(&socket, &shared.impairment, &mut rng, datagram, peer).await;
+                continue;
+            }
+        };
+        shared.stats.record(index, len, peer);
+        let opened = {
+            let mut sessions = shared.sessions.lock().unwrap();
+            sessions
+                .touch(peer, len, Instant::now())
+                .then(|| sessions.active())
+        };
+        if let Some(active) = opened {
+            println!("[session] new {} ({} active)", peer, active);
+        }
+
+        let reply = if let Some(dtls) = &mut dtls {
+            // What is on the wire; the session prints what it decrypts
+            let records = dtls::describe(&buf[..len]);
+            println!("Received {} bytes from {}{}: {}", len, peer, on, records);
+            dtls.receive(&buf[..len], peer);
+            continue;
+        } else if shared.reliable {
+            println!("Received {} bytes from {}{}", len, peer, on);
+            match receive(&mut receivers, &buf[..len], peer) {
+                Some(ack) => ack,
+                None => continue,
+            }
+        } else if shared.sizes {
+            println!("Received {} bytes from {}{}", len, peer, on);
+            format!("SIZE {}", len).into_bytes()
+        } else {
+            let msg = String::from_utf8_lossy(&buf[..len]);
+            println!("Received {} bytes from {}{}: {}", len, peer, on, msg);
+            buf[..len].to_vec()
+        };
+        send(&socket, &shared.impairment, &mut rng, reply, peer).await;
+    }
+}
 
 #[tokio::main]
 async fn main() {
-    let addr = "127.0.0.1:8080";
+    let config = match parse_args(std::env::args().skip(1).collect()) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}\n{}", err, usage());
+            std::process::exit(2);
+        }
+    };
+    let mut addr = config.bind;
 
     // TODO: Implement
-    let socket = UdpSocket::bind(addr)
-        .await
-        .expect("bind failed");
+    let reuse_port = config.sockets > 1;
+    let mut sockets = Vec::new();
+    for _ in 0..config.sockets {
+        let socket = bind(addr, config.v6only, reuse_port).expect("bind failed");
+        // With port 0, the others join the port the first one got
+        addr = socket.local_addr().expect("local_addr failed");
+        sockets.push(Arc::new(socket));
+    }
+    let mut notes = Vec::new();
+    if addr.is_ipv6() && !config.v6only {
+        notes.push("dual-stack".to_string());
+    }
+    if reuse_port {
+        notes.push(format!("{} sockets, SO_REUSEPORT", config.sockets));
+    }
+    if notes.is_empty() {
+        println!("UDP Echo Server listening on {}", addr);
+    } else {
+        println!(
+            "UDP Echo Server listening on {} ({})",
+            addr,
+            notes.join(", ")
+        );
+    }
+
+    let stats = Stats::new(config.sockets);
+    if !config.stats_every.is_zero() {
+        tokio::spawn(stats::report(Arc::clone(&stats), config.stats_every));
+    }
+    if let Some(stats_addr) = config.stats_addr {
+        let listener = TcpListener::bind(stats_addr)
+            .await
+            .expect("stats bind failed");
+        println!("Stats on http://{}/stats", stats_addr);
+        tokio::spawn(stats::serve(listener, Arc::clone(&stats)));
+    }
+
+    let impairment = config.impairment;
+    let seed = config.seed.unwrap_or_else(|| {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        now.as_nanos() as u64
+    });
+    if !impairment.is_none() {
+        println!(
+            "Simulating loss {}, duplication {}, delay {:?} ± {:?} (--seed {})",
+            impairment.loss, impairment.dup, impairment.delay, impairment.jitter, seed
+        );
+    }
     // 1. Create UdpSocket bound to addr
     // 2. Loop:
     //    - recv_from() to get datagram and sender address
@@ -73,27 +594,46 @@ async fn main() {
     //    - Print received data info
     //    - send_to() to echo back to sender
     // 3. Handle errors gracefully
-    let mut buf = [0u8; 2048];
-
-    loop {
-        match socket.recv_from(&mut buf).await {
-            Ok((len, peer)) => {
-                PACKETS_RECEIVED.fetch_add(1, Ordering::Relaxed);
-                BYTES_PROCESSED.fetch_add(len as u64, Ordering::Relaxed);
-
-                let msg = String::from_utf8_lossy(&buf[..len]);
-                println!("Received {} bytes from {}: {}", len, peer, msg);
-
-                match socket.send_to(&buf[..len], &peer).await {
-                    Ok(_) => {}
-                    Err(err) => {
-                        eprintln!("send_to error: {}", err);
-                    }
-                }
-            }
-            Err(err) => {
-                eprintln!("recv_from error: {}", err);
-            }
+    if config.reliable {
+        println!("Reliable mode: answering DATA packets with ACKs");
+    }
+    if config.sizes {
+        println!("Size mode: answering each datagram with SIZE <bytes received>");
+    }
+    let dtls = config.dtls.then(|| match dtls::Server::new(config.idle) {
+        Ok(server) => server,
+        Err(err) => {
+            eprintln!("DTLS setup failed: {}", err);
+            std::process::exit(1);
         }
+    });
+    if let Some(server) = &dtls {
+        println!("DTLS mode: echoing inside DTLS 1.2 sessions, one per peer");
+        println!("  Certificate SHA-256: {}", server.fingerprint);
+        println!("  Watch the handshake: {}", dtls::tcpdump_hint(addr));
+        println!("  Records start with 0x16 (handshake) or 0x17 (encrypted data); without --dtls the dump shows the message itself");
     }
+    let sessions = Arc::new(Mutex::new(Sessions::default()));
+    tokio::spawn(session::expire_idle(Arc::clone(&sessions), config.idle));
+    let shared = Arc::new(Shared {
+        stats,
+        sessions: Arc::clone(&sessions),
+        impairment,
+        reliable: config.reliable,
+        sizes: config.sizes,
+        dtls,
+        label: reuse_port,
+    });
+
+    // Socket i draws its faults from seed + i: same seed, same faults per
+    // socket, though which socket a sender lands on is the kernel's choice
+    for (index, socket) in sockets.into_iter().enumerate() {
+        let rng = Rng::new(seed.wrapping_add(index as u64));
+        tokio::spawn(serve_socket(index, socket, Arc::clone(&shared), rng));
+    }
+
+    // The receive loops never end on their own
+    shutdown_signal().await;
+    let summary = sessions.lock().unwrap().summary(Instant::now());
+    println!("Shutting down: {}", summary);
 }
@@ -0,0 +1,196 @@
+//! Reliable delivery over UDP: what TCP does, in miniature
+//!
+//! Every message travels in one DATA packet with a sequence number. The
+//! receiver answers each one with a cumulative ACK, "everything below N
+//! arrived", delivers messages in order, and holds the ones that overtook
+//! a lost packet until the gap is filled. The sender keeps up to `window`
+//! packets unacknowledged; a packet not acknowledged within its timeout is
+//! sent again, with the timeout doubled each time up to 8x (exponential
+//! backoff).
+//!
+//! ```text
+//! DATA: [0x01][seq: u32 big-endian][payload]
+//! ACK:  [0x02][next expected seq: u32 big-endian]
+//! ```
+//!
+//! What TCP adds: byte instead of message sequence numbers, a handshake
+//! that picks random initial numbers, a timeout estimated from measured
+//! round trips, fast retransmit on duplicate ACKs, and a window the
+//! receiver and the congestion controller keep resizing. Sequence numbers
+//! here never wrap: 2^32 messages is more than a lab will send.
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+const DATA: u8 = 0x01;
+const ACK: u8 = 0x02;
+const HEADER: usize = 5;
+
+/// Give up on a packet after this many retransmissions
+pub const MAX_RETRIES: u32 = 8;
+
+/// Out-of-order packets further ahead than this are dropped, not buffered
+const MAX_AHEAD: u32 = 1024;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Packet<'a> {
+    Data {
+        seq: u32,
+        payload: &'a [u8],
+    },
+    /// Everything below `next` arrived
+    Ack {
+        next: u32,
+    },
+}
+
+impl<'a> Packet<'a> {
+    pub fn encode(&self) -> Vec<u8> {
+        let (kind, seq, payload) = match *self {
+            Packet::Data { seq, payload } => (DATA, seq, payload),
+            Packet::Ack { next } => (ACK, next, &[][..]),
+        };
+        let mut buf = Vec::with_capacity(HEADER + payload.len());
+        buf.push(kind);
+        buf.extend_from_slice(&seq.to_be_bytes());
+        buf.extend_from_slice(payload);
+        buf
+    }
+
+    /// `None` for anything that isn't one of our packets
+    pub fn decode(buf: &'a [u8]) -> Option<Self> {
+        if buf.len() < HEADER {
+            return None;
+        }
+        let seq = u32::from_be_bytes(buf[1..HEADER].try_into().ok()?);
+        match buf[0] {
+            DATA => Some(Packet::Data {
+                seq,
+                payload: &buf[HEADER..],
+            }),
+            ACK if buf.len() == HEADER => Some(Packet::Ack { next: seq }),
+            _ => None,
+        }
+    }
+}
+
+/// One direction of a conversation, on the receiving end
+#[derive(Default)]
+pub struct Receiver {
+    next: u32,
+    /// Arrived ahead of a gap
+    ahead: BTreeMap<u32, Vec<u8>>,
+}
+
+impl Receiver {
+    /// The cumulative ACK to send back
+    pub fn next(&self) -> u32 {
+        self.next
+    }
+
+    /// Take one DATA packet; returns the messages now deliverable, in
+    /// order. Duplicates (already delivered or already buffered) yield none
+    pub fn receive(&mut self, seq: u32, payload: &[u8]) -> Vec<(u32, Vec<u8>)> {
+        if seq < self.next || seq - self.next >= MAX_AHEAD {
+            return Vec::new();
+        }
+        self.ahead.entry(seq).or_insert_with(|| payload.to_vec());
+        let mut delivered = Vec::new();
+        while let Some(payload) = self.ahead.remove(&self.next) {
+            delivered.push((self.next, payload));
+            self.next += 1;
+        }
+        delivered
+    }
+
+    /// Messages waiting for a gap to fill
+    pub fn buffered(&self) -> usize {
+        self.ahead.len()
+    }
+}
+
+struct InFlight {
+    packet: Vec<u8>,
+    /// Retransmit if no ACK covers it by then
+    due: Instant,
+    retries: u32,
+}
+
+/// The sending end: a sliding window of unacknowledged packets
+pub struct Sender {
+    window: usize,
+    rto: Duration,
+    next_seq: u32,
+    in_flight: BTreeMap<u32, InFlight>,
+    pub retransmissions: usize,
+}
+
+impl Sender {
+    pub fn new(window: usize, rto: Duration) -> Self {
+        Self {
+            window,
+            rto,
+            next_seq: 0,
+            in_flight: BTreeMap::new(),
+            retransmissions: 0,
+        }
+    }
+
+    /// Is there room in the window for another packet?
+    pub fn can_send(&self) -> bool {
+        self.in_flight.len() < self.window
+    }
+
+    /// Nothing is waiting for an ACK
+    pub fn is_idle(&self) -> bool {
+        self.in_flight.is_empty()
+    }
+
+    /// Number `payload` and return the packet to transmit
+    pub fn send(&mut self, payload: &[u8], now: Instant) -> Vec<u8> {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let packet = Packet::Data { seq, payload }.encode();
+        self.in_flight.insert(
+            seq,
+            InFlight {
+                packet: packet.clone(),
+                due: now + self.rto,
+                retries: 0,
+            },
+        );
+        packet
+    }
+
+    /// Slide the window past everything below `next`; returns how many
+    /// packets that acknowledged. Old and duplicate ACKs acknowledge none
+    pub fn on_ack(&mut self, next: u32) -> usize {
+        let before = self.in_flight.len();
+        self.in_flight = self.in_flight.split_off(&next);
+        before - self.in_flight.len()
+    }
+
+    /// When the earliest retransmission is due
+    pub fn next_due(&self) -> Option<Instant> {
+        self.in_flight.values().map(|f| f.due).min()
+    }
+
+    /// The packets whose timeout has passed, to send again, or `Err(seq)`
+    /// for a packet that ran out of retries
+    pub fn due(&mut self, now: Instant) -> Result<Vec<Vec<u8>>, u32> {
+        let mut resend = Vec::new();
+        for (&seq, flight) in self.in_flight.iter_mut() {
+            if flight.due > now {
+                continue;
+            }
+            if flight.retries == MAX_RETRIES {
+                return Err(seq);
+            }
+            flight.retries += 1;
+            flight.due = now + self.rto * 2u32.pow(flight.retries.min(3));
+            self.retransmissions += 1;
+            resend.push(flight.packet.clone());
+        }
+        Ok(resend)
+    }
+}
@@ -0,0 +1,133 @@
+//! Per-peer sessions: state for a protocol without connections
+//!
+//! UDP has no handshake and no FIN, so the server never learns that a
+//! client is gone. Anything it keeps per sender (counters here, the
+//! reliable mode's receive windows, a game's player state) must be dropped
+//! on a timer instead: a peer silent for `--idle-ms` is expired, and its
+//! next datagram starts a new session. NAT tables and conntrack time out
+//! UDP "connections" the same way.
+//!
+//! ```text
+//! [session] new 127.0.0.1:50123 (1 active)
+//! [session] 127.0.0.1:50123 expired after 60.0s idle: 3 packets, 18 bytes over 1.2s
+//! ```
+//!
+//! On Ctrl-C or SIGTERM the server prints every session still active,
+//! then the totals including the expired ones.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub struct Session {
+    first_seen: Instant,
+    last_seen: Instant,
+    packets: u64,
+    bytes: u64,
+}
+
+#[derive(Default)]
+pub struct Sessions {
+    active: HashMap<SocketAddr, Session>,
+    /// Sessions that expired, and what they carried
+    expired: u64,
+    expired_packets: u64,
+    expired_bytes: u64,
+}
+
+impl Sessions {
+    /// One datagram of `len` bytes from `peer`; true if it opened a session
+    pub fn touch(&mut self, peer: SocketAddr, len: usize, now: Instant) -> bool {
+        let mut opened = false;
+        let session = self.active.entry(peer).or_insert_with(|| {
+            opened = true;
+            Session {
+                first_seen: now,
+                last_seen: now,
+                packets: 0,
+                bytes: 0,
+            }
+        });
+        session.last_seen = now;
+        session.packets += 1;
+        session.bytes += len as u64;
+        opened
+    }
+
+    pub fn active(&self) -> usize {
+        self.active.len()
+    }
+
+    /// Remove and return the sessions silent for at least `idle`
+    fn expire(&mut self, idle: Duration, now: Instant) -> Vec<(SocketAddr, Session)> {
+        let idle_peers: Vec<SocketAddr> = self
+            .active
+            .iter()
+            .filter(|(_, session)| now.duration_since(session.last_seen) >= idle)
+            .map(|(peer, _)| *peer)
+            .collect();
+        let expired: Vec<(SocketAddr, Session)> = idle_peers
+            .into_iter()
+            .filter_map(|peer| self.active.remove_entry(&peer))
+            .collect();
+        for (_, session) in &expired {
+            self.expired += 1;
+            self.expired_packets += session.packets;
+            self.expired_bytes += session.bytes;
+        }
+        expired
+    }
+
+    /// The shutdown dump: every active session, then the totals
+    pub fn summary(&self, now: Instant) -> String {
+        let mut peers: Vec<(&SocketAddr, &Session)> = self.active.iter().collect();
+        peers.sort_by_key(|(_, session)| session.first_seen);
+
+        let plural = if peers.len() == 1 { "" } else { "s" };
+        let mut out = format!("{} active session{}\n", peers.len(), plural);
+        let (mut packets, mut bytes) = (self.expired_packets, self.expired_bytes);
+        for (peer, session) in peers {
+            out.push_str(&format!(
+                "  {}: {} packets, {} bytes, last seen {:.1}s ago\n",
+                peer,
+                session.packets,
+                session.bytes,
+                now.duration_since(session.last_seen).as_secs_f64()
+            ));
+            packets += session.packets;
+            bytes += session.bytes;
+        }
+        out.push_str(&format!(
+            "Sessions: {} in total, {} expired; {} packets, {} bytes",
+            self.active.len() as u64 + self.expired,
+            self.expired,
+            packets,
+            bytes
+        ));
+        out
+    }
+}
+
+/// Expire idle sessions forever, checking at least every second
+pub async fn expire_idle(sessions: Arc<Mutex<Sessions>>, idle: Duration) {
+    let mut ticks = tokio::time::interval(idle.min(Duration::from_secs(1)));
+    loop {
+        ticks.tick().await;
+        let now = Instant::now();
+        let expired = sessions.lock().unwrap().expire(idle, now);
+        for (peer, session) in expired {
+            println!(
+                "[session] {} expired after {:.1}s idle: {} packets, {} bytes over {:.1}s",
+                peer,
+                now.duration_since(session.last_seen).as_secs_f64(),
+                session.packets,
+                session.bytes,
+                session
+                    .last_seen
+                    .duration_since(session.first_seen)
+                    .as_secs_f64()
+            );
+        }
+    }
+}
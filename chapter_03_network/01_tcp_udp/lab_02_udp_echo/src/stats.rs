@@ -0,0 +1,179 @@
+//! Traffic statistics: a periodic report and a `/stats` endpoint
+//!
+//! The receive loop records every datagram (`record`). Two readers:
+//!
+//! - the reporter prints, every `--stats-every` seconds, what happened in
+//!   that interval: packets/s, bytes/s and how many distinct senders
+//!   (address and port) were seen, next to the totals. Quiet intervals
+//!   print nothing
+//! - with `--stats-addr`, a TCP listener answers `GET /stats` with the
+//!   totals as JSON; rates there are averages since the server started
+//!
+//! ```bash
+//! curl -s localhost:8081/stats
+//! {"uptime_secs":12.5,"packets":40,"bytes":1234,"unique_peers":3,"packets_per_sec":3.2,"bytes_per_sec":98.7,"per_socket":[40]}
+//! ```
+//!
+//! `per_socket` counts packets by the socket that received them. With
+//! `--sockets N` the kernel picks that socket, so the counts show how it
+//! spreads senders; the report prints the split for each interval.
+//!
+//! UDP has no connections, so "peers" is all the server can count. Every
+//! sender stays in the set, so `unique_peers` counts everyone ever seen;
+//! the sessions in `src/session.rs` are the part that expires.
+
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+#[derive(Default)]
+struct Peers {
+    all: HashSet<SocketAddr>,
+    /// Since the last report
+    recent: HashSet<SocketAddr>,
+}
+
+pub struct Stats {
+    started: Instant,
+    packets: AtomicU64,
+    bytes: AtomicU64,
+    peers: Mutex<Peers>,
+    /// Packets received by each socket
+    per_socket: Vec<AtomicU64>,
+}
+
+impl Stats {
+    pub fn new(sockets: usize) -> Arc<Self> {
+        Arc::new(Self {
+            started: Instant::now(),
+            packets: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+            peers: Mutex::new(Peers::default()),
+            per_socket: (0..sockets).map(|_| AtomicU64::new(0)).collect(),
+        })
+    }
+
+    /// One datagram of `len` bytes from `peer`, received by `socket`
+    pub fn record(&self, socket: usize, len: usize, peer: SocketAddr) {
+        self.packets.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(len as u64, Ordering::Relaxed);
+        self.per_socket[socket].fetch_add(1, Ordering::Relaxed);
+        let mut peers = self.peers.lock().unwrap();
+        peers.all.insert(peer);
+        peers.recent.insert(peer);
+    }
+
+    fn totals(&self) -> (u64, u64) {
+        (
+            self.packets.load(Ordering::Relaxed),
+            self.bytes.load(Ordering::Relaxed),
+        )
+    }
+
+    fn per_socket(&self) -> Vec<u64> {
+        self.per_socket
+            .iter()
+            .map(|count| count.load(Ordering::Relaxed))
+            .collect()
+    }
+
+    /// Totals and averages since start, as a JSON object
+    pub fn json(&self) -> String {
+        let uptime = self.started.elapsed().as_secs_f64();
+        let (packets, bytes) = self.totals();
+        let unique_peers = self.peers.lock().unwrap().all.len();
+        let per_socket: Vec<String> = self.per_socket().iter().map(u64::to_string).collect();
+        format!(
+            "{{\"uptime_secs\":{:.1},\"packets\":{},\"bytes\":{},\"unique_peers\":{},\
+             \"packets_per_sec\":{:.1},\"bytes_per_sec\":{:.1},\"per_socket\":[{}]}}",
+            uptime,
+            packets,
+            bytes,
+            unique_peers,
+            packets as f64 / uptime.max(1e-9),
+            bytes as f64 / uptime.max(1e-9),
+            per_socket.join(",")
+        )
+    }
+}
+
+/// Print what happened in each `every` interval
+pub async fn report(stats: Arc<Stats>, every: Duration) {
+    let mut ticks = tokio::time::interval(every);
+    ticks.tick().await;
+    let mut last = (0, 0, Instant::now());
+    let mut last_per_socket = stats.per_socket();
+    loop {
+        ticks.tick().await;
+        let (packets, bytes) = stats.totals();
+        let now = Instant::now();
+        let secs = (now - last.2).as_secs_f64();
+        let (new_packets, new_bytes) = (packets - last.0, bytes - last.1);
+        last = (packets, bytes, now);
+
+        let (recent, all) = {
+            let mut peers = stats.peers.lock().unwrap();
+            (std::mem::take(&mut peers.recent).len(), peers.all.len())
+        };
+        let per_socket = stats.per_socket();
+        let split: Vec<u64> = per_socket
+            .iter()
+            .zip(&last_per_socket)
+            .map(|(now, before)| now - before)
+            .collect();
+        last_per_socket = per_socket;
+        if new_packets == 0 {
+            continue;
+        }
+        let split = if split.len() > 1 {
+            format!(", per socket {:?}", split)
+        } else {
+            String::new()
+        };
+        println!(
+            "[stats] {:.1} packets/s, {:.1} bytes/s, {} peers ({} packets, {} bytes, {} peers in total){}",
+            new_packets as f64 / secs,
+            new_bytes as f64 / secs,
+            recent,
+            packets,
+            bytes,
+            all,
+            split
+        );
+    }
+}
+
+/// GET /stats: the counters as JSON
+pub async fn serve(listener: TcpListener, stats: Arc<Stats>) {
+    loop {
+        let mut socket = match listener.accept().await {
+            Ok((socket, _)) => socket,
+            Err(err) => {
+                eprintln!("stats accept error: {}", err);
+                continue;
+            }
+        };
+        let stats = Arc::clone(&stats);
+        tokio::spawn(async move {
+            let mut buf = [0u8; 1024];
+            if let Ok(n) = socket.read(&mut buf).await {
+                let response = if buf[..n].starts_with(b"GET /stats ") {
+                    let body = stats.json();
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                } else {
+                    "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                        .to_string()
+                };
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+    }
+}
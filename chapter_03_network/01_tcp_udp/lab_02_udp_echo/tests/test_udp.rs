@@ -1,9 +1,10 @@
 //! Lab 2 Tests
 
-use std::net::UdpSocket;
-use std::process::{Child, Command};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, UdpSocket};
+use std::process::{Child, Command, Stdio};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 struct ServerGuard {
     child: Child,
@@ -125,3 +126,918 @@ fn test_03_multiple_clients() {
         }
     }
 }
+
+#[test]
+fn test_04_dual_stack_bind() {
+    // Skip on hosts without IPv6
+    if UdpSocket::bind("[::1]:0").is_err() {
+        return;
+    }
+
+    let _server = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_udp_echo"))
+            .args(["--bind", "[::]:8094"])
+            .spawn()
+            .expect("Failed to start server"),
+    };
+    thread::sleep(Duration::from_millis(300));
+
+    for (local, target) in [("[::1]:0", "[::1]:8094"), ("127.0.0.1:0", "127.0.0.1:8094")] {
+        let socket = UdpSocket::bind(local).unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        socket.send_to(b"both families", target).unwrap();
+
+        let mut buffer = [0u8; 1024];
+        let (n, _) = socket
+            .recv_from(&mut buffer)
+            .unwrap_or_else(|err| panic!("no echo via {}: {}", target, err));
+        assert_eq!(&buffer[..n], b"both families");
+    }
+}
+
+#[test]
+fn test_05_client_resolves_either_family() {
+    let _server = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_udp_echo"))
+            .args(["--bind", "127.0.0.1:8095"])
+            .spawn()
+            .expect("Failed to start server"),
+    };
+    thread::sleep(Duration::from_millis(300));
+
+    // localhost may resolve to ::1 first; the client falls through to IPv4
+    let output = Command::new(env!("CARGO_BIN_EXE_udp_client"))
+        .args(["localhost:8095", "hello", "there"])
+        .output()
+        .expect("Failed to run client");
+    assert!(output.status.success(), "client should get an echo");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "hello there\n");
+}
+
+#[test]
+fn test_06_invalid_bind_args() {
+    for args in [
+        &["--bind"][..],
+        &["--bind", "127.0.0.1:8096", "--v6only"],
+        &["--bogus"],
+        &["--stats-every", "soon"],
+        &["--stats-addr"],
+        &["--loss", "1.5"],
+        &["--dup", "often"],
+        &["--delay-ms", "50±x"],
+        &["--seed", "-1"],
+        &["--sockets", "0"],
+        &["--idle-ms", "0"],
+    ] {
+        let output = Command::new(env!("CARGO_BIN_EXE_udp_echo"))
+            .args(args)
+            .output()
+            .expect("Failed to execute program");
+        assert_eq!(
+            output.status.code(),
+            Some(2),
+            "{:?} should be rejected",
+            args
+        );
+    }
+}
+
+#[test]
+fn test_07_stats_report_and_endpoint() {
+    let mut server = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_udp_echo"))
+            .args(["--bind", "127.0.0.1:8137", "--stats-every", "1"])
+            .args(["--stats-addr", "127.0.0.1:8138"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("Failed to start server"),
+    };
+    thread::sleep(Duration::from_millis(300));
+
+    // Two senders, five datagrams of 5 bytes
+    let sockets = [
+        UdpSocket::bind("127.0.0.1:0").unwrap(),
+        UdpSocket::bind("127.0.0.1:0").unwrap(),
+    ];
+    for i in 0..5 {
+        let socket = &sockets[i % 2];
+        socket
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        socket.send_to(b"stats", "127.0.0.1:8137").unwrap();
+        let mut buffer = [0u8; 64];
+        socket.recv_from(&mut buffer).expect("no echo");
+    }
+
+    let mut stream = TcpStream::connect("127.0.0.1:8138").expect("stats endpoint");
+    stream
+        .write_all(b"GET /stats HTTP/1.1\r\nHost: x\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    assert!(response.contains("application/json"), "{}", response);
+    for field in ["\"packets\":5", "\"bytes\":25", "\"unique_peers\":2"] {
+        assert!(response.contains(field), "{} missing: {}", field, response);
+    }
+
+    // Wait for the next report, then read what the server printed
+    thread::sleep(Duration::from_millis(1500));
+    let _ = server.child.kill();
+    let mut stdout = String::new();
+    server
+        .child
+        .stdout
+        .take()
+        .unwrap()
+        .read_to_string(&mut stdout)
+        .unwrap();
+    let report = stdout
+        .lines()
+        .find(|line| line.starts_with("[stats]"))
+        .unwrap_or_else(|| panic!("no [stats] line: {}", stdout));
+    assert!(report.contains("packets/s"), "{}", report);
+    assert!(
+        report.contains("5 packets, 25 bytes, 2 peers in total"),
+        "{}",
+        report
+    );
+}
+
+/// Start `udp_echo --bind 127.0.0.1:<port> <args>`
+fn start_impaired(port: u16, args: &[&str]) -> ServerGuard {
+    let server = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_udp_echo"))
+            .args(["--bind", &format!("127.0.0.1:{}", port)])
+            .args(args)
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Failed to start server"),
+    };
+    thread::sleep(Duration::from_millis(300));
+    server
+}
+
+/// Echoes of `msg` that arrive within `wait`
+fn echoes(socket: &UdpSocket, port: u16, msg: &[u8], wait: Duration) -> usize {
+    socket.send_to(msg, ("127.0.0.1", port)).unwrap();
+    let deadline = Instant::now() + wait;
+    let mut count = 0;
+    let mut buffer = [0u8; 1024];
+    while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+        socket
+            .set_read_timeout(Some(left.max(Duration::from_millis(1))))
+            .unwrap();
+        match socket.recv_from(&mut buffer) {
+            Ok((n, _)) if &buffer[..n] == msg => count += 1,
+            Ok(_) => {}
+            Err(_) => break,
+        }
+    }
+    count
+}
+
+#[test]
+fn test_08_loss_dup_and_delay() {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let wait = Duration::from_millis(500);
+
+    let _lossy = start_impaired(8139, &["--loss", "1"]);
+    assert_eq!(
+        echoes(&socket, 8139, b"gone", wait),
+        0,
+        "--loss 1 drops all"
+    );
+
+    let _doubled = start_impaired(8140, &["--dup", "1"]);
+    assert_eq!(
+        echoes(&socket, 8140, b"twice", wait),
+        2,
+        "--dup 1 echoes twice"
+    );
+
+    let _slow = start_impaired(8141, &["--delay-ms", "200+-0"]);
+    socket.send_to(b"late", "127.0.0.1:8141").unwrap();
+    let start = Instant::now();
+    let mut buffer = [0u8; 64];
+    socket
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    socket.recv_from(&mut buffer).expect("delayed echo");
+    let delay = start.elapsed();
+    assert!(
+        delay >= Duration::from_millis(200) && delay < Duration::from_millis(800),
+        "echo after {:?}, want about 200ms",
+        delay
+    );
+}
+
+#[test]
+fn test_09_same_seed_same_losses() {
+    let run = |port: u16| -> Vec<usize> {
+        let _server = start_impaired(port, &["--loss", "0.5", "--seed", "7"]);
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        (0..16)
+            .map(|i| {
+                let msg = format!("probe {}", i);
+                echoes(&socket, port, msg.as_bytes(), Duration::from_millis(150))
+            })
+            .collect()
+    };
+
+    let first = run(8142);
+    let second = run(8143);
+    assert_eq!(first, second, "--seed should replay the same drops");
+    assert!(
+        first.contains(&0),
+        "some probes should be lost: {:?}",
+        first
+    );
+    assert!(
+        first.contains(&1),
+        "some probes should get through: {:?}",
+        first
+    );
+}
+
+#[test]
+fn test_10_reliable_delivery_over_lossy_link() {
+    // ACKs lost and delayed by the server, DATA lost by the client
+    let mut server = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_udp_echo"))
+            .args([
+                "--bind",
+                "127.0.0.1:8148",
+                "--reliable",
+                "--stats-every",
+                "0",
+            ])
+            .args(["--loss", "0.3", "--delay-ms", "10+-10", "--seed", "3"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("Failed to start server"),
+    };
+    thread::sleep(Duration::from_millis(300));
+
+    let lines: Vec<String> = (1..=30).map(|i| format!("line {}", i)).collect();
+    let mut client = Command::new(env!("CARGO_BIN_EXE_reliable_client"))
+        .args(["127.0.0.1:8148", "--window", "4", "--rto-ms", "50"])
+        .args(["--loss", "0.3", "--seed", "5"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to run client");
+    client
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(format!("{}\n", lines.join("\n")).as_bytes())
+        .unwrap();
+    let output = client.wait_with_output().unwrap();
+    let summary = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "client failed: {}", summary);
+    assert!(summary.contains("all acknowledged"), "{}", summary);
+    assert!(
+        !summary.contains("(0 retransmissions"),
+        "losses should force retransmissions: {}",
+        summary
+    );
+
+    let _ = server.child.kill();
+    let mut stdout = String::new();
+    server
+        .child
+        .stdout
+        .take()
+        .unwrap()
+        .read_to_string(&mut stdout)
+        .unwrap();
+    // Every line exactly once, in order, despite gaps and duplicates
+    let delivered: Vec<&str> = stdout
+        .lines()
+        .filter(|line| line.contains("Delivered #"))
+        .map(|line| line.split_once(": ").unwrap().1)
+        .collect();
+    assert_eq!(delivered, lines, "{}", stdout);
+    assert!(stdout.contains("held, waiting for"), "{}", stdout);
+}
+
+#[test]
+fn test_11_reliable_client_gives_up() {
+    // Nobody on 8149: every packet times out until the retries run out
+    let mut client = Command::new(env!("CARGO_BIN_EXE_reliable_client"))
+        .args(["127.0.0.1:8149", "--rto-ms", "5"])
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to run client");
+    client
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"anyone?\n")
+        .unwrap();
+    let output = client.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("no ACK for #0"), "{}", stderr);
+
+    for args in [
+        &[][..],
+        &["127.0.0.1:8149", "--window", "0"],
+        &["127.0.0.1:8149", "--rto-ms", "soon"],
+        &["127.0.0.1:8149", "--loss", "2"],
+        &["127.0.0.1:8149", "--bogus", "1"],
+    ] {
+        let output = Command::new(env!("CARGO_BIN_EXE_reliable_client"))
+            .args(args)
+            .stdin(Stdio::null())
+            .output()
+            .expect("Failed to run client");
+        assert_eq!(
+            output.status.code(),
+            Some(2),
+            "{:?} should be rejected",
+            args
+        );
+    }
+}
+
+#[test]
+fn test_12_reuseport_spreads_senders() {
+    let _server = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_udp_echo"))
+            .args(["--bind", "127.0.0.1:8150", "--sockets", "4"])
+            .args(["--stats-addr", "127.0.0.1:8151", "--stats-every", "0"])
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Failed to start server"),
+    };
+    thread::sleep(Duration::from_millis(300));
+
+    // 32 senders, each its own port: the kernel hashes them to sockets
+    for i in 0..32 {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        let msg = format!("sender {}", i);
+        socket.send_to(msg.as_bytes(), "127.0.0.1:8150").unwrap();
+        let mut buffer = [0u8; 64];
+        let (n, _) = socket.recv_from(&mut buffer).expect("no echo");
+        assert_eq!(&buffer[..n], msg.as_bytes());
+    }
+
+    let mut stream = TcpStream::connect("127.0.0.1:8151").expect("stats endpoint");
+    stream
+        .write_all(b"GET /stats HTTP/1.1\r\nHost: x\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let list = response
+        .split_once("\"per_socket\":[")
+        .and_then(|(_, rest)| rest.split_once(']'))
+        .unwrap_or_else(|| panic!("no per_socket: {}", response))
+        .0;
+    let counts: Vec<u64> = list.split(',').map(|n| n.parse().unwrap()).collect();
+    assert_eq!(counts.len(), 4, "{}", response);
+    assert_eq!(counts.iter().sum::<u64>(), 32, "{}", response);
+    assert!(
+        counts.iter().filter(|&&n| n > 0).count() >= 2,
+        "32 senders should not all hash to one socket: {:?}",
+        counts
+    );
+}
+
+#[test]
+fn test_13_idle_sessions_expire_and_summary_on_shutdown() {
+    let mut server = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_udp_echo"))
+            .args(["--bind", "127.0.0.1:8153", "--idle-ms", "300"])
+            .args(["--stats-every", "0"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("Failed to start server"),
+    };
+    thread::sleep(Duration::from_millis(300));
+
+    let early = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let late = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let mut buffer = [0u8; 64];
+    for socket in [&early, &late] {
+        socket
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+    }
+    for _ in 0..2 {
+        early.send_to(b"early", "127.0.0.1:8153").unwrap();
+        early.recv_from(&mut buffer).expect("no echo");
+    }
+    // Long enough for the early session to expire
+    thread::sleep(Duration::from_millis(800));
+    late.send_to(b"late", "127.0.0.1:8153").unwrap();
+    late.recv_from(&mut buffer).expect("no echo");
+
+    let killed = Command::new("kill")
+        .args(["-TERM", &server.child.id().to_string()])
+        .status()
+        .expect("Failed to run kill");
+    assert!(killed.success());
+    let status = server.child.wait().unwrap();
+    assert!(
+        status.success(),
+        "SIGTERM should exit cleanly: {:?}",
+        status
+    );
+    let mut stdout = String::new();
+    server
+        .child
+        .stdout
+        .take()
+        .unwrap()
+        .read_to_string(&mut stdout)
+        .unwrap();
+
+    let early = early.local_addr().unwrap().to_string();
+    let late = late.local_addr().unwrap().to_string();
+    assert!(
+        stdout.contains(&format!("[session] {} expired after", early)),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains("2 packets, 10 bytes over"),
+        "expiry should report the session's counters: {}",
+        stdout
+    );
+    let summary = stdout
+        .split_once("Shutting down: ")
+        .unwrap_or_else(|| panic!("no shutdown summary: {}", stdout))
+        .1;
+    assert!(summary.starts_with("1 active session\n"), "{}", summary);
+    assert!(
+        summary.contains(&format!("  {}: 1 packets, 4 bytes", late)),
+        "{}",
+        summary
+    );
+    assert!(!summary.contains(&early), "{}", summary);
+    assert!(
+        summary.contains("Sessions: 2 in total, 1 expired; 3 packets, 14 bytes"),
+        "{}",
+        summary
+    );
+}
+
+fn discovery(args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_discovery"));
+    command.args(args).stdout(Stdio::piped());
+    command
+}
+
+fn stdout_of(mut child: Child) -> String {
+    let mut stdout = String::new();
+    child
+        .stdout
+        .take()
+        .unwrap()
+        .read_to_string(&mut stdout)
+        .unwrap();
+    child.wait().unwrap();
+    stdout
+}
+
+#[test]
+fn test_14_multicast_discovery() {
+    let group = ["--group", "239.255.42.99:8154", "--interface", "127.0.0.1"];
+    let api = discovery(&["announce", "api", "127.0.0.1:8080", "--every-ms", "100"])
+        .args(group)
+        .spawn()
+        .expect("Failed to start announcer");
+    let mut api = ServerGuard { child: api };
+    // Leaves on its own after 8 announcements, saying BYE
+    let cache = discovery(&["announce", "cache", "127.0.0.1:6379", "--every-ms", "100"])
+        .args(["--count", "8"])
+        .args(group)
+        .spawn()
+        .expect("Failed to start announcer");
+    // Killed without a BYE: forgotten after three missed announcements
+    let mut db = ServerGuard {
+        child: discovery(&["announce", "db", "127.0.0.1:5432", "--every-ms", "100"])
+            .args(group)
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Failed to start announcer"),
+    };
+    thread::sleep(Duration::from_millis(300));
+
+    let listener = discovery(&["listen", "--for-ms", "2000"])
+        .args(group)
+        .spawn()
+        .expect("Failed to start listener");
+    thread::sleep(Duration::from_millis(300));
+    let _ = db.child.kill();
+    let listened = stdout_of(listener);
+
+    for found in [
+        "Found api at 127.0.0.1:8080",
+        "Found cache at 127.0.0.1:6379",
+        "Found db at 127.0.0.1:5432",
+    ] {
+        assert!(listened.contains(found), "{}", listened);
+    }
+    assert!(listened.contains("cache left (BYE from"), "{}", listened);
+    assert!(listened.contains("db lost: no HELLO from"), "{}", listened);
+    let summary = listened
+        .split_once("1 service:\n")
+        .unwrap_or_else(|| panic!("only api should remain: {}", listened))
+        .1;
+    assert!(
+        summary.starts_with("  api at 127.0.0.1:8080\n"),
+        "{}",
+        summary
+    );
+    assert!(
+        listened.trim_end().ends_with("Left 239.255.42.99"),
+        "{}",
+        listened
+    );
+
+    let cached = stdout_of(cache);
+    assert!(cached.starts_with("Joined 239.255.42.99 on 127.0.0.1 (ttl 1)"));
+    assert!(
+        cached.contains("DISCOVER from 127.0.0.1:8154: announced"),
+        "{}",
+        cached
+    );
+    assert!(
+        cached.contains("Said BYE after 8 announcements"),
+        "{}",
+        cached
+    );
+    assert!(
+        cached.trim_end().ends_with("Left 239.255.42.99"),
+        "{}",
+        cached
+    );
+
+    // SIGTERM: BYE and leave, like --count
+    let killed = Command::new("kill")
+        .args(["-TERM", &api.child.id().to_string()])
+        .status()
+        .expect("Failed to run kill");
+    assert!(killed.success());
+    let status = api.child.wait().unwrap();
+    assert!(
+        status.success(),
+        "SIGTERM should exit cleanly: {:?}",
+        status
+    );
+}
+
+#[test]
+fn test_15_broadcast_discovery_and_bad_args() {
+    let group = ["--group", "127.255.255.255:8155"];
+    let _db = ServerGuard {
+        child: discovery(&["announce", "db", "127.0.0.1:5432", "--every-ms", "100"])
+            .args(group)
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Failed to start announcer"),
+    };
+    thread::sleep(Duration::from_millis(300));
+    let listened = stdout_of(
+        discovery(&["listen", "--for-ms", "500"])
+            .args(group)
+            .spawn()
+            .expect("Failed to start listener"),
+    );
+    assert!(
+        listened.starts_with("Broadcasting to 127.255.255.255:8155"),
+        "{}",
+        listened
+    );
+    assert!(
+        listened.contains("Found db at 127.0.0.1:5432"),
+        "{}",
+        listened
+    );
+    assert!(!listened.contains("Joined"), "{}", listened);
+
+    for args in [
+        &[][..],
+        &["shout"],
+        &["announce", "api"],
+        &["announce", "api", "127.0.0.1:8080", "--ttl", "0"],
+        &["announce", "api", "127.0.0.1:8080", "--for-ms", "100"],
+        &["listen", "--every-ms", "100"],
+        &["listen", "--group", "[ff02::1]:4242"],
+        &["listen", "--interface", "eth0"],
+    ] {
+        let output = Command::new(env!("CARGO_BIN_EXE_discovery"))
+            .args(args)
+            .output()
+            .expect("Failed to run discovery");
+        assert_eq!(
+            output.status.code(),
+            Some(2),
+            "{:?} should be rejected",
+            args
+        );
+    }
+}
+
+#[test]
+fn test_16_datagram_size_limits() {
+    let _v4 = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_udp_echo"))
+            .args(["--bind", "127.0.0.1:8156", "--sizes", "--stats-every", "0"])
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Failed to start server"),
+    };
+    let _v6 = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_udp_echo"))
+            .args(["--bind", "[::1]:8157", "--sizes", "--stats-every", "0"])
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Failed to start server"),
+    };
+    thread::sleep(Duration::from_millis(300));
+
+    let probe = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_size_probe"))
+            .args(args)
+            .output()
+            .expect("Failed to run size_probe");
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        assert!(output.status.success(), "{:?}: {}", args, stdout);
+        stdout
+    };
+
+    // Far beyond the old 2048-byte receive buffer, nothing truncated
+    let stdout = probe(&["127.0.0.1:8156", "--from", "1024"]);
+    assert!(
+        stdout.contains("  8192 bytes: received in full"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains(" 65507 bytes: received in full"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains(" 65508 bytes: EMSGSIZE"), "{}", stdout);
+    assert!(
+        stdout.contains("Largest datagram the kernel sends: 65507 bytes"),
+        "{}",
+        stdout
+    );
+    let stdout = probe(&["127.0.0.1:8156", "--from", "60000", "--df"]);
+    assert!(
+        stdout.contains("rejected with EMSGSIZE (--df)"),
+        "{}",
+        stdout
+    );
+
+    // IPv6 allows 65527-byte payloads, more than loopback's MTU: the
+    // largest are sent in fragments and reassembled
+    let stdout = probe(&["[::1]:8157", "--from", "65000"]);
+    assert!(
+        stdout.contains("Path MTU to [::1]:8157: 65536"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains(" 65488 bytes: received in full (1 IP packet)"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains(" 65489 bytes: received in full (2 IP packets)"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains("Largest datagram the kernel sends: 65527 bytes"),
+        "{}",
+        stdout
+    );
+
+    for args in [
+        &[][..],
+        &["127.0.0.1:8156", "--from", "0"],
+        &["127.0.0.1:8156", "--from", "100", "--to", "10"],
+        &["127.0.0.1:8156", "--bogus", "1"],
+    ] {
+        let output = Command::new(env!("CARGO_BIN_EXE_size_probe"))
+            .args(args)
+            .output()
+            .expect("Failed to run size_probe");
+        assert_eq!(
+            output.status.code(),
+            Some(2),
+            "{:?} should be rejected",
+            args
+        );
+    }
+    let output = Command::new(env!("CARGO_BIN_EXE_udp_echo"))
+        .args(["--sizes", "--reliable"])
+        .output()
+        .expect("Failed to run server");
+    assert_eq!(output.status.code(), Some(2));
+}
+
+fn dtls_client(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_dtls_client"))
+        .args(args)
+        .output()
+        .expect("Failed to run dtls_client")
+}
+
+#[test]
+fn test_17_dtls_echo() {
+    let mut server = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_udp_echo"))
+            .args(["--bind", "127.0.0.1:8158", "--dtls", "--stats-every", "0"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("Failed to start server"),
+    };
+    let mut lines = BufReader::new(server.child.stdout.take().unwrap()).lines();
+    let fingerprint = lines
+        .by_ref()
+        .map(|line| line.unwrap())
+        .find_map(|line| {
+            line.strip_prefix("  Certificate SHA-256: ")
+                .map(str::to_string)
+        })
+        .expect("server should print its certificate's fingerprint");
+
+    let output = dtls_client(&[
+        "127.0.0.1:8158",
+        "hello",
+        "world",
+        "--fingerprint",
+        &fingerprint,
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    // The cookie round trip comes before the certificate
+    let cookie = stdout
+        .find("HelloVerifyRequest")
+        .expect("no cookie exchange");
+    let certificate = stdout.find("Certificate").expect("no certificate");
+    assert!(cookie < certificate, "{}", stdout);
+    assert!(stdout.contains("DTLSv1.2"), "{}", stdout);
+    assert!(stdout.contains("(matches --fingerprint)"), "{}", stdout);
+    assert!(
+        stdout.contains("\nhello\n") && stdout.contains("\nworld\n"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains("5 bytes of plaintext went out as a"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("Closed with close_notify"), "{}", stdout);
+
+    // Encrypts just the same, but refuses to trust another certificate
+    let output = dtls_client(&["127.0.0.1:8158", "hi", "--fingerprint", &"00".repeat(32)]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("does not match --fingerprint"));
+
+    // Plain UDP gets no echo from a DTLS server
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    assert_eq!(
+        echoes(&socket, 8158, b"plain", Duration::from_millis(300)),
+        0
+    );
+
+    drop(server);
+    let log: Vec<String> = lines.map(|line| line.unwrap()).collect();
+    let log = log.join("\n");
+    assert!(log.contains(": ClientHello"), "{}", log);
+    assert!(log.contains("Decrypted 5 bytes from"), "{}", log);
+    assert!(log.contains("closed (close_notify)"), "{}", log);
+    assert!(log.contains("5 bytes from 127.0.0.1"), "{}", log);
+    assert!(log.contains(": not DTLS"), "{}", log);
+
+    for args in [
+        &["127.0.0.1:8158"][..],
+        &["127.0.0.1:8158", "hi", "--fingerprint", "AB:CD"],
+        &["127.0.0.1:8158", "hi", "--bogus"],
+    ] {
+        assert_eq!(
+            dtls_client(args).status.code(),
+            Some(2),
+            "{:?} should be rejected",
+            args
+        );
+    }
+    let output = Command::new(env!("CARGO_BIN_EXE_udp_echo"))
+        .args(["--dtls", "--sizes"])
+        .output()
+        .expect("Failed to run server");
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_18_dtls_retransmits_lost_flight() {
+    // Seed 19 loses the server's first reply to the cookie-bearing
+    // ClientHello: the client's timer (1s) sends the flight again
+    let _server = start_impaired(8159, &["--dtls", "--loss", "0.3", "--seed", "19"]);
+    let output = dtls_client(&["127.0.0.1:8159", "hello"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    let client_hellos = stdout
+        .lines()
+        .filter(|line| line.starts_with('>') && line.ends_with("ClientHello"))
+        .count();
+    assert_eq!(client_hellos, 3, "{}", stdout);
+    assert!(stdout.contains("\nhello\n"), "{}", stdout);
+}
+
+/// A bare-bones DTLS 1.2 ClientHello in one record, enough for the
+/// server's cookie check
+fn raw_client_hello(record_seq: u8, message_seq: u8, cookie: &[u8]) -> Vec<u8> {
+    let mut body = vec![0xfe, 0xfd];
+    body.extend_from_slice(&[7; 32]);
+    body.push(0);
+    body.push(cookie.len() as u8);
+    body.extend_from_slice(cookie);
+    // One cipher suite (ECDHE-ECDSA-AES128-GCM-SHA256), no compression
+    body.extend_from_slice(&[0, 2, 0xc0, 0x2b, 1, 0]);
+    let len = (body.len() as u32).to_be_bytes();
+    let mut message = vec![1, len[1], len[2], len[3], 0, message_seq, 0, 0, 0];
+    message.extend_from_slice(&len[1..]);
+    message.extend_from_slice(&body);
+    let mut record = vec![22, 0xfe, 0xfd, 0, 0, 0, 0, 0, 0, 0, record_seq];
+    record.extend_from_slice(&(message.len() as u16).to_be_bytes());
+    record.extend_from_slice(&message);
+    record
+}
+
+fn reply(socket: &UdpSocket, wait: Duration) -> Option<Vec<u8>> {
+    socket.set_read_timeout(Some(wait)).unwrap();
+    let mut buffer = [0u8; 2048];
+    let (n, _) = socket.recv_from(&mut buffer).ok()?;
+    Some(buffer[..n].to_vec())
+}
+
+#[test]
+fn test_19_dtls_cookie_before_any_session() {
+    let mut server = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_udp_echo"))
+            .args(["--bind", "127.0.0.1:8160", "--dtls", "--stats-every", "0"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("Failed to start server"),
+    };
+    let mut lines = BufReader::new(server.child.stdout.take().unwrap()).lines();
+    lines
+        .by_ref()
+        .map(|line| line.unwrap())
+        .find(|line| line.starts_with("  Certificate SHA-256: "))
+        .expect("server should start");
+
+    // No cookie: a HelloVerifyRequest with the ClientHello's record number
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket
+        .send_to(&raw_client_hello(0, 0, &[]), "127.0.0.1:8160")
+        .unwrap();
+    let verify = reply(&socket, Duration::from_secs(2)).expect("no HelloVerifyRequest");
+    assert_eq!((verify[0], verify[13]), (22, 3), "{:?}", verify);
+    assert_eq!(&verify[5..11], &[0; 6]);
+    let cookie = verify[28..28 + verify[27] as usize].to_vec();
+    assert!(!cookie.is_empty());
+
+    // The cookie is only good from the address it was sent to
+    let spoofed = UdpSocket::bind("127.0.0.1:0").unwrap();
+    spoofed
+        .send_to(&raw_client_hello(1, 1, &cookie), "127.0.0.1:8160")
+        .unwrap();
+    assert_eq!(reply(&spoofed, Duration::from_millis(300)), None);
+    let mut wrong = cookie.clone();
+    wrong[0] ^= 1;
+    socket
+        .send_to(&raw_client_hello(1, 1, &wrong), "127.0.0.1:8160")
+        .unwrap();
+    assert_eq!(reply(&socket, Duration::from_millis(300)), None);
+
+    // The right one reaches OpenSSL, which answers: with an alert, as this
+    // ClientHello lacks the extensions a real handshake needs
+    socket
+        .send_to(&raw_client_hello(1, 1, &cookie), "127.0.0.1:8160")
+        .unwrap();
+    let answer = reply(&socket, Duration::from_secs(2)).expect("no session");
+    assert_eq!(answer[0], 21, "{:?}", answer);
+
+    drop(server);
+    let log: Vec<String> = lines.map(|line| line.unwrap()).collect();
+    let log = log.join("\n");
+    assert!(log.contains("sent a cookie, no session yet"), "{}", log);
+    assert_eq!(log.matches("wrong cookie: ignored").count(), 2, "{}", log);
+}
@@ -0,0 +1,265 @@
+//! HTTP authentication for `GET /admin`, without a framework
+//!
+//! Basic (RFC 7617): the password travels base64-encoded, which anyone on
+//! the path can decode - only use it over `--tls`.
+//!
+//! ```text
+//! GET /admin                                  -> 401
+//!                                                WWW-Authenticate: Basic realm="raw_http"
+//! GET /admin
+//! Authorization: Basic YWRtaW46czNjcmV0       -> 200 (base64 of "admin:s3cret")
+//! ```
+//!
+//! Digest (RFC 7616, MD5, qop=auth): the password never crosses the wire.
+//! The server hands out a nonce, the client proves it knows the password by
+//! hashing it together with the nonce and the request:
+//!
+//! ```text
+//! GET /admin                                  -> 401
+//!     WWW-Authenticate: Digest realm="raw_http", qop="auth", algorithm=MD5, nonce="..."
+//! GET /admin
+//! Authorization: Digest username="admin", realm="raw_http", nonce="...", uri="/admin",
+//!                qop=auth, nc=00000001, cnonce="...", response="..."
+//!
+//! HA1      = MD5(username:realm:password)
+//! HA2      = MD5(method:uri)
+//! response = MD5(HA1:nonce:nc:cnonce:qop:HA2)
+//! ```
+//!
+//! Nonces are stateless: a timestamp plus an MD5 over it and a per-process
+//! secret, so the server can tell its own nonces apart from made-up ones
+//! without storing them. Old nonces get `stale=true` and the client retries
+//! without asking the user again. Replays within the nonce lifetime are not
+//! caught (that needs a store of seen `nc` values per nonce).
+
+use md5::{Digest, Md5};
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Protection space shown by the browser's login prompt
+pub const REALM: &str = "raw_http";
+
+/// How long a Digest nonce is accepted
+const NONCE_TTL_SECS: u64 = 300;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Scheme {
+    Basic,
+    Digest,
+}
+
+/// The credentials `/admin` accepts
+pub struct Admin<'a> {
+    pub user: &'a str,
+    pub password: &'a str,
+    pub scheme: Scheme,
+}
+
+/// Why a request was refused, and the `WWW-Authenticate` value to send back
+pub struct Denied {
+    pub reason: &'static str,
+    pub challenge: String,
+}
+
+impl Admin<'_> {
+    /// Check the `Authorization` header of `method target`
+    pub fn check(
+        &self,
+        method: &str,
+        target: &str,
+        authorization: Option<&str>,
+    ) -> Result<(), Denied> {
+        let deny = |reason, stale| Denied {
+            reason,
+            challenge: self.challenge(stale),
+        };
+        let Some(value) = authorization else {
+            return Err(deny("no credentials", false));
+        };
+        let (scheme, credentials) = value.trim().split_once(' ').unwrap_or((value, ""));
+
+        match self.scheme {
+            Scheme::Basic if scheme.eq_ignore_ascii_case("Basic") => {
+                let (user, password) = base64_decode(credentials.trim())
+                    .and_then(|raw| String::from_utf8(raw).ok())
+                    .and_then(|pair| {
+                        pair.split_once(':')
+                            .map(|(u, p)| (u.to_string(), p.to_string()))
+                    })
+                    .ok_or_else(|| deny("malformed Basic credentials", false))?;
+                // `&`, not `&&`: always compare both, so timing does not tell
+                // whether the user name alone was right
+                if secret_eq(&user, self.user) & secret_eq(&password, self.password) {
+                    Ok(())
+                } else {
+                    Err(deny("wrong user or password", false))
+                }
+            }
+            Scheme::Digest if scheme.eq_ignore_ascii_case("Digest") => self
+                .check_digest(method, target, &digest_params(credentials))
+                .map_err(|(reason, stale)| deny(reason, stale)),
+            _ => Err(deny("wrong authentication scheme", false)),
+        }
+    }
+
+    fn check_digest(
+        &self,
+        method: &str,
+        target: &str,
+        params: &HashMap<String, String>,
+    ) -> Result<(), (&'static str, bool)> {
+        let get = |key: &str| params.get(key).map(String::as_str);
+        let (Some(user), Some(nonce), Some(uri), Some(response)) =
+            (get("username"), get("nonce"), get("uri"), get("response"))
+        else {
+            return Err(("incomplete Digest credentials", false));
+        };
+        if get("realm") != Some(REALM) || get("qop") != Some("auth") {
+            return Err(("wrong realm or qop", false));
+        }
+        // The hash covers `uri`: it must be the request we are answering,
+        // or a response captured for one path would open another
+        if uri != target {
+            return Err(("Digest uri does not match the request", false));
+        }
+        let (Some(nc), Some(cnonce)) = (get("nc"), get("cnonce")) else {
+            return Err(("qop=auth needs nc and cnonce", false));
+        };
+        match nonce_age(nonce) {
+            None => return Err(("unknown nonce", false)),
+            Some(age) if age > NONCE_TTL_SECS => return Err(("stale nonce", true)),
+            Some(_) => {}
+        }
+
+        let ha1 = md5_hex(&format!("{}:{}:{}", self.user, REALM, self.password));
+        let ha2 = md5_hex(&format!("{}:{}", method, uri));
+        let expected = md5_hex(&format!("{}:{}:{}:{}:auth:{}", ha1, nonce, nc, cnonce, ha2));
+        if secret_eq(user, self.user) & secret_eq(response, &expected) {
+            Ok(())
+        } else {
+            Err(("wrong user or password", false))
+        }
+    }
+
+    /// `WWW-Authenticate` value for a 401
+    fn challenge(&self, stale: bool) -> String {
+        match self.scheme {
+            Scheme::Basic => format!("Basic realm=\"{}\", charset=\"UTF-8\"", REALM),
+            Scheme::Digest => format!(
+                "Digest realm=\"{}\", qop=\"auth\", algorithm=MD5, nonce=\"{}\"{}",
+                REALM,
+                new_nonce(now_secs()),
+                if stale { ", stale=true" } else { "" }
+            ),
+        }
+    }
+}
+
+/// Compare two secrets in time that depends only on their hashes.
+///
+/// `==` stops at the first differing byte, so an attacker measuring response
+/// times could guess a password one byte at a time. Hashing first makes both
+/// sides 16 bytes, so not even the length leaks; then every byte is looked at.
+fn secret_eq(given: &str, expected: &str) -> bool {
+    let (a, b) = (Md5::digest(given), Md5::digest(expected));
+    a.iter()
+        .zip(b.iter())
+        .fold(0u8, |diff, (x, y)| diff | (x ^ y))
+        == 0
+}
+
+fn md5_hex(input: &str) -> String {
+    format!("{:x}", Md5::digest(input))
+}
+
+/// Standard base64 (with or without `=` padding)
+fn base64_decode(input: &str) -> Option<Vec<u8>> {
+    fn value(c: u8) -> Option<u32> {
+        let v = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        Some(v as u32)
+    }
+
+    let input = input.trim_end_matches('=');
+    if input.len() % 4 == 1 {
+        return None;
+    }
+    // Every character is 6 bits; emit a byte whenever 8 have piled up
+    let mut out = Vec::with_capacity(input.len() * 3 / 4);
+    let (mut acc, mut bits) = (0u32, 0);
+    for c in input.bytes() {
+        acc = (acc << 6) | value(c)?;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+    Some(out)
+}
+
+/// `key=value, key="quoted, value"` pairs of a Digest header, keys lowercased
+fn digest_params(input: &str) -> HashMap<String, String> {
+    let mut params = HashMap::new();
+    let mut rest = input;
+    loop {
+        rest = rest.trim_start_matches([' ', ',']);
+        let Some((key, after)) = rest.split_once('=') else {
+            break;
+        };
+        let (value, next) = match after.strip_prefix('"') {
+            Some(quoted) => match quoted.find('"') {
+                Some(end) => (&quoted[..end], &quoted[end + 1..]),
+                None => break,
+            },
+            None => {
+                let end = after.find(',').unwrap_or(after.len());
+                (after[..end].trim(), &after[end..])
+            }
+        };
+        params.insert(key.trim().to_ascii_lowercase(), value.to_string());
+        rest = next;
+    }
+    params
+}
+
+/// Random per process: nonces from a previous run are unknown
+fn secret() -> u64 {
+    static SECRET: OnceLock<u64> = OnceLock::new();
+    *SECRET.get_or_init(|| RandomState::new().hash_one(SystemTime::now()))
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// `<timestamp hex>-<MD5(timestamp:secret)>`
+fn new_nonce(timestamp: u64) -> String {
+    format!(
+        "{:x}-{}",
+        timestamp,
+        md5_hex(&format!("{}:{}", timestamp, secret()))
+    )
+}
+
+/// Seconds since we issued `nonce`; None if we never did
+fn nonce_age(nonce: &str) -> Option<u64> {
+    let timestamp = u64::from_str_radix(nonce.split_once('-')?.0, 16).ok()?;
+    if !secret_eq(nonce, &new_nonce(timestamp)) {
+        return None;
+    }
+    Some(now_secs().saturating_sub(timestamp))
+}
@@ -43,10 +43,188 @@
 //! - [ ] Routes to different handlers based on path
 //! - [ ] Returns proper HTTP response format
 //! - [ ] Handles 404 for unknown paths
+//!
+//! ## Extension: Response Compression
+//! - `Accept-Encoding: gzip` + body >= `--gzip-min-bytes` -> gzip body, `Content-Encoding: gzip`
+//! - `Vary: Accept-Encoding` whenever compression is enabled
+//! - `--no-gzip` turns it off for benchmark comparisons (try `GET /large`)
+//! ```bash
+//! curl -s --compressed -v http://localhost:8080/large > /dev/null
+//! cargo run -- 8080 --no-gzip
+//! ```
+//!
+//! ## Extension: HTTPS
+//! - `--tls` wraps every accepted stream in rustls before the HTTP code sees it
+//! - `--cert`/`--key` PEM files; a self-signed pair is generated if missing
+//! - The log shows handshake time per connection: compare with plain HTTP
+//! ```bash
+//! cargo run -- 8443 --tls
+//! curl -k https://localhost:8443/
+//! curl --cacert cert.pem https://localhost:8443/
+//! curl -k -w "connect=%{time_connect} tls=%{time_appconnect}\n" -o /dev/null -s https://localhost:8443/
+//! ```
+//!
+//! ## Extension: Access Log
+//! - One `tracing` line per request: peer, method, path, status, bytes, duration_us
+//! - Verbosity via `RUST_LOG`; raw request bytes only at `trace`
+//! ```bash
+//! RUST_LOG=access=info,raw_http=warn cargo run   # access log only
+//! RUST_LOG=trace cargo run                       # everything, including raw bytes
+//! ```
+//!
+//! ## Extension: File Upload
+//! - `POST /upload` with `multipart/form-data` writes each file part to `--upload-dir`
+//! - The body is parsed as it streams in: file bytes go to disk chunk by chunk
+//! - Responds with a JSON summary of stored files and plain form fields
+//! - No Content-Length -> 411, larger than `--max-upload-bytes` -> 413
+//! ```bash
+//! curl -F "file=@Cargo.toml" -F "note=hi" http://localhost:8080/upload
+//! cargo run -- 8080 --upload-dir /tmp/uploads --max-upload-bytes 1048576
+//! ```
+//!
+//! ## Extension: Authentication
+//! - `--admin-password` adds `GET /admin`, protected by HTTP auth (see `src/auth.rs`)
+//! - `--auth basic` (default): `Authorization: Basic base64(user:password)`,
+//!   compared in constant time
+//! - `--auth digest`: challenge/response with a server nonce, the password
+//!   never crosses the wire
+//! - Wrong or missing credentials -> 401 + `WWW-Authenticate` with the challenge
+//! ```bash
+//! cargo run -- 8080 --admin-password s3cret
+//! curl -i http://localhost:8080/admin                 # 401, WWW-Authenticate: Basic realm="raw_http"
+//! curl -u admin:s3cret http://localhost:8080/admin    # Welcome, admin!
+//! cargo run -- 8080 --admin-password s3cret --auth digest
+//! curl --digest -u admin:s3cret http://localhost:8080/admin
+//! ```
+//!
+//! ## Extension: PROXY protocol
+//! - `--accept-proxy`: behind a TCP load balancer (HAProxy `send-proxy`), every
+//!   connection starts with a PROXY v1/v2 header naming the real client
+//!   (see `chapter_03_network/shared/proxy_protocol.rs`)
+//! - The header is read before TLS, and its source is the `peer` in the logs
+//! - A connection without a valid header is closed
+//! ```bash
+//! cargo run -- 8080 --accept-proxy
+//! printf 'PROXY TCP4 203.0.113.7 127.0.0.1 40000 8080\r\nGET / HTTP/1.1\r\n\r\n' | nc 127.0.0.1 8080
+//! # access log: peer=203.0.113.7:40000
+//! ```
+//!
+//! ## Extension: Strict Parsing
+//! - `--strict` answers 400 to request heads that two parsers could frame
+//!   differently (see `chapter_03_network/shared/strict.rs`, shared with
+//!   the reverse proxy): Content-Length with Transfer-Encoding,
+//!   conflicting or odd Content-Length, obfuscated Transfer-Encoding,
+//!   obs-folded headers, bare LF or CR line endings
+//! - The reason is in the body and in the log; lenient parsing stays the
+//!   default so the two can be compared
+//! ```bash
+//! cargo run -- 8080 --strict
+//! printf 'POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 4\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n' | nc 127.0.0.1 8080
+//! # 400 Bad Request: both Content-Length and Transfer-Encoding
+//! ```
+
+mod auth;
+mod multipart;
+#[allow(dead_code)] // encode is for the reverse proxy, which sends headers
+#[path = "../../../shared/proxy_protocol.rs"]
+mod proxy_protocol;
+#[path = "../../../shared/strict.rs"]
+mod strict;
+mod tls;
+
+use clap::Parser;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use multipart::{Event, MultipartParser};
+use std::io::{IsTerminal, Write};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::fs::File;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tracing::{debug, error, info, trace, warn};
+use tracing_subscriber::EnvFilter;
+
+#[derive(Parser, Debug)]
+#[command(name = "raw_http")]
+#[command(about = "HTTP/1.1 server built on raw TCP")]
+struct Config {
+    /// Port to listen on
+    #[arg(default_value = "8080")]
+    port: u16,
+
+    /// Disable gzip response compression (for benchmark comparisons)
+    #[arg(long)]
+    no_gzip: bool,
+
+    /// Only compress bodies of at least this many bytes
+    #[arg(long, default_value = "1024")]
+    gzip_min_bytes: usize,
+
+    /// Serve HTTPS instead of plain HTTP
+    #[arg(long)]
+    tls: bool,
+
+    /// PEM certificate for --tls (self-signed one is generated if missing)
+    #[arg(long, default_value = "cert.pem")]
+    cert: PathBuf,
+
+    /// PEM private key for --tls (generated together with --cert)
+    #[arg(long, default_value = "key.pem")]
+    key: PathBuf,
+
+    /// Where POST /upload stores files (default: <tmp>/raw_http_uploads)
+    #[arg(long)]
+    upload_dir: Option<PathBuf>,
+
+    /// Reject upload bodies larger than this with 413
+    #[arg(long, default_value = "10485760")]
+    max_upload_bytes: usize,
+
+    /// Password for GET /admin (without one the route does not exist)
+    #[arg(long)]
+    admin_password: Option<String>,
+
+    /// User name for GET /admin
+    #[arg(long, default_value = "admin")]
+    admin_user: String,
+
+    /// How GET /admin asks for credentials
+    #[arg(long, value_enum, default_value = "basic")]
+    auth: auth::Scheme,
+
+    /// Expect a PROXY protocol (v1 or v2) header on every connection
+    #[arg(long)]
+    accept_proxy: bool,
+
+    /// Reject ambiguous request heads (smuggling defense) with 400
+    #[arg(long)]
+    strict: bool,
+}
+
+impl Config {
+    fn upload_dir(&self) -> PathBuf {
+        self.upload_dir
+            .clone()
+            .unwrap_or_else(|| std::env::temp_dir().join("raw_http_uploads"))
+    }
 
-use std::time::{SystemTime, UNIX_EPOCH};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::{TcpListener, TcpStream};
+    fn admin(&self) -> Option<auth::Admin<'_>> {
+        Some(auth::Admin {
+            user: &self.admin_user,
+            password: self.admin_password.as_deref()?,
+            scheme: self.auth,
+        })
+    }
+}
+
+/// Request heads larger than this are rejected
+const MAX_HEAD_BYTES: usize = 16 * 1024;
+
+/// Request bytes read and thrown away after an early upload error
+const MAX_DRAIN_BYTES: usize = 1024 * 1024;
 
 // ============================================================
 // TODO: Implement the raw HTTP server
@@ -57,9 +235,20 @@ struct HttpRequest {
     method: String,
     path: String,
     headers: Vec<(String, String)>,
+    #[allow(dead_code)]
     body: String,
 }
 
+impl HttpRequest {
+    /// Case-insensitive header lookup
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
 /// Parse raw HTTP request bytes into HttpRequest
 fn parse_request(raw: &str) -> Option<HttpRequest> {
     // TODO: Implement
@@ -92,8 +281,13 @@ fn parse_request(raw: &str) -> Option<HttpRequest> {
     })
 }
 
-/// Build HTTP response string
-fn build_response(status_code: u16, content_type: &str, body: &str) -> String {
+/// Build HTTP response bytes
+fn build_response(
+    status_code: u16,
+    content_type: &str,
+    extra_headers: &[(&str, String)],
+    body: &[u8],
+) -> Vec<u8> {
     // TODO: Implement
     // Format:
     // HTTP/1.1 {status_code} {reason}\r\n
@@ -104,24 +298,385 @@ fn build_response(status_code: u16, content_type: &str, body: &str) -> String {
     // {body}
     let reason = match status_code {
         200 => "OK",
+        201 => "Created",
         400 => "Bad Request",
+        401 => "Unauthorized",
         404 => "Not Found",
+        411 => "Length Required",
+        413 => "Content Too Large",
+        415 => "Unsupported Media Type",
         500 => "Internal Server Error",
         _ => "OK",
     };
 
-    format!(
-        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
         status_code,
         reason,
         content_type,
-        body.as_bytes().len(),
-        body
+        body.len(),
+    );
+    for (key, value) in extra_headers {
+        head.push_str(&format!("{}: {}\r\n", key, value));
+    }
+    head.push_str("\r\n");
+
+    let mut response = head.into_bytes();
+    response.extend_from_slice(body);
+    response
+}
+
+/// Does the Accept-Encoding header allow gzip? (`gzip;q=0` means no)
+fn accepts_gzip(accept_encoding: &str) -> bool {
+    accept_encoding.split(',').any(|entry| {
+        let mut params = entry.split(';').map(str::trim);
+        let coding = params.next().unwrap_or("");
+        let rejected = params.any(|p| {
+            p.strip_prefix("q=")
+                .and_then(|q| q.parse::<f32>().ok())
+                .is_some_and(|q| q == 0.0)
+        });
+        (coding.eq_ignore_ascii_case("gzip") || coding == "*") && !rejected
+    })
+}
+
+/// Gzip the body if enabled, accepted by the client and large enough.
+///
+/// Returns the body to send plus the headers describing it. `Vary` is sent
+/// whenever compression is on, so caches keep gzip and identity copies apart.
+fn compress_body(
+    request: &HttpRequest,
+    body: Vec<u8>,
+    config: &Config,
+) -> (Vec<u8>, Vec<(&'static str, String)>) {
+    if config.no_gzip {
+        return (body, Vec::new());
+    }
+
+    let mut headers = vec![("Vary", "Accept-Encoding".to_string())];
+    let wanted = request.header("Accept-Encoding").is_some_and(accepts_gzip);
+    if !wanted || body.len() < config.gzip_min_bytes {
+        return (body, headers);
+    }
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    match encoder.write_all(&body).and_then(|_| encoder.finish()) {
+        Ok(compressed) => {
+            debug!(from = body.len(), to = compressed.len(), "gzip");
+            headers.push(("Content-Encoding", "gzip".to_string()));
+            (compressed, headers)
+        }
+        Err(_) => (body, headers),
+    }
+}
+
+/// Read until the blank line that ends the headers (CRLF or bare LF).
+///
+/// Returns (head, rest): `rest` is whatever part of the body arrived in the
+/// same reads. Without a blank line, everything read before EOF is the head.
+async fn read_head<S>(stream: &mut S) -> Option<(Vec<u8>, Vec<u8>)>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+
+    loop {
+        if let Some(end) = strict::head_end(&buf) {
+            let rest = buf.split_off(end);
+            return Some((buf, rest));
+        }
+        if buf.len() > MAX_HEAD_BYTES {
+            return Some((buf, Vec::new()));
+        }
+        match stream.read(&mut chunk).await {
+            Ok(0) | Err(_) if buf.is_empty() => return None,
+            Ok(0) | Err(_) => return Some((buf, Vec::new())),
+            Ok(n) => buf.extend_from_slice(&chunk[..n]),
+        }
+    }
+}
+
+/// Discard what the client is still sending after an early error response.
+///
+/// Closing a socket with unread data makes the kernel send RST, and the
+/// client may lose our response (e.g. a 413) before it reads it.
+async fn drain<S>(stream: &mut S)
+where
+    S: AsyncRead + Unpin,
+{
+    let mut buf = [0u8; 8192];
+    let mut total = 0;
+    while total < MAX_DRAIN_BYTES {
+        match tokio::time::timeout(Duration::from_secs(1), stream.read(&mut buf)).await {
+            Ok(Ok(n)) if n > 0 => total += n,
+            _ => break,
+        }
+    }
+}
+
+/// Quote a string as a JSON string literal
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Keep only the last path component and a safe set of characters, so
+/// `../../etc/passwd` can't escape the upload directory
+fn sanitize_filename(name: &str) -> String {
+    let base = name.rsplit(['/', '\\']).next().unwrap_or("");
+    let clean: String = base
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    match clean.trim_start_matches('.') {
+        "" => "upload".to_string(),
+        clean => clean.to_string(),
+    }
+}
+
+struct StoredFile {
+    field: String,
+    filename: String,
+    content_type: Option<String>,
+    path: PathBuf,
+    size: u64,
+}
+
+/// The part being received: a file (its StoredFile is the last in the list)
+/// or a plain form field
+enum CurrentPart {
+    File(File),
+    Field(String, Vec<u8>),
+}
+
+/// JSON body listing stored files and form fields
+fn upload_summary(files: &[StoredFile], fields: &[(String, String)]) -> String {
+    let files: Vec<String> = files
+        .iter()
+        .map(|f| {
+            format!(
+                "{{\"field\":{},\"filename\":{},\"content_type\":{},\"path\":{},\"size\":{}}}",
+                json_string(&f.field),
+                json_string(&f.filename),
+                f.content_type
+                    .as_deref()
+                    .map_or("null".to_string(), json_string),
+                json_string(&f.path.to_string_lossy()),
+                f.size
+            )
+        })
+        .collect();
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|(name, value)| format!("{}:{}", json_string(name), json_string(value)))
+        .collect();
+
+    format!(
+        "{{\"files\":[{}],\"fields\":{{{}}}}}",
+        files.join(","),
+        fields.join(",")
     )
 }
 
-/// Handle incoming connection
-async fn handle_connection(mut stream: TcpStream) {
+type UploadResponse = (u16, &'static str, Vec<u8>);
+
+fn upload_error(status: u16, message: &str) -> UploadResponse {
+    let body = format!("{{\"error\":{}}}", json_string(message));
+    (status, "application/json", body.into_bytes())
+}
+
+/// `POST /upload`: stream a multipart body into files under the upload dir
+async fn handle_upload<S>(
+    stream: &mut S,
+    request: &HttpRequest,
+    rest: Vec<u8>,
+    config: &Config,
+) -> UploadResponse
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let boundary = match request.header("Content-Type").and_then(multipart::boundary) {
+        Some(b) => b,
+        None => return upload_error(415, "expected multipart/form-data with a boundary"),
+    };
+    let length: usize = match request.header("Content-Length").map(str::parse) {
+        Some(Ok(n)) => n,
+        Some(Err(_)) => return upload_error(400, "invalid Content-Length"),
+        None => return upload_error(411, "Content-Length required"),
+    };
+    if length > config.max_upload_bytes {
+        return upload_error(413, "upload too large");
+    }
+
+    // curl waits for this before sending large bodies
+    let expects_continue = request
+        .header("Expect")
+        .is_some_and(|e| e.eq_ignore_ascii_case("100-continue"));
+    if expects_continue && rest.is_empty() {
+        let _ = stream.write_all(b"HTTP/1.1 100 Continue\r\n\r\n").await;
+    }
+
+    let dir = config.upload_dir();
+    if let Err(e) = tokio::fs::create_dir_all(&dir).await {
+        error!(dir = %dir.display(), error = %e, "cannot create upload dir");
+        return upload_error(500, "cannot create upload directory");
+    }
+
+    let mut parser = MultipartParser::new(&boundary);
+    let mut files: Vec<StoredFile> = Vec::new();
+    let mut fields: Vec<(String, String)> = Vec::new();
+    let mut current: Option<CurrentPart> = None;
+
+    let mut received = rest.len().min(length);
+    let mut chunk = rest;
+    chunk.truncate(length);
+    let mut buf = vec![0u8; 16 * 1024];
+
+    let result: Result<(), (u16, String)> = async {
+        loop {
+            let events = parser.feed(&chunk).map_err(|e| (400, e))?;
+            for event in events {
+                match event {
+                    Event::Part(info) => {
+                        current = Some(match info.filename {
+                            Some(filename) => {
+                                let stamp = SystemTime::now()
+                                    .duration_since(UNIX_EPOCH)
+                                    .unwrap_or_default()
+                                    .as_nanos();
+                                let filename = sanitize_filename(&filename);
+                                let path = dir.join(format!("{}-{}", stamp, filename));
+                                let file = File::create(&path)
+                                    .await
+                                    .map_err(|e| (500, format!("cannot create file: {}", e)))?;
+                                files.push(StoredFile {
+                                    field: info.name,
+                                    filename,
+                                    content_type: info.content_type,
+                                    path,
+                                    size: 0,
+                                });
+                                CurrentPart::File(file)
+                            }
+                            None => CurrentPart::Field(info.name, Vec::new()),
+                        });
+                    }
+                    Event::Data(data) => match current.as_mut() {
+                        Some(CurrentPart::File(file)) => {
+                            file.write_all(&data)
+                                .await
+                                .map_err(|e| (500, format!("write failed: {}", e)))?;
+                            if let Some(stored) = files.last_mut() {
+                                stored.size += data.len() as u64;
+                            }
+                        }
+                        Some(CurrentPart::Field(_, value)) => value.extend_from_slice(&data),
+                        None => {}
+                    },
+                    Event::PartEnd => match current.take() {
+                        Some(CurrentPart::File(mut file)) => {
+                            file.flush()
+                                .await
+                                .map_err(|e| (500, format!("write failed: {}", e)))?;
+                            if let Some(stored) = files.last() {
+                                debug!(path = %stored.path.display(), size = stored.size, "stored upload");
+                            }
+                        }
+                        Some(CurrentPart::Field(name, value)) => {
+                            fields.push((name, String::from_utf8_lossy(&value).to_string()));
+                        }
+                        None => {}
+                    },
+                }
+            }
+
+            if received >= length {
+                break;
+            }
+            let want = buf.len().min(length - received);
+            let n = stream
+                .read(&mut buf[..want])
+                .await
+                .map_err(|e| (400, format!("read failed: {}", e)))?;
+            if n == 0 {
+                break;
+            }
+            received += n;
+            chunk = buf[..n].to_vec();
+        }
+
+        if parser.is_done() {
+            Ok(())
+        } else {
+            Err((400, "truncated multipart body".to_string()))
+        }
+    }
+    .await;
+
+    if let Err((status, message)) = result {
+        // Don't leave half-written files behind
+        for file in &files {
+            let _ = tokio::fs::remove_file(&file.path).await;
+        }
+        return upload_error(status, &message);
+    }
+
+    (
+        201,
+        "application/json",
+        upload_summary(&files, &fields).into_bytes(),
+    )
+}
+
+/// One line per request, like nginx's access.log.
+///
+/// Logged under the `access` target so it can be filtered on its own:
+/// `RUST_LOG=access=info,raw_http=warn`
+fn log_access(
+    peer: SocketAddr,
+    method: &str,
+    path: &str,
+    status: u16,
+    bytes: usize,
+    start: Instant,
+) {
+    info!(
+        target: "access",
+        %peer,
+        %method,
+        %path,
+        status,
+        bytes,
+        duration_us = start.elapsed().as_micros() as u64,
+        "request"
+    );
+}
+
+/// Handle incoming connection (plain TCP or TLS - the HTTP part is identical)
+async fn handle_connection<S>(mut stream: S, peer: SocketAddr, config: Arc<Config>)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
     // TODO: Implement
     // 1. Read request into buffer
     // 2. Parse request
@@ -131,27 +686,58 @@ async fn handle_connection(mut stream: TcpStream) {
     //    - GET /time -> current time
     //    - * -> 404 Not Found
     // 4. Send response
-    let mut buffer = [0u8; 4096];
-    // print!("buffer before read: {:#?}", buffer);
-    let bytes_read = match stream.read(&mut buffer).await {
-        Ok(0) => return,
-        Ok(n) => n,
-        Err(_) => return,
+    let start = Instant::now();
+    let (head, rest) = match read_head(&mut stream).await {
+        Some(pair) => pair,
+        None => return,
     };
-    // print!("buffer after read: {:#?}", buffer);
-    let raw_request = String::from_utf8_lossy(&buffer[..bytes_read]);
-    println!("raw request:\n {:#?}", raw_request);
+    let raw_request = String::from_utf8_lossy(&head);
+    trace!(%peer, raw = ?raw_request, "raw request");
+
+    if config.strict {
+        if let Err(reason) = strict::check(&head) {
+            warn!(%peer, reason, "rejected ambiguous request");
+            let body = format!("400 Bad Request: {}", reason);
+            let response = build_response(400, "text/plain", &[], body.as_bytes());
+            let _ = stream.write_all(&response).await;
+            let _ = stream.shutdown().await;
+            // Whatever was smuggled after the head is never parsed
+            drain(&mut stream).await;
+            log_access(peer, "-", "-", 400, response.len(), start);
+            return;
+        }
+    }
 
     let request = match parse_request(&raw_request) {
         Some(req) => req,
         None => {
-            let response = build_response(400, "text/plain", "400 Bad Request");
-            let _ = stream.write_all(response.as_bytes()).await;
-            println!("bad request");
+            let response = build_response(400, "text/plain", &[], b"400 Bad Request");
+            let _ = stream.write_all(&response).await;
+            log_access(peer, "-", "-", 400, response.len(), start);
             return;
         }
     };
-    println!("method={} path={}", request.method, request.path);
+    if request.method == "POST" && request.path == "/upload" {
+        let (status_code, content_type, body) =
+            handle_upload(&mut stream, &request, rest, &config).await;
+        let response = build_response(status_code, content_type, &[], &body);
+        if let Err(e) = stream.write_all(&response).await {
+            warn!(%peer, error = %e, "write failed");
+        }
+        let _ = stream.shutdown().await;
+        drain(&mut stream).await;
+        log_access(
+            peer,
+            &request.method,
+            &request.path,
+            status_code,
+            response.len(),
+            start,
+        );
+        return;
+    }
+
+    let mut auth_headers = Vec::new();
     let (status_code, body) = if request.method == "GET" && request.path == "/" {
         (200, "Hello, World!".to_string())
     } else if request.method == "GET" && request.path.starts_with("/hello/") {
@@ -166,34 +752,141 @@ async fn handle_connection(mut stream: TcpStream) {
             .duration_since(UNIX_EPOCH)
             .unwrap_or_default();
         (200, format!("Current time: {}", now.as_secs()))
+    } else if request.method == "GET" && request.path == "/large" {
+        // Big, repetitive body: a good candidate for compression
+        (
+            200,
+            "All work and no play makes Jack a dull boy.\n".repeat(400),
+        )
+    } else if let (true, Some(admin)) = (
+        request.method == "GET" && request.path == "/admin",
+        config.admin(),
+    ) {
+        match admin.check(
+            &request.method,
+            &request.path,
+            request.header("Authorization"),
+        ) {
+            Ok(()) => (200, format!("Welcome, {}!", admin.user)),
+            Err(denied) => {
+                warn!(%peer, reason = denied.reason, "admin login refused");
+                auth_headers.push(("WWW-Authenticate", denied.challenge));
+                (401, "401 Unauthorized".to_string())
+            }
+        }
     } else {
         (404, "404 Not Found".to_string())
     };
 
-    let response = build_response(status_code, "text/plain", &body);
-    let _ = stream.write_all(response.as_bytes()).await;
+    let (body, mut headers) = compress_body(&request, body.into_bytes(), &config);
+    headers.extend(auth_headers);
+    let response = build_response(status_code, "text/plain", &headers, &body);
+    if let Err(e) = stream.write_all(&response).await {
+        warn!(%peer, error = %e, "write failed");
+    }
+    // For TLS this sends close_notify so clients don't see a truncated stream
+    let _ = stream.shutdown().await;
+
+    log_access(
+        peer,
+        &request.method,
+        &request.path,
+        status_code,
+        response.len(),
+        start,
+    );
+}
+
+/// Log to stdout, verbosity from RUST_LOG (default: info)
+fn init_tracing() {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_ansi(std::io::stdout().is_terminal())
+        .init();
 }
 
 #[tokio::main]
 async fn main() {
-    let port = std::env::args().nth(1).unwrap_or_else(|| "8080".to_string());
-    let addr = format!("127.0.0.1:{}", port);
+    let config = Arc::new(Config::parse());
+    init_tracing();
+    let addr = format!("127.0.0.1:{}", config.port);
 
-    println!("start server at: {:#?}", addr);
+    let acceptor = if config.tls {
+        match tls::load_or_generate(&config.cert, &config.key) {
+            Ok(acceptor) => Some(acceptor),
+            Err(e) => {
+                error!(error = %e, "cannot set up TLS");
+                std::process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+
+    info!(
+        %addr,
+        tls = acceptor.is_some(),
+        accept_proxy = config.accept_proxy,
+        strict = config.strict,
+        "start server"
+    );
+    if acceptor.is_some() {
+        info!("TLS enabled: curl -k https://localhost:{}/", config.port);
+    }
+    if config.no_gzip {
+        info!("gzip compression disabled");
+    } else {
+        info!(
+            min_bytes = config.gzip_min_bytes,
+            "gzip compression enabled"
+        );
+    }
     // TODO: Implement
     // 1. Bind listener
     let listener = TcpListener::bind(addr).await.expect("Failed to bind");
     loop {
-        let (stream, _) = match listener.accept().await {
-            Ok(pair) => {
-                print!("pair: {:#?}", pair);
-                pair
+        let (mut stream, socket_peer) = match listener.accept().await {
+            Ok(pair) => pair,
+            Err(e) => {
+                warn!(error = %e, "accept failed");
+                continue;
             }
-            Err(_) => continue,
         };
+        debug!(peer = %socket_peer, "accepted connection");
 
+        let config = Arc::clone(&config);
+        let acceptor = acceptor.clone();
         tokio::spawn(async move {
-            handle_connection(stream).await;
+            // The header comes before everything else, TLS included
+            let peer = if config.accept_proxy {
+                match proxy_protocol::read_header(&mut stream).await {
+                    Ok(Some(addresses)) => addresses.source,
+                    // LOCAL / UNKNOWN: the balancer's own health check
+                    Ok(None) => socket_peer,
+                    Err(e) => {
+                        warn!(peer = %socket_peer, error = %e, "bad PROXY header");
+                        return;
+                    }
+                }
+            } else {
+                socket_peer
+            };
+            match acceptor {
+                Some(acceptor) => {
+                    // The handshake is the extra round trips TLS costs per connection
+                    let start = Instant::now();
+                    match acceptor.accept(stream).await {
+                        Ok(tls_stream) => {
+                            let handshake_us = start.elapsed().as_micros() as u64;
+                            info!(%peer, handshake_us, "TLS handshake");
+                            handle_connection(tls_stream, peer, config).await;
+                        }
+                        Err(e) => warn!(%peer, error = %e, "TLS handshake failed"),
+                    }
+                }
+                None => handle_connection(stream, peer, config).await,
+            }
         });
     }
     // 2. Accept connections
@@ -0,0 +1,190 @@
+//! Streaming multipart/form-data parser
+//!
+//! A multipart body is a list of parts, each introduced by a boundary line
+//! taken from the `Content-Type` header:
+//!
+//! ```text
+//! --XyZ\r\n
+//! Content-Disposition: form-data; name="file"; filename="a.txt"\r\n
+//! Content-Type: text/plain\r\n
+//! \r\n
+//! ...file bytes...\r\n
+//! --XyZ--\r\n
+//! ```
+//!
+//! The parser is fed the body chunk by chunk as it arrives from the socket
+//! and hands file bytes out straight away, so an upload never has to fit in
+//! memory. The only bytes it holds back are the few that could be the start
+//! of the next boundary.
+
+/// Limit for one part's header block, so a missing blank line can't grow the buffer forever
+const MAX_PART_HEADER_BYTES: usize = 8 * 1024;
+
+/// Headers of one part
+#[derive(Debug, PartialEq)]
+pub struct PartInfo {
+    pub name: String,
+    pub filename: Option<String>,
+    pub content_type: Option<String>,
+}
+
+/// What the parser found in the bytes fed so far
+#[derive(Debug, PartialEq)]
+pub enum Event {
+    /// A new part starts; Data events for it follow
+    Part(PartInfo),
+    Data(Vec<u8>),
+    PartEnd,
+}
+
+enum State {
+    Preamble,
+    AfterBoundary,
+    Headers,
+    Body,
+    Done,
+}
+
+pub struct MultipartParser {
+    delimiter: Vec<u8>,
+    buf: Vec<u8>,
+    state: State,
+}
+
+/// Extract the boundary from `multipart/form-data; boundary=...`
+pub fn boundary(content_type: &str) -> Option<String> {
+    let mut params = content_type.split(';').map(str::trim);
+    if !params.next()?.eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    params
+        .filter_map(|p| p.split_once('='))
+        .find(|(key, _)| key.trim().eq_ignore_ascii_case("boundary"))
+        .map(|(_, value)| value.trim().trim_matches('"').to_string())
+        .filter(|b| !b.is_empty() && b.len() <= 70)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Read a `key="value"` parameter from a Content-Disposition header
+fn disposition_param(value: &str, key: &str) -> Option<String> {
+    value
+        .split(';')
+        .filter_map(|p| p.trim().split_once('='))
+        .find(|(k, _)| k.trim().eq_ignore_ascii_case(key))
+        .map(|(_, v)| v.trim().trim_matches('"').to_string())
+}
+
+fn parse_part_headers(head: &str) -> Result<PartInfo, String> {
+    let mut disposition = None;
+    let mut content_type = None;
+
+    for line in head.split("\r\n") {
+        if let Some((key, value)) = line.split_once(':') {
+            if key.trim().eq_ignore_ascii_case("Content-Disposition") {
+                disposition = Some(value.trim().to_string());
+            } else if key.trim().eq_ignore_ascii_case("Content-Type") {
+                content_type = Some(value.trim().to_string());
+            }
+        }
+    }
+
+    let disposition = disposition.ok_or("part without Content-Disposition")?;
+    let name = disposition_param(&disposition, "name").ok_or("part without a name")?;
+
+    Ok(PartInfo {
+        name,
+        filename: disposition_param(&disposition, "filename").filter(|f| !f.is_empty()),
+        content_type,
+    })
+}
+
+impl MultipartParser {
+    pub fn new(boundary: &str) -> Self {
+        // Every boundary except the first follows a CRLF. Pretending the body
+        // starts with one lets a single delimiter match all of them.
+        Self {
+            delimiter: format!("\r\n--{}", boundary).into_bytes(),
+            buf: b"\r\n".to_vec(),
+            state: State::Preamble,
+        }
+    }
+
+    /// True once the closing `--boundary--` has been seen
+    pub fn is_done(&self) -> bool {
+        matches!(self.state, State::Done)
+    }
+
+    /// Consume the next chunk of the body
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<Vec<Event>, String> {
+        self.buf.extend_from_slice(chunk);
+        let mut events = Vec::new();
+
+        loop {
+            match self.state {
+                State::Preamble => match find(&self.buf, &self.delimiter) {
+                    Some(i) => {
+                        self.buf.drain(..i + self.delimiter.len());
+                        self.state = State::AfterBoundary;
+                    }
+                    None => {
+                        let keep = self.delimiter.len() - 1;
+                        self.buf.drain(..self.buf.len().saturating_sub(keep));
+                        return Ok(events);
+                    }
+                },
+                State::AfterBoundary => {
+                    // `--` closes the body, CRLF starts another part
+                    if self.buf.len() < 2 {
+                        return Ok(events);
+                    }
+                    if self.buf.starts_with(b"--") {
+                        self.state = State::Done;
+                    } else if self.buf.starts_with(b"\r\n") {
+                        self.buf.drain(..2);
+                        self.state = State::Headers;
+                    } else {
+                        return Err("malformed boundary line".to_string());
+                    }
+                }
+                State::Headers => match find(&self.buf, b"\r\n\r\n") {
+                    Some(i) => {
+                        let head = String::from_utf8_lossy(&self.buf[..i]).to_string();
+                        self.buf.drain(..i + 4);
+                        events.push(Event::Part(parse_part_headers(&head)?));
+                        self.state = State::Body;
+                    }
+                    None if self.buf.len() > MAX_PART_HEADER_BYTES => {
+                        return Err("part headers too large".to_string());
+                    }
+                    None => return Ok(events),
+                },
+                State::Body => match find(&self.buf, &self.delimiter) {
+                    Some(i) => {
+                        if i > 0 {
+                            events.push(Event::Data(self.buf[..i].to_vec()));
+                        }
+                        self.buf.drain(..i + self.delimiter.len());
+                        events.push(Event::PartEnd);
+                        self.state = State::AfterBoundary;
+                    }
+                    None => {
+                        // Everything except a possible partial delimiter is safe to hand out
+                        let safe = self.buf.len().saturating_sub(self.delimiter.len() - 1);
+                        if safe > 0 {
+                            events.push(Event::Data(self.buf.drain(..safe).collect()));
+                        }
+                        return Ok(events);
+                    }
+                },
+                State::Done => {
+                    // The epilogue after the closing boundary is ignored
+                    self.buf.clear();
+                    return Ok(events);
+                }
+            }
+        }
+    }
+}
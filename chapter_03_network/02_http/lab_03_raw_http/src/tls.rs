@@ -0,0 +1,56 @@
+//! HTTPS support: wraps accepted TCP streams in rustls
+//!
+//! TLS sits *between* TCP and HTTP: the bytes our parser sees are exactly
+//! the same, they're just encrypted on the wire. Compare:
+//!
+//! ```bash
+//! sudo tcpdump -i lo -A port 8080   # plain: request readable
+//! sudo tcpdump -i lo -A port 8443   # TLS: ClientHello, then noise
+//! ```
+
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
+
+/// Write a self-signed certificate for localhost / 127.0.0.1.
+///
+/// Clients won't trust it by default - use `curl -k` (skip verification)
+/// or `curl --cacert cert.pem` (trust this one certificate).
+pub fn generate_self_signed(cert_path: &Path, key_path: &Path) -> io::Result<()> {
+    let names = vec!["localhost".to_string(), "127.0.0.1".to_string()];
+    let certified = rcgen::generate_simple_self_signed(names).map_err(io::Error::other)?;
+
+    std::fs::write(cert_path, certified.cert.pem())?;
+    std::fs::write(key_path, certified.key_pair.serialize_pem())?;
+    Ok(())
+}
+
+/// Load cert + key from PEM files, generating a self-signed pair first if
+/// either file is missing
+pub fn load_or_generate(cert_path: &Path, key_path: &Path) -> io::Result<TlsAcceptor> {
+    if !cert_path.exists() || !key_path.exists() {
+        generate_self_signed(cert_path, key_path)?;
+        tracing::info!(
+            cert = %cert_path.display(),
+            key = %key_path.display(),
+            "generated self-signed certificate"
+        );
+    }
+
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|iter| iter.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
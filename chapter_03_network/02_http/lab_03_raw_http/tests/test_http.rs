@@ -1,10 +1,17 @@
 //! Lab 3 Tests
 
+#[path = "../../../shared/smuggling_cases.rs"]
+mod smuggling_cases;
+
 use std::io::{Read, Write};
 use std::net::TcpStream;
-use std::process::{Child, Command};
+use std::process::{Child, Command, Stdio};
+use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
+use tokio_rustls::rustls;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::CertificateDer;
 
 struct ServerGuard {
     child: Child,
@@ -32,6 +39,38 @@ fn start_server() -> Option<ServerGuard> {
     Some(ServerGuard { child })
 }
 
+fn start_server_with(args: &[&str]) -> Option<ServerGuard> {
+    Command::new("cargo")
+        .args(["build", "--quiet"])
+        .status()
+        .ok()?;
+
+    let child = Command::new("cargo")
+        .args(["run", "--quiet", "--"])
+        .args(args)
+        .spawn()
+        .ok()?;
+
+    thread::sleep(Duration::from_millis(500));
+
+    Some(ServerGuard { child })
+}
+
+/// Send a request to `port`, returning (head, body) split at the blank line
+fn send_request_bytes(port: u16, request: &str) -> Option<(String, Vec<u8>)> {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).ok()?;
+    stream.set_read_timeout(Some(Duration::from_secs(2))).ok()?;
+
+    stream.write_all(request.as_bytes()).ok()?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).ok()?;
+
+    let split = response.windows(4).position(|w| w == b"\r\n\r\n")?;
+    let head = String::from_utf8_lossy(&response[..split]).to_string();
+    Some((head, response[split + 4..].to_vec()))
+}
+
 fn send_request(request: &str) -> Option<String> {
     let mut stream = TcpStream::connect("127.0.0.1:8080").ok()?;
     stream.set_read_timeout(Some(Duration::from_secs(2))).ok()?;
@@ -162,3 +201,674 @@ fn test_06_has_content_length() {
         "Response should have Content-Length header"
     );
 }
+
+#[test]
+fn test_07_gzip_when_accepted() {
+    let _server = match start_server_with(&["8091"]) {
+        Some(s) => s,
+        None => return,
+    };
+
+    let request =
+        "GET /large HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: gzip, deflate\r\n\r\n";
+    let (head, body) = match send_request_bytes(8091, request) {
+        Some(r) => r,
+        None => return,
+    };
+
+    assert!(
+        head.contains("Content-Encoding: gzip"),
+        "Large body should be gzipped when client accepts gzip"
+    );
+    assert!(
+        head.contains("Vary: Accept-Encoding"),
+        "Vary header required"
+    );
+    assert!(
+        head.contains(&format!("Content-Length: {}", body.len())),
+        "Content-Length must describe the compressed body"
+    );
+
+    let mut decoded = String::new();
+    flate2::read::GzDecoder::new(&body[..])
+        .read_to_string(&mut decoded)
+        .expect("Body should be valid gzip");
+    assert!(decoded.starts_with("All work and no play"));
+    assert!(
+        body.len() < decoded.len(),
+        "Compressed body should be smaller"
+    );
+}
+
+#[test]
+fn test_08_no_gzip_without_accept_encoding() {
+    let _server = match start_server_with(&["8092"]) {
+        Some(s) => s,
+        None => return,
+    };
+
+    let request = "GET /large HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    let (head, body) = match send_request_bytes(8092, request) {
+        Some(r) => r,
+        None => return,
+    };
+
+    assert!(
+        !head.contains("Content-Encoding"),
+        "Client did not ask for gzip"
+    );
+    assert!(body.starts_with(b"All work and no play"));
+}
+
+#[test]
+fn test_09_small_body_not_compressed() {
+    let _server = match start_server_with(&["8093"]) {
+        Some(s) => s,
+        None => return,
+    };
+
+    let request = "GET / HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: gzip\r\n\r\n";
+    let (head, body) = match send_request_bytes(8093, request) {
+        Some(r) => r,
+        None => return,
+    };
+
+    assert!(
+        !head.contains("Content-Encoding"),
+        "Bodies below the threshold should not be compressed"
+    );
+    assert_eq!(body, b"Hello, World!");
+}
+
+#[test]
+fn test_10_no_gzip_flag_disables_compression() {
+    let _server = match start_server_with(&["8094", "--no-gzip"]) {
+        Some(s) => s,
+        None => return,
+    };
+
+    let request = "GET /large HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: gzip\r\n\r\n";
+    let (head, _) = match send_request_bytes(8094, request) {
+        Some(r) => r,
+        None => return,
+    };
+
+    assert!(
+        !head.contains("Content-Encoding"),
+        "--no-gzip should disable gzip"
+    );
+    assert!(
+        !head.contains("Vary"),
+        "No Vary header when compression is off"
+    );
+}
+
+/// Send a request over TLS, trusting only the server's own certificate
+fn send_tls_request(port: u16, cert_path: &std::path::Path, request: &str) -> Option<String> {
+    let mut roots = rustls::RootCertStore::empty();
+    for cert in CertificateDer::pem_file_iter(cert_path).ok()? {
+        roots.add(cert.ok()?).ok()?;
+    }
+    let config = rustls::ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let conn =
+        rustls::ClientConnection::new(Arc::new(config), "localhost".try_into().ok()?).ok()?;
+
+    let sock = TcpStream::connect(("127.0.0.1", port)).ok()?;
+    sock.set_read_timeout(Some(Duration::from_secs(2))).ok()?;
+    let mut tls = rustls::StreamOwned::new(conn, sock);
+
+    tls.write_all(request.as_bytes()).ok()?;
+    let mut response = String::new();
+    tls.read_to_string(&mut response).ok()?;
+
+    Some(response)
+}
+
+#[test]
+fn test_11_tls_serves_https() {
+    let dir = std::env::temp_dir().join(format!("raw_http_tls_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let cert = dir.join("cert.pem");
+    let key = dir.join("key.pem");
+
+    let _server = match start_server_with(&[
+        "8095",
+        "--tls",
+        "--cert",
+        cert.to_str().unwrap(),
+        "--key",
+        key.to_str().unwrap(),
+    ]) {
+        Some(s) => s,
+        None => return,
+    };
+
+    assert!(cert.exists(), "Self-signed certificate should be generated");
+    assert!(key.exists(), "Private key should be generated");
+
+    let request = "GET /hello/TLS HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    let response = send_tls_request(8095, &cert, request).expect("TLS request should succeed");
+
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "HTTP works unchanged over TLS"
+    );
+    assert!(response.contains("Hello, TLS!"));
+
+    // Plain HTTP to a TLS port gets no HTTP response back
+    let plain = send_request_bytes(8095, request);
+    assert!(
+        !plain.is_some_and(|(head, _)| head.starts_with("HTTP/1.1 200")),
+        "Plain HTTP should not be served on the TLS port"
+    );
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_12_access_log_line_per_request() {
+    // Run the binary directly so killing it closes its stdout pipe
+    let child = Command::new(env!("CARGO_BIN_EXE_raw_http"))
+        .arg("8096")
+        .env("RUST_LOG", "access=info")
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start server");
+    let mut server = ServerGuard { child };
+    thread::sleep(Duration::from_millis(500));
+
+    let request = "GET /hello/log HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    let sent = send_request_bytes(8096, request);
+    thread::sleep(Duration::from_millis(100));
+
+    let _ = server.child.kill();
+    let mut output = String::new();
+    server
+        .child
+        .stdout
+        .take()
+        .unwrap()
+        .read_to_string(&mut output)
+        .unwrap();
+
+    if sent.is_none() {
+        return;
+    }
+
+    let line = output
+        .lines()
+        .find(|l| l.contains("path=/hello/log"))
+        .expect("Access log should contain one line for the request");
+    for field in [
+        "peer=127.0.0.1:",
+        "method=GET",
+        "status=200",
+        "bytes=",
+        "duration_us=",
+    ] {
+        assert!(
+            line.contains(field),
+            "Access log line should contain `{}`: {}",
+            field,
+            line
+        );
+    }
+    assert!(
+        !output.contains("raw request"),
+        "Raw request bytes should only be logged at trace level"
+    );
+}
+
+/// Fresh upload directory per test
+fn upload_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("raw_http_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+/// multipart/form-data body with one file part and one plain field
+fn multipart_body(boundary: &str, filename: &str, content: &[u8]) -> Vec<u8> {
+    let mut body = Vec::new();
+    body.extend_from_slice(
+        format!(
+            "--{b}\r\nContent-Disposition: form-data; name=\"note\"\r\n\r\nhello\r\n\
+             --{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{f}\"\r\n\
+             Content-Type: application/octet-stream\r\n\r\n",
+            b = boundary,
+            f = filename
+        )
+        .as_bytes(),
+    );
+    body.extend_from_slice(content);
+    body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+    body
+}
+
+fn upload_request(boundary: &str, body: &[u8]) -> Vec<u8> {
+    let mut request = format!(
+        "POST /upload HTTP/1.1\r\nHost: localhost\r\n\
+         Content-Type: multipart/form-data; boundary={}\r\nContent-Length: {}\r\n\r\n",
+        boundary,
+        body.len()
+    )
+    .into_bytes();
+    request.extend_from_slice(body);
+    request
+}
+
+/// Write `request` in `chunk`-sized pieces, then read the whole response
+fn send_in_chunks(port: u16, request: &[u8], chunk: usize) -> Option<(String, String)> {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).ok()?;
+    stream.set_read_timeout(Some(Duration::from_secs(5))).ok()?;
+
+    for piece in request.chunks(chunk) {
+        stream.write_all(piece).ok()?;
+        stream.flush().ok()?;
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).ok()?;
+    let response = String::from_utf8_lossy(&response).to_string();
+    let (head, body) = response.split_once("\r\n\r\n")?;
+    Some((head.to_string(), body.to_string()))
+}
+
+#[test]
+fn test_13_upload_writes_file() {
+    let dir = upload_dir("upload");
+    let _server = start_server_with(&["8097", "--upload-dir", dir.to_str().unwrap()]);
+
+    // Binary content that contains CRLF and dashes, like a real file might
+    let content: Vec<u8> = (0..20_000u32).map(|i| (i % 251) as u8).collect();
+    let boundary = "----raw-http-test";
+    let request = upload_request(
+        boundary,
+        &multipart_body(boundary, "../../a b.bin", &content),
+    );
+
+    let (head, body) = match send_in_chunks(8097, &request, 64 * 1024) {
+        Some(r) => r,
+        None => return,
+    };
+
+    assert!(
+        head.starts_with("HTTP/1.1 201"),
+        "Upload should return 201: {}",
+        head
+    );
+    assert!(head.contains("Content-Type: application/json"));
+    assert!(
+        body.contains("\"fields\":{\"note\":\"hello\"}"),
+        "Form field in summary: {}",
+        body
+    );
+    assert!(
+        body.contains("\"filename\":\"a_b.bin\""),
+        "Filename is sanitized: {}",
+        body
+    );
+    assert!(
+        body.contains("\"size\":20000"),
+        "Summary reports file size: {}",
+        body
+    );
+
+    let stored: Vec<_> = std::fs::read_dir(&dir).unwrap().flatten().collect();
+    assert_eq!(
+        stored.len(),
+        1,
+        "Exactly one file stored inside the upload dir"
+    );
+    assert_eq!(std::fs::read(stored[0].path()).unwrap(), content);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_14_upload_streamed_in_small_pieces() {
+    let dir = upload_dir("stream");
+    let _server = start_server_with(&["8098", "--upload-dir", dir.to_str().unwrap()]);
+
+    // Boundaries split across reads must still be found
+    let content = b"line one\r\n--not-a-boundary\r\nline two".repeat(50);
+    let boundary = "XyZ";
+    let request = upload_request(boundary, &multipart_body(boundary, "notes.txt", &content));
+
+    let (head, _) = match send_in_chunks(8098, &request, 7) {
+        Some(r) => r,
+        None => return,
+    };
+
+    assert!(
+        head.starts_with("HTTP/1.1 201"),
+        "Upload should return 201: {}",
+        head
+    );
+    let stored: Vec<_> = std::fs::read_dir(&dir).unwrap().flatten().collect();
+    assert_eq!(stored.len(), 1);
+    assert_eq!(std::fs::read(stored[0].path()).unwrap(), content);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_15_upload_rejects_bad_requests() {
+    let dir = upload_dir("reject");
+    let _server = start_server_with(&[
+        "8099",
+        "--upload-dir",
+        dir.to_str().unwrap(),
+        "--max-upload-bytes",
+        "1000",
+    ]);
+
+    let not_multipart =
+        "POST /upload HTTP/1.1\r\nContent-Type: text/plain\r\nContent-Length: 1\r\n\r\nx";
+    let (head, _) = match send_request_bytes(8099, not_multipart) {
+        Some(r) => r,
+        None => return,
+    };
+    assert!(
+        head.starts_with("HTTP/1.1 415"),
+        "Non-multipart body: {}",
+        head
+    );
+
+    let no_length =
+        "POST /upload HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=x\r\n\r\n";
+    let (head, _) = send_request_bytes(8099, no_length).expect("server should answer");
+    assert!(
+        head.starts_with("HTTP/1.1 411"),
+        "Missing Content-Length: {}",
+        head
+    );
+
+    let boundary = "big";
+    let request = upload_request(boundary, &multipart_body(boundary, "big.bin", &[0u8; 2000]));
+    let (head, _) = send_in_chunks(8099, &request, 64 * 1024).expect("server should answer");
+    assert!(
+        head.starts_with("HTTP/1.1 413"),
+        "Body over the limit: {}",
+        head
+    );
+
+    let truncated = "POST /upload HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=x\r\n\
+                     Content-Length: 60\r\n\r\n--x\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\nab";
+    // Client gives up mid-body: half-close so the server sees EOF
+    let mut stream = TcpStream::connect(("127.0.0.1", 8099)).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream.write_all(truncated.as_bytes()).unwrap();
+    stream.shutdown(std::net::Shutdown::Write).unwrap();
+    let mut response = String::new();
+    let _ = stream.read_to_string(&mut response);
+    assert!(
+        response.starts_with("HTTP/1.1 400"),
+        "Truncated body: {}",
+        response
+    );
+    assert_eq!(
+        std::fs::read_dir(&dir).map(|d| d.count()).unwrap_or(0),
+        0,
+        "Rejected uploads leave no files behind"
+    );
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+fn admin_request(authorization: Option<&str>) -> String {
+    match authorization {
+        Some(value) => format!(
+            "GET /admin HTTP/1.1\r\nHost: localhost\r\nAuthorization: {}\r\n\r\n",
+            value
+        ),
+        None => "GET /admin HTTP/1.1\r\nHost: localhost\r\n\r\n".to_string(),
+    }
+}
+
+fn www_authenticate(head: &str) -> String {
+    head.lines()
+        .find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.eq_ignore_ascii_case("www-authenticate")
+                .then(|| value.trim().to_string())
+        })
+        .unwrap_or_else(|| panic!("No WWW-Authenticate header: {}", head))
+}
+
+#[test]
+fn test_16_basic_auth_protects_admin() {
+    let _server = start_server_with(&["8101", "--admin-password", "s3cret"]);
+
+    let (head, body) = match send_request_bytes(8101, &admin_request(None)) {
+        Some(r) => r,
+        None => return,
+    };
+    assert!(head.starts_with("HTTP/1.1 401"), "No credentials: {}", head);
+    assert!(www_authenticate(&head).starts_with("Basic realm=\"raw_http\""));
+    assert_eq!(body, b"401 Unauthorized");
+
+    // base64("admin:s3cret") and base64("admin:wrong")
+    let (head, body) =
+        send_request_bytes(8101, &admin_request(Some("Basic YWRtaW46czNjcmV0"))).unwrap();
+    assert!(head.starts_with("HTTP/1.1 200"), "Right password: {}", head);
+    assert_eq!(body, b"Welcome, admin!");
+
+    for bad in [
+        "Basic YWRtaW46d3Jvbmc=",
+        "Basic !!!not-base64!!!",
+        "Basic bm8tY29sb24=",
+        "Bearer s3cret",
+    ] {
+        let (head, _) = send_request_bytes(8101, &admin_request(Some(bad))).unwrap();
+        assert!(
+            head.starts_with("HTTP/1.1 401"),
+            "{} accepted: {}",
+            bad,
+            head
+        );
+        assert!(head
+            .to_ascii_lowercase()
+            .contains("www-authenticate: basic"));
+    }
+
+    // Other routes stay open
+    let (head, _) = send_request_bytes(8101, "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    assert!(head.starts_with("HTTP/1.1 200"));
+
+    // Without --admin-password there is no /admin at all
+    let _plain = start_server_with(&["8089"]);
+    let (head, _) = send_request_bytes(8089, &admin_request(None)).unwrap();
+    assert!(
+        head.starts_with("HTTP/1.1 404"),
+        "No password set: {}",
+        head
+    );
+}
+
+#[test]
+fn test_17_digest_auth_protects_admin() {
+    use md5::{Digest, Md5};
+
+    fn md5_hex(input: &str) -> String {
+        format!("{:x}", Md5::digest(input))
+    }
+
+    fn param<'a>(challenge: &'a str, key: &str) -> &'a str {
+        let start = challenge
+            .find(&format!("{}=\"", key))
+            .unwrap_or_else(|| panic!("No {} in {}", key, challenge))
+            + key.len()
+            + 2;
+        let end = challenge[start..].find('"').unwrap() + start;
+        &challenge[start..end]
+    }
+
+    fn digest(nonce: &str, uri: &str, password: &str) -> String {
+        let ha1 = md5_hex(&format!("admin:raw_http:{}", password));
+        let ha2 = md5_hex(&format!("GET:{}", uri));
+        let response = md5_hex(&format!("{}:{}:00000001:c0ffee:auth:{}", ha1, nonce, ha2));
+        format!(
+            "Digest username=\"admin\", realm=\"raw_http\", nonce=\"{}\", uri=\"{}\", \
+             qop=auth, nc=00000001, cnonce=\"c0ffee\", response=\"{}\"",
+            nonce, uri, response
+        )
+    }
+
+    let _server = start_server_with(&["8090", "--admin-password", "s3cret", "--auth", "digest"]);
+
+    let (head, _) = match send_request_bytes(8090, &admin_request(None)) {
+        Some(r) => r,
+        None => return,
+    };
+    assert!(head.starts_with("HTTP/1.1 401"), "No credentials: {}", head);
+    let challenge = www_authenticate(&head);
+    assert!(challenge.starts_with("Digest "), "{}", challenge);
+    assert_eq!(param(&challenge, "realm"), "raw_http");
+    assert_eq!(param(&challenge, "qop"), "auth");
+    let nonce = param(&challenge, "nonce").to_string();
+
+    let (head, body) = send_request_bytes(
+        8090,
+        &admin_request(Some(&digest(&nonce, "/admin", "s3cret"))),
+    )
+    .unwrap();
+    assert!(head.starts_with("HTTP/1.1 200"), "Right password: {}", head);
+    assert_eq!(body, b"Welcome, admin!");
+
+    let refused = [
+        ("wrong password", digest(&nonce, "/admin", "wrong")),
+        (
+            "made-up nonce",
+            digest("5f5e100-0123456789abcdef", "/admin", "s3cret"),
+        ),
+        ("other uri", digest(&nonce, "/admin?x=1", "s3cret")),
+        ("Basic", "Basic YWRtaW46czNjcmV0".to_string()),
+    ];
+    for (what, authorization) in refused {
+        let (head, _) = send_request_bytes(8090, &admin_request(Some(&authorization))).unwrap();
+        assert!(
+            head.starts_with("HTTP/1.1 401"),
+            "{} accepted: {}",
+            what,
+            head
+        );
+        assert!(www_authenticate(&head).starts_with("Digest "));
+    }
+}
+
+#[test]
+fn test_18_accept_proxy_logs_the_real_client() {
+    let child = Command::new(env!("CARGO_BIN_EXE_raw_http"))
+        .args(["8100", "--accept-proxy"])
+        .env("RUST_LOG", "access=info")
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start server");
+    let mut server = ServerGuard { child };
+    thread::sleep(Duration::from_millis(500));
+
+    let send = |request: &[u8]| -> Vec<u8> {
+        let mut stream = TcpStream::connect("127.0.0.1:8100").expect("connect");
+        stream
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        stream.write_all(request).unwrap();
+        let mut response = Vec::new();
+        let _ = stream.read_to_end(&mut response);
+        response
+    };
+
+    let v1 = send(b"PROXY TCP4 203.0.113.7 127.0.0.1 40000 8100\r\nGET /hello/v1 HTTP/1.1\r\n\r\n");
+    let mut v2_request = b"\r\n\r\n\0\r\nQUIT\n\x21\x11\x00\x0c".to_vec();
+    v2_request.extend_from_slice(&[198, 51, 100, 9, 127, 0, 0, 1, 0x9c, 0x41, 0x1f, 0xa4]);
+    v2_request.extend_from_slice(b"GET /hello/v2 HTTP/1.1\r\n\r\n");
+    let v2 = send(&v2_request);
+    let missing = send(b"GET /hello/none HTTP/1.1\r\n\r\n");
+    thread::sleep(Duration::from_millis(100));
+
+    let _ = server.child.kill();
+    let mut output = String::new();
+    server
+        .child
+        .stdout
+        .take()
+        .unwrap()
+        .read_to_string(&mut output)
+        .unwrap();
+
+    assert!(
+        v1.ends_with(b"Hello, v1!"),
+        "{}",
+        String::from_utf8_lossy(&v1)
+    );
+    assert!(
+        v2.ends_with(b"Hello, v2!"),
+        "{}",
+        String::from_utf8_lossy(&v2)
+    );
+    assert!(missing.is_empty(), "no header should mean no response");
+
+    for (path, peer) in [
+        ("path=/hello/v1", "peer=203.0.113.7:40000"),
+        ("path=/hello/v2", "peer=198.51.100.9:40001"),
+    ] {
+        let line = output
+            .lines()
+            .find(|l| l.contains(path))
+            .expect("access log line");
+        assert!(line.contains(peer), "expected {}: {}", peer, line);
+    }
+    assert!(!output.contains("path=/hello/none"));
+}
+
+/// Send raw bytes to `port` and return everything that comes back
+fn send_raw(port: u16, request: &[u8]) -> String {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).expect("connect");
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    stream.write_all(request).unwrap();
+    let mut response = Vec::new();
+    let _ = stream.read_to_end(&mut response);
+    String::from_utf8_lossy(&response).to_string()
+}
+
+#[test]
+fn test_19_strict_rejects_smuggling_attempts() {
+    let _server = start_server_with(&["8127", "--strict"]);
+
+    for (case, request) in smuggling_cases::REJECTED {
+        let response = send_raw(8127, request);
+        assert!(
+            response.starts_with("HTTP/1.1 400"),
+            "{}: got {:?}",
+            case,
+            response
+        );
+        assert!(
+            response.contains("400 Bad Request: "),
+            "{}: no reason",
+            case
+        );
+    }
+    for (case, request) in smuggling_cases::ACCEPTED {
+        let response = send_raw(8127, request);
+        assert!(
+            response.starts_with("HTTP/1.1 200"),
+            "{}: got {:?}",
+            case,
+            response
+        );
+    }
+
+    // Without --strict the same bare-LF request is served: it is read as
+    // one long request line, and the Host header is never seen
+    let _lenient = start_server_with(&["8129"]);
+    let response = send_raw(8129, b"GET / HTTP/1.1\nHost: x\n\n");
+    assert!(response.starts_with("HTTP/1.1 200"), "got {:?}", response);
+}
@@ -0,0 +1,247 @@
+//! API key authentication
+//!
+//! Clients send a key as a bearer token:
+//!
+//! ```text
+//! Authorization: Bearer <key>
+//! ```
+//!
+//! Keys come from `API_KEYS` (comma separated) or `API_KEYS_FILE` (one per
+//! line, `#` starts a comment), each entry `name:scope:key`:
+//!
+//! ```bash
+//! API_KEYS="admin:write:s3cret,dashboard:read:r34d0nly" cargo run
+//! ```
+//!
+//! - Reads (GET/HEAD/OPTIONS) stay open; a key is optional but must be valid
+//! - Writes need a `write` key: none or an unknown one is a 401, a `read`
+//!   key is a 403
+//! - `GET /stats` needs any valid key and shows how often each key was used
+//!
+//! With no keys configured, authentication is off and everything is open.
+
+use axum::{
+    extract::{Request, State},
+    http::{header, Method},
+    middleware::Next,
+    response::Response,
+    Json,
+};
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::error::AppError;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Scope {
+    Read,
+    Write,
+}
+
+impl Scope {
+    fn name(self) -> &'static str {
+        match self {
+            Scope::Read => "read",
+            Scope::Write => "write",
+        }
+    }
+}
+
+struct ApiKey {
+    name: String,
+    scope: Scope,
+    secret: String,
+    /// Requests let through with this key
+    requests: AtomicU64,
+    /// Requests refused because the scope was too small
+    denied: AtomicU64,
+}
+
+/// Configured keys plus counters; shared by the middleware and /stats
+pub struct Auth {
+    keys: Vec<ApiKey>,
+    /// Reads without a key
+    anonymous: AtomicU64,
+    /// 401s: missing, malformed or unknown credentials
+    rejected: AtomicU64,
+}
+
+impl Auth {
+    /// Keys from `API_KEYS`, else from the file named by `API_KEYS_FILE`,
+    /// else none (authentication off)
+    pub fn from_env() -> Result<Self, String> {
+        let spec = match (std::env::var("API_KEYS"), std::env::var("API_KEYS_FILE")) {
+            (Ok(keys), _) => keys.replace(',', "\n"),
+            (Err(_), Ok(path)) => std::fs::read_to_string(&path)
+                .map_err(|e| format!("cannot read API_KEYS_FILE {}: {}", path, e))?,
+            (Err(_), Err(_)) => String::new(),
+        };
+        Self::parse(&spec)
+    }
+
+    /// One `name:scope:key` entry per line; blank lines and `#` comments are skipped
+    fn parse(spec: &str) -> Result<Self, String> {
+        let mut keys: Vec<ApiKey> = Vec::new();
+        for entry in spec
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or("").trim())
+        {
+            if entry.is_empty() {
+                continue;
+            }
+            // The key goes last, so it may contain ':' itself
+            let mut parts = entry.splitn(3, ':');
+            let (name, scope, secret) = match (parts.next(), parts.next(), parts.next()) {
+                (Some(name), Some(scope), Some(secret)) if !name.is_empty() => {
+                    (name, scope, secret)
+                }
+                _ => {
+                    return Err(format!(
+                        "API key entry '{}' is not name:scope:key",
+                        name_of(entry)
+                    ))
+                }
+            };
+            let scope = match scope {
+                "read" => Scope::Read,
+                "write" => Scope::Write,
+                other => {
+                    return Err(format!(
+                        "API key '{}': unknown scope '{}' (read|write)",
+                        name, other
+                    ))
+                }
+            };
+            if secret.is_empty() {
+                return Err(format!("API key '{}' has an empty key", name));
+            }
+            if keys.iter().any(|k| k.name == name || k.secret == secret) {
+                return Err(format!("API key '{}' is configured twice", name));
+            }
+            keys.push(ApiKey {
+                name: name.to_string(),
+                scope,
+                secret: secret.to_string(),
+                requests: AtomicU64::new(0),
+                denied: AtomicU64::new(0),
+            });
+        }
+
+        Ok(Self {
+            keys,
+            anonymous: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+        })
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    fn find(&self, secret: &str) -> Option<&ApiKey> {
+        // Compare against every key, in constant time, so response timing
+        // doesn't tell how much of a guess was right
+        self.keys.iter().fold(None, |found, key| {
+            if constant_time_eq(key.secret.as_bytes(), secret.as_bytes()) {
+                Some(key)
+            } else {
+                found
+            }
+        })
+    }
+
+    fn reject(&self, message: &str) -> AppError {
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+        AppError::Unauthorized(message.to_string())
+    }
+}
+
+/// Only the name part of an entry, so error messages never echo a key
+fn name_of(entry: &str) -> &str {
+    entry.split(':').next().unwrap_or("")
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Methods that don't change anything
+fn is_read(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+/// Middleware: check the bearer token against the configured keys
+pub async fn require_key(
+    State(auth): State<Arc<Auth>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    if !auth.enabled() {
+        return Ok(next.run(request).await);
+    }
+
+    let needs_key = !is_read(request.method()) || request.uri().path() == "/stats";
+    let key = match request.headers().get(header::AUTHORIZATION) {
+        None if needs_key => return Err(auth.reject("missing bearer token")),
+        None => {
+            auth.anonymous.fetch_add(1, Ordering::Relaxed);
+            return Ok(next.run(request).await);
+        }
+        Some(value) => {
+            let token = value
+                .to_str()
+                .ok()
+                .and_then(|v| v.strip_prefix("Bearer "))
+                .ok_or_else(|| auth.reject("expected Authorization: Bearer <key>"))?;
+            auth.find(token.trim())
+                .ok_or_else(|| auth.reject("invalid API key"))?
+        }
+    };
+
+    if !is_read(request.method()) && key.scope != Scope::Write {
+        key.denied.fetch_add(1, Ordering::Relaxed);
+        return Err(AppError::Forbidden(format!(
+            "API key '{}' is read-only",
+            key.name
+        )));
+    }
+    key.requests.fetch_add(1, Ordering::Relaxed);
+    Ok(next.run(request).await)
+}
+
+#[derive(Serialize)]
+pub struct KeyStats {
+    name: String,
+    scope: &'static str,
+    requests: u64,
+    denied: u64,
+}
+
+/// Response of GET /stats
+#[derive(Serialize)]
+pub struct StatsResponse {
+    auth_enabled: bool,
+    keys: Vec<KeyStats>,
+    anonymous: u64,
+    rejected: u64,
+}
+
+/// GET /stats - per-key request counters (never the keys themselves)
+pub async fn stats(State(auth): State<Arc<Auth>>) -> Json<StatsResponse> {
+    Json(StatsResponse {
+        auth_enabled: auth.enabled(),
+        keys: auth
+            .keys
+            .iter()
+            .map(|key| KeyStats {
+                name: key.name.clone(),
+                scope: key.scope.name(),
+                requests: key.requests.load(Ordering::Relaxed),
+                denied: key.denied.load(Ordering::Relaxed),
+            })
+            .collect(),
+        anonymous: auth.anonymous.load(Ordering::Relaxed),
+        rejected: auth.rejected.load(Ordering::Relaxed),
+    })
+}
@@ -0,0 +1,202 @@
+//! POST /items:batch - several writes, all or nothing
+//!
+//! The body is an array of operations, applied in order inside one store
+//! transaction:
+//!
+//! ```text
+//! [{"op":"create","name":"Widget","price":9.99},
+//!  {"op":"update","id":1,"price":4.99},
+//!  {"op":"delete","id":2}]
+//!
+//! 200 {"results":[{"status":201,"item":{...},"etag":"..."},
+//!                 {"status":200,"item":{...},"etag":"..."},
+//!                 {"status":204,"id":2}]}
+//! ```
+//!
+//! If any operation fails, none are applied. The response still has one
+//! result per operation, so the client can tell which one to fix:
+//!
+//! ```text
+//! 404 {"error":"operation 2 failed, nothing was applied","code":"batch_failed",
+//!      "results":[{"status":424,"code":"failed_dependency",...},
+//!                 {"status":424,"code":"failed_dependency",...},
+//!                 {"status":404,"code":"not_found","error":"item 2 not found"}]}
+//! ```
+//!
+//! 424 Failed Dependency marks operations that were fine on their own but
+//! were rolled back (or never tried) because another one failed.
+
+use axum::{
+    extract::State,
+    http::{Method, StatusCode, Uri},
+    Json,
+};
+use serde::Serialize;
+
+use crate::error::{self, AppError, AppJson, FieldError};
+use crate::store::{Applied, BatchOp, BatchOutcome, Item};
+use crate::{etag, validate, AppState};
+
+/// Most operations one batch may hold
+const MAX_BATCH_SIZE: usize = 100;
+
+/// The route is `/items:action`: matchit 0.7 has no way to escape `:`, so
+/// the handler checks that the whole path really is `/items:batch`
+const PATH: &str = "/items:batch";
+
+/// Outcome of one operation, in the same order as the request
+#[derive(Serialize, Default)]
+pub struct OpResult {
+    status: u16,
+    /// Only for deletes; creates and updates carry the whole item
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    item: Option<Item>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    etag: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    fields: Vec<FieldError>,
+}
+
+impl OpResult {
+    fn applied(applied: Applied) -> Self {
+        let (status, id, item) = match applied {
+            Applied::Created(item) => (StatusCode::CREATED, None, Some(item)),
+            Applied::Updated(item) => (StatusCode::OK, None, Some(item)),
+            Applied::Deleted(id) => (StatusCode::NO_CONTENT, Some(id), None),
+        };
+        Self {
+            status: status.as_u16(),
+            id,
+            etag: item.as_ref().map(etag::of),
+            item,
+            ..Self::default()
+        }
+    }
+
+    fn invalid(fields: Vec<FieldError>) -> Self {
+        Self {
+            status: StatusCode::UNPROCESSABLE_ENTITY.as_u16(),
+            code: Some("validation_failed"),
+            error: Some("validation failed".to_string()),
+            fields,
+            ..Self::default()
+        }
+    }
+
+    fn not_found(id: u64) -> Self {
+        Self {
+            status: StatusCode::NOT_FOUND.as_u16(),
+            code: Some("not_found"),
+            error: Some(format!("item {} not found", id)),
+            ..Self::default()
+        }
+    }
+
+    /// Valid on its own, not applied because another operation failed
+    fn skipped() -> Self {
+        Self {
+            status: StatusCode::FAILED_DEPENDENCY.as_u16(),
+            code: Some("failed_dependency"),
+            error: Some("not applied: another operation in the batch failed".to_string()),
+            ..Self::default()
+        }
+    }
+}
+
+/// Response of a committed batch
+#[derive(Serialize)]
+pub struct BatchResponse {
+    results: Vec<OpResult>,
+}
+
+/// Field problems of one operation; empty if it is valid
+fn problems(op: &BatchOp) -> Vec<FieldError> {
+    let checked = match op {
+        BatchOp::Create { name, price } => validate(Some(name), Some(*price)),
+        BatchOp::Update { name, price, .. } => validate(name.as_deref(), *price),
+        BatchOp::Delete { .. } => Ok(()),
+    };
+    match checked {
+        Err(AppError::Validation(fields)) => fields,
+        _ => Vec::new(),
+    }
+}
+
+/// POST /items:batch - Apply create/update/delete operations atomically
+pub async fn apply(
+    State(store): State<AppState>,
+    method: Method,
+    uri: Uri,
+    body: Result<AppJson<Vec<BatchOp>>, AppError>,
+) -> Result<Json<BatchResponse>, AppError> {
+    // Check the path before the body: POST /itemsX is a 404 whatever it sends
+    if uri.path() != PATH {
+        return Err(error::route_not_found(method, uri).await);
+    }
+    let AppJson(ops) = body?;
+    if ops.is_empty() || ops.len() > MAX_BATCH_SIZE {
+        return Err(AppError::Validation(vec![FieldError {
+            field: "operations",
+            message: "must hold between 1 and 100 operations",
+        }]));
+    }
+
+    // Validate everything first: nothing reaches the store if any op is bad
+    let problems: Vec<Vec<FieldError>> = ops.iter().map(problems).collect();
+    let invalid = problems.iter().filter(|p| !p.is_empty()).count();
+    if invalid > 0 {
+        let results = problems
+            .into_iter()
+            .map(|fields| {
+                if fields.is_empty() {
+                    OpResult::skipped()
+                } else {
+                    OpResult::invalid(fields)
+                }
+            })
+            .collect();
+        return Err(AppError::BatchFailed(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("{} invalid operation(s), nothing was applied", invalid),
+            results,
+        ));
+    }
+
+    let count = ops.len();
+    match store.apply_batch(ops).await? {
+        BatchOutcome::Applied(applied) => Ok(Json(BatchResponse {
+            results: applied.into_iter().map(OpResult::applied).collect(),
+        })),
+        BatchOutcome::NotFound { index, id } => {
+            let results = (0..count)
+                .map(|i| {
+                    if i == index {
+                        OpResult::not_found(id)
+                    } else {
+                        OpResult::skipped()
+                    }
+                })
+                .collect();
+            Err(AppError::BatchFailed(
+                StatusCode::NOT_FOUND,
+                format!("operation {} failed, nothing was applied", index),
+                results,
+            ))
+        }
+    }
+}
+
+/// Any other method on `/items:action`: 405 for the batch path, 404 otherwise
+pub async fn other_method(method: Method, uri: Uri) -> AppError {
+    if uri.path() == PATH {
+        AppError::MethodNotAllowed
+    } else {
+        error::route_not_found(method, uri).await
+    }
+}
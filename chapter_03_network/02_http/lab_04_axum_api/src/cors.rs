@@ -0,0 +1,105 @@
+//! CORS: letting browser pages on other origins call the API
+//!
+//! A browser only lets `https://app.example.com` read responses from
+//! `http://localhost:8080` if the API says so in `Access-Control-Allow-*`
+//! headers. Anything beyond a simple GET is asked about first, with a
+//! preflight:
+//!
+//! ```text
+//! OPTIONS /items/1
+//! Origin: https://app.example.com
+//! Access-Control-Request-Method: PUT
+//! Access-Control-Request-Headers: content-type, if-match
+//!
+//! 200 OK
+//! Access-Control-Allow-Origin: https://app.example.com
+//! Access-Control-Allow-Methods: GET,POST,PUT,PATCH,DELETE
+//! Access-Control-Max-Age: 600          <- skip the preflight for 10 minutes
+//! ```
+//!
+//! Configured from the environment; with `CORS_ORIGINS` unset there is no
+//! CORS layer and browsers keep the default same-origin rule:
+//!
+//! - `CORS_ORIGINS`: comma separated origins, or `*` for any
+//! - `CORS_METHODS`: default `GET,POST,PUT,PATCH,DELETE`
+//! - `CORS_HEADERS`: request headers pages may send, default
+//!   `content-type,authorization,if-match,if-none-match,x-request-id`
+//! - `CORS_MAX_AGE`: seconds a preflight may be cached, default 600
+
+use axum::http::{header, HeaderName, HeaderValue, Method};
+use std::time::Duration;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+const DEFAULT_METHODS: &str = "GET,POST,PUT,PATCH,DELETE";
+const DEFAULT_HEADERS: &str = "content-type,authorization,if-match,if-none-match,x-request-id";
+const DEFAULT_MAX_AGE: u64 = 600;
+
+fn var_or(name: &str, default: &str) -> String {
+    std::env::var(name).unwrap_or_else(|_| default.to_string())
+}
+
+/// Non-empty, trimmed entries of a comma separated list
+fn entries(list: &str) -> impl Iterator<Item = &str> {
+    list.split(',').map(str::trim).filter(|s| !s.is_empty())
+}
+
+/// None when `CORS_ORIGINS` is unset: CORS stays off
+pub fn from_env() -> Result<Option<CorsLayer>, String> {
+    let Ok(origins) = std::env::var("CORS_ORIGINS") else {
+        return Ok(None);
+    };
+
+    let allow_origin = if origins.trim() == "*" {
+        AllowOrigin::any()
+    } else {
+        let list = entries(&origins)
+            .map(|origin| {
+                // The browser sends scheme://host[:port] and compares exactly
+                if !(origin.starts_with("http://") || origin.starts_with("https://"))
+                    || origin.ends_with('/')
+                {
+                    return Err(format!(
+                        "CORS origin '{}' must look like https://host[:port]",
+                        origin
+                    ));
+                }
+                HeaderValue::from_str(origin)
+                    .map_err(|_| format!("CORS origin '{}' is not a valid header value", origin))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if list.is_empty() {
+            return Err("CORS_ORIGINS is set but lists no origin".to_string());
+        }
+        AllowOrigin::list(list)
+    };
+
+    let methods = entries(&var_or("CORS_METHODS", DEFAULT_METHODS))
+        .map(|m| {
+            Method::from_bytes(m.to_ascii_uppercase().as_bytes())
+                .map_err(|_| format!("CORS method '{}' is not a valid method", m))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let headers = entries(&var_or("CORS_HEADERS", DEFAULT_HEADERS))
+        .map(|h| {
+            HeaderName::from_bytes(h.as_bytes())
+                .map_err(|_| format!("CORS header '{}' is not a valid header name", h))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let max_age = match std::env::var("CORS_MAX_AGE") {
+        Ok(secs) => secs
+            .trim()
+            .parse()
+            .map_err(|_| format!("CORS_MAX_AGE must be a number of seconds, got '{}'", secs))?,
+        Err(_) => DEFAULT_MAX_AGE,
+    };
+
+    Ok(Some(
+        CorsLayer::new()
+            .allow_origin(allow_origin)
+            .allow_methods(methods)
+            .allow_headers(headers)
+            // Scripts can only read response headers that are listed here
+            .expose_headers([header::ETAG, HeaderName::from_static("x-request-id")])
+            .max_age(Duration::from_secs(max_age)),
+    ))
+}
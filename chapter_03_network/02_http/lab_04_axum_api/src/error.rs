@@ -0,0 +1,194 @@
+//! One error type for every failure the API can report
+//!
+//! Whatever goes wrong - a missing item, a bad field, malformed JSON, an
+//! unknown route, a store failure, even a panic - the client gets the same
+//! JSON shape:
+//!
+//! ```json
+//! {"error": "item 7 not found", "code": "not_found"}
+//! ```
+//!
+//! `error` is for humans and may change; `code` is for programs and doesn't.
+//! Inside a request the body also carries `request_id`, the same id as the
+//! `X-Request-Id` header and the log lines.
+
+use axum::{
+    extract::rejection::{JsonRejection, PathRejection, QueryRejection},
+    extract::{FromRequest, FromRequestParts},
+    http::{header, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use std::any::Any;
+
+use crate::batch::OpResult;
+use crate::middleware::current_request_id;
+use crate::store::StoreError;
+
+/// One invalid field in a request body
+#[derive(Serialize)]
+pub struct FieldError {
+    pub field: &'static str,
+    pub message: &'static str,
+}
+
+/// Errors returned by the handlers
+pub enum AppError {
+    NotFound(String),
+    Validation(Vec<FieldError>),
+    /// The request could not be parsed (bad JSON, path or query string);
+    /// keeps the status axum chose (400, 415, 422, ...)
+    BadRequest(StatusCode, String),
+    /// No usable credentials: missing, malformed or unknown
+    Unauthorized(String),
+    /// Valid credentials, not allowed to do this
+    Forbidden(String),
+    /// If-Match did not match the current ETag
+    PreconditionFailed(String),
+    /// A write that needs If-Match came without one
+    PreconditionRequired(String),
+    /// A batch was rejected as a whole; one result per operation says why
+    BatchFailed(StatusCode, String, Vec<OpResult>),
+    MethodNotAllowed,
+    Store(StoreError),
+    Panic,
+}
+
+impl AppError {
+    fn status(&self) -> StatusCode {
+        match self {
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::BadRequest(status, _) => *status,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::PreconditionFailed(_) => StatusCode::PRECONDITION_FAILED,
+            AppError::PreconditionRequired(_) => StatusCode::PRECONDITION_REQUIRED,
+            AppError::BatchFailed(status, ..) => *status,
+            AppError::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            AppError::Store(_) | AppError::Panic => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            AppError::NotFound(_) => "not_found",
+            AppError::Validation(_) => "validation_failed",
+            AppError::BadRequest(..) => "bad_request",
+            AppError::Unauthorized(_) => "unauthorized",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::PreconditionFailed(_) => "precondition_failed",
+            AppError::PreconditionRequired(_) => "precondition_required",
+            AppError::BatchFailed(..) => "batch_failed",
+            AppError::MethodNotAllowed => "method_not_allowed",
+            AppError::Store(_) | AppError::Panic => "internal_error",
+        }
+    }
+}
+
+impl From<StoreError> for AppError {
+    fn from(e: StoreError) -> Self {
+        AppError::Store(e)
+    }
+}
+
+// axum's own rejections answer with plain text; rewrap them
+impl From<JsonRejection> for AppError {
+    fn from(rejection: JsonRejection) -> Self {
+        AppError::BadRequest(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<PathRejection> for AppError {
+    fn from(rejection: PathRejection) -> Self {
+        AppError::BadRequest(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for AppError {
+    fn from(rejection: QueryRejection) -> Self {
+        AppError::BadRequest(rejection.status(), rejection.body_text())
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        let code = self.code();
+        let mut body = match self {
+            AppError::NotFound(message)
+            | AppError::BadRequest(_, message)
+            | AppError::Unauthorized(message)
+            | AppError::Forbidden(message)
+            | AppError::PreconditionFailed(message)
+            | AppError::PreconditionRequired(message) => {
+                serde_json::json!({ "error": message, "code": code })
+            }
+            AppError::Validation(fields) => serde_json::json!({
+                "error": "validation failed",
+                "code": code,
+                "fields": fields,
+            }),
+            AppError::BatchFailed(_, message, results) => serde_json::json!({
+                "error": message,
+                "code": code,
+                "results": results,
+            }),
+            AppError::MethodNotAllowed => {
+                serde_json::json!({ "error": "method not allowed", "code": code })
+            }
+            AppError::Store(e) => {
+                // Log the details, don't leak them to the client
+                tracing::error!(error = %e, "store error");
+                serde_json::json!({ "error": "internal error", "code": code })
+            }
+            AppError::Panic => serde_json::json!({ "error": "internal error", "code": code }),
+        };
+        if let Some(id) = current_request_id() {
+            body["request_id"] = id.into();
+        }
+
+        if status == StatusCode::UNAUTHORIZED {
+            // RFC 9110: a 401 must say which scheme would work
+            return (status, [(header::WWW_AUTHENTICATE, "Bearer")], Json(body)).into_response();
+        }
+        (status, Json(body)).into_response()
+    }
+}
+
+/// `Json` whose rejection is an AppError
+#[derive(FromRequest)]
+#[from_request(via(axum::Json), rejection(AppError))]
+pub struct AppJson<T>(pub T);
+
+/// `Path` whose rejection is an AppError
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Path), rejection(AppError))]
+pub struct AppPath<T>(pub T);
+
+/// `Query` whose rejection is an AppError
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Query), rejection(AppError))]
+pub struct AppQuery<T>(pub T);
+
+/// Router fallback: no route matched the path
+pub async fn route_not_found(method: Method, uri: Uri) -> AppError {
+    AppError::NotFound(format!("no route for {} {}", method, uri.path()))
+}
+
+/// The path exists but not with this method
+pub async fn method_not_allowed() -> AppError {
+    AppError::MethodNotAllowed
+}
+
+/// CatchPanicLayer callback: the task survived, the request gets a 500
+pub fn handle_panic(panic: Box<dyn Any + Send + 'static>) -> Response {
+    let message = panic
+        .downcast_ref::<String>()
+        .map(String::as_str)
+        .or_else(|| panic.downcast_ref::<&str>().copied())
+        .unwrap_or("unknown panic");
+    tracing::error!(panic = message, "handler panicked");
+    AppError::Panic.into_response()
+}
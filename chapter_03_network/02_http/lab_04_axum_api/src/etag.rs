@@ -0,0 +1,73 @@
+//! Entity tags for conditional requests
+//!
+//! An item's ETag is a hash of its JSON, so it changes exactly when the
+//! item does:
+//!
+//! ```text
+//! GET /items/1                           -> 200, ETag: "3f9a..."
+//! GET /items/1  If-None-Match: "3f9a..."  -> 304, no body (cache still fresh)
+//! PUT /items/1  If-Match: "3f9a..."       -> 200, new ETag
+//! PUT /items/1  If-Match: "3f9a..."       -> 412 (someone changed it first)
+//! PUT /items/1  (no If-Match)             -> 428
+//! ```
+//!
+//! The last two are optimistic concurrency: a client may only overwrite the
+//! version it has seen, so two clients can't silently undo each other.
+
+use axum::http::{header, HeaderMap, HeaderValue};
+use sha2::{Digest, Sha256};
+
+use crate::store::Item;
+
+/// Strong ETag of an item: the first 64 bits of SHA-256 over its JSON
+pub fn of(item: &Item) -> String {
+    let json = serde_json::to_vec(item).expect("an item always serializes");
+    let digest = Sha256::digest(&json);
+    let hex: String = digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("\"{}\"", hex)
+}
+
+/// `ETag` header for a response
+pub fn header(etag: &str) -> [(header::HeaderName, HeaderValue); 1] {
+    [(
+        header::ETAG,
+        HeaderValue::from_str(etag).expect("hex is a valid header value"),
+    )]
+}
+
+/// Does a list header like `If-None-Match: "a", W/"b"` contain `etag`?
+///
+/// `*` matches anything. `W/` is ignored: If-None-Match uses the weak
+/// comparison (RFC 9110 13.1.2), and this server only hands out strong tags.
+fn list_contains(value: &HeaderValue, etag: &str) -> bool {
+    let Ok(value) = value.to_str() else {
+        return false;
+    };
+    value
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag)
+}
+
+/// True when the client's cached copy is still current
+pub fn none_match(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get(header::IF_NONE_MATCH)
+        .is_some_and(|value| list_contains(value, etag))
+}
+
+/// The raw If-Match header, if the client sent one
+pub fn if_match(headers: &HeaderMap) -> Option<&HeaderValue> {
+    headers.get(header::IF_MATCH)
+}
+
+/// If-Match uses the strong comparison: a weak tag never matches
+pub fn matches(if_match: &HeaderValue, etag: &str) -> bool {
+    let Ok(value) = if_match.to_str() else {
+        return false;
+    };
+    value
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag == etag)
+}
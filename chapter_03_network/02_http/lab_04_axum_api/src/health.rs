@@ -0,0 +1,113 @@
+//! Health probes and graceful shutdown
+//!
+//! - `GET /healthz` (liveness): 200 as long as the process can answer
+//! - `GET /readyz` (readiness): 200 while accepting traffic, 503 once
+//!   shutdown has started
+//!
+//! On Ctrl-C or SIGTERM the server drains instead of dying mid-request:
+//!
+//! ```text
+//! signal -> readyz says 503 -> wait DRAIN_DELAY_MS (load balancer notices)
+//!        -> stop accepting -> finish in-flight requests -> exit 0
+//! ```
+//!
+//! Requests still running after `SHUTDOWN_TIMEOUT_MS` are cut off (exit 1).
+
+use axum::{extract::State, http::StatusCode, Json};
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::sleep;
+
+/// Whether the server should still get new traffic
+pub struct Health {
+    ready: AtomicBool,
+}
+
+impl Health {
+    pub fn new() -> Self {
+        Self {
+            ready: AtomicBool::new(true),
+        }
+    }
+}
+
+/// GET /healthz - the process is alive
+pub async fn healthz() -> Json<Value> {
+    Json(json!({ "status": "ok" }))
+}
+
+/// GET /readyz - send traffic here?
+pub async fn readyz(State(health): State<Arc<Health>>) -> (StatusCode, Json<Value>) {
+    if health.ready.load(Ordering::SeqCst) {
+        (StatusCode::OK, Json(json!({ "status": "ready" })))
+    } else {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({ "status": "draining" })),
+        )
+    }
+}
+
+/// Milliseconds from an env var, or the default
+fn millis_from_env(name: &str, default: u64) -> Duration {
+    Duration::from_millis(
+        std::env::var(name)
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(default),
+    )
+}
+
+/// Resolves when Ctrl-C or SIGTERM arrives
+async fn signal() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+/// Future for `with_graceful_shutdown`: axum stops accepting when it
+/// resolves, then waits for the open requests
+pub async fn shutdown_signal(health: Arc<Health>) {
+    signal().await;
+
+    let drain_delay = millis_from_env("DRAIN_DELAY_MS", 0);
+    let timeout = millis_from_env("SHUTDOWN_TIMEOUT_MS", 30_000);
+    health.ready.store(false, Ordering::SeqCst);
+    tracing::info!(
+        drain_delay_ms = drain_delay.as_millis() as u64,
+        "shutdown started, readiness off"
+    );
+
+    // Keep accepting for a moment: whoever routes traffic here polls
+    // /readyz and needs time to notice before connections are refused
+    sleep(drain_delay).await;
+
+    tokio::spawn(async move {
+        sleep(timeout).await;
+        tracing::error!(
+            timeout_ms = timeout.as_millis() as u64,
+            "in-flight requests did not finish in time"
+        );
+        std::process::exit(1);
+    });
+    tracing::info!("no longer accepting connections, draining");
+}
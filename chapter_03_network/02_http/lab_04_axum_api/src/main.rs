@@ -52,143 +52,490 @@
 //! - [ ] Returns JSON with correct Content-Type
 //! - [ ] Uses proper status codes (200, 201, 404, etc.)
 //! - [ ] Handles concurrent requests safely
+//!
+//! ## Extension: PATCH + Validation
+//! - `PATCH /items/:id` updates only the fields present in the body
+//! - POST/PUT/PATCH reject an empty name or a negative price with
+//!   `422 Unprocessable Entity` and a body listing every invalid field
+//! - Optional first argument: port (default 8080)
+//! ```bash
+//! curl -X PATCH -H "Content-Type: application/json" \
+//!   -d '{"price":4.99}' http://localhost:8080/items/1
+//! # Returns: {"id":1,"name":"Widget","price":4.99}
+//!
+//! curl -X POST -H "Content-Type: application/json" \
+//!   -d '{"name":"","price":-1}' http://localhost:8080/items
+//! # 422: {"error":"validation failed","code":"validation_failed","fields":[{"field":"name","message":"must not be empty"},
+//! #       {"field":"price","message":"must not be negative"}]}
+//! ```
+//!
+//! ## Extension: Swappable Store
+//! - Handlers talk to an `ItemStore` trait (list/get/create/update/delete),
+//!   see `src/store.rs`
+//! - `ITEM_STORE=memory` (default): `RwLock<HashMap>`, readers don't block each other
+//! - `ITEM_STORE=sqlite`: sqlx pool on `DATABASE_URL` (default `sqlite:items.db`),
+//!   items survive a restart
+//! - Backend errors become `500 {"error":"internal error"}`
+//! ```bash
+//! ITEM_STORE=sqlite DATABASE_URL=sqlite:/tmp/items.db cargo run
+//! ```
+//!
+//! ## Extension: Pagination, Sorting, Filtering
+//! - `GET /items?page=2&limit=10&sort=price&min_price=1&max_price=50`
+//! - `page` >= 1 (default 1), `limit` 1..=100 (default 20),
+//!   `sort` = `name` | `price` (default: id), price bounds are inclusive
+//! - The response is an envelope; `total` counts every match, not just this page
+//! - Out-of-range values are a 422 with the same body as other validation errors
+//! ```bash
+//! curl 'http://localhost:8080/items?sort=price&max_price=10&limit=2'
+//! # {"items":[...],"page":1,"limit":2,"total":5}
+//! ```
+//!
+//! ## Extension: JSON Errors Everywhere
+//! - Every error is `{"error": "...", "code": "..."}` (see `src/error.rs`);
+//!   validation errors add `fields`
+//! - Codes: `not_found`, `validation_failed`, `bad_request`,
+//!   `method_not_allowed`, `internal_error`
+//! - Malformed JSON/path/query, unknown routes and wrong methods are
+//!   covered too, not just handler errors
+//! - A panicking handler becomes a 500 instead of a dropped connection
+//!   (`ENABLE_PANIC_ROUTE=1` adds `GET /debug/panic` to try it)
+//! ```bash
+//! curl http://localhost:8080/items/7
+//! # 404: {"error":"item 7 not found","code":"not_found"}
+//! curl http://localhost:8080/nope
+//! # 404: {"error":"no route for GET /nope","code":"not_found"}
+//! ```
+//!
+//! ## Extension: Request IDs + Access Logs
+//! - Every response has an `X-Request-Id` header: the one the client sent,
+//!   or a new UUID (see `src/middleware.rs`)
+//! - One JSON log line per request on stdout with `method`, `path`,
+//!   `status`, `latency_ms` and the `request_id`; `RUST_LOG` sets the level
+//! - Error bodies include the same `request_id`
+//! ```bash
+//! curl -i -H 'X-Request-Id: abc-123' http://localhost:8080/items/7
+//! # x-request-id: abc-123
+//! # 404: {"error":"item 7 not found","code":"not_found","request_id":"abc-123"}
+//! # log: {"level":"INFO","message":"request completed","status":404,"latency_ms":0.21,
+//! #       "span":{"method":"GET","path":"/items/7","request_id":"abc-123","name":"request"},...}
+//! ```
+//!
+//! ## Extension: API Keys
+//! - `API_KEYS="name:scope:key,..."` or `API_KEYS_FILE=keys.txt` (one entry
+//!   per line) turns authentication on (see `src/auth.rs`); scope is `read` or `write`
+//! - POST/PUT/PATCH/DELETE need `Authorization: Bearer <write key>`:
+//!   `401 unauthorized` without a valid key, `403 forbidden` with a read key
+//! - Reads stay open; `GET /stats` needs a key and shows per-key counters
+//! ```bash
+//! API_KEYS="admin:write:s3cret,dash:read:r34d" cargo run
+//! curl -X DELETE http://localhost:8080/items/1
+//! # 401: {"error":"missing bearer token","code":"unauthorized",...}
+//! curl -X DELETE -H 'Authorization: Bearer r34d' http://localhost:8080/items/1
+//! # 403: {"error":"API key 'dash' is read-only","code":"forbidden",...}
+//! curl -H 'Authorization: Bearer r34d' http://localhost:8080/stats
+//! # {"auth_enabled":true,"keys":[{"name":"admin","scope":"write","requests":0,"denied":0},
+//! #   {"name":"dash","scope":"read","requests":1,"denied":1}],"anonymous":0,"rejected":1}
+//! ```
+//!
+//! ## Extension: Health Probes + Graceful Shutdown
+//! - `GET /healthz`: 200 `{"status":"ok"}` while the process runs
+//! - `GET /readyz`: 200 `{"status":"ready"}`, or 503 `{"status":"draining"}`
+//!   once shutdown has started (see `src/health.rs`)
+//! - Ctrl-C / SIGTERM: readiness goes off, the server keeps accepting for
+//!   `DRAIN_DELAY_MS` (default 0), then stops accepting and lets in-flight
+//!   requests finish; after `SHUTDOWN_TIMEOUT_MS` (default 30000) it gives up
+//! ```bash
+//! DRAIN_DELAY_MS=5000 cargo run &
+//! kill -TERM %1; curl -i http://localhost:8080/readyz
+//! # 503: {"status":"draining"}
+//! ```
+//!
+//! ## Extension: ETags + Conditional Requests
+//! - Single-item responses carry an `ETag`: a hash of the item's JSON
+//!   (see `src/etag.rs`)
+//! - `GET /items/:id` with a matching `If-None-Match` is a `304 Not Modified`
+//!   without a body
+//! - `PUT /items/:id` needs `If-Match` with the current ETag:
+//!   `428 precondition_required` without one, `412 precondition_failed` if
+//!   the item changed since (optimistic concurrency)
+//! ```bash
+//! curl -i http://localhost:8080/items/1
+//! # etag: "aa7b1eaf5c05dd34"
+//! curl -i -H 'If-None-Match: "aa7b1eaf5c05dd34"' http://localhost:8080/items/1
+//! # 304 Not Modified
+//! curl -X PUT -H 'If-Match: "aa7b1eaf5c05dd34"' -H "Content-Type: application/json" \
+//!   -d '{"name":"Super Widget","price":19.99}' http://localhost:8080/items/1
+//! # 200, new ETag; sending the same If-Match again is a 412
+//! ```
+//!
+//! ## Extension: CORS
+//! - Off unless `CORS_ORIGINS` is set (comma separated, or `*`); see `src/cors.rs`
+//! - `CORS_METHODS`, `CORS_HEADERS` and `CORS_MAX_AGE` tune what preflights
+//!   allow and how long browsers may cache the answer
+//! - `ETag` and `X-Request-Id` are exposed to scripts
+//! ```bash
+//! CORS_ORIGINS=http://localhost:5173 cargo run
+//! curl -i -X OPTIONS -H 'Origin: http://localhost:5173' \
+//!   -H 'Access-Control-Request-Method: PUT' http://localhost:8080/items/1
+//! # 200, access-control-allow-origin: http://localhost:5173
+//! #      access-control-allow-methods: GET,POST,PUT,PATCH,DELETE
+//! #      access-control-max-age: 600
+//! ```
+//!
+//! ## Extension: Batch Operations
+//! - `POST /items:batch` takes an array of `create` / `update` (partial) /
+//!   `delete` operations, up to 100, and applies them in order, all or nothing
+//!   (see `src/batch.rs`)
+//! - 200 with one result per operation: its status, the item and its ETag
+//! - If any operation is invalid (422) or names a missing item (404), nothing
+//!   is applied: `batch_failed` with per-operation results, the others `424`
+//! ```bash
+//! curl -X POST -H "Content-Type: application/json" http://localhost:8080/items:batch \
+//!   -d '[{"op":"create","name":"Gadget","price":5},{"op":"delete","id":1}]'
+//! # {"results":[{"status":201,"item":{"id":2,...},"etag":"..."},{"status":204,"id":1}]}
+//! ```
+
+mod auth;
+mod batch;
+mod cors;
+mod error;
+mod etag;
+mod health;
+mod middleware;
+mod store;
 
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    routing::{delete, get, post, put},
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
     Json, Router,
 };
+use error::{AppError, AppJson, AppPath, AppQuery, FieldError};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
+use store::{CreateItem, Item, ItemStore, ListQuery, SortKey, UpdateItem};
+use tower::ServiceBuilder;
+use tower_http::catch_panic::CatchPanicLayer;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+
+/// Most items one page may hold
+const MAX_PAGE_SIZE: u64 = 100;
 
 // ============================================================
 // TODO: Implement the REST API
 // ============================================================
 
-/// Item model
-#[derive(Clone, Serialize, Deserialize)]
-struct Item {
-    id: u64,
-    name: String,
-    price: f64,
+/// Check the fields that are present; collect every problem, not just the first
+fn validate(name: Option<&str>, price: Option<f64>) -> Result<(), AppError> {
+    let mut errors = Vec::new();
+    if name.is_some_and(|n| n.trim().is_empty()) {
+        errors.push(FieldError {
+            field: "name",
+            message: "must not be empty",
+        });
+    }
+    if price.is_some_and(|p| p < 0.0) {
+        errors.push(FieldError {
+            field: "price",
+            message: "must not be negative",
+        });
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::Validation(errors))
+    }
 }
 
-/// Request body for creating/updating items
+/// Query string of GET /items
 #[derive(Deserialize)]
-struct CreateItem {
-    name: String,
-    price: f64,
+struct ListParams {
+    page: Option<u64>,
+    limit: Option<u64>,
+    sort: Option<String>,
+    min_price: Option<f64>,
+    max_price: Option<f64>,
 }
 
-/// Application state
-struct AppState {
-    items: Mutex<HashMap<u64, Item>>,
-    next_id: Mutex<u64>,
+/// Response of GET /items
+#[derive(Serialize)]
+struct PageResponse {
+    items: Vec<Item>,
+    page: u64,
+    limit: u64,
+    total: u64,
 }
 
-// TODO: Implement handlers
+/// Turn the raw query string into a store query, collecting every problem
+fn list_query(params: &ListParams) -> Result<(u64, ListQuery), AppError> {
+    let mut errors = Vec::new();
+    let page = params.page.unwrap_or(1);
+    let limit = params.limit.unwrap_or(20);
+
+    if page == 0 {
+        errors.push(FieldError {
+            field: "page",
+            message: "must be at least 1",
+        });
+    }
+    if limit == 0 || limit > MAX_PAGE_SIZE {
+        errors.push(FieldError {
+            field: "limit",
+            message: "must be between 1 and 100",
+        });
+    }
+    let sort = match params.sort.as_deref() {
+        None => SortKey::Id,
+        Some("name") => SortKey::Name,
+        Some("price") => SortKey::Price,
+        Some(_) => {
+            errors.push(FieldError {
+                field: "sort",
+                message: "must be one of: name, price",
+            });
+            SortKey::Id
+        }
+    };
+    if let (Some(min), Some(max)) = (params.min_price, params.max_price) {
+        if min > max {
+            errors.push(FieldError {
+                field: "min_price",
+                message: "must not be greater than max_price",
+            });
+        }
+    }
 
-/// GET /items - List all items
-async fn list_items(State(state): State<Arc<AppState>>) -> Json<Vec<Item>> {
-    let items = state.items.lock().expect("items mutex poisoned");
-    let mut result: Vec<Item> = items.values().cloned().collect();
-    result.sort_by_key(|item| item.id);
-    Json(result)
+    if !errors.is_empty() {
+        return Err(AppError::Validation(errors));
+    }
+    let query = ListQuery {
+        // Saturate: a huge page number is simply past the end
+        offset: (page - 1).saturating_mul(limit),
+        limit,
+        sort,
+        min_price: params.min_price,
+        max_price: params.max_price,
+    };
+    Ok((page, query))
 }
 
-/// POST /items - Create new item
-async fn create_item(
-    State(state): State<Arc<AppState>>,
-    Json(payload): Json<CreateItem>,
-) -> (StatusCode, Json<Item>) {
-    let id = {
-        let mut next_id = state.next_id.lock().expect("next_id mutex poisoned");
-        let id = *next_id;
-        *next_id += 1;
+fn item_not_found(id: u64) -> AppError {
+    AppError::NotFound(format!("item {} not found", id))
+}
+
+fn item_changed(id: u64) -> AppError {
+    AppError::PreconditionFailed(format!(
+        "item {} has changed since you fetched it; GET it again",
         id
-    };
+    ))
+}
 
-    let item = Item {
-        id,
-        name: payload.name,
-        price: payload.price,
-    };
+/// A single item with its ETag header
+fn tagged(item: Item) -> impl IntoResponse {
+    (etag::header(&etag::of(&item)), Json(item))
+}
+
+/// Application state: any backend that implements ItemStore
+type AppState = Arc<dyn ItemStore>;
+
+// TODO: Implement handlers
 
-    let mut items = state.items.lock().expect("items mutex poisoned");
-    items.insert(id, item.clone());
+/// GET /items - List one page of items
+async fn list_items(
+    State(store): State<AppState>,
+    AppQuery(params): AppQuery<ListParams>,
+) -> Result<Json<PageResponse>, AppError> {
+    let (page, query) = list_query(&params)?;
+    let result = store.list(&query).await?;
+
+    Ok(Json(PageResponse {
+        items: result.items,
+        page,
+        limit: query.limit,
+        total: result.total,
+    }))
+}
+
+/// POST /items - Create new item
+async fn create_item(
+    State(store): State<AppState>,
+    AppJson(payload): AppJson<CreateItem>,
+) -> Result<impl IntoResponse, AppError> {
+    validate(Some(&payload.name), Some(payload.price))?;
 
-    (StatusCode::CREATED, Json(item))
+    let item = store.create(payload).await?;
+    Ok((StatusCode::CREATED, tagged(item)))
 }
 
-/// GET /items/:id - Get single item
+/// GET /items/:id - Get single item; 304 if the client's copy is current
 async fn get_item(
-    State(state): State<Arc<AppState>>,
-    Path(id): Path<u64>,
-) -> Result<Json<Item>, StatusCode> {
-    let items = state.items.lock().expect("items mutex poisoned");
-    items
-        .get(&id)
-        .cloned()
-        .map(Json)
-        .ok_or(StatusCode::NOT_FOUND)
+    State(store): State<AppState>,
+    AppPath(id): AppPath<u64>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let item = store.get(id).await?.ok_or_else(|| item_not_found(id))?;
+
+    let etag = etag::of(&item);
+    if etag::none_match(&headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, etag::header(&etag)).into_response());
+    }
+    Ok(tagged(item).into_response())
 }
 
-/// PUT /items/:id - Update item
+/// PUT /items/:id - Replace item; needs If-Match with the current ETag
 async fn update_item(
-    State(state): State<Arc<AppState>>,
-    Path(id): Path<u64>,
-    Json(payload): Json<CreateItem>,
-) -> Result<Json<Item>, StatusCode> {
-    // todo!("Implement update_item")
-    let mut items = state.items.lock().expect("items mutex poisoned");
-    if !items.contains_key(&id) {
-        return Err(StatusCode::NOT_FOUND);
+    State(store): State<AppState>,
+    AppPath(id): AppPath<u64>,
+    headers: HeaderMap,
+    AppJson(payload): AppJson<CreateItem>,
+) -> Result<impl IntoResponse, AppError> {
+    validate(Some(&payload.name), Some(payload.price))?;
+    let if_match = etag::if_match(&headers).ok_or_else(|| {
+        AppError::PreconditionRequired("PUT needs If-Match with the item's ETag".to_string())
+    })?;
+
+    let current = store.get(id).await?.ok_or_else(|| item_not_found(id))?;
+    if !etag::matches(if_match, &etag::of(&current)) {
+        return Err(item_changed(id));
     }
-    let item = Item {
-        id,
-        name: payload.name,
-        price: payload.price,
-    };
-    items.insert(id, item.clone());
+    // Another PUT may land between the check above and this write;
+    // update_if catches that
+    store
+        .update_if(id, &current, payload.into())
+        .await?
+        .map(tagged)
+        .ok_or_else(|| item_changed(id))
+}
+
+/// PATCH /items/:id - Update only the given fields
+async fn patch_item(
+    State(store): State<AppState>,
+    AppPath(id): AppPath<u64>,
+    AppJson(payload): AppJson<UpdateItem>,
+) -> Result<impl IntoResponse, AppError> {
+    validate(payload.name.as_deref(), payload.price)?;
 
-    Ok(Json(item))
+    store
+        .update(id, payload)
+        .await?
+        .map(tagged)
+        .ok_or_else(|| item_not_found(id))
 }
 
 /// DELETE /items/:id - Delete item
-async fn delete_item(State(state): State<Arc<AppState>>, Path(id): Path<u64>) -> StatusCode {
-    let mut items = state.items.lock().expect("items mutex poisoned");
-    match items.remove(&id) {
-        Some(_) => StatusCode::NO_CONTENT,
-        None => StatusCode::NOT_FOUND,
+async fn delete_item(
+    State(store): State<AppState>,
+    AppPath(id): AppPath<u64>,
+) -> Result<StatusCode, AppError> {
+    if store.delete(id).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(item_not_found(id))
     }
 }
 
+/// GET /debug/panic - only routed when ENABLE_PANIC_ROUTE=1
+async fn panic_handler() -> StatusCode {
+    panic!("panic route hit")
+}
+
 #[tokio::main]
 async fn main() {
+    middleware::init_tracing();
+
     // TODO: Implement
     // 1. Create AppState
-    let state = Arc::new(AppState {
-        items: Mutex::new(HashMap::new()),
-        next_id: Mutex::new(1),
-    });
+    let state = match store::from_env().await {
+        Ok(store) => store,
+        Err(e) => {
+            eprintln!("cannot open item store: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let auth = match auth::Auth::from_env() {
+        Ok(auth) => Arc::new(auth),
+        Err(e) => {
+            eprintln!("invalid API key config: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if !auth.enabled() {
+        tracing::warn!("no API keys configured, authentication is off");
+    }
+
+    let cors = match cors::from_env() {
+        Ok(cors) => cors,
+        Err(e) => {
+            eprintln!("invalid CORS config: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let health = Arc::new(health::Health::new());
 
     // 2. Build router with routes
 
-    let app = Router::new()
+    let mut app = Router::new()
         .route("/items", get(list_items).post(create_item))
         .route(
-            "/items/:id",
-            get(get_item).put(update_item).delete(delete_item),
+            "/items:action",
+            post(batch::apply).fallback(batch::other_method),
         )
-        .with_state(state);
+        .route(
+            "/items/:id",
+            get(get_item)
+                .put(update_item)
+                .patch(patch_item)
+                .delete(delete_item),
+        );
+    // A deliberately broken route, to see the panic layer at work
+    if std::env::var("ENABLE_PANIC_ROUTE").is_ok_and(|v| v == "1") {
+        app = app.route("/debug/panic", get(panic_handler));
+    }
+    let app = app
+        .with_state(state)
+        // /stats reads the key counters, not the store: it gets its own state
+        .route("/stats", get(auth::stats))
+        .with_state(auth.clone())
+        .route("/healthz", get(health::healthz))
+        .route("/readyz", get(health::readyz))
+        .with_state(health.clone())
+        .fallback(error::route_not_found)
+        .method_not_allowed_fallback(error::method_not_allowed)
+        // Top to bottom = outermost to innermost. The panic layer sits inside
+        // the logger, so a panic is still logged (as a 500) with its id.
+        // CORS answers preflights before auth sees them, and adds its headers
+        // to auth's 401/403 too, so the page can read why it was refused.
+        .layer(
+            ServiceBuilder::new()
+                .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+                .layer(PropagateRequestIdLayer::x_request_id())
+                .layer(axum::middleware::from_fn(middleware::log_requests))
+                .option_layer(cors)
+                .layer(axum::middleware::from_fn_with_state(
+                    auth,
+                    auth::require_key,
+                ))
+                .layer(CatchPanicLayer::custom(error::handle_panic)),
+        );
     // 3. Run server
 
-    let addr = "0.0.0:8080";
-    let listener = tokio::net::TcpListener::bind(addr)
+    let port: u16 = std::env::args()
+        .nth(1)
+        .and_then(|p| p.parse().ok())
+        .unwrap_or(8080);
+    let addr = format!("0.0.0.0:{port}");
+    let listener = tokio::net::TcpListener::bind(&addr)
         .await
         .expect("failed to bind address");
-    println!("Listening on http://{addr}");
-    axum::serve(listener, app).await.expect("server error");
+    tracing::info!(%addr, "listening");
+    axum::serve(listener, app)
+        .with_graceful_shutdown(health::shutdown_signal(health))
+        .await
+        .expect("server error");
+    tracing::info!("shutdown complete");
 }
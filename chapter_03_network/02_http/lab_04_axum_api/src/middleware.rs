@@ -0,0 +1,78 @@
+//! Request IDs and access logs
+//!
+//! Every request gets an `X-Request-Id`: the client's own if it sent one,
+//! a fresh UUID otherwise. The same id is
+//!
+//! - echoed in the response headers,
+//! - attached to every log line written while the request runs,
+//! - included in JSON error bodies (`"request_id"`),
+//!
+//! so a user reporting "I got a 500" can hand over one string that finds
+//! the matching log lines.
+//!
+//! ```text
+//! SetRequestId -> PropagateRequestId -> log_requests -> CatchPanic -> router
+//! ```
+
+use axum::{extract::Request, middleware::Next, response::Response};
+use std::time::Instant;
+use tracing::{info, info_span, Instrument};
+
+tokio::task_local! {
+    /// Id of the request the current task is serving
+    static REQUEST_ID: String;
+}
+
+/// Id of the request being handled, if called from inside one
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// Log method, path, status and latency of each request, inside a span that
+/// carries the request id.
+///
+/// Expects `SetRequestIdLayer` to run first, so the header is always there.
+pub async fn log_requests(request: Request, next: Next) -> Response {
+    let request_id = request
+        .headers()
+        .get("x-request-id")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("-")
+        .to_string();
+    let span = info_span!(
+        "request",
+        %request_id,
+        method = %request.method(),
+        path = %request.uri().path(),
+    );
+
+    // The task-local is how AppError finds the id without every handler
+    // having to pass it along
+    REQUEST_ID
+        .scope(request_id, async move {
+            let start = Instant::now();
+            let response = next.run(request).await;
+            info!(
+                status = response.status().as_u16(),
+                latency_ms = start.elapsed().as_secs_f64() * 1000.0,
+                "request completed"
+            );
+            response
+        })
+        .instrument(span)
+        .await
+}
+
+/// JSON logs on stdout, level from `RUST_LOG` (default `info`)
+pub fn init_tracing() {
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info"));
+
+    tracing_subscriber::fmt()
+        .json()
+        .with_env_filter(filter)
+        .with_current_span(true)
+        .with_span_list(false)
+        .flatten_event(true)
+        .init();
+}
@@ -0,0 +1,530 @@
+//! Item persistence behind one trait
+//!
+//! Handlers only see `Arc<dyn ItemStore>`, so the backend is picked once at
+//! startup and can change without touching any handler:
+//!
+//! - `memory`: `RwLock<HashMap>` - reads run in parallel, gone on restart
+//! - `sqlite`: sqlx connection pool - survives restarts
+//!
+//! ```bash
+//! ITEM_STORE=memory cargo run
+//! ITEM_STORE=sqlite DATABASE_URL=sqlite:items.db cargo run
+//! ```
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::{
+    SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions, SqliteSynchronous,
+};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+/// Item model
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Item {
+    pub id: u64,
+    pub name: String,
+    pub price: f64,
+}
+
+/// Request body for creating/updating items
+#[derive(Deserialize)]
+pub struct CreateItem {
+    pub name: String,
+    pub price: f64,
+}
+
+/// Request body for partial updates: absent fields are left unchanged
+#[derive(Deserialize)]
+pub struct UpdateItem {
+    pub name: Option<String>,
+    pub price: Option<f64>,
+}
+
+impl UpdateItem {
+    fn apply(self, item: &mut Item) {
+        if let Some(name) = self.name {
+            item.name = name;
+        }
+        if let Some(price) = self.price {
+            item.price = price;
+        }
+    }
+}
+
+impl From<CreateItem> for UpdateItem {
+    /// A full replacement is a partial update that sets every field
+    fn from(item: CreateItem) -> Self {
+        Self {
+            name: Some(item.name),
+            price: Some(item.price),
+        }
+    }
+}
+
+/// One write in a `POST /items:batch` body, tagged by `op`:
+///
+/// ```json
+/// {"op":"create","name":"Widget","price":9.99}
+/// {"op":"update","id":1,"price":4.99}
+/// {"op":"delete","id":2}
+/// ```
+#[derive(Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum BatchOp {
+    Create {
+        name: String,
+        price: f64,
+    },
+    /// Partial, like PATCH
+    Update {
+        id: u64,
+        name: Option<String>,
+        price: Option<f64>,
+    },
+    Delete {
+        id: u64,
+    },
+}
+
+/// What one operation of a committed batch did
+pub enum Applied {
+    Created(Item),
+    Updated(Item),
+    Deleted(u64),
+}
+
+/// A batch is all or nothing: either every operation was applied, or the
+/// one at `index` named a missing item and none were
+pub enum BatchOutcome {
+    Applied(Vec<Applied>),
+    NotFound { index: usize, id: u64 },
+}
+
+/// Column to order a listing by; ties are broken by id so pages are stable
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SortKey {
+    Id,
+    Name,
+    Price,
+}
+
+impl SortKey {
+    /// Column name for ORDER BY. Placeholders can't stand in for column
+    /// names, so SQL gets one of these fixed strings, never user input.
+    fn column(self) -> &'static str {
+        match self {
+            SortKey::Id => "id",
+            SortKey::Name => "name",
+            SortKey::Price => "price",
+        }
+    }
+}
+
+/// Which slice of the items to return, already validated
+pub struct ListQuery {
+    pub offset: u64,
+    pub limit: u64,
+    pub sort: SortKey,
+    pub min_price: Option<f64>,
+    pub max_price: Option<f64>,
+}
+
+impl ListQuery {
+    fn matches(&self, item: &Item) -> bool {
+        self.min_price.is_none_or(|min| item.price >= min)
+            && self.max_price.is_none_or(|max| item.price <= max)
+    }
+}
+
+/// One page of a listing plus the number of items matching the filter
+pub struct Page {
+    pub items: Vec<Item>,
+    pub total: u64,
+}
+
+/// A backend failure (I/O, SQL, ...). "Not found" is not an error: the
+/// methods return None/false for it.
+#[derive(Debug)]
+pub struct StoreError(pub String);
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<sqlx::Error> for StoreError {
+    fn from(e: sqlx::Error) -> Self {
+        StoreError(e.to_string())
+    }
+}
+
+/// `async_trait` boxes the returned futures, which is what makes the trait
+/// usable as `dyn ItemStore`
+#[async_trait]
+pub trait ItemStore: Send + Sync {
+    /// Filter, sort, then cut out one page
+    async fn list(&self, query: &ListQuery) -> Result<Page, StoreError>;
+    async fn get(&self, id: u64) -> Result<Option<Item>, StoreError>;
+    async fn create(&self, item: CreateItem) -> Result<Item, StoreError>;
+    /// Apply the fields that are present; None if the id does not exist
+    async fn update(&self, id: u64, changes: UpdateItem) -> Result<Option<Item>, StoreError>;
+    /// Compare-and-swap: update only if the stored item still equals
+    /// `expected`; None if it changed or is gone
+    async fn update_if(
+        &self,
+        id: u64,
+        expected: &Item,
+        changes: UpdateItem,
+    ) -> Result<Option<Item>, StoreError>;
+    /// True if an item was removed
+    async fn delete(&self, id: u64) -> Result<bool, StoreError>;
+    /// Apply every operation in order, atomically: other requests see the
+    /// store before or after the batch, never halfway
+    async fn apply_batch(&self, ops: Vec<BatchOp>) -> Result<BatchOutcome, StoreError>;
+}
+
+/// In-memory store: many readers or one writer at a time
+pub struct MemoryStore {
+    items: RwLock<HashMap<u64, Item>>,
+    next_id: AtomicU64,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self {
+            items: RwLock::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+        }
+    }
+}
+
+#[async_trait]
+impl ItemStore for MemoryStore {
+    async fn list(&self, query: &ListQuery) -> Result<Page, StoreError> {
+        let items = self.items.read().await;
+        let mut matching: Vec<&Item> = items.values().filter(|item| query.matches(item)).collect();
+        matching.sort_by(|a, b| {
+            let by_key = match query.sort {
+                SortKey::Id => std::cmp::Ordering::Equal,
+                SortKey::Name => a.name.cmp(&b.name),
+                SortKey::Price => a.price.total_cmp(&b.price),
+            };
+            by_key.then(a.id.cmp(&b.id))
+        });
+
+        Ok(Page {
+            total: matching.len() as u64,
+            items: matching
+                .into_iter()
+                .skip(usize::try_from(query.offset).unwrap_or(usize::MAX))
+                .take(usize::try_from(query.limit).unwrap_or(usize::MAX))
+                .cloned()
+                .collect(),
+        })
+    }
+
+    async fn get(&self, id: u64) -> Result<Option<Item>, StoreError> {
+        Ok(self.items.read().await.get(&id).cloned())
+    }
+
+    async fn create(&self, item: CreateItem) -> Result<Item, StoreError> {
+        let item = Item {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            name: item.name,
+            price: item.price,
+        };
+        self.items.write().await.insert(item.id, item.clone());
+        Ok(item)
+    }
+
+    async fn update(&self, id: u64, changes: UpdateItem) -> Result<Option<Item>, StoreError> {
+        let mut items = self.items.write().await;
+        let Some(item) = items.get_mut(&id) else {
+            return Ok(None);
+        };
+        changes.apply(item);
+        Ok(Some(item.clone()))
+    }
+
+    async fn update_if(
+        &self,
+        id: u64,
+        expected: &Item,
+        changes: UpdateItem,
+    ) -> Result<Option<Item>, StoreError> {
+        // Compare and write under the same lock, so nobody slips in between
+        let mut items = self.items.write().await;
+        match items.get_mut(&id) {
+            Some(item) if item == expected => {
+                changes.apply(item);
+                Ok(Some(item.clone()))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    async fn delete(&self, id: u64) -> Result<bool, StoreError> {
+        Ok(self.items.write().await.remove(&id).is_some())
+    }
+
+    async fn apply_batch(&self, ops: Vec<BatchOp>) -> Result<BatchOutcome, StoreError> {
+        // Work on a copy under the write lock; swap it in only if every
+        // operation succeeded. Ids taken by a failed batch are not reused.
+        let mut items = self.items.write().await;
+        let mut draft = items.clone();
+        let mut applied = Vec::with_capacity(ops.len());
+
+        for (index, op) in ops.into_iter().enumerate() {
+            match op {
+                BatchOp::Create { name, price } => {
+                    let item = Item {
+                        id: self.next_id.fetch_add(1, Ordering::Relaxed),
+                        name,
+                        price,
+                    };
+                    draft.insert(item.id, item.clone());
+                    applied.push(Applied::Created(item));
+                }
+                BatchOp::Update { id, name, price } => {
+                    let Some(item) = draft.get_mut(&id) else {
+                        return Ok(BatchOutcome::NotFound { index, id });
+                    };
+                    UpdateItem { name, price }.apply(item);
+                    applied.push(Applied::Updated(item.clone()));
+                }
+                BatchOp::Delete { id } => {
+                    if draft.remove(&id).is_none() {
+                        return Ok(BatchOutcome::NotFound { index, id });
+                    }
+                    applied.push(Applied::Deleted(id));
+                }
+            }
+        }
+
+        *items = draft;
+        Ok(BatchOutcome::Applied(applied))
+    }
+}
+
+/// SQLite store. SQLite has no unsigned integers, so ids cross the
+/// boundary as i64.
+pub struct SqliteStore {
+    pool: SqlitePool,
+}
+
+type ItemRow = (i64, String, f64);
+
+fn from_row((id, name, price): ItemRow) -> Item {
+    Item {
+        id: id as u64,
+        name,
+        price,
+    }
+}
+
+impl SqliteStore {
+    /// Open (creating if needed) the database and make sure the table exists
+    pub async fn connect(url: &str) -> Result<Self, StoreError> {
+        // WAL lets reads run while a write commits; see chapter_04 lab_05
+        let options = SqliteConnectOptions::from_str(url)?
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal)
+            .busy_timeout(Duration::from_secs(5))
+            .synchronous(SqliteSynchronous::Normal);
+        // Every connection to `sqlite::memory:` opens its own empty database
+        let max_connections = if url.contains(":memory:") { 1 } else { 5 };
+        let pool = SqlitePoolOptions::new()
+            .max_connections(max_connections)
+            .connect_with(options)
+            .await?;
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS items (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL,
+                price REAL NOT NULL
+            )",
+        )
+        .execute(&pool)
+        .await?;
+
+        Ok(Self { pool })
+    }
+}
+
+#[async_trait]
+impl ItemStore for SqliteStore {
+    async fn list(&self, query: &ListQuery) -> Result<Page, StoreError> {
+        // A NULL bound value switches its condition off
+        const FILTER: &str = "(?1 IS NULL OR price >= ?1) AND (?2 IS NULL OR price <= ?2)";
+
+        let (total,): (i64,) =
+            sqlx::query_as(&format!("SELECT COUNT(*) FROM items WHERE {}", FILTER))
+                .bind(query.min_price)
+                .bind(query.max_price)
+                .fetch_one(&self.pool)
+                .await?;
+
+        let sql = format!(
+            "SELECT id, name, price FROM items WHERE {} ORDER BY {}, id LIMIT ?3 OFFSET ?4",
+            FILTER,
+            query.sort.column()
+        );
+        // SQLite integers are i64: clamp absurdly large pages instead of wrapping
+        let rows: Vec<ItemRow> = sqlx::query_as(&sql)
+            .bind(query.min_price)
+            .bind(query.max_price)
+            .bind(i64::try_from(query.limit).unwrap_or(i64::MAX))
+            .bind(i64::try_from(query.offset).unwrap_or(i64::MAX))
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(Page {
+            items: rows.into_iter().map(from_row).collect(),
+            total: total as u64,
+        })
+    }
+
+    async fn get(&self, id: u64) -> Result<Option<Item>, StoreError> {
+        let row: Option<ItemRow> = sqlx::query_as("SELECT id, name, price FROM items WHERE id = ?")
+            .bind(id as i64)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(from_row))
+    }
+
+    async fn create(&self, item: CreateItem) -> Result<Item, StoreError> {
+        let row: ItemRow = sqlx::query_as(
+            "INSERT INTO items (name, price) VALUES (?, ?) RETURNING id, name, price",
+        )
+        .bind(item.name)
+        .bind(item.price)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(from_row(row))
+    }
+
+    async fn update(&self, id: u64, changes: UpdateItem) -> Result<Option<Item>, StoreError> {
+        // COALESCE keeps the current value when a field is NULL (absent).
+        // fetch_all, not fetch_optional: the latter stops stepping after the
+        // first row, and sqlx never finishes the UPDATE, so it is not committed.
+        let row: Option<ItemRow> = sqlx::query_as(
+            "UPDATE items SET name = COALESCE(?, name), price = COALESCE(?, price)
+             WHERE id = ? RETURNING id, name, price",
+        )
+        .bind(changes.name)
+        .bind(changes.price)
+        .bind(id as i64)
+        .fetch_all(&self.pool)
+        .await?
+        .pop();
+        Ok(row.map(from_row))
+    }
+
+    async fn update_if(
+        &self,
+        id: u64,
+        expected: &Item,
+        changes: UpdateItem,
+    ) -> Result<Option<Item>, StoreError> {
+        // The WHERE clause is the compare: if another writer got there
+        // first, no row matches and nothing is updated
+        let row: Option<ItemRow> = sqlx::query_as(
+            "UPDATE items SET name = COALESCE(?, name), price = COALESCE(?, price)
+             WHERE id = ? AND name = ? AND price = ? RETURNING id, name, price",
+        )
+        .bind(changes.name)
+        .bind(changes.price)
+        .bind(id as i64)
+        .bind(&expected.name)
+        .bind(expected.price)
+        .fetch_all(&self.pool)
+        .await?
+        .pop();
+        Ok(row.map(from_row))
+    }
+
+    async fn delete(&self, id: u64) -> Result<bool, StoreError> {
+        let result = sqlx::query("DELETE FROM items WHERE id = ?")
+            .bind(id as i64)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn apply_batch(&self, ops: Vec<BatchOp>) -> Result<BatchOutcome, StoreError> {
+        // Dropping the transaction without commit() rolls it back, so every
+        // early return below undoes the operations before it
+        let mut tx = self.pool.begin().await?;
+        let mut applied = Vec::with_capacity(ops.len());
+
+        for (index, op) in ops.into_iter().enumerate() {
+            match op {
+                BatchOp::Create { name, price } => {
+                    let row: ItemRow = sqlx::query_as(
+                        "INSERT INTO items (name, price) VALUES (?, ?) RETURNING id, name, price",
+                    )
+                    .bind(name)
+                    .bind(price)
+                    .fetch_one(&mut *tx)
+                    .await?;
+                    applied.push(Applied::Created(from_row(row)));
+                }
+                BatchOp::Update { id, name, price } => {
+                    let row: Option<ItemRow> = sqlx::query_as(
+                        "UPDATE items SET name = COALESCE(?, name), price = COALESCE(?, price)
+                         WHERE id = ? RETURNING id, name, price",
+                    )
+                    .bind(name)
+                    .bind(price)
+                    .bind(id as i64)
+                    .fetch_all(&mut *tx)
+                    .await?
+                    .pop();
+                    match row {
+                        Some(row) => applied.push(Applied::Updated(from_row(row))),
+                        None => return Ok(BatchOutcome::NotFound { index, id }),
+                    }
+                }
+                BatchOp::Delete { id } => {
+                    let result = sqlx::query("DELETE FROM items WHERE id = ?")
+                        .bind(id as i64)
+                        .execute(&mut *tx)
+                        .await?;
+                    if result.rows_affected() == 0 {
+                        return Ok(BatchOutcome::NotFound { index, id });
+                    }
+                    applied.push(Applied::Deleted(id));
+                }
+            }
+        }
+
+        tx.commit().await?;
+        Ok(BatchOutcome::Applied(applied))
+    }
+}
+
+/// Pick the backend from `ITEM_STORE` (memory|sqlite, default memory).
+/// The SQLite file comes from `DATABASE_URL` (default `sqlite:items.db`).
+pub async fn from_env() -> Result<Arc<dyn ItemStore>, StoreError> {
+    let kind = std::env::var("ITEM_STORE").unwrap_or_else(|_| "memory".to_string());
+    match kind.as_str() {
+        "memory" => Ok(Arc::new(MemoryStore::new())),
+        "sqlite" => {
+            let url =
+                std::env::var("DATABASE_URL").unwrap_or_else(|_| "sqlite:items.db".to_string());
+            Ok(Arc::new(SqliteStore::connect(&url).await?))
+        }
+        other => Err(StoreError(format!(
+            "unknown ITEM_STORE '{}' (memory|sqlite)",
+            other
+        ))),
+    }
+}
@@ -202,8 +202,1038 @@ fn test_05_delete_item() {
         None => return,
     };
 
+    assert!(response.contains("404"), "Deleted item should return 404");
+}
+
+fn start_server_on(port: u16) -> Option<ServerGuard> {
+    Command::new("cargo")
+        .args(["build", "--quiet"])
+        .status()
+        .ok()?;
+
+    let child = Command::new("cargo")
+        .args(["run", "--quiet", "--", &port.to_string()])
+        .spawn()
+        .ok()?;
+
+    thread::sleep(Duration::from_millis(1000));
+
+    Some(ServerGuard { child })
+}
+
+/// Send one JSON request with `Connection: close`, return (status line, body)
+fn send_json(port: u16, method: &str, path: &str, body: &str) -> Option<(String, String)> {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).ok()?;
+    stream.set_read_timeout(Some(Duration::from_secs(2))).ok()?;
+
+    let request = format!(
+        "{} {} HTTP/1.1\r\n\
+         Host: localhost\r\n\
+         Connection: close\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         \r\n\
+         {}",
+        method,
+        path,
+        body.len(),
+        body
+    );
+    stream.write_all(request.as_bytes()).ok()?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).ok()?;
+
+    let (head, body) = response.split_once("\r\n\r\n")?;
+    let status = head.lines().next()?.to_string();
+    Some((status, body.to_string()))
+}
+
+#[test]
+fn test_06_patch_updates_only_given_fields() {
+    let _server = match start_server_on(8101) {
+        Some(s) => s,
+        None => return,
+    };
+
+    let created = send_json(8101, "POST", "/items", r#"{"name":"Widget","price":9.99}"#);
+    let (status, _) = match created {
+        Some(r) => r,
+        None => return,
+    };
     assert!(
-        response.contains("404"),
-        "Deleted item should return 404"
+        status.contains("201"),
+        "Create should return 201: {}",
+        status
+    );
+
+    let (status, body) = send_json(8101, "PATCH", "/items/1", r#"{"price":4.5}"#)
+        .expect("server should answer PATCH");
+    assert!(
+        status.contains("200"),
+        "PATCH should return 200: {}",
+        status
+    );
+    assert!(
+        body.contains(r#""name":"Widget""#),
+        "Name unchanged: {}",
+        body
+    );
+    assert!(body.contains(r#""price":4.5"#), "Price updated: {}", body);
+
+    let (_, body) = send_json(8101, "PATCH", "/items/1", r#"{"name":"Gadget"}"#)
+        .expect("server should answer PATCH");
+    assert!(
+        body.contains(r#""name":"Gadget""#),
+        "Name updated: {}",
+        body
+    );
+    assert!(body.contains(r#""price":4.5"#), "Price unchanged: {}", body);
+
+    let (status, _) =
+        send_json(8101, "PATCH", "/items/999", r#"{"price":1}"#).expect("server should answer");
+    assert!(
+        status.contains("404"),
+        "Unknown id should be 404: {}",
+        status
+    );
+}
+
+#[test]
+fn test_07_validation_returns_422() {
+    let _server = match start_server_on(8102) {
+        Some(s) => s,
+        None => return,
+    };
+
+    let (status, body) = match send_json(8102, "POST", "/items", r#"{"name":"  ","price":-1}"#) {
+        Some(r) => r,
+        None => return,
+    };
+    assert!(
+        status.contains("422"),
+        "Invalid create should be 422: {}",
+        status
+    );
+    assert!(
+        body.contains(r#""error":"validation failed""#),
+        "Body: {}",
+        body
+    );
+    assert!(
+        body.contains(r#""field":"name""#),
+        "Name error listed: {}",
+        body
+    );
+    assert!(
+        body.contains(r#""field":"price""#),
+        "Price error listed: {}",
+        body
+    );
+
+    // Nothing was stored
+    let (_, body) = send_json(8102, "GET", "/items", "").expect("server should answer");
+    assert!(body.contains(r#""items":[]"#), "Body: {}", body);
+    assert!(body.contains(r#""total":0"#), "Body: {}", body);
+
+    send_json(8102, "POST", "/items", r#"{"name":"Widget","price":9.99}"#);
+    let (status, body) = send_json(8102, "PATCH", "/items/1", r#"{"price":-0.01}"#)
+        .expect("server should answer PATCH");
+    assert!(
+        status.contains("422"),
+        "Invalid patch should be 422: {}",
+        status
+    );
+    assert!(
+        !body.contains(r#""field":"name""#),
+        "Absent fields are not validated: {}",
+        body
+    );
+
+    let (status, _) = send_json(8102, "PUT", "/items/1", r#"{"name":"","price":1}"#)
+        .expect("server should answer PUT");
+    assert!(
+        status.contains("422"),
+        "Invalid PUT should be 422: {}",
+        status
+    );
+
+    let (_, body) = send_json(8102, "GET", "/items/1", "").expect("server should answer");
+    assert!(
+        body.contains(r#""price":9.99"#),
+        "Rejected updates change nothing: {}",
+        body
+    );
+}
+
+fn start_server_with_store(port: u16, store: &str, database_url: &str) -> ServerGuard {
+    let child = Command::new(env!("CARGO_BIN_EXE_axum_api"))
+        .arg(port.to_string())
+        .env("ITEM_STORE", store)
+        .env("DATABASE_URL", database_url)
+        .spawn()
+        .expect("Failed to start server");
+
+    thread::sleep(Duration::from_millis(1000));
+
+    ServerGuard { child }
+}
+
+#[test]
+fn test_08_sqlite_store_survives_restart() {
+    let db = std::env::temp_dir().join(format!("axum_api_items_{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&db);
+    let url = format!("sqlite:{}", db.display());
+
+    {
+        let _server = start_server_with_store(8103, "sqlite", &url);
+        let (status, body) = send_json(8103, "POST", "/items", r#"{"name":"Widget","price":9.99}"#)
+            .expect("server should answer POST");
+        assert!(
+            status.contains("201"),
+            "Create should return 201: {}",
+            status
+        );
+        assert!(body.contains(r#""id":1"#), "First id is 1: {}", body);
+
+        let (_, body) = send_json(8103, "PATCH", "/items/1", r#"{"price":4.5}"#)
+            .expect("server should answer PATCH");
+        assert!(
+            body.contains(r#""name":"Widget""#),
+            "Name unchanged: {}",
+            body
+        );
+        assert!(body.contains(r#""price":4.5"#), "Price updated: {}", body);
+    }
+
+    // Same handlers, new process: the item is read back from the file
+    let _server = start_server_with_store(8103, "sqlite", &url);
+    let (status, body) = send_json(8103, "GET", "/items", "").expect("server should answer GET");
+    assert!(status.contains("200"), "List should return 200: {}", status);
+    assert_eq!(
+        body,
+        r#"{"items":[{"id":1,"name":"Widget","price":4.5}],"page":1,"limit":20,"total":1}"#
+    );
+
+    let (status, _) = send_json(8103, "DELETE", "/items/1", "").expect("server should answer");
+    assert!(
+        status.contains("204"),
+        "Delete should return 204: {}",
+        status
+    );
+    let (status, _) = send_json(8103, "GET", "/items/1", "").expect("server should answer");
+    assert!(status.contains("404"), "Deleted item is gone: {}", status);
+
+    let _ = std::fs::remove_file(&db);
+}
+
+#[test]
+fn test_09_unknown_store_fails_to_start() {
+    let output = Command::new(env!("CARGO_BIN_EXE_axum_api"))
+        .arg("8104")
+        .env("ITEM_STORE", "redis")
+        .output()
+        .expect("Failed to run server");
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown ITEM_STORE"));
+}
+
+/// Ids of the items in a list response, in order, plus the total
+fn page_ids(body: &str) -> (Vec<u64>, u64) {
+    let value: serde_json::Value = serde_json::from_str(body).expect("list body is JSON");
+    let ids = value["items"]
+        .as_array()
+        .expect("items array")
+        .iter()
+        .map(|item| item["id"].as_u64().unwrap())
+        .collect();
+    (ids, value["total"].as_u64().expect("total"))
+}
+
+/// Same checks against either backend: the SQL and in-memory paths must agree
+fn check_list_queries(port: u16) {
+    // id 1..=5
+    for (name, price) in [
+        ("delta", 40.0),
+        ("alpha", 10.0),
+        ("echo", 10.0),
+        ("charlie", 5.5),
+        ("bravo", 99.0),
+    ] {
+        let body = format!(r#"{{"name":"{}","price":{}}}"#, name, price);
+        let (status, _) = send_json(port, "POST", "/items", &body).expect("server should answer");
+        assert!(status.contains("201"), "Create: {}", status);
+    }
+    let get = |path: &str| send_json(port, "GET", path, "").expect("server should answer");
+
+    // Defaults: page 1, id order
+    let (_, body) = get("/items");
+    assert!(body.contains(r#""page":1,"limit":20"#), "Body: {}", body);
+    assert_eq!(page_ids(&body), (vec![1, 2, 3, 4, 5], 5));
+
+    // Pages split the list; total stays the full count
+    assert_eq!(page_ids(&get("/items?limit=2").1), (vec![1, 2], 5));
+    assert_eq!(page_ids(&get("/items?page=3&limit=2").1), (vec![5], 5));
+    assert_eq!(page_ids(&get("/items?page=4&limit=2").1), (vec![], 5));
+    assert_eq!(
+        page_ids(&get("/items?page=18446744073709551615&limit=100").1),
+        (vec![], 5),
+        "A huge page number is past the end, not an overflow"
+    );
+
+    // Sorting; equal prices fall back to id
+    assert_eq!(
+        page_ids(&get("/items?sort=name").1),
+        (vec![2, 5, 4, 1, 3], 5)
+    );
+    assert_eq!(
+        page_ids(&get("/items?sort=price").1),
+        (vec![4, 2, 3, 1, 5], 5)
+    );
+
+    // Inclusive price bounds; total counts matches only
+    assert_eq!(
+        page_ids(&get("/items?min_price=10&max_price=40&sort=price").1),
+        (vec![2, 3, 1], 3)
+    );
+    assert_eq!(
+        page_ids(&get("/items?min_price=10&max_price=40&sort=price&page=2&limit=2").1),
+        (vec![1], 3)
+    );
+    assert_eq!(page_ids(&get("/items?min_price=100").1), (vec![], 0));
+
+    // Invalid values are 422 with every problem listed
+    let (status, body) = get("/items?page=0&limit=101&sort=color&min_price=5&max_price=1");
+    assert!(status.contains("422"), "Invalid query: {}", status);
+    for field in ["page", "limit", "sort", "min_price"] {
+        assert!(
+            body.contains(&format!(r#""field":"{}""#, field)),
+            "{} error listed: {}",
+            field,
+            body
+        );
+    }
+    let (status, _) = get("/items?limit=0");
+    assert!(status.contains("422"), "limit=0: {}", status);
+}
+
+#[test]
+fn test_10_list_pagination_sorting_filtering() {
+    let _server = match start_server_on(8105) {
+        Some(s) => s,
+        None => return,
+    };
+    check_list_queries(8105);
+}
+
+#[test]
+fn test_11_list_queries_on_sqlite() {
+    let db = std::env::temp_dir().join(format!("axum_api_list_{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&db);
+
+    let _server = start_server_with_store(8106, "sqlite", &format!("sqlite:{}", db.display()));
+    check_list_queries(8106);
+
+    let _ = std::fs::remove_file(&db);
+}
+
+/// Every error is JSON with "error" and "code"; return the code
+fn error_code(body: &str) -> String {
+    let value: serde_json::Value =
+        serde_json::from_str(body).unwrap_or_else(|_| panic!("error body is not JSON: {}", body));
+    assert!(
+        value["error"].is_string(),
+        "missing error message: {}",
+        body
     );
+    value["code"].as_str().expect("missing code").to_string()
+}
+
+#[test]
+fn test_12_errors_are_json() {
+    let child = Command::new(env!("CARGO_BIN_EXE_axum_api"))
+        .arg("8107")
+        .env("ENABLE_PANIC_ROUTE", "1")
+        .spawn()
+        .expect("Failed to start server");
+    let _server = ServerGuard { child };
+    thread::sleep(Duration::from_millis(1000));
+
+    let send = |method: &str, path: &str, body: &str| {
+        send_json(8107, method, path, body).expect("server should answer")
+    };
+
+    let (status, body) = send("GET", "/items/7", "");
+    assert!(status.contains("404"), "Missing item: {}", status);
+    assert_eq!(error_code(&body), "not_found");
+    assert!(body.contains("item 7 not found"), "Body: {}", body);
+
+    let (status, body) = send("GET", "/no/such/route", "");
+    assert!(status.contains("404"), "Unknown route: {}", status);
+    assert_eq!(error_code(&body), "not_found");
+
+    let (status, body) = send("POST", "/items/1", "{}");
+    assert!(status.contains("405"), "Wrong method: {}", status);
+    assert_eq!(error_code(&body), "method_not_allowed");
+
+    let (status, body) = send("GET", "/items/abc", "");
+    assert!(status.contains("400"), "Bad path parameter: {}", status);
+    assert_eq!(error_code(&body), "bad_request");
+
+    let (status, body) = send("POST", "/items", r#"{"name":"Widget""#);
+    assert!(status.contains("400"), "Malformed JSON: {}", status);
+    assert_eq!(error_code(&body), "bad_request");
+
+    let (status, body) = send("GET", "/items?page=abc", "");
+    assert!(status.contains("400"), "Bad query string: {}", status);
+    assert_eq!(error_code(&body), "bad_request");
+
+    let (status, body) = send("POST", "/items", r#"{"name":"","price":1}"#);
+    assert!(status.contains("422"), "Validation: {}", status);
+    assert_eq!(error_code(&body), "validation_failed");
+
+    let (status, body) = send("GET", "/debug/panic", "");
+    assert!(status.contains("500"), "Panic: {}", status);
+    assert_eq!(error_code(&body), "internal_error");
+    assert!(
+        !body.contains("panic route hit"),
+        "Panic details leaked: {}",
+        body
+    );
+
+    // The server survived the panic
+    let (status, _) = send("GET", "/items", "");
+    assert!(status.contains("200"), "After panic: {}", status);
+}
+
+/// Send a request with extra header lines, return (response head, body)
+fn send_with_headers(
+    port: u16,
+    method: &str,
+    path: &str,
+    headers: &str,
+    body: &str,
+) -> (String, String) {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).expect("connect");
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let request = format!(
+        "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\
+         Content-Type: application/json\r\nContent-Length: {}\r\n{}\r\n{}",
+        method,
+        path,
+        body.len(),
+        headers,
+        body
+    );
+    stream.write_all(request.as_bytes()).unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").expect("no header end");
+    (head.to_string(), body.to_string())
+}
+
+fn header_value(head: &str, name: &str) -> Option<String> {
+    head.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        key.eq_ignore_ascii_case(name)
+            .then(|| value.trim().to_string())
+    })
+}
+
+#[test]
+fn test_13_request_ids_and_access_log() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_axum_api"))
+        .arg("8108")
+        .env("ENABLE_PANIC_ROUTE", "1")
+        .env_remove("RUST_LOG")
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .expect("Failed to start server");
+    let mut stdout = child.stdout.take().unwrap();
+    let server = ServerGuard { child };
+    thread::sleep(Duration::from_millis(1000));
+
+    // The client's id is kept, echoed, and put in the error body
+    let (head, body) = send_with_headers(
+        8108,
+        "GET",
+        "/items/7",
+        "X-Request-Id: lab4-test-id\r\n",
+        "",
+    );
+    assert!(head.starts_with("HTTP/1.1 404"), "Head: {}", head);
+    assert_eq!(
+        header_value(&head, "x-request-id").as_deref(),
+        Some("lab4-test-id")
+    );
+    let error: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(error["request_id"], "lab4-test-id");
+
+    // Without one, the server makes one up - and uses the same one in the body
+    let (head, body) = send_with_headers(8108, "GET", "/debug/panic", "", "");
+    assert!(head.starts_with("HTTP/1.1 500"), "Head: {}", head);
+    let generated = header_value(&head, "x-request-id").expect("missing X-Request-Id");
+    assert_eq!(generated.len(), 36, "expected a UUID: {}", generated);
+    let error: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(error["request_id"], generated.as_str());
+
+    // Successful responses get an id too
+    let (head, _) = send_with_headers(8108, "GET", "/items", "", "");
+    assert!(head.starts_with("HTTP/1.1 200"), "Head: {}", head);
+    assert!(header_value(&head, "x-request-id").is_some());
+
+    // Stop the server, then read everything it logged
+    drop(server);
+    let mut logs = String::new();
+    stdout.read_to_string(&mut logs).unwrap();
+    let lines: Vec<serde_json::Value> = logs
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+    let completed = |request_id: &str| {
+        lines
+            .iter()
+            .find(|line| {
+                line["message"] == "request completed" && line["span"]["request_id"] == request_id
+            })
+            .unwrap_or_else(|| panic!("no access log for {} in:\n{}", request_id, logs))
+    };
+
+    let line = completed("lab4-test-id");
+    assert_eq!(line["status"], 404);
+    assert_eq!(line["span"]["method"], "GET");
+    assert_eq!(line["span"]["path"], "/items/7");
+    assert!(line["latency_ms"].as_f64().is_some(), "Line: {}", line);
+
+    // The panic is logged as a 500 under the generated id
+    assert_eq!(completed(&generated)["status"], 500);
+    assert!(
+        lines.iter().any(|line| line["panic"] == "panic route hit"
+            && line["span"]["request_id"] == generated.as_str()),
+        "panic not logged with its request id:\n{}",
+        logs
+    );
+}
+
+fn start_server_with_keys(port: u16, var: &str, value: &str) -> ServerGuard {
+    let child = Command::new(env!("CARGO_BIN_EXE_axum_api"))
+        .arg(port.to_string())
+        .env_remove("API_KEYS")
+        .env_remove("API_KEYS_FILE")
+        .env(var, value)
+        .spawn()
+        .expect("Failed to start server");
+    thread::sleep(Duration::from_millis(1000));
+    ServerGuard { child }
+}
+
+#[test]
+fn test_14_api_keys_guard_writes() {
+    let _server = start_server_with_keys(
+        8109,
+        "API_KEYS",
+        "admin:write:admin-secret,viewer:read:viewer-secret",
+    );
+    let send = |method: &str, path: &str, auth: &str, body: &str| {
+        let headers = if auth.is_empty() {
+            String::new()
+        } else {
+            format!("Authorization: Bearer {}\r\n", auth)
+        };
+        send_with_headers(8109, method, path, &headers, body)
+    };
+    let item = r#"{"name":"Widget","price":9.99}"#;
+
+    // Reads stay open
+    let (head, _) = send("GET", "/items", "", "");
+    assert!(head.starts_with("HTTP/1.1 200"), "Anonymous read: {}", head);
+
+    // Writes without a valid key: 401 with a challenge
+    let (head, body) = send("POST", "/items", "", item);
+    assert!(head.starts_with("HTTP/1.1 401"), "No key: {}", head);
+    assert_eq!(error_code(&body), "unauthorized");
+    assert_eq!(
+        header_value(&head, "www-authenticate").as_deref(),
+        Some("Bearer")
+    );
+    let (head, _) = send("POST", "/items", "not-a-key", item);
+    assert!(head.starts_with("HTTP/1.1 401"), "Unknown key: {}", head);
+
+    // A read key is known but not allowed to write: 403
+    let (head, body) = send("POST", "/items", "viewer-secret", item);
+    assert!(head.starts_with("HTTP/1.1 403"), "Read key: {}", head);
+    assert_eq!(error_code(&body), "forbidden");
+
+    let (head, _) = send("POST", "/items", "admin-secret", item);
+    assert!(head.starts_with("HTTP/1.1 201"), "Write key: {}", head);
+
+    // /stats needs a key and never shows the secrets
+    let (head, _) = send("GET", "/stats", "", "");
+    assert!(
+        head.starts_with("HTTP/1.1 401"),
+        "Anonymous stats: {}",
+        head
+    );
+    let (head, body) = send("GET", "/stats", "viewer-secret", "");
+    assert!(head.starts_with("HTTP/1.1 200"), "Stats: {}", head);
+    assert!(!body.contains("secret"), "Stats leak keys: {}", body);
+
+    let stats: serde_json::Value = serde_json::from_str(&body).unwrap();
+    let key = |name: &str| {
+        stats["keys"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|k| k["name"] == name)
+            .unwrap_or_else(|| panic!("no stats for {}: {}", name, body))
+            .clone()
+    };
+    assert_eq!(stats["auth_enabled"], true);
+    assert_eq!(key("admin")["requests"], 1);
+    assert_eq!(key("admin")["scope"], "write");
+    // The denied POST and this /stats call
+    assert_eq!(key("viewer")["denied"], 1);
+    assert_eq!(key("viewer")["requests"], 1);
+    assert_eq!(stats["anonymous"], 1);
+    // No key on POST, unknown key on POST, no key on /stats
+    assert_eq!(stats["rejected"], 3);
+}
+
+#[test]
+fn test_15_api_keys_from_file() {
+    let path = std::env::temp_dir().join(format!("lab4_keys_{}.txt", std::process::id()));
+    std::fs::write(
+        &path,
+        "# name:scope:key\nci:write:from-file:with-colon\n\nreader:read:r  # read only\n",
+    )
+    .unwrap();
+    let _server = start_server_with_keys(8110, "API_KEYS_FILE", path.to_str().unwrap());
+
+    let (head, _) = send_with_headers(
+        8110,
+        "POST",
+        "/items",
+        "Authorization: Bearer from-file:with-colon\r\n",
+        r#"{"name":"Widget","price":9.99}"#,
+    );
+    assert!(head.starts_with("HTTP/1.1 201"), "Key from file: {}", head);
+    let (head, _) = send_with_headers(
+        8110,
+        "DELETE",
+        "/items/1",
+        "Authorization: Bearer r\r\n",
+        "",
+    );
+    assert!(
+        head.starts_with("HTTP/1.1 403"),
+        "Read key from file: {}",
+        head
+    );
+    let _ = std::fs::remove_file(&path);
+
+    // A broken entry stops the server before it listens
+    let output = Command::new(env!("CARGO_BIN_EXE_axum_api"))
+        .arg("8111")
+        .env("API_KEYS", "admin:superuser:k")
+        .output()
+        .expect("Failed to run server");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown scope"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_16_health_probes_and_graceful_shutdown() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_axum_api"))
+        .arg("8112")
+        .env_remove("API_KEYS")
+        .env_remove("API_KEYS_FILE")
+        .env("DRAIN_DELAY_MS", "1500")
+        .spawn()
+        .expect("Failed to start server");
+    thread::sleep(Duration::from_millis(1000));
+
+    let (head, body) = send_with_headers(8112, "GET", "/healthz", "", "");
+    assert!(head.starts_with("HTTP/1.1 200"), "healthz: {}", head);
+    assert!(body.contains(r#""status":"ok""#), "healthz: {}", body);
+    let (head, body) = send_with_headers(8112, "GET", "/readyz", "", "");
+    assert!(head.starts_with("HTTP/1.1 200"), "readyz: {}", head);
+    assert!(body.contains(r#""status":"ready""#), "readyz: {}", body);
+
+    // A request that is still in flight: headers sent, body not finished
+    let item = r#"{"name":"Widget","price":9.99}"#;
+    let mut in_flight = TcpStream::connect("127.0.0.1:8112").expect("connect");
+    in_flight
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    write!(
+        in_flight,
+        "POST /items HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\
+         Content-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+        item.len(),
+        &item[..10]
+    )
+    .unwrap();
+    thread::sleep(Duration::from_millis(200));
+
+    let killed = Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status()
+        .expect("Failed to run kill");
+    assert!(killed.success());
+    thread::sleep(Duration::from_millis(300));
+
+    // Draining: still alive, no longer ready
+    let (head, body) = send_with_headers(8112, "GET", "/readyz", "", "");
+    assert!(
+        head.starts_with("HTTP/1.1 503"),
+        "readyz draining: {}",
+        head
+    );
+    assert!(body.contains(r#""status":"draining""#), "readyz: {}", body);
+    let (head, _) = send_with_headers(8112, "GET", "/healthz", "", "");
+    assert!(
+        head.starts_with("HTTP/1.1 200"),
+        "healthz draining: {}",
+        head
+    );
+
+    // After the drain delay new connections are refused
+    thread::sleep(Duration::from_millis(1500));
+    assert!(
+        TcpStream::connect("127.0.0.1:8112").is_err(),
+        "still accepting after the drain delay"
+    );
+
+    // ...but the request in flight is completed
+    in_flight.write_all(&item.as_bytes()[10..]).unwrap();
+    let mut response = String::new();
+    in_flight.read_to_string(&mut response).unwrap();
+    assert!(
+        response.starts_with("HTTP/1.1 201"),
+        "In-flight request: {}",
+        response
+    );
+
+    let status = child.wait().expect("server did not exit");
+    assert!(status.success(), "Exit status after drain: {}", status);
+}
+
+#[test]
+fn test_17_etags_and_conditional_requests() {
+    let _server = start_server_with_keys(8113, "API_KEYS", "");
+    let item = r#"{"name":"Widget","price":9.99}"#;
+    let (head, _) = send_with_headers(8113, "POST", "/items", "", item);
+    assert!(head.starts_with("HTTP/1.1 201"), "Create: {}", head);
+    let created = header_value(&head, "etag").expect("POST should return an ETag");
+
+    // Same content, same tag
+    let (head, body) = send_with_headers(8113, "GET", "/items/1", "", "");
+    assert!(head.starts_with("HTTP/1.1 200"), "Get: {}", head);
+    let etag = header_value(&head, "etag").expect("GET should return an ETag");
+    assert_eq!(etag, created);
+    assert!(
+        etag.starts_with('"') && etag.ends_with('"'),
+        "ETag: {}",
+        etag
+    );
+    assert!(body.contains("Widget"));
+
+    // Cached copy still current: 304 without a body
+    let if_none_match = |value: &str| {
+        send_with_headers(
+            8113,
+            "GET",
+            "/items/1",
+            &format!("If-None-Match: {}\r\n", value),
+            "",
+        )
+    };
+    let (head, body) = if_none_match(&etag);
+    assert!(head.starts_with("HTTP/1.1 304"), "If-None-Match: {}", head);
+    assert!(body.is_empty(), "304 with a body: {}", body);
+    assert_eq!(header_value(&head, "etag").as_deref(), Some(etag.as_str()));
+    let (head, _) = if_none_match(&format!("\"other\", W/{}", etag));
+    assert!(
+        head.starts_with("HTTP/1.1 304"),
+        "Weak tag in list: {}",
+        head
+    );
+    let (head, _) = if_none_match("\"other\"");
+    assert!(head.starts_with("HTTP/1.1 200"), "Stale tag: {}", head);
+
+    // PUT without If-Match: 428
+    let update = r#"{"name":"Super Widget","price":19.99}"#;
+    let (head, body) = send_with_headers(8113, "PUT", "/items/1", "", update);
+    assert!(head.starts_with("HTTP/1.1 428"), "No If-Match: {}", head);
+    assert_eq!(error_code(&body), "precondition_required");
+
+    // With the current tag: updated, new tag
+    let put = |tag: &str| {
+        send_with_headers(
+            8113,
+            "PUT",
+            "/items/1",
+            &format!("If-Match: {}\r\n", tag),
+            update,
+        )
+    };
+    let (head, body) = put(&etag);
+    assert!(head.starts_with("HTTP/1.1 200"), "If-Match: {}", head);
+    assert!(body.contains("Super Widget"));
+    let new_etag = header_value(&head, "etag").expect("PUT should return an ETag");
+    assert_ne!(new_etag, etag);
+
+    // A second writer still holding the old tag loses
+    let (head, body) = put(&etag);
+    assert!(head.starts_with("HTTP/1.1 412"), "Stale If-Match: {}", head);
+    assert_eq!(error_code(&body), "precondition_failed");
+    let (head, _) = put(&format!("W/{}", new_etag));
+    assert!(head.starts_with("HTTP/1.1 412"), "Weak If-Match: {}", head);
+
+    // The old tag no longer validates a cached copy either
+    let (head, _) = if_none_match(&etag);
+    assert!(
+        head.starts_with("HTTP/1.1 200"),
+        "Old tag after PUT: {}",
+        head
+    );
+
+    // PATCH changes the tag too
+    let (head, _) = send_with_headers(8113, "PATCH", "/items/1", "", r#"{"price":1.5}"#);
+    assert!(head.starts_with("HTTP/1.1 200"), "Patch: {}", head);
+    assert_ne!(header_value(&head, "etag"), Some(new_etag));
+
+    let (head, _) = send_with_headers(8113, "PUT", "/items/99", "If-Match: *\r\n", update);
+    assert!(head.starts_with("HTTP/1.1 404"), "Missing item: {}", head);
+}
+
+#[test]
+fn test_18_cors_preflight_and_origins() {
+    let child = Command::new(env!("CARGO_BIN_EXE_axum_api"))
+        .arg("8114")
+        .env("API_KEYS", "admin:write:admin-secret")
+        .env(
+            "CORS_ORIGINS",
+            "https://app.example.com, http://localhost:5173",
+        )
+        .env("CORS_MAX_AGE", "120")
+        .env_remove("CORS_METHODS")
+        .env_remove("CORS_HEADERS")
+        .spawn()
+        .expect("Failed to start server");
+    let _server = ServerGuard { child };
+    thread::sleep(Duration::from_millis(1000));
+
+    let preflight = |origin: &str, method: &str| {
+        send_with_headers(
+            8114,
+            "OPTIONS",
+            "/items/1",
+            &format!(
+                "Origin: {}\r\nAccess-Control-Request-Method: {}\r\n\
+                 Access-Control-Request-Headers: content-type, authorization, if-match\r\n",
+                origin, method
+            ),
+            "",
+        )
+    };
+
+    // Allowed origin: answered by the CORS layer, auth and the router never see it
+    let (head, _) = preflight("https://app.example.com", "PUT");
+    assert!(head.starts_with("HTTP/1.1 200"), "Preflight: {}", head);
+    assert_eq!(
+        header_value(&head, "access-control-allow-origin").as_deref(),
+        Some("https://app.example.com")
+    );
+    let methods = header_value(&head, "access-control-allow-methods").unwrap_or_default();
+    for method in ["GET", "POST", "PUT", "PATCH", "DELETE"] {
+        assert!(
+            methods.contains(method),
+            "{} not allowed: {}",
+            method,
+            methods
+        );
+    }
+    let headers = header_value(&head, "access-control-allow-headers").unwrap_or_default();
+    for name in ["content-type", "authorization", "if-match"] {
+        assert!(headers.contains(name), "{} not allowed: {}", name, headers);
+    }
+    assert_eq!(
+        header_value(&head, "access-control-max-age").as_deref(),
+        Some("120")
+    );
+
+    let (head, _) = preflight("http://localhost:5173", "DELETE");
+    assert_eq!(
+        header_value(&head, "access-control-allow-origin").as_deref(),
+        Some("http://localhost:5173")
+    );
+
+    // Unknown origin: no allow header, the browser blocks the call
+    let (head, _) = preflight("https://evil.example.com", "PUT");
+    assert_eq!(header_value(&head, "access-control-allow-origin"), None);
+
+    // Real requests carry the headers too, including refused ones
+    let (head, _) = send_with_headers(
+        8114,
+        "GET",
+        "/items",
+        "Origin: https://app.example.com\r\n",
+        "",
+    );
+    assert!(head.starts_with("HTTP/1.1 200"), "GET: {}", head);
+    assert_eq!(
+        header_value(&head, "access-control-allow-origin").as_deref(),
+        Some("https://app.example.com")
+    );
+    let exposed = header_value(&head, "access-control-expose-headers").unwrap_or_default();
+    assert!(exposed.contains("etag"), "ETag not exposed: {}", exposed);
+
+    let (head, _) = send_with_headers(
+        8114,
+        "POST",
+        "/items",
+        "Origin: https://app.example.com\r\n",
+        r#"{"name":"Widget","price":9.99}"#,
+    );
+    assert!(head.starts_with("HTTP/1.1 401"), "Anonymous POST: {}", head);
+    assert!(
+        header_value(&head, "access-control-allow-origin").is_some(),
+        "401 without CORS headers: {}",
+        head
+    );
+
+    // Bad settings stop the server before it listens
+    for (var, value) in [
+        ("CORS_ORIGINS", "app.example.com"),
+        ("CORS_MAX_AGE", "ten"),
+        ("CORS_METHODS", "GET,NOT A METHOD"),
+    ] {
+        let output = Command::new(env!("CARGO_BIN_EXE_axum_api"))
+            .arg("8115")
+            .env("CORS_ORIGINS", "https://app.example.com")
+            .env(var, value)
+            .output()
+            .expect("Failed to run server");
+        assert!(!output.status.success(), "{}={} accepted", var, value);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("invalid CORS config"));
+    }
+}
+
+fn batch(port: u16, ops: &str) -> (String, serde_json::Value) {
+    let (status, body) = send_json(port, "POST", "/items:batch", ops).expect("batch request");
+    let value = serde_json::from_str(&body).unwrap_or_else(|_| panic!("not JSON: {}", body));
+    (status, value)
+}
+
+fn result_statuses(value: &serde_json::Value) -> Vec<u64> {
+    value["results"]
+        .as_array()
+        .unwrap_or_else(|| panic!("no results: {}", value))
+        .iter()
+        .map(|r| r["status"].as_u64().unwrap())
+        .collect()
+}
+
+fn check_batch(port: u16) {
+    // id 1 and 2 to work on
+    for name in ["One", "Two"] {
+        let body = format!(r#"{{"name":"{}","price":1.0}}"#, name);
+        send_json(port, "POST", "/items", &body).unwrap();
+    }
+
+    // Every kind of operation in one go
+    let (status, value) = batch(
+        port,
+        r#"[{"op":"create","name":"Three","price":3.0},
+            {"op":"update","id":1,"price":1.5},
+            {"op":"delete","id":2}]"#,
+    );
+    assert!(status.contains("200"), "Batch: {} {}", status, value);
+    assert_eq!(result_statuses(&value), [201, 200, 204]);
+    let results = &value["results"];
+    assert_eq!(results[0]["item"]["name"], "Three");
+    assert!(results[0]["etag"].is_string());
+    assert_eq!(results[1]["item"]["price"], 1.5);
+    assert_eq!(results[2]["id"], 2);
+    let created = results[0]["item"]["id"].as_u64().unwrap();
+
+    let (_, body) = send_json(port, "GET", "/items", "").unwrap();
+    let (ids, _) = page_ids(&body);
+    assert_eq!(ids, [1, created]);
+
+    // A missing item rolls back the operations before it
+    let (status, value) = batch(
+        port,
+        r#"[{"op":"create","name":"Ghost","price":9.0},
+            {"op":"update","id":1,"name":"Renamed"},
+            {"op":"delete","id":2}]"#,
+    );
+    assert!(status.contains("404"), "Missing item: {} {}", status, value);
+    assert_eq!(value["code"], "batch_failed");
+    assert_eq!(result_statuses(&value), [424, 424, 404]);
+    assert_eq!(value["results"][2]["code"], "not_found");
+    assert_eq!(value["results"][0]["code"], "failed_dependency");
+
+    let (_, body) = send_json(port, "GET", "/items", "").unwrap();
+    let (ids, total) = page_ids(&body);
+    assert_eq!((ids.as_slice(), total), ([1, created].as_slice(), 2));
+    let (_, body) = send_json(port, "GET", "/items/1", "").unwrap();
+    assert!(body.contains("One"), "Rolled back update applied: {}", body);
+
+    // Invalid operations are reported together, before anything runs
+    let (status, value) = batch(
+        port,
+        r#"[{"op":"create","name":"","price":1.0},
+            {"op":"delete","id":1},
+            {"op":"update","id":1,"price":-2}]"#,
+    );
+    assert!(status.contains("422"), "Invalid ops: {} {}", status, value);
+    assert_eq!(result_statuses(&value), [422, 424, 422]);
+    assert_eq!(value["results"][0]["fields"][0]["field"], "name");
+    assert_eq!(value["results"][2]["fields"][0]["field"], "price");
+    let (status, _) = send_json(port, "GET", "/items/1", "").unwrap();
+    assert!(status.contains("200"), "Item 1 deleted by a rejected batch");
+
+    // Shape of the batch itself
+    let (status, value) = batch(port, "[]");
+    assert!(status.contains("422"), "Empty batch: {}", status);
+    assert_eq!(value["fields"][0]["field"], "operations");
+    let (status, body) =
+        send_json(port, "POST", "/items:batch", r#"[{"op":"upsert","id":1}]"#).unwrap();
+    assert!(status.contains("422"), "Unknown op: {} {}", status, body);
+    assert_eq!(error_code(&body), "bad_request");
+
+    // Only /items:batch lives on that route
+    let (status, body) = send_json(port, "GET", "/items:batch", "").unwrap();
+    assert!(status.contains("405"), "GET batch: {}", status);
+    assert_eq!(error_code(&body), "method_not_allowed");
+    let (status, body) = send_json(port, "POST", "/items:merge", "[]").unwrap();
+    assert!(status.contains("404"), "Other action: {}", status);
+    assert_eq!(error_code(&body), "not_found");
+}
+
+#[test]
+fn test_19_batch_operations() {
+    let _server = match start_server_on(8116) {
+        Some(s) => s,
+        None => return,
+    };
+    check_batch(8116);
+}
+
+#[test]
+fn test_20_batch_operations_on_sqlite() {
+    let db = std::env::temp_dir().join(format!("axum_api_batch_{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&db);
+
+    let _server = start_server_with_store(8117, "sqlite", &format!("sqlite:{}", db.display()));
+    check_batch(8117);
+
+    let _ = std::fs::remove_file(&db);
 }
@@ -0,0 +1,157 @@
+//! Tunnel benchmark: splice(2) vs the userspace copy loop
+//!
+//! Everything runs in one process on loopback:
+//!
+//! ```text
+//! client --> relay (mode under test) --> sink
+//! ```
+//!
+//! The client pushes N MiB through the relay, the sink counts bytes and
+//! answers with the total once the client half-closes. CPU time is read
+//! with getrusage() around each run.
+//!
+//! ```bash
+//! cargo run --release --bin tunnel_bench -- --mib 2048
+//! ```
+
+// Shared with the proxy; the bench does not use all of it
+#[allow(dead_code)]
+#[path = "../tunnel.rs"]
+mod tunnel;
+
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tunnel::TunnelMode;
+
+const CHUNK: usize = 256 * 1024;
+
+/// Accept one connection, count everything until EOF, reply with the count
+async fn sink(listener: TcpListener) -> std::io::Result<()> {
+    let (mut stream, _) = listener.accept().await?;
+    let mut buf = vec![0u8; CHUNK];
+    let mut total = 0u64;
+    loop {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        total += n as u64;
+    }
+    stream.write_all(&total.to_be_bytes()).await
+}
+
+/// Accept one connection and tunnel it to `target`
+async fn relay_once(
+    listener: TcpListener,
+    target: String,
+    mode: TunnelMode,
+) -> std::io::Result<()> {
+    let (client, _) = listener.accept().await?;
+    let server = TcpStream::connect(target).await?;
+    tunnel::relay(client, server, mode).await.map(|_| ())
+}
+
+/// User and system CPU time used by this process so far
+#[cfg(target_os = "linux")]
+fn cpu_time() -> (Duration, Duration) {
+    use nix::sys::resource::{getrusage, UsageWho};
+
+    match getrusage(UsageWho::RUSAGE_SELF) {
+        Ok(usage) => {
+            let user = usage.user_time();
+            let sys = usage.system_time();
+            (
+                Duration::new(user.tv_sec() as u64, user.tv_usec() as u32 * 1000),
+                Duration::new(sys.tv_sec() as u64, sys.tv_usec() as u32 * 1000),
+            )
+        }
+        Err(_) => (Duration::ZERO, Duration::ZERO),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn cpu_time() -> (Duration, Duration) {
+    (Duration::ZERO, Duration::ZERO)
+}
+
+async fn run(mode: TunnelMode, bytes: u64) -> std::io::Result<()> {
+    let sink_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let sink_addr = sink_listener.local_addr()?.to_string();
+    let relay_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let relay_addr = relay_listener.local_addr()?;
+
+    let sink_task = tokio::spawn(sink(sink_listener));
+    let relay_task = tokio::spawn(relay_once(relay_listener, sink_addr, mode));
+
+    let (user_before, sys_before) = cpu_time();
+    let start = Instant::now();
+
+    let mut client = TcpStream::connect(relay_addr).await?;
+    let buf = vec![0xabu8; CHUNK];
+    let mut sent = 0u64;
+    while sent < bytes {
+        let n = (bytes - sent).min(CHUNK as u64) as usize;
+        client.write_all(&buf[..n]).await?;
+        sent += n as u64;
+    }
+    client.shutdown().await?;
+
+    let mut reply = [0u8; 8];
+    client.read_exact(&mut reply).await?;
+    let received = u64::from_be_bytes(reply);
+
+    let elapsed = start.elapsed();
+    let (user_after, sys_after) = cpu_time();
+    let _ = sink_task.await;
+    let _ = relay_task.await;
+
+    let mib = received as f64 / (1024.0 * 1024.0);
+    let secs = elapsed.as_secs_f64().max(1e-9);
+    println!(
+        "mode={:<6} bytes={} elapsed_ms={:.1} throughput={:.1} MiB/s user_ms={:.1} sys_ms={:.1}",
+        mode.name(),
+        received,
+        secs * 1000.0,
+        mib / secs,
+        (user_after - user_before).as_secs_f64() * 1000.0,
+        (sys_after - sys_before).as_secs_f64() * 1000.0
+    );
+
+    if received != bytes {
+        eprintln!(
+            "warning: sent {} bytes but sink counted {}",
+            bytes, received
+        );
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+    let mut mib = 512u64;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next().and_then(|v| v.parse().ok())) {
+            ("--mib", Some(n)) if n > 0 => mib = n,
+            _ => {
+                eprintln!("usage: tunnel_bench [--mib N]");
+                std::process::exit(2);
+            }
+        }
+    }
+
+    // CPU time covers client and sink too; they are identical in both runs,
+    // so the difference between the lines is the relay
+    let modes = if cfg!(target_os = "linux") {
+        vec![TunnelMode::Copy, TunnelMode::Splice]
+    } else {
+        vec![TunnelMode::Copy]
+    };
+    for mode in modes {
+        if let Err(e) = run(mode, mib * 1024 * 1024).await {
+            eprintln!("tunnel_bench: {} failed: {}", mode.name(), e);
+            std::process::exit(1);
+        }
+    }
+}
@@ -44,10 +44,56 @@
 //! - [ ] Round-robin balances across backends
 //! - [ ] X-Forwarded-For header is added
 //! - [ ] Backend failures don't crash proxy
+//!
+//! ## Extension: Tunnels + splice
+//! - `CONNECT host:port` opens a raw TCP tunnel (what browsers do for HTTPS via a proxy)
+//! - Requests with `Upgrade:` (WebSocket) are forwarded, then become a raw tunnel too
+//! - `--tunnel splice` (Linux default) relays with splice(2) through a pipe,
+//!   `--tunnel copy` uses the userspace read/write loop
+//! - Only listens on 127.0.0.1: an open CONNECT proxy on a public interface gets abused
+//! ```bash
+//! cargo run -- --port 8080 --tunnel splice
+//! curl -x http://127.0.0.1:8080 https://example.com/   # uses CONNECT
+//! cargo run --release --bin tunnel_bench -- --mib 2048
+//! ```
+//!
+//! ## Extension: PROXY protocol
+//! - `--accept-proxy`: every connection must start with a PROXY v1/v2 header
+//!   (see `chapter_03_network/shared/proxy_protocol.rs`); its source
+//!   address is the client, for X-Forwarded-For and for the next hop.
+//!   Connections without one are closed
+//! - `--send-proxy v1|v2`: write a header in front of every backend
+//!   connection, so the backend learns the client IP even from raw bytes
+//! ```bash
+//! cargo run -- --port 8080 --send-proxy v2        # backend: raw_http --accept-proxy
+//! printf 'PROXY TCP4 203.0.113.7 127.0.0.1 40000 8080\r\nGET / HTTP/1.1\r\n\r\n' \
+//!     | nc 127.0.0.1 8090                         # a second proxy: --port 8090 --accept-proxy
+//! ```
+//!
+//! ## Extension: Strict Parsing
+//! - `--strict`: requests whose framing two parsers could read differently
+//!   (Content-Length with Transfer-Encoding, obs-fold, bare LF, ...) get a
+//!   400 naming the problem and never reach a backend
+//! - The checks and the test cases are shared with the raw HTTP lab:
+//!   `chapter_03_network/shared/strict.rs` and `smuggling_cases.rs`
+//! ```bash
+//! cargo run -- --port 8080 --strict
+//! printf 'POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 6\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\nG' \
+//!     | nc 127.0.0.1 8080                         # 400: both Content-Length and Transfer-Encoding
+//! ```
+
+#[path = "../../../shared/proxy_protocol.rs"]
+mod proxy_protocol;
+#[path = "../../../shared/strict.rs"]
+mod strict;
+mod tunnel;
 
+use proxy_protocol::Addresses;
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream};
+use tunnel::TunnelMode;
 
 // ============================================================
 // TODO: Implement the reverse proxy
@@ -60,6 +106,7 @@ const BACKENDS: &[&str] = &["127.0.0.1:8081", "127.0.0.1:8082"];
 static COUNTER: AtomicUsize = AtomicUsize::new(0);
 
 /// Select next backend using round-robin
+#[allow(dead_code)]
 fn next_backend() -> &'static str {
     next_backend_with_count().0
 }
@@ -113,13 +160,31 @@ fn add_x_forwarded_for(request: &[u8], client_addr: &str) -> Vec<u8> {
     out.extend_from_slice(&body_with_delim[4..]);
     out
 }
+
+/// Connect to a backend; with `--send-proxy`, tell it who the client is
+/// before anything else
+async fn connect_backend(backend: &str, client: &Addresses, config: &Config) -> Option<TcpStream> {
+    let mut stream = TcpStream::connect(backend).await.ok()?;
+    if let Some(version) = config.send_proxy {
+        let header = proxy_protocol::encode(version, client);
+        stream.write_all(&header).await.ok()?;
+    }
+    Some(stream)
+}
+
 /// Forward request to backend and return response
-async fn forward_request(request: &[u8], backend: &str, client_addr: &str) -> Option<Vec<u8>> {
+async fn forward_request(
+    request: &[u8],
+    backend: &str,
+    client: &Addresses,
+    config: &Config,
+) -> Option<Vec<u8>> {
     // TODO: Implement
     // 1. Connect to backend
-    let mut backend_stream = TcpStream::connect(backend).await.ok()?;
+    let mut backend_stream = connect_backend(backend, client, config).await?;
+    let client_addr = client.source.to_string();
     // 2. Add/modify X-Forwarded-For header
-    let forwarded = add_x_forwarded_for(request, client_addr);
+    let forwarded = add_x_forwarded_for(request, &client_addr);
     backend_stream.write_all(&forwarded).await.ok()?;
     // 3. Read response from backend (headers + optional body)
     let mut response = Vec::with_capacity(4096);
@@ -170,9 +235,25 @@ async fn forward_request(request: &[u8], backend: &str, client_addr: &str) -> Op
     Some(response)
 }
 
-/// Get client address as a string
-fn get_client_address(stream: &TcpStream) -> Option<String> {
-    stream.peer_addr().ok().map(|addr| addr.to_string())
+/// Where the connection really comes from: the PROXY header with
+/// `--accept-proxy`, otherwise the socket itself
+async fn client_addresses(stream: &mut TcpStream, config: &Config) -> Option<Addresses> {
+    let own = Addresses {
+        source: stream.peer_addr().ok()?,
+        destination: stream.local_addr().ok()?,
+    };
+    if !config.accept_proxy {
+        return Some(own);
+    }
+    match proxy_protocol::read_header(stream).await {
+        Ok(Some(addresses)) => Some(addresses),
+        // LOCAL / UNKNOWN: the balancer's own health check
+        Ok(None) => Some(own),
+        Err(e) => {
+            eprintln!("{}: bad PROXY header: {}", own.source, e);
+            None
+        }
+    }
 }
 
 /// Read an HTTP request from the client stream
@@ -188,17 +269,16 @@ async fn read_request(stream: &mut TcpStream) -> Option<Vec<u8>> {
             Err(_) => return None,
         };
         buf.extend_from_slice(&tmp[..n]);
-        header_end = buf.windows(4).position(|w| w == b"\r\n\r\n");
+        header_end = strict::head_end(&buf);
         if buf.len() > 64 * 1024 {
             return None;
         }
     }
 
-    let end_idx = header_end?;
-    let header_bytes = &buf[..end_idx];
-    let header_str = String::from_utf8_lossy(header_bytes);
+    let head_len = header_end?;
+    let header_str = String::from_utf8_lossy(&buf[..head_len]);
     let mut content_length = 0usize;
-    for line in header_str.split("\r\n") {
+    for line in header_str.lines() {
         if line.to_ascii_lowercase().starts_with("content-length:") {
             if let Some(v) = line.split(':').nth(1) {
                 content_length = v.trim().parse().unwrap_or(0);
@@ -206,7 +286,7 @@ async fn read_request(stream: &mut TcpStream) -> Option<Vec<u8>> {
         }
     }
 
-    let expected_len = end_idx + 4 + content_length;
+    let expected_len = head_len + content_length;
     while buf.len() < expected_len {
         let n = match stream.read(&mut tmp).await {
             Ok(0) => break,
@@ -219,30 +299,135 @@ async fn read_request(stream: &mut TcpStream) -> Option<Vec<u8>> {
     Some(buf)
 }
 
-/// Handle incoming client connection
-async fn handle_client(mut stream: TcpStream) {
-    // TODO: Implement
-    // 1. Get client address
-    let client_addr = match get_client_address(&stream) {
-        Some(addr) => addr,
+/// Look up a header value (case-insensitive) in the request head
+fn header_value(request: &[u8], name: &str) -> Option<String> {
+    let end = request.windows(4).position(|w| w == b"\r\n\r\n")?;
+    String::from_utf8_lossy(&request[..end])
+        .split("\r\n")
+        .skip(1)
+        .filter_map(|line| line.split_once(':'))
+        .find(|(key, _)| key.trim().eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.trim().to_string())
+}
+
+/// Bytes the client sent after the request head (e.g. an early TLS hello)
+fn bytes_after_head(request: &[u8]) -> &[u8] {
+    match request.windows(4).position(|w| w == b"\r\n\r\n") {
+        Some(end) => &request[end + 4..],
+        None => &[],
+    }
+}
+
+/// CONNECT host:port - reply 200, then relay raw bytes both ways
+async fn handle_connect(mut stream: TcpStream, target: &str, mode: TunnelMode, request: &[u8]) {
+    let mut upstream = match TcpStream::connect(target).await {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("CONNECT {} failed: {}", target, e);
+            let msg = b"HTTP/1.1 502 Bad Gateway\r\nContent-Length: 11\r\n\r\nBad Gateway";
+            let _ = stream.write_all(msg).await;
+            return;
+        }
+    };
+
+    if stream
+        .write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
+        .await
+        .is_err()
+    {
+        return;
+    }
+    let early = bytes_after_head(request);
+    if !early.is_empty() && upstream.write_all(early).await.is_err() {
+        return;
+    }
+
+    match tunnel::relay(stream, upstream, mode).await {
+        Ok((up, down)) => println!(
+            "tunnel {} closed ({}): {} bytes up, {} bytes down",
+            target,
+            mode.name(),
+            up,
+            down
+        ),
+        Err(e) => eprintln!("tunnel {} error: {}", target, e),
+    }
+}
+
+/// Upgrade (e.g. WebSocket): forward the handshake to a backend, then the
+/// connection is no longer HTTP - relay raw bytes, 101 response included
+async fn handle_upgrade(stream: TcpStream, request: &[u8], client: &Addresses, config: &Config) {
+    let (backend, _) = next_backend_with_count();
+    let mut backend_stream = match connect_backend(backend, client, config).await {
+        Some(s) => s,
         None => {
-            eprintln!("failed to get client address");
+            let mut stream = stream;
+            let msg = b"HTTP/1.1 502 Bad Gateway\r\nContent-Length: 11\r\n\r\nBad Gateway";
+            let _ = stream.write_all(msg).await;
             return;
         }
     };
-    println!("client connected: {}", client_addr);
+
+    let forwarded = add_x_forwarded_for(request, &client.source.to_string());
+    if backend_stream.write_all(&forwarded).await.is_err() {
+        return;
+    }
+    if let Err(e) = tunnel::relay(stream, backend_stream, config.tunnel).await {
+        eprintln!("upgrade tunnel to {} error: {}", backend, e);
+    }
+}
+
+/// Handle incoming client connection
+async fn handle_client(mut stream: TcpStream, config: &Config) {
+    // TODO: Implement
+    // 1. Get client address
+    let client = match client_addresses(&mut stream, config).await {
+        Some(addresses) => addresses,
+        None => return,
+    };
+    println!("client connected: {}", client.source);
     // 2. Read request
     let request = match read_request(&mut stream).await {
         Some(req) => req,
         None => return,
     };
-    
+    if config.strict {
+        let head_len = strict::head_end(&request).unwrap_or(request.len());
+        if let Err(reason) = strict::check(&request[..head_len]) {
+            eprintln!("{}: rejected: {}", client.source, reason);
+            let body = format!("400 Bad Request: {}", reason);
+            let msg = format!(
+                "HTTP/1.1 400 Bad Request\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            let _ = stream.write_all(msg.as_bytes()).await;
+            return;
+        }
+    }
+
+    // Tunnels: after the handshake these are raw bytes, not HTTP
+    let request_line = String::from_utf8_lossy(&request)
+        .split("\r\n")
+        .next()
+        .unwrap_or("")
+        .to_string();
+    let mut parts = request_line.split_whitespace();
+    if let (Some("CONNECT"), Some(target)) = (parts.next(), parts.next()) {
+        handle_connect(stream, target, config.tunnel, &request).await;
+        return;
+    }
+    if header_value(&request, "Upgrade").is_some() {
+        handle_upgrade(stream, &request, &client, config).await;
+        return;
+    }
+
     // 3. Select backend
     let (backend, count) = next_backend_with_count();
     println!("round-robin count: {}", count);
     println!("request redirect to backend: {}", backend);
     // 4. Forward request
-    let response = match forward_request(&request, backend, &client_addr).await {
+    let response = match forward_request(&request, backend, &client, config).await {
         Some(resp) => resp,
         None => {
             let msg = b"HTTP/1.1 502 Bad Gateway\r\nContent-Length: 11\r\n\r\nBad Gateway";
@@ -255,15 +440,78 @@ async fn handle_client(mut stream: TcpStream) {
     // 6. Handle errors gracefully
 }
 
+struct Config {
+    port: u16,
+    tunnel: TunnelMode,
+    /// Expect a PROXY header on every client connection
+    accept_proxy: bool,
+    /// Send a PROXY header on every backend connection
+    send_proxy: Option<proxy_protocol::Version>,
+    /// Answer ambiguous requests with 400 instead of forwarding them
+    strict: bool,
+}
+
+/// `reverse_proxy [--port N] [--tunnel copy|splice] [--accept-proxy] [--send-proxy v1|v2] [--strict]`
+fn parse_args() -> Config {
+    let mut config = Config {
+        port: 8080,
+        tunnel: TunnelMode::platform_default(),
+        accept_proxy: false,
+        send_proxy: None,
+        strict: false,
+    };
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        if arg == "--accept-proxy" {
+            config.accept_proxy = true;
+            continue;
+        }
+        if arg == "--strict" {
+            config.strict = true;
+            continue;
+        }
+        let value = args.next();
+        match (arg.as_str(), value.as_deref()) {
+            ("--port", Some(v)) if v.parse::<u16>().is_ok() => config.port = v.parse().unwrap(),
+            ("--tunnel", Some(v)) if TunnelMode::parse(v).is_some() => {
+                config.tunnel = TunnelMode::parse(v).unwrap()
+            }
+            ("--send-proxy", Some(v)) if proxy_protocol::Version::parse(v).is_some() => {
+                config.send_proxy = proxy_protocol::Version::parse(v)
+            }
+            _ => {
+                eprintln!(
+                    "usage: reverse_proxy [--port N] [--tunnel copy|splice] [--accept-proxy] [--send-proxy v1|v2] [--strict]"
+                );
+                std::process::exit(2);
+            }
+        }
+    }
+
+    config
+}
+
 #[tokio::main]
 async fn main() {
-    let addr = "127.0.0.1:8080";
+    let config = Arc::new(parse_args());
+    let addr = format!("127.0.0.1:{}", config.port);
 
     // TODO: Implement
     // 1. Bind listener
-    let listener = TcpListener::bind(addr).await.expect("Failed to bind");
+    let listener = TcpListener::bind(&addr).await.expect("Failed to bind");
     // 2. Print startup info
     println!("start proxy server at: {:#?}", addr);
+    println!("tunnel mode: {}", config.tunnel.name());
+    if config.accept_proxy {
+        println!("expecting PROXY protocol headers from clients");
+    }
+    if let Some(version) = config.send_proxy {
+        println!("sending PROXY protocol {:?} headers to backends", version);
+    }
+    if config.strict {
+        println!("strict request parsing: ambiguous requests get 400");
+    }
     // 3. Accept and handle connections
     loop {
         let (stream, _) = match listener.accept().await {
@@ -274,8 +522,9 @@ async fn main() {
             Err(_) => continue,
         };
 
+        let config = Arc::clone(&config);
         tokio::spawn(async move {
-            handle_client(stream).await;
+            handle_client(stream, &config).await;
         });
     }
 }
@@ -0,0 +1,140 @@
+//! Raw-bytes tunnel for CONNECT and protocol upgrades (WebSocket)
+//!
+//! Once a tunnel is up the proxy stops parsing HTTP: it only moves bytes
+//! between two sockets. Two ways to do that:
+//!
+//! - `copy`: the classic loop - read() into a userspace buffer, write() it
+//!   out. Every byte is copied kernel -> user -> kernel.
+//! - `splice` (Linux only): splice(2) socket -> pipe -> socket. The data
+//!   stays in kernel pages; the proxy only tells the kernel where to move it.
+//!
+//! Compare them with `cargo run --release --bin tunnel_bench`.
+
+use std::io;
+use tokio::net::TcpStream;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TunnelMode {
+    Copy,
+    Splice,
+}
+
+impl TunnelMode {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "copy" => Some(TunnelMode::Copy),
+            "splice" => Some(TunnelMode::Splice),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TunnelMode::Copy => "copy",
+            TunnelMode::Splice => "splice",
+        }
+    }
+
+    /// splice where the kernel has it, the copy loop everywhere else
+    pub fn platform_default() -> Self {
+        if cfg!(target_os = "linux") {
+            TunnelMode::Splice
+        } else {
+            TunnelMode::Copy
+        }
+    }
+}
+
+/// Relay bytes in both directions until both sides have closed.
+///
+/// Returns (client -> server bytes, server -> client bytes).
+pub async fn relay(
+    mut client: TcpStream,
+    mut server: TcpStream,
+    mode: TunnelMode,
+) -> io::Result<(u64, u64)> {
+    match mode {
+        TunnelMode::Copy => tokio::io::copy_bidirectional(&mut client, &mut server).await,
+        #[cfg(target_os = "linux")]
+        TunnelMode::Splice => splice::relay(&client, &server).await,
+        #[cfg(not(target_os = "linux"))]
+        TunnelMode::Splice => tokio::io::copy_bidirectional(&mut client, &mut server).await,
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod splice {
+    use nix::fcntl::{splice, OFlag, SpliceFFlags};
+    use nix::sys::socket::{shutdown, Shutdown};
+    use nix::unistd::pipe2;
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use tokio::io::Interest;
+    use tokio::net::TcpStream;
+
+    /// Default pipe capacity: the most one splice() into the pipe can move
+    const PIPE_CHUNK: usize = 64 * 1024;
+
+    pub async fn relay(client: &TcpStream, server: &TcpStream) -> io::Result<(u64, u64)> {
+        // readable()/writable() only need &TcpStream, so both directions can
+        // share the sockets without splitting them
+        tokio::try_join!(one_way(client, server), one_way(server, client))
+    }
+
+    /// src -> pipe -> dst until src reaches EOF, then half-close dst
+    async fn one_way(src: &TcpStream, dst: &TcpStream) -> io::Result<u64> {
+        let (read_end, write_end) = pipe2(OFlag::O_NONBLOCK | OFlag::O_CLOEXEC)?;
+        // OwnedFd closes both ends when this function returns
+        let (read_end, write_end) = unsafe {
+            (
+                OwnedFd::from_raw_fd(read_end),
+                OwnedFd::from_raw_fd(write_end),
+            )
+        };
+        let flags = SpliceFFlags::SPLICE_F_MOVE | SpliceFFlags::SPLICE_F_NONBLOCK;
+        let mut total = 0u64;
+
+        loop {
+            // EAGAIN becomes WouldBlock, and async_io waits for readiness and retries
+            let n = src
+                .async_io(Interest::READABLE, || {
+                    splice(
+                        src.as_raw_fd(),
+                        None,
+                        write_end.as_raw_fd(),
+                        None,
+                        PIPE_CHUNK,
+                        flags,
+                    )
+                    .map_err(io::Error::from)
+                })
+                .await?;
+            if n == 0 {
+                break;
+            }
+
+            // Empty the pipe before reading more, so it can never fill up
+            let mut left = n;
+            while left > 0 {
+                left -= dst
+                    .async_io(Interest::WRITABLE, || {
+                        splice(
+                            read_end.as_raw_fd(),
+                            None,
+                            dst.as_raw_fd(),
+                            None,
+                            left,
+                            flags,
+                        )
+                        .map_err(io::Error::from)
+                    })
+                    .await?;
+            }
+            total += n as u64;
+        }
+
+        // Pass the EOF on, like copy_bidirectional does
+        let _ = shutdown(dst.as_raw_fd(), Shutdown::Write);
+        Ok(total)
+    }
+}
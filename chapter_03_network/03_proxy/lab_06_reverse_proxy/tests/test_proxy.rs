@@ -1,5 +1,8 @@
 //! Lab 6 Tests
 
+#[path = "../../../shared/smuggling_cases.rs"]
+mod smuggling_cases;
+
 use std::io::{Read, Write};
 use std::net::TcpStream;
 use std::process::{Child, Command};
@@ -115,3 +118,269 @@ fn test_04_round_robin_counter() {
     assert_eq!(next_backend(), "server3");
     assert_eq!(next_backend(), "server1"); // Cycles back
 }
+
+/// Start the proxy binary directly on its own port with the given tunnel mode
+fn start_tunnel_proxy(port: u16, mode: &str) -> ServerGuard {
+    let child = Command::new(env!("CARGO_BIN_EXE_reverse_proxy"))
+        .args(["--port", &port.to_string(), "--tunnel", mode])
+        .spawn()
+        .expect("Failed to start proxy");
+    thread::sleep(Duration::from_millis(300));
+    ServerGuard { child }
+}
+
+/// Echo server on an ephemeral port: writes back everything, then closes
+fn start_echo_server() -> u16 {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    thread::spawn(move || {
+        if let Ok((mut stream, _)) = listener.accept() {
+            let mut buf = [0u8; 16 * 1024];
+            loop {
+                match stream.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => {
+                        if stream.write_all(&buf[..n]).is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+    });
+    port
+}
+
+/// CONNECT through the proxy, push 1 MiB, expect it all back followed by EOF
+fn check_connect_tunnel(proxy_port: u16, mode: &str) {
+    let _proxy = start_tunnel_proxy(proxy_port, mode);
+    let echo_port = start_echo_server();
+
+    let mut stream = TcpStream::connect(("127.0.0.1", proxy_port)).expect("connect to proxy");
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let connect = format!(
+        "CONNECT 127.0.0.1:{0} HTTP/1.1\r\nHost: 127.0.0.1:{0}\r\n\r\n",
+        echo_port
+    );
+    stream.write_all(connect.as_bytes()).unwrap();
+
+    let mut head = Vec::new();
+    let mut byte = [0u8; 1];
+    while !head.ends_with(b"\r\n\r\n") {
+        stream.read_exact(&mut byte).expect("read CONNECT reply");
+        head.push(byte[0]);
+    }
+    let head = String::from_utf8_lossy(&head);
+    assert!(
+        head.starts_with("HTTP/1.1 200"),
+        "unexpected reply: {}",
+        head
+    );
+
+    let payload: Vec<u8> = (0..1024 * 1024).map(|i| (i % 251) as u8).collect();
+    let mut writer = stream.try_clone().unwrap();
+    let sent = payload.clone();
+    let sender = thread::spawn(move || {
+        writer.write_all(&sent).unwrap();
+        // Half-close: the tunnel must pass the EOF on to the echo server
+        writer.shutdown(std::net::Shutdown::Write).unwrap();
+    });
+
+    let mut echoed = Vec::new();
+    stream.read_to_end(&mut echoed).expect("read echoed bytes");
+    sender.join().unwrap();
+    assert_eq!(echoed.len(), payload.len());
+    assert!(echoed == payload, "echoed bytes differ ({} mode)", mode);
+}
+
+#[test]
+fn test_05_connect_tunnel_copy() {
+    check_connect_tunnel(8111, "copy");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_06_connect_tunnel_splice() {
+    check_connect_tunnel(8112, "splice");
+}
+
+#[test]
+fn test_07_connect_unreachable_target_is_502() {
+    let _proxy = start_tunnel_proxy(8113, "copy");
+
+    // Grab a free port, then close it so nothing is listening there
+    let closed_port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let response = {
+        let mut stream = TcpStream::connect("127.0.0.1:8113").expect("connect to proxy");
+        stream
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        let request = format!("CONNECT 127.0.0.1:{} HTTP/1.1\r\n\r\n", closed_port);
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        let _ = stream.read_to_string(&mut response);
+        response
+    };
+    assert!(response.starts_with("HTTP/1.1 502"), "got: {}", response);
+}
+
+/// What a fake backend saw on one connection: the PROXY header (as the
+/// client's source address) and the request head
+fn read_proxied(stream: &TcpStream) -> (String, String) {
+    use std::io::BufRead;
+
+    let mut reader = std::io::BufReader::new(stream);
+    let source = match reader.fill_buf().ok().and_then(|buf| buf.first().copied()) {
+        // v2: signature, version/command, family, length, addresses
+        Some(b'\r') => {
+            let mut fixed = [0u8; 16];
+            reader.read_exact(&mut fixed).unwrap();
+            assert_eq!(&fixed[..12], b"\r\n\r\n\0\r\nQUIT\n");
+            assert_eq!(fixed[12..14], [0x21, 0x11], "PROXY command, TCP over IPv4");
+            let mut body = vec![0u8; u16::from_be_bytes([fixed[14], fixed[15]]) as usize];
+            reader.read_exact(&mut body).unwrap();
+            let ip = std::net::Ipv4Addr::new(body[0], body[1], body[2], body[3]);
+            format!("v2 {}:{}", ip, u16::from_be_bytes([body[8], body[9]]))
+        }
+        Some(b'P') => {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            format!("v1 {}:{}", fields[2], fields[4])
+        }
+        _ => "none".to_string(),
+    };
+
+    let mut head = String::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).unwrap_or(0) == 0 || line == "\r\n" {
+            break;
+        }
+        head.push_str(&line);
+    }
+    (source, head)
+}
+
+/// Stand-ins for both BACKENDS: answer every request with what they saw
+fn start_proxied_backends() {
+    for port in [8081, 8082] {
+        let Ok(listener) = std::net::TcpListener::bind(("127.0.0.1", port)) else {
+            continue;
+        };
+        thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let (source, head) = read_proxied(&stream);
+                let body = format!("source={}\n{}", source, head);
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+    }
+}
+
+fn start_proxy_with(port: u16, args: &[&str]) -> ServerGuard {
+    let child = Command::new(env!("CARGO_BIN_EXE_reverse_proxy"))
+        .args(["--port", &port.to_string(), "--tunnel", "copy"])
+        .args(args)
+        .spawn()
+        .expect("Failed to start proxy");
+    thread::sleep(Duration::from_millis(300));
+    ServerGuard { child }
+}
+
+fn send_raw(port: u16, request: &[u8]) -> String {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).expect("connect to proxy");
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    stream.write_all(request).unwrap();
+    let mut response = String::new();
+    let _ = stream.read_to_string(&mut response);
+    response
+}
+
+#[test]
+fn test_08_proxy_protocol_carries_client_through() {
+    start_proxied_backends();
+    let _proxy = start_proxy_with(8114, &["--accept-proxy", "--send-proxy", "v2"]);
+
+    let v1 = b"PROXY TCP4 203.0.113.7 127.0.0.1 40000 8114\r\nGET / HTTP/1.1\r\nHost: x\r\n\r\n";
+    let response = send_raw(8114, v1);
+    assert!(response.starts_with("HTTP/1.1 200"), "got: {}", response);
+    assert!(
+        response.contains("source=v2 203.0.113.7:40000"),
+        "{}",
+        response
+    );
+    assert!(
+        response.contains("X-Forwarded-For: 203.0.113.7:40000"),
+        "{}",
+        response
+    );
+
+    // The same client, announced with a v2 header this time
+    let mut v2 = b"\r\n\r\n\0\r\nQUIT\n\x21\x11\x00\x0c".to_vec();
+    v2.extend_from_slice(&[198, 51, 100, 9, 127, 0, 0, 1, 0x9c, 0x41, 0x1f, 0xd2]);
+    v2.extend_from_slice(b"GET / HTTP/1.1\r\nHost: x\r\n\r\n");
+    let response = send_raw(8114, &v2);
+    assert!(
+        response.contains("source=v2 198.51.100.9:40001"),
+        "{}",
+        response
+    );
+}
+
+#[test]
+fn test_09_send_proxy_v1_from_socket_address() {
+    start_proxied_backends();
+    let _proxy = start_proxy_with(8115, &["--send-proxy", "v1"]);
+
+    let response = send_raw(8115, b"GET / HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.contains("source=v1 127.0.0.1:"), "{}", response);
+}
+
+#[test]
+fn test_10_accept_proxy_closes_connections_without_header() {
+    let _proxy = start_proxy_with(8116, &["--accept-proxy"]);
+
+    let response = send_raw(8116, b"GET / HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(response.is_empty(), "got: {}", response);
+}
+
+#[test]
+fn test_11_strict_rejects_smuggling_before_the_backend() {
+    start_proxied_backends();
+    let _proxy = start_proxy_with(8128, &["--strict"]);
+
+    for (case, request) in smuggling_cases::REJECTED {
+        let response = send_raw(8128, request);
+        assert!(
+            response.starts_with("HTTP/1.1 400"),
+            "{}: got {:?}",
+            case,
+            response
+        );
+        // The stand-in backends echo `source=`: the request never got there
+        assert!(!response.contains("source="), "{}: forwarded", case);
+    }
+    for (case, request) in smuggling_cases::ACCEPTED {
+        let response = send_raw(8128, request);
+        assert!(
+            response.starts_with("HTTP/1.1 200") && response.contains("source="),
+            "{}: got {:?}",
+            case,
+            response
+        );
+    }
+}
@@ -0,0 +1,405 @@
+//! HAProxy PROXY protocol: who the client really is, at the TCP level
+//!
+//! A load balancer in TCP mode can't add `X-Forwarded-For` - it never looks
+//! at the bytes, and they may be TLS. Instead it writes one header in front
+//! of the connection, before the first byte from the client:
+//!
+//! ```text
+//! v1 (text, at most 107 bytes):
+//!   PROXY TCP4 203.0.113.7 192.0.2.1 40000 80\r\n
+//!   PROXY UNKNOWN\r\n                              <- addresses not known
+//!
+//! v2 (binary):
+//!   \r\n\r\n\0\r\nQUIT\n   12-byte signature
+//!   0x21                   version 2, command PROXY (0x20 = LOCAL)
+//!   0x11                   TCP over IPv4 (0x21 = TCP over IPv6)
+//!   0x00 0x0c              length of what follows
+//!   src ip, dst ip, src port, dst port   (big endian), then optional TLVs
+//! ```
+//!
+//! LOCAL / UNKNOWN are what the balancer sends for its own health checks:
+//! the connection's own addresses are the real ones.
+//!
+//! The receiving side must be configured to expect the header: whoever can
+//! connect directly could otherwise claim to be any address. That is why it
+//! is all or nothing per listener - no guessing whether a header is there.
+//!
+//! Two labs include this file with `#[path]`: the raw HTTP server
+//! (`02_http/lab_03_raw_http`) only reads headers, the reverse proxy
+//! (`03_proxy/lab_06_reverse_proxy`) also writes them with `encode`.
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+
+/// First 12 bytes of every v2 header; can't be the start of an HTTP request
+pub const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// Longest v1 line, CRLF included
+const V1_MAX_LEN: usize = 107;
+
+/// Longest v2 header: the fixed part plus a 16-bit length
+const V2_MAX_LEN: usize = 16 + u16::MAX as usize;
+
+/// How long a new connection may take to send its header
+const HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Version {
+    V1,
+    V2,
+}
+
+impl Version {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "v1" => Some(Version::V1),
+            "v2" => Some(Version::V2),
+            _ => None,
+        }
+    }
+}
+
+/// The two ends of the client's original connection
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Addresses {
+    pub source: SocketAddr,
+    pub destination: SocketAddr,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Parsed {
+    /// Could still become a valid header: read more
+    Incomplete,
+    /// A whole header of `len` bytes. `None` for LOCAL / UNKNOWN
+    Header {
+        addresses: Option<Addresses>,
+        len: usize,
+    },
+}
+
+/// Parse a v1 or v2 header at the start of `buf`
+pub fn parse(buf: &[u8]) -> Result<Parsed, String> {
+    if buf.len() < V2_SIGNATURE.len() && V2_SIGNATURE.starts_with(buf) {
+        return Ok(Parsed::Incomplete);
+    }
+    if buf.starts_with(&V2_SIGNATURE) {
+        return parse_v2(buf);
+    }
+    if buf.len() < 6 && b"PROXY ".starts_with(buf) {
+        return Ok(Parsed::Incomplete);
+    }
+    if buf.starts_with(b"PROXY ") {
+        return parse_v1(buf);
+    }
+    Err("no PROXY protocol header".to_string())
+}
+
+fn parse_v1(buf: &[u8]) -> Result<Parsed, String> {
+    let window = &buf[..buf.len().min(V1_MAX_LEN)];
+    let Some(end) = window.windows(2).position(|w| w == b"\r\n") else {
+        if buf.len() >= V1_MAX_LEN {
+            return Err("v1 header longer than 107 bytes".to_string());
+        }
+        return Ok(Parsed::Incomplete);
+    };
+    let line = std::str::from_utf8(&buf[..end]).map_err(|_| "v1 header is not ASCII")?;
+    let len = end + 2;
+
+    let fields: Vec<&str> = line.split(' ').collect();
+    let addresses = match fields[..] {
+        ["PROXY", "UNKNOWN", ..] => None,
+        ["PROXY", protocol @ ("TCP4" | "TCP6"), source, destination, source_port, destination_port] =>
+        {
+            let ip = |text: &str| -> Result<IpAddr, String> {
+                let ip = match protocol {
+                    "TCP4" => text.parse::<Ipv4Addr>().map(IpAddr::V4),
+                    _ => text.parse::<Ipv6Addr>().map(IpAddr::V6),
+                };
+                ip.map_err(|_| format!("bad {} address: {}", protocol, text))
+            };
+            let port = |text: &str| -> Result<u16, String> {
+                text.parse().map_err(|_| format!("bad port: {}", text))
+            };
+            Some(Addresses {
+                source: SocketAddr::new(ip(source)?, port(source_port)?),
+                destination: SocketAddr::new(ip(destination)?, port(destination_port)?),
+            })
+        }
+        _ => return Err(format!("bad v1 header: {:?}", line)),
+    };
+    Ok(Parsed::Header { addresses, len })
+}
+
+fn parse_v2(buf: &[u8]) -> Result<Parsed, String> {
+    if buf.len() < 16 {
+        return Ok(Parsed::Incomplete);
+    }
+    let version = buf[12] >> 4;
+    let command = buf[12] & 0x0f;
+    let family = buf[13] >> 4;
+    let body_len = u16::from_be_bytes([buf[14], buf[15]]) as usize;
+    if version != 2 {
+        return Err(format!("unsupported v2 version {}", version));
+    }
+    let len = 16 + body_len;
+    if buf.len() < len {
+        return Ok(Parsed::Incomplete);
+    }
+    let body = &buf[16..len];
+
+    let addresses = match (command, family) {
+        // LOCAL: a health check from the balancer itself, whatever the family
+        (0x0, _) => None,
+        (0x1, 0x0) => None,
+        (0x1, 0x1) => {
+            let block = body.get(..12).ok_or("v2 IPv4 address block too short")?;
+            let ip = |at: usize| IpAddr::from(<[u8; 4]>::try_from(&block[at..at + 4]).unwrap());
+            let port = |at: usize| u16::from_be_bytes([block[at], block[at + 1]]);
+            Some(Addresses {
+                source: SocketAddr::new(ip(0), port(8)),
+                destination: SocketAddr::new(ip(4), port(10)),
+            })
+        }
+        (0x1, 0x2) => {
+            let block = body.get(..36).ok_or("v2 IPv6 address block too short")?;
+            let ip = |at: usize| IpAddr::from(<[u8; 16]>::try_from(&block[at..at + 16]).unwrap());
+            let port = |at: usize| u16::from_be_bytes([block[at], block[at + 1]]);
+            Some(Addresses {
+                source: SocketAddr::new(ip(0), port(32)),
+                destination: SocketAddr::new(ip(16), port(34)),
+            })
+        }
+        (0x1, family) => return Err(format!("unsupported v2 address family {}", family)),
+        (command, _) => return Err(format!("unsupported v2 command {}", command)),
+    };
+    Ok(Parsed::Header { addresses, len })
+}
+
+/// The header that tells the next hop about `addresses`
+pub fn encode(version: Version, addresses: &Addresses) -> Vec<u8> {
+    // Both ends in one family; mixed pairs become IPv6 (v4-mapped)
+    let (source, destination) = match (addresses.source.ip(), addresses.destination.ip()) {
+        (IpAddr::V4(source), IpAddr::V4(destination)) => {
+            (IpAddr::V4(source), IpAddr::V4(destination))
+        }
+        (source, destination) => (IpAddr::V6(to_v6(source)), IpAddr::V6(to_v6(destination))),
+    };
+    let (source_port, destination_port) = (addresses.source.port(), addresses.destination.port());
+
+    match version {
+        Version::V1 => {
+            let protocol = if source.is_ipv4() { "TCP4" } else { "TCP6" };
+            format!(
+                "PROXY {} {} {} {} {}\r\n",
+                protocol, source, destination, source_port, destination_port
+            )
+            .into_bytes()
+        }
+        Version::V2 => {
+            let mut out = V2_SIGNATURE.to_vec();
+            out.push(0x21);
+            match (source, destination) {
+                (IpAddr::V4(source), IpAddr::V4(destination)) => {
+                    out.extend_from_slice(&[0x11, 0, 12]);
+                    out.extend_from_slice(&source.octets());
+                    out.extend_from_slice(&destination.octets());
+                }
+                (source, destination) => {
+                    out.extend_from_slice(&[0x21, 0, 36]);
+                    out.extend_from_slice(&to_v6(source).octets());
+                    out.extend_from_slice(&to_v6(destination).octets());
+                }
+            }
+            out.extend_from_slice(&source_port.to_be_bytes());
+            out.extend_from_slice(&destination_port.to_be_bytes());
+            out
+        }
+    }
+}
+
+fn to_v6(ip: IpAddr) -> Ipv6Addr {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+        IpAddr::V6(ip) => ip,
+    }
+}
+
+/// Take the header off a freshly accepted connection.
+///
+/// Peeks until the whole header has arrived, then reads exactly that many
+/// bytes: whatever follows (an HTTP request, a TLS hello) stays in the
+/// socket for the next layer. `None` means LOCAL / UNKNOWN.
+pub async fn read_header(stream: &mut TcpStream) -> io::Result<Option<Addresses>> {
+    let read = async {
+        let mut buf = vec![0u8; V2_MAX_LEN];
+        loop {
+            let n = stream.peek(&mut buf).await?;
+            if n == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            match parse(&buf[..n]).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))? {
+                Parsed::Header { addresses, len } => {
+                    stream.read_exact(&mut buf[..len]).await?;
+                    return Ok(addresses);
+                }
+                // peek returns at once while anything is queued: don't spin
+                Parsed::Incomplete => tokio::time::sleep(Duration::from_millis(5)).await,
+            }
+        }
+    };
+    tokio::time::timeout(HEADER_TIMEOUT, read)
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "no PROXY header in time"))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addresses(source: &str, destination: &str) -> Addresses {
+        Addresses {
+            source: source.parse().unwrap(),
+            destination: destination.parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_v1_tcp4() {
+        let buf = b"PROXY TCP4 203.0.113.7 192.0.2.1 40000 80\r\nGET / HTTP/1.1\r\n";
+        assert_eq!(
+            parse(buf),
+            Ok(Parsed::Header {
+                addresses: Some(addresses("203.0.113.7:40000", "192.0.2.1:80")),
+                len: 43,
+            })
+        );
+    }
+
+    #[test]
+    fn test_v1_tcp6_and_unknown() {
+        let buf = b"PROXY TCP6 2001:db8::7 2001:db8::1 40000 443\r\n";
+        assert_eq!(
+            parse(buf),
+            Ok(Parsed::Header {
+                addresses: Some(addresses("[2001:db8::7]:40000", "[2001:db8::1]:443")),
+                len: buf.len(),
+            })
+        );
+        assert_eq!(
+            parse(b"PROXY UNKNOWN\r\n"),
+            Ok(Parsed::Header {
+                addresses: None,
+                len: 15
+            })
+        );
+    }
+
+    #[test]
+    fn test_v1_rejects_bad_headers() {
+        for buf in [
+            &b"GET / HTTP/1.1\r\n\r\n"[..],
+            b"PROXY TCP4 203.0.113.7 192.0.2.1 40000\r\n",
+            b"PROXY TCP4 2001:db8::7 192.0.2.1 40000 80\r\n",
+            b"PROXY TCP4 203.0.113.7 192.0.2.1 40000 65536\r\n",
+            b"PROXY UDP4 203.0.113.7 192.0.2.1 40000 80\r\n",
+        ] {
+            assert!(parse(buf).is_err(), "{:?}", String::from_utf8_lossy(buf));
+        }
+        // No CRLF within 107 bytes
+        let mut long = b"PROXY TCP6 ".to_vec();
+        long.resize(200, b'1');
+        assert!(parse(&long).is_err());
+    }
+
+    #[test]
+    fn test_v2_tcp4_with_tlv() {
+        let mut buf = V2_SIGNATURE.to_vec();
+        buf.extend_from_slice(&[0x21, 0x11, 0, 12 + 4]);
+        buf.extend_from_slice(&[203, 0, 113, 7, 192, 0, 2, 1]);
+        buf.extend_from_slice(&[0x9c, 0x40, 0, 80]);
+        // A TLV (type, length, value) the parser skips
+        buf.extend_from_slice(&[0x04, 0, 1, 0xff]);
+        buf.extend_from_slice(b"GET /");
+        assert_eq!(
+            parse(&buf),
+            Ok(Parsed::Header {
+                addresses: Some(addresses("203.0.113.7:40000", "192.0.2.1:80")),
+                len: 32,
+            })
+        );
+    }
+
+    #[test]
+    fn test_v2_local_and_errors() {
+        let mut local = V2_SIGNATURE.to_vec();
+        local.extend_from_slice(&[0x20, 0x00, 0, 0]);
+        assert_eq!(
+            parse(&local),
+            Ok(Parsed::Header {
+                addresses: None,
+                len: 16
+            })
+        );
+
+        let mut version1 = V2_SIGNATURE.to_vec();
+        version1.extend_from_slice(&[0x11, 0x11, 0, 0]);
+        assert!(parse(&version1).is_err());
+
+        let mut short_block = V2_SIGNATURE.to_vec();
+        short_block.extend_from_slice(&[0x21, 0x21, 0, 12]);
+        short_block.extend_from_slice(&[0; 12]);
+        assert!(parse(&short_block).is_err());
+
+        let mut unix = V2_SIGNATURE.to_vec();
+        unix.extend_from_slice(&[0x21, 0x31, 0, 0]);
+        assert!(parse(&unix).is_err());
+    }
+
+    #[test]
+    fn test_partial_headers_are_incomplete() {
+        let v1 = encode(Version::V1, &addresses("203.0.113.7:40000", "192.0.2.1:80"));
+        let v2 = encode(
+            Version::V2,
+            &addresses("[2001:db8::7]:1", "[2001:db8::1]:2"),
+        );
+        for header in [v1, v2] {
+            for cut in 1..header.len() {
+                assert_eq!(parse(&header[..cut]), Ok(Parsed::Incomplete), "cut {}", cut);
+            }
+        }
+    }
+
+    #[test]
+    fn test_encode_round_trips() {
+        for (source, destination) in [
+            ("203.0.113.7:40000", "192.0.2.1:80"),
+            ("[2001:db8::7]:40000", "[2001:db8::1]:443"),
+        ] {
+            let original = addresses(source, destination);
+            for version in [Version::V1, Version::V2] {
+                let header = encode(version, &original);
+                assert_eq!(
+                    parse(&header),
+                    Ok(Parsed::Header {
+                        addresses: Some(original),
+                        len: header.len(),
+                    })
+                );
+            }
+        }
+        assert_eq!(
+            encode(Version::V1, &addresses("203.0.113.7:40000", "192.0.2.1:80")),
+            b"PROXY TCP4 203.0.113.7 192.0.2.1 40000 80\r\n"
+        );
+    }
+
+    #[test]
+    fn test_encode_mixed_families_as_ipv6() {
+        let mixed = addresses("203.0.113.7:40000", "[2001:db8::1]:443");
+        let header = encode(Version::V1, &mixed);
+        assert!(header.starts_with(b"PROXY TCP6 ::ffff:203.0.113.7 2001:db8::1 "));
+    }
+}
@@ -0,0 +1,101 @@
+//! Request-smuggling test cases, shared by two labs
+//!
+//! `02_http/lab_03_raw_http` and `03_proxy/lab_06_reverse_proxy` both run
+//! these bytes against their `--strict` mode:
+//!
+//! ```ignore
+//! #[path = "../../../shared/smuggling_cases.rs"]
+//! mod smuggling_cases;
+//! ```
+//!
+//! Every `REJECTED` request must get a 400 (and, through the proxy, never
+//! reach a backend); every `ACCEPTED` one is well-formed and must still be
+//! served. `GET /` is the target throughout, so a request that slips
+//! through gets a 200 from either lab.
+
+/// (what it tries, the bytes)
+pub const REJECTED: &[(&str, &[u8])] = &[
+    (
+        "CL.TE: front end counts bytes, back end reads chunks",
+        b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 6\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\nG",
+    ),
+    (
+        "TE.CL: front end reads chunks, back end counts bytes",
+        b"POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\nContent-Length: 3\r\n\r\n8\r\nSMUGGLED\r\n0\r\n\r\n",
+    ),
+    (
+        "two different Content-Lengths",
+        b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 0\r\nContent-Length: 5\r\n\r\nhello",
+    ),
+    (
+        "signed Content-Length",
+        b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: +5\r\n\r\nhello",
+    ),
+    (
+        "Content-Length list",
+        b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 5, 5\r\n\r\nhello",
+    ),
+    (
+        "obfuscated Transfer-Encoding value",
+        b"POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: xchunked\r\n\r\n0\r\n\r\n",
+    ),
+    (
+        "Transfer-Encoding list",
+        b"POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked, identity\r\n\r\n0\r\n\r\n",
+    ),
+    (
+        "space before the colon",
+        b"POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding : chunked\r\n\r\n0\r\n\r\n",
+    ),
+    (
+        "two Transfer-Encoding headers",
+        b"POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\nTransfer-Encoding: identity\r\n\r\n0\r\n\r\n",
+    ),
+    (
+        "Transfer-Encoding hidden in an obs-fold",
+        b"POST / HTTP/1.1\r\nHost: x\r\nX-Note: a\r\n Transfer-Encoding: chunked\r\n\r\n0\r\n\r\n",
+    ),
+    (
+        "bare LF line endings",
+        b"GET / HTTP/1.1\nHost: x\n\n",
+    ),
+    (
+        "one bare LF hides a header",
+        b"GET / HTTP/1.1\r\nHost: x\nContent-Length: 5\r\n\r\nhello",
+    ),
+    (
+        "bare CR inside a line",
+        b"GET / HTTP/1.1\r\nHost: x\rContent-Length: 5\r\n\r\nhello",
+    ),
+    (
+        "NUL byte in a header",
+        b"GET / HTTP/1.1\r\nHost: x\r\nX-Note: a\0b\r\n\r\n",
+    ),
+    (
+        "header line without a colon",
+        b"GET / HTTP/1.1\r\nHost: x\r\nContent-Length 5\r\n\r\nhello",
+    ),
+    (
+        "two Host headers",
+        b"GET / HTTP/1.1\r\nHost: x\r\nHost: y\r\n\r\n",
+    ),
+    (
+        "HTTP/1.1 without Host",
+        b"GET / HTTP/1.1\r\n\r\n",
+    ),
+    (
+        "double space in the request line",
+        b"GET  / HTTP/1.1\r\nHost: x\r\n\r\n",
+    ),
+];
+
+/// Well-formed requests that `--strict` must not reject
+pub const ACCEPTED: &[(&str, &[u8])] = &[
+    ("plain GET", b"GET / HTTP/1.1\r\nHost: x\r\n\r\n"),
+    (
+        "explicit empty body",
+        b"GET / HTTP/1.1\r\nHost: x\r\nContent-Length: 0\r\n\r\n",
+    ),
+    ("tab as whitespace", b"GET / HTTP/1.1\r\nHost:\tx\r\n\r\n"),
+    ("HTTP/1.0 needs no Host", b"GET / HTTP/1.0\r\n\r\n"),
+];
@@ -0,0 +1,124 @@
+//! Strict request parsing (`--strict`): refuse the heads smuggling needs
+//!
+//! A proxy and the server behind it parse every request twice. If they
+//! disagree on where a request ends, an attacker can hide a second request
+//! in the body of the first: the proxy forwards one request, the backend
+//! answers two, and the hidden one skips whatever the proxy checks. The
+//! disagreements that make this work:
+//!
+//! - `Content-Length` together with `Transfer-Encoding`: one side counts
+//!   bytes, the other reads chunks (CL.TE, TE.CL)
+//! - two `Content-Length`s, or one like `+5` that some parsers accept
+//! - `Transfer-Encoding: xchunked`, `chunked, identity`, or
+//!   `Transfer-Encoding : chunked`: chunked to one parser, not to another
+//! - bare LF line endings, or a header folded onto the next line
+//!   (obs-fold): a header one parser sees and the other doesn't
+//!
+//! RFC 9112 lets a server answer all of these with 400, and `check` names
+//! the first problem it finds. Two labs include this file with `#[path]`,
+//! so they can't drift apart on a security check:
+//!
+//! - `02_http/lab_03_raw_http`: without `--strict` the server stays
+//!   lenient, to compare
+//! - `03_proxy/lab_06_reverse_proxy`: the proxy is the place to stop them;
+//!   it answers before any backend sees a byte, whatever the backend's
+//!   parser would have made of it
+//!
+//! Both run the request bytes in `smuggling_cases.rs`, next to this file,
+//! against them.
+
+/// Length of the head including the blank line that ends it, if it is all
+/// in `buf`. A bare LF counts as a line end here, so a bare-LF request gets
+/// an answer (a 400 under `--strict`) instead of waiting for more bytes
+pub fn head_end(buf: &[u8]) -> Option<usize> {
+    (0..buf.len()).find_map(|i| match &buf[i..] {
+        [b'\n', b'\n', ..] => Some(i + 2),
+        [b'\n', b'\r', b'\n', ..] => Some(i + 3),
+        _ => None,
+    })
+}
+
+/// `tchar` from RFC 9110: what a method or header name may contain
+fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+/// `METHOD SP target SP HTTP/1.x`, single spaces; returns the version
+fn request_line(line: &str) -> Result<&str, &'static str> {
+    let parts: Vec<&str> = line.split(' ').collect();
+    let [method, target, version] = parts[..] else {
+        return Err("request line is not METHOD SP target SP version");
+    };
+    if !is_token(method) || target.is_empty() {
+        return Err("request line is not METHOD SP target SP version");
+    }
+    match version {
+        "HTTP/1.1" | "HTTP/1.0" => Ok(version),
+        _ => Err("unsupported HTTP version"),
+    }
+}
+
+/// Does `head` (request line, headers, blank line) frame unambiguously?
+/// The reason for the 400 if not
+pub fn check(head: &[u8]) -> Result<(), &'static str> {
+    // Line endings first: everything after this splits on CRLF
+    for (i, &b) in head.iter().enumerate() {
+        match b {
+            b'\n' if i == 0 || head[i - 1] != b'\r' => return Err("bare LF line ending"),
+            b'\r' if head.get(i + 1) != Some(&b'\n') => return Err("bare CR"),
+            0 => return Err("NUL byte"),
+            _ => {}
+        }
+    }
+    let text = std::str::from_utf8(head).map_err(|_| "head is not UTF-8")?;
+    let mut lines = text.split("\r\n");
+    let version = request_line(lines.next().unwrap_or(""))?;
+
+    let mut content_length = false;
+    let mut transfer_encoding = false;
+    let mut hosts = 0;
+    for line in lines.take_while(|line| !line.is_empty()) {
+        if line.starts_with([' ', '\t']) {
+            return Err("obs-fold: header continued on the next line");
+        }
+        let (name, value) = line.split_once(':').ok_or("header line without a colon")?;
+        if !is_token(name) {
+            // Catches `Transfer-Encoding : chunked` too
+            return Err("invalid header name");
+        }
+        let value = value.trim_matches([' ', '\t']);
+        if value.bytes().any(|b| (b < 0x20 && b != b'\t') || b == 0x7f) {
+            return Err("control character in a header value");
+        }
+
+        if name.eq_ignore_ascii_case("content-length") {
+            if content_length {
+                return Err("more than one Content-Length");
+            }
+            if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+                return Err("Content-Length is not a plain number");
+            }
+            content_length = true;
+        } else if name.eq_ignore_ascii_case("transfer-encoding") {
+            if transfer_encoding {
+                return Err("more than one Transfer-Encoding");
+            }
+            if !value.eq_ignore_ascii_case("chunked") {
+                return Err("Transfer-Encoding other than chunked");
+            }
+            transfer_encoding = true;
+        } else if name.eq_ignore_ascii_case("host") {
+            hosts += 1;
+        }
+    }
+
+    if content_length && transfer_encoding {
+        return Err("both Content-Length and Transfer-Encoding");
+    }
+    if version == "HTTP/1.1" && hosts != 1 {
+        return Err("HTTP/1.1 needs exactly one Host");
+    }
+    Ok(())
+}
@@ -0,0 +1,587 @@
+//! Lab 5 Reference Answer
+//!
+//! Several tasks write to one SQLite file while others read it. The same
+//! load runs under every combination of:
+//!
+//! - journal mode: DELETE (the rollback journal, SQLite's default) or WAL
+//! - busy_timeout: 0 (fail at once on a lock) or 5000ms (wait for it)
+//! - transaction start: BEGIN DEFERRED (lock on first write) or BEGIN
+//!   IMMEDIATE (take the write lock up front)
+//!
+//! with SQLite's default synchronous=FULL, then once more with the
+//! recommended settings, which also relax synchronous to NORMAL.
+//!
+//! Each write is a read-then-write transaction, the shape of most real ones:
+//! read the writer's last sequence number, insert the next. A transaction
+//! refused with SQLITE_BUSY is rolled back instead of retried, so the numbers
+//! show exactly when SQLite refuses work.
+
+use sqlx::sqlite::{
+    SqliteConnectOptions, SqliteConnection, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous,
+};
+use sqlx::{ConnectOptions, Connection};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// ============================================================
+// Settings under test
+// ============================================================
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Journal {
+    Delete,
+    Wal,
+}
+
+impl Journal {
+    fn name(self) -> &'static str {
+        match self {
+            Journal::Delete => "delete",
+            Journal::Wal => "wal",
+        }
+    }
+
+    fn mode(self) -> SqliteJournalMode {
+        match self {
+            Journal::Delete => SqliteJournalMode::Delete,
+            Journal::Wal => SqliteJournalMode::Wal,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Begin {
+    Deferred,
+    Immediate,
+}
+
+impl Begin {
+    fn name(self) -> &'static str {
+        match self {
+            Begin::Deferred => "deferred",
+            Begin::Immediate => "immediate",
+        }
+    }
+
+    fn sql(self) -> &'static str {
+        match self {
+            Begin::Deferred => "BEGIN DEFERRED",
+            Begin::Immediate => "BEGIN IMMEDIATE",
+        }
+    }
+}
+
+/// When a commit waits for the disk
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Synchronous {
+    /// fsync on every commit (SQLite's default)
+    Full,
+    /// In WAL: fsync only on checkpoints. A power cut may lose the last
+    /// commits, but never corrupts the file
+    Normal,
+}
+
+impl Synchronous {
+    fn name(self) -> &'static str {
+        match self {
+            Synchronous::Full => "full",
+            Synchronous::Normal => "normal",
+        }
+    }
+
+    fn mode(self) -> SqliteSynchronous {
+        match self {
+            Synchronous::Full => SqliteSynchronous::Full,
+            Synchronous::Normal => SqliteSynchronous::Normal,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Setup {
+    journal: Journal,
+    busy_timeout: Duration,
+    begin: Begin,
+    synchronous: Synchronous,
+}
+
+/// What the other labs' SQLite pools use, plus BEGIN IMMEDIATE for
+/// transactions that read before they write
+const RECOMMENDED: Setup = Setup {
+    journal: Journal::Wal,
+    busy_timeout: Duration::from_secs(5),
+    begin: Begin::Immediate,
+    synchronous: Synchronous::Normal,
+};
+
+// ============================================================
+// Experiment
+// ============================================================
+
+#[derive(Debug, Clone, Copy)]
+struct Load {
+    writers: usize,
+    readers: usize,
+    /// Transactions per writer
+    ops: usize,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Report {
+    /// Write transactions that committed (rows in the table)
+    committed: u64,
+    /// Write transactions refused with SQLITE_BUSY and rolled back
+    busy: u64,
+    /// Reads that returned a result
+    reads: u64,
+    /// Reads refused with SQLITE_BUSY
+    read_busy: u64,
+    /// From the first write to the last writer finishing
+    elapsed: Duration,
+}
+
+impl Report {
+    fn add(&mut self, other: Report) {
+        self.reads += other.reads;
+        self.read_busy += other.read_busy;
+    }
+}
+
+// SQLITE_BUSY (5) and SQLITE_LOCKED (6), including extended codes like
+// SQLITE_BUSY_SNAPSHOT (517): another connection holds the lock we need
+fn is_busy(err: &sqlx::Error) -> bool {
+    match err {
+        sqlx::Error::Database(db) => db
+            .code()
+            .and_then(|code| code.parse::<i32>().ok())
+            .is_some_and(|code| matches!(code & 0xff, 5 | 6)),
+        _ => false,
+    }
+}
+
+/// Create the table in a fresh file; the journal mode is set here, once.
+/// WAL is a property of the file and sticks for every later connection
+async fn create_db(path: &Path, journal: Journal) -> Result<(), sqlx::Error> {
+    remove_db(path);
+    let mut conn = SqliteConnectOptions::new()
+        .filename(path)
+        .create_if_missing(true)
+        .journal_mode(journal.mode())
+        .connect()
+        .await?;
+    sqlx::query(
+        "CREATE TABLE events (
+            id INTEGER PRIMARY KEY,
+            writer INTEGER NOT NULL,
+            seq INTEGER NOT NULL,
+            payload TEXT NOT NULL
+        )",
+    )
+    .execute(&mut conn)
+    .await?;
+    conn.close().await
+}
+
+/// The database file and the journal / WAL files next to it
+fn remove_db(path: &Path) {
+    for suffix in ["", "-journal", "-wal", "-shm"] {
+        let mut file = path.as_os_str().to_owned();
+        file.push(suffix);
+        let _ = std::fs::remove_file(file);
+    }
+}
+
+/// One read-then-write transaction; rolled back if any step fails
+async fn write_once(
+    conn: &mut SqliteConnection,
+    begin: Begin,
+    writer: i64,
+) -> Result<(), sqlx::Error> {
+    let result = async {
+        sqlx::query(begin.sql()).execute(&mut *conn).await?;
+        // A DEFERRED transaction holds only a read lock here ...
+        let last: i64 =
+            sqlx::query_scalar("SELECT COALESCE(MAX(seq), 0) FROM events WHERE writer = ?")
+                .bind(writer)
+                .fetch_one(&mut *conn)
+                .await?;
+        // ... and asks for the write lock here, too late to wait for it
+        sqlx::query("INSERT INTO events (writer, seq, payload) VALUES (?, ?, ?)")
+            .bind(writer)
+            .bind(last + 1)
+            .bind(format!("event {} from writer {}", last + 1, writer))
+            .execute(&mut *conn)
+            .await?;
+        sqlx::query("COMMIT").execute(&mut *conn).await?;
+        Ok(())
+    }
+    .await;
+    if result.is_err() {
+        // Even a BEGIN that failed can leave the transaction open. "No
+        // transaction is active" here is fine too
+        let _ = sqlx::query("ROLLBACK").execute(&mut *conn).await;
+    }
+    result
+}
+
+/// Run the load against a fresh database at `path` and leave the file there
+async fn run(path: &Path, setup: Setup, load: Load) -> Result<Report, sqlx::Error> {
+    create_db(path, setup.journal).await?;
+
+    // One connection per task, so tasks wait for SQLite's locks and never
+    // for the pool
+    let options = SqliteConnectOptions::new()
+        .filename(path)
+        .busy_timeout(setup.busy_timeout)
+        .synchronous(setup.synchronous.mode());
+    let pool = SqlitePoolOptions::new()
+        .max_connections((load.writers + load.readers) as u32)
+        .connect_with(options)
+        .await?;
+
+    let writing = Arc::new(AtomicBool::new(true));
+    let mut readers = Vec::with_capacity(load.readers);
+    for _ in 0..load.readers {
+        let pool = pool.clone();
+        let writing = Arc::clone(&writing);
+        readers.push(tokio::spawn(async move {
+            let mut conn = pool.acquire().await?;
+            let mut report = Report::default();
+            while writing.load(Ordering::Relaxed) {
+                let count: Result<i64, _> = sqlx::query_scalar("SELECT COUNT(*) FROM events")
+                    .fetch_one(&mut *conn)
+                    .await;
+                match count {
+                    Ok(_) => report.reads += 1,
+                    Err(err) if is_busy(&err) => report.read_busy += 1,
+                    Err(err) => return Err(err),
+                }
+            }
+            Ok::<_, sqlx::Error>(report)
+        }));
+    }
+
+    let start = Instant::now();
+    let mut writers = Vec::with_capacity(load.writers);
+    for writer in 0..load.writers {
+        let pool = pool.clone();
+        writers.push(tokio::spawn(async move {
+            let mut conn = pool.acquire().await?;
+            for _ in 0..load.ops {
+                match write_once(&mut conn, setup.begin, writer as i64).await {
+                    Err(err) if !is_busy(&err) => return Err(err),
+                    _ => {}
+                }
+            }
+            Ok::<_, sqlx::Error>(())
+        }));
+    }
+
+    let mut report = Report::default();
+    for writer in writers {
+        writer.await.expect("writer task panicked")?;
+    }
+    report.elapsed = start.elapsed();
+    writing.store(false, Ordering::Relaxed);
+    for reader in readers {
+        report.add(reader.await.expect("reader task panicked")?);
+    }
+
+    // Count commits in the table, not from the errors: a COMMIT that failed
+    // with SQLITE_BUSY may still complete when sqlx resets the statement
+    let rows: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM events")
+        .fetch_one(&pool)
+        .await?;
+    report.committed = rows as u64;
+    report.busy = (load.writers * load.ops) as u64 - report.committed;
+
+    pool.close().await;
+    Ok(report)
+}
+
+// ============================================================
+// Command line
+// ============================================================
+
+struct Config {
+    load: Load,
+    journals: Vec<Journal>,
+    busy_timeouts: Vec<Duration>,
+    begins: Vec<Begin>,
+    db: PathBuf,
+}
+
+fn usage() -> String {
+    "usage: sqlite_concurrency [--writers N] [--readers N] [--ops N] \
+     [--journal all|delete|wal] [--busy-timeout all|MS] [--begin all|deferred|immediate] [--db PATH]"
+        .to_string()
+}
+
+fn parse_args(args: Vec<String>) -> Result<Config, String> {
+    let mut config = Config {
+        load: Load {
+            writers: 8,
+            readers: 4,
+            ops: 50,
+        },
+        journals: vec![Journal::Delete, Journal::Wal],
+        busy_timeouts: vec![Duration::ZERO, RECOMMENDED.busy_timeout],
+        begins: vec![Begin::Deferred, Begin::Immediate],
+        db: std::env::temp_dir().join(format!("sqlite_concurrency_{}.db", std::process::id())),
+    };
+
+    let mut args = args.into_iter();
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value\n{}", flag, usage()))?;
+        let bad = || format!("invalid value for {}: {}", flag, value);
+
+        match flag.as_str() {
+            "--writers" => match value.parse() {
+                Ok(n) if n >= 1 => config.load.writers = n,
+                _ => return Err(bad()),
+            },
+            "--readers" => config.load.readers = value.parse().map_err(|_| bad())?,
+            "--ops" => match value.parse() {
+                Ok(n) if n >= 1 => config.load.ops = n,
+                _ => return Err(bad()),
+            },
+            "--journal" => {
+                config.journals = match value.as_str() {
+                    "all" => vec![Journal::Delete, Journal::Wal],
+                    "delete" => vec![Journal::Delete],
+                    "wal" => vec![Journal::Wal],
+                    _ => return Err(bad()),
+                }
+            }
+            "--busy-timeout" if value == "all" => {}
+            "--busy-timeout" => {
+                let ms = value.parse().map_err(|_| bad())?;
+                config.busy_timeouts = vec![Duration::from_millis(ms)];
+            }
+            "--begin" => {
+                config.begins = match value.as_str() {
+                    "all" => vec![Begin::Deferred, Begin::Immediate],
+                    "deferred" => vec![Begin::Deferred],
+                    "immediate" => vec![Begin::Immediate],
+                    _ => return Err(bad()),
+                }
+            }
+            "--db" => config.db = PathBuf::from(value),
+            _ => return Err(format!("unknown option {}\n{}", flag, usage())),
+        }
+    }
+
+    Ok(config)
+}
+
+#[tokio::main]
+async fn main() {
+    let config = match parse_args(std::env::args().skip(1).collect()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("sqlite_concurrency: {}", e);
+            std::process::exit(2);
+        }
+    };
+
+    let load = config.load;
+    println!(
+        "writers={} readers={} ops={} db={}",
+        load.writers,
+        load.readers,
+        load.ops,
+        config.db.display()
+    );
+    let mut setups = Vec::new();
+    for &journal in &config.journals {
+        for &busy_timeout in &config.busy_timeouts {
+            for &begin in &config.begins {
+                setups.push(Setup {
+                    journal,
+                    busy_timeout,
+                    begin,
+                    synchronous: Synchronous::Full,
+                });
+            }
+        }
+    }
+    setups.push(RECOMMENDED);
+
+    for setup in setups {
+        let report = match run(&config.db, setup, load).await {
+            Ok(report) => report,
+            Err(e) => {
+                eprintln!("sqlite_concurrency: {}", e);
+                remove_db(&config.db);
+                std::process::exit(1);
+            }
+        };
+        if setup == RECOMMENDED {
+            println!("recommended:");
+        }
+        let secs = report.elapsed.as_secs_f64().max(0.001);
+        println!(
+            "journal={:<6} busy_timeout_ms={:<4} begin={:<9} synchronous={:<6} committed={} busy={} reads={} read_busy={} elapsed_ms={} commits_per_sec={:.0}",
+            setup.journal.name(),
+            setup.busy_timeout.as_millis(),
+            setup.begin.name(),
+            setup.synchronous.name(),
+            report.committed,
+            report.busy,
+            report.reads,
+            report.read_busy,
+            report.elapsed.as_millis(),
+            report.committed as f64 / secs
+        );
+    }
+    remove_db(&config.db);
+}
+
+// Key concepts demonstrated:
+//
+// 1. BUSY_TIMEOUT TURNS REFUSALS INTO WAITS:
+//    - With 0, any lock held by someone else is an immediate SQLITE_BUSY
+//    - With a timeout SQLite retries until the lock frees up or time runs out
+//    - sqlx already defaults to 5s; raw SQLite (and many drivers) to 0
+//
+// 2. WAL LETS READERS AND THE WRITER RUN TOGETHER:
+//    - DELETE: a committing writer needs every reader out of the file, and
+//      readers are refused while it commits
+//    - WAL: readers see the last committed snapshot, never block, never
+//      get SQLITE_BUSY
+//    - Still one writer at a time in both modes
+//
+// 3. NO TIMEOUT SAVES A DEFERRED READ-THEN-WRITE:
+//    - Two transactions read, then both want to write: one must give up,
+//      or both wait forever. SQLite refuses at once, busy_timeout or not
+//    - WAL adds SQLITE_BUSY_SNAPSHOT: the write lock came free, but the
+//      transaction's snapshot is older than the last commit
+//    - BEGIN IMMEDIATE asks for the write lock first, where waiting is safe
+//
+// 4. THE DEFAULTS FOR A SQLITE FILE:
+//    - journal_mode=WAL, busy_timeout=5s, synchronous=NORMAL
+//    - BEGIN IMMEDIATE (or retry on SQLITE_BUSY) for transactions that read
+//      before they write
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SMALL: Load = Load {
+        writers: 4,
+        readers: 2,
+        ops: 20,
+    };
+
+    fn db_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "sqlite_concurrency_test_{}_{}.db",
+            name,
+            std::process::id()
+        ))
+    }
+
+    async fn rows(path: &Path) -> i64 {
+        let mut conn = SqliteConnectOptions::new()
+            .filename(path)
+            .connect()
+            .await
+            .unwrap();
+        sqlx::query_scalar("SELECT COUNT(*) FROM events")
+            .fetch_one(&mut conn)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_recommended_setup_commits_everything() {
+        let path = db_path("recommended");
+        let report = run(&path, RECOMMENDED, SMALL).await.unwrap();
+        assert_eq!(report.committed, 80);
+        assert_eq!(report.busy + report.read_busy, 0);
+        assert_eq!(rows(&path).await, 80);
+        remove_db(&path);
+    }
+
+    #[tokio::test]
+    async fn test_refused_transactions_leave_nothing_behind() {
+        let path = db_path("refused");
+        let setup = Setup {
+            journal: Journal::Delete,
+            busy_timeout: Duration::ZERO,
+            begin: Begin::Deferred,
+            synchronous: Synchronous::Full,
+        };
+        run(&path, setup, SMALL).await.unwrap();
+        // Each writer's sequence has no gaps or repeats, however many of
+        // its transactions were refused
+        let mut conn = SqliteConnectOptions::new()
+            .filename(&path)
+            .connect()
+            .await
+            .unwrap();
+        let gaps: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM (SELECT writer FROM events
+             GROUP BY writer HAVING MAX(seq) != COUNT(*))",
+        )
+        .fetch_one(&mut conn)
+        .await
+        .unwrap();
+        assert_eq!(gaps, 0);
+        conn.close().await.unwrap();
+        remove_db(&path);
+    }
+
+    #[tokio::test]
+    async fn test_wal_readers_never_busy() {
+        let path = db_path("wal_readers");
+        let setup = Setup {
+            busy_timeout: Duration::ZERO,
+            ..RECOMMENDED
+        };
+        let report = run(&path, setup, SMALL).await.unwrap();
+        assert_eq!(report.read_busy, 0);
+        assert!(report.reads > 0);
+        remove_db(&path);
+    }
+
+    #[tokio::test]
+    async fn test_wal_sticks_to_the_file() {
+        let path = db_path("sticky");
+        run(&path, RECOMMENDED, SMALL).await.unwrap();
+        // A new connection that asks for nothing still finds WAL
+        let mut conn = SqliteConnectOptions::new()
+            .filename(&path)
+            .connect()
+            .await
+            .unwrap();
+        let mode: String = sqlx::query_scalar("PRAGMA journal_mode")
+            .fetch_one(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(mode, "wal");
+        conn.close().await.unwrap();
+        remove_db(&path);
+    }
+
+    #[test]
+    fn test_parse_args_picks_one_combination() {
+        let args = [
+            "--journal",
+            "wal",
+            "--busy-timeout",
+            "250",
+            "--begin",
+            "immediate",
+        ];
+        let config = parse_args(args.iter().map(|s| s.to_string()).collect()).unwrap();
+        assert_eq!(config.journals, vec![Journal::Wal]);
+        assert_eq!(config.busy_timeouts, vec![Duration::from_millis(250)]);
+        assert_eq!(config.begins, vec![Begin::Immediate]);
+        assert!(parse_args(vec!["--begin".into(), "later".into()]).is_err());
+    }
+}
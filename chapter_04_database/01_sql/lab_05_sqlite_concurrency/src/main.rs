@@ -0,0 +1,486 @@
+//! Lab 5: SQLite Concurrency
+//!
+//! ## Goal
+//! Find out when SQLite makes concurrent writers wait and when it refuses
+//! them with SQLITE_BUSY, and pick pool options from the numbers
+//!
+//! ## Requirements
+//! 1. Several writer tasks (`--writers`, `--ops` transactions each) and
+//!    reader tasks (`--readers`) share one SQLite file, one connection each
+//! 2. Each write is a read-then-write transaction: read the writer's last
+//!    sequence number, insert the next one
+//! 3. A transaction refused with SQLITE_BUSY (code 5, 6 or an extended code
+//!    of them) is rolled back and counted, not retried
+//! 4. Run the load under every combination of journal mode (DELETE, WAL),
+//!    busy_timeout (0, 5000ms) and BEGIN DEFERRED / BEGIN IMMEDIATE, then
+//!    once more with the recommended settings (WAL, 5000ms, IMMEDIATE,
+//!    synchronous=NORMAL)
+//! 5. Report commits, refused writes, reads, refused reads and throughput
+//!
+//! ## Expected Output
+//! ```
+//! $ cargo run
+//! writers=8 readers=4 ops=50 db=/tmp/sqlite_concurrency_4242.db
+//! journal=delete busy_timeout_ms=0    begin=deferred  synchronous=full   committed=22 busy=378 reads=441 read_busy=327 ...
+//! journal=delete busy_timeout_ms=5000 begin=deferred  synchronous=full   committed=308 busy=92 reads=1452 read_busy=0 ...
+//! journal=delete busy_timeout_ms=5000 begin=immediate synchronous=full   committed=400 busy=0 reads=3489 read_busy=0 ...
+//! journal=wal    busy_timeout_ms=0    begin=deferred  synchronous=full   committed=106 busy=294 reads=1366 read_busy=0 ...
+//! journal=wal    busy_timeout_ms=5000 begin=deferred  synchronous=full   committed=89 busy=311 reads=1481 read_busy=0 ...
+//! journal=wal    busy_timeout_ms=5000 begin=immediate synchronous=full   committed=400 busy=0 reads=24228 read_busy=0 ...
+//! recommended:
+//! journal=wal    busy_timeout_ms=5000 begin=immediate synchronous=normal committed=400 busy=0 reads=10299 read_busy=0 ...
+//! ```
+//! (exact numbers vary from run to run and disk to disk)
+//!
+//! ## Hints
+//! - Set the journal mode once, on the connection that creates the file:
+//!   WAL is stored in the database and every later connection gets it
+//! - `SqliteConnectOptions::busy_timeout` is how long a statement waits for
+//!   a lock before giving up; sqlx defaults to 5s, SQLite itself to 0
+//! - Run BEGIN / COMMIT / ROLLBACK yourself with `sqlx::query(..)`: the
+//!   `pool.begin()` API always starts DEFERRED
+//! - After SQLITE_BUSY the transaction may still be open (roll it back) or,
+//!   for a COMMIT, finish later - count commits from the table afterwards
+//! - Delete the `-wal`, `-shm` and `-journal` files along with the database
+//!
+//! ## Verification
+//! ```bash
+//! cargo test
+//! cargo run -- --journal wal --busy-timeout 5000
+//! cargo run -- --writers 16 --readers 0 --begin deferred
+//! ```
+//!
+//! ## Acceptance Criteria
+//! - [ ] Every refused transaction is rolled back: no writer's sequence has
+//!   gaps or repeats
+//! - [ ] WAL readers are never refused, whatever the timeout
+//! - [ ] The recommended settings commit every write
+//! - [ ] Can explain why busy_timeout doesn't save a deferred read-then-write
+//!   transaction, and why BEGIN IMMEDIATE does
+//!
+//! Check solution/main.rs after completing
+
+use sqlx::sqlite::{
+    SqliteConnectOptions, SqliteConnection, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous,
+};
+use sqlx::{ConnectOptions, Connection};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// ============================================================
+// Settings under test
+// ============================================================
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Journal {
+    Delete,
+    Wal,
+}
+
+impl Journal {
+    fn name(self) -> &'static str {
+        match self {
+            Journal::Delete => "delete",
+            Journal::Wal => "wal",
+        }
+    }
+
+    fn mode(self) -> SqliteJournalMode {
+        match self {
+            Journal::Delete => SqliteJournalMode::Delete,
+            Journal::Wal => SqliteJournalMode::Wal,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Begin {
+    Deferred,
+    Immediate,
+}
+
+impl Begin {
+    fn name(self) -> &'static str {
+        match self {
+            Begin::Deferred => "deferred",
+            Begin::Immediate => "immediate",
+        }
+    }
+
+    fn sql(self) -> &'static str {
+        match self {
+            Begin::Deferred => "BEGIN DEFERRED",
+            Begin::Immediate => "BEGIN IMMEDIATE",
+        }
+    }
+}
+
+/// When a commit waits for the disk
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Synchronous {
+    /// fsync on every commit (SQLite's default)
+    Full,
+    /// In WAL: fsync only on checkpoints. A power cut may lose the last
+    /// commits, but never corrupts the file
+    Normal,
+}
+
+impl Synchronous {
+    fn name(self) -> &'static str {
+        match self {
+            Synchronous::Full => "full",
+            Synchronous::Normal => "normal",
+        }
+    }
+
+    fn mode(self) -> SqliteSynchronous {
+        match self {
+            Synchronous::Full => SqliteSynchronous::Full,
+            Synchronous::Normal => SqliteSynchronous::Normal,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Setup {
+    journal: Journal,
+    busy_timeout: Duration,
+    begin: Begin,
+    synchronous: Synchronous,
+}
+
+/// What the other labs' SQLite pools use, plus BEGIN IMMEDIATE for
+/// transactions that read before they write
+const RECOMMENDED: Setup = Setup {
+    journal: Journal::Wal,
+    busy_timeout: Duration::from_secs(5),
+    begin: Begin::Immediate,
+    synchronous: Synchronous::Normal,
+};
+
+// ============================================================
+// Experiment
+// ============================================================
+
+#[derive(Debug, Clone, Copy)]
+struct Load {
+    writers: usize,
+    readers: usize,
+    /// Transactions per writer
+    ops: usize,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Report {
+    /// Write transactions that committed (rows in the table)
+    committed: u64,
+    /// Write transactions refused with SQLITE_BUSY and rolled back
+    busy: u64,
+    /// Reads that returned a result
+    reads: u64,
+    /// Reads refused with SQLITE_BUSY
+    read_busy: u64,
+    /// From the first write to the last writer finishing
+    elapsed: Duration,
+}
+
+impl Report {
+    fn add(&mut self, other: Report) {
+        self.reads += other.reads;
+        self.read_busy += other.read_busy;
+    }
+}
+
+// SQLITE_BUSY (5) and SQLITE_LOCKED (6), including extended codes like
+// SQLITE_BUSY_SNAPSHOT (517): another connection holds the lock we need
+fn is_busy(err: &sqlx::Error) -> bool {
+    match err {
+        sqlx::Error::Database(db) => db
+            .code()
+            .and_then(|code| code.parse::<i32>().ok())
+            .is_some_and(|code| matches!(code & 0xff, 5 | 6)),
+        _ => false,
+    }
+}
+
+/// Create the table in a fresh file; the journal mode is set here, once.
+/// WAL is a property of the file and sticks for every later connection
+async fn create_db(path: &Path, journal: Journal) -> Result<(), sqlx::Error> {
+    remove_db(path);
+    let mut conn = SqliteConnectOptions::new()
+        .filename(path)
+        .create_if_missing(true)
+        .journal_mode(journal.mode())
+        .connect()
+        .await?;
+    sqlx::query(
+        "CREATE TABLE events (
+            id INTEGER PRIMARY KEY,
+            writer INTEGER NOT NULL,
+            seq INTEGER NOT NULL,
+            payload TEXT NOT NULL
+        )",
+    )
+    .execute(&mut conn)
+    .await?;
+    conn.close().await
+}
+
+/// The database file and the journal / WAL files next to it
+fn remove_db(path: &Path) {
+    for suffix in ["", "-journal", "-wal", "-shm"] {
+        let mut file = path.as_os_str().to_owned();
+        file.push(suffix);
+        let _ = std::fs::remove_file(file);
+    }
+}
+
+/// One read-then-write transaction; rolled back if any step fails
+async fn write_once(
+    conn: &mut SqliteConnection,
+    begin: Begin,
+    writer: i64,
+) -> Result<(), sqlx::Error> {
+    let result = async {
+        sqlx::query(begin.sql()).execute(&mut *conn).await?;
+        // A DEFERRED transaction holds only a read lock here ...
+        let last: i64 =
+            sqlx::query_scalar("SELECT COALESCE(MAX(seq), 0) FROM events WHERE writer = ?")
+                .bind(writer)
+                .fetch_one(&mut *conn)
+                .await?;
+        // ... and asks for the write lock here, too late to wait for it
+        sqlx::query("INSERT INTO events (writer, seq, payload) VALUES (?, ?, ?)")
+            .bind(writer)
+            .bind(last + 1)
+            .bind(format!("event {} from writer {}", last + 1, writer))
+            .execute(&mut *conn)
+            .await?;
+        sqlx::query("COMMIT").execute(&mut *conn).await?;
+        Ok(())
+    }
+    .await;
+    if result.is_err() {
+        // Even a BEGIN that failed can leave the transaction open. "No
+        // transaction is active" here is fine too
+        let _ = sqlx::query("ROLLBACK").execute(&mut *conn).await;
+    }
+    result
+}
+
+/// Run the load against a fresh database at `path` and leave the file there
+async fn run(path: &Path, setup: Setup, load: Load) -> Result<Report, sqlx::Error> {
+    create_db(path, setup.journal).await?;
+
+    // One connection per task, so tasks wait for SQLite's locks and never
+    // for the pool
+    let options = SqliteConnectOptions::new()
+        .filename(path)
+        .busy_timeout(setup.busy_timeout)
+        .synchronous(setup.synchronous.mode());
+    let pool = SqlitePoolOptions::new()
+        .max_connections((load.writers + load.readers) as u32)
+        .connect_with(options)
+        .await?;
+
+    let writing = Arc::new(AtomicBool::new(true));
+    let mut readers = Vec::with_capacity(load.readers);
+    for _ in 0..load.readers {
+        let pool = pool.clone();
+        let writing = Arc::clone(&writing);
+        readers.push(tokio::spawn(async move {
+            let mut conn = pool.acquire().await?;
+            let mut report = Report::default();
+            while writing.load(Ordering::Relaxed) {
+                let count: Result<i64, _> = sqlx::query_scalar("SELECT COUNT(*) FROM events")
+                    .fetch_one(&mut *conn)
+                    .await;
+                match count {
+                    Ok(_) => report.reads += 1,
+                    Err(err) if is_busy(&err) => report.read_busy += 1,
+                    Err(err) => return Err(err),
+                }
+            }
+            Ok::<_, sqlx::Error>(report)
+        }));
+    }
+
+    let start = Instant::now();
+    let mut writers = Vec::with_capacity(load.writers);
+    for writer in 0..load.writers {
+        let pool = pool.clone();
+        writers.push(tokio::spawn(async move {
+            let mut conn = pool.acquire().await?;
+            for _ in 0..load.ops {
+                match write_once(&mut conn, setup.begin, writer as i64).await {
+                    Err(err) if !is_busy(&err) => return Err(err),
+                    _ => {}
+                }
+            }
+            Ok::<_, sqlx::Error>(())
+        }));
+    }
+
+    let mut report = Report::default();
+    for writer in writers {
+        writer.await.expect("writer task panicked")?;
+    }
+    report.elapsed = start.elapsed();
+    writing.store(false, Ordering::Relaxed);
+    for reader in readers {
+        report.add(reader.await.expect("reader task panicked")?);
+    }
+
+    // Count commits in the table, not from the errors: a COMMIT that failed
+    // with SQLITE_BUSY may still complete when sqlx resets the statement
+    let rows: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM events")
+        .fetch_one(&pool)
+        .await?;
+    report.committed = rows as u64;
+    report.busy = (load.writers * load.ops) as u64 - report.committed;
+
+    pool.close().await;
+    Ok(report)
+}
+
+// ============================================================
+// Command line
+// ============================================================
+
+struct Config {
+    load: Load,
+    journals: Vec<Journal>,
+    busy_timeouts: Vec<Duration>,
+    begins: Vec<Begin>,
+    db: PathBuf,
+}
+
+fn usage() -> String {
+    "usage: sqlite_concurrency [--writers N] [--readers N] [--ops N] \
+     [--journal all|delete|wal] [--busy-timeout all|MS] [--begin all|deferred|immediate] [--db PATH]"
+        .to_string()
+}
+
+fn parse_args(args: Vec<String>) -> Result<Config, String> {
+    let mut config = Config {
+        load: Load {
+            writers: 8,
+            readers: 4,
+            ops: 50,
+        },
+        journals: vec![Journal::Delete, Journal::Wal],
+        busy_timeouts: vec![Duration::ZERO, RECOMMENDED.busy_timeout],
+        begins: vec![Begin::Deferred, Begin::Immediate],
+        db: std::env::temp_dir().join(format!("sqlite_concurrency_{}.db", std::process::id())),
+    };
+
+    let mut args = args.into_iter();
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value\n{}", flag, usage()))?;
+        let bad = || format!("invalid value for {}: {}", flag, value);
+
+        match flag.as_str() {
+            "--writers" => match value.parse() {
+                Ok(n) if n >= 1 => config.load.writers = n,
+                _ => return Err(bad()),
+            },
+            "--readers" => config.load.readers = value.parse().map_err(|_| bad())?,
+            "--ops" => match value.parse() {
+                Ok(n) if n >= 1 => config.load.ops = n,
+                _ => return Err(bad()),
+            },
+            "--journal" => {
+                config.journals = match value.as_str() {
+                    "all" => vec![Journal::Delete, Journal::Wal],
+                    "delete" => vec![Journal::Delete],
+                    "wal" => vec![Journal::Wal],
+                    _ => return Err(bad()),
+                }
+            }
+            "--busy-timeout" if value == "all" => {}
+            "--busy-timeout" => {
+                let ms = value.parse().map_err(|_| bad())?;
+                config.busy_timeouts = vec![Duration::from_millis(ms)];
+            }
+            "--begin" => {
+                config.begins = match value.as_str() {
+                    "all" => vec![Begin::Deferred, Begin::Immediate],
+                    "deferred" => vec![Begin::Deferred],
+                    "immediate" => vec![Begin::Immediate],
+                    _ => return Err(bad()),
+                }
+            }
+            "--db" => config.db = PathBuf::from(value),
+            _ => return Err(format!("unknown option {}\n{}", flag, usage())),
+        }
+    }
+
+    Ok(config)
+}
+
+#[tokio::main]
+async fn main() {
+    let config = match parse_args(std::env::args().skip(1).collect()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("sqlite_concurrency: {}", e);
+            std::process::exit(2);
+        }
+    };
+
+    let load = config.load;
+    println!(
+        "writers={} readers={} ops={} db={}",
+        load.writers,
+        load.readers,
+        load.ops,
+        config.db.display()
+    );
+    let mut setups = Vec::new();
+    for &journal in &config.journals {
+        for &busy_timeout in &config.busy_timeouts {
+            for &begin in &config.begins {
+                setups.push(Setup {
+                    journal,
+                    busy_timeout,
+                    begin,
+                    synchronous: Synchronous::Full,
+                });
+            }
+        }
+    }
+    setups.push(RECOMMENDED);
+
+    for setup in setups {
+        let report = match run(&config.db, setup, load).await {
+            Ok(report) => report,
+            Err(e) => {
+                eprintln!("sqlite_concurrency: {}", e);
+                remove_db(&config.db);
+                std::process::exit(1);
+            }
+        };
+        if setup == RECOMMENDED {
+            println!("recommended:");
+        }
+        let secs = report.elapsed.as_secs_f64().max(0.001);
+        println!(
+            "journal={:<6} busy_timeout_ms={:<4} begin={:<9} synchronous={:<6} committed={} busy={} reads={} read_busy={} elapsed_ms={} commits_per_sec={:.0}",
+            setup.journal.name(),
+            setup.busy_timeout.as_millis(),
+            setup.begin.name(),
+            setup.synchronous.name(),
+            report.committed,
+            report.busy,
+            report.reads,
+            report.read_busy,
+            report.elapsed.as_millis(),
+            report.committed as f64 / secs
+        );
+    }
+    remove_db(&config.db);
+}
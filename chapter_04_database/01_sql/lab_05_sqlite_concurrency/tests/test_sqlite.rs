@@ -0,0 +1,152 @@
+//! Lab 5 Tests - SQLite Concurrency
+//!
+//! Run with: cargo test
+
+use std::collections::HashMap;
+use std::process::Command;
+
+fn run_experiment(args: &[&str]) -> (String, String, bool) {
+    let output = Command::new(env!("CARGO_BIN_EXE_sqlite_concurrency"))
+        .args(args)
+        .output()
+        .expect("Failed to execute program");
+
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+
+    (stdout, stderr, output.status.success())
+}
+
+/// Parse `journal=... key=value ...` lines; the line after `recommended:`
+/// gets `recommended=1`
+fn rows(stdout: &str) -> Vec<HashMap<String, String>> {
+    let mut recommended = false;
+    let mut rows = Vec::new();
+    for line in stdout.lines() {
+        if line == "recommended:" {
+            recommended = true;
+            continue;
+        }
+        if !line.starts_with("journal=") {
+            continue;
+        }
+        let mut row: HashMap<String, String> = line
+            .split_whitespace()
+            .filter_map(|field| {
+                let (key, value) = field.split_once('=')?;
+                Some((key.to_string(), value.to_string()))
+            })
+            .collect();
+        if recommended {
+            row.insert("recommended".to_string(), "1".to_string());
+        }
+        rows.push(row);
+    }
+    rows
+}
+
+fn number(row: &HashMap<String, String>, key: &str) -> u64 {
+    row[key].parse().expect("numeric field")
+}
+
+fn run_rows(args: &[&str]) -> Vec<HashMap<String, String>> {
+    let (stdout, stderr, success) = run_experiment(args);
+    assert!(success, "experiment failed: {}", stderr);
+    rows(&stdout)
+}
+
+const SMALL: [&str; 6] = ["--writers", "4", "--readers", "2", "--ops", "20"];
+
+#[test]
+fn test_01_full_matrix_plus_recommended() {
+    let rows = run_rows(&SMALL);
+    assert_eq!(rows.len(), 9, "2 journals x 2 timeouts x 2 begins + 1");
+
+    for row in &rows {
+        assert_eq!(
+            number(row, "committed") + number(row, "busy"),
+            80,
+            "every transaction either commits or is refused: {:?}",
+            row
+        );
+    }
+
+    let recommended = rows.last().unwrap();
+    assert_eq!(recommended["recommended"], "1");
+    assert_eq!(recommended["journal"], "wal");
+    assert_eq!(recommended["busy_timeout_ms"], "5000");
+    assert_eq!(recommended["begin"], "immediate");
+    assert_eq!(recommended["synchronous"], "normal");
+}
+
+#[test]
+fn test_02_recommended_settings_commit_everything() {
+    let rows = run_rows(&SMALL);
+    let recommended = rows.last().unwrap();
+    assert_eq!(number(recommended, "committed"), 80);
+    assert_eq!(number(recommended, "busy"), 0);
+    assert_eq!(number(recommended, "read_busy"), 0);
+}
+
+#[test]
+fn test_03_wal_readers_are_never_refused() {
+    let mut args = SMALL.to_vec();
+    args.extend(["--journal", "wal", "--busy-timeout", "0"]);
+    for row in run_rows(&args) {
+        assert_eq!(number(&row, "read_busy"), 0, "{:?}", row);
+        assert!(number(&row, "reads") > 0, "{:?}", row);
+    }
+}
+
+#[test]
+fn test_04_immediate_with_timeout_never_refuses() {
+    let args = [
+        "--writers",
+        "8",
+        "--readers",
+        "2",
+        "--ops",
+        "25",
+        "--busy-timeout",
+        "5000",
+        "--begin",
+        "immediate",
+    ];
+    for row in run_rows(&args) {
+        assert_eq!(number(&row, "committed"), 200, "{:?}", row);
+        assert_eq!(number(&row, "busy"), 0, "{:?}", row);
+    }
+}
+
+#[test]
+fn test_05_single_writer_never_contends() {
+    let args = [
+        "--writers",
+        "1",
+        "--readers",
+        "0",
+        "--ops",
+        "30",
+        "--busy-timeout",
+        "0",
+    ];
+    for row in run_rows(&args) {
+        assert_eq!(number(&row, "committed"), 30, "{:?}", row);
+    }
+}
+
+#[test]
+fn test_06_invalid_arguments() {
+    for args in [
+        &["--journal", "truncate"][..],
+        &["--begin", "later"],
+        &["--busy-timeout", "-1"],
+        &["--writers", "0"],
+        &["--ops"],
+        &["--bogus", "1"],
+    ] {
+        let (_, stderr, success) = run_experiment(args);
+        assert!(!success, "{:?} should be rejected", args);
+        assert!(!stderr.is_empty(), "{:?} should explain the error", args);
+    }
+}
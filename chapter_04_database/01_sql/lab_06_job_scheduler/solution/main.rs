@@ -0,0 +1,635 @@
+//! Lab 6 Reference Answer
+//!
+//! Start the same worker binary as many times as you like: every scheduled
+//! run of every job is done by exactly one of them. Coordination uses only
+//! the Postgres the service already has:
+//!
+//! - `scheduled_jobs` holds each job's period and next due time
+//! - `pg_try_advisory_lock(namespace, hashtext(job))` decides who runs a due
+//!   job; the others see `false` and move on
+//! - under the lock the worker checks the job is still due, runs it, and
+//!   moves `next_run_at` on
+//! - while a job runs, a heartbeat asks `pg_locks` whether the lock is still
+//!   ours; a lost lock (connection dropped, backend terminated) abandons the
+//!   run, and another worker retries it
+//!
+//! Every attempt is recorded in `job_runs`, so "exactly one" can be checked
+//! with SQL.
+
+use sqlx::postgres::{PgConnectOptions, PgConnection, PgPool, PgPoolOptions};
+use sqlx::{ConnectOptions, Connection};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// First key of every advisory lock this lab takes ("JOB"), so they can't
+/// collide with other applications' locks on the same database
+const LOCK_NAMESPACE: i32 = 0x004A_4F42;
+
+/// Second key of the lock that serializes schema setup
+const SETUP_LOCK: i32 = 0;
+
+/// How often each job checks whether it is due
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How often a running job checks it still holds its lock. A lost lock is
+/// noticed at most this late - while another worker may already run the job
+const HEARTBEAT_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Pause before reconnecting after a database error
+const RECONNECT_DELAY: Duration = Duration::from_millis(500);
+
+// ============================================================
+// Jobs
+// ============================================================
+
+#[derive(Debug, Clone, PartialEq)]
+struct Job {
+    name: String,
+    every: Duration,
+    /// How long the simulated work takes
+    work: Duration,
+}
+
+/// `name:every_ms:work_ms`, e.g. `cleanup:2000:300`
+fn parse_job(spec: &str) -> Result<Job, String> {
+    let bad = || format!("invalid job '{}', expected name:every_ms:work_ms", spec);
+    let mut parts = spec.split(':');
+    let (Some(name), Some(every), Some(work), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(bad());
+    };
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err(bad());
+    }
+    let every: u64 = every.parse().map_err(|_| bad())?;
+    let work: u64 = work.parse().map_err(|_| bad())?;
+    if every == 0 {
+        return Err(format!("job '{}' must run every 1ms or more", name));
+    }
+    Ok(Job {
+        name: name.to_string(),
+        every: Duration::from_millis(every),
+        work: Duration::from_millis(work),
+    })
+}
+
+// ============================================================
+// Metrics
+// ============================================================
+
+#[derive(Default)]
+struct Metrics {
+    /// Runs this worker finished
+    runs: AtomicU64,
+    /// Due jobs whose lock another worker held
+    contended: AtomicU64,
+    /// Lock taken, but the previous holder had just run the job
+    already_ran: AtomicU64,
+    /// Runs abandoned because the lock went away mid-run
+    lock_lost: AtomicU64,
+    /// Database errors (each followed by a reconnect)
+    errors: AtomicU64,
+}
+
+impl Metrics {
+    fn line(&self, worker: &str) -> String {
+        format!(
+            "metrics worker={} runs={} contended={} already_ran={} lock_lost={} errors={}",
+            worker,
+            self.runs.load(Ordering::Relaxed),
+            self.contended.load(Ordering::Relaxed),
+            self.already_ran.load(Ordering::Relaxed),
+            self.lock_lost.load(Ordering::Relaxed),
+            self.errors.load(Ordering::Relaxed)
+        )
+    }
+}
+
+fn bump(counter: &AtomicU64) {
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+// ============================================================
+// Advisory locks
+// ============================================================
+//
+// Session-level locks belong to a connection, not to a task: take them on a
+// dedicated PgConnection. Taken through a pool, the lock stays with whatever
+// connection ran the query - which goes back to the pool still holding it.
+
+/// Take the job's lock if nobody holds it; never waits
+async fn try_lock(conn: &mut PgConnection, job: &str) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar("SELECT pg_try_advisory_lock($1, hashtext($2))")
+        .bind(LOCK_NAMESPACE)
+        .bind(job)
+        .fetch_one(conn)
+        .await
+}
+
+async fn unlock(conn: &mut PgConnection, job: &str) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar("SELECT pg_advisory_unlock($1, hashtext($2))")
+        .bind(LOCK_NAMESPACE)
+        .bind(job)
+        .fetch_one(conn)
+        .await
+}
+
+/// Does this session still hold the job's lock? An error means the
+/// connection is gone, and the lock with it
+async fn still_held(conn: &mut PgConnection, job: &str) -> Result<bool, sqlx::Error> {
+    // Two-key locks show up as classid / objid (oids) with objsubid = 2
+    sqlx::query_scalar(
+        "SELECT EXISTS (
+            SELECT 1 FROM pg_locks
+            WHERE locktype = 'advisory' AND granted AND pid = pg_backend_pid()
+              AND classid = $1::oid AND objid = hashtext($2)::oid AND objsubid = 2
+        )",
+    )
+    .bind(LOCK_NAMESPACE)
+    .bind(job)
+    .fetch_one(conn)
+    .await
+}
+
+// ============================================================
+// Scheduling
+// ============================================================
+
+/// Create the tables. Workers often start together, and concurrent
+/// CREATE TABLE IF NOT EXISTS can still fail, so setup runs under a
+/// transaction-level advisory lock, released by the commit
+async fn setup_schema(pool: &PgPool) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("SELECT pg_advisory_xact_lock($1, $2)")
+        .bind(LOCK_NAMESPACE)
+        .bind(SETUP_LOCK)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS scheduled_jobs (
+            name TEXT PRIMARY KEY,
+            every_ms BIGINT NOT NULL,
+            next_run_at TIMESTAMPTZ NOT NULL DEFAULT now()
+        )",
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS job_runs (
+            id BIGSERIAL PRIMARY KEY,
+            job TEXT NOT NULL,
+            worker TEXT NOT NULL,
+            due_at TIMESTAMPTZ NOT NULL,
+            started_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+            finished_at TIMESTAMPTZ,
+            outcome TEXT NOT NULL DEFAULT 'running'
+        )",
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await
+}
+
+/// Add the job, or update its period; a schedule already running keeps its
+/// next due time
+async fn register(pool: &PgPool, job: &Job) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO scheduled_jobs (name, every_ms) VALUES ($1, $2)
+         ON CONFLICT (name) DO UPDATE SET every_ms = EXCLUDED.every_ms",
+    )
+    .bind(&job.name)
+    .bind(job.every.as_millis() as i64)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// What one look at a job came to
+#[derive(Debug, PartialEq)]
+enum Attempt {
+    NotDue,
+    Contended,
+    AlreadyRan,
+    Done,
+    LockLost,
+}
+
+async fn attempt(
+    pool: &PgPool,
+    conn: &mut PgConnection,
+    worker: &str,
+    job: &Job,
+) -> Result<Attempt, sqlx::Error> {
+    // Cheap check first, without the lock
+    let due: bool =
+        sqlx::query_scalar("SELECT next_run_at <= now() FROM scheduled_jobs WHERE name = $1")
+            .bind(&job.name)
+            .fetch_one(pool)
+            .await?;
+    if !due {
+        return Ok(Attempt::NotDue);
+    }
+    if !try_lock(conn, &job.name).await? {
+        return Ok(Attempt::Contended);
+    }
+
+    // Check again under the lock: the previous holder may have run it and
+    // moved next_run_at on between our check and our lock
+    let run: Option<(i64, String)> = sqlx::query_as(
+        "INSERT INTO job_runs (job, worker, due_at)
+         SELECT name, $2, next_run_at FROM scheduled_jobs
+         WHERE name = $1 AND next_run_at <= now()
+         RETURNING id, to_char(due_at, 'HH24:MI:SS.MS')",
+    )
+    .bind(&job.name)
+    .bind(worker)
+    .fetch_optional(pool)
+    .await?;
+    let Some((run_id, due_at)) = run else {
+        unlock(conn, &job.name).await?;
+        return Ok(Attempt::AlreadyRan);
+    };
+
+    println!(
+        "[{}] {}: run #{} (due {})",
+        worker, job.name, run_id, due_at
+    );
+    let started = Instant::now();
+    // The job itself; a real one would do its work here
+    let work = tokio::time::sleep(job.work);
+    tokio::pin!(work);
+    let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+    let held = loop {
+        tokio::select! {
+            _ = &mut work => break true,
+            _ = heartbeat.tick() => {
+                if !still_held(conn, &job.name).await.unwrap_or(false) {
+                    break false;
+                }
+            }
+        }
+    };
+
+    if !held {
+        // Dropping the work future stops the job
+        println!(
+            "[{}] {}: LOST LOCK during run #{}, abandoning it",
+            worker, job.name, run_id
+        );
+        sqlx::query("UPDATE job_runs SET finished_at = now(), outcome = 'lock_lost' WHERE id = $1")
+            .bind(run_id)
+            .execute(pool)
+            .await?;
+        return Ok(Attempt::LockLost);
+    }
+
+    // Record the run and move the schedule on, together. A worker that
+    // fell behind runs once now rather than once per missed period
+    let mut tx = pool.begin().await?;
+    sqlx::query("UPDATE job_runs SET finished_at = now(), outcome = 'done' WHERE id = $1")
+        .bind(run_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(
+        "UPDATE scheduled_jobs
+         SET next_run_at = GREATEST(next_run_at + every_ms * interval '1 millisecond', now())
+         WHERE name = $1",
+    )
+    .bind(&job.name)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    unlock(conn, &job.name).await?;
+
+    println!(
+        "[{}] {}: run #{} done in {}ms",
+        worker,
+        job.name,
+        run_id,
+        started.elapsed().as_millis()
+    );
+    Ok(Attempt::Done)
+}
+
+/// One job on one worker: poll, attempt, reconnect after errors
+async fn job_loop(
+    options: PgConnectOptions,
+    pool: PgPool,
+    worker: Arc<str>,
+    job: Job,
+    metrics: Arc<Metrics>,
+    mut stop: watch::Receiver<bool>,
+) {
+    // Named per job, so `pg_stat_activity` shows which connection holds what
+    let options = options.application_name(&format!("job_scheduler:{}:{}", worker, job.name));
+    let mut conn: Option<PgConnection> = None;
+    let mut poll = tokio::time::interval(POLL_INTERVAL);
+
+    loop {
+        tokio::select! {
+            _ = stop.changed() => break,
+            _ = poll.tick() => {}
+        }
+        let lock_conn = match conn.as_mut() {
+            Some(lock_conn) => lock_conn,
+            None => match options.connect().await {
+                Ok(new_conn) => conn.insert(new_conn),
+                Err(err) => {
+                    eprintln!("[{}] {}: connect failed: {}", worker, job.name, err);
+                    bump(&metrics.errors);
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                    continue;
+                }
+            },
+        };
+
+        match attempt(&pool, lock_conn, &worker, &job).await {
+            Ok(Attempt::NotDue) => {}
+            Ok(Attempt::Contended) => bump(&metrics.contended),
+            Ok(Attempt::AlreadyRan) => bump(&metrics.already_ran),
+            Ok(Attempt::Done) => bump(&metrics.runs),
+            Ok(Attempt::LockLost) => {
+                bump(&metrics.lock_lost);
+                // Whatever state that session is in, start a fresh one
+                conn = None;
+            }
+            Err(err) => {
+                eprintln!("[{}] {}: {}", worker, job.name, err);
+                bump(&metrics.errors);
+                // The lock may or may not be held: closing the session is
+                // the one way to be sure it is released
+                conn = None;
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        }
+    }
+
+    if let Some(conn) = conn {
+        let _ = conn.close().await;
+    }
+}
+
+// ============================================================
+// Command line
+// ============================================================
+
+#[derive(Debug)]
+struct Config {
+    worker: String,
+    jobs: Vec<Job>,
+    /// Stop after this long; None = until Ctrl-C
+    duration: Option<Duration>,
+    report_every: Duration,
+}
+
+fn default_jobs() -> Vec<Job> {
+    ["cleanup:2000:300", "report:5000:1500", "sync:1000:100"]
+        .iter()
+        .map(|spec| parse_job(spec).expect("valid default job"))
+        .collect()
+}
+
+fn usage() -> String {
+    "usage: job_scheduler [--worker NAME] [--job name:every_ms:work_ms]... \
+     [--duration SECS] [--report-secs SECS]"
+        .to_string()
+}
+
+fn parse_args(args: Vec<String>) -> Result<Config, String> {
+    let mut config = Config {
+        worker: format!("worker-{}", std::process::id()),
+        jobs: Vec::new(),
+        duration: None,
+        report_every: Duration::from_secs(5),
+    };
+
+    let mut args = args.into_iter();
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value\n{}", flag, usage()))?;
+        let bad = || format!("invalid value for {}: {}", flag, value);
+
+        match flag.as_str() {
+            "--worker" if !value.is_empty() => config.worker = value,
+            "--job" => {
+                let job = parse_job(&value)?;
+                if config.jobs.iter().any(|j| j.name == job.name) {
+                    return Err(format!("job '{}' given twice", job.name));
+                }
+                config.jobs.push(job);
+            }
+            "--duration" => {
+                let secs: f64 = value.parse().map_err(|_| bad())?;
+                config.duration = Some(Duration::try_from_secs_f64(secs).map_err(|_| bad())?);
+            }
+            "--report-secs" => match value.parse() {
+                Ok(secs) if secs >= 1 => config.report_every = Duration::from_secs(secs),
+                _ => return Err(bad()),
+            },
+            _ => return Err(format!("unknown option {}\n{}", flag, usage())),
+        }
+    }
+
+    if config.jobs.is_empty() {
+        config.jobs = default_jobs();
+    }
+    Ok(config)
+}
+
+#[tokio::main]
+async fn main() {
+    dotenvy::dotenv().ok();
+    let config = match parse_args(std::env::args().skip(1).collect()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("job_scheduler: {}", e);
+            std::process::exit(2);
+        }
+    };
+    let Ok(url) = std::env::var("DATABASE_URL") else {
+        eprintln!("job_scheduler: DATABASE_URL must be set");
+        std::process::exit(2);
+    };
+
+    let options = match PgConnectOptions::from_str(&url) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("job_scheduler: invalid DATABASE_URL: {}", e);
+            std::process::exit(2);
+        }
+    };
+    let pool = PgPoolOptions::new()
+        .max_connections(4)
+        .acquire_timeout(Duration::from_secs(5))
+        .connect_with(
+            options
+                .clone()
+                .application_name(&format!("job_scheduler:{}", config.worker)),
+        )
+        .await;
+    let pool = match pool {
+        Ok(pool) => pool,
+        Err(e) => {
+            eprintln!("job_scheduler: cannot connect: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let registered = async {
+        setup_schema(&pool).await?;
+        for job in &config.jobs {
+            register(&pool, job).await?;
+        }
+        Ok::<_, sqlx::Error>(())
+    };
+    if let Err(e) = registered.await {
+        eprintln!("job_scheduler: setup failed: {}", e);
+        std::process::exit(1);
+    }
+
+    let jobs: Vec<String> = config
+        .jobs
+        .iter()
+        .map(|job| {
+            format!(
+                "{}(every {}ms, {}ms work)",
+                job.name,
+                job.every.as_millis(),
+                job.work.as_millis()
+            )
+        })
+        .collect();
+    println!("worker={} jobs={}", config.worker, jobs.join(", "));
+
+    let worker: Arc<str> = Arc::from(config.worker.as_str());
+    let metrics = Arc::new(Metrics::default());
+    let (stop, stopped) = watch::channel(false);
+    let mut loops = Vec::new();
+    for job in config.jobs {
+        loops.push(tokio::spawn(job_loop(
+            options.clone(),
+            pool.clone(),
+            Arc::clone(&worker),
+            job,
+            Arc::clone(&metrics),
+            stopped.clone(),
+        )));
+    }
+
+    let deadline = async {
+        match config.duration {
+            Some(duration) => tokio::time::sleep(duration).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(deadline);
+    let mut report = tokio::time::interval(config.report_every);
+    report.tick().await;
+    loop {
+        tokio::select! {
+            _ = &mut deadline => break,
+            _ = tokio::signal::ctrl_c() => break,
+            _ = report.tick() => println!("{}", metrics.line(&worker)),
+        }
+    }
+
+    // A job in the middle of a run finishes it first
+    let _ = stop.send(true);
+    for handle in loops {
+        let _ = handle.await;
+    }
+    pool.close().await;
+    println!("{}", metrics.line(&worker));
+}
+
+// Key concepts demonstrated:
+//
+// 1. THE DATABASE AS COORDINATOR:
+//    - No ZooKeeper, etcd or Redis: every worker already talks to Postgres
+//    - pg_try_advisory_lock never waits, so a busy job costs one round trip
+//    - A crashed worker's session ends and Postgres drops its locks
+//
+// 2. LOCK + STATE, NOT LOCK ALONE:
+//    - The lock only says "one at a time"; next_run_at says "not again"
+//    - Re-check the state after taking the lock: the previous holder may
+//      have just finished
+//
+// 3. SESSION LOCKS BELONG TO CONNECTIONS:
+//    - Take them on a dedicated connection, never through a pool
+//    - pg_advisory_xact_lock (setup_schema) is the pool-safe variant:
+//      released at commit, but holds a transaction open meanwhile
+//
+// 4. A LOCK CAN BE LOST:
+//    - Network blip, failover, pg_terminate_backend: the lock is gone and
+//      another worker may start the same job
+//    - The heartbeat bounds how long two runs can overlap; it can't make
+//      it zero. Jobs must tolerate a rare overlap (idempotent writes), or
+//      check a fencing token (the run id) where they write
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_job() {
+        assert_eq!(
+            parse_job("cleanup:2000:300"),
+            Ok(Job {
+                name: "cleanup".to_string(),
+                every: Duration::from_millis(2000),
+                work: Duration::from_millis(300),
+            })
+        );
+        for spec in [
+            "cleanup",
+            "cleanup:2000",
+            "a:b:c",
+            ":1:1",
+            "x:0:1",
+            "x:1:1:1",
+        ] {
+            assert!(parse_job(spec).is_err(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn test_default_jobs_when_none_given() {
+        let config = parse_args(args(&["--worker", "a"])).unwrap();
+        assert_eq!(config.worker, "a");
+        assert_eq!(config.jobs, default_jobs());
+        assert_eq!(config.duration, None);
+    }
+
+    #[test]
+    fn test_jobs_replace_defaults() {
+        let config = parse_args(args(&["--job", "tick:100:10", "--duration", "1.5"])).unwrap();
+        assert_eq!(config.jobs.len(), 1);
+        assert_eq!(config.duration, Some(Duration::from_millis(1500)));
+        assert!(parse_args(args(&["--job", "a:1:1", "--job", "a:2:2"])).is_err());
+        assert!(parse_args(args(&["--duration", "-1"])).is_err());
+    }
+
+    #[test]
+    fn test_metrics_line() {
+        let metrics = Metrics::default();
+        bump(&metrics.runs);
+        bump(&metrics.runs);
+        bump(&metrics.lock_lost);
+        assert_eq!(
+            metrics.line("a"),
+            "metrics worker=a runs=2 contended=0 already_ran=0 lock_lost=1 errors=0"
+        );
+    }
+}
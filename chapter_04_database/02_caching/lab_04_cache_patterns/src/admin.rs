@@ -0,0 +1,166 @@
+//! Admin protocol for the running cache
+//!
+//! One request line, one reply line, over a Unix socket or TCP. Replies
+//! start with `OK`, `NOT_FOUND` or `ERR`:
+//!
+//! ```text
+//! GET user:1                  -> OK {"id":1,"name":"Alice",...} ttl=42s
+//! SET user:9 60 {"id":9,...}  -> OK
+//! DEL user:9                  -> OK deleted=1
+//! STATS                       -> OK hits=5 misses=3 hit_rate=62.5% keys=2
+//! FLUSH                       -> OK flushed=2
+//! WARM 300                    -> OK warmed=3
+//! ```
+//!
+//! Values are JSON, like everything else in the cache; SET rejects anything
+//! that doesn't parse, so `Cache::get` never meets a value it can't read.
+//!
+//! There is no authentication: the Unix socket is protected by file
+//! permissions, and `--tcp` should stay on 127.0.0.1.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, UnixListener};
+
+use crate::{Cache, Database};
+
+/// Where `serve` listens and `cache_admin` connects unless told otherwise
+pub const DEFAULT_SOCKET: &str = "/tmp/cache_patterns.sock";
+
+pub async fn serve(
+    cache: Arc<Cache>,
+    db: Arc<Database>,
+    uds: PathBuf,
+    tcp: Option<String>,
+) -> std::io::Result<()> {
+    // A socket file left by a previous run would make bind fail
+    let _ = std::fs::remove_file(&uds);
+    let unix = UnixListener::bind(&uds)?;
+    println!("Admin socket: {}", uds.display());
+    let tcp = match tcp {
+        Some(addr) => {
+            let listener = TcpListener::bind(&addr).await?;
+            println!("Admin TCP:    {}", addr);
+            Some(listener)
+        }
+        None => None,
+    };
+
+    loop {
+        tokio::select! {
+            accepted = unix.accept() => match accepted {
+                Ok((stream, _)) => {
+                    tokio::spawn(handle(stream, Arc::clone(&cache), Arc::clone(&db)));
+                }
+                Err(err) => accept_failed(err).await,
+            },
+            // Without --tcp this branch never fires
+            accepted = async { tcp.as_ref().unwrap().accept().await }, if tcp.is_some() => match accepted {
+                Ok((stream, _)) => {
+                    tokio::spawn(handle(stream, Arc::clone(&cache), Arc::clone(&db)));
+                }
+                Err(err) => accept_failed(err).await,
+            },
+        }
+    }
+}
+
+/// A failed accept (EMFILE under load, say) loses one admin connection, not
+/// the cache: log it and keep listening. The pending connection stays in the
+/// backlog until a descriptor frees up, so pause rather than spin on it.
+async fn accept_failed(err: std::io::Error) {
+    eprintln!("Admin accept error: {}", err);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+}
+
+/// Answer request lines until the client hangs up
+async fn handle<S>(stream: S, cache: Arc<Cache>, db: Arc<Database>)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = execute(&line, &cache, &db).await;
+        println!("[admin] {} -> {}", line.trim(), reply);
+        if writer
+            .write_all(format!("{}\n", reply).as_bytes())
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+}
+
+async fn execute(line: &str, cache: &Cache, db: &Database) -> String {
+    // SET's value is the rest of the line and may contain spaces
+    let mut parts = line.trim().splitn(4, ' ');
+    let command = parts.next().unwrap_or("").to_ascii_uppercase();
+    let args: Vec<&str> = parts.collect();
+
+    match (command.as_str(), args.as_slice()) {
+        ("GET", [key]) => match cache.peek(key) {
+            Some((value, left)) => format!("OK {} ttl={}s", value, left.as_secs()),
+            None => "NOT_FOUND".to_string(),
+        },
+        ("SET", [key, ttl, value]) => {
+            let Some(ttl) = parse_ttl(ttl) else {
+                return format!(
+                    "ERR ttl must be a positive number of seconds, got '{}'",
+                    ttl
+                );
+            };
+            if let Err(err) = serde_json::from_str::<serde_json::Value>(value) {
+                return format!("ERR value is not JSON: {}", err);
+            }
+            cache.set_raw(key, value.to_string(), ttl);
+            "OK".to_string()
+        }
+        ("DEL", [key]) => format!("OK deleted={}", cache.delete(key) as u8),
+        ("STATS", []) => {
+            let (hits, misses, hit_rate) = cache.stats();
+            format!(
+                "OK hits={} misses={} hit_rate={:.1}% keys={}",
+                hits,
+                misses,
+                hit_rate,
+                cache.live_keys()
+            )
+        }
+        ("FLUSH", []) => format!("OK flushed={}", cache.flush()),
+        ("WARM", [ttl]) => {
+            let Some(ttl) = parse_ttl(ttl) else {
+                return format!(
+                    "ERR ttl must be a positive number of seconds, got '{}'",
+                    ttl
+                );
+            };
+            // Fill the cache before traffic arrives, so the first requests
+            // after a deploy or a flush don't all go to the database
+            let users = db.all_users().await;
+            for user in &users {
+                let value = serde_json::to_string(user).expect("User serializes");
+                cache.set_raw(&format!("user:{}", user.id), value, ttl);
+            }
+            format!("OK warmed={}", users.len())
+        }
+        ("GET" | "DEL", _) => format!("ERR usage: {} <key>", command),
+        ("SET", _) => "ERR usage: SET <key> <ttl_secs> <json>".to_string(),
+        ("WARM", _) => "ERR usage: WARM <ttl_secs>".to_string(),
+        ("STATS" | "FLUSH", _) => format!("ERR usage: {}", command),
+        _ => format!("ERR unknown command '{}'", command),
+    }
+}
+
+fn parse_ttl(text: &str) -> Option<Duration> {
+    match text.parse::<u64>() {
+        Ok(secs) if secs > 0 => Some(Duration::from_secs(secs)),
+        _ => None,
+    }
+}
@@ -0,0 +1,144 @@
+//! cache_admin: operate a running `cache_patterns serve`
+//!
+//! ```bash
+//! cache_admin get user:1
+//! cache_admin set user:9 '{"id":9,"name":"Zoe","email":"zoe@example.com"}' --ttl 60
+//! cache_admin del user:9
+//! cache_admin stats
+//! cache_admin flush
+//! cache_admin warm --from-db --ttl 300
+//! cache_admin --tcp 127.0.0.1:7070 stats
+//! ```
+//!
+//! Exit codes: 0 done, 1 not found or refused by the server, 2 could not
+//! talk to the server. Scripts can tell "no such key" from "cache is down".
+
+use clap::{Parser, Subcommand};
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
+/// Same default as `admin::DEFAULT_SOCKET` in the service
+const DEFAULT_SOCKET: &str = "/tmp/cache_patterns.sock";
+
+#[derive(Parser)]
+#[command(about = "Admin CLI for the cache_patterns service")]
+struct Cli {
+    /// Unix socket of the service
+    #[arg(long, default_value = DEFAULT_SOCKET, conflicts_with = "tcp")]
+    uds: PathBuf,
+    /// Talk TCP instead, e.g. 127.0.0.1:7070
+    #[arg(long)]
+    tcp: Option<String>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Show a key's JSON value and remaining TTL
+    Get { key: String },
+    /// Store a JSON value
+    Set {
+        key: String,
+        value: String,
+        /// Seconds until it expires
+        #[arg(long, default_value_t = 60)]
+        ttl: u64,
+    },
+    /// Remove a key
+    Del { key: String },
+    /// Hits, misses, hit rate and live keys
+    Stats,
+    /// Remove every key
+    Flush,
+    /// Load every user from the database into the cache
+    Warm {
+        /// The source to warm from (the only one there is, but say it)
+        #[arg(long, required = true)]
+        from_db: bool,
+        #[arg(long, default_value_t = 300)]
+        ttl: u64,
+    },
+}
+
+impl Command {
+    fn request(&self) -> String {
+        match self {
+            Command::Get { key } => format!("GET {}", key),
+            // The protocol is line based: a value must stay on one line
+            Command::Set { key, value, ttl } => {
+                format!("SET {} {} {}", key, ttl, value.replace('\n', " "))
+            }
+            Command::Del { key } => format!("DEL {}", key),
+            Command::Stats => "STATS".to_string(),
+            Command::Flush => "FLUSH".to_string(),
+            Command::Warm { ttl, .. } => format!("WARM {}", ttl),
+        }
+    }
+}
+
+/// Send one request line, read one reply line
+fn exchange<S: std::io::Read + Write>(mut stream: S, request: &str) -> std::io::Result<String> {
+    stream.write_all(format!("{}\n", request).as_bytes())?;
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply)?;
+    if reply.is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            "server closed the connection",
+        ));
+    }
+    Ok(reply.trim_end().to_string())
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let request = cli.command.request();
+    let timeout = Some(Duration::from_secs(5));
+
+    let (target, reply) = match &cli.tcp {
+        Some(addr) => (
+            addr.clone(),
+            TcpStream::connect(addr).and_then(|stream| {
+                stream.set_read_timeout(timeout)?;
+                exchange(stream, &request)
+            }),
+        ),
+        None => (
+            cli.uds.display().to_string(),
+            UnixStream::connect(&cli.uds).and_then(|stream| {
+                stream.set_read_timeout(timeout)?;
+                exchange(stream, &request)
+            }),
+        ),
+    };
+    let reply = match reply {
+        Ok(reply) => reply,
+        Err(err) => {
+            eprintln!("cache_admin: {}: {}", target, err);
+            return ExitCode::from(2);
+        }
+    };
+
+    let (status, rest) = reply.split_once(' ').unwrap_or((&reply, ""));
+    match status {
+        "OK" => {
+            if !rest.is_empty() {
+                println!("{}", rest);
+            }
+            ExitCode::SUCCESS
+        }
+        "NOT_FOUND" => {
+            eprintln!("(not found)");
+            ExitCode::from(1)
+        }
+        _ => {
+            eprintln!("cache_admin: {}", rest);
+            ExitCode::from(1)
+        }
+    }
+}
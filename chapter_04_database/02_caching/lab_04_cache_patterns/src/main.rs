@@ -43,9 +43,28 @@
 //! - [ ] TTL causes automatic invalidation
 //! - [ ] Cache misses fetch from database
 //! - [ ] Statistics are tracked accurately
+//!
+//! ## Extension: Admin CLI
+//! - `cargo run -- serve` keeps the cache running as a service and takes
+//!   admin commands on a Unix socket (`--uds`, default
+//!   `/tmp/cache_patterns.sock`) and optionally TCP (`--tcp 127.0.0.1:7070`)
+//! - The protocol is one text line per request and per reply, see `admin.rs`
+//! - `cache_admin` (`src/cache_admin.rs`) is the operator's side: `get`,
+//!   `set`, `del`, `stats`, `flush` and `warm --from-db`
+//! ```bash
+//! cargo run -- serve &
+//! cargo run --bin cache_admin -- warm --from-db --ttl 300
+//! cargo run --bin cache_admin -- get user:1
+//! cargo run --bin cache_admin -- --tcp 127.0.0.1:7070 stats
+//! ```
+
+mod admin;
+
+use clap::{Parser, Subcommand};
 
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
@@ -127,6 +146,45 @@ impl Cache {
         println!("  Stored in cache with {}s TTL", ttl.as_secs());
     }
 
+    /// Raw JSON of a live entry and the TTL it has left. Doesn't count as a
+    /// hit or miss: an operator looking must not skew the hit rate.
+    fn peek(&self, key: &str) -> Option<(String, Duration)> {
+        let data = self.data.lock().unwrap();
+        let entry = data.get(key)?;
+        let left = entry.expires_at.checked_duration_since(Instant::now())?;
+        Some((entry.value.clone(), left))
+    }
+
+    /// Store already-serialized JSON
+    fn set_raw(&self, key: &str, value: String, ttl: Duration) {
+        let mut data = self.data.lock().unwrap();
+        let entry = CacheEntry {
+            value,
+            expires_at: Instant::now() + ttl,
+        };
+        data.insert(key.to_string(), entry);
+    }
+
+    /// Remove one key; false if it wasn't there
+    fn delete(&self, key: &str) -> bool {
+        self.data.lock().unwrap().remove(key).is_some()
+    }
+
+    /// Remove every key; returns how many were there
+    fn flush(&self) -> usize {
+        let mut data = self.data.lock().unwrap();
+        let count = data.len();
+        data.clear();
+        count
+    }
+
+    /// Keys that haven't expired yet
+    fn live_keys(&self) -> usize {
+        let now = Instant::now();
+        let data = self.data.lock().unwrap();
+        data.values().filter(|entry| entry.expires_at > now).count()
+    }
+
     /// Get cache statistics
     fn stats(&self) -> (u64, u64, f64) {
         let stats = self.stats.lock().unwrap();
@@ -183,6 +241,14 @@ impl Database {
         tokio::time::sleep(Duration::from_millis(100)).await;
         self.users.get(&id).cloned()
     }
+
+    /// Every user, sorted by id (one slow query, for warming the cache)
+    async fn all_users(&self) -> Vec<User> {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let mut users: Vec<User> = self.users.values().cloned().collect();
+        users.sort_by_key(|user| user.id);
+        users
+    }
 }
 
 /// Cache-aside implementation
@@ -202,8 +268,38 @@ async fn get_user_cached(cache: &Cache, db: &Database, id: i64, ttl: Duration) -
     Some(user)
 }
 
+#[derive(Parser)]
+#[command(about = "Cache-aside demo, or the cache as a service")]
+struct Args {
+    #[command(subcommand)]
+    mode: Option<Mode>,
+}
+
+#[derive(Subcommand)]
+enum Mode {
+    /// Keep the cache running and accept admin commands
+    Serve {
+        /// Unix socket for admin commands
+        #[arg(long, default_value = admin::DEFAULT_SOCKET)]
+        uds: PathBuf,
+        /// Also accept admin commands over TCP, e.g. 127.0.0.1:7070
+        #[arg(long)]
+        tcp: Option<String>,
+    },
+}
+
 #[tokio::main]
 async fn main() {
+    if let Some(Mode::Serve { uds, tcp }) = Args::parse().mode {
+        let cache = Arc::new(Cache::new());
+        let db = Arc::new(Database::new());
+        if let Err(err) = admin::serve(cache, db, uds, tcp).await {
+            eprintln!("Admin listener failed: {}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     // TODO: Implement demo
     println!("=== Cache-Aside Pattern Demo ===\n");
     // 1. Create cache and database
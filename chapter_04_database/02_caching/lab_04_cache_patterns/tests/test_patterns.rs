@@ -1,7 +1,158 @@
 //! Lab 4 Tests
 
+use std::process::{Child, Command, Output};
+use std::thread;
+use std::time::Duration;
+
 #[test]
 fn test_placeholder() {
     // Cache pattern tests are in the solution file
     assert!(true);
 }
+
+struct ServerGuard {
+    child: Child,
+}
+
+impl Drop for ServerGuard {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+    }
+}
+
+/// Run cache_admin against the socket, return (exit code, stdout, stderr)
+fn admin(socket: &str, args: &[&str]) -> (i32, String, String) {
+    let Output {
+        status,
+        stdout,
+        stderr,
+    } = Command::new(env!("CARGO_BIN_EXE_cache_admin"))
+        .args(["--uds", socket])
+        .args(args)
+        .output()
+        .expect("Failed to run cache_admin");
+    (
+        status.code().unwrap_or(-1),
+        String::from_utf8_lossy(&stdout).trim().to_string(),
+        String::from_utf8_lossy(&stderr).trim().to_string(),
+    )
+}
+
+#[test]
+fn test_admin_cli() {
+    let socket =
+        std::env::temp_dir().join(format!("cache_patterns_test_{}.sock", std::process::id()));
+    let socket = socket.to_str().unwrap();
+    let _server = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_cache_patterns"))
+            .args(["serve", "--uds", socket, "--tcp", "127.0.0.1:7471"])
+            .spawn()
+            .expect("Failed to start cache service"),
+    };
+    thread::sleep(Duration::from_millis(500));
+
+    assert_eq!(
+        admin(socket, &["get", "user:1"]),
+        (1, String::new(), "(not found)".to_string())
+    );
+
+    let (code, out, _) = admin(socket, &["warm", "--from-db", "--ttl", "300"]);
+    assert_eq!((code, out.as_str()), (0, "warmed=3"));
+    let (code, out, _) = admin(socket, &["get", "user:1"]);
+    assert_eq!(code, 0);
+    assert!(out.starts_with(r#"{"id":1,"name":"Alice""#), "{}", out);
+    assert!(
+        out.ends_with("ttl=299s") || out.ends_with("ttl=300s"),
+        "{}",
+        out
+    );
+
+    let value = r#"{"id":9,"name":"Zoe","email":"zoe@example.com"}"#;
+    assert_eq!(admin(socket, &["set", "user:9", value, "--ttl", "60"]).0, 0);
+    let (code, _, err) = admin(socket, &["set", "user:10", "not json"]);
+    assert_eq!(code, 1);
+    assert!(err.contains("not JSON"), "{}", err);
+
+    assert_eq!(admin(socket, &["del", "user:9"]).1, "deleted=1");
+    assert_eq!(admin(socket, &["del", "user:9"]).1, "deleted=0");
+
+    // Same service over TCP; peeking with `get` didn't count as hits
+    let (code, out, _) = admin(socket, &["--tcp", "127.0.0.1:7471", "stats"]);
+    assert_eq!(
+        code, 2,
+        "--uds and --tcp together should be refused: {}",
+        out
+    );
+    let output = Command::new(env!("CARGO_BIN_EXE_cache_admin"))
+        .args(["--tcp", "127.0.0.1:7471", "stats"])
+        .output()
+        .unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout).trim(),
+        "hits=0 misses=0 hit_rate=0.0% keys=3"
+    );
+
+    assert_eq!(admin(socket, &["flush"]).1, "flushed=3");
+    assert_eq!(
+        admin(socket, &["stats"]).1,
+        "hits=0 misses=0 hit_rate=0.0% keys=0"
+    );
+
+    // warm needs its source spelled out
+    assert_eq!(admin(socket, &["warm"]).0, 2);
+
+    drop(_server);
+    let (code, _, err) = admin(socket, &["stats"]);
+    assert_eq!(code, 2, "{}", err);
+    let _ = std::fs::remove_file(socket);
+}
+
+#[test]
+fn test_admin_survives_failed_accept() {
+    let socket =
+        std::env::temp_dir().join(format!("cache_patterns_emfile_{}.sock", std::process::id()));
+    let socket = socket.to_str().unwrap();
+    let log =
+        std::env::temp_dir().join(format!("cache_patterns_emfile_{}.log", std::process::id()));
+    // A low descriptor limit makes accept fail with EMFILE once a handful
+    // of admin clients are connected
+    let mut server = ServerGuard {
+        child: Command::new("sh")
+            .args([
+                "-c",
+                "ulimit -n 16 && exec \"$0\" serve --uds \"$1\"",
+                env!("CARGO_BIN_EXE_cache_patterns"),
+                socket,
+            ])
+            .stderr(std::fs::File::create(&log).unwrap())
+            .spawn()
+            .expect("Failed to start cache service"),
+    };
+    thread::sleep(Duration::from_millis(500));
+
+    let clients: Vec<_> = (0..32)
+        .map(|_| std::os::unix::net::UnixStream::connect(socket).expect("connect"))
+        .collect();
+    thread::sleep(Duration::from_millis(500));
+    drop(clients);
+    thread::sleep(Duration::from_millis(500));
+
+    assert!(
+        server.child.try_wait().unwrap().is_none(),
+        "a failed accept took the cache service down"
+    );
+    let errors = std::fs::read_to_string(&log).unwrap();
+    assert!(errors.contains("Admin accept error"), "{}", errors);
+    assert_eq!(
+        admin(socket, &["stats"]),
+        (
+            0,
+            "hits=0 misses=0 hit_rate=0.0% keys=0".to_string(),
+            String::new()
+        )
+    );
+
+    drop(server);
+    let _ = std::fs::remove_file(socket);
+    let _ = std::fs::remove_file(log);
+}
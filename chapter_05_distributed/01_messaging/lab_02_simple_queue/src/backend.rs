@@ -0,0 +1,255 @@
+//! Where the queue lives: this process, or Redis
+//!
+//! `Backend` is the part of `Queue` a producer or worker needs. `Memory`
+//! wraps the in-process queue; `Redis` keeps the same pending / in flight /
+//! dead bookkeeping in Redis, so producers and workers in separate
+//! processes (or machines) share one queue. `produce` and `work` in
+//! `main.rs` are written against the trait and run unchanged on either.
+//!
+//! Redis keys, all under a prefix (`--queue`, default `simple_queue`):
+//!
+//! ```text
+//! <q>:pending     LIST  ids waiting; LPUSH at the back, taken from the right
+//! <q>:processing  LIST  ids handed to a worker, not yet acknowledged
+//! <q>:deadlines   ZSET  id -> when its visibility timeout ends (unix ms)
+//! <q>:payload     HASH  id -> payload
+//! <q>:attempts    HASH  id -> deliveries so far
+//! <q>:dead        LIST  ids that timed out max_attempts times
+//! <q>:acked       STRING acknowledged since the queue was created
+//! ```
+//!
+//! Plain RPOP would lose a message whose worker dies before finishing it.
+//! Moving the id to `processing` and setting its deadline happen in one Lua
+//! script, so a message is always in exactly one place; whoever runs
+//! `check_timeouts` moves expired ids back to `pending`, as
+//! `Queue::check_timeouts` does in memory.
+
+use redis::aio::MultiplexedConnection;
+use redis::{RedisResult, Script};
+use std::future::Future;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+use crate::SharedQueue;
+
+/// A message handed to a worker
+#[derive(Debug, Clone)]
+pub struct Delivery {
+    pub id: String,
+    pub payload: String,
+    pub attempts: u32,
+}
+
+/// How many messages are in each state
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Counts {
+    pub pending: usize,
+    pub in_flight: usize,
+    pub dead: usize,
+    pub acked: u64,
+}
+
+/// The queue operations producers and workers use. Only Redis can fail,
+/// hence `RedisResult` for both
+pub trait Backend: Clone + Send + Sync + 'static {
+    fn enqueue(&self, payload: String) -> impl Future<Output = RedisResult<String>> + Send;
+    fn dequeue(&self) -> impl Future<Output = RedisResult<Option<Delivery>>> + Send;
+    fn acknowledge(&self, id: &str) -> impl Future<Output = RedisResult<bool>> + Send;
+    /// Put messages whose visibility timeout ran out back in the queue,
+    /// or in the dead letters once they are out of attempts
+    fn check_timeouts(&self) -> impl Future<Output = RedisResult<()>> + Send;
+    fn counts(&self) -> impl Future<Output = RedisResult<Counts>> + Send;
+}
+
+/// The in-process `Queue`
+#[derive(Clone)]
+pub struct Memory(pub SharedQueue);
+
+impl Backend for Memory {
+    async fn enqueue(&self, payload: String) -> RedisResult<String> {
+        Ok(self.0.lock().unwrap().enqueue(payload))
+    }
+
+    async fn dequeue(&self) -> RedisResult<Option<Delivery>> {
+        Ok(self.0.lock().unwrap().dequeue().map(|msg| Delivery {
+            id: msg.id,
+            payload: msg.payload,
+            attempts: msg.attempts,
+        }))
+    }
+
+    async fn acknowledge(&self, id: &str) -> RedisResult<bool> {
+        Ok(self.0.lock().unwrap().acknowledge(id))
+    }
+
+    async fn check_timeouts(&self) -> RedisResult<()> {
+        self.0.lock().unwrap().check_timeouts();
+        Ok(())
+    }
+
+    async fn counts(&self) -> RedisResult<Counts> {
+        let queue = self.0.lock().unwrap();
+        let (pending, in_flight) = queue.stats();
+        Ok(Counts {
+            pending,
+            in_flight,
+            dead: queue.dead.len(),
+            acked: queue.acked,
+        })
+    }
+}
+
+/// Take the oldest pending id, start its visibility timeout
+/// KEYS: pending processing attempts deadlines payload; ARGV: deadline
+const DEQUEUE: &str = r"
+local id = redis.call('RPOPLPUSH', KEYS[1], KEYS[2])
+if not id then return false end
+local attempts = redis.call('HINCRBY', KEYS[3], id, 1)
+redis.call('ZADD', KEYS[4], ARGV[1], id)
+return {id, redis.call('HGET', KEYS[5], id), attempts}
+";
+
+/// Only a message still in flight can be acknowledged: one that already
+/// timed out is someone else's now
+/// KEYS: deadlines processing payload attempts acked; ARGV: id
+const ACKNOWLEDGE: &str = r"
+if redis.call('ZREM', KEYS[1], ARGV[1]) == 0 then return 0 end
+redis.call('LREM', KEYS[2], 1, ARGV[1])
+redis.call('HDEL', KEYS[3], ARGV[1])
+redis.call('HDEL', KEYS[4], ARGV[1])
+redis.call('INCR', KEYS[5])
+return 1
+";
+
+/// KEYS: deadlines processing attempts pending dead; ARGV: now max_attempts
+const CHECK_TIMEOUTS: &str = r"
+local expired = redis.call('ZRANGEBYSCORE', KEYS[1], '-inf', ARGV[1])
+for _, id in ipairs(expired) do
+  redis.call('ZREM', KEYS[1], id)
+  redis.call('LREM', KEYS[2], 1, id)
+  local attempts = tonumber(redis.call('HGET', KEYS[3], id) or '0')
+  if attempts >= tonumber(ARGV[2]) then
+    redis.call('RPUSH', KEYS[5], id)
+  else
+    redis.call('LPUSH', KEYS[4], id)
+  end
+end
+return #expired
+";
+
+/// The queue in Redis, shared by every process that uses the same prefix
+#[derive(Clone)]
+pub struct Redis {
+    con: MultiplexedConnection,
+    prefix: String,
+    visibility_timeout: Duration,
+    max_attempts: u32,
+}
+
+impl Redis {
+    pub async fn connect(
+        url: &str,
+        prefix: &str,
+        visibility_timeout: Duration,
+        max_attempts: u32,
+    ) -> RedisResult<Self> {
+        let client = redis::Client::open(url)?;
+        let con = client.get_multiplexed_tokio_connection().await?;
+        Ok(Self {
+            con,
+            prefix: prefix.to_string(),
+            visibility_timeout,
+            max_attempts,
+        })
+    }
+
+    fn key(&self, name: &str) -> String {
+        format!("{}:{}", self.prefix, name)
+    }
+
+    /// Deadlines are compared across processes, so they use wall-clock
+    /// time: the workers' clocks must roughly agree
+    fn now_ms() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64)
+    }
+}
+
+impl Backend for Redis {
+    async fn enqueue(&self, payload: String) -> RedisResult<String> {
+        let id = Uuid::new_v4().to_string()[..8].to_string();
+        // Payload first, in the same transaction: a worker never sees an
+        // id it can't look up
+        redis::pipe()
+            .atomic()
+            .hset(self.key("payload"), &id, payload)
+            .ignore()
+            .lpush(self.key("pending"), &id)
+            .ignore()
+            .query_async::<_, ()>(&mut self.con.clone())
+            .await?;
+        Ok(id)
+    }
+
+    async fn dequeue(&self) -> RedisResult<Option<Delivery>> {
+        let deadline = Self::now_ms() + self.visibility_timeout.as_millis() as u64;
+        let taken: Option<(String, String, u32)> = Script::new(DEQUEUE)
+            .key(self.key("pending"))
+            .key(self.key("processing"))
+            .key(self.key("attempts"))
+            .key(self.key("deadlines"))
+            .key(self.key("payload"))
+            .arg(deadline)
+            .invoke_async(&mut self.con.clone())
+            .await?;
+        Ok(taken.map(|(id, payload, attempts)| Delivery {
+            id,
+            payload,
+            attempts,
+        }))
+    }
+
+    async fn acknowledge(&self, id: &str) -> RedisResult<bool> {
+        let acked: i64 = Script::new(ACKNOWLEDGE)
+            .key(self.key("deadlines"))
+            .key(self.key("processing"))
+            .key(self.key("payload"))
+            .key(self.key("attempts"))
+            .key(self.key("acked"))
+            .arg(id)
+            .invoke_async(&mut self.con.clone())
+            .await?;
+        Ok(acked == 1)
+    }
+
+    async fn check_timeouts(&self) -> RedisResult<()> {
+        let _: i64 = Script::new(CHECK_TIMEOUTS)
+            .key(self.key("deadlines"))
+            .key(self.key("processing"))
+            .key(self.key("attempts"))
+            .key(self.key("pending"))
+            .key(self.key("dead"))
+            .arg(Self::now_ms())
+            .arg(self.max_attempts)
+            .invoke_async(&mut self.con.clone())
+            .await?;
+        Ok(())
+    }
+
+    async fn counts(&self) -> RedisResult<Counts> {
+        let (pending, in_flight, dead, acked): (usize, usize, usize, Option<u64>) = redis::pipe()
+            .llen(self.key("pending"))
+            .llen(self.key("processing"))
+            .llen(self.key("dead"))
+            .get(self.key("acked"))
+            .query_async(&mut self.con.clone())
+            .await?;
+        Ok(Counts {
+            pending,
+            in_flight,
+            dead,
+            acked: acked.unwrap_or(0),
+        })
+    }
+}
@@ -0,0 +1,248 @@
+//! Admin dashboard: what is waiting, what is in flight, what died
+//!
+//! ```text
+//! GET  /            HTML page, refreshes every 2 seconds
+//! GET  /api/state   the same numbers as JSON
+//! POST /redrive     dead letters back into the queue  -> 303 to /
+//! POST /purge       drop all dead letters             -> 303 to /
+//! ```
+//!
+//! It binds to 127.0.0.1 only, so other machines can't reach it. That
+//! doesn't protect the buttons: any page open in the operator's browser can
+//! submit `<form method=post action="http://127.0.0.1:9090/purge">`, and
+//! the browser sends it. So the two POSTs must come from the dashboard's
+//! own page: an `Origin` (or, without one, a `Referer`) naming anything else
+//! gets 403. The allowed origins come from the port, not from `Host`, which
+//! a DNS-rebound name would control. A request with neither header is not
+//! from a browser form (curl, a script) and goes through.
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::{self, Next},
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::Serialize;
+use std::fmt::Write;
+use std::time::Instant;
+use tokio::net::TcpListener;
+
+use crate::{Event, Message, Queue, SharedQueue};
+
+/// The queue at one moment, as the dashboard shows it
+#[derive(Serialize)]
+struct Snapshot {
+    pending: usize,
+    in_flight: usize,
+    dead: usize,
+    acked: u64,
+    max_attempts: u32,
+    messages: Vec<MessageView>,
+}
+
+#[derive(Serialize)]
+struct MessageView {
+    id: String,
+    payload: String,
+    /// "pending", "in_flight" or "dead"
+    state: &'static str,
+    attempts: u32,
+    history: Vec<EventView>,
+}
+
+#[derive(Serialize)]
+struct EventView {
+    /// Milliseconds since the message was enqueued
+    after_ms: u128,
+    event: String,
+}
+
+impl MessageView {
+    fn new(msg: &Message, state: &'static str) -> Self {
+        let start = msg.history.first().map_or_else(Instant::now, |(at, _)| *at);
+        Self {
+            id: msg.id.clone(),
+            payload: msg.payload.clone(),
+            state,
+            attempts: msg.attempts,
+            history: msg
+                .history
+                .iter()
+                .map(|(at, event)| EventView {
+                    after_ms: at.duration_since(start).as_millis(),
+                    event: describe(*event),
+                })
+                .collect(),
+        }
+    }
+}
+
+fn describe(event: Event) -> String {
+    match event {
+        Event::Enqueued => "enqueued".to_string(),
+        Event::Dequeued { attempt } => format!("dequeued (attempt {})", attempt),
+        Event::TimedOut => "timed out".to_string(),
+        Event::DeadLettered => "dead-lettered".to_string(),
+        Event::Redriven => "redriven".to_string(),
+    }
+}
+
+fn snapshot(queue: &Queue) -> Snapshot {
+    // In flight oldest first; the map itself has no order
+    let mut in_flight: Vec<&Message> = queue.processing.values().collect();
+    in_flight.sort_by_key(|msg| msg.dequeued_at);
+
+    let messages = queue
+        .pending
+        .iter()
+        .map(|msg| MessageView::new(msg, "pending"))
+        .chain(
+            in_flight
+                .iter()
+                .map(|msg| MessageView::new(msg, "in_flight")),
+        )
+        .chain(queue.dead.iter().map(|msg| MessageView::new(msg, "dead")))
+        .collect();
+    Snapshot {
+        pending: queue.pending.len(),
+        in_flight: queue.processing.len(),
+        dead: queue.dead.len(),
+        acked: queue.acked,
+        max_attempts: queue.max_attempts,
+        messages,
+    }
+}
+
+pub async fn serve(queue: SharedQueue, port: u16) -> std::io::Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port)).await?;
+    println!("Dashboard on http://127.0.0.1:{}/", port);
+    axum::serve(listener, router(queue, port)).await
+}
+
+fn router(queue: SharedQueue, port: u16) -> Router {
+    let actions = Router::new()
+        .route("/redrive", post(redrive))
+        .route("/purge", post(purge))
+        .route_layer(middleware::from_fn_with_state(port, same_origin));
+    Router::new()
+        .route("/", get(page))
+        .route("/api/state", get(state))
+        .merge(actions)
+        .with_state(queue)
+}
+
+/// Refuse a POST that another site's page made the browser send
+async fn same_origin(State(port): State<u16>, request: Request, next: Next) -> Response {
+    if !from_dashboard(request.headers(), port) {
+        println!("Dashboard: refused a cross-site {}", request.uri());
+        return (StatusCode::FORBIDDEN, "cross-site request refused\n").into_response();
+    }
+    next.run(request).await
+}
+
+fn from_dashboard(headers: &HeaderMap, port: u16) -> bool {
+    let ours = [
+        format!("http://127.0.0.1:{}", port),
+        format!("http://localhost:{}", port),
+    ];
+    let get = |name| headers.get(name).and_then(|value| value.to_str().ok());
+    match (get(header::ORIGIN), get(header::REFERER)) {
+        (Some(origin), _) => ours.iter().any(|ours| origin == ours),
+        (None, Some(referer)) => ours.iter().any(|ours| {
+            referer
+                .strip_prefix(ours.as_str())
+                .is_some_and(|path| path.is_empty() || path.starts_with('/'))
+        }),
+        (None, None) => true,
+    }
+}
+
+/// GET /api/state
+async fn state(State(queue): State<SharedQueue>) -> Json<Snapshot> {
+    Json(snapshot(&queue.lock().unwrap()))
+}
+
+/// POST /redrive - Redirect, so a browser refresh doesn't post again
+async fn redrive(State(queue): State<SharedQueue>) -> Redirect {
+    let count = queue.lock().unwrap().redrive();
+    println!("Dashboard: redrove {} dead letter(s)", count);
+    Redirect::to("/")
+}
+
+/// POST /purge
+async fn purge(State(queue): State<SharedQueue>) -> Redirect {
+    let count = queue.lock().unwrap().purge_dead();
+    println!("Dashboard: purged {} dead letter(s)", count);
+    Redirect::to("/")
+}
+
+/// GET /
+async fn page(State(queue): State<SharedQueue>) -> Html<String> {
+    // Render outside the lock
+    let snapshot = snapshot(&queue.lock().unwrap());
+    Html(render(&snapshot))
+}
+
+fn render(s: &Snapshot) -> String {
+    let mut html = String::from(
+        "<!doctype html>\n<html><head><meta charset=\"utf-8\">\
+         <meta http-equiv=\"refresh\" content=\"2\"><title>Queue</title>\
+         <style>body{font-family:sans-serif}td,th{padding:2px 8px;text-align:left}\
+         .dead{color:#b00}</style></head><body>\n<h1>Queue</h1>\n",
+    );
+    let _ = writeln!(
+        html,
+        "<p>pending <b>{}</b> &middot; in flight <b>{}</b> &middot; \
+         dead letters <b>{}</b> &middot; acked <b>{}</b> &middot; \
+         max attempts {}</p>",
+        s.pending, s.in_flight, s.dead, s.acked, s.max_attempts
+    );
+    html.push_str(
+        "<form method=\"post\" action=\"/redrive\" style=\"display:inline\">\
+         <button>Redrive dead letters</button></form>\n\
+         <form method=\"post\" action=\"/purge\" style=\"display:inline\">\
+         <button>Purge dead letters</button></form>\n",
+    );
+
+    html.push_str(
+        "<table>\n<tr><th>id</th><th>payload</th><th>state</th>\
+         <th>attempts</th><th>history</th></tr>\n",
+    );
+    for msg in &s.messages {
+        let history: Vec<String> = msg
+            .history
+            .iter()
+            .map(|e| format!("+{:.1}s {}", e.after_ms as f64 / 1000.0, e.event))
+            .collect();
+        let _ = writeln!(
+            html,
+            "<tr class=\"{}\"><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            msg.state,
+            msg.id,
+            escape(&msg.payload),
+            msg.state,
+            msg.attempts,
+            escape(&history.join(", "))
+        );
+    }
+    html.push_str("</table>\n</body></html>\n");
+    html
+}
+
+/// Payloads come from producers: never put them in the page unescaped
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
@@ -41,8 +41,51 @@
 //! - [ ] Dequeue returns one message at a time
 //! - [ ] Acknowledged messages are removed
 //! - [ ] Unacked messages are redelivered after timeout
+//!
+//! ## Extension: Dead Letters and Dashboard
+//! - A message that times out `max_attempts` times moves to a dead-letter
+//!   queue (DLQ) instead of going around forever; `redrive` puts DLQ
+//!   messages back in the queue with fresh attempts, `purge_dead` drops them
+//! - Every message keeps its history: enqueued, dequeued (attempt n), timed
+//!   out, dead-lettered, redriven
+//! - `cargo run -- --dashboard [port]` runs a producer and a flaky worker
+//!   (every 4th job always fails) and serves an admin dashboard on
+//!   `127.0.0.1:<port>` (default 9090), see `dashboard.rs`
+//! ```bash
+//! cargo run -- --dashboard
+//! # open http://127.0.0.1:9090/
+//! curl http://127.0.0.1:9090/api/state
+//! curl -X POST http://127.0.0.1:9090/redrive
+//! ```
+//!
+//! ## Extension: Redis Backend
+//! - `Backend` (see `backend.rs`) is what producers and workers need from a
+//!   queue; `Memory` wraps `Queue`, `Redis` keeps the same states in Redis
+//!   lists so separate processes share one queue
+//! - `produce` and `work` take any `Backend`: the same worker code runs
+//!   in-process or against Redis
+//! - `run` starts a producer and N workers in one process, on either
+//!   backend; `produce` and `work` are the halves, for separate processes
+//!   (Redis only: a memory queue dies with its process)
+//! - Visibility timeout 1s, 3 attempts, `--fail-every K` drops every K-th
+//!   job like the dashboard's flaky worker
+//! ```bash
+//! cargo run -- run --jobs 10 --workers 2 --fail-every 4
+//! docker run -d -p 6379:6379 redis:7
+//! cargo run -- produce 10 --redis redis://127.0.0.1/
+//! cargo run -- work --redis redis://127.0.0.1/ --name w1 --until-idle &
+//! cargo run -- work --redis redis://127.0.0.1/ --name w2 --until-idle
+//! [w1] done job-1 (attempt 1)
+//! [w2] done job-2 (attempt 1)
+//! ```
+
+mod backend;
+mod dashboard;
 
+use backend::{Backend, Memory, Redis};
+use redis::RedisResult;
 use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use uuid::Uuid;
 
@@ -50,6 +93,16 @@ use uuid::Uuid;
 // TODO: Implement simple message queue
 // ============================================================
 
+/// What happened to a message, in the order it happened
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Event {
+    Enqueued,
+    Dequeued { attempt: u32 },
+    TimedOut,
+    DeadLettered,
+    Redriven,
+}
+
 /// Message in the queue
 #[derive(Debug, Clone)]
 struct Message {
@@ -57,22 +110,40 @@ struct Message {
     payload: String,
     attempts: u32,
     dequeued_at: Option<Instant>,
+    history: Vec<(Instant, Event)>,
+}
+
+impl Message {
+    fn record(&mut self, event: Event) {
+        self.history.push((Instant::now(), event));
+    }
 }
 
 /// Simple message queue
 struct Queue {
     pending: VecDeque<Message>,
     processing: HashMap<String, Message>,
+    /// Messages that timed out `max_attempts` times, oldest first
+    dead: Vec<Message>,
     visibility_timeout: Duration,
+    max_attempts: u32,
+    /// Acknowledged since start
+    acked: u64,
 }
 
+/// Shared between the worker tasks and the dashboard
+type SharedQueue = Arc<Mutex<Queue>>;
+
 impl Queue {
-    fn new(visibility_timeout: Duration) -> Self {
+    fn new(visibility_timeout: Duration, max_attempts: u32) -> Self {
         // TODO: Initialize queue
         Queue {
             pending: VecDeque::new(),
             processing: HashMap::new(),
+            dead: Vec::new(),
             visibility_timeout,
+            max_attempts,
+            acked: 0,
         }
     }
 
@@ -80,12 +151,14 @@ impl Queue {
     fn enqueue(&mut self, payload: String) -> String {
         // TODO: Create message with UUID, add to pending
         let id = Uuid::new_v4().to_string()[..8].to_string();
-        let msg = Message {
+        let mut msg = Message {
             id: id.clone(),
             payload,
             attempts: 0,
             dequeued_at: None,
+            history: Vec::new(),
         };
+        msg.record(Event::Enqueued);
         self.pending.push_back(msg);
         id
     }
@@ -98,6 +171,9 @@ impl Queue {
         let mut msg = self.pending.pop_front()?;
         msg.attempts += 1;
         msg.dequeued_at = Some(Instant::now());
+        msg.record(Event::Dequeued {
+            attempt: msg.attempts,
+        });
 
         let id = msg.id.clone();
         self.processing.insert(id, msg.clone());
@@ -107,7 +183,11 @@ impl Queue {
     /// Acknowledge message (remove from processing)
     fn acknowledge(&mut self, id: &str) -> bool {
         // TODO: Remove message from processing
-        self.processing.remove(id).is_some()
+        let acked = self.processing.remove(id).is_some();
+        if acked {
+            self.acked += 1;
+        }
+        acked
     }
 
     /// Check for timed out messages and redeliver
@@ -130,11 +210,37 @@ impl Queue {
         for id in expired_ids {
             if let Some(mut msg) = self.processing.remove(&id) {
                 msg.dequeued_at = None;
-                self.pending.push_back(msg);
+                msg.record(Event::TimedOut);
+                // Out of attempts: park it instead of failing forever
+                if msg.attempts >= self.max_attempts {
+                    msg.record(Event::DeadLettered);
+                    self.dead.push(msg);
+                } else {
+                    self.pending.push_back(msg);
+                }
             }
         }
     }
 
+    /// Move every dead letter back to the queue with fresh attempts;
+    /// returns how many
+    fn redrive(&mut self) -> usize {
+        let count = self.dead.len();
+        for mut msg in self.dead.drain(..) {
+            msg.attempts = 0;
+            msg.record(Event::Redriven);
+            self.pending.push_back(msg);
+        }
+        count
+    }
+
+    /// Drop every dead letter; returns how many
+    fn purge_dead(&mut self) -> usize {
+        let count = self.dead.len();
+        self.dead.clear();
+        count
+    }
+
     /// Get queue statistics
     fn stats(&self) -> (usize, usize) {
         // TODO: Return (pending_count, processing_count)
@@ -143,13 +249,271 @@ impl Queue {
     }
 }
 
+/// A producer and a flaky worker sharing one queue, behind the dashboard
+async fn run_dashboard(port: u16) {
+    let queue: SharedQueue = Arc::new(Mutex::new(Queue::new(Duration::from_secs(1), 3)));
+
+    // Producer: a job every 300ms
+    let producer = Arc::clone(&queue);
+    tokio::spawn(async move {
+        for n in 1.. {
+            producer.lock().unwrap().enqueue(format!("job-{}", n));
+            tokio::time::sleep(Duration::from_millis(300)).await;
+        }
+    });
+
+    // Worker: acks everything except every 4th job, which it never finishes
+    let worker = Arc::clone(&queue);
+    tokio::spawn(async move {
+        loop {
+            let msg = worker.lock().unwrap().dequeue();
+            if let Some(msg) = msg {
+                let n: u64 = msg.payload.trim_start_matches("job-").parse().unwrap_or(0);
+                if !n.is_multiple_of(4) {
+                    worker.lock().unwrap().acknowledge(&msg.id);
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    });
+
+    // Redeliver (or dead-letter) whatever timed out
+    let checker = Arc::clone(&queue);
+    tokio::spawn(async move {
+        loop {
+            checker.lock().unwrap().check_timeouts();
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    });
+
+    if let Err(err) = dashboard::serve(queue, port).await {
+        eprintln!("Dashboard failed on port {}: {}", port, err);
+        std::process::exit(1);
+    }
+}
+
+/// Enqueue `job-1` .. `job-N`
+async fn produce<B: Backend>(queue: &B, jobs: u64) -> RedisResult<()> {
+    for n in 1..=jobs {
+        let id = queue.enqueue(format!("job-{}", n)).await?;
+        println!("Enqueued: job-{} ({})", n, id);
+    }
+    Ok(())
+}
+
+/// Take jobs and acknowledge them, except every `fail_every`-th, which is
+/// dropped without an ack as if the worker crashed halfway. With
+/// `until_idle` it returns once nothing is pending or in flight
+async fn work<B: Backend>(
+    queue: B,
+    name: String,
+    fail_every: Option<u64>,
+    until_idle: bool,
+) -> RedisResult<()> {
+    loop {
+        queue.check_timeouts().await?;
+        let Some(msg) = queue.dequeue().await? else {
+            if until_idle {
+                let counts = queue.counts().await?;
+                if counts.pending == 0 && counts.in_flight == 0 {
+                    return Ok(());
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            continue;
+        };
+
+        let n: u64 = msg.payload.trim_start_matches("job-").parse().unwrap_or(0);
+        if fail_every.is_some_and(|k| n.is_multiple_of(k)) {
+            println!(
+                "[{}] dropped {} (attempt {})",
+                name, msg.payload, msg.attempts
+            );
+            continue;
+        }
+        // The "work"
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        if queue.acknowledge(&msg.id).await? {
+            println!("[{}] done {} (attempt {})", name, msg.payload, msg.attempts);
+        } else {
+            // Too slow: it timed out and went to someone else
+            println!("[{}] late for {}, not acked", name, msg.payload);
+        }
+    }
+}
+
+/// A producer and `workers` workers in one process, until the queue is empty
+async fn run_all<B: Backend>(
+    queue: B,
+    jobs: u64,
+    workers: usize,
+    fail_every: Option<u64>,
+) -> RedisResult<()> {
+    produce(&queue, jobs).await?;
+    let handles: Vec<_> = (1..=workers)
+        .map(|i| {
+            let name = format!("w{}", i);
+            tokio::spawn(work(queue.clone(), name, fail_every, true))
+        })
+        .collect();
+    for handle in handles {
+        handle.await.expect("worker panicked")?;
+    }
+    let counts = queue.counts().await?;
+    println!(
+        "Final: acked={}, dead={}, pending={}, in_flight={}",
+        counts.acked, counts.dead, counts.pending, counts.in_flight
+    );
+    Ok(())
+}
+
+/// `run`, `produce` and `work`
+#[derive(Debug)]
+enum Command {
+    Run { jobs: u64, workers: usize },
+    Produce { jobs: u64 },
+    Work { name: String, until_idle: bool },
+}
+
+#[derive(Debug)]
+struct Options {
+    command: Command,
+    redis: Option<String>,
+    queue: String,
+    fail_every: Option<u64>,
+}
+
+fn usage() -> String {
+    [
+        "usage: simple_queue [--dashboard [PORT]]",
+        "       simple_queue run [--jobs N] [--workers N] [--redis URL] [--queue NAME] [--fail-every K]",
+        "       simple_queue produce N --redis URL [--queue NAME]",
+        "       simple_queue work --redis URL [--queue NAME] [--name NAME] [--fail-every K] [--until-idle]",
+    ]
+    .join("\n")
+}
+
+fn parse_args(args: Vec<String>) -> Result<Options, String> {
+    let mut args = args.into_iter();
+    let mut command = match args.next().as_deref() {
+        Some("run") => Command::Run {
+            jobs: 10,
+            workers: 2,
+        },
+        Some("produce") => {
+            let count = args.next().ok_or("produce needs a job count")?;
+            let jobs = count
+                .parse()
+                .map_err(|_| format!("invalid job count: {}", count))?;
+            Command::Produce { jobs }
+        }
+        Some("work") => Command::Work {
+            name: format!("worker-{}", std::process::id()),
+            until_idle: false,
+        },
+        Some(other) => return Err(format!("unknown command: {}", other)),
+        None => return Err("missing command".to_string()),
+    };
+    let mut redis = None;
+    let mut queue = "simple_queue".to_string();
+    let mut fail_every = None;
+    while let Some(arg) = args.next() {
+        if arg == "--until-idle" {
+            match &mut command {
+                Command::Work { until_idle, .. } => *until_idle = true,
+                _ => return Err("--until-idle is for work".to_string()),
+            }
+            continue;
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value", arg))?;
+        match (arg.as_str(), &mut command) {
+            ("--redis", _) => redis = Some(value),
+            ("--queue", _) => queue = value,
+            ("--fail-every", _) => match value.parse::<u64>() {
+                Ok(k) if k >= 1 => fail_every = Some(k),
+                _ => return Err(format!("--fail-every must be at least 1, got {}", value)),
+            },
+            ("--jobs", Command::Run { jobs, .. }) => {
+                *jobs = value
+                    .parse()
+                    .map_err(|_| format!("invalid --jobs: {}", value))?;
+            }
+            ("--workers", Command::Run { workers, .. }) => match value.parse::<usize>() {
+                Ok(n) if n >= 1 => *workers = n,
+                _ => return Err(format!("--workers must be at least 1, got {}", value)),
+            },
+            ("--name", Command::Work { name, .. }) => *name = value,
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+    if redis.is_none() && !matches!(command, Command::Run { .. }) {
+        return Err("produce and work need --redis: a memory queue dies with its process".into());
+    }
+    Ok(Options {
+        command,
+        redis,
+        queue,
+        fail_every,
+    })
+}
+
+/// Run `command` against `queue`, whichever backend it is
+async fn execute<B: Backend>(
+    queue: B,
+    command: Command,
+    fail_every: Option<u64>,
+) -> RedisResult<()> {
+    match command {
+        Command::Run { jobs, workers } => run_all(queue, jobs, workers, fail_every).await,
+        Command::Produce { jobs } => produce(&queue, jobs).await,
+        Command::Work { name, until_idle } => work(queue, name, fail_every, until_idle).await,
+    }
+}
+
+async fn run_command(options: Options) {
+    let (timeout, attempts) = (Duration::from_secs(1), 3);
+    let result = match &options.redis {
+        Some(url) => match Redis::connect(url, &options.queue, timeout, attempts).await {
+            Ok(queue) => execute(queue, options.command, options.fail_every).await,
+            Err(err) => Err(err),
+        },
+        None => {
+            let queue = Memory(Arc::new(Mutex::new(Queue::new(timeout, attempts))));
+            execute(queue, options.command, options.fail_every).await
+        }
+    };
+    if let Err(err) = result {
+        eprintln!("Redis error: {}", err);
+        std::process::exit(1);
+    }
+}
+
 #[tokio::main]
 async fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("--dashboard") {
+        let port = args.get(2).and_then(|p| p.parse().ok()).unwrap_or(9090);
+        run_dashboard(port).await;
+        return;
+    }
+    if args.len() > 1 {
+        match parse_args(args[1..].to_vec()) {
+            Ok(options) => run_command(options).await,
+            Err(err) => {
+                eprintln!("{}\n{}", err, usage());
+                std::process::exit(2);
+            }
+        }
+        return;
+    }
+
     // TODO: Implement demo
     println!("=== Simple Queue Demo ===\n");
 
     // 1. Create queue
-    let mut queue = Queue::new(Duration::from_secs(2));
+    let mut queue = Queue::new(Duration::from_secs(2), 5);
     // 2. Enqueue messages
     println!("Enqueuing 5 messages...");
     for i in 1..=5 {
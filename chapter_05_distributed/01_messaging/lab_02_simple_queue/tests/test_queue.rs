@@ -4,3 +4,214 @@
 fn test_placeholder() {
     assert!(true);
 }
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::process::{Child, Command};
+use std::thread;
+use std::time::{Duration, Instant};
+
+struct ServerGuard {
+    child: Child,
+}
+
+impl Drop for ServerGuard {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+    }
+}
+
+/// Send one request with `Connection: close`, return (status line, whole head, body)
+fn send(port: u16, method: &str, path: &str) -> (String, String, String) {
+    send_with(port, method, path, "")
+}
+
+/// `send` with extra header lines, each ending in CRLF
+fn send_with(port: u16, method: &str, path: &str, headers: &str) -> (String, String, String) {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).expect("connect");
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let request = format!(
+        "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: 0\r\n{}\r\n",
+        method, path, headers
+    );
+    stream.write_all(request.as_bytes()).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").expect("no head");
+    let status = head.lines().next().unwrap_or("").to_string();
+    (status, head.to_string(), body.to_string())
+}
+
+/// `"name":123` in a flat JSON body
+fn count(body: &str, name: &str) -> u64 {
+    let key = format!("\"{}\":", name);
+    let start = body.find(&key).expect("field missing") + key.len();
+    body[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_digit())
+        .collect::<String>()
+        .parse()
+        .expect("not a number")
+}
+
+/// Poll /api/state until it has a dead letter
+fn wait_for_dead_letter(port: u16) -> String {
+    let deadline = Instant::now() + Duration::from_secs(15);
+    loop {
+        let (_, _, body) = send(port, "GET", "/api/state");
+        if count(&body, "dead") > 0 {
+            return body;
+        }
+        assert!(Instant::now() < deadline, "No dead letter: {}", body);
+        thread::sleep(Duration::from_millis(250));
+    }
+}
+
+#[test]
+fn test_dashboard_redrive_and_purge() {
+    let _server = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_simple_queue"))
+            .args(["--dashboard", "9191"])
+            .spawn()
+            .expect("Failed to start dashboard"),
+    };
+    thread::sleep(Duration::from_millis(500));
+
+    let (status, _, page) = send(9191, "GET", "/");
+    assert!(status.contains("200"), "{}", status);
+    assert!(page.contains("Redrive dead letters"));
+
+    // job-4 always fails: after 3 timeouts it is a dead letter
+    let state = wait_for_dead_letter(9191);
+    assert!(state.contains("\"payload\":\"job-4\""), "{}", state);
+    assert!(state.contains("dead-lettered"));
+    assert!(state.contains("dequeued (attempt 3)"));
+    assert!(count(&state, "acked") > 0);
+
+    let (status, head, _) = send(9191, "POST", "/redrive");
+    assert!(status.contains("303"), "{}", status);
+    assert!(head.to_ascii_lowercase().contains("location: /"));
+    let (_, _, state) = send(9191, "GET", "/api/state");
+    assert!(state.contains("redriven"), "{}", state);
+
+    wait_for_dead_letter(9191);
+    let (status, _, _) = send(9191, "POST", "/purge");
+    assert!(status.contains("303"), "{}", status);
+    let (_, _, state) = send(9191, "GET", "/api/state");
+    assert_eq!(count(&state, "dead"), 0, "{}", state);
+}
+
+/// `simple_queue <args>`: (exit code, stdout)
+#[test]
+fn test_dashboard_refuses_cross_site_posts() {
+    let _server = ServerGuard {
+        child: Command::new(env!("CARGO_BIN_EXE_simple_queue"))
+            .args(["--dashboard", "9192"])
+            .spawn()
+            .expect("Failed to start dashboard"),
+    };
+    thread::sleep(Duration::from_millis(500));
+    wait_for_dead_letter(9192);
+
+    // What a form on another site makes the browser send
+    let (status, _, _) = send_with(9192, "POST", "/purge", "Origin: https://evil.example\r\n");
+    assert!(status.contains("403"), "{}", status);
+    let (status, _, _) = send_with(
+        9192,
+        "POST",
+        "/redrive",
+        "Referer: http://127.0.0.1:9192.evil.example/\r\n",
+    );
+    assert!(status.contains("403"), "{}", status);
+    let (_, _, state) = send(9192, "GET", "/api/state");
+    assert!(count(&state, "dead") > 0, "{}", state);
+    assert!(!state.contains("redriven"), "{}", state);
+
+    // The dashboard's own buttons still work
+    let (status, _, _) = send_with(9192, "POST", "/purge", "Origin: http://127.0.0.1:9192\r\n");
+    assert!(status.contains("303"), "{}", status);
+    let (_, _, state) = send(9192, "GET", "/api/state");
+    assert_eq!(count(&state, "dead"), 0, "{}", state);
+}
+
+fn run(args: &[&str]) -> (Option<i32>, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_simple_queue"))
+        .args(args)
+        .output()
+        .expect("Failed to run queue");
+    (
+        output.status.code(),
+        String::from_utf8_lossy(&output.stdout).to_string(),
+    )
+}
+
+/// Payloads acknowledged in `output`, e.g. "job-3" for "[w1] done job-3 (attempt 1)"
+fn done(output: &str) -> Vec<String> {
+    let mut jobs: Vec<String> = output
+        .lines()
+        .filter_map(|line| line.split_once("] done ")?.1.split(' ').next())
+        .map(str::to_string)
+        .collect();
+    jobs.sort_by_key(|job| job[4..].parse::<u32>().unwrap_or(0));
+    jobs
+}
+
+#[test]
+fn test_workers_on_memory_backend() {
+    let (code, out) = run(&["run", "--jobs", "8", "--workers", "2", "--fail-every", "4"]);
+    assert_eq!(code, Some(0), "{}", out);
+    assert_eq!(
+        done(&out),
+        ["job-1", "job-2", "job-3", "job-5", "job-6", "job-7"]
+    );
+    assert!(out.contains("dropped job-4 (attempt 3)"), "{}", out);
+    assert!(out.contains("Final: acked=6, dead=2, pending=0, in_flight=0"));
+
+    // Without Redis there is nothing for another process to share
+    assert_eq!(run(&["work"]).0, Some(2));
+    assert_eq!(run(&["produce", "3"]).0, Some(2));
+    assert_eq!(run(&["run", "--workers", "0"]).0, Some(2));
+}
+
+#[test]
+fn test_processes_share_redis_queue() {
+    if TcpStream::connect("127.0.0.1:6379").is_err() {
+        // Run with: docker run -d -p 6379:6379 redis:7
+        eprintln!("No Redis on 127.0.0.1:6379, skipping");
+        return;
+    }
+    let url = "redis://127.0.0.1/";
+    let queue = format!("simple_queue_test_{}", std::process::id());
+
+    let (code, out) = run(&["produce", "6", "--redis", url, "--queue", &queue]);
+    assert_eq!(code, Some(0), "{}", out);
+
+    // Two worker processes on the same queue; both drop every 3rd job
+    let worker = |name: &str| {
+        Command::new(env!("CARGO_BIN_EXE_simple_queue"))
+            .args(["work", "--redis", url, "--queue", &queue, "--name", name])
+            .args(["--fail-every", "3", "--until-idle"])
+            .output()
+            .expect("Failed to run worker")
+    };
+    let (a, b) = thread::scope(|s| {
+        let a = s.spawn(|| worker("a"));
+        let b = s.spawn(|| worker("b"));
+        (a.join().unwrap(), b.join().unwrap())
+    });
+    let out = String::from_utf8_lossy(&a.stdout).to_string() + &String::from_utf8_lossy(&b.stdout);
+
+    // Each job done exactly once across both processes, the dropped ones
+    // redelivered until they are dead letters
+    assert_eq!(done(&out), ["job-1", "job-2", "job-4", "job-5"], "{}", out);
+    assert!(out.contains("dropped job-6 (attempt 3)"), "{}", out);
+
+    let (_, out) = run(&["run", "--jobs", "0", "--redis", url, "--queue", &queue]);
+    assert!(
+        out.contains("acked=4, dead=2, pending=0, in_flight=0"),
+        "{}",
+        out
+    );
+}
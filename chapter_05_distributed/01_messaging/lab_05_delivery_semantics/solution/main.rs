@@ -0,0 +1,481 @@
+//! Lab 5 Reference Answer
+//!
+//! A producer sends N messages to a consumer over a channel that drops,
+//! duplicates and reorders them; acks travel back over an equally lossy
+//! channel. The same seeded network is replayed under each ack strategy:
+//!
+//! - at-most-once: send once, never look at acks
+//! - at-least-once: resend until acked (or out of attempts)
+//! - exactly-once: at-least-once + a consumer that skips ids it has seen
+//!
+//! Time is simulated in ticks, so a run is instant and fully reproducible.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet};
+
+// ============================================================
+// Random numbers
+// ============================================================
+
+#[allow(dead_code)] // below and shuffle are for the quorum lab
+#[path = "../../../../shared/rng.rs"]
+mod rng;
+
+use rng::Rng;
+
+// ============================================================
+// Lossy channel
+// ============================================================
+
+/// Most extra ticks a reordered packet is held back
+const MAX_REORDER_DELAY: u64 = 3;
+
+/// Fault probabilities, each in 0.0..=1.0
+#[derive(Debug, Clone, Copy)]
+struct Faults {
+    drop: f64,
+    duplicate: f64,
+    reorder: f64,
+}
+
+/// One direction of an unreliable network link.
+///
+/// Every packet takes one tick; a reordered one is held back 1..=3 extra
+/// ticks, so later packets overtake it.
+struct LossyChannel {
+    faults: Faults,
+    /// (due tick, send sequence, message id), smallest first
+    in_flight: BinaryHeap<Reverse<(u64, u64, u32)>>,
+    sequence: u64,
+}
+
+impl LossyChannel {
+    fn new(faults: Faults) -> Self {
+        Self {
+            faults,
+            in_flight: BinaryHeap::new(),
+            sequence: 0,
+        }
+    }
+
+    fn send(&mut self, now: u64, id: u32, rng: &mut Rng) {
+        if rng.chance(self.faults.drop) {
+            return;
+        }
+        let copies = if rng.chance(self.faults.duplicate) {
+            2
+        } else {
+            1
+        };
+        for _ in 0..copies {
+            let mut due = now + 1;
+            if rng.chance(self.faults.reorder) {
+                due += rng.between_1_and(MAX_REORDER_DELAY);
+            }
+            // The sequence number keeps same-tick packets in send order
+            self.sequence += 1;
+            self.in_flight.push(Reverse((due, self.sequence, id)));
+        }
+    }
+
+    /// Everything due at or before `now`, in arrival order
+    fn receive(&mut self, now: u64) -> Vec<u32> {
+        let mut arrived = Vec::new();
+        while let Some(&Reverse((due, _, id))) = self.in_flight.peek() {
+            if due > now {
+                break;
+            }
+            self.in_flight.pop();
+            arrived.push(id);
+        }
+        arrived
+    }
+
+    fn is_empty(&self) -> bool {
+        self.in_flight.is_empty()
+    }
+}
+
+// ============================================================
+// Ack strategies
+// ============================================================
+
+/// What the producer and the consumer do with acknowledgements.
+///
+/// The consumer acks every arrival; a strategy decides whether the producer
+/// listens and whether the consumer looks at what it already processed.
+trait AckStrategy {
+    fn name(&self) -> &'static str;
+
+    /// Producer: send an unacked message again? `attempts` sends so far,
+    /// `waited` ticks since the last one.
+    fn should_resend(&self, attempts: u32, waited: u64) -> bool;
+
+    /// Consumer: process a message that just arrived? `seen` is true if the
+    /// same id was processed before.
+    fn should_process(&self, seen: bool) -> bool;
+}
+
+/// Fire and forget: nothing is ever sent twice
+struct AtMostOnce;
+
+impl AckStrategy for AtMostOnce {
+    fn name(&self) -> &'static str {
+        "at-most-once"
+    }
+
+    fn should_resend(&self, _attempts: u32, _waited: u64) -> bool {
+        false
+    }
+
+    fn should_process(&self, _seen: bool) -> bool {
+        true
+    }
+}
+
+/// Resend after `timeout` ticks without an ack, up to `max_attempts` sends
+struct AtLeastOnce {
+    timeout: u64,
+    max_attempts: u32,
+}
+
+impl AckStrategy for AtLeastOnce {
+    fn name(&self) -> &'static str {
+        "at-least-once"
+    }
+
+    fn should_resend(&self, attempts: u32, waited: u64) -> bool {
+        attempts < self.max_attempts && waited >= self.timeout
+    }
+
+    fn should_process(&self, _seen: bool) -> bool {
+        true
+    }
+}
+
+/// At-least-once delivery plus an idempotent consumer. The network still
+/// delivers duplicates; the consumer just never acts on one twice.
+struct ExactlyOnce(AtLeastOnce);
+
+impl AckStrategy for ExactlyOnce {
+    fn name(&self) -> &'static str {
+        "exactly-once"
+    }
+
+    fn should_resend(&self, attempts: u32, waited: u64) -> bool {
+        self.0.should_resend(attempts, waited)
+    }
+
+    fn should_process(&self, seen: bool) -> bool {
+        !seen
+    }
+}
+
+// ============================================================
+// Simulation
+// ============================================================
+
+/// Producer-side state of one message
+struct Outgoing {
+    attempts: u32,
+    last_sent: u64,
+    acked: bool,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Report {
+    /// Transmissions, retries included
+    sends: u64,
+    /// Distinct messages the consumer processed
+    delivered: u64,
+    /// Processings beyond the first of a message
+    duplicated: u64,
+    /// Messages never processed
+    lost: u64,
+    /// Processings of an id lower than one already processed
+    reordered: u64,
+    /// Ticks until nothing was left to send or in flight
+    ticks: u64,
+}
+
+fn simulate(strategy: &dyn AckStrategy, messages: u32, faults: Faults, seed: u64) -> Report {
+    let mut rng = Rng::new(seed);
+    let mut data = LossyChannel::new(faults);
+    let mut acks = LossyChannel::new(faults);
+    let mut outgoing: Vec<Outgoing> = Vec::with_capacity(messages as usize);
+    let mut processed = HashSet::new();
+    let mut highest: Option<u32> = None;
+    let mut report = Report::default();
+    let mut now = 0;
+
+    loop {
+        // Producer: one new message per tick, then any retries that are due
+        if (outgoing.len() as u32) < messages {
+            let id = outgoing.len() as u32;
+            data.send(now, id, &mut rng);
+            report.sends += 1;
+            outgoing.push(Outgoing {
+                attempts: 1,
+                last_sent: now,
+                acked: false,
+            });
+        }
+        for (id, msg) in outgoing.iter_mut().enumerate() {
+            if !msg.acked && strategy.should_resend(msg.attempts, now - msg.last_sent) {
+                data.send(now, id as u32, &mut rng);
+                report.sends += 1;
+                msg.attempts += 1;
+                msg.last_sent = now;
+            }
+        }
+
+        // Consumer: process (or skip) each arrival, ack it either way
+        for id in data.receive(now) {
+            let seen = processed.contains(&id);
+            if strategy.should_process(seen) {
+                if seen {
+                    report.duplicated += 1;
+                } else {
+                    processed.insert(id);
+                }
+                if highest.is_some_and(|h| id < h) {
+                    report.reordered += 1;
+                }
+                highest = highest.max(Some(id));
+            }
+            acks.send(now, id, &mut rng);
+        }
+
+        // Producer: collect acks
+        for id in acks.receive(now) {
+            outgoing[id as usize].acked = true;
+        }
+
+        now += 1;
+
+        // Done when everything was sent, nothing is in flight and no
+        // message will ever be resent
+        let settled = outgoing
+            .iter()
+            .all(|msg| msg.acked || !strategy.should_resend(msg.attempts, u64::MAX));
+        if outgoing.len() as u32 == messages && data.is_empty() && acks.is_empty() && settled {
+            break;
+        }
+    }
+
+    report.delivered = processed.len() as u64;
+    report.lost = u64::from(messages) - report.delivered;
+    report.ticks = now;
+    report
+}
+
+// ============================================================
+// Command line
+// ============================================================
+
+struct Config {
+    messages: u32,
+    faults: Faults,
+    seed: u64,
+    timeout: u64,
+    max_attempts: u32,
+    strategies: Vec<String>,
+}
+
+const STRATEGY_NAMES: [&str; 3] = ["at-most-once", "at-least-once", "exactly-once"];
+
+fn usage() -> String {
+    "usage: delivery_semantics [--messages N] [--drop P] [--duplicate P] [--reorder P] \
+     [--seed S] [--timeout TICKS] [--max-attempts N] [--strategy all|at-most-once|at-least-once|exactly-once]"
+        .to_string()
+}
+
+fn parse_args(args: Vec<String>) -> Result<Config, String> {
+    let mut config = Config {
+        messages: 1000,
+        faults: Faults {
+            drop: 0.1,
+            duplicate: 0.05,
+            reorder: 0.1,
+        },
+        seed: 42,
+        timeout: 4,
+        max_attempts: 10,
+        strategies: STRATEGY_NAMES.iter().map(|s| s.to_string()).collect(),
+    };
+
+    let mut args = args.into_iter();
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value\n{}", flag, usage()))?;
+        let bad = || format!("invalid value for {}: {}", flag, value);
+        let probability = || match value.parse::<f64>() {
+            Ok(p) if (0.0..=1.0).contains(&p) => Ok(p),
+            _ => Err(format!("{} must be between 0 and 1, got {}", flag, value)),
+        };
+
+        match flag.as_str() {
+            "--messages" => config.messages = value.parse().map_err(|_| bad())?,
+            "--drop" => config.faults.drop = probability()?,
+            "--duplicate" => config.faults.duplicate = probability()?,
+            "--reorder" => config.faults.reorder = probability()?,
+            "--seed" => config.seed = value.parse().map_err(|_| bad())?,
+            "--timeout" => config.timeout = value.parse().map_err(|_| bad())?,
+            "--max-attempts" => match value.parse() {
+                Ok(n) if n >= 1 => config.max_attempts = n,
+                _ => return Err(bad()),
+            },
+            "--strategy" if value == "all" => {}
+            "--strategy" if STRATEGY_NAMES.contains(&value.as_str()) => {
+                config.strategies = vec![value.clone()]
+            }
+            "--strategy" => return Err(bad()),
+            _ => return Err(format!("unknown option {}\n{}", flag, usage())),
+        }
+    }
+
+    Ok(config)
+}
+
+fn build_strategy(name: &str, config: &Config) -> Box<dyn AckStrategy> {
+    let at_least_once = AtLeastOnce {
+        timeout: config.timeout,
+        max_attempts: config.max_attempts,
+    };
+    match name {
+        "at-most-once" => Box::new(AtMostOnce),
+        "at-least-once" => Box::new(at_least_once),
+        _ => Box::new(ExactlyOnce(at_least_once)),
+    }
+}
+
+fn main() {
+    let config = match parse_args(std::env::args().skip(1).collect()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("delivery_semantics: {}", e);
+            std::process::exit(2);
+        }
+    };
+
+    println!(
+        "messages={} drop={} duplicate={} reorder={} seed={}",
+        config.messages,
+        config.faults.drop,
+        config.faults.duplicate,
+        config.faults.reorder,
+        config.seed
+    );
+    for name in &config.strategies {
+        let strategy = build_strategy(name, &config);
+        // Same seed for every strategy: each faces the same dice
+        let report = simulate(
+            strategy.as_ref(),
+            config.messages,
+            config.faults,
+            config.seed,
+        );
+        println!(
+            "strategy={:<13} sends={} delivered={} duplicated={} lost={} reordered={} ticks={}",
+            strategy.name(),
+            report.sends,
+            report.delivered,
+            report.duplicated,
+            report.lost,
+            report.reordered,
+            report.ticks
+        );
+    }
+}
+
+// Key concepts demonstrated:
+//
+// 1. THE GUARANTEE IS A PRODUCER + CONSUMER CONTRACT:
+//    - Retrying on the producer turns loss into duplicates
+//    - Deduplicating on the consumer turns duplicates into no-ops
+//    - Neither side alone gives "exactly once"
+//
+// 2. LOST ACKS CAUSE DUPLICATES:
+//    - The producer can't tell "message lost" from "ack lost"
+//    - Either way it resends, so at-least-once duplicates even on a channel
+//      that never duplicates by itself
+//
+// 3. AT-MOST-ONCE ASSUMES AN HONEST CHANNEL:
+//    - It never sends twice, but a channel that duplicates (UDP, a broker
+//      redelivering after a failover) still hands the consumer copies
+//
+// 4. THE COST OF SAFETY:
+//    - `sends` grows with the drop rate; `ticks` grows with the timeout
+//    - The dedup set grows with every id ever processed - real systems
+//      bound it with a time window or a per-producer sequence number
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NO_FAULTS: Faults = Faults {
+        drop: 0.0,
+        duplicate: 0.0,
+        reorder: 0.0,
+    };
+
+    fn at_least_once() -> AtLeastOnce {
+        AtLeastOnce {
+            timeout: 4,
+            max_attempts: 10,
+        }
+    }
+
+    #[test]
+    fn test_perfect_channel_delivers_everything_once() {
+        for strategy in [
+            Box::new(AtMostOnce) as Box<dyn AckStrategy>,
+            Box::new(at_least_once()),
+            Box::new(ExactlyOnce(at_least_once())),
+        ] {
+            let report = simulate(strategy.as_ref(), 100, NO_FAULTS, 1);
+            assert_eq!(report.delivered, 100, "{}", strategy.name());
+            assert_eq!(report.sends, 100, "{}", strategy.name());
+            assert_eq!(report.duplicated + report.lost + report.reordered, 0);
+        }
+    }
+
+    #[test]
+    fn test_reordered_packets_arrive_late() {
+        let mut rng = Rng::new(7);
+        let mut channel = LossyChannel::new(Faults {
+            reorder: 1.0,
+            ..NO_FAULTS
+        });
+        channel.send(0, 1, &mut rng);
+        assert!(channel.receive(1).is_empty());
+        assert_eq!(channel.receive(1 + MAX_REORDER_DELAY), vec![1]);
+    }
+
+    #[test]
+    fn test_exactly_once_survives_every_fault() {
+        let faults = Faults {
+            drop: 0.3,
+            duplicate: 0.3,
+            reorder: 0.3,
+        };
+        let report = simulate(&ExactlyOnce(at_least_once()), 500, faults, 3);
+        assert_eq!(report.delivered, 500);
+        assert_eq!(report.duplicated, 0);
+        assert_eq!(report.lost, 0);
+    }
+
+    #[test]
+    fn test_same_seed_same_report() {
+        let faults = Faults {
+            drop: 0.2,
+            duplicate: 0.1,
+            reorder: 0.2,
+        };
+        assert_eq!(
+            simulate(&at_least_once(), 200, faults, 9),
+            simulate(&at_least_once(), 200, faults, 9)
+        );
+    }
+}
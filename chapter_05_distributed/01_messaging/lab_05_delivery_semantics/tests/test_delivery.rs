@@ -0,0 +1,200 @@
+//! Lab 5 Tests - Delivery Semantics Simulator
+//!
+//! Run with: cargo test
+
+use std::collections::HashMap;
+use std::process::Command;
+
+fn run_sim(args: &[&str]) -> (String, String, bool) {
+    let output = Command::new(env!("CARGO_BIN_EXE_delivery_semantics"))
+        .args(args)
+        .output()
+        .expect("Failed to execute program");
+
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+
+    (stdout, stderr, output.status.success())
+}
+
+/// Parse `strategy=... key=value ...` lines into strategy -> (key -> value)
+fn reports(stdout: &str) -> HashMap<String, HashMap<String, u64>> {
+    stdout
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let strategy = fields.next()?.strip_prefix("strategy=")?.to_string();
+            let values = fields
+                .filter_map(|field| {
+                    let (key, value) = field.split_once('=')?;
+                    Some((key.to_string(), value.parse().ok()?))
+                })
+                .collect();
+            Some((strategy, values))
+        })
+        .collect()
+}
+
+fn run_reports(args: &[&str]) -> HashMap<String, HashMap<String, u64>> {
+    let (stdout, stderr, success) = run_sim(args);
+    assert!(success, "simulator failed: {}", stderr);
+    reports(&stdout)
+}
+
+#[test]
+fn test_01_perfect_channel_delivers_everything_once() {
+    let reports = run_reports(&[
+        "--messages",
+        "300",
+        "--drop",
+        "0",
+        "--duplicate",
+        "0",
+        "--reorder",
+        "0",
+    ]);
+
+    assert_eq!(reports.len(), 3, "expected one line per strategy");
+    for (strategy, report) in &reports {
+        assert_eq!(report["delivered"], 300, "{}", strategy);
+        assert_eq!(
+            report["sends"], 300,
+            "{} resent on a perfect channel",
+            strategy
+        );
+        assert_eq!(report["duplicated"], 0, "{}", strategy);
+        assert_eq!(report["lost"], 0, "{}", strategy);
+        assert_eq!(report["reordered"], 0, "{}", strategy);
+    }
+}
+
+#[test]
+fn test_02_at_most_once_loses_but_never_resends() {
+    let reports = run_reports(&[
+        "--messages",
+        "1000",
+        "--drop",
+        "0.2",
+        "--duplicate",
+        "0",
+        "--strategy",
+        "at-most-once",
+    ]);
+
+    let report = &reports["at-most-once"];
+    assert_eq!(report["sends"], 1000);
+    assert_eq!(report["duplicated"], 0);
+    assert!(report["lost"] > 0, "20% drop should lose messages");
+    assert_eq!(report["delivered"] + report["lost"], 1000);
+}
+
+#[test]
+fn test_03_at_least_once_duplicates_without_a_duplicating_channel() {
+    let reports = run_reports(&[
+        "--messages",
+        "1000",
+        "--drop",
+        "0.2",
+        "--duplicate",
+        "0",
+        "--strategy",
+        "at-least-once",
+    ]);
+
+    let report = &reports["at-least-once"];
+    assert_eq!(report["lost"], 0);
+    assert_eq!(report["delivered"], 1000);
+    // Lost acks make the producer resend messages that did arrive
+    assert!(
+        report["duplicated"] > 0,
+        "lost acks should cause duplicates"
+    );
+    assert!(report["sends"] > 1000);
+}
+
+#[test]
+fn test_04_exactly_once_survives_every_fault() {
+    let reports = run_reports(&[
+        "--messages",
+        "2000",
+        "--drop",
+        "0.3",
+        "--duplicate",
+        "0.3",
+        "--reorder",
+        "0.3",
+        "--strategy",
+        "exactly-once",
+    ]);
+
+    let report = &reports["exactly-once"];
+    assert_eq!(report["delivered"], 2000);
+    assert_eq!(report["duplicated"], 0);
+    assert_eq!(report["lost"], 0);
+}
+
+#[test]
+fn test_05_retries_run_out() {
+    // Two sends at 50% drop: about a quarter of the messages never arrive
+    let reports = run_reports(&[
+        "--messages",
+        "1000",
+        "--drop",
+        "0.5",
+        "--max-attempts",
+        "2",
+        "--strategy",
+        "at-least-once",
+    ]);
+
+    let report = &reports["at-least-once"];
+    assert!(report["lost"] > 0, "two attempts cannot beat 50% loss");
+    assert!(report["sends"] <= 2000);
+}
+
+#[test]
+fn test_06_reordering_is_reported() {
+    let reports = run_reports(&[
+        "--messages",
+        "500",
+        "--drop",
+        "0",
+        "--duplicate",
+        "0",
+        "--reorder",
+        "0.3",
+        "--strategy",
+        "at-most-once",
+    ]);
+
+    let report = &reports["at-most-once"];
+    assert_eq!(report["delivered"], 500);
+    assert!(report["reordered"] > 0);
+}
+
+#[test]
+fn test_07_same_seed_same_output() {
+    let args = ["--seed", "7", "--drop", "0.2", "--duplicate", "0.1"];
+    let (first, _, _) = run_sim(&args);
+    let (second, _, _) = run_sim(&args);
+    assert_eq!(first, second);
+
+    let (other, _, _) = run_sim(&["--seed", "8", "--drop", "0.2", "--duplicate", "0.1"]);
+    assert_ne!(first, other, "a different seed should roll different dice");
+}
+
+#[test]
+fn test_08_invalid_arguments() {
+    for args in [
+        &["--drop", "1.5"][..],
+        &["--duplicate", "-0.1"],
+        &["--strategy", "twice"],
+        &["--max-attempts", "0"],
+        &["--messages"],
+        &["--bogus", "1"],
+    ] {
+        let (_, stderr, success) = run_sim(args);
+        assert!(!success, "{:?} should be rejected", args);
+        assert!(!stderr.is_empty(), "{:?} should explain the error", args);
+    }
+}
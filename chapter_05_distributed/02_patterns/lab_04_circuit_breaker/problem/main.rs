@@ -8,6 +8,10 @@
 //! 2. Open after N consecutive failures
 //! 3. Transition to HalfOpen after timeout
 //! 4. Close after success in HalfOpen
+//! 5. Optionally persist state and failure count (to a file or Redis), so
+//!    a restarted service starts OPEN instead of hammering a dependency the
+//!    previous process already gave up on; saved state older than `max_age`
+//!    is ignored
 //!
 //! ## Expected Behavior
 //! ```
@@ -36,6 +40,11 @@
 //! - Track consecutive failures
 //! - Reset failure count on success
 //! - In HalfOpen, single success closes, single failure opens
+//! - `Instant` can't be saved: store the open deadline as Unix milliseconds
+//! - An open deadline that passed while the process was down -> HalfOpen
+//! - Write to a temp file and `rename` it, so a crash never leaves half a file
+//! - Redis needs only `*2\r\n$3\r\nGET\r\n$<len>\r\n<key>\r\n` over a
+//!   `TcpStream`; use short timeouts, Redis may be down too
 //!
 //! ## Acceptance Criteria
 //! - [ ] Starts in Closed state
@@ -43,7 +52,10 @@
 //! - [ ] Rejects calls when Open
 //! - [ ] Transitions to HalfOpen after timeout
 //! - [ ] Closes on HalfOpen success
+//! - [ ] An OPEN circuit is still OPEN after a restart
+//! - [ ] Stale or corrupt saved state starts CLOSED instead of failing
 
+use std::path::PathBuf;
 use std::time::{Duration, Instant};
 
 // ============================================================
@@ -69,6 +81,7 @@ struct CircuitBreaker {
     failure_count: u32,
     failure_threshold: u32,
     reset_timeout: Duration,
+    store: Option<Box<dyn StateStore>>,
 }
 
 impl CircuitBreaker {
@@ -78,6 +91,22 @@ impl CircuitBreaker {
         todo!("Implement CircuitBreaker::new")
     }
 
+    /// Load saved state (ignore it if older than `max_age`), then save every
+    /// change from now on
+    fn with_store(self, store: Box<dyn StateStore>, max_age: Duration) -> Self {
+        // TODO: store.load() -> Snapshot::decode -> restore unless stale
+        // Open with a future deadline -> Open, past deadline -> HalfOpen
+
+        todo!("Implement with_store")
+    }
+
+    /// Save state and failure count if a store is set
+    fn persist(&mut self) {
+        // TODO: Build a Snapshot, encode it, store.save(); log errors, don't fail
+
+        todo!("Implement persist")
+    }
+
     /// Get current state name
     fn state_name(&self) -> &str {
         // TODO: Return state name
@@ -115,6 +144,65 @@ impl CircuitBreaker {
     }
 }
 
+// ============================================================
+// TODO: Persistence
+// ============================================================
+
+/// What survives a restart
+struct Snapshot {
+    state: &'static str, // "closed", "open" or "half_open"
+    open_until_ms: u64,  // Unix time, only for "open"
+    failure_count: u32,
+    saved_at_ms: u64,
+}
+
+impl Snapshot {
+    /// `key=value` lines
+    fn encode(&self) -> String {
+        todo!("Implement Snapshot::encode")
+    }
+
+    fn decode(text: &str) -> Result<Snapshot, String> {
+        todo!("Implement Snapshot::decode")
+    }
+}
+
+trait StateStore {
+    /// None if nothing was saved yet
+    fn load(&mut self) -> Result<Option<String>, String>;
+    fn save(&mut self, text: &str) -> Result<(), String>;
+}
+
+struct FileStore {
+    path: PathBuf,
+}
+
+impl StateStore for FileStore {
+    fn load(&mut self) -> Result<Option<String>, String> {
+        todo!("Implement FileStore::load")
+    }
+
+    fn save(&mut self, text: &str) -> Result<(), String> {
+        todo!("Implement FileStore::save")
+    }
+}
+
+/// GET/SET one key over a raw RESP connection
+struct RedisStore {
+    addr: String,
+    key: String,
+}
+
+impl StateStore for RedisStore {
+    fn load(&mut self) -> Result<Option<String>, String> {
+        todo!("Implement RedisStore::load")
+    }
+
+    fn save(&mut self, text: &str) -> Result<(), String> {
+        todo!("Implement RedisStore::save")
+    }
+}
+
 #[tokio::main]
 async fn main() {
     // TODO: Implement demo
@@ -124,6 +212,7 @@ async fn main() {
     // 4. Show rejected calls
     // 5. Wait for timeout
     // 6. Show recovery
+    // 7. Restart with a store while OPEN: the new breaker starts OPEN
 
     todo!("Implement main")
 }
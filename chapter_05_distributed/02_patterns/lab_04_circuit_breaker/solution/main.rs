@@ -1,7 +1,10 @@
 //! Lab 4 Reference Answer
 
 use std::fmt;
-use std::time::{Duration, Instant};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 /// Circuit breaker states
 #[derive(Debug)]
@@ -48,6 +51,10 @@ struct CircuitBreaker {
     successful_calls: u64,
     failed_calls: u64,
     rejected_calls: u64,
+    // Persistence (optional)
+    store: Option<Box<dyn StateStore>>,
+    /// (state, failure_count) last written, to skip writes that change nothing
+    saved: Option<(String, u32)>,
 }
 
 impl CircuitBreaker {
@@ -61,6 +68,88 @@ impl CircuitBreaker {
             successful_calls: 0,
             failed_calls: 0,
             rejected_calls: 0,
+            store: None,
+            saved: None,
+        }
+    }
+
+    /// Load the state a previous process saved, and save every change from
+    /// now on. Saved state older than `max_age` is ignored: whatever was
+    /// wrong with the dependency then says little about it now.
+    fn with_store(mut self, mut store: Box<dyn StateStore>, max_age: Duration) -> Self {
+        match store
+            .load()
+            .and_then(|text| text.map(|t| Snapshot::decode(&t)).transpose())
+        {
+            Ok(Some(snapshot)) => self.restore(snapshot, max_age),
+            Ok(None) => println!("  [Circuit] No saved state, starting CLOSED"),
+            // A broken store must not keep the service from starting
+            Err(e) => eprintln!("  [Circuit] Ignoring saved state: {}", e),
+        }
+        self.store = Some(store);
+        self.persist();
+        self
+    }
+
+    fn restore(&mut self, snapshot: Snapshot, max_age: Duration) {
+        let now = unix_ms();
+        let age = Duration::from_millis(now.saturating_sub(snapshot.saved_at_ms));
+        if age > max_age {
+            println!(
+                "  [Circuit] Saved state is {}s old (max {}s), starting CLOSED",
+                age.as_secs(),
+                max_age.as_secs()
+            );
+            return;
+        }
+
+        self.failure_count = snapshot.failure_count;
+        self.state = match snapshot.state {
+            SavedState::Closed => State::Closed,
+            SavedState::HalfOpen => State::HalfOpen,
+            SavedState::Open { until_ms } if until_ms > now => State::Open {
+                until: Instant::now() + Duration::from_millis(until_ms - now),
+            },
+            // The timeout ran out while we were down: one probe call decides,
+            // not a flood of them
+            SavedState::Open { .. } => State::HalfOpen,
+        };
+        println!(
+            "  [Circuit] Restored {} with {} failure(s), saved {}ms ago",
+            self.state,
+            self.failure_count,
+            age.as_millis()
+        );
+    }
+
+    /// Write the state if it changed since the last write
+    fn persist(&mut self) {
+        let Some(store) = self.store.as_mut() else {
+            return;
+        };
+        let key = (self.state.to_string(), self.failure_count);
+        if self.saved.as_ref() == Some(&key) {
+            return;
+        }
+
+        let now = unix_ms();
+        let state = match self.state {
+            State::Closed => SavedState::Closed,
+            State::HalfOpen => SavedState::HalfOpen,
+            // Instant means nothing to another process: store wall-clock time
+            State::Open { until } => SavedState::Open {
+                until_ms: now + until.saturating_duration_since(Instant::now()).as_millis() as u64,
+            },
+        };
+        let snapshot = Snapshot {
+            state,
+            failure_count: self.failure_count,
+            saved_at_ms: now,
+        };
+        match store.save(&snapshot.encode()) {
+            Ok(()) => self.saved = Some(key),
+            // Calls go on without persistence; the next change tries again
+            Err(e) => eprintln!("  [Circuit] Could not save state: {}", e),
         }
     }
 
@@ -75,6 +164,7 @@ impl CircuitBreaker {
             if Instant::now() >= until {
                 println!("  [Circuit] Timeout expired, transitioning to HALF_OPEN");
                 self.state = State::HalfOpen;
+                self.persist();
             }
         }
     }
@@ -127,6 +217,7 @@ impl CircuitBreaker {
                 // Shouldn't happen, but handle gracefully
             }
         }
+        self.persist();
     }
 
     /// Record failure
@@ -157,6 +248,7 @@ impl CircuitBreaker {
                 // Shouldn't happen
             }
         }
+        self.persist();
     }
 
     /// Get statistics
@@ -170,6 +262,190 @@ impl CircuitBreaker {
     }
 }
 
+// ============================================================
+// Persistence
+// ============================================================
+
+fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SavedState {
+    Closed,
+    Open { until_ms: u64 },
+    HalfOpen,
+}
+
+/// What survives a restart, as `key=value` lines:
+///
+/// ```text
+/// state=open
+/// open_until_ms=1760000030000
+/// failure_count=3
+/// saved_at_ms=1760000000000
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Snapshot {
+    state: SavedState,
+    failure_count: u32,
+    saved_at_ms: u64,
+}
+
+impl Snapshot {
+    fn encode(&self) -> String {
+        let (state, until) = match self.state {
+            SavedState::Closed => ("closed", None),
+            SavedState::Open { until_ms } => ("open", Some(until_ms)),
+            SavedState::HalfOpen => ("half_open", None),
+        };
+        let mut text = format!("state={}\n", state);
+        if let Some(until_ms) = until {
+            text += &format!("open_until_ms={}\n", until_ms);
+        }
+        text += &format!(
+            "failure_count={}\nsaved_at_ms={}\n",
+            self.failure_count, self.saved_at_ms
+        );
+        text
+    }
+
+    fn decode(text: &str) -> Result<Snapshot, String> {
+        let field = |name: &str| -> Result<&str, String> {
+            text.lines()
+                .find_map(|line| line.strip_prefix(name)?.strip_prefix('='))
+                .map(str::trim)
+                .ok_or_else(|| format!("missing {}", name))
+        };
+        let number = |name: &str| -> Result<u64, String> {
+            field(name)?
+                .parse()
+                .map_err(|_| format!("{} is not a number", name))
+        };
+
+        let state = match field("state")? {
+            "closed" => SavedState::Closed,
+            "half_open" => SavedState::HalfOpen,
+            "open" => SavedState::Open {
+                until_ms: number("open_until_ms")?,
+            },
+            other => return Err(format!("unknown state '{}'", other)),
+        };
+        Ok(Snapshot {
+            state,
+            failure_count: number("failure_count")? as u32,
+            saved_at_ms: number("saved_at_ms")?,
+        })
+    }
+}
+
+/// Somewhere to keep the encoded snapshot between runs
+trait StateStore {
+    /// None if nothing was saved yet
+    fn load(&mut self) -> Result<Option<String>, String>;
+    fn save(&mut self, text: &str) -> Result<(), String>;
+}
+
+/// A file on local disk: survives restarts of this one process
+struct FileStore {
+    path: PathBuf,
+}
+
+impl StateStore for FileStore {
+    fn load(&mut self) -> Result<Option<String>, String> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(text) => Ok(Some(text)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("{}: {}", self.path.display(), e)),
+        }
+    }
+
+    fn save(&mut self, text: &str) -> Result<(), String> {
+        // Write then rename: a crash mid-write leaves the old file, never half
+        // of a new one
+        let tmp = self.path.with_extension("tmp");
+        std::fs::write(&tmp, text)
+            .and_then(|_| std::fs::rename(&tmp, &self.path))
+            .map_err(|e| format!("{}: {}", self.path.display(), e))
+    }
+}
+
+/// A Redis key: shared by every instance of the service, so a new replica
+/// also knows the dependency is down. Speaks just enough RESP for GET/SET.
+struct RedisStore {
+    addr: String,
+    key: String,
+}
+
+impl RedisStore {
+    /// Send one command, return the bulk string reply (None for nil)
+    fn command(&self, args: &[&str]) -> Result<Option<String>, String> {
+        let err = |e: std::io::Error| format!("redis {}: {}", self.addr, e);
+        let addr = self
+            .addr
+            .to_socket_addrs()
+            .map_err(err)?
+            .next()
+            .ok_or_else(|| format!("redis {}: no address", self.addr))?;
+        // Short timeouts: Redis being down must not stall the service
+        let timeout = Duration::from_millis(500);
+        let mut stream = TcpStream::connect_timeout(&addr, timeout).map_err(err)?;
+        stream.set_read_timeout(Some(timeout)).map_err(err)?;
+
+        let mut request = format!("*{}\r\n", args.len());
+        for arg in args {
+            request += &format!("${}\r\n{}\r\n", arg.len(), arg);
+        }
+        stream.write_all(request.as_bytes()).map_err(err)?;
+
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        reader.read_line(&mut line).map_err(err)?;
+        let line = line.trim_end();
+        match line.split_at(line.len().min(1)) {
+            ("+", status) => Ok(Some(status.to_string())),
+            ("-", message) => Err(format!("redis {}: {}", self.addr, message)),
+            ("$", "-1") => Ok(None),
+            ("$", len) => {
+                let len: usize = len.parse().map_err(|_| "bad RESP length".to_string())?;
+                let mut body = vec![0; len + 2]; // data + \r\n
+                std::io::Read::read_exact(&mut reader, &mut body).map_err(err)?;
+                body.truncate(len);
+                String::from_utf8(body)
+                    .map(Some)
+                    .map_err(|_| "reply is not UTF-8".to_string())
+            }
+            _ => Err(format!("redis {}: unexpected reply '{}'", self.addr, line)),
+        }
+    }
+}
+
+impl StateStore for RedisStore {
+    fn load(&mut self) -> Result<Option<String>, String> {
+        self.command(&["GET", &self.key])
+    }
+
+    fn save(&mut self, text: &str) -> Result<(), String> {
+        self.command(&["SET", &self.key, text]).map(|_| ())
+    }
+}
+
+/// `BREAKER_REDIS=host:port` picks Redis, otherwise a file in the temp dir
+fn store_from_env() -> Box<dyn StateStore> {
+    match std::env::var("BREAKER_REDIS") {
+        Ok(addr) => Box::new(RedisStore {
+            addr,
+            key: "circuit_breaker:demo".to_string(),
+        }),
+        Err(_) => Box::new(FileStore {
+            path: std::env::temp_dir().join("circuit_breaker_demo.state"),
+        }),
+    }
+}
+
 /// Simulated external service
 struct UnreliableService {
     should_fail: bool,
@@ -289,12 +565,36 @@ async fn main() {
     println!("Failed:      {}", failed);
     println!("Rejected:    {}", rejected);
 
+    // Test 7: Restart with the circuit open
+    println!("\nTest 7: Restart while the circuit is open");
+    println!("-----------------------------------------");
+    service.set_failing(true);
+    let max_age = Duration::from_secs(60);
+    let mut breaker =
+        CircuitBreaker::new(3, Duration::from_secs(30)).with_store(store_from_env(), max_age);
+    for _ in 0..3 {
+        let _ = breaker.call(|| service.call());
+    }
+    println!("  State before restart: {}", breaker.state());
+    drop(breaker);
+
+    println!("  (process restarts)");
+    let mut breaker =
+        CircuitBreaker::new(3, Duration::from_secs(30)).with_store(store_from_env(), max_age);
+    println!("  State after restart: {}", breaker.state());
+    match breaker.call(|| service.call()) {
+        Err(CircuitError::Open) => println!("  Call: REJECTED, the dead dependency is left alone"),
+        Ok(result) => println!("  Call: {}", result),
+        Err(e) => println!("  Call: Error - {}", e),
+    }
+
     println!("\n=== Key Concepts ===");
     println!("- CLOSED: Normal operation, counting failures");
     println!("- OPEN: Failing fast, rejecting all calls");
     println!("- HALF_OPEN: Testing if service recovered");
     println!("- Prevents cascade failures");
     println!("- Gives failing service time to recover");
+    println!("- Persisted state stops a restart from hammering a known-bad dependency");
 }
 
 // Key concepts demonstrated:
@@ -319,6 +619,13 @@ async fn main() {
 //    - Give service time to recover
 //    - Don't test constantly
 //    - Configurable based on service
+//
+// 5. PERSISTENCE:
+//    - A fresh process starts CLOSED and sends full traffic at a dependency
+//      the old process had already given up on
+//    - Saving state + failure count (file or Redis) lets it start OPEN
+//    - An open deadline that passed during the restart becomes HALF_OPEN
+//    - State older than max_age is dropped: stale knowledge is worse than none
 
 #[cfg(test)]
 mod tests {
@@ -371,4 +678,134 @@ mod tests {
 
         assert!(matches!(breaker.state(), State::Closed));
     }
+
+    /// A FileStore in the temp dir, holding `saved` (if any)
+    fn file_store(name: &str, saved: Option<&str>) -> Box<FileStore> {
+        let path = std::env::temp_dir().join(format!(
+            "circuit_breaker_test_{}_{}.state",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        if let Some(text) = saved {
+            std::fs::write(&path, text).unwrap();
+        }
+        Box::new(FileStore { path })
+    }
+
+    fn saved(state: SavedState, saved_ago: Duration) -> String {
+        Snapshot {
+            state,
+            failure_count: 3,
+            saved_at_ms: unix_ms() - saved_ago.as_millis() as u64,
+        }
+        .encode()
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        for state in [
+            SavedState::Closed,
+            SavedState::HalfOpen,
+            SavedState::Open { until_ms: 1234 },
+        ] {
+            let snapshot = Snapshot {
+                state,
+                failure_count: 2,
+                saved_at_ms: 99,
+            };
+            assert_eq!(Snapshot::decode(&snapshot.encode()), Ok(snapshot));
+        }
+        assert!(Snapshot::decode("state=open\nfailure_count=1\nsaved_at_ms=1\n").is_err());
+        assert!(Snapshot::decode("state=sideways\n").is_err());
+    }
+
+    #[test]
+    fn test_open_state_survives_restart() {
+        let store = file_store("restart", None);
+        let path = store.path.clone();
+        let mut breaker = CircuitBreaker::new(1, Duration::from_secs(30))
+            .with_store(store, Duration::from_secs(60));
+        let _ = breaker.call(|| Err::<(), _>("fail".to_string()));
+        drop(breaker);
+
+        let store = Box::new(FileStore { path: path.clone() });
+        let mut breaker = CircuitBreaker::new(1, Duration::from_secs(30))
+            .with_store(store, Duration::from_secs(60));
+        assert!(matches!(breaker.state(), State::Open { .. }));
+        let result = breaker.call(|| Ok::<_, String>("success"));
+        assert!(matches!(result, Err(CircuitError::Open)));
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_expired_open_state_restores_half_open() {
+        let text = saved(
+            SavedState::Open {
+                until_ms: unix_ms() - 1000,
+            },
+            Duration::from_secs(5),
+        );
+        let breaker = CircuitBreaker::new(3, Duration::from_secs(30))
+            .with_store(file_store("expired", Some(&text)), Duration::from_secs(60));
+        assert!(matches!(breaker.state(), State::HalfOpen));
+        assert_eq!(breaker.failure_count, 3);
+    }
+
+    #[test]
+    fn test_stale_state_is_ignored() {
+        let text = saved(
+            SavedState::Open {
+                until_ms: unix_ms() + 60_000,
+            },
+            Duration::from_secs(120),
+        );
+        let breaker = CircuitBreaker::new(3, Duration::from_secs(30))
+            .with_store(file_store("stale", Some(&text)), Duration::from_secs(60));
+        assert!(matches!(breaker.state(), State::Closed));
+        assert_eq!(breaker.failure_count, 0);
+    }
+
+    #[test]
+    fn test_corrupt_state_starts_closed() {
+        let breaker = CircuitBreaker::new(3, Duration::from_secs(30)).with_store(
+            file_store("corrupt", Some("garbage")),
+            Duration::from_secs(60),
+        );
+        assert!(matches!(breaker.state(), State::Closed));
+    }
+
+    #[test]
+    fn test_redis_store_speaks_resp() {
+        use std::io::Read;
+        use std::net::TcpListener;
+
+        // A fake Redis answering one SET and one GET
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for reply in ["+OK\r\n", "$5\r\nhello\r\n"] {
+                let (mut conn, _) = listener.accept().unwrap();
+                let mut buf = [0; 256];
+                let n = conn.read(&mut buf).unwrap();
+                requests.push(String::from_utf8_lossy(&buf[..n]).to_string());
+                conn.write_all(reply.as_bytes()).unwrap();
+            }
+            requests
+        });
+
+        let mut store = RedisStore {
+            addr,
+            key: "cb".to_string(),
+        };
+        store.save("hello").unwrap();
+        assert_eq!(store.load().unwrap(), Some("hello".to_string()));
+        let requests = server.join().unwrap();
+        assert_eq!(
+            requests[0],
+            "*3\r\n$3\r\nSET\r\n$2\r\ncb\r\n$5\r\nhello\r\n"
+        );
+        assert_eq!(requests[1], "*2\r\n$3\r\nGET\r\n$2\r\ncb\r\n");
+    }
 }
@@ -0,0 +1,392 @@
+//! Lab 6 Reference Answer
+//!
+//! One register replicated on N in-process nodes. A write is acknowledged
+//! once W nodes have it; the other live nodes get it right away or, with
+//! probability `--lag`, some operations later. A read asks R live nodes and
+//! keeps the highest version. Nodes crash and recover at random, keeping
+//! whatever they stored.
+//!
+//! A read is stale when it returns an older version than the last
+//! acknowledged write. Sweeping every (R, W) shows the rule: stale reads
+//! happen only when R + W <= N.
+
+// ============================================================
+// Random numbers
+// ============================================================
+
+#[allow(dead_code)] // between_1_and is for the delivery semantics lab
+#[path = "../../../../shared/rng.rs"]
+mod rng;
+
+use rng::Rng;
+
+// ============================================================
+// Cluster
+// ============================================================
+
+/// One replica. The register's value is simply the version that wrote it.
+#[derive(Clone)]
+struct Node {
+    up: bool,
+    version: u64,
+}
+
+impl Node {
+    /// Last writer wins: a late, older copy never overwrites a newer one
+    fn apply(&mut self, version: u64) {
+        self.version = self.version.max(version);
+    }
+}
+
+/// A replica's copy of a write, still on its way
+struct Delayed {
+    due: u64,
+    node: usize,
+    version: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Settings {
+    nodes: usize,
+    ops: u64,
+    /// Share of operations that are writes
+    write_ratio: f64,
+    /// Chance per operation that an up node crashes
+    fail: f64,
+    /// Chance per operation that a down node comes back
+    recover: f64,
+    /// Chance that a replica outside the write quorum gets its copy late
+    lag: f64,
+    /// Most operations a late copy can take
+    max_lag: u64,
+    seed: u64,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Report {
+    writes: u64,
+    /// Fewer than W nodes up
+    write_failures: u64,
+    reads: u64,
+    /// Fewer than R nodes up
+    read_failures: u64,
+    /// Reads older than the last acknowledged write
+    stale: u64,
+    /// Largest number of versions a stale read was behind
+    max_behind: u64,
+}
+
+struct Cluster {
+    nodes: Vec<Node>,
+    delayed: Vec<Delayed>,
+    /// Version of the last acknowledged write
+    latest: u64,
+    rng: Rng,
+}
+
+impl Cluster {
+    fn new(nodes: usize, seed: u64) -> Self {
+        Self {
+            nodes: (0..nodes)
+                .map(|_| Node {
+                    up: true,
+                    version: 0,
+                })
+                .collect(),
+            delayed: Vec::new(),
+            latest: 0,
+            rng: Rng::new(seed),
+        }
+    }
+
+    /// Up nodes in random order: the first ones answer first
+    fn live_nodes(&mut self) -> Vec<usize> {
+        let mut live: Vec<usize> = (0..self.nodes.len())
+            .filter(|&i| self.nodes[i].up)
+            .collect();
+        self.rng.shuffle(&mut live);
+        live
+    }
+
+    /// Crash and recover nodes; a crashed node keeps its data
+    fn churn(&mut self, fail: f64, recover: f64) {
+        for node in &mut self.nodes {
+            let flip = if node.up { fail } else { recover };
+            if self.rng.chance(flip) {
+                node.up = !node.up;
+            }
+        }
+    }
+
+    /// Hand over late copies that are due; a node that is down misses them
+    fn deliver(&mut self, now: u64) {
+        let nodes = &mut self.nodes;
+        self.delayed.retain(|copy| {
+            if copy.due > now {
+                return true;
+            }
+            if nodes[copy.node].up {
+                nodes[copy.node].apply(copy.version);
+            }
+            false
+        });
+    }
+
+    /// Acknowledged once `w` nodes have it; false if fewer than `w` are up
+    fn write(&mut self, w: usize, now: u64, lag: f64, max_lag: u64) -> bool {
+        let live = self.live_nodes();
+        if live.len() < w {
+            return false;
+        }
+
+        let version = self.latest + 1;
+        let (quorum, rest) = live.split_at(w);
+        for &i in quorum {
+            self.nodes[i].apply(version);
+        }
+        for &i in rest {
+            if self.rng.chance(lag) {
+                let due = now + 1 + self.rng.below(max_lag as usize) as u64;
+                self.delayed.push(Delayed {
+                    due,
+                    node: i,
+                    version,
+                });
+            } else {
+                self.nodes[i].apply(version);
+            }
+        }
+        self.latest = version;
+        true
+    }
+
+    /// Newest version among `r` answering nodes; None if fewer than `r` are up
+    fn read(&mut self, r: usize) -> Option<u64> {
+        let live = self.live_nodes();
+        if live.len() < r {
+            return None;
+        }
+        live[..r].iter().map(|&i| self.nodes[i].version).max()
+    }
+}
+
+fn simulate(settings: &Settings, r: usize, w: usize) -> Report {
+    let mut cluster = Cluster::new(settings.nodes, settings.seed);
+    let mut report = Report::default();
+
+    for now in 0..settings.ops {
+        cluster.deliver(now);
+        cluster.churn(settings.fail, settings.recover);
+
+        if cluster.rng.chance(settings.write_ratio) {
+            report.writes += 1;
+            if !cluster.write(w, now, settings.lag, settings.max_lag) {
+                report.write_failures += 1;
+            }
+        } else {
+            report.reads += 1;
+            match cluster.read(r) {
+                None => report.read_failures += 1,
+                Some(version) if version < cluster.latest => {
+                    report.stale += 1;
+                    report.max_behind = report.max_behind.max(cluster.latest - version);
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    report
+}
+
+// ============================================================
+// Command line
+// ============================================================
+
+struct Config {
+    settings: Settings,
+    /// Some((r, w)) for one run, None to sweep every combination
+    quorum: Option<(usize, usize)>,
+}
+
+fn usage() -> String {
+    "usage: quorum [--nodes N] [--r R --w W] [--ops N] [--write-ratio P] [--fail P] \
+     [--recover P] [--lag P] [--max-lag OPS] [--seed S]"
+        .to_string()
+}
+
+fn parse_args(args: Vec<String>) -> Result<Config, String> {
+    let mut settings = Settings {
+        nodes: 3,
+        ops: 10_000,
+        write_ratio: 0.3,
+        fail: 0.02,
+        recover: 0.2,
+        lag: 0.5,
+        max_lag: 20,
+        seed: 42,
+    };
+    let (mut r, mut w) = (None, None);
+
+    let mut args = args.into_iter();
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value\n{}", flag, usage()))?;
+        let bad = || format!("invalid value for {}: {}", flag, value);
+        let probability = || match value.parse::<f64>() {
+            Ok(p) if (0.0..=1.0).contains(&p) => Ok(p),
+            _ => Err(format!("{} must be between 0 and 1, got {}", flag, value)),
+        };
+        let positive = || match value.parse::<u64>() {
+            Ok(n) if n >= 1 => Ok(n),
+            _ => Err(bad()),
+        };
+
+        match flag.as_str() {
+            "--nodes" => settings.nodes = positive()? as usize,
+            "--r" => r = Some(positive()? as usize),
+            "--w" => w = Some(positive()? as usize),
+            "--ops" => settings.ops = value.parse().map_err(|_| bad())?,
+            "--write-ratio" => settings.write_ratio = probability()?,
+            "--fail" => settings.fail = probability()?,
+            "--recover" => settings.recover = probability()?,
+            "--lag" => settings.lag = probability()?,
+            "--max-lag" => settings.max_lag = positive()?,
+            "--seed" => settings.seed = value.parse().map_err(|_| bad())?,
+            _ => return Err(format!("unknown option {}\n{}", flag, usage())),
+        }
+    }
+
+    let quorum = match (r, w) {
+        (None, None) => None,
+        (Some(r), Some(w)) if r <= settings.nodes && w <= settings.nodes => Some((r, w)),
+        (Some(_), Some(_)) => {
+            return Err(format!(
+                "--r and --w must be at most --nodes ({})",
+                settings.nodes
+            ))
+        }
+        _ => return Err("--r and --w go together".to_string()),
+    };
+    Ok(Config { settings, quorum })
+}
+
+fn main() {
+    let config = match parse_args(std::env::args().skip(1).collect()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("quorum: {}", e);
+            std::process::exit(2);
+        }
+    };
+    let s = config.settings;
+
+    println!(
+        "nodes={} ops={} write_ratio={} fail={} recover={} lag={} max_lag={} seed={}",
+        s.nodes, s.ops, s.write_ratio, s.fail, s.recover, s.lag, s.max_lag, s.seed
+    );
+
+    let combinations: Vec<(usize, usize)> = match config.quorum {
+        Some(quorum) => vec![quorum],
+        None => (1..=s.nodes)
+            .flat_map(|r| (1..=s.nodes).map(move |w| (r, w)))
+            .collect(),
+    };
+    for (r, w) in combinations {
+        // Same seed for every combination: reruns give the same table
+        let report = simulate(&s, r, w);
+        let ok_reads = report.reads - report.read_failures;
+        println!(
+            "r={} w={} overlap={:<3} writes={} write_failures={} reads={} read_failures={} stale={} stale_pct={:.2} max_behind={}",
+            r,
+            w,
+            if r + w > s.nodes { "yes" } else { "no" },
+            report.writes,
+            report.write_failures,
+            report.reads,
+            report.read_failures,
+            report.stale,
+            100.0 * report.stale as f64 / ok_reads.max(1) as f64,
+            report.max_behind
+        );
+    }
+}
+
+// Key concepts demonstrated:
+//
+// 1. R + W > N:
+//    - Any R nodes and any W nodes out of N share at least one node
+//    - That node has the last acknowledged write, and the read keeps the
+//      highest version it sees - so the read can't be stale
+//    - With R + W <= N the two sets can miss each other entirely
+//
+// 2. STALENESS NEEDS A CAUSE:
+//    - With no lag and no crashes every live node gets every write at once;
+//      even R = W = 1 never reads stale
+//    - Replication lag and crashed nodes coming back are what open the gap
+//
+// 3. QUORUMS TRADE AVAILABILITY FOR FRESHNESS:
+//    - W = N: every write fails while any node is down
+//    - R = 1, W = 1: always available, often stale
+//    - R = W = majority: survives a minority of crashes and never reads stale
+//
+// 4. WHAT THIS MODEL LEAVES OUT:
+//    - One writer with a global version counter; real systems need
+//      timestamps or vector clocks to order concurrent writes
+//    - No read repair / anti-entropy: a stale replica only catches up on
+//      the next write it receives
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> Settings {
+        Settings {
+            nodes: 5,
+            ops: 5_000,
+            write_ratio: 0.3,
+            fail: 0.05,
+            recover: 0.2,
+            lag: 0.8,
+            max_lag: 30,
+            seed: 1,
+        }
+    }
+
+    #[test]
+    fn test_overlapping_quorums_never_read_stale() {
+        for r in 1..=5 {
+            for w in 1..=5 {
+                if r + w > 5 {
+                    assert_eq!(simulate(&settings(), r, w).stale, 0, "r={} w={}", r, w);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_small_quorums_read_stale() {
+        assert!(simulate(&settings(), 1, 1).stale > 0);
+    }
+
+    #[test]
+    fn test_late_copy_does_not_roll_back() {
+        let mut node = Node {
+            up: true,
+            version: 5,
+        };
+        node.apply(3);
+        assert_eq!(node.version, 5);
+    }
+
+    #[test]
+    fn test_write_needs_w_live_nodes() {
+        let mut cluster = Cluster::new(3, 7);
+        cluster.nodes[0].up = false;
+        cluster.nodes[1].up = false;
+        assert!(!cluster.write(2, 0, 0.0, 1));
+        assert!(cluster.write(1, 0, 0.0, 1));
+        assert_eq!(cluster.latest, 1);
+    }
+}
@@ -0,0 +1,134 @@
+//! Lab 6 Tests - Quorum Reads and Writes
+//!
+//! Run with: cargo test
+
+use std::collections::HashMap;
+use std::process::Command;
+
+fn run_sim(args: &[&str]) -> (String, String, bool) {
+    let output = Command::new(env!("CARGO_BIN_EXE_quorum"))
+        .args(args)
+        .output()
+        .expect("Failed to execute program");
+
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+
+    (stdout, stderr, output.status.success())
+}
+
+/// One `r=.. w=.. key=value ...` line per run
+fn runs(args: &[&str]) -> Vec<HashMap<String, String>> {
+    let (stdout, stderr, success) = run_sim(args);
+    assert!(success, "simulator failed: {}", stderr);
+    stdout
+        .lines()
+        .filter(|line| line.starts_with("r="))
+        .map(|line| {
+            line.split_whitespace()
+                .filter_map(|field| field.split_once('='))
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        })
+        .collect()
+}
+
+fn num(run: &HashMap<String, String>, key: &str) -> u64 {
+    run[key]
+        .parse()
+        .unwrap_or_else(|_| panic!("{} is not a number: {:?}", key, run))
+}
+
+#[test]
+fn test_01_sweep_covers_every_combination() {
+    let runs = runs(&["--nodes", "4", "--ops", "2000"]);
+    assert_eq!(runs.len(), 16);
+    for run in &runs {
+        let overlap = num(run, "r") + num(run, "w") > 4;
+        assert_eq!(run["overlap"], if overlap { "yes" } else { "no" });
+    }
+}
+
+#[test]
+fn test_02_overlapping_quorums_are_never_stale() {
+    for nodes in ["3", "5"] {
+        for run in runs(&["--nodes", nodes, "--lag", "0.8", "--fail", "0.05"]) {
+            if run["overlap"] == "yes" {
+                assert_eq!(num(&run, "stale"), 0, "stale read with {:?}", run);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_03_small_quorums_go_stale() {
+    let runs = runs(&["--nodes", "5", "--lag", "0.8", "--fail", "0.05"]);
+    let stale_without_overlap: u64 = runs
+        .iter()
+        .filter(|run| run["overlap"] == "no")
+        .map(|run| num(run, "stale"))
+        .sum();
+    assert!(stale_without_overlap > 0, "R + W <= N never read stale");
+
+    let one_one = runs
+        .iter()
+        .find(|run| run["r"] == "1" && run["w"] == "1")
+        .unwrap();
+    assert!(num(one_one, "stale") > 0);
+    assert!(num(one_one, "max_behind") >= 1);
+}
+
+#[test]
+fn test_04_no_lag_no_crashes_no_staleness() {
+    let runs = runs(&["--lag", "0", "--fail", "0", "--r", "1", "--w", "1"]);
+    assert_eq!(runs.len(), 1);
+    assert_eq!(num(&runs[0], "stale"), 0);
+    assert_eq!(num(&runs[0], "read_failures"), 0);
+    assert_eq!(num(&runs[0], "write_failures"), 0);
+}
+
+#[test]
+fn test_05_full_quorums_cost_availability() {
+    let args = ["--nodes", "3", "--fail", "0.1", "--recover", "0.3"];
+
+    let all_writes = runs(&[&args[..], &["--r", "1", "--w", "3"]].concat());
+    assert!(
+        num(&all_writes[0], "write_failures") > 0,
+        "W = N never failed"
+    );
+
+    let all_reads = runs(&[&args[..], &["--r", "3", "--w", "1"]].concat());
+    assert!(
+        num(&all_reads[0], "read_failures") > 0,
+        "R = N never failed"
+    );
+
+    let majority = runs(&[&args[..], &["--r", "2", "--w", "2"]].concat());
+    assert!(
+        num(&majority[0], "write_failures") < num(&all_writes[0], "write_failures"),
+        "a majority should fail less often than all nodes"
+    );
+}
+
+#[test]
+fn test_06_same_seed_same_output() {
+    let (first, _, _) = run_sim(&["--seed", "11", "--ops", "3000"]);
+    let (second, _, _) = run_sim(&["--seed", "11", "--ops", "3000"]);
+    assert_eq!(first, second);
+}
+
+#[test]
+fn test_07_invalid_arguments() {
+    for args in [
+        &["--r", "2"][..],
+        &["--nodes", "3", "--r", "4", "--w", "1"],
+        &["--nodes", "0"],
+        &["--lag", "2"],
+        &["--fail"],
+        &["--bogus", "1"],
+    ] {
+        let (_, stderr, success) = run_sim(args);
+        assert!(!success, "{:?} should be rejected", args);
+        assert!(!stderr.is_empty(), "{:?} should explain the error", args);
+    }
+}
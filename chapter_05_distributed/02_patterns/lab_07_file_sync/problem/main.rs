@@ -0,0 +1,694 @@
+//! Lab 7: Block-Level File Sync (mini-rsync)
+//!
+//! ## Goal
+//! Bring a remote copy of a file up to date by sending only what changed,
+//! and measure how many bytes that saves over copying the whole file
+//!
+//! ## Requirements
+//! 1. `file_sync serve --dir DIR` receives files into DIR over TCP;
+//!    `file_sync push FILE --to HOST:PORT` sends one
+//! 2. The receiver cuts its old copy (the basis) into `--block-size` blocks
+//!    and sends a weak rolling checksum and a SHA-256 per block
+//! 3. The sender slides a window over the new file byte by byte, updating
+//!    the weak checksum in O(1), and confirms weak hits with SHA-256
+//! 4. Matches go over the wire as "copy block i", the rest as literal bytes
+//! 5. The receiver rebuilds the file into a temp file, checks the whole-file
+//!    SHA-256, fsyncs and renames it over the old copy
+//! 6. The sender reports bytes on the wire against a full copy
+//!
+//! ## Expected Output
+//! ```
+//! $ cargo run -- serve --dir /tmp/replica --port 7070
+//! $ cargo run -- push data.bin --to 127.0.0.1:7070     # 1 MiB, 5 bytes changed
+//! file=data.bin size=1048576 block_size=2048 basis_size=1048576 basis_blocks=512
+//! matched_blocks=511 matched_bytes=1046528 literal_bytes=2048
+//! sent=4667 received=18454 total=23121 full_copy=1048576 saved_pct=97.80
+//! ```
+//!
+//! ## Hints
+//! - Weak checksum (rsync): `a = sum(x_i)`, `b = sum((len - i) * x_i)`,
+//!   both mod 2^16. A byte joining the back adds `a` to `b`; a byte
+//!   leaving the front subtracts `len * byte`
+//! - Index signatures in a `HashMap<u32, Vec<usize>>`: weak sums collide
+//! - Near the end of the file let the window shrink, so the basis's short
+//!   last block can still match
+//! - `Read`/`Write` wrappers that count bytes give the report for free
+//! - Never accept `../` in a file name from the network
+//!
+//! ## Verification
+//! ```bash
+//! cargo test
+//! head -c 1048576 /dev/urandom > data.bin
+//! cargo run -- serve --dir /tmp/replica &
+//! cargo run -- push data.bin --to 127.0.0.1:7070     # first time: all literal
+//! printf 'hello' | dd of=data.bin bs=1 seek=500000 conv=notrunc
+//! cargo run -- push data.bin --to 127.0.0.1:7070     # ~98% saved
+//! cmp data.bin /tmp/replica/data.bin
+//! ```
+//!
+//! ## Acceptance Criteria
+//! - [ ] The receiver's copy is byte-for-byte the sender's file
+//! - [ ] A small edit costs about one block of literals
+//! - [ ] Bytes inserted at the front still leave every old block matched
+//! - [ ] A failed or corrupted transfer never replaces the old copy
+//!
+//! Check solution/main.rs after completing
+
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+
+const MAGIC: &[u8; 4] = b"SYNC";
+
+/// Smallest and largest block size a push may ask for
+const MIN_BLOCK: usize = 64;
+const MAX_BLOCK: usize = 1 << 20;
+
+/// Literal bytes go out in frames of at most this much
+const MAX_DATA: usize = 64 * 1024;
+
+// Op tags, sender -> receiver
+const OP_END: u8 = 0;
+const OP_COPY: u8 = 1;
+const OP_DATA: u8 = 2;
+
+// Status bytes, receiver -> sender
+const STATUS_OK: u8 = 0;
+const STATUS_ERR: u8 = 1;
+
+// ============================================================
+// Checksums
+// ============================================================
+
+/// rsync's weak checksum: two 16-bit sums over a window. `a` is the sum of
+/// the bytes, `b` weighs each byte by its distance from the window's end,
+/// so both can be updated when a byte leaves the front or joins the back.
+#[derive(Clone, Copy, Debug)]
+struct Rolling {
+    a: u32,
+    b: u32,
+    len: u32,
+}
+
+impl Rolling {
+    fn new(window: &[u8]) -> Self {
+        let mut sum = Rolling { a: 0, b: 0, len: 0 };
+        for &byte in window {
+            sum.push(byte);
+        }
+        sum
+    }
+
+    fn digest(&self) -> u32 {
+        self.a | (self.b << 16)
+    }
+
+    /// `byte` joins the back: every byte already in moves one step further
+    /// from the end, which adds the whole of `a` to `b` once more
+    fn push(&mut self, byte: u8) {
+        // TODO: Update a, then b (with the new a), both mod 2^16; len grows
+
+        todo!("Implement Rolling::push")
+    }
+
+    /// `byte` leaves the front, where it counted `len` times in `b`
+    fn pop(&mut self, byte: u8) {
+        // TODO: Take the byte out of a, and len * byte out of b; len shrinks
+        // Hint: wrapping_sub, then & 0xffff
+
+        todo!("Implement Rolling::pop")
+    }
+}
+
+fn strong(block: &[u8]) -> [u8; 32] {
+    Sha256::digest(block).into()
+}
+
+/// What the receiver knows about one block of its basis
+#[derive(Clone, Debug, PartialEq)]
+struct Signature {
+    weak: u32,
+    strong: [u8; 32],
+}
+
+/// Length of block `index` in a basis of `basis_len` bytes: all `block`
+/// long but the last
+fn block_len(index: usize, block: usize, basis_len: u64) -> usize {
+    (basis_len - (index * block) as u64).min(block as u64) as usize
+}
+
+/// Read until `buf` is full or the input ends; returns how much was read
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+/// Checksums of every block of `basis`, read one block at a time
+fn signatures(basis: &mut impl Read, block: usize) -> io::Result<Vec<Signature>> {
+    let mut sigs = Vec::new();
+    let mut buf = vec![0u8; block];
+    loop {
+        let n = read_full(basis, &mut buf)?;
+        if n == 0 {
+            return Ok(sigs);
+        }
+        sigs.push(Signature {
+            weak: Rolling::new(&buf[..n]).digest(),
+            strong: strong(&buf[..n]),
+        });
+    }
+}
+
+// ============================================================
+// Delta
+// ============================================================
+
+#[derive(Debug, PartialEq)]
+enum Op {
+    /// Block `i` of the basis
+    Copy(u32),
+    /// Bytes the basis doesn't have
+    Data(Vec<u8>),
+}
+
+/// Describe `new` as blocks of the basis plus literal bytes
+fn delta(new: &[u8], sigs: &[Signature], block: usize, basis_len: u64) -> Vec<Op> {
+    // TODO: Find the basis blocks in `new`, at any offset
+    //
+    // Suggested steps:
+    // 1. Map weak checksum -> block indexes
+    // 2. Window = new[start..end], at most `block` long, with a Rolling sum
+    // 3. Weak hit: compare strong(window) with the candidates that have the
+    //    same length (block_len). Match: flush the pending literal as
+    //    Op::Data, push Op::Copy(i), start a fresh window after the block
+    // 4. No match: the first byte becomes literal; pop it, push new[end]
+    //    (if there is one) and move on by one byte
+    // 5. Flush the literal at the end
+
+    todo!("Implement delta")
+}
+
+// ============================================================
+// Wire format (big-endian)
+// ============================================================
+//
+// sender -> receiver   "SYNC" name_len:u16 name block:u32
+// receiver -> sender   0 basis_len:u64 count:u32 (weak:u32 strong:[32])*
+//                      or 1 len:u16 message
+// sender -> receiver   (1 index:u32 | 2 len:u32 bytes)* 0 sha256:[32] size:u64
+// receiver -> sender   0 written:u64, or 1 len:u16 message
+
+/// Counts the bytes that pass through, for the report
+struct Counted<T> {
+    inner: T,
+    bytes: u64,
+}
+
+impl<T> Counted<T> {
+    fn new(inner: T) -> Self {
+        Counted { inner, bytes: 0 }
+    }
+}
+
+impl<T: Read> Read for Counted<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.bytes += n as u64;
+        Ok(n)
+    }
+}
+
+impl<T: Write> Write for Counted<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.bytes += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut buf = [0u8; N];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn read_u8(reader: &mut impl Read) -> io::Result<u8> {
+    Ok(read_array::<1>(reader)?[0])
+}
+
+fn read_u16(reader: &mut impl Read) -> io::Result<u16> {
+    Ok(u16::from_be_bytes(read_array(reader)?))
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    Ok(u32::from_be_bytes(read_array(reader)?))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    Ok(u64::from_be_bytes(read_array(reader)?))
+}
+
+fn read_string(reader: &mut impl Read) -> io::Result<String> {
+    let mut buf = vec![0u8; read_u16(reader)? as usize];
+    reader.read_exact(&mut buf)?;
+    String::from_utf8(buf).map_err(|_| invalid("not UTF-8"))
+}
+
+fn write_string(writer: &mut impl Write, s: &str) -> io::Result<()> {
+    writer.write_all(&(s.len() as u16).to_be_bytes())?;
+    writer.write_all(s.as_bytes())
+}
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+/// After a status byte: nothing more if it is OK, else the reason
+fn read_status(reader: &mut impl Read) -> io::Result<Result<(), String>> {
+    match read_u8(reader)? {
+        STATUS_OK => Ok(Ok(())),
+        STATUS_ERR => Ok(Err(read_string(reader)?)),
+        other => Err(invalid(format!("unknown status {}", other))),
+    }
+}
+
+// ============================================================
+// Receiver
+// ============================================================
+
+/// A plain file name: no directories, no dot files (temp files are those)
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 255
+        && !name.starts_with('.')
+        && !name.contains(['/', '\\', '\0'])
+}
+
+/// One push: returns a line for the log
+fn receive(stream: TcpStream, dir: &Path) -> io::Result<String> {
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+
+    if &read_array::<4>(&mut reader)? != MAGIC {
+        return Err(invalid("not a sync client"));
+    }
+    let name = read_string(&mut reader)?;
+    let block = read_u32(&mut reader)? as usize;
+
+    let refuse = |writer: &mut BufWriter<TcpStream>, reason: String| {
+        writer.write_all(&[STATUS_ERR])?;
+        write_string(writer, &reason)?;
+        writer.flush()?;
+        Err(invalid(reason))
+    };
+    if !valid_name(&name) {
+        return refuse(&mut writer, format!("invalid file name {:?}", name));
+    }
+    if !(MIN_BLOCK..=MAX_BLOCK).contains(&block) {
+        return refuse(
+            &mut writer,
+            format!("block size must be {}..={}", MIN_BLOCK, MAX_BLOCK),
+        );
+    }
+
+    // No basis yet is fine: no blocks, the whole file comes as literals
+    let path = dir.join(&name);
+    let mut basis = match File::open(&path) {
+        Ok(file) => Some(file),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e),
+    };
+    let (basis_len, sigs) = match basis.as_mut() {
+        Some(file) => (
+            file.metadata()?.len(),
+            signatures(&mut BufReader::new(&*file), block)?,
+        ),
+        None => (0, Vec::new()),
+    };
+
+    writer.write_all(&[STATUS_OK])?;
+    writer.write_all(&basis_len.to_be_bytes())?;
+    writer.write_all(&(sigs.len() as u32).to_be_bytes())?;
+    for sig in &sigs {
+        writer.write_all(&sig.weak.to_be_bytes())?;
+        writer.write_all(&sig.strong)?;
+    }
+    writer.flush()?;
+
+    // Build the new version next to the old one; the old one stays intact
+    // until the new one is complete and verified
+    let temp = dir.join(format!(
+        ".{}.{}.{}.tmp",
+        name,
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    ));
+    let result = rebuild(&mut reader, &temp, basis.as_mut(), &sigs, block, basis_len);
+    let (copied, literal, written) = match result {
+        Ok(counts) => counts,
+        Err(e) => {
+            let _ = fs::remove_file(&temp);
+            return refuse(&mut writer, e.to_string());
+        }
+    };
+    // rename() replaces atomically: readers see the old file or the new
+    // one, never half of each
+    fs::rename(&temp, &path)?;
+
+    writer.write_all(&[STATUS_OK])?;
+    writer.write_all(&written.to_be_bytes())?;
+    writer.flush()?;
+    Ok(format!(
+        "{}: {} bytes, {} block(s) copied, {} literal bytes",
+        name, written, copied, literal
+    ))
+}
+
+/// Apply the ops into `temp`; returns (blocks copied, literal bytes, size)
+fn rebuild(
+    reader: &mut impl Read,
+    temp: &Path,
+    mut basis: Option<&mut File>,
+    sigs: &[Signature],
+    block: usize,
+    basis_len: u64,
+) -> io::Result<(u64, u64, u64)> {
+    let file = File::create(temp)?;
+    let mut out = BufWriter::new(&file);
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; block.max(MAX_DATA)];
+    let (mut copied, mut literal, mut written) = (0u64, 0u64, 0u64);
+
+    loop {
+        let bytes = match read_u8(reader)? {
+            OP_COPY => {
+                let index = read_u32(reader)? as usize;
+                // TODO: Refuse an index the basis doesn't have, then seek to
+                // block `index` of the basis, read it into buf, count it in
+                // `copied` and yield &buf[..len]
+
+                todo!("Copy a block from the basis")
+            }
+            OP_DATA => {
+                let len = read_u32(reader)? as usize;
+                if len > MAX_DATA {
+                    return Err(invalid(format!("data frame of {} bytes", len)));
+                }
+                reader.read_exact(&mut buf[..len])?;
+                literal += len as u64;
+                &buf[..len]
+            }
+            OP_END => break,
+            other => return Err(invalid(format!("unknown op {}", other))),
+        };
+        out.write_all(bytes)?;
+        hasher.update(bytes);
+        written += bytes.len() as u64;
+    }
+
+    let expected: [u8; 32] = read_array(reader)?;
+    let size = read_u64(reader)?;
+    if size != written || expected != <[u8; 32]>::from(hasher.finalize()) {
+        return Err(invalid("checksum mismatch, file not replaced"));
+    }
+    out.flush()?;
+    drop(out);
+    // On disk before the rename, or a crash could leave an empty file
+    file.sync_all()?;
+    Ok((copied, literal, written))
+}
+
+fn serve(port: u16, dir: PathBuf) -> io::Result<()> {
+    fs::create_dir_all(&dir)?;
+    let listener = TcpListener::bind(("0.0.0.0", port))?;
+    println!("serving {} on port {}", dir.display(), port);
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("accept failed: {}", e);
+                continue;
+            }
+        };
+        let dir = dir.clone();
+        thread::spawn(move || {
+            let peer = stream
+                .peer_addr()
+                .map(|a| a.to_string())
+                .unwrap_or_default();
+            match receive(stream, &dir) {
+                Ok(line) => println!("[{}] {}", peer, line),
+                Err(e) => eprintln!("[{}] sync failed: {}", peer, e),
+            }
+        });
+    }
+    Ok(())
+}
+
+// ============================================================
+// Sender
+// ============================================================
+
+struct Report {
+    size: u64,
+    basis_len: u64,
+    basis_blocks: usize,
+    matched_blocks: u64,
+    matched_bytes: u64,
+    literal_bytes: u64,
+    sent: u64,
+    received: u64,
+}
+
+fn push(file: &Path, to: &str, name: &str, block: usize) -> Result<Report, String> {
+    let new = fs::read(file).map_err(|e| format!("cannot read {}: {}", file.display(), e))?;
+
+    let stream = TcpStream::connect(to).map_err(|e| format!("cannot connect to {}: {}", to, e))?;
+    let io_err = |e: io::Error| format!("connection to {}: {}", to, e);
+    let mut reader = Counted::new(BufReader::new(stream.try_clone().map_err(io_err)?));
+    let mut writer = Counted::new(BufWriter::new(stream));
+
+    writer.write_all(MAGIC).map_err(io_err)?;
+    write_string(&mut writer, name).map_err(io_err)?;
+    writer
+        .write_all(&(block as u32).to_be_bytes())
+        .map_err(io_err)?;
+    writer.flush().map_err(io_err)?;
+
+    read_status(&mut reader)
+        .map_err(io_err)?
+        .map_err(|reason| format!("refused: {}", reason))?;
+    let basis_len = read_u64(&mut reader).map_err(io_err)?;
+    let count = read_u32(&mut reader).map_err(io_err)?;
+    let mut sigs = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        sigs.push(Signature {
+            weak: read_u32(&mut reader).map_err(io_err)?,
+            strong: read_array(&mut reader).map_err(io_err)?,
+        });
+    }
+
+    let ops = delta(&new, &sigs, block, basis_len);
+
+    let mut report = Report {
+        size: new.len() as u64,
+        basis_len,
+        basis_blocks: sigs.len(),
+        matched_blocks: 0,
+        matched_bytes: 0,
+        literal_bytes: 0,
+        sent: 0,
+        received: 0,
+    };
+    for op in &ops {
+        match op {
+            Op::Copy(i) => {
+                report.matched_blocks += 1;
+                report.matched_bytes += block_len(*i as usize, block, basis_len) as u64;
+                writer.write_all(&[OP_COPY]).map_err(io_err)?;
+                writer.write_all(&i.to_be_bytes()).map_err(io_err)?;
+            }
+            Op::Data(bytes) => {
+                report.literal_bytes += bytes.len() as u64;
+                for chunk in bytes.chunks(MAX_DATA) {
+                    writer.write_all(&[OP_DATA]).map_err(io_err)?;
+                    writer
+                        .write_all(&(chunk.len() as u32).to_be_bytes())
+                        .map_err(io_err)?;
+                    writer.write_all(chunk).map_err(io_err)?;
+                }
+            }
+        }
+    }
+    writer.write_all(&[OP_END]).map_err(io_err)?;
+    writer.write_all(&strong(&new)).map_err(io_err)?;
+    writer
+        .write_all(&(new.len() as u64).to_be_bytes())
+        .map_err(io_err)?;
+    writer.flush().map_err(io_err)?;
+
+    read_status(&mut reader)
+        .map_err(io_err)?
+        .map_err(|reason| format!("receiver failed: {}", reason))?;
+    let written = read_u64(&mut reader).map_err(io_err)?;
+    if written != report.size {
+        return Err(format!(
+            "receiver wrote {} of {} bytes",
+            written, report.size
+        ));
+    }
+
+    report.sent = writer.bytes;
+    report.received = reader.bytes;
+    Ok(report)
+}
+
+// ============================================================
+// Main
+// ============================================================
+
+enum Config {
+    Serve {
+        port: u16,
+        dir: PathBuf,
+    },
+    Push {
+        file: PathBuf,
+        to: String,
+        name: String,
+        block: usize,
+    },
+}
+
+fn usage() -> String {
+    "usage: file_sync serve --dir DIR [--port PORT]\n       \
+     file_sync push FILE --to HOST:PORT [--name NAME] [--block-size BYTES]"
+        .to_string()
+}
+
+fn parse_args(args: Vec<String>) -> Result<Config, String> {
+    let mut args = args.into_iter();
+    let command = args.next().ok_or_else(usage)?;
+    let file = match command.as_str() {
+        "serve" => None,
+        "push" => Some(PathBuf::from(
+            args.next()
+                .ok_or_else(|| format!("push needs a FILE\n{}", usage()))?,
+        )),
+        _ => return Err(format!("unknown command {}\n{}", command, usage())),
+    };
+
+    let (mut port, mut dir, mut to, mut name) = (7070u16, None, None, None);
+    let mut block = 2048;
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value\n{}", flag, usage()))?;
+        let bad = || format!("invalid value for {}: {}", flag, value);
+        match (flag.as_str(), &file) {
+            ("--port", None) => port = value.parse().map_err(|_| bad())?,
+            ("--dir", None) => dir = Some(PathBuf::from(&value)),
+            ("--to", Some(_)) => to = Some(value),
+            ("--name", Some(_)) => name = Some(value),
+            ("--block-size", Some(_)) => match value.parse::<usize>() {
+                Ok(n) if (MIN_BLOCK..=MAX_BLOCK).contains(&n) => block = n,
+                _ => {
+                    return Err(format!(
+                        "--block-size must be {}..={}, got {}",
+                        MIN_BLOCK, MAX_BLOCK, value
+                    ))
+                }
+            },
+            _ => return Err(format!("unknown option {}\n{}", flag, usage())),
+        }
+    }
+
+    match file {
+        None => Ok(Config::Serve {
+            port,
+            dir: dir.ok_or_else(|| format!("serve needs --dir\n{}", usage()))?,
+        }),
+        Some(file) => {
+            let name = match name {
+                Some(name) => name,
+                None => file
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .ok_or_else(|| format!("no file name in {}", file.display()))?,
+            };
+            Ok(Config::Push {
+                to: to.ok_or_else(|| format!("push needs --to\n{}", usage()))?,
+                file,
+                name,
+                block,
+            })
+        }
+    }
+}
+
+fn main() {
+    let config = match parse_args(std::env::args().skip(1).collect()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("file_sync: {}", e);
+            std::process::exit(2);
+        }
+    };
+
+    match config {
+        Config::Serve { port, dir } => {
+            if let Err(e) = serve(port, dir) {
+                eprintln!("file_sync: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Config::Push {
+            file,
+            to,
+            name,
+            block,
+        } => match push(&file, &to, &name, block) {
+            Ok(r) => {
+                let total = r.sent + r.received;
+                println!(
+                    "file={} size={} block_size={} basis_size={} basis_blocks={}",
+                    name, r.size, block, r.basis_len, r.basis_blocks
+                );
+                println!(
+                    "matched_blocks={} matched_bytes={} literal_bytes={}",
+                    r.matched_blocks, r.matched_bytes, r.literal_bytes
+                );
+                println!(
+                    "sent={} received={} total={} full_copy={} saved_pct={:.2}",
+                    r.sent,
+                    r.received,
+                    total,
+                    r.size,
+                    100.0 * (1.0 - total as f64 / r.size.max(1) as f64)
+                );
+            }
+            Err(e) => {
+                eprintln!("file_sync: {}", e);
+                std::process::exit(1);
+            }
+        },
+    }
+}
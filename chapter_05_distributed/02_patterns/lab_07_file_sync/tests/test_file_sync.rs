@@ -0,0 +1,176 @@
+//! Lab 7 Tests - Block-Level File Sync
+//!
+//! Run with: cargo test
+
+use std::collections::HashMap;
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::process::{Child, Command};
+use std::thread;
+use std::time::Duration;
+
+struct ServerGuard {
+    child: Child,
+}
+
+impl Drop for ServerGuard {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+    }
+}
+
+/// A fresh directory per test: `<tmp>/file_sync_<pid>_<test>/{sender,receiver}`
+fn dirs(test: &str) -> (PathBuf, PathBuf) {
+    let root = std::env::temp_dir().join(format!("file_sync_{}_{}", std::process::id(), test));
+    let _ = std::fs::remove_dir_all(&root);
+    let (sender, receiver) = (root.join("sender"), root.join("receiver"));
+    std::fs::create_dir_all(&sender).unwrap();
+    std::fs::create_dir_all(&receiver).unwrap();
+    (sender, receiver)
+}
+
+fn start_server(port: u16, dir: &PathBuf) -> ServerGuard {
+    let child = Command::new(env!("CARGO_BIN_EXE_file_sync"))
+        .args(["serve", "--port", &port.to_string(), "--dir"])
+        .arg(dir)
+        .spawn()
+        .expect("Failed to start server");
+    for _ in 0..50 {
+        if TcpStream::connect(("127.0.0.1", port)).is_ok() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    ServerGuard { child }
+}
+
+/// Run `file_sync push`; the `key=value` fields of its report
+fn push(port: u16, file: &PathBuf, extra: &[&str]) -> Result<HashMap<String, String>, String> {
+    let output = Command::new(env!("CARGO_BIN_EXE_file_sync"))
+        .arg("push")
+        .arg(file)
+        .args(["--to", &format!("127.0.0.1:{}", port)])
+        .args(extra)
+        .output()
+        .expect("Failed to execute program");
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .filter_map(|field| field.split_once('='))
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect())
+}
+
+fn num(report: &HashMap<String, String>, key: &str) -> f64 {
+    report[key]
+        .parse()
+        .unwrap_or_else(|_| panic!("{} is not a number: {:?}", key, report))
+}
+
+/// Deterministic bytes without repeating blocks
+fn noise(len: usize, seed: u64) -> Vec<u8> {
+    let mut x = seed;
+    (0..len)
+        .map(|_| {
+            x = x
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (x >> 33) as u8
+        })
+        .collect()
+}
+
+#[test]
+fn test_01_first_push_copies_everything() {
+    let (sender, receiver) = dirs("first");
+    let _server = start_server(8120, &receiver);
+
+    let file = sender.join("data.bin");
+    std::fs::write(&file, noise(100_000, 1)).unwrap();
+    let report = push(8120, &file, &[]).expect("push failed");
+
+    assert_eq!(num(&report, "basis_blocks"), 0.0);
+    assert_eq!(num(&report, "literal_bytes"), 100_000.0);
+    assert!(num(&report, "saved_pct") <= 0.0, "{:?}", report);
+    assert_eq!(
+        std::fs::read(receiver.join("data.bin")).unwrap(),
+        std::fs::read(&file).unwrap()
+    );
+}
+
+#[test]
+fn test_02_small_edit_sends_little() {
+    let (sender, receiver) = dirs("edit");
+    let _server = start_server(8121, &receiver);
+
+    let file = sender.join("data.bin");
+    let mut data = noise(256 * 1024, 2);
+    std::fs::write(&file, &data).unwrap();
+    push(8121, &file, &[]).expect("first push failed");
+
+    data[100_000..100_010].copy_from_slice(b"0123456789");
+    std::fs::write(&file, &data).unwrap();
+    let report = push(8121, &file, &["--block-size", "1024"]).expect("push failed");
+
+    assert_eq!(num(&report, "basis_blocks"), 256.0);
+    assert!(num(&report, "literal_bytes") <= 2048.0, "{:?}", report);
+    assert!(num(&report, "saved_pct") > 90.0, "{:?}", report);
+    assert_eq!(std::fs::read(receiver.join("data.bin")).unwrap(), data);
+}
+
+#[test]
+fn test_03_insert_shifts_without_losing_matches() {
+    let (sender, receiver) = dirs("insert");
+    let _server = start_server(8122, &receiver);
+
+    let file = sender.join("log.txt");
+    let data = noise(64 * 1024, 3);
+    std::fs::write(&file, &data).unwrap();
+    push(8122, &file, &[]).expect("first push failed");
+
+    // Every byte moves: only a rolling checksum still finds the blocks
+    let mut shifted = b"one new line\n".to_vec();
+    shifted.extend_from_slice(&data);
+    std::fs::write(&file, &shifted).unwrap();
+    let report = push(8122, &file, &[]).expect("push failed");
+
+    assert_eq!(num(&report, "matched_blocks"), num(&report, "basis_blocks"));
+    assert_eq!(num(&report, "literal_bytes"), 13.0);
+    assert_eq!(std::fs::read(receiver.join("log.txt")).unwrap(), shifted);
+}
+
+#[test]
+fn test_04_refuses_bad_names() {
+    let (sender, receiver) = dirs("names");
+    let _server = start_server(8123, &receiver);
+
+    let file = sender.join("data.bin");
+    std::fs::write(&file, b"hello").unwrap();
+    for name in ["../escape", ".hidden", "a/b"] {
+        let err = push(8123, &file, &["--name", name]).expect_err(name);
+        assert!(err.contains("invalid file name"), "{}: {}", name, err);
+    }
+    assert!(!receiver.parent().unwrap().join("escape").exists());
+
+    // Still serving after the refusals
+    push(8123, &file, &[]).expect("push failed");
+    assert_eq!(std::fs::read(receiver.join("data.bin")).unwrap(), b"hello");
+}
+
+#[test]
+fn test_05_invalid_arguments() {
+    for args in [
+        vec!["push", "x", "--to", "127.0.0.1:1", "--block-size", "8"],
+        vec!["push", "x"],
+        vec!["serve"],
+        vec!["pull"],
+    ] {
+        let output = Command::new(env!("CARGO_BIN_EXE_file_sync"))
+            .args(&args)
+            .output()
+            .expect("Failed to execute program");
+        assert_eq!(output.status.code(), Some(2), "{:?}", args);
+    }
+}
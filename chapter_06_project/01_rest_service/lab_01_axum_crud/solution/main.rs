@@ -1,11 +1,15 @@
 //! Lab 1: Axum CRUD API - Solution
 //!
 //! A complete REST API for managing items using Axum.
+//!
+//! `GET /items` answers in JSON or CSV depending on the Accept header, and
+//! every response large enough to be worth it is compressed with gzip or
+//! brotli, whichever the client's Accept-Encoding prefers.
 
 use axum::{
     extract::{Path, Query, State},
-    http::StatusCode,
-    response::IntoResponse,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
     routing::{get, post},
     Json, Router,
 };
@@ -14,6 +18,7 @@ use serde_json::json;
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
+use tower_http::compression::CompressionLayer;
 use uuid::Uuid;
 
 // Item model
@@ -57,6 +62,13 @@ struct PaginatedResponse<T> {
     total: usize,
 }
 
+// Representations GET /items can be served in
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Json,
+    Csv,
+}
+
 // Shared state type
 type AppState = Arc<RwLock<HashMap<Uuid, Item>>>;
 
@@ -65,6 +77,7 @@ enum AppError {
     NotFound(String),
     #[allow(dead_code)]
     BadRequest(String),
+    NotAcceptable(String),
 }
 
 impl IntoResponse for AppError {
@@ -72,6 +85,7 @@ impl IntoResponse for AppError {
         let (status, message) = match self {
             AppError::NotFound(msg) => (StatusCode::NOT_FOUND, msg),
             AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg),
+            AppError::NotAcceptable(msg) => (StatusCode::NOT_ACCEPTABLE, msg),
         };
 
         let body = Json(json!({
@@ -118,11 +132,76 @@ async fn get_item(
         .ok_or_else(|| AppError::NotFound(format!("Item {} not found", id)))
 }
 
-// Handler: List all items with pagination
+// Pick the format for an Accept header: the acceptable media range with
+// the highest q-value, the first one on a tie. No header means JSON; None
+// means nothing we can produce is acceptable.
+fn negotiate(accept: Option<&str>) -> Option<Format> {
+    let accept = match accept.map(str::trim) {
+        None | Some("") => return Some(Format::Json),
+        Some(accept) => accept,
+    };
+
+    let mut best: Option<(f32, Format)> = None;
+    for range in accept.split(',') {
+        let mut params = range.split(';');
+        let media = params.next().unwrap_or("").trim().to_ascii_lowercase();
+        let q = params
+            .find_map(|param| param.trim().strip_prefix("q="))
+            .and_then(|q| q.parse::<f32>().ok())
+            .unwrap_or(1.0);
+
+        let format = match media.as_str() {
+            "application/json" | "application/*" | "*/*" => Format::Json,
+            "text/csv" | "text/*" => Format::Csv,
+            _ => continue,
+        };
+        match best {
+            _ if q <= 0.0 => {}
+            Some((best_q, _)) if best_q >= q => {}
+            _ => best = Some((q, format)),
+        }
+    }
+    best.map(|(_, format)| format)
+}
+
+// Quote a CSV field if it holds a separator, quote or line break (RFC 4180)
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// One header line, then one line per item
+fn to_csv(items: &[Item]) -> String {
+    let mut csv = String::from("id,name,description,price,created_at\n");
+    for item in items {
+        csv.push_str(&format!(
+            "{},{},{},{},{}\n",
+            item.id,
+            csv_field(&item.name),
+            csv_field(item.description.as_deref().unwrap_or("")),
+            item.price,
+            csv_field(&item.created_at),
+        ));
+    }
+    csv
+}
+
+// Handler: List all items with pagination, as JSON or CSV
 async fn list_items(
     State(state): State<AppState>,
     Query(pagination): Query<Pagination>,
-) -> Json<PaginatedResponse<Item>> {
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let accept = headers
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok());
+    let format = negotiate(accept).ok_or_else(|| {
+        AppError::NotAcceptable("Supported formats: application/json, text/csv".to_string())
+    })?;
+
     let page = pagination.page.unwrap_or(1).max(1);
     let limit = pagination.limit.unwrap_or(10).min(100);
 
@@ -132,12 +211,30 @@ async fn list_items(
     let skip = (page - 1) * limit;
     let items: Vec<Item> = items.values().skip(skip).take(limit).cloned().collect();
 
-    Json(PaginatedResponse {
-        items,
-        page,
-        limit,
-        total,
-    })
+    // Caches must key the response on Accept too, not only on the URL
+    let response = match format {
+        Format::Json => (
+            [(header::VARY, "accept")],
+            Json(PaginatedResponse {
+                items,
+                page,
+                limit,
+                total,
+            }),
+        )
+            .into_response(),
+        // CSV has no room for the page envelope: the total goes in a header
+        Format::Csv => (
+            [
+                (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+                (header::VARY, "accept".to_string()),
+                (header::HeaderName::from_static("x-total-count"), total.to_string()),
+            ],
+            to_csv(&items),
+        )
+            .into_response(),
+    };
+    Ok(response)
 }
 
 // Handler: Update item
@@ -200,6 +297,8 @@ async fn main() {
             "/items/:id",
             get(get_item).put(update_item).delete(delete_item),
         )
+        // gzip or br per Accept-Encoding; bodies under 32 bytes are left alone
+        .layer(CompressionLayer::new())
         .with_state(state);
 
     println!("Server running on http://localhost:3000");
@@ -212,6 +311,8 @@ async fn main() {
     println!();
     println!("  # List items");
     println!("  curl http://localhost:3000/items");
+    println!("  curl -H 'Accept: text/csv' http://localhost:3000/items");
+    println!("  curl --compressed -v http://localhost:3000/items");
     println!();
     println!("  # Get item (replace <id> with actual UUID)");
     println!("  curl http://localhost:3000/items/<id>");
@@ -250,4 +351,35 @@ mod tests {
         let result = get_item(State(state), Path(id)).await;
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(negotiate(None), Some(Format::Json));
+        assert_eq!(negotiate(Some("*/*")), Some(Format::Json));
+        assert_eq!(negotiate(Some("text/csv")), Some(Format::Csv));
+        assert_eq!(negotiate(Some("text/html, text/*;q=0.8")), Some(Format::Csv));
+        assert_eq!(
+            negotiate(Some("text/csv;q=0.5, application/json")),
+            Some(Format::Json)
+        );
+        assert_eq!(negotiate(Some("text/csv, */*")), Some(Format::Csv));
+        assert_eq!(negotiate(Some("text/html")), None);
+        assert_eq!(negotiate(Some("text/csv;q=0")), None);
+    }
+
+    #[test]
+    fn test_to_csv_quotes_fields() {
+        let item = Item {
+            id: Uuid::nil(),
+            name: "Widget, \"large\"".to_string(),
+            description: None,
+            price: 9.5,
+            created_at: "0".to_string(),
+        };
+        assert_eq!(
+            to_csv(&[item]),
+            "id,name,description,price,created_at\n\
+             00000000-0000-0000-0000-000000000000,\"Widget, \"\"large\"\"\",,9.5,0\n"
+        );
+    }
 }
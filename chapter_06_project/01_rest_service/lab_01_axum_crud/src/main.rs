@@ -9,6 +9,10 @@
 //! 3. GET /items - List all items with optional pagination (?page=1&limit=10)
 //! 4. PUT /items/:id - Update an item (returns 404 if not found)
 //! 5. DELETE /items/:id - Delete an item (returns 204 No Content)
+//! 6. GET /items answers `Accept: text/csv` with CSV, anything JSON-ish (or
+//!    no Accept) with JSON, and anything else with 406 Not Acceptable
+//! 7. Responses are compressed (gzip or br) when the client sends
+//!    Accept-Encoding
 //!
 //! ## Data Model
 //! ```rust
@@ -26,6 +30,11 @@
 //! - Use `axum::extract::{State, Path, Query, Json}`
 //! - Implement proper error responses with status codes
 //! - Use `uuid::Uuid::new_v4()` to generate IDs
+//! - Accept is a list of media ranges with q-values:
+//!   `text/csv;q=0.5, application/json` prefers JSON
+//! - CSV has no room for `page`/`total`: send the total as `X-Total-Count`
+//! - Set `Vary: Accept`, so caches keep the JSON and CSV versions apart
+//! - `tower_http::compression::CompressionLayer` handles Accept-Encoding
 //!
 //! ## Verification
 //! ```bash
@@ -39,6 +48,9 @@
 //!
 //! curl http://localhost:3000/items
 //! curl http://localhost:3000/items/<id>
+//!
+//! curl -H 'Accept: text/csv' http://localhost:3000/items
+//! curl --compressed -v http://localhost:3000/items   # content-encoding: br
 //! ```
 //!
 //! ## Acceptance Criteria
@@ -47,13 +59,15 @@
 //! - [ ] 404 returned for non-existent items
 //! - [ ] Pagination works with page and limit params
 //! - [ ] JSON serialization/deserialization works
+//! - [ ] The list comes as CSV on request, 406 for unsupported types
+//! - [ ] Large responses come back compressed
 //!
 //! Check solution/main.rs after completing
 
 use axum::{
     extract::{Path, Query, State},
-    http::StatusCode,
-    response::IntoResponse,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
     routing::{get, post},
     Json, Router,
 };
@@ -95,6 +109,13 @@ struct Pagination {
     limit: Option<usize>,
 }
 
+// Representations GET /items can be served in
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Json,
+    Csv,
+}
+
 // Shared state type
 type AppState = Arc<RwLock<HashMap<Uuid, Item>>>;
 
@@ -102,6 +123,7 @@ type AppState = Arc<RwLock<HashMap<Uuid, Item>>>;
 enum AppError {
     NotFound(String),
     BadRequest(String),
+    NotAcceptable(String),
 }
 
 impl IntoResponse for AppError {
@@ -142,11 +164,33 @@ async fn get_item(
     todo!()
 }
 
-// Handler: List all items with pagination
+// Pick the format for an Accept header; None if nothing we can produce
+// is acceptable
+fn negotiate(accept: Option<&str>) -> Option<Format> {
+    // TODO: Implement content negotiation
+    //
+    // Steps:
+    // 1. No header (or an empty one) -> Json
+    // 2. Split on ',' into media ranges; split each on ';' for its q-value
+    //    (default 1.0, q=0 means "not this")
+    // 3. application/json, application/*, */* -> Json; text/csv, text/* -> Csv
+    // 4. Return the format with the highest q, or None
+    todo!()
+}
+
+// One header line (id,name,description,price,created_at), then one line
+// per item; quote fields containing ',', '"' or a line break
+fn to_csv(items: &[Item]) -> String {
+    // TODO: Implement CSV output
+    todo!()
+}
+
+// Handler: List all items with pagination, as JSON or CSV
 async fn list_items(
     State(state): State<AppState>,
     Query(pagination): Query<Pagination>,
-) -> Json<Vec<Item>> {
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
     // TODO: Implement list items with pagination
     //
     // Steps:
@@ -156,6 +200,9 @@ async fn list_items(
     // 4. Return items
     //
     // Default: page=1, limit=10
+    //
+    // Then negotiate() on the Accept header: 406 NotAcceptable if None,
+    // otherwise JSON, or to_csv() with a text/csv content type
     todo!()
 }
 
@@ -203,6 +250,8 @@ async fn main() {
     // - GET  /items/:id  -> get_item
     // - PUT  /items/:id  -> update_item
     // - DELETE /items/:id -> delete_item
+    //
+    // Then add a CompressionLayer around all of them
     let app = Router::new()
         // Add routes here
         .with_state(state);
@@ -301,3 +301,102 @@ async fn test_10_delete_item_not_found() {
 
     assert_eq!(response.status(), 404, "Should return 404 for non-existent item");
 }
+
+#[tokio::test]
+#[ignore = "requires running server"]
+async fn test_11_list_items_as_csv() {
+    let client = reqwest::Client::new();
+
+    client
+        .post(format!("{}/items", BASE_URL))
+        .json(&json!({"name": "Widget, \"large\"", "price": 9.5}))
+        .send()
+        .await
+        .expect("Failed to create item");
+
+    let response = client
+        .get(format!("{}/items?limit=100", BASE_URL))
+        .header("Accept", "text/csv")
+        .send()
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status(), 200);
+    let content_type = response.headers()["content-type"]
+        .to_str()
+        .unwrap()
+        .to_string();
+    assert!(content_type.starts_with("text/csv"), "got {}", content_type);
+    assert!(response.headers().contains_key("x-total-count"));
+
+    let body = response.text().await.expect("Failed to read body");
+    let mut lines = body.lines();
+    assert_eq!(lines.next(), Some("id,name,description,price,created_at"));
+    assert!(
+        body.contains(",\"Widget, \"\"large\"\"\",,9.5,"),
+        "Fields with commas and quotes should be quoted: {}",
+        body
+    );
+}
+
+#[tokio::test]
+#[ignore = "requires running server"]
+async fn test_12_list_items_not_acceptable() {
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{}/items", BASE_URL))
+        .header("Accept", "application/xml")
+        .send()
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status(), 406, "Should return 406 Not Acceptable");
+}
+
+#[tokio::test]
+#[ignore = "requires running server"]
+async fn test_13_list_items_compressed() {
+    let client = reqwest::Client::new();
+
+    // Enough items that the list is worth compressing
+    for i in 1..=5 {
+        client
+            .post(format!("{}/items", BASE_URL))
+            .json(&json!({"name": format!("Compressed Item {}", i), "price": 1.0}))
+            .send()
+            .await
+            .expect("Failed to create item");
+    }
+
+    // reqwest here has no decompression features, so the body arrives as sent
+    for encoding in ["gzip", "br"] {
+        let response = client
+            .get(format!("{}/items", BASE_URL))
+            .header("Accept-Encoding", encoding)
+            .send()
+            .await
+            .expect("Failed to send request");
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            response
+                .headers()
+                .get("content-encoding")
+                .map(|v| v.to_str().unwrap()),
+            Some(encoding),
+            "Should be compressed with {}",
+            encoding
+        );
+    }
+
+    let response = client
+        .get(format!("{}/items", BASE_URL))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert!(
+        response.headers().get("content-encoding").is_none(),
+        "No Accept-Encoding, no compression"
+    );
+}
@@ -9,23 +9,125 @@
 //! 3. All CRUD operations should use SQLx queries
 //! 4. Use connection pool for database access
 //! 5. Handle database errors gracefully
+//! 6. Items may belong to a category (foreign key); list/get return the
+//!    category name via a JOIN
+//! 7. Deleting a category that still has items is refused (409) unless
+//!    `?cascade=true` is given, which deletes its items in the same transaction
+//! 8. `POST /items/:id/reserve` takes `quantity` units of stock; concurrent
+//!    reservations must never sell more than there is
+//! 9. Requests with an `X-Api-Key` header are metered: requests and bytes
+//!    (request body + response body) per key and month, counted in memory
+//!    and flushed into the `usage` table every `USAGE_FLUSH_SECS` (default 5)
+//! 10. A key over its monthly request quota gets 429 with `Retry-After`
+//!    (seconds until the month turns); over its byte allowance, 402
+//! 11. `POST /jobs` queues long-running work and answers 202 at once;
+//!    `JOB_WORKERS` background workers (default 2) take jobs from the
+//!    `jobs` table and record their progress there after every step
+//! 12. Progress can be polled (`GET /jobs/:id`) or followed as Server-Sent
+//!    Events (`GET /jobs/:id/events`) until the job is done or failed
 //!
 //! ## Database Schema
 //! ```sql
+//! CREATE TABLE IF NOT EXISTS categories (
+//!     id TEXT PRIMARY KEY,
+//!     name TEXT NOT NULL UNIQUE,
+//!     created_at TEXT NOT NULL
+//! );
+//!
 //! CREATE TABLE IF NOT EXISTS items (
 //!     id TEXT PRIMARY KEY,
 //!     name TEXT NOT NULL,
 //!     description TEXT,
 //!     price REAL NOT NULL,
+//!     stock INTEGER NOT NULL DEFAULT 0 CHECK (stock >= 0),
+//!     category_id TEXT REFERENCES categories(id),
+//!     created_at TEXT NOT NULL
+//! );
+//!
+//! CREATE INDEX IF NOT EXISTS idx_items_category_id ON items(category_id);
+//!
+//! CREATE TABLE IF NOT EXISTS api_keys (
+//!     key TEXT PRIMARY KEY,
+//!     tenant TEXT NOT NULL,
+//!     monthly_requests INTEGER NOT NULL CHECK (monthly_requests >= 0),
+//!     monthly_bytes INTEGER NOT NULL CHECK (monthly_bytes >= 0),
 //!     created_at TEXT NOT NULL
 //! );
+//!
+//! CREATE TABLE IF NOT EXISTS usage (
+//!     api_key TEXT NOT NULL REFERENCES api_keys(key),
+//!     month TEXT NOT NULL,               -- "2024-05", UTC
+//!     requests INTEGER NOT NULL DEFAULT 0,
+//!     bytes INTEGER NOT NULL DEFAULT 0,
+//!     PRIMARY KEY (api_key, month)
+//! );
+//!
+//! CREATE TABLE IF NOT EXISTS jobs (
+//!     id TEXT PRIMARY KEY,
+//!     status TEXT NOT NULL DEFAULT 'queued'
+//!         CHECK (status IN ('queued', 'running', 'done', 'failed')),
+//!     steps INTEGER NOT NULL CHECK (steps > 0),
+//!     progress INTEGER NOT NULL DEFAULT 0 CHECK (progress BETWEEN 0 AND steps),
+//!     step_ms INTEGER NOT NULL,
+//!     fail_at INTEGER,
+//!     error TEXT,
+//!     created_at TEXT NOT NULL,
+//!     updated_at TEXT NOT NULL
+//! );
+//!
+//! CREATE INDEX IF NOT EXISTS idx_jobs_status ON jobs(status);
 //! ```
 //!
+//! ## API Endpoints
+//! - `GET/POST /items`, `GET/PUT/DELETE /items/:id` - items carry
+//!   `category_id` and `category_name`
+//! - `GET /categories` - categories with `item_count`
+//! - `POST /categories` - 201, or 409 if the name exists
+//! - `GET /categories/:id`
+//! - `DELETE /categories/:id[?cascade=true]` - 204, 404, or 409 while items remain
+//! - An unknown `category_id` on an item is a 400
+//! - `POST /items/:id/reserve {"quantity": n}` - 200
+//!   `{"item_id", "reserved", "remaining"}`, 409 if not enough stock, 400 if n < 1
+//! - `POST /api-keys {"tenant", "monthly_requests", "monthly_bytes"}` - 201
+//!   with the new `key` (admin, not metered)
+//! - `GET /usage` with `X-Api-Key` - this month's `requests`, `bytes`, the
+//!   `unflushed` part of them and the `quota`; 401 without a known key
+//! - Any metered route: 401 for an unknown key, 429 / 402 over quota;
+//!   without the header, requests pass unmetered
+//! - `POST /jobs {"steps", "step_ms"?, "fail_at"?}` - 202 with the job and
+//!   `Location: /jobs/<id>`; 400 for steps outside 1..=1000, step_ms outside
+//!   0..=60000 or a fail_at that isn't one of the steps
+//! - `GET /jobs/:id` - `{"id", "status", "steps", "progress", "error", ...}`, 404
+//! - `GET /jobs/:id/events` - `text/event-stream`: one event per change,
+//!   named after the status, with the job as data; ends after `done`/`failed`
+//!
 //! ## Hints
 //! - Use `SqlitePool::connect(":memory:")` for in-memory database
 //! - Use `sqlx::query!` or `sqlx::query_as!` for type-safe queries
 //! - Store UUID as TEXT in SQLite
 //! - Share pool via Axum State
+//! - SQLx enables `PRAGMA foreign_keys` by default; check the error with
+//!   `db_err.is_foreign_key_violation()` / `is_unique_violation()`
+//! - `LEFT JOIN categories` keeps items without a category
+//! - `pool.begin()` gives a transaction; pass `&mut *tx` as the executor
+//! - SQLite has no `SELECT ... FOR UPDATE`: read and write the stock in one
+//!   transaction and retry when it fails with SQLITE_BUSY (code 5)
+//! - `DATABASE_URL=sqlite:items.db?mode=rwc` gives a multi-connection pool,
+//!   where reservations really race
+//! - Metering is a `middleware::from_fn_with_state` layer on the API routes;
+//!   `response.body().size_hint().exact()` is the length of a JSON body
+//! - Flush with one upsert per key:
+//!   `INSERT ... ON CONFLICT (api_key, month) DO UPDATE SET requests = requests + excluded.requests`
+//! - Quota checks must add the unflushed counts to the stored ones
+//! - The jobs table is the queue. Claim with one statement so two workers
+//!   can't take the same job: `UPDATE jobs SET status = 'running' WHERE id =
+//!   (SELECT id FROM jobs WHERE status = 'queued' ORDER BY rowid LIMIT 1)
+//!   RETURNING *`
+//! - `tokio::sync::Notify` wakes an idle worker; `notify_one` keeps a permit
+//!   when nobody waits, so no job is missed between claim and wait
+//! - A `tokio::sync::watch` channel per unfinished job holds its latest
+//!   row; `tokio_stream::wrappers::WatchStream` turns a receiver into the
+//!   SSE stream, which ends when the worker drops the sender
 //!
 //! ## Verification
 //! ```bash
@@ -39,37 +141,83 @@
 //! - [ ] All CRUD operations work with database
 //! - [ ] Proper error handling for database failures
 //! - [ ] Connection pool properly configured
+//! - [ ] Item responses include the category name
+//! - [ ] Foreign key and unique violations map to 400/409, not 500
+//! - [ ] Cascading category delete is all-or-nothing
+//! - [ ] 50 concurrent reservations of 10 units sell exactly 10
+//! - [ ] `GET /usage` counts every metered request, flushed or not
+//! - [ ] The usage table is written once per key per flush, not per request
+//! - [ ] Quotas answer 429 (with `Retry-After`) and 402
+//! - [ ] `POST /jobs` returns before the work starts
+//! - [ ] Every job is run by exactly one worker, and progress only grows
+//! - [ ] The SSE stream ends with the job's final state, even when
+//!   subscribing after it finished
 //!
 //! Check solution/main.rs after completing
 
 use axum::{
-    extract::{Path, Query, State},
-    http::StatusCode,
-    response::IntoResponse,
+    extract::{Path, Query, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
     routing::{get, post},
     Json, Router,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use sqlx::sqlite::SqlitePool;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{watch, Notify};
 use uuid::Uuid;
 
-// Item model - matches database schema
+// Category model - matches database schema
+#[derive(Clone, Serialize, Deserialize, sqlx::FromRow)]
+struct Category {
+    id: String,
+    name: String,
+    created_at: String,
+}
+
+// Category with the number of items in it (LEFT JOIN + GROUP BY)
+#[derive(Serialize, sqlx::FromRow)]
+struct CategorySummary {
+    id: String,
+    name: String,
+    created_at: String,
+    item_count: i64,
+}
+
+// Item as returned by the API: the items row plus its category's name
 #[derive(Clone, Serialize, Deserialize, sqlx::FromRow)]
 struct Item {
     id: String,  // UUID stored as TEXT
     name: String,
     description: Option<String>,
     price: f64,
+    stock: i64,
+    category_id: Option<String>,
+    category_name: Option<String>,  // from the JOIN, not stored in items
     created_at: String,
 }
 
 // Request bodies
+#[derive(Deserialize)]
+struct CreateCategory {
+    name: String,
+}
+
 #[derive(Deserialize)]
 struct CreateItem {
     name: String,
     description: Option<String>,
     price: f64,
+    #[serde(default)]
+    stock: i64,
+    category_id: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -77,6 +225,26 @@ struct UpdateItem {
     name: Option<String>,
     description: Option<String>,
     price: Option<f64>,
+    stock: Option<i64>,
+    category_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ReserveRequest {
+    quantity: i64,
+}
+
+#[derive(Serialize)]
+struct Reservation {
+    item_id: String,
+    reserved: i64,
+    remaining: i64,
+}
+
+#[derive(Deserialize)]
+struct DeleteCategoryParams {
+    #[serde(default)]
+    cascade: bool,
 }
 
 // Pagination
@@ -97,6 +265,13 @@ struct PaginatedResponse {
 // Error type
 enum AppError {
     NotFound(String),
+    BadRequest(String),
+    Conflict(String),
+    Unauthorized(String),
+    // Byte allowance used up: only a bigger plan helps
+    PaymentRequired(String),
+    // Request quota used up: retry when the month turns (seconds)
+    TooManyRequests(String, u64),
     Database(String),
 }
 
@@ -104,6 +279,19 @@ impl IntoResponse for AppError {
     fn into_response(self) -> axum::response::Response {
         let (status, message) = match self {
             AppError::NotFound(msg) => (StatusCode::NOT_FOUND, msg),
+            AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg),
+            AppError::Conflict(msg) => (StatusCode::CONFLICT, msg),
+            AppError::Unauthorized(msg) => (StatusCode::UNAUTHORIZED, msg),
+            AppError::PaymentRequired(msg) => (StatusCode::PAYMENT_REQUIRED, msg),
+            AppError::TooManyRequests(msg, retry_after) => {
+                let body = Json(json!({ "error": msg }));
+                return (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    [(header::RETRY_AFTER, retry_after.to_string())],
+                    body,
+                )
+                    .into_response();
+            }
             AppError::Database(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
         };
         (status, Json(json!({ "error": message }))).into_response()
@@ -114,6 +302,8 @@ impl From<sqlx::Error> for AppError {
     fn from(err: sqlx::Error) -> Self {
         match err {
             sqlx::Error::RowNotFound => AppError::NotFound("Resource not found".to_string()),
+            // TODO: map foreign key violations to BadRequest and unique
+            // violations to Conflict (sqlx::Error::Database(db) => ...)
             _ => AppError::Database(err.to_string()),
         }
     }
@@ -121,16 +311,16 @@ impl From<sqlx::Error> for AppError {
 
 // Initialize database schema
 async fn init_db(pool: &SqlitePool) -> Result<(), sqlx::Error> {
-    // TODO: Create the items table if it doesn't exist
+    // TODO: Create the tables and index if they don't exist
+    //
+    // Suggested steps:
+    // 1. categories first - items references it
+    // 2. items with category_id TEXT REFERENCES categories(id)
+    // 3. CREATE INDEX ... ON items(category_id)
+    // 4. api_keys, then usage (its api_key references api_keys)
+    // 5. jobs, and an index on jobs(status)
     //
-    // SQL:
-    // CREATE TABLE IF NOT EXISTS items (
-    //     id TEXT PRIMARY KEY,
-    //     name TEXT NOT NULL,
-    //     description TEXT,
-    //     price REAL NOT NULL,
-    //     created_at TEXT NOT NULL
-    // )
+    // (see the schema in the header)
     todo!()
 }
 
@@ -143,8 +333,9 @@ async fn create_item(
     //
     // Steps:
     // 1. Generate UUID and timestamp
-    // 2. INSERT INTO items VALUES (...)
-    // 3. Return the created item with 201 status
+    // 2. INSERT INTO items VALUES (...) including category_id
+    // 3. SELECT it back through the LEFT JOIN to get category_name
+    // 4. Return the created item with 201 status
     todo!()
 }
 
@@ -155,7 +346,9 @@ async fn get_item(
 ) -> Result<Json<Item>, AppError> {
     // TODO: Query item from database
     //
-    // SQL: SELECT * FROM items WHERE id = ?
+    // SQL: SELECT items.*, categories.name AS category_name
+    //      FROM items LEFT JOIN categories ON categories.id = items.category_id
+    //      WHERE items.id = ?
     todo!()
 }
 
@@ -168,7 +361,7 @@ async fn list_items(
     //
     // Steps:
     // 1. Get total count: SELECT COUNT(*) FROM items
-    // 2. Get page of items: SELECT * FROM items LIMIT ? OFFSET ?
+    // 2. Get page of items through the LEFT JOIN ... LIMIT ? OFFSET ?
     // 3. Return paginated response
     todo!()
 }
@@ -183,8 +376,8 @@ async fn update_item(
     //
     // Steps:
     // 1. First check if item exists
-    // 2. Build UPDATE query for provided fields
-    // 3. Return updated item
+    // 2. Build UPDATE query for provided fields (category_id included)
+    // 3. Return updated item, re-read through the JOIN
     todo!()
 }
 
@@ -202,15 +395,283 @@ async fn delete_item(
     todo!()
 }
 
+// Handler: Reserve stock
+async fn reserve_item(
+    State(pool): State<SqlitePool>,
+    Path(id): Path<String>,
+    Json(payload): Json<ReserveRequest>,
+) -> Result<Json<Reservation>, AppError> {
+    // TODO: Take payload.quantity units of stock without overselling
+    //
+    // Steps:
+    // 1. quantity < 1 -> 400
+    // 2. In one transaction: SELECT stock, check it, UPDATE stock, commit
+    //    (unknown id -> 404, not enough stock -> 409)
+    // 3. If a statement fails with SQLITE_BUSY, the transaction lost a race:
+    //    roll back, wait a moment, try again (a few attempts at most)
+    //
+    // Postgres would lock the row instead:
+    //     SELECT stock FROM items WHERE id = $1 FOR UPDATE
+    todo!()
+}
+
+// Handler: Create category
+async fn create_category(
+    State(pool): State<SqlitePool>,
+    Json(payload): Json<CreateCategory>,
+) -> Result<(StatusCode, Json<Category>), AppError> {
+    // TODO: INSERT INTO categories, return 201
+    // A duplicate name should become 409 Conflict
+    todo!()
+}
+
+// Handler: List categories with their item counts
+async fn list_categories(
+    State(pool): State<SqlitePool>,
+) -> Result<Json<Vec<CategorySummary>>, AppError> {
+    // TODO: categories LEFT JOIN items, GROUP BY categories.id
+    // Hint: COUNT(items.id), not COUNT(*), so empty categories count 0
+    todo!()
+}
+
+// Handler: Get category by ID
+async fn get_category(
+    State(pool): State<SqlitePool>,
+    Path(id): Path<String>,
+) -> Result<Json<Category>, AppError> {
+    // TODO: SELECT * FROM categories WHERE id = ?
+    todo!()
+}
+
+// Handler: Delete category
+async fn delete_category(
+    State(pool): State<SqlitePool>,
+    Path(id): Path<String>,
+    Query(params): Query<DeleteCategoryParams>,
+) -> Result<StatusCode, AppError> {
+    // TODO: Delete the category, optionally with its items
+    //
+    // Steps:
+    // 1. Begin a transaction
+    // 2. If params.cascade: DELETE FROM items WHERE category_id = ?
+    // 3. DELETE FROM categories WHERE id = ? - a foreign key violation
+    //    here means items remain: return 409
+    // 4. 0 rows affected -> 404, otherwise commit and return 204
+    todo!()
+}
+
+// ---------------------------------------------------------------------------
+// Usage metering
+// ---------------------------------------------------------------------------
+
+#[derive(Clone, Copy, Default, Serialize)]
+struct Usage {
+    requests: i64,
+    bytes: i64,
+}
+
+// A key's allowance, from api_keys
+#[derive(sqlx::FromRow)]
+struct ApiKey {
+    key: String,
+    tenant: String,
+    monthly_requests: i64,
+    monthly_bytes: i64,
+}
+
+#[derive(Deserialize)]
+struct CreateApiKey {
+    tenant: String,
+    monthly_requests: i64,
+    monthly_bytes: i64,
+}
+
+#[derive(Serialize)]
+struct UsageReport {
+    tenant: String,
+    month: String,
+    requests: i64,
+    bytes: i64,
+    // Counted but not yet flushed to the usage table (included above)
+    unflushed: Usage,
+    quota: Usage,
+}
+
+// Counts per (api key, month) not yet written to the usage table
+#[derive(Default)]
+struct Meter {
+    pending: Mutex<HashMap<(String, String), Usage>>,
+}
+
+impl Meter {
+    fn record(&self, key: &str, month: &str, bytes: i64) {
+        // TODO: add one request and `bytes` to the pending entry
+        todo!()
+    }
+
+    async fn flush(&self, pool: &SqlitePool) -> Result<usize, sqlx::Error> {
+        // TODO: write the pending counts to the usage table
+        //
+        // Steps:
+        // 1. Take the whole map out of the mutex (don't hold it across .await)
+        // 2. In one transaction, upsert each (key, month):
+        //    INSERT INTO usage ... ON CONFLICT (api_key, month) DO UPDATE
+        //    SET requests = requests + excluded.requests, bytes = ...
+        // 3. If that fails, add the counts back so the next flush retries
+        todo!()
+    }
+}
+
+// State of the metering middleware and the /usage and /api-keys routes
+#[derive(Clone)]
+struct Metering {
+    pool: SqlitePool,
+    meter: Arc<Meter>,
+}
+
+// The current billing month ("2024-05", UTC) and seconds until the next one
+fn billing_month() -> (String, u64) {
+    // TODO: convert SystemTime::now() to a calendar month without a date crate
+    // (days since 1970 -> civil date, e.g. Howard Hinnant's civil_from_days)
+    todo!()
+}
+
+// Middleware: refuse keys over quota, count the rest
+async fn meter_requests(
+    State(metering): State<Metering>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    // TODO: Meter requests that carry X-Api-Key
+    //
+    // Steps:
+    // 1. No header -> next.run(request).await, unmetered
+    // 2. Look the key up in api_keys (unknown -> 401)
+    // 3. Stored usage + meter's pending usage for this month:
+    //    requests >= quota -> 429 with Retry-After, bytes >= quota -> 402
+    // 4. Run the request; record 1 request and request + response body bytes
+    todo!()
+}
+
+// Handler: Create an API key (admin; not metered)
+async fn create_api_key(
+    State(metering): State<Metering>,
+    Json(payload): Json<CreateApiKey>,
+) -> Result<(StatusCode, Json<serde_json::Value>), AppError> {
+    // TODO: INSERT INTO api_keys with a fresh random key, return 201 with it
+    todo!()
+}
+
+// Handler: This month's usage of the caller's key (not metered)
+async fn get_usage(
+    State(metering): State<Metering>,
+    headers: HeaderMap,
+) -> Result<Json<UsageReport>, AppError> {
+    // TODO: stored + unflushed usage for the X-Api-Key key, and its quota
+    // (401 without a known key)
+    todo!()
+}
+
+// ---------------------------------------------------------------------------
+// Background jobs
+// ---------------------------------------------------------------------------
+
+// A jobs row, as stored and as returned by the API
+#[derive(Clone, Serialize, sqlx::FromRow)]
+struct Job {
+    id: String,
+    // queued -> running -> done | failed
+    status: String,
+    steps: i64,
+    // Steps completed
+    progress: i64,
+    step_ms: i64,
+    fail_at: Option<i64>,
+    error: Option<String>,
+    created_at: String,
+    updated_at: String,
+}
+
+// The simulated work: `steps` steps of `step_ms` each; with `fail_at`, the
+// job fails at that step instead of completing it
+#[derive(Deserialize)]
+struct CreateJob {
+    steps: i64,
+    step_ms: Option<i64>,
+    fail_at: Option<i64>,
+}
+
+// Shared by the job handlers and the workers
+struct Jobs {
+    pool: SqlitePool,
+    // Wakes an idle worker when a job is queued
+    queued: Notify,
+    // Latest state of every unfinished job, for the SSE streams
+    progress: Mutex<HashMap<String, watch::Sender<Job>>>,
+}
+
+async fn run_worker(worker: usize, jobs: Arc<Jobs>) {
+    // TODO: Run queued jobs forever
+    //
+    // Steps:
+    // 1. Claim the oldest queued job (one UPDATE ... RETURNING *); if there
+    //    is none, wait on jobs.queued and try again
+    // 2. For each remaining step: sleep step_ms, then store the progress
+    //    (status 'done' after the last step, 'failed' with an error at
+    //    fail_at) and send the new row on the job's watch channel
+    // 3. When the job finishes, remove its sender so the streams end
+    todo!()
+}
+
+// Handler: Queue a job - 202 with its URL in Location
+async fn create_job(
+    State(jobs): State<Arc<Jobs>>,
+    Json(payload): Json<CreateJob>,
+) -> Result<(StatusCode, [(header::HeaderName, String); 1], Json<Job>), AppError> {
+    // TODO: validate, INSERT the job as 'queued', create its watch channel,
+    // wake a worker with jobs.queued.notify_one() and return 202
+    todo!()
+}
+
+// Handler: Poll a job
+async fn get_job(
+    State(jobs): State<Arc<Jobs>>,
+    Path(id): Path<String>,
+) -> Result<Json<Job>, AppError> {
+    // TODO: SELECT the job (404 if missing)
+    todo!()
+}
+
+// Handler: Follow a job as Server-Sent Events
+async fn job_events(
+    State(jobs): State<Arc<Jobs>>,
+    Path(id): Path<String>,
+) -> Result<Response, AppError> {
+    // TODO: Stream the job's states until it finishes
+    //
+    // Steps:
+    // 1. Subscribe to the job's watch channel; a finished job has none:
+    //    send its stored row as the only event (404 if there is no row)
+    // 2. Map each state to Event::default().event(status).json_data(&job)
+    // 3. Sse::new(stream).keep_alive(KeepAlive::new()).into_response()
+    todo!()
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // TODO: Set up database connection pool
     //
     // Steps:
-    // 1. Create SqlitePool with ":memory:" or file path
+    // 1. Create SqlitePool with ":memory:" or DATABASE_URL
     // 2. Call init_db to create schema
-    // 3. Build router with pool as state
-    // 4. Start server
+    // 3. Build router with pool as state (/items, /items/:id/reserve and
+    //    /categories routes)
+    // 4. Spawn a task that calls meter.flush every USAGE_FLUSH_SECS
+    // 5. Requeue jobs left running, spawn JOB_WORKERS run_worker tasks and
+    //    add the /jobs routes (Arc<Jobs> as state)
+    // 6. Layer meter_requests over those routes; merge /usage and /api-keys
+    //    (Metering as state) outside the layer
+    // 7. Start server
 
     println!("Server running on http://localhost:3000");
 
@@ -1,37 +1,90 @@
 //! Lab 2: Database Integration - Solution
 //!
-//! CRUD API with SQLite persistence using SQLx.
+//! CRUD API with SQLite persistence using SQLx: items belong to categories,
+//! stock is reserved without overselling, requests made with an API key
+//! are metered against the key's monthly quota, and long-running jobs are
+//! queued for background workers whose progress can be polled or streamed.
 
 use axum::{
-    extract::{Path, Query, State},
-    http::StatusCode,
-    response::IntoResponse,
+    body::HttpBody,
+    extract::{Path, Query, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::{self, Next},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
     routing::{get, post},
     Json, Router,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use sqlx::sqlite::{SqlitePool, SqlitePoolOptions};
+use sqlx::sqlite::{
+    SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions, SqliteSynchronous,
+};
 use sqlx::Row;
-use std::time::Duration;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{watch, Notify};
+use tokio::time::sleep;
+use tokio_stream::{wrappers::WatchStream, StreamExt};
 use uuid::Uuid;
 
-// Item model - matches database schema
+// Category model - matches database schema
+#[derive(Clone, Serialize, Deserialize, sqlx::FromRow)]
+struct Category {
+    id: String,
+    name: String,
+    created_at: String,
+}
+
+// Category with the number of items in it (LEFT JOIN + GROUP BY)
+#[derive(Serialize, sqlx::FromRow)]
+struct CategorySummary {
+    id: String,
+    name: String,
+    created_at: String,
+    item_count: i64,
+}
+
+// Item as returned by the API: the items row plus its category's name
 #[derive(Clone, Serialize, Deserialize, sqlx::FromRow)]
 struct Item {
     id: String,
     name: String,
     description: Option<String>,
     price: f64,
+    stock: i64,
+    category_id: Option<String>,
+    category_name: Option<String>,
     created_at: String,
 }
 
+// Every item query selects through this JOIN, so category_name is always filled.
+// LEFT JOIN keeps items without a category (category_name is NULL).
+const ITEM_SELECT: &str = "
+    SELECT items.id, items.name, items.description, items.price, items.stock,
+           items.category_id, categories.name AS category_name, items.created_at
+    FROM items
+    LEFT JOIN categories ON categories.id = items.category_id";
+
 // Request bodies
+#[derive(Deserialize)]
+struct CreateCategory {
+    name: String,
+}
+
 #[derive(Deserialize)]
 struct CreateItem {
     name: String,
     description: Option<String>,
     price: f64,
+    #[serde(default)]
+    stock: i64,
+    category_id: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -39,6 +92,26 @@ struct UpdateItem {
     name: Option<String>,
     description: Option<String>,
     price: Option<f64>,
+    stock: Option<i64>,
+    category_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ReserveRequest {
+    quantity: i64,
+}
+
+#[derive(Serialize)]
+struct Reservation {
+    item_id: String,
+    reserved: i64,
+    remaining: i64,
+}
+
+#[derive(Deserialize)]
+struct DeleteCategoryParams {
+    #[serde(default)]
+    cascade: bool,
 }
 
 // Pagination
@@ -59,6 +132,13 @@ struct PaginatedResponse {
 // Error type
 enum AppError {
     NotFound(String),
+    BadRequest(String),
+    Conflict(String),
+    Unauthorized(String),
+    // Byte allowance used up: only a bigger plan helps
+    PaymentRequired(String),
+    // Request quota used up: retry when the month turns (seconds)
+    TooManyRequests(String, u64),
     Database(String),
 }
 
@@ -66,6 +146,19 @@ impl IntoResponse for AppError {
     fn into_response(self) -> axum::response::Response {
         let (status, message) = match self {
             AppError::NotFound(msg) => (StatusCode::NOT_FOUND, msg),
+            AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg),
+            AppError::Conflict(msg) => (StatusCode::CONFLICT, msg),
+            AppError::Unauthorized(msg) => (StatusCode::UNAUTHORIZED, msg),
+            AppError::PaymentRequired(msg) => (StatusCode::PAYMENT_REQUIRED, msg),
+            AppError::TooManyRequests(msg, retry_after) => {
+                let body = Json(json!({ "error": msg }));
+                return (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    [(header::RETRY_AFTER, retry_after.to_string())],
+                    body,
+                )
+                    .into_response();
+            }
             AppError::Database(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
         };
         (status, Json(json!({ "error": message }))).into_response()
@@ -76,6 +169,13 @@ impl From<sqlx::Error> for AppError {
     fn from(err: sqlx::Error) -> Self {
         match err {
             sqlx::Error::RowNotFound => AppError::NotFound("Resource not found".to_string()),
+            // The database enforces the relations; turn its verdict into a client error
+            sqlx::Error::Database(ref db) if db.is_foreign_key_violation() => {
+                AppError::BadRequest("Referenced category does not exist".to_string())
+            }
+            sqlx::Error::Database(ref db) if db.is_unique_violation() => {
+                AppError::Conflict("Category name already exists".to_string())
+            }
             _ => AppError::Database(err.to_string()),
         }
     }
@@ -83,6 +183,24 @@ impl From<sqlx::Error> for AppError {
 
 // Initialize database schema
 async fn init_db(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    // Parent table first: items.category_id points at it
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS categories (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE,
+            created_at TEXT NOT NULL
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // No ON DELETE action (NO ACTION): deleting a category that still has
+    // items fails instead of silently taking them along; delete_category
+    // removes them explicitly. (RESTRICT would behave the same but SQLite
+    // reports it with an error code SQLx doesn't classify as a foreign key
+    // violation.)
     sqlx::query(
         r#"
         CREATE TABLE IF NOT EXISTS items (
@@ -90,6 +208,29 @@ async fn init_db(pool: &SqlitePool) -> Result<(), sqlx::Error> {
             name TEXT NOT NULL,
             description TEXT,
             price REAL NOT NULL,
+            stock INTEGER NOT NULL DEFAULT 0 CHECK (stock >= 0),
+            category_id TEXT REFERENCES categories(id),
+            created_at TEXT NOT NULL
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // SQLite doesn't index foreign keys by itself; without this the JOIN and
+    // every category delete scan the whole items table
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_items_category_id ON items(category_id)")
+        .execute(pool)
+        .await?;
+
+    // One key per tenant, with its monthly allowance
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS api_keys (
+            key TEXT PRIMARY KEY,
+            tenant TEXT NOT NULL,
+            monthly_requests INTEGER NOT NULL CHECK (monthly_requests >= 0),
+            monthly_bytes INTEGER NOT NULL CHECK (monthly_bytes >= 0),
             created_at TEXT NOT NULL
         )
         "#,
@@ -97,13 +238,53 @@ async fn init_db(pool: &SqlitePool) -> Result<(), sqlx::Error> {
     .execute(pool)
     .await?;
 
+    // The aggregate: one row per key and month, bumped by every flush
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS usage (
+            api_key TEXT NOT NULL REFERENCES api_keys(key),
+            month TEXT NOT NULL,
+            requests INTEGER NOT NULL DEFAULT 0,
+            bytes INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (api_key, month)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // The job queue: workers claim queued rows and record their progress.
+    // Steps, delay and fail_at describe the simulated work
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS jobs (
+            id TEXT PRIMARY KEY,
+            status TEXT NOT NULL DEFAULT 'queued'
+                CHECK (status IN ('queued', 'running', 'done', 'failed')),
+            steps INTEGER NOT NULL CHECK (steps > 0),
+            progress INTEGER NOT NULL DEFAULT 0 CHECK (progress BETWEEN 0 AND steps),
+            step_ms INTEGER NOT NULL,
+            fail_at INTEGER,
+            error TEXT,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // Workers look for the oldest queued job; don't scan finished ones
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_jobs_status ON jobs(status)")
+        .execute(pool)
+        .await?;
+
     println!("Database initialized");
     Ok(())
 }
 
 // Simple timestamp
 fn now_timestamp() -> String {
-    use std::time::{SystemTime, UNIX_EPOCH};
     let duration = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
     format!("{}", duration.as_secs())
 }
@@ -116,40 +297,41 @@ async fn create_item(
     let id = Uuid::new_v4().to_string();
     let created_at = now_timestamp();
 
+    // An unknown category_id fails the foreign key check -> 400
     sqlx::query(
-        "INSERT INTO items (id, name, description, price, created_at) VALUES (?, ?, ?, ?, ?)",
+        "INSERT INTO items (id, name, description, price, stock, category_id, created_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
     )
     .bind(&id)
     .bind(&payload.name)
     .bind(&payload.description)
     .bind(payload.price)
+    .bind(payload.stock)
+    .bind(&payload.category_id)
     .bind(&created_at)
     .execute(&pool)
     .await?;
 
-    let item = Item {
-        id,
-        name: payload.name,
-        description: payload.description,
-        price: payload.price,
-        created_at,
-    };
+    // Read it back through the JOIN to get category_name
+    let item = fetch_item(&pool, &id).await?;
 
     Ok((StatusCode::CREATED, Json(item)))
 }
 
+// Load one item with its category name
+async fn fetch_item(pool: &SqlitePool, id: &str) -> Result<Item, AppError> {
+    sqlx::query_as::<_, Item>(&format!("{} WHERE items.id = ?", ITEM_SELECT))
+        .bind(id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Item {} not found", id)))
+}
+
 // Handler: Get item by ID
 async fn get_item(
     State(pool): State<SqlitePool>,
     Path(id): Path<String>,
 ) -> Result<Json<Item>, AppError> {
-    let item = sqlx::query_as::<_, Item>("SELECT * FROM items WHERE id = ?")
-        .bind(&id)
-        .fetch_optional(&pool)
-        .await?
-        .ok_or_else(|| AppError::NotFound(format!("Item {} not found", id)))?;
-
-    Ok(Json(item))
+    Ok(Json(fetch_item(&pool, &id).await?))
 }
 
 // Handler: List items with pagination
@@ -168,11 +350,14 @@ async fn list_items(
         .get("count");
 
     // Get items for current page
-    let items = sqlx::query_as::<_, Item>("SELECT * FROM items ORDER BY created_at DESC LIMIT ? OFFSET ?")
-        .bind(limit)
-        .bind(offset)
-        .fetch_all(&pool)
-        .await?;
+    let items = sqlx::query_as::<_, Item>(&format!(
+        "{} ORDER BY items.created_at DESC LIMIT ? OFFSET ?",
+        ITEM_SELECT
+    ))
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(&pool)
+    .await?;
 
     Ok(Json(PaginatedResponse {
         items,
@@ -189,34 +374,29 @@ async fn update_item(
     Json(payload): Json<UpdateItem>,
 ) -> Result<Json<Item>, AppError> {
     // First check if item exists
-    let existing = sqlx::query_as::<_, Item>("SELECT * FROM items WHERE id = ?")
-        .bind(&id)
-        .fetch_optional(&pool)
-        .await?
-        .ok_or_else(|| AppError::NotFound(format!("Item {} not found", id)))?;
+    let existing = fetch_item(&pool, &id).await?;
 
     // Apply updates
     let name = payload.name.unwrap_or(existing.name);
     let description = payload.description.or(existing.description);
     let price = payload.price.unwrap_or(existing.price);
+    let stock = payload.stock.unwrap_or(existing.stock);
+    let category_id = payload.category_id.or(existing.category_id);
 
-    sqlx::query("UPDATE items SET name = ?, description = ?, price = ? WHERE id = ?")
-        .bind(&name)
-        .bind(&description)
-        .bind(price)
-        .bind(&id)
-        .execute(&pool)
-        .await?;
-
-    let updated = Item {
-        id: existing.id,
-        name,
-        description,
-        price,
-        created_at: existing.created_at,
-    };
+    sqlx::query(
+        "UPDATE items SET name = ?, description = ?, price = ?, stock = ?, category_id = ? WHERE id = ?",
+    )
+    .bind(&name)
+    .bind(&description)
+    .bind(price)
+    .bind(stock)
+    .bind(&category_id)
+    .bind(&id)
+    .execute(&pool)
+    .await?;
 
-    Ok(Json(updated))
+    // The category may have changed: read the name through the JOIN again
+    Ok(Json(fetch_item(&pool, &id).await?))
 }
 
 // Handler: Delete item
@@ -236,36 +416,916 @@ async fn delete_item(
     Ok(StatusCode::NO_CONTENT)
 }
 
+// How often a reservation is retried when SQLite reports the database busy
+const MAX_RESERVE_ATTEMPTS: u32 = 5;
+
+// Outcome of one reservation attempt that ran to the end
+enum ReserveOutcome {
+    Reserved(i64),
+    NotFound,
+    Insufficient(i64),
+}
+
+// SQLITE_BUSY (5) and SQLITE_LOCKED (6), including extended codes like
+// SQLITE_BUSY_SNAPSHOT (517): another connection holds the lock we need
+fn is_busy(err: &sqlx::Error) -> bool {
+    match err {
+        sqlx::Error::Database(db) => db
+            .code()
+            .and_then(|code| code.parse::<i32>().ok())
+            .is_some_and(|code| matches!(code & 0xff, 5 | 6)),
+        _ => false,
+    }
+}
+
+// One attempt: read the stock, then write it, inside one transaction.
+//
+// SQLite has no row locks. The SELECT takes a shared lock on the whole
+// database; the UPDATE needs the write lock. If another transaction read the
+// same stock and already holds the write lock, SQLite answers SQLITE_BUSY
+// rather than let both write - the caller rolls back and tries again.
+// Postgres would lock just the row instead:
+//
+//     SELECT stock FROM items WHERE id = $1 FOR UPDATE;  -- others wait here
+//     UPDATE items SET stock = stock - $2 WHERE id = $1;
+async fn try_reserve(
+    pool: &SqlitePool,
+    id: &str,
+    quantity: i64,
+) -> Result<ReserveOutcome, sqlx::Error> {
+    // Dropped without commit (early return, error, cancelled request) = rollback
+    let mut tx = pool.begin().await?;
+
+    let stock: Option<i64> = sqlx::query_scalar("SELECT stock FROM items WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?;
+    let stock = match stock {
+        Some(stock) => stock,
+        None => return Ok(ReserveOutcome::NotFound),
+    };
+    if stock < quantity {
+        return Ok(ReserveOutcome::Insufficient(stock));
+    }
+
+    let remaining = stock - quantity;
+    sqlx::query("UPDATE items SET stock = ? WHERE id = ?")
+        .bind(remaining)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(ReserveOutcome::Reserved(remaining))
+}
+
+// Handler: Reserve stock
+async fn reserve_item(
+    State(pool): State<SqlitePool>,
+    Path(id): Path<String>,
+    Json(payload): Json<ReserveRequest>,
+) -> Result<Json<Reservation>, AppError> {
+    if payload.quantity < 1 {
+        return Err(AppError::BadRequest("quantity must be at least 1".to_string()));
+    }
+
+    let mut attempt = 1;
+    let outcome = loop {
+        match try_reserve(&pool, &id, payload.quantity).await {
+            Err(err) if is_busy(&err) && attempt < MAX_RESERVE_ATTEMPTS => {
+                // Back off a little longer each time so the winner can commit
+                sleep(Duration::from_millis(5 << attempt)).await;
+                attempt += 1;
+            }
+            result => break result?,
+        }
+    };
+
+    match outcome {
+        ReserveOutcome::Reserved(remaining) => Ok(Json(Reservation {
+            item_id: id,
+            reserved: payload.quantity,
+            remaining,
+        })),
+        ReserveOutcome::NotFound => Err(AppError::NotFound(format!("Item {} not found", id))),
+        ReserveOutcome::Insufficient(available) => Err(AppError::Conflict(format!(
+            "Insufficient stock: requested {}, available {}",
+            payload.quantity, available
+        ))),
+    }
+}
+
+// Handler: Create category
+async fn create_category(
+    State(pool): State<SqlitePool>,
+    Json(payload): Json<CreateCategory>,
+) -> Result<(StatusCode, Json<Category>), AppError> {
+    let category = Category {
+        id: Uuid::new_v4().to_string(),
+        name: payload.name,
+        created_at: now_timestamp(),
+    };
+
+    // A duplicate name fails the UNIQUE constraint -> 409
+    sqlx::query("INSERT INTO categories (id, name, created_at) VALUES (?, ?, ?)")
+        .bind(&category.id)
+        .bind(&category.name)
+        .bind(&category.created_at)
+        .execute(&pool)
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(category)))
+}
+
+// Handler: List categories with their item counts
+async fn list_categories(
+    State(pool): State<SqlitePool>,
+) -> Result<Json<Vec<CategorySummary>>, AppError> {
+    // COUNT(items.id), not COUNT(*): an empty category still yields one
+    // LEFT JOIN row, with items.id NULL
+    let categories = sqlx::query_as::<_, CategorySummary>(
+        r#"
+        SELECT categories.id, categories.name, categories.created_at,
+               COUNT(items.id) AS item_count
+        FROM categories
+        LEFT JOIN items ON items.category_id = categories.id
+        GROUP BY categories.id
+        ORDER BY categories.name
+        "#,
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(Json(categories))
+}
+
+// Handler: Get category by ID
+async fn get_category(
+    State(pool): State<SqlitePool>,
+    Path(id): Path<String>,
+) -> Result<Json<Category>, AppError> {
+    let category = sqlx::query_as::<_, Category>("SELECT * FROM categories WHERE id = ?")
+        .bind(&id)
+        .fetch_optional(&pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Category {} not found", id)))?;
+
+    Ok(Json(category))
+}
+
+// Handler: Delete category
+//
+// Without ?cascade=true the foreign key refuses while items still use the
+// category (409). With it, the items go first - in one transaction, so a
+// failure halfway leaves both tables untouched.
+async fn delete_category(
+    State(pool): State<SqlitePool>,
+    Path(id): Path<String>,
+    Query(params): Query<DeleteCategoryParams>,
+) -> Result<StatusCode, AppError> {
+    let mut tx = pool.begin().await?;
+
+    if params.cascade {
+        sqlx::query("DELETE FROM items WHERE category_id = ?")
+            .bind(&id)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    let result = sqlx::query("DELETE FROM categories WHERE id = ?")
+        .bind(&id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|err| match err {
+            sqlx::Error::Database(ref db) if db.is_foreign_key_violation() => AppError::Conflict(
+                format!("Category {} still has items; use ?cascade=true", id),
+            ),
+            other => other.into(),
+        })?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound(format!("Category {} not found", id)));
+    }
+
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// ---------------------------------------------------------------------------
+// Usage metering
+//
+// Writing a row per request would make every request a database write. The
+// middleware adds to counters in memory instead, and a background task
+// upserts them into `usage` every few seconds: one write per key per flush.
+// The price is that a crash loses up to one interval of counts, so quota
+// checks read the stored total *plus* what is still in memory.
+// ---------------------------------------------------------------------------
+
+// How often pending usage is written to the usage table
+const DEFAULT_FLUSH_SECS: u64 = 5;
+
+#[derive(Clone, Copy, Default, Serialize)]
+struct Usage {
+    requests: i64,
+    bytes: i64,
+}
+
+// A key's allowance, from api_keys
+#[derive(sqlx::FromRow)]
+struct ApiKey {
+    key: String,
+    tenant: String,
+    monthly_requests: i64,
+    monthly_bytes: i64,
+}
+
+#[derive(Deserialize)]
+struct CreateApiKey {
+    tenant: String,
+    monthly_requests: i64,
+    monthly_bytes: i64,
+}
+
+#[derive(Serialize)]
+struct UsageReport {
+    tenant: String,
+    month: String,
+    requests: i64,
+    bytes: i64,
+    // Counted but not yet flushed to the usage table (included above)
+    unflushed: Usage,
+    quota: Usage,
+}
+
+// Counts per (api key, month) not yet written to the usage table
+#[derive(Default)]
+struct Meter {
+    pending: Mutex<HashMap<(String, String), Usage>>,
+}
+
+impl Meter {
+    fn record(&self, key: &str, month: &str, bytes: i64) {
+        let mut pending = self.pending.lock().unwrap();
+        let usage = pending
+            .entry((key.to_string(), month.to_string()))
+            .or_default();
+        usage.requests += 1;
+        usage.bytes += bytes;
+    }
+
+    fn pending(&self, key: &str, month: &str) -> Usage {
+        let pending = self.pending.lock().unwrap();
+        pending
+            .get(&(key.to_string(), month.to_string()))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    // Write everything pending in one transaction; on failure the counts go
+    // back into memory for the next flush, so nothing is counted twice or lost
+    async fn flush(&self, pool: &SqlitePool) -> Result<usize, sqlx::Error> {
+        let batch = std::mem::take(&mut *self.pending.lock().unwrap());
+        if batch.is_empty() {
+            return Ok(0);
+        }
+        match write_usage(pool, &batch).await {
+            Ok(()) => Ok(batch.len()),
+            Err(err) => {
+                let mut pending = self.pending.lock().unwrap();
+                for (slot, usage) in batch {
+                    let entry = pending.entry(slot).or_default();
+                    entry.requests += usage.requests;
+                    entry.bytes += usage.bytes;
+                }
+                Err(err)
+            }
+        }
+    }
+}
+
+async fn write_usage(
+    pool: &SqlitePool,
+    batch: &HashMap<(String, String), Usage>,
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    for ((key, month), usage) in batch {
+        // Upsert: the first flush of a month inserts, later ones add
+        sqlx::query(
+            r#"
+            INSERT INTO usage (api_key, month, requests, bytes) VALUES (?, ?, ?, ?)
+            ON CONFLICT (api_key, month) DO UPDATE SET
+                requests = requests + excluded.requests,
+                bytes = bytes + excluded.bytes
+            "#,
+        )
+        .bind(key)
+        .bind(month)
+        .bind(usage.requests)
+        .bind(usage.bytes)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await
+}
+
+async fn flush_periodically(meter: Arc<Meter>, pool: SqlitePool, every: Duration) {
+    let mut ticks = tokio::time::interval(every);
+    loop {
+        ticks.tick().await;
+        if let Err(err) = meter.flush(&pool).await {
+            eprintln!("usage flush failed, will retry: {}", err);
+        }
+    }
+}
+
+// State of the metering middleware and the /usage and /api-keys routes
+#[derive(Clone)]
+struct Metering {
+    pool: SqlitePool,
+    meter: Arc<Meter>,
+}
+
+impl Metering {
+    // Stored plus unflushed usage of one key in one month
+    async fn usage(&self, key: &str, month: &str) -> Result<(Usage, Usage), AppError> {
+        let stored = sqlx::query_as::<_, (i64, i64)>(
+            "SELECT requests, bytes FROM usage WHERE api_key = ? AND month = ?",
+        )
+        .bind(key)
+        .bind(month)
+        .fetch_optional(&self.pool)
+        .await?
+        .unwrap_or((0, 0));
+        let pending = self.meter.pending(key, month);
+        let total = Usage {
+            requests: stored.0 + pending.requests,
+            bytes: stored.1 + pending.bytes,
+        };
+        Ok((total, pending))
+    }
+}
+
+// The X-Api-Key header, looked up in api_keys. None without the header
+async fn api_key(pool: &SqlitePool, headers: &HeaderMap) -> Result<Option<ApiKey>, AppError> {
+    let Some(key) = headers.get("x-api-key") else {
+        return Ok(None);
+    };
+    let key = key
+        .to_str()
+        .map_err(|_| AppError::Unauthorized("Invalid API key".to_string()))?;
+    sqlx::query_as::<_, ApiKey>("SELECT * FROM api_keys WHERE key = ?")
+        .bind(key)
+        .fetch_optional(pool)
+        .await?
+        .map(Some)
+        .ok_or_else(|| AppError::Unauthorized("Unknown API key".to_string()))
+}
+
+// Days since 1970-01-01 -> (year, month), proleptic Gregorian
+// (Howard Hinnant's civil_from_days)
+fn civil_month(days: i64) -> (i64, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month)
+}
+
+// Days from 1970-01-01 to the first of (year, month)
+fn days_from_civil(year: i64, month: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (i64::from(month) + 9) % 12;
+    let doy = (153 * mp + 2) / 5;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+// The current billing month ("2024-05", UTC) and seconds until the next one
+fn billing_month() -> (String, u64) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    let (year, month) = civil_month(now.div_euclid(86_400));
+    let (next_year, next_month) = if month == 12 {
+        (year + 1, 1)
+    } else {
+        (year, month + 1)
+    };
+    let next = days_from_civil(next_year, next_month) * 86_400;
+    (format!("{:04}-{:02}", year, month), (next - now) as u64)
+}
+
+// Middleware: refuse keys over quota, count the rest.
+//
+// Requests without X-Api-Key pass unmetered, so the earlier labs' clients
+// keep working; a production API would answer 401. Rejected requests are
+// not counted. Bytes are the request body plus the response body, so the
+// request that crosses the byte allowance still completes - the next one
+// gets the 402.
+async fn meter_requests(
+    State(metering): State<Metering>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let Some(key) = api_key(&metering.pool, request.headers()).await? else {
+        return Ok(next.run(request).await);
+    };
+
+    let (month, retry_after) = billing_month();
+    let (used, _) = metering.usage(&key.key, &month).await?;
+    if used.requests >= key.monthly_requests {
+        return Err(AppError::TooManyRequests(
+            format!(
+                "Monthly request quota of {} used up for {}",
+                key.monthly_requests, month
+            ),
+            retry_after,
+        ));
+    }
+    if used.bytes >= key.monthly_bytes {
+        return Err(AppError::PaymentRequired(format!(
+            "Monthly transfer allowance of {} bytes used up for {}",
+            key.monthly_bytes, month
+        )));
+    }
+
+    let request_bytes = request.body().size_hint().exact().unwrap_or(0);
+    let response = next.run(request).await;
+    // Buffered JSON bodies know their length; a stream would be counted as 0
+    let response_bytes = response.body().size_hint().exact().unwrap_or(0);
+    metering
+        .meter
+        .record(&key.key, &month, (request_bytes + response_bytes) as i64);
+    Ok(response)
+}
+
+// Handler: Create an API key (admin; not metered)
+async fn create_api_key(
+    State(metering): State<Metering>,
+    Json(payload): Json<CreateApiKey>,
+) -> Result<(StatusCode, Json<serde_json::Value>), AppError> {
+    if payload.monthly_requests < 0 || payload.monthly_bytes < 0 {
+        return Err(AppError::BadRequest(
+            "Quotas cannot be negative".to_string(),
+        ));
+    }
+    let key = Uuid::new_v4().simple().to_string();
+    sqlx::query(
+        "INSERT INTO api_keys (key, tenant, monthly_requests, monthly_bytes, created_at) VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(&key)
+    .bind(&payload.tenant)
+    .bind(payload.monthly_requests)
+    .bind(payload.monthly_bytes)
+    .bind(now_timestamp())
+    .execute(&metering.pool)
+    .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({
+            "key": key,
+            "tenant": payload.tenant,
+            "monthly_requests": payload.monthly_requests,
+            "monthly_bytes": payload.monthly_bytes,
+        })),
+    ))
+}
+
+// Handler: This month's usage of the caller's key (not metered, so a tenant
+// over quota can still see why)
+async fn get_usage(
+    State(metering): State<Metering>,
+    headers: HeaderMap,
+) -> Result<Json<UsageReport>, AppError> {
+    let key = api_key(&metering.pool, &headers)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized("X-Api-Key header required".to_string()))?;
+    let (month, _) = billing_month();
+    let (used, unflushed) = metering.usage(&key.key, &month).await?;
+
+    Ok(Json(UsageReport {
+        tenant: key.tenant,
+        month,
+        requests: used.requests,
+        bytes: used.bytes,
+        unflushed,
+        quota: Usage {
+            requests: key.monthly_requests,
+            bytes: key.monthly_bytes,
+        },
+    }))
+}
+
+// ---------------------------------------------------------------------------
+// Background jobs
+//
+// POST /jobs only records the job and answers 202; the work runs later, in
+// one of JOB_WORKERS worker tasks. The jobs table is the queue: a worker
+// claims the oldest queued row with a single UPDATE, so two workers never
+// take the same job, and a job survives a restart (with a file database)
+// because it is a row, not a message in memory. Each step the worker
+// stores the progress and publishes the row on the job's watch channel,
+// which is what the SSE stream follows; polling GET /jobs/:id reads the row.
+// ---------------------------------------------------------------------------
+
+const DEFAULT_JOB_WORKERS: usize = 2;
+const DEFAULT_STEP_MS: i64 = 200;
+const MAX_JOB_STEPS: i64 = 1000;
+const MAX_STEP_MS: i64 = 60_000;
+
+// A jobs row, as stored and as returned by the API
+#[derive(Clone, Serialize, sqlx::FromRow)]
+struct Job {
+    id: String,
+    // queued -> running -> done | failed
+    status: String,
+    steps: i64,
+    // Steps completed
+    progress: i64,
+    step_ms: i64,
+    fail_at: Option<i64>,
+    error: Option<String>,
+    created_at: String,
+    updated_at: String,
+}
+
+impl Job {
+    fn is_finished(&self) -> bool {
+        matches!(self.status.as_str(), "done" | "failed")
+    }
+}
+
+// The simulated work: `steps` steps of `step_ms` each; with `fail_at`, the
+// job fails at that step instead of completing it
+#[derive(Deserialize)]
+struct CreateJob {
+    steps: i64,
+    step_ms: Option<i64>,
+    fail_at: Option<i64>,
+}
+
+// Shared by the job handlers and the workers
+struct Jobs {
+    pool: SqlitePool,
+    // Wakes an idle worker when a job is queued
+    queued: Notify,
+    // Latest state of every unfinished job; the sender is dropped when the
+    // job finishes, which ends the SSE streams following it
+    progress: Mutex<HashMap<String, watch::Sender<Job>>>,
+}
+
+impl Jobs {
+    // Tell the job's subscribers; call after the row is written
+    fn publish(&self, job: &Job) {
+        let mut progress = self.progress.lock().unwrap();
+        if job.is_finished() {
+            if let Some(sender) = progress.remove(&job.id) {
+                sender.send_replace(job.clone());
+            }
+        } else if let Some(sender) = progress.get(&job.id) {
+            // send() fails without receivers; the value must be kept anyway
+            sender.send_replace(job.clone());
+        } else {
+            progress.insert(job.id.clone(), watch::channel(job.clone()).0);
+        }
+    }
+
+    // Jobs a previous run left behind: running ones go back in the queue
+    // (they resume at the last recorded step), all get a progress channel
+    async fn recover(&self) -> Result<usize, sqlx::Error> {
+        sqlx::query("UPDATE jobs SET status = 'queued' WHERE status = 'running'")
+            .execute(&self.pool)
+            .await?;
+        let unfinished = sqlx::query_as::<_, Job>("SELECT * FROM jobs WHERE status = 'queued'")
+            .fetch_all(&self.pool)
+            .await?;
+        for job in &unfinished {
+            self.publish(job);
+        }
+        Ok(unfinished.len())
+    }
+}
+
+// Take the oldest queued job. One statement, so it is atomic: SQLite lets
+// one writer in at a time, and the row is no longer 'queued' for the next
+async fn claim_job(pool: &SqlitePool) -> Result<Option<Job>, sqlx::Error> {
+    sqlx::query_as::<_, Job>(
+        r#"
+        UPDATE jobs SET status = 'running', updated_at = ?
+        WHERE id = (SELECT id FROM jobs WHERE status = 'queued' ORDER BY rowid LIMIT 1)
+        RETURNING *
+        "#,
+    )
+    .bind(now_timestamp())
+    .fetch_optional(pool)
+    .await
+}
+
+// Record one step (or the end) of a job and publish the new row
+async fn update_job(
+    jobs: &Jobs,
+    id: &str,
+    status: &str,
+    progress: i64,
+    error: Option<String>,
+) -> Result<(), sqlx::Error> {
+    let job = sqlx::query_as::<_, Job>(
+        "UPDATE jobs SET status = ?, progress = ?, error = ?, updated_at = ? WHERE id = ? RETURNING *",
+    )
+    .bind(status)
+    .bind(progress)
+    .bind(error)
+    .bind(now_timestamp())
+    .bind(id)
+    .fetch_one(&jobs.pool)
+    .await?;
+    jobs.publish(&job);
+    Ok(())
+}
+
+async fn run_job(jobs: &Jobs, job: &Job) -> Result<&'static str, sqlx::Error> {
+    // A recovered job starts where it stopped
+    for step in job.progress + 1..=job.steps {
+        sleep(Duration::from_millis(job.step_ms as u64)).await;
+        if job.fail_at == Some(step) {
+            let error = format!("step {} of {} failed", step, job.steps);
+            update_job(jobs, &job.id, "failed", step - 1, Some(error)).await?;
+            return Ok("failed");
+        }
+        let status = if step == job.steps { "done" } else { "running" };
+        update_job(jobs, &job.id, status, step, None).await?;
+    }
+    Ok("done")
+}
+
+async fn run_worker(worker: usize, jobs: Arc<Jobs>) {
+    loop {
+        let job = match claim_job(&jobs.pool).await {
+            Ok(Some(job)) => job,
+            // notify_one() stores a permit when no worker is waiting, so a
+            // job queued between the claim and this wait is not missed
+            Ok(None) => {
+                jobs.queued.notified().await;
+                continue;
+            }
+            Err(err) => {
+                eprintln!("[worker {}] claim failed: {}", worker, err);
+                sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+        jobs.publish(&job);
+        println!("[worker {}] job {} started", worker, job.id);
+        match run_job(&jobs, &job).await {
+            Ok(outcome) => println!("[worker {}] job {} {}", worker, job.id, outcome),
+            Err(err) => {
+                eprintln!("[worker {}] job {} failed: {}", worker, job.id, err);
+                // Best effort: the database may be what failed
+                let error = Some(format!("internal error: {}", err));
+                let _ = update_job(&jobs, &job.id, "failed", job.progress, error).await;
+            }
+        }
+    }
+}
+
+async fn fetch_job(pool: &SqlitePool, id: &str) -> Result<Job, AppError> {
+    sqlx::query_as::<_, Job>("SELECT * FROM jobs WHERE id = ?")
+        .bind(id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Job {} not found", id)))
+}
+
+// Handler: Queue a job - 202 with its URL in Location
+async fn create_job(
+    State(jobs): State<Arc<Jobs>>,
+    Json(payload): Json<CreateJob>,
+) -> Result<(StatusCode, [(header::HeaderName, String); 1], Json<Job>), AppError> {
+    let step_ms = payload.step_ms.unwrap_or(DEFAULT_STEP_MS);
+    if !(1..=MAX_JOB_STEPS).contains(&payload.steps) {
+        return Err(AppError::BadRequest(format!(
+            "steps must be between 1 and {}",
+            MAX_JOB_STEPS
+        )));
+    }
+    if !(0..=MAX_STEP_MS).contains(&step_ms) {
+        return Err(AppError::BadRequest(format!(
+            "step_ms must be between 0 and {}",
+            MAX_STEP_MS
+        )));
+    }
+    if payload
+        .fail_at
+        .is_some_and(|step| !(1..=payload.steps).contains(&step))
+    {
+        return Err(AppError::BadRequest(
+            "fail_at must be one of the steps".to_string(),
+        ));
+    }
+
+    let now = now_timestamp();
+    let job = sqlx::query_as::<_, Job>(
+        r#"
+        INSERT INTO jobs (id, steps, step_ms, fail_at, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?)
+        RETURNING *
+        "#,
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(payload.steps)
+    .bind(step_ms)
+    .bind(payload.fail_at)
+    .bind(&now)
+    .bind(&now)
+    .fetch_one(&jobs.pool)
+    .await?;
+
+    // Subscribers can attach before a worker picks it up
+    jobs.publish(&job);
+    jobs.queued.notify_one();
+
+    let location = format!("/jobs/{}", job.id);
+    Ok((
+        StatusCode::ACCEPTED,
+        [(header::LOCATION, location)],
+        Json(job),
+    ))
+}
+
+// Handler: Poll a job
+async fn get_job(
+    State(jobs): State<Arc<Jobs>>,
+    Path(id): Path<String>,
+) -> Result<Json<Job>, AppError> {
+    Ok(Json(fetch_job(&jobs.pool, &id).await?))
+}
+
+// Handler: Follow a job as Server-Sent Events.
+//
+// One event per state change, named after the status (`queued`, `running`,
+// `done`, `failed`) with the job as JSON data; the stream ends after
+// `done` or `failed`. A watch channel keeps only the latest value, so a
+// slow client skips steps instead of falling behind.
+async fn job_events(
+    State(jobs): State<Arc<Jobs>>,
+    Path(id): Path<String>,
+) -> Result<Response, AppError> {
+    let subscribed = jobs
+        .progress
+        .lock()
+        .unwrap()
+        .get(&id)
+        .map(watch::Sender::subscribe);
+    let receiver = match subscribed {
+        Some(receiver) => receiver,
+        // Finished (or unknown): the stream is the stored row, then the end.
+        // A receiver whose sender is gone yields its value once and stops
+        None => watch::channel(fetch_job(&jobs.pool, &id).await?).1,
+    };
+
+    let stream = WatchStream::new(receiver).map(|job| {
+        Ok::<_, Infallible>(
+            Event::default()
+                .event(job.status.clone())
+                .json_data(&job)
+                .expect("a job serializes to JSON"),
+        )
+    });
+    Ok(Sse::new(stream)
+        .keep_alive(
+            KeepAlive::new()
+                .interval(Duration::from_secs(5))
+                .text("keep-alive"),
+        )
+        .into_response())
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Create connection pool with configuration
+    // Create connection pool with configuration.
+    // SQLx turns on `PRAGMA foreign_keys` for every connection; SQLite
+    // itself defaults to off and would ignore the REFERENCES clauses.
+    // Each connection to "sqlite::memory:" gets its own empty database, so
+    // the in-memory pool must stay at one connection. Set DATABASE_URL to a
+    // file (sqlite:items.db?mode=rwc) to get a real pool, where concurrent
+    // reservations actually contend for the lock.
+    let url = std::env::var("DATABASE_URL").unwrap_or_else(|_| "sqlite::memory:".to_string());
+    // WAL keeps item reads flowing while a reservation commits; the
+    // read-then-write in try_reserve still needs its retry (see chapter_04
+    // lab_05)
+    let options = SqliteConnectOptions::from_str(&url)?
+        .journal_mode(SqliteJournalMode::Wal)
+        .busy_timeout(Duration::from_secs(5))
+        .synchronous(SqliteSynchronous::Normal);
+    let max_connections = if url.contains(":memory:") { 1 } else { 5 };
     let pool = SqlitePoolOptions::new()
-        .max_connections(5)
+        .max_connections(max_connections)
         .acquire_timeout(Duration::from_secs(3))
-        .connect("sqlite::memory:")
+        .connect_with(options)
         .await?;
 
     // Initialize database schema
     init_db(&pool).await?;
 
-    // Build router
-    let app = Router::new()
+    // Usage is aggregated in memory and flushed every USAGE_FLUSH_SECS
+    let flush_secs = std::env::var("USAGE_FLUSH_SECS")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .filter(|&secs| secs > 0)
+        .unwrap_or(DEFAULT_FLUSH_SECS);
+    let metering = Metering {
+        pool: pool.clone(),
+        meter: Arc::new(Meter::default()),
+    };
+    tokio::spawn(flush_periodically(
+        Arc::clone(&metering.meter),
+        pool.clone(),
+        Duration::from_secs(flush_secs),
+    ));
+
+    // The workers drain the jobs table; jobs a previous run left are requeued
+    let workers = std::env::var("JOB_WORKERS")
+        .ok()
+        .and_then(|workers| workers.parse().ok())
+        .filter(|&workers| workers > 0)
+        .unwrap_or(DEFAULT_JOB_WORKERS);
+    let jobs = Arc::new(Jobs {
+        pool: pool.clone(),
+        queued: Notify::new(),
+        progress: Mutex::new(HashMap::new()),
+    });
+    let recovered = jobs.recover().await?;
+    if recovered > 0 {
+        println!("Requeued {} unfinished jobs", recovered);
+    }
+    for worker in 1..=workers {
+        tokio::spawn(run_worker(worker, Arc::clone(&jobs)));
+    }
+    let jobs_api = Router::new()
+        .route("/jobs", post(create_job))
+        .route("/jobs/:id", get(get_job))
+        .route("/jobs/:id/events", get(job_events))
+        .with_state(jobs);
+
+    // Build router: the API routes are metered, /usage and /api-keys are not
+    let api = Router::new()
         .route("/items", get(list_items).post(create_item))
         .route(
             "/items/:id",
             get(get_item).put(update_item).delete(delete_item),
         )
-        .with_state(pool);
+        .route("/items/:id/reserve", post(reserve_item))
+        .route("/categories", get(list_categories).post(create_category))
+        .route(
+            "/categories/:id",
+            get(get_category).delete(delete_category),
+        )
+        .with_state(pool)
+        .merge(jobs_api)
+        .layer(middleware::from_fn_with_state(
+            metering.clone(),
+            meter_requests,
+        ));
+    let app = Router::new()
+        .route("/usage", get(get_usage))
+        .route("/api-keys", post(create_api_key))
+        .with_state(metering)
+        .merge(api);
 
     println!("Server running on http://localhost:3000");
     println!();
-    println!("Using in-memory SQLite database");
-    println!("Data persists within session but resets on restart");
+    if url.contains(":memory:") {
+        println!("Using in-memory SQLite database");
+        println!("Data persists within session but resets on restart");
+    } else {
+        println!("Using SQLite database {}", url);
+    }
     println!();
     println!("Try:");
     println!("  curl -X POST http://localhost:3000/items \\");
     println!("    -H \"Content-Type: application/json\" \\");
     println!("    -d '{{\"name\": \"Widget\", \"price\": 9.99}}'");
+    println!("  curl -X POST http://localhost:3000/categories \\");
+    println!("    -H \"Content-Type: application/json\" \\");
+    println!("    -d '{{\"name\": \"Tools\"}}'");
+    println!("  curl -X POST http://localhost:3000/api-keys \\");
+    println!("    -H \"Content-Type: application/json\" \\");
+    println!(
+        "    -d '{{\"tenant\": \"acme\", \"monthly_requests\": 1000, \"monthly_bytes\": 1000000}}'"
+    );
+    println!("  curl -H \"X-Api-Key: <key>\" http://localhost:3000/usage");
+    println!("  curl -X POST http://localhost:3000/jobs \\");
+    println!("    -H \"Content-Type: application/json\" \\");
+    println!("    -d '{{\"steps\": 10}}'");
+    println!("  curl -N http://localhost:3000/jobs/<id>/events");
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await?;
     axum::serve(listener, app).await?;
@@ -12,9 +12,54 @@ struct Item {
     name: String,
     description: Option<String>,
     price: f64,
+    stock: i64,
+    category_id: Option<String>,
+    category_name: Option<String>,
     created_at: String,
 }
 
+#[derive(Debug, Deserialize)]
+struct Reservation {
+    reserved: i64,
+    remaining: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Category {
+    id: String,
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CategorySummary {
+    id: String,
+    item_count: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Usage {
+    requests: i64,
+    bytes: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct UsageReport {
+    tenant: String,
+    requests: i64,
+    bytes: i64,
+    unflushed: Usage,
+    quota: Usage,
+}
+
+#[derive(Debug, Deserialize)]
+struct Job {
+    id: String,
+    status: String,
+    steps: i64,
+    progress: i64,
+    error: Option<String>,
+}
+
 #[derive(Debug, Deserialize)]
 struct PaginatedResponse {
     items: Vec<Item>,
@@ -208,3 +253,566 @@ async fn test_05_concurrent_creates() {
 
     assert_eq!(success_count, 10, "All concurrent creates should succeed");
 }
+
+/// Category names are UNIQUE, so every test makes its own
+async fn create_category(client: &reqwest::Client, name: &str) -> Category {
+    let resp = client
+        .post(format!("{}/categories", BASE_URL))
+        .json(&json!({ "name": format!("{} {}", name, uuid::Uuid::new_v4()) }))
+        .send()
+        .await
+        .expect("Failed to create category");
+    assert_eq!(resp.status(), 201);
+    resp.json().await.unwrap()
+}
+
+#[tokio::test]
+#[ignore = "requires running server"]
+async fn test_06_item_carries_category_name() {
+    let client = reqwest::Client::new();
+    let category = create_category(&client, "Tools").await;
+
+    let create_resp = client
+        .post(format!("{}/items", BASE_URL))
+        .json(&json!({
+            "name": "Hammer",
+            "price": 12.5,
+            "category_id": category.id
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(create_resp.status(), 201);
+    let created: Item = create_resp.json().await.unwrap();
+    assert_eq!(created.category_id.as_deref(), Some(category.id.as_str()));
+    assert_eq!(
+        created.category_name.as_deref(),
+        Some(category.name.as_str())
+    );
+
+    // The list endpoint goes through the same JOIN
+    let resp = client
+        .get(format!("{}/items?limit=100", BASE_URL))
+        .send()
+        .await
+        .unwrap();
+    let paginated: PaginatedResponse = resp.json().await.unwrap();
+    let listed = paginated
+        .items
+        .iter()
+        .find(|item| item.id == created.id)
+        .expect("created item should be listed");
+    assert_eq!(
+        listed.category_name.as_deref(),
+        Some(category.name.as_str())
+    );
+
+    // Items without a category still show up (LEFT JOIN)
+    let resp = client
+        .post(format!("{}/items", BASE_URL))
+        .json(&json!({ "name": "Uncategorized", "price": 1.0 }))
+        .send()
+        .await
+        .unwrap();
+    let loose: Item = resp.json().await.unwrap();
+    assert_eq!(loose.category_id, None);
+    assert_eq!(loose.category_name, None);
+
+    let resp = client
+        .get(format!("{}/categories", BASE_URL))
+        .send()
+        .await
+        .unwrap();
+    let summaries: Vec<CategorySummary> = resp.json().await.unwrap();
+    let summary = summaries
+        .iter()
+        .find(|c| c.id == category.id)
+        .expect("category should be listed");
+    assert_eq!(summary.item_count, 1);
+}
+
+#[tokio::test]
+#[ignore = "requires running server"]
+async fn test_07_constraint_violations_are_client_errors() {
+    let client = reqwest::Client::new();
+
+    // Foreign key: the category must exist
+    let resp = client
+        .post(format!("{}/items", BASE_URL))
+        .json(&json!({
+            "name": "Orphan",
+            "price": 1.0,
+            "category_id": "no-such-category"
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 400);
+
+    // UNIQUE: same name twice
+    let category = create_category(&client, "Garden").await;
+    let resp = client
+        .post(format!("{}/categories", BASE_URL))
+        .json(&json!({ "name": category.name }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 409);
+}
+
+#[tokio::test]
+#[ignore = "requires running server"]
+async fn test_08_delete_category_needs_cascade() {
+    let client = reqwest::Client::new();
+    let category = create_category(&client, "Kitchen").await;
+
+    let resp = client
+        .post(format!("{}/items", BASE_URL))
+        .json(&json!({
+            "name": "Pan",
+            "price": 30.0,
+            "category_id": category.id
+        }))
+        .send()
+        .await
+        .unwrap();
+    let item: Item = resp.json().await.unwrap();
+
+    // Refused while an item still points at it
+    let resp = client
+        .delete(format!("{}/categories/{}", BASE_URL, category.id))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 409);
+    let resp = client
+        .get(format!("{}/items/{}", BASE_URL, item.id))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200, "Refused delete must not touch items");
+
+    // Cascade removes the category and its items together
+    let resp = client
+        .delete(format!(
+            "{}/categories/{}?cascade=true",
+            BASE_URL, category.id
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 204);
+    let resp = client
+        .get(format!("{}/items/{}", BASE_URL, item.id))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+    let resp = client
+        .get(format!("{}/categories/{}", BASE_URL, category.id))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+}
+
+#[tokio::test]
+#[ignore = "requires running server"]
+async fn test_09_concurrent_reservations_never_oversell() {
+    let client = reqwest::Client::new();
+
+    let resp = client
+        .post(format!("{}/items", BASE_URL))
+        .json(&json!({ "name": "Limited Edition", "price": 99.0, "stock": 10 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+    let item: Item = resp.json().await.unwrap();
+    assert_eq!(item.stock, 10);
+
+    // 50 buyers race for 10 units
+    let mut handles = vec![];
+    for _ in 0..50 {
+        let client = client.clone();
+        let url = format!("{}/items/{}/reserve", BASE_URL, item.id);
+        handles.push(tokio::spawn(async move {
+            client
+                .post(url)
+                .json(&json!({ "quantity": 1 }))
+                .send()
+                .await
+        }));
+    }
+
+    let mut reserved = 0;
+    let mut refused = 0;
+    let mut remaining_seen = vec![];
+    for handle in handles {
+        let resp = handle.await.unwrap().expect("request failed");
+        match resp.status().as_u16() {
+            200 => {
+                let reservation: Reservation = resp.json().await.unwrap();
+                assert_eq!(reservation.reserved, 1);
+                remaining_seen.push(reservation.remaining);
+                reserved += 1;
+            }
+            409 => refused += 1,
+            other => panic!("unexpected status {}", other),
+        }
+    }
+
+    assert_eq!(reserved, 10, "exactly the stock should be sold");
+    assert_eq!(refused, 40);
+    // Every successful reservation saw a different stock level
+    remaining_seen.sort();
+    assert_eq!(remaining_seen, (0..10).collect::<Vec<i64>>());
+
+    let resp = client
+        .get(format!("{}/items/{}", BASE_URL, item.id))
+        .send()
+        .await
+        .unwrap();
+    let after: Item = resp.json().await.unwrap();
+    assert_eq!(after.stock, 0);
+}
+
+#[tokio::test]
+#[ignore = "requires running server"]
+async fn test_10_reserve_rejects_bad_requests() {
+    let client = reqwest::Client::new();
+
+    let resp = client
+        .post(format!("{}/items", BASE_URL))
+        .json(&json!({ "name": "Few Left", "price": 5.0, "stock": 2 }))
+        .send()
+        .await
+        .unwrap();
+    let item: Item = resp.json().await.unwrap();
+    let reserve_url = format!("{}/items/{}/reserve", BASE_URL, item.id);
+
+    let resp = client
+        .post(&reserve_url)
+        .json(&json!({ "quantity": 0 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 400);
+
+    // More than available: refused, stock untouched
+    let resp = client
+        .post(&reserve_url)
+        .json(&json!({ "quantity": 3 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 409);
+    let resp = client
+        .post(&reserve_url)
+        .json(&json!({ "quantity": 2 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let reservation: Reservation = resp.json().await.unwrap();
+    assert_eq!(reservation.remaining, 0);
+
+    let resp = client
+        .post(format!("{}/items/no-such-item/reserve", BASE_URL))
+        .json(&json!({ "quantity": 1 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+}
+
+async fn create_api_key(client: &reqwest::Client, requests: i64, bytes: i64) -> String {
+    let resp = client
+        .post(format!("{}/api-keys", BASE_URL))
+        .json(&json!({ "tenant": "acme", "monthly_requests": requests, "monthly_bytes": bytes }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    body["key"].as_str().unwrap().to_string()
+}
+
+async fn usage(client: &reqwest::Client, key: &str) -> UsageReport {
+    let resp = client
+        .get(format!("{}/usage", BASE_URL))
+        .header("X-Api-Key", key)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    resp.json().await.unwrap()
+}
+
+#[tokio::test]
+#[ignore = "requires running server"]
+async fn test_11_usage_is_metered_per_key() {
+    let client = reqwest::Client::new();
+    let key = create_api_key(&client, 1000, 1_000_000).await;
+    let other = create_api_key(&client, 1000, 1_000_000).await;
+
+    for _ in 0..3 {
+        let resp = client
+            .post(format!("{}/items", BASE_URL))
+            .header("X-Api-Key", &key)
+            .json(&json!({ "name": "Metered", "price": 1.0 }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 201);
+    }
+
+    // Counted at once, whether or not the aggregator has flushed yet
+    let report = usage(&client, &key).await;
+    assert_eq!(report.tenant, "acme");
+    assert_eq!(report.requests, 3, "{:?}", report);
+    assert!(report.bytes > 0, "{:?}", report);
+    assert!(report.unflushed.requests <= report.requests);
+    assert_eq!(report.quota.requests, 1000);
+
+    // Another key, and /usage itself, are not charged to this key
+    assert_eq!(usage(&client, &other).await.requests, 0);
+    assert_eq!(usage(&client, &key).await.requests, 3);
+
+    let resp = client
+        .get(format!("{}/usage", BASE_URL))
+        .header("X-Api-Key", "no-such-key")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 401);
+    let resp = client
+        .get(format!("{}/items", BASE_URL))
+        .header("X-Api-Key", "no-such-key")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 401);
+}
+
+#[tokio::test]
+#[ignore = "requires running server"]
+async fn test_12_quotas_return_429_and_402() {
+    let client = reqwest::Client::new();
+
+    // Request quota: the fourth request is refused until next month
+    let key = create_api_key(&client, 3, 1_000_000).await;
+    for expected in [200, 200, 200, 429] {
+        let resp = client
+            .get(format!("{}/items", BASE_URL))
+            .header("X-Api-Key", &key)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), expected);
+        if expected == 429 {
+            let retry_after: u64 = resp.headers()["retry-after"]
+                .to_str()
+                .unwrap()
+                .parse()
+                .unwrap();
+            assert!(retry_after > 0 && retry_after <= 31 * 86_400);
+        }
+    }
+    // Refused requests are not counted
+    assert_eq!(usage(&client, &key).await.requests, 3);
+
+    // Byte allowance: one listing crosses 10 bytes, the next one is refused
+    let key = create_api_key(&client, 1000, 10).await;
+    for expected in [200, 402] {
+        let resp = client
+            .get(format!("{}/items", BASE_URL))
+            .header("X-Api-Key", &key)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), expected);
+    }
+
+    // Without a key nothing is metered
+    let resp = client
+        .get(format!("{}/items", BASE_URL))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+}
+
+#[tokio::test]
+#[ignore = "requires running server"]
+async fn test_13_usage_survives_the_flush() {
+    let client = reqwest::Client::new();
+    let key = create_api_key(&client, 1000, 1_000_000).await;
+    for _ in 0..5 {
+        client
+            .get(format!("{}/categories", BASE_URL))
+            .header("X-Api-Key", &key)
+            .send()
+            .await
+            .unwrap();
+    }
+    let before = usage(&client, &key).await;
+
+    // Flushed every USAGE_FLUSH_SECS, 5 by default: poll until the counts
+    // have moved to the table, with a margin over the default
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(8);
+    let after = loop {
+        let after = usage(&client, &key).await;
+        if after.unflushed.requests == 0 || std::time::Instant::now() >= deadline {
+            break after;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(250)).await;
+    };
+    assert_eq!(after.unflushed.requests, 0, "flushed: {:?}", after);
+    assert_eq!(after.unflushed.bytes, 0, "flushed: {:?}", after);
+    assert_eq!(after.requests, 5);
+    assert_eq!(after.bytes, before.bytes);
+}
+
+async fn create_job(client: &reqwest::Client, body: serde_json::Value) -> Job {
+    let resp = client
+        .post(format!("{}/jobs", BASE_URL))
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 202);
+    let job: Job = resp.json().await.unwrap();
+    assert_eq!(job.progress, 0);
+    job
+}
+
+async fn get_job(client: &reqwest::Client, id: &str) -> Job {
+    let resp = client
+        .get(format!("{}/jobs/{}", BASE_URL, id))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    resp.json().await.unwrap()
+}
+
+// The whole SSE stream of a job as (event, job) pairs; it ends with the job
+async fn job_events(client: &reqwest::Client, id: &str) -> Vec<(String, Job)> {
+    let resp = client
+        .get(format!("{}/jobs/{}/events", BASE_URL, id))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers()["content-type"], "text/event-stream");
+    let body = tokio::time::timeout(std::time::Duration::from_secs(10), resp.text())
+        .await
+        .expect("the stream ends when the job does")
+        .unwrap();
+
+    body.split("\n\n")
+        .filter_map(|event| {
+            let name = event
+                .lines()
+                .find_map(|line| line.strip_prefix("event: "))?;
+            let data = event.lines().find_map(|line| line.strip_prefix("data: "))?;
+            Some((name.to_string(), serde_json::from_str(data).unwrap()))
+        })
+        .collect()
+}
+
+#[tokio::test]
+#[ignore = "requires running server"]
+async fn test_14_job_is_queued_and_polled_to_completion() {
+    let client = reqwest::Client::new();
+    let resp = client
+        .post(format!("{}/jobs", BASE_URL))
+        .json(&json!({ "steps": 3, "step_ms": 50 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 202);
+    let location = resp.headers()["location"].to_str().unwrap().to_string();
+    let job: Job = resp.json().await.unwrap();
+    assert_eq!(location, format!("/jobs/{}", job.id));
+    assert_eq!(job.steps, 3);
+
+    // Poll until a worker has finished it
+    let mut last = job;
+    for _ in 0..100 {
+        last = get_job(&client, &last.id).await;
+        assert!(last.progress <= 3);
+        if last.status == "done" {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    assert_eq!(last.status, "done", "{:?}", last);
+    assert_eq!(last.progress, 3);
+    assert!(last.error.is_none());
+
+    let resp = client
+        .get(format!("{}/jobs/no-such-job", BASE_URL))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+}
+
+#[tokio::test]
+#[ignore = "requires running server"]
+async fn test_15_job_progress_streams_as_sse() {
+    let client = reqwest::Client::new();
+    let job = create_job(&client, json!({ "steps": 4, "step_ms": 100 })).await;
+
+    let events = job_events(&client, &job.id).await;
+    let (last_event, last) = events.last().expect("at least the final event");
+    assert_eq!(last_event, "done");
+    assert_eq!(last.progress, 4);
+    assert!(
+        events.iter().any(|(event, _)| event == "running"),
+        "{:?}",
+        events
+    );
+    // Progress only moves forward, and the event is named after the status
+    for pair in events.windows(2) {
+        assert!(pair[0].1.progress <= pair[1].1.progress, "{:?}", events);
+    }
+    for (event, job) in &events {
+        assert_eq!(event, &job.status);
+    }
+
+    // Subscribing after the end replays the final state and closes
+    let events = job_events(&client, &job.id).await;
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].0, "done");
+}
+
+#[tokio::test]
+#[ignore = "requires running server"]
+async fn test_16_failing_and_invalid_jobs() {
+    let client = reqwest::Client::new();
+    let job = create_job(&client, json!({ "steps": 5, "step_ms": 20, "fail_at": 3 })).await;
+
+    let events = job_events(&client, &job.id).await;
+    let (last_event, last) = events.last().unwrap();
+    assert_eq!(last_event, "failed");
+    assert_eq!(last.progress, 2, "steps before the failing one completed");
+    assert!(last.error.as_deref().unwrap().contains("step 3"));
+    assert_eq!(get_job(&client, &job.id).await.status, "failed");
+
+    for body in [
+        json!({ "steps": 0 }),
+        json!({ "steps": 3, "fail_at": 4 }),
+        json!({ "steps": 3, "step_ms": -1 }),
+    ] {
+        let resp = client
+            .post(format!("{}/jobs", BASE_URL))
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 400, "{}", body);
+    }
+}
@@ -1,27 +1,38 @@
 //! Lab 4: Prometheus Metrics - Solution
 //!
 //! Export HTTP metrics in Prometheus format.
+//!
+//! Responses are compressed, and size and latency are both labelled with
+//! the encoding, so the cost and the payoff of compression can be compared:
+//!
+//! ```text
+//! http_response_size_bytes_sum{encoding="identity",method="GET",path="/items"}
+//! http_response_size_bytes_sum{encoding="gzip",method="GET",path="/items"}
+//! ```
 
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
     middleware::{self, Next},
-    response::IntoResponse,
+    response::{IntoResponse, Response},
     routing::get,
     Json, Router,
 };
 use prometheus::{
-    Encoder, HistogramOpts, HistogramVec, Opts, Registry, TextEncoder,
+    exponential_buckets, Encoder, HistogramOpts, HistogramVec, Opts, Registry, TextEncoder,
     CounterVec,
 };
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use std::time::Instant;
+use tower_http::compression::CompressionLayer;
 
 // Metrics struct to hold all our metrics
 struct Metrics {
     requests_total: CounterVec,
     request_duration: HistogramVec,
+    response_size: HistogramVec,
     registry: Registry,
 }
 
@@ -39,23 +50,38 @@ impl Metrics {
             .register(Box::new(requests_total.clone()))
             .unwrap();
 
-        // Request duration histogram with method, path labels
+        // Request duration histogram with method, path, encoding labels
         let request_duration = HistogramVec::new(
             HistogramOpts::new(
                 "http_request_duration_seconds",
                 "HTTP request latency in seconds",
             )
             .buckets(vec![0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5]),
-            &["method", "path"],
+            &["method", "path", "encoding"],
         )
         .unwrap();
         registry
             .register(Box::new(request_duration.clone()))
             .unwrap();
 
+        // Response body size as sent, after compression: 64 B .. 1 MiB
+        let response_size = HistogramVec::new(
+            HistogramOpts::new(
+                "http_response_size_bytes",
+                "HTTP response body size in bytes, as sent",
+            )
+            .buckets(exponential_buckets(64.0, 4.0, 8).unwrap()),
+            &["method", "path", "encoding"],
+        )
+        .unwrap();
+        registry
+            .register(Box::new(response_size.clone()))
+            .unwrap();
+
         Metrics {
             requests_total,
             request_duration,
+            response_size,
             registry,
         }
     }
@@ -76,14 +102,33 @@ async fn metrics_middleware(
 
     let response = next.run(request).await;
 
-    let duration = start.elapsed().as_secs_f64();
-    let status = response.status().as_u16().to_string();
+    // The compression layer sits inside this one, so this body is what goes
+    // on the wire. Buffering it to count the bytes means the latency below
+    // includes the time spent compressing.
+    let (parts, body) = response.into_parts();
+    let body = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
 
-    // Record latency
+    let duration = start.elapsed().as_secs_f64();
+    let status = parts.status.as_u16().to_string();
+    let encoding = parts
+        .headers
+        .get(header::CONTENT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("identity")
+        .to_string();
+
+    // Record latency and size
     metrics
         .request_duration
-        .with_label_values(&[&method, &path])
+        .with_label_values(&[&method, &path, &encoding])
         .observe(duration);
+    metrics
+        .response_size
+        .with_label_values(&[&method, &path, &encoding])
+        .observe(body.len() as f64);
 
     // Increment request counter
     metrics
@@ -91,7 +136,7 @@ async fn metrics_middleware(
         .with_label_values(&[&method, &path, &status])
         .inc();
 
-    response
+    Response::from_parts(parts, Body::from(body))
 }
 
 // Normalize path to avoid high cardinality from path parameters
@@ -134,24 +179,26 @@ struct Item {
     name: String,
 }
 
-async fn list_items() -> Json<Vec<Item>> {
+// ?count=N makes the list large enough for compression to matter
+#[derive(Deserialize)]
+struct ListParams {
+    count: Option<u32>,
+}
+
+async fn list_items(Query(params): Query<ListParams>) -> Json<Vec<Item>> {
     // Simulate some work
     tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
 
-    Json(vec![
-        Item {
-            id: 1,
-            name: "Widget".to_string(),
-        },
-        Item {
-            id: 2,
-            name: "Gadget".to_string(),
-        },
-        Item {
-            id: 3,
-            name: "Doohickey".to_string(),
-        },
-    ])
+    let names = ["Widget", "Gadget", "Doohickey"];
+    let count = params.count.unwrap_or(3).min(10_000);
+    Json(
+        (1..=count)
+            .map(|id| Item {
+                id,
+                name: names[(id as usize - 1) % names.len()].to_string(),
+            })
+            .collect(),
+    )
 }
 
 async fn get_item(Path(id): Path<u32>) -> impl IntoResponse {
@@ -189,6 +236,8 @@ async fn main() {
         .route("/health", get(health))
         .route("/items", get(list_items))
         .route("/items/:id", get(get_item))
+        // Inner layer: the metrics middleware sees compressed responses
+        .layer(CompressionLayer::new())
         .layer(middleware::from_fn_with_state(
             metrics.clone(),
             metrics_middleware,
@@ -201,6 +250,8 @@ async fn main() {
     println!("  curl http://localhost:3000/items");
     println!("  curl http://localhost:3000/items/1");
     println!("  curl http://localhost:3000/items/999  # 404");
+    println!("  curl 'http://localhost:3000/items?count=1000'");
+    println!("  curl --compressed 'http://localhost:3000/items?count=1000'");
     println!();
     println!("Then check metrics:");
     println!("  curl http://localhost:3000/metrics");
@@ -233,7 +284,12 @@ mod tests {
         // Should be able to observe histogram
         metrics
             .request_duration
-            .with_label_values(&["GET", "/items"])
+            .with_label_values(&["GET", "/items", "identity"])
             .observe(0.042);
+
+        metrics
+            .response_size
+            .with_label_values(&["GET", "/items", "gzip"])
+            .observe(1500.0);
     }
 }
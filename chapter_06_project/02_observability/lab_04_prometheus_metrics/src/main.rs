@@ -8,16 +8,23 @@
 //! 2. Add labels for method, path, and status
 //! 3. Record metrics in middleware for every request
 //! 4. Expose /metrics endpoint in Prometheus text format
+//! 5. Compress responses, and record response sizes and latency labelled
+//!    with the encoding, to see what compression saves and what it costs
 //!
 //! ## Metrics to Implement
 //! - `http_requests_total` (Counter): Total requests with labels
 //! - `http_request_duration_seconds` (Histogram): Request latency
+//! - `http_response_size_bytes` (Histogram): Body size as sent
 //!
 //! ## Hints
 //! - Use `prometheus::{Counter, CounterVec, Histogram, HistogramVec}`
 //! - Use `lazy_static!` to create global metrics
 //! - Use `prometheus::TextEncoder` to format output
-//! - Labels: method, path, status
+//! - Labels: method, path, status; encoding (`Content-Encoding`, or
+//!   `identity`) on the histograms
+//! - Put `tower_http::compression::CompressionLayer` inside the metrics
+//!   middleware, so the middleware sees the compressed body
+//! - `axum::body::to_bytes` buffers a body so its bytes can be counted
 //!
 //! ## Verification
 //! ```bash
@@ -25,6 +32,8 @@
 //! # Make some requests
 //! curl http://localhost:3000/items
 //! curl http://localhost:3000/items/1
+//! curl 'http://localhost:3000/items?count=1000'
+//! curl --compressed 'http://localhost:3000/items?count=1000'
 //!
 //! # Check metrics
 //! curl http://localhost:3000/metrics
@@ -47,14 +56,16 @@
 //! - [ ] Request counter increments correctly
 //! - [ ] Histogram records latency distribution
 //! - [ ] Labels are correctly applied
+//! - [ ] gzip responses show up smaller in `http_response_size_bytes`
 //!
 //! Check solution/main.rs after completing
 
 use axum::{
-    extract::State,
+    body::Body,
+    extract::{Query, State},
     http::StatusCode,
     middleware::{self, Next},
-    response::IntoResponse,
+    response::{IntoResponse, Response},
     routing::get,
     Json, Router,
 };
@@ -62,14 +73,16 @@ use prometheus::{
     Counter, CounterVec, Encoder, Histogram, HistogramOpts, HistogramVec,
     Opts, Registry, TextEncoder,
 };
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use std::time::Instant;
+use tower_http::compression::CompressionLayer;
 
 // Metrics struct to hold all our metrics
 struct Metrics {
     requests_total: CounterVec,
     request_duration: HistogramVec,
+    response_size: HistogramVec,
     registry: Registry,
 }
 
@@ -85,15 +98,20 @@ impl Metrics {
         // ).unwrap();
         // registry.register(Box::new(requests_total.clone())).unwrap();
 
-        // TODO: Create latency histogram with labels [method, path]
+        // TODO: Create latency histogram with labels [method, path, encoding]
         //
         // let request_duration = HistogramVec::new(
         //     HistogramOpts::new("http_request_duration_seconds", "HTTP request latency")
         //         .buckets(vec![0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0]),
-        //     &["method", "path"]
+        //     &["method", "path", "encoding"]
         // ).unwrap();
         // registry.register(Box::new(request_duration.clone())).unwrap();
 
+        // TODO: Create response size histogram with labels [method, path, encoding]
+        //
+        // Sizes span orders of magnitude: prometheus::exponential_buckets(64.0, 4.0, 8)
+        // gives 64 B .. 1 MiB
+
         todo!()
     }
 }
@@ -107,8 +125,9 @@ type AppState = Arc<Metrics>;
 // 1. Record the start time
 // 2. Extract method and path from request
 // 3. Call next.run(request)
-// 4. Record duration in histogram
-// 5. Increment request counter with labels
+// 4. Buffer the response body to count its bytes
+// 5. Record duration and size in histograms, labelled with Content-Encoding
+// 6. Increment request counter with labels
 async fn metrics_middleware(
     State(metrics): State<AppState>,
     request: axum::extract::Request,
@@ -121,19 +140,26 @@ async fn metrics_middleware(
     // let path = request.uri().path().to_string();
     //
     // let response = next.run(request).await;
+    // let (parts, body) = response.into_parts();
+    // let body = axum::body::to_bytes(body, usize::MAX).await.unwrap();
     //
     // let duration = start.elapsed().as_secs_f64();
-    // let status = response.status().as_u16().to_string();
+    // let status = parts.status.as_u16().to_string();
+    // let encoding = /* Content-Encoding header, or "identity" */;
     //
     // metrics.request_duration
-    //     .with_label_values(&[&method, &path])
+    //     .with_label_values(&[&method, &path, &encoding])
     //     .observe(duration);
     //
+    // metrics.response_size
+    //     .with_label_values(&[&method, &path, &encoding])
+    //     .observe(body.len() as f64);
+    //
     // metrics.requests_total
     //     .with_label_values(&[&method, &path, &status])
     //     .inc();
     //
-    // response
+    // Response::from_parts(parts, Body::from(body))
 
     todo!()
 }
@@ -164,14 +190,18 @@ struct Item {
     name: String,
 }
 
-async fn list_items() -> Json<Vec<Item>> {
+// ?count=N makes the list large enough for compression to matter
+#[derive(Deserialize)]
+struct ListParams {
+    count: Option<u32>,
+}
+
+async fn list_items(Query(params): Query<ListParams>) -> Json<Vec<Item>> {
     // Simulate some work
     tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
 
-    Json(vec![
-        Item { id: 1, name: "Widget".to_string() },
-        Item { id: 2, name: "Gadget".to_string() },
-    ])
+    let count = params.count.unwrap_or(2).min(10_000);
+    Json((1..=count).map(|id| Item { id, name: format!("Item {}", id) }).collect())
 }
 
 async fn get_item(axum::extract::Path(id): axum::extract::Path<u32>) -> impl IntoResponse {
@@ -201,6 +231,7 @@ async fn main() {
     //     .route("/items", get(list_items))
     //     .route("/items/:id", get(get_item))
     //     .route("/metrics", get(metrics_handler))
+    //     .layer(CompressionLayer::new())
     //     .layer(middleware::from_fn_with_state(metrics.clone(), metrics_middleware))
     //     .with_state(metrics);
 
@@ -232,3 +232,68 @@ async fn test_07_histogram_buckets() {
 
     println!("Found {} histogram buckets", bucket_count);
 }
+
+#[tokio::test]
+#[ignore = "requires running server"]
+async fn test_08_response_size_by_encoding() {
+    let client = reqwest::Client::new();
+
+    // Same large list, once plain and once gzipped. reqwest has no
+    // decompression features here, so Accept-Encoding is passed through.
+    let plain = client
+        .get(format!("{}/items?count=500", BASE_URL))
+        .send()
+        .await
+        .unwrap();
+    assert!(plain.headers().get("content-encoding").is_none());
+
+    let gzipped = client
+        .get(format!("{}/items?count=500", BASE_URL))
+        .header("Accept-Encoding", "gzip")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(
+        gzipped.headers().get("content-encoding").unwrap(),
+        "gzip",
+        "Large responses should be compressed"
+    );
+
+    let metrics = client
+        .get(format!("{}/metrics", BASE_URL))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    let size_sum = |encoding: &str| -> f64 {
+        let label = format!("encoding=\"{}\"", encoding);
+        metrics
+            .lines()
+            .find(|line| {
+                line.starts_with("http_response_size_bytes_sum")
+                    && line.contains(&label)
+                    && line.contains("path=\"/items\"")
+            })
+            .and_then(|line| line.rsplit(' ').next())
+            .and_then(|value| value.parse().ok())
+            .unwrap_or_else(|| panic!("No response size for {}", encoding))
+    };
+
+    let identity = size_sum("identity");
+    let gzip = size_sum("gzip");
+    assert!(
+        gzip < identity,
+        "gzip bytes ({}) should be fewer than identity bytes ({})",
+        gzip,
+        identity
+    );
+    assert!(
+        metrics.contains("http_request_duration_seconds_count{encoding=\"gzip\""),
+        "Latency should be labelled by encoding"
+    );
+
+    println!("/items bytes sent: identity {}, gzip {}", identity, gzip);
+}
@@ -0,0 +1,187 @@
+//! Lab 6: Correlating Logs, Traces and Metrics
+//!
+//! ## Goal
+//! Emit a log line, a trace span and a metric sample for the same simulated
+//! request, all tagged with the same request_id/trace_id, so the three
+//! pillars of observability can be joined after the fact.
+//!
+//! ## Requirements
+//! 1. Each simulated request gets a request_id (UUID) and a trace_id (32 hex chars)
+//! 2. Logs are JSON lines written to `<dir>/logs.jsonl`, carrying both ids via the request span
+//! 3. A custom `Layer` writes one JSON line per closed span to `<dir>/traces.jsonl`
+//!    (trace_id, span_id, parent_span_id, name, duration_us); child spans inherit trace_id
+//! 4. A latency histogram is written to `<dir>/metrics.txt` in OpenMetrics format,
+//!    with the trace_id attached to each bucket as an exemplar
+//! 5. Print one `request_id=... trace_id=...` line per request to stdout
+//!
+//! ## Hints
+//! - `tracing_subscriber::fmt::layer().json().with_span_list(true)` puts span fields on every log line
+//! - Implement `Layer::on_new_span` / `Layer::on_close` and store state in `span.extensions_mut()`
+//! - Read span fields with a `tracing::field::Visit` implementation
+//! - Exemplar syntax: `name_bucket{le="0.01"} 3 # {trace_id="..."} 0.0042`
+//! - Never put request_id in a metric label: that's unbounded cardinality
+//!
+//! ## Verification
+//! ```bash
+//! cargo test
+//! cargo run --bin emit_signals -- --requests 5
+//! cargo run --bin correlate -- <request_id from the output>
+//! ```
+//!
+//! ## Acceptance Criteria
+//! - [ ] Every request_id appears in logs.jsonl
+//! - [ ] Every trace_id appears in traces.jsonl with a root span and child spans
+//! - [ ] metrics.txt carries trace_id exemplars and ends with `# EOF`
+//! - [ ] `correlate` prints logs, the span tree and exemplars for one request
+//!
+//! Check solution/main.rs after completing
+
+use clap::Parser;
+use rand::Rng;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::Subscriber;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+use uuid::Uuid;
+
+#[derive(Parser, Debug)]
+#[command(name = "emit_signals")]
+#[command(about = "Emit correlated logs, traces and metrics for simulated requests")]
+struct Args {
+    /// Number of simulated requests
+    #[arg(short, long, default_value = "5")]
+    requests: usize,
+
+    /// Directory the signal files are written to
+    #[arg(short, long, default_value = "signals")]
+    dir: PathBuf,
+}
+
+const ROUTES: [&str; 3] = ["/items", "/items/:id", "/health"];
+
+/// Per-span bookkeeping stored in the span's extensions
+struct SpanRecord {
+    trace_id: String,
+    span_id: String,
+    parent_span_id: Option<String>,
+    request_id: Option<String>,
+    start: Instant,
+}
+
+/// Collects the correlation fields declared on a span
+#[derive(Default)]
+struct IdVisitor {
+    trace_id: Option<String>,
+    request_id: Option<String>,
+}
+
+impl Visit for IdVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        // TODO: Remember "trace_id" and "request_id" fields
+        todo!("Implement IdVisitor::record_debug")
+    }
+}
+
+/// Writes one JSON line per closed span
+struct SpanExporter {
+    out: Mutex<File>,
+}
+
+impl<S> Layer<S> for SpanExporter
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        // TODO: Build a SpanRecord and insert it into the span's extensions
+        //
+        // Suggested steps:
+        // 1. Record attrs with an IdVisitor
+        // 2. If the span has a parent, copy its trace_id/request_id
+        // 3. Otherwise use the trace_id field (or generate one)
+        todo!("Implement SpanExporter::on_new_span")
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        // TODO: Write the SpanRecord as a JSON line with its duration
+        todo!("Implement SpanExporter::on_close")
+    }
+}
+
+/// Latency histogram that remembers the latest trace_id per bucket
+struct Histogram {
+    name: &'static str,
+    bounds: Vec<f64>,
+    series: BTreeMap<String, Series>,
+}
+
+struct Series {
+    counts: Vec<u64>,
+    exemplars: Vec<Option<(String, f64)>>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(name: &'static str, bounds: Vec<f64>) -> Self {
+        Self {
+            name,
+            bounds,
+            series: BTreeMap::new(),
+        }
+    }
+
+    fn observe(&mut self, route: &str, value: f64, trace_id: &str) {
+        // TODO: Update cumulative bucket counts, sum, count and the exemplar
+        todo!("Implement Histogram::observe")
+    }
+
+    /// Render in OpenMetrics text format (exemplars follow a `#`)
+    fn render(&self) -> String {
+        // TODO: One `_bucket` line per bound plus +Inf, then `_sum`, `_count`, `# EOF`
+        todo!("Implement Histogram::render")
+    }
+}
+
+struct RequestSummary {
+    request_id: String,
+    trace_id: String,
+    route: &'static str,
+    status: u16,
+    duration: Duration,
+}
+
+/// Handle one fake request, emitting a log line, spans and a metric sample
+fn simulate_request(histogram: &mut Histogram) -> RequestSummary {
+    // TODO: Implement
+    //
+    // Suggested steps:
+    // 1. Generate request_id (Uuid::new_v4()) and trace_id (Uuid::new_v4().simple())
+    // 2. Enter an "http_request" span with both ids as fields
+    // 3. Enter child spans "db_query" and "serialize_response", sleep a little in each
+    // 4. Observe the duration in the histogram with the trace_id
+    // 5. Log "Request completed" with status and duration_ms
+    todo!("Implement simulate_request")
+}
+
+fn init_tracing(logs: File, traces: File) {
+    // TODO: registry() + fmt JSON layer writing to `logs` + SpanExporter writing to `traces`
+    todo!("Implement init_tracing")
+}
+
+fn main() {
+    let args = Args::parse();
+
+    fs::create_dir_all(&args.dir).expect("Failed to create output directory");
+
+    // TODO: Create the three files, init tracing, simulate requests,
+    // print one line per request and write metrics.txt
+
+    todo!("Implement main")
+}
@@ -0,0 +1,392 @@
+//! Lab 6: Correlating Logs, Traces and Metrics - Solution
+//!
+//! Emit all three observability signals for the same simulated request,
+//! tagged with a shared request_id / trace_id so they can be joined later.
+
+use clap::Parser;
+use rand::Rng;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::{info, info_span, warn, Subscriber};
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
+use uuid::Uuid;
+
+#[derive(Parser, Debug)]
+#[command(name = "emit_signals")]
+#[command(about = "Emit correlated logs, traces and metrics for simulated requests")]
+struct Args {
+    /// Number of simulated requests
+    #[arg(short, long, default_value = "5")]
+    requests: usize,
+
+    /// Directory the signal files are written to
+    #[arg(short, long, default_value = "signals")]
+    dir: PathBuf,
+}
+
+const ROUTES: [&str; 3] = ["/items", "/items/:id", "/health"];
+
+// ============================================================
+// Traces: a tiny span exporter
+// ============================================================
+
+/// Per-span bookkeeping stored in the span's extensions
+struct SpanRecord {
+    trace_id: String,
+    span_id: String,
+    parent_span_id: Option<String>,
+    request_id: Option<String>,
+    start: Instant,
+}
+
+/// Collects the correlation fields declared on a span
+#[derive(Default)]
+struct IdVisitor {
+    trace_id: Option<String>,
+    request_id: Option<String>,
+}
+
+impl Visit for IdVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "trace_id" => self.trace_id = Some(value.to_string()),
+            "request_id" => self.request_id = Some(value.to_string()),
+            _ => {}
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        // `%value` fields arrive here as Display wrapped in Debug
+        self.record_str(field, &format!("{:?}", value));
+    }
+}
+
+/// Writes one JSON line per closed span, like an OpenTelemetry exporter.
+///
+/// Child spans inherit trace_id from their parent, so only the root span
+/// has to declare it.
+struct SpanExporter {
+    out: Mutex<File>,
+}
+
+impl<S> Layer<S> for SpanExporter
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let span = ctx.span(id).expect("span must exist in registry");
+
+        let mut ids = IdVisitor::default();
+        attrs.record(&mut ids);
+
+        let parent = span.parent().and_then(|parent| {
+            parent.extensions().get::<SpanRecord>().map(|rec| {
+                (
+                    rec.trace_id.clone(),
+                    rec.span_id.clone(),
+                    rec.request_id.clone(),
+                )
+            })
+        });
+
+        let (trace_id, parent_span_id, request_id) = match parent {
+            Some((trace_id, span_id, request_id)) => (trace_id, Some(span_id), request_id),
+            None => (
+                ids.trace_id.clone().unwrap_or_else(new_trace_id),
+                None,
+                ids.request_id.clone(),
+            ),
+        };
+
+        span.extensions_mut().insert(SpanRecord {
+            trace_id,
+            span_id: new_span_id(),
+            parent_span_id,
+            request_id: ids.request_id.or(request_id),
+            start: Instant::now(),
+        });
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else { return };
+        let extensions = span.extensions();
+        let Some(rec) = extensions.get::<SpanRecord>() else {
+            return;
+        };
+
+        let line = serde_json::json!({
+            "trace_id": rec.trace_id,
+            "span_id": rec.span_id,
+            "parent_span_id": rec.parent_span_id,
+            "request_id": rec.request_id,
+            "name": span.name(),
+            "duration_us": rec.start.elapsed().as_micros() as u64,
+        });
+
+        let mut out = self.out.lock().unwrap();
+        let _ = writeln!(out, "{}", line);
+    }
+}
+
+/// 128-bit trace id, hex encoded (W3C traceparent format)
+fn new_trace_id() -> String {
+    Uuid::new_v4().simple().to_string()
+}
+
+/// 64-bit span id, hex encoded
+fn new_span_id() -> String {
+    format!("{:016x}", rand::thread_rng().gen::<u64>())
+}
+
+// ============================================================
+// Metrics: histogram with exemplars
+// ============================================================
+
+/// Latency histogram that remembers the latest trace_id per bucket.
+///
+/// Prometheus aggregates away individual requests, so the only link from a
+/// metric back to a trace is an exemplar: one sampled observation that
+/// carries its trace_id next to the bucket count.
+struct Histogram {
+    name: &'static str,
+    bounds: Vec<f64>,
+    series: BTreeMap<String, Series>,
+}
+
+struct Series {
+    counts: Vec<u64>,
+    exemplars: Vec<Option<(String, f64)>>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(name: &'static str, bounds: Vec<f64>) -> Self {
+        Self {
+            name,
+            bounds,
+            series: BTreeMap::new(),
+        }
+    }
+
+    fn observe(&mut self, route: &str, value: f64, trace_id: &str) {
+        let buckets = self.bounds.len() + 1; // last bucket is +Inf
+        let series = self
+            .series
+            .entry(route.to_string())
+            .or_insert_with(|| Series {
+                counts: vec![0; buckets],
+                exemplars: vec![None; buckets],
+                sum: 0.0,
+                count: 0,
+            });
+
+        let idx = self
+            .bounds
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or(self.bounds.len());
+
+        // Buckets are cumulative: every bucket >= idx sees this observation
+        for count in &mut series.counts[idx..] {
+            *count += 1;
+        }
+        series.exemplars[idx] = Some((trace_id.to_string(), value));
+        series.sum += value;
+        series.count += 1;
+    }
+
+    /// Render in OpenMetrics text format (exemplars follow a `#`)
+    fn render(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "# TYPE {} histogram", self.name);
+
+        for (route, series) in &self.series {
+            for (i, count) in series.counts.iter().enumerate() {
+                let le = match self.bounds.get(i) {
+                    Some(bound) => bound.to_string(),
+                    None => "+Inf".to_string(),
+                };
+                let _ = write!(
+                    out,
+                    "{}_bucket{{route=\"{}\",le=\"{}\"}} {}",
+                    self.name, route, le, count
+                );
+                if let Some((trace_id, value)) = &series.exemplars[i] {
+                    let _ = write!(out, " # {{trace_id=\"{}\"}} {:.6}", trace_id, value);
+                }
+                out.push('\n');
+            }
+            let _ = writeln!(
+                out,
+                "{}_sum{{route=\"{}\"}} {:.6}",
+                self.name, route, series.sum
+            );
+            let _ = writeln!(
+                out,
+                "{}_count{{route=\"{}\"}} {}",
+                self.name, route, series.count
+            );
+        }
+
+        out.push_str("# EOF\n");
+        out
+    }
+}
+
+// ============================================================
+// Simulated request
+// ============================================================
+
+struct RequestSummary {
+    request_id: String,
+    trace_id: String,
+    route: &'static str,
+    status: u16,
+    duration: Duration,
+}
+
+/// Handle one fake request, emitting a log line, spans and a metric sample
+fn simulate_request(histogram: &mut Histogram) -> RequestSummary {
+    let mut rng = rand::thread_rng();
+    let request_id = Uuid::new_v4().to_string();
+    let trace_id = new_trace_id();
+    let route = ROUTES[rng.gen_range(0..ROUTES.len())];
+
+    let span = info_span!(
+        "http_request",
+        request_id = %request_id,
+        trace_id = %trace_id,
+        route,
+    );
+    let _entered = span.enter();
+    let start = Instant::now();
+
+    info!("Request started");
+
+    {
+        let _db = info_span!("db_query", table = "items").entered();
+        let rows = rng.gen_range(0..5);
+        thread::sleep(Duration::from_millis(rng.gen_range(1..20)));
+        info!(rows, "Query finished");
+    }
+
+    {
+        let _serialize = info_span!("serialize_response").entered();
+        thread::sleep(Duration::from_millis(rng.gen_range(0..3)));
+    }
+
+    let status = if rng.gen_bool(0.2) { 404 } else { 200 };
+    let duration = start.elapsed();
+    let duration_ms = duration.as_millis() as u64;
+
+    // Same trace_id goes into the metric as an exemplar
+    histogram.observe(route, duration.as_secs_f64(), &trace_id);
+
+    if status >= 400 {
+        warn!(status, duration_ms, "Request completed");
+    } else {
+        info!(status, duration_ms, "Request completed");
+    }
+
+    RequestSummary {
+        request_id,
+        trace_id,
+        route,
+        status,
+        duration,
+    }
+}
+
+fn init_tracing(logs: File, traces: File) {
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .json()
+                .with_writer(Arc::new(logs))
+                .with_current_span(false)
+                .with_span_list(true)
+                .flatten_event(true),
+        )
+        .with(SpanExporter {
+            out: Mutex::new(traces),
+        })
+        .init();
+}
+
+fn main() {
+    let args = Args::parse();
+
+    if let Err(e) = fs::create_dir_all(&args.dir) {
+        eprintln!("Error: cannot create {}: {}", args.dir.display(), e);
+        std::process::exit(1);
+    }
+
+    let create = |name: &str| {
+        let path = args.dir.join(name);
+        File::create(&path).unwrap_or_else(|e| {
+            eprintln!("Error: cannot create {}: {}", path.display(), e);
+            std::process::exit(1);
+        })
+    };
+
+    init_tracing(create("logs.jsonl"), create("traces.jsonl"));
+
+    let mut histogram = Histogram::new(
+        "http_request_duration_seconds",
+        vec![0.005, 0.01, 0.025, 0.05, 0.1],
+    );
+
+    for _ in 0..args.requests {
+        let summary = simulate_request(&mut histogram);
+        println!(
+            "request_id={} trace_id={} route={} status={} duration_ms={}",
+            summary.request_id,
+            summary.trace_id,
+            summary.route,
+            summary.status,
+            summary.duration.as_millis()
+        );
+    }
+
+    let metrics_path = args.dir.join("metrics.txt");
+    if let Err(e) = fs::write(&metrics_path, histogram.render()) {
+        eprintln!("Error: cannot write {}: {}", metrics_path.display(), e);
+        std::process::exit(1);
+    }
+
+    println!();
+    println!("Signals written to {}/", args.dir.display());
+    println!("  logs.jsonl    structured log lines");
+    println!("  traces.jsonl  one line per closed span");
+    println!("  metrics.txt   OpenMetrics histogram with trace_id exemplars");
+    println!();
+    println!("Join them with: cargo run --bin correlate -- <request_id>");
+}
+
+// Key concepts demonstrated:
+//
+// 1. ONE ID, THREE SIGNALS:
+//    - Logs carry request_id/trace_id through the enclosing span
+//    - Spans inherit trace_id from their parent
+//    - Metrics carry trace_id only as exemplars
+//
+// 2. WHY METRICS ARE DIFFERENT:
+//    - Putting request_id in a label would explode cardinality
+//    - Exemplars attach a sample trace to a bucket instead
+//    - Not every request has an exemplar - only the latest per bucket
+//
+// 3. SPAN EXPORTING:
+//    - A Layer sees on_new_span / on_close for every span
+//    - Timing + parent links are all a trace backend needs
@@ -0,0 +1,118 @@
+//! Signal correlation tool
+//!
+//! Grep-joins the files written by `emit_signals`: given a request_id or
+//! trace_id, print every log line, span and metric exemplar that belongs to
+//! the same request.
+//!
+//! ```bash
+//! cargo run --bin correlate -- <request_id|trace_id> [--dir signals]
+//! ```
+
+use clap::Parser;
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Parser, Debug)]
+#[command(name = "correlate")]
+#[command(about = "Join logs, traces and metrics by request_id or trace_id")]
+struct Args {
+    /// request_id or trace_id to look up
+    id: String,
+
+    /// Directory containing logs.jsonl, traces.jsonl and metrics.txt
+    #[arg(short, long, default_value = "signals")]
+    dir: PathBuf,
+}
+
+fn read_lines(path: &Path) -> Vec<String> {
+    match fs::read_to_string(path) {
+        Ok(content) => content.lines().map(String::from).collect(),
+        Err(e) => {
+            eprintln!("Error: cannot read {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Find the trace_id recorded alongside `id` in any span list of a log line
+fn trace_id_for(logs: &[String], id: &str) -> Option<String> {
+    logs.iter()
+        .filter(|line| line.contains(id))
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .find_map(|log| {
+            log["spans"]
+                .as_array()?
+                .iter()
+                .find_map(|span| span["trace_id"].as_str().map(String::from))
+        })
+}
+
+/// Print spans as an indented tree, children below their parent
+fn print_span_tree(spans: &[Value], parent: Option<&str>, depth: usize) {
+    for span in spans {
+        if span["parent_span_id"].as_str() != parent {
+            continue;
+        }
+        let duration_ms = span["duration_us"].as_u64().unwrap_or(0) as f64 / 1000.0;
+        println!(
+            "  {}{} ({:.2}ms) span_id={}",
+            "  ".repeat(depth),
+            span["name"].as_str().unwrap_or("?"),
+            duration_ms,
+            span["span_id"].as_str().unwrap_or("?")
+        );
+        print_span_tree(spans, span["span_id"].as_str(), depth + 1);
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let logs = read_lines(&args.dir.join("logs.jsonl"));
+    let traces = read_lines(&args.dir.join("traces.jsonl"));
+    let metrics = read_lines(&args.dir.join("metrics.txt"));
+
+    // The id may be either one; resolve to trace_id for traces and metrics
+    let trace_id = trace_id_for(&logs, &args.id).unwrap_or_else(|| args.id.clone());
+
+    let matching_logs: Vec<&String> = logs
+        .iter()
+        .filter(|line| line.contains(&args.id) || line.contains(&trace_id))
+        .collect();
+    let spans: Vec<Value> = traces
+        .iter()
+        .filter(|line| line.contains(&trace_id))
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+    let exemplars: Vec<&String> = metrics
+        .iter()
+        .filter(|line| line.contains(&trace_id))
+        .collect();
+
+    if matching_logs.is_empty() && spans.is_empty() && exemplars.is_empty() {
+        eprintln!("No signals found for {}", args.id);
+        std::process::exit(1);
+    }
+
+    println!("trace_id={}", trace_id);
+    println!();
+
+    println!("== logs ({}) ==", matching_logs.len());
+    for line in &matching_logs {
+        println!("  {}", line);
+    }
+    println!();
+
+    println!("== trace ({} spans) ==", spans.len());
+    print_span_tree(&spans, None, 0);
+    println!();
+
+    println!("== metrics ({} exemplars) ==", exemplars.len());
+    if exemplars.is_empty() {
+        println!("  (no exemplar - a later request in the same bucket replaced it)");
+    }
+    for line in &exemplars {
+        println!("  {}", line);
+    }
+}
@@ -0,0 +1,154 @@
+//! Lab 6: Signal Correlation Tests
+//!
+//! Run with: cargo test
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn run_bin(bin: &str, args: &[&str]) -> (String, String, bool) {
+    let output = Command::new("cargo")
+        .args(["run", "--quiet", "--bin", bin, "--"])
+        .args(args)
+        .output()
+        .expect("Failed to execute program");
+
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    let success = output.status.success();
+
+    (stdout, stderr, success)
+}
+
+/// Fresh output directory per test so tests can run in parallel
+fn signal_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "signal_correlation_{}_{}",
+        name,
+        std::process::id()
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+/// Run the emitter and return (request_id, trace_id) pairs from its output
+fn emit(dir: &Path, requests: usize) -> Vec<(String, String)> {
+    let (stdout, stderr, success) = run_bin(
+        "emit_signals",
+        &[
+            "--requests",
+            &requests.to_string(),
+            "--dir",
+            dir.to_str().unwrap(),
+        ],
+    );
+    assert!(success, "emit_signals should succeed: {}", stderr);
+
+    let ids: Vec<(String, String)> = stdout
+        .lines()
+        .filter(|line| line.starts_with("request_id="))
+        .map(|line| {
+            let field = |key: &str| {
+                line.split_whitespace()
+                    .find_map(|part| part.strip_prefix(key))
+                    .expect("missing field in summary line")
+                    .to_string()
+            };
+            (field("request_id="), field("trace_id="))
+        })
+        .collect();
+
+    assert_eq!(ids.len(), requests, "One summary line per request expected");
+    ids
+}
+
+#[test]
+fn test_01_writes_three_signal_files() {
+    let dir = signal_dir("files");
+    emit(&dir, 3);
+
+    for file in ["logs.jsonl", "traces.jsonl", "metrics.txt"] {
+        assert!(dir.join(file).exists(), "{} should be written", file);
+    }
+}
+
+#[test]
+fn test_02_ids_shared_by_logs_and_traces() {
+    let dir = signal_dir("shared");
+    let ids = emit(&dir, 4);
+
+    let logs = fs::read_to_string(dir.join("logs.jsonl")).unwrap();
+    let traces = fs::read_to_string(dir.join("traces.jsonl")).unwrap();
+
+    for (request_id, trace_id) in &ids {
+        assert!(
+            logs.contains(request_id),
+            "logs should mention {}",
+            request_id
+        );
+        assert!(logs.contains(trace_id), "logs should mention {}", trace_id);
+
+        let spans = traces.lines().filter(|l| l.contains(trace_id)).count();
+        assert!(
+            spans >= 3,
+            "trace {} should have root + child spans, got {}",
+            trace_id,
+            spans
+        );
+    }
+}
+
+#[test]
+fn test_03_metrics_have_exemplars() {
+    let dir = signal_dir("metrics");
+    let ids = emit(&dir, 1);
+
+    let metrics = fs::read_to_string(dir.join("metrics.txt")).unwrap();
+    let (_, trace_id) = &ids[0];
+
+    assert!(
+        metrics.contains(&format!("# {{trace_id=\"{}\"}}", trace_id)),
+        "single request should appear as an exemplar"
+    );
+    assert!(
+        metrics.contains("le=\"+Inf\""),
+        "histogram needs a +Inf bucket"
+    );
+    assert!(
+        metrics.trim_end().ends_with("# EOF"),
+        "OpenMetrics ends with # EOF"
+    );
+}
+
+#[test]
+fn test_04_correlate_joins_by_request_id() {
+    let dir = signal_dir("join");
+    let ids = emit(&dir, 2);
+    let (request_id, trace_id) = &ids[1];
+
+    let (stdout, stderr, success) =
+        run_bin("correlate", &[request_id, "--dir", dir.to_str().unwrap()]);
+
+    assert!(success, "correlate should succeed: {}", stderr);
+    assert!(stdout.contains(&format!("trace_id={}", trace_id)));
+    assert!(stdout.contains("== logs"));
+    assert!(
+        stdout.contains("http_request"),
+        "span tree should show root span"
+    );
+    assert!(
+        stdout.contains("db_query"),
+        "span tree should show child span"
+    );
+    assert!(stdout.contains("== metrics (1 exemplars)"));
+}
+
+#[test]
+fn test_05_correlate_unknown_id_fails() {
+    let dir = signal_dir("unknown");
+    emit(&dir, 1);
+
+    let (_, _, success) = run_bin("correlate", &["no-such-id", "--dir", dir.to_str().unwrap()]);
+
+    assert!(!success, "unknown id should exit with an error");
+}
@@ -1,8 +1,16 @@
 //! Lab 5: Load Testing and Analysis - Solution
 //!
 //! A command-line HTTP load testing tool.
+//!
+//! With `--memory-url` it also polls the target server's memory sidecar once
+//! a second and prints memory next to latency, to spot leaks under load.
+//!
+//! `--no-keepalive` opens a new connection for every request, and
+//! `--compare` runs both ways back to back and puts them side by side:
+//! throughput, latency, and the local ports left in TIME_WAIT.
 
 use clap::Parser;
+use serde::Deserialize;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
@@ -23,12 +31,55 @@ struct Args {
     /// Test duration in seconds
     #[arg(short, long, default_value = "10")]
     duration: u64,
+
+    /// Memory sidecar of the target server, e.g. http://localhost:3001/memory
+    #[arg(short, long)]
+    memory_url: Option<String>,
+
+    /// A new connection for every request instead of reusing them
+    #[arg(long)]
+    no_keepalive: bool,
+
+    /// Run with keep-alive, then without, and compare the two
+    #[arg(long, conflicts_with = "no_keepalive")]
+    compare: bool,
 }
 
 struct Stats {
     successful: AtomicU64,
     failed: AtomicU64,
     latencies: Mutex<Vec<Duration>>,
+    /// Latencies since the last per-second tick
+    window: Mutex<Vec<Duration>>,
+}
+
+/// What the target server's sidecar reports
+#[derive(Deserialize, Clone, Copy)]
+struct MemorySample {
+    rss_bytes: Option<u64>,
+    live_bytes: u64,
+    allocations: u64,
+}
+
+/// One second of the test
+struct Tick {
+    second: u64,
+    requests: usize,
+    p50: Duration,
+    p99: Duration,
+    memory: Option<MemorySample>,
+    /// Sockets to the target port in TIME_WAIT
+    time_wait: Option<usize>,
+}
+
+/// One run, for the --compare summary
+struct Summary {
+    rps: f64,
+    p50: Duration,
+    p99: Duration,
+    failed: u64,
+    /// TIME_WAIT sockets before the run and the most seen during it
+    time_wait: Option<(usize, usize)>,
 }
 
 impl Stats {
@@ -37,12 +88,19 @@ impl Stats {
             successful: AtomicU64::new(0),
             failed: AtomicU64::new(0),
             latencies: Mutex::new(Vec::with_capacity(100_000)),
+            window: Mutex::new(Vec::new()),
         }
     }
 
     async fn record_success(&self, latency: Duration) {
         self.successful.fetch_add(1, Ordering::Relaxed);
         self.latencies.lock().await.push(latency);
+        self.window.lock().await.push(latency);
+    }
+
+    /// Latencies recorded since the previous call
+    async fn take_window(&self) -> Vec<Duration> {
+        std::mem::take(&mut *self.window.lock().await)
     }
 
     fn record_failure(&self) {
@@ -65,16 +123,24 @@ async fn worker(
 ) {
     while Instant::now() < end_time {
         let start = Instant::now();
-        let result = client.get(&url).send().await;
+        // Read the whole body: only then does the connection go back to
+        // the pool, otherwise keep-alive never reuses it
+        let result = match client.get(&url).send().await {
+            Ok(resp) => {
+                let status = resp.status();
+                resp.bytes().await.map(|_| status)
+            }
+            Err(e) => Err(e),
+        };
         let latency = start.elapsed();
 
         match result {
-            Ok(resp) if resp.status().is_success() => {
+            Ok(status) if status.is_success() => {
                 stats.record_success(latency).await;
             }
-            Ok(resp) => {
+            Ok(status) => {
                 // Non-success status code
-                eprintln!("Request failed with status: {}", resp.status());
+                eprintln!("Request failed with status: {}", status);
                 stats.record_failure();
             }
             Err(e) => {
@@ -104,6 +170,180 @@ fn format_duration(d: Duration) -> String {
     }
 }
 
+async fn fetch_memory(client: &reqwest::Client, url: &str) -> Option<MemorySample> {
+    client.get(url).send().await.ok()?.json().await.ok()
+}
+
+/// Least-squares slope of (x, y) points: how fast y grows per unit of x
+fn trend(points: &[(f64, f64)]) -> f64 {
+    let n = points.len() as f64;
+    if points.len() < 2 {
+        return 0.0;
+    }
+    let mean_x = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let covariance: f64 = points.iter().map(|p| (p.0 - mean_x) * (p.1 - mean_y)).sum();
+    let variance: f64 = points.iter().map(|p| (p.0 - mean_x).powi(2)).sum();
+    if variance == 0.0 {
+        0.0
+    } else {
+        covariance / variance
+    }
+}
+
+/// Port the URL points at, default ports included
+fn target_port(url: &str) -> Option<u16> {
+    reqwest::Url::parse(url).ok()?.port_or_known_default()
+}
+
+/// Sockets in TIME_WAIT whose remote port is `port`: the client side of
+/// connections we closed. Each holds a local port for 60s (Linux).
+/// `None` where /proc/net/tcp doesn't exist.
+fn time_wait_count(port: u16) -> Option<usize> {
+    let mut count = None;
+    for path in ["/proc/net/tcp", "/proc/net/tcp6"] {
+        let Ok(table) = std::fs::read_to_string(path) else {
+            continue;
+        };
+        // sl local_address rem_address st ...; addresses are HEXIP:HEXPORT,
+        // state 06 is TIME_WAIT
+        let matching = table
+            .lines()
+            .skip(1)
+            .map(|line| line.split_whitespace().collect::<Vec<_>>())
+            .filter(|fields| fields.len() > 3 && fields[3] == "06")
+            .filter(|fields| {
+                fields[2]
+                    .rsplit(':')
+                    .next()
+                    .and_then(|hex| u16::from_str_radix(hex, 16).ok())
+                    == Some(port)
+            })
+            .count();
+        count = Some(count.unwrap_or(0) + matching);
+    }
+    count
+}
+
+/// How many local ports outgoing connections can use
+fn ephemeral_ports() -> Option<u32> {
+    let range = std::fs::read_to_string("/proc/sys/net/ipv4/ip_local_port_range").ok()?;
+    let mut bounds = range.split_whitespace().map(|n| n.parse::<u32>());
+    let (Some(Ok(low)), Some(Ok(high))) = (bounds.next(), bounds.next()) else {
+        return None;
+    };
+    Some(high.saturating_sub(low) + 1)
+}
+
+fn format_bytes(bytes: f64) -> String {
+    if bytes.abs() < 1024.0 * 1024.0 {
+        format!("{:.1} KB", bytes / 1024.0)
+    } else {
+        format!("{:.1} MB", bytes / (1024.0 * 1024.0))
+    }
+}
+
+/// `value / max` as a bar of up to `width` characters
+fn bar(value: f64, max: f64, width: usize) -> String {
+    let len = if max > 0.0 { (value / max * width as f64).round() as usize } else { 0 };
+    format!("{:<width$}", "#".repeat(len.min(width)), width = width)
+}
+
+fn display_timeline(timeline: &[Tick]) {
+    let memory: Vec<(u64, MemorySample)> = timeline
+        .iter()
+        .filter_map(|tick| tick.memory.map(|m| (tick.second, m)))
+        .collect();
+    if memory.is_empty() {
+        println!("\nMemory: no samples (is the sidecar running?)");
+        return;
+    }
+
+    println!("\nLatency and Memory (per second):");
+    println!("  {:>4} {:>7} {:>9} {:>9} {:>10} {:>10} {:>9}  {:<20}  live heap",
+        "sec", "req/s", "p50", "p99", "rss", "live heap", "allocs/s", "p99");
+
+    let max_p99 = timeline.iter().map(|t| t.p99).max().unwrap_or_default().as_secs_f64();
+    let max_live = memory.iter().map(|(_, m)| m.live_bytes).max().unwrap_or(0) as f64;
+    let mut previous_allocations: Option<u64> = None;
+    for tick in timeline {
+        let Some(m) = tick.memory else {
+            continue;
+        };
+        let allocs = previous_allocations.map(|prev| m.allocations.saturating_sub(prev));
+        previous_allocations = Some(m.allocations);
+        let line = format!("  {:>4} {:>7} {:>9} {:>9} {:>10} {:>10} {:>9}  {}  {}",
+            tick.second,
+            tick.requests,
+            format_duration(tick.p50),
+            format_duration(tick.p99),
+            m.rss_bytes.map(|b| format_bytes(b as f64)).unwrap_or_else(|| "-".to_string()),
+            format_bytes(m.live_bytes as f64),
+            allocs.map(|a| a.to_string()).unwrap_or_else(|| "-".to_string()),
+            bar(tick.p99.as_secs_f64(), max_p99, 20),
+            bar(m.live_bytes as f64, max_live, 20));
+        println!("{}", line.trim_end());
+    }
+
+    // Under a steady load a healthy server's heap levels off; a leak keeps
+    // climbing. Fit a line through the live-heap samples and look at the slope.
+    let points: Vec<(f64, f64)> = memory
+        .iter()
+        .map(|(second, m)| (*second as f64, m.live_bytes as f64))
+        .collect();
+    let slope = trend(&points);
+    let span = (memory.last().unwrap().0 - memory[0].0) as f64;
+    let growth = slope * span;
+    let start = memory[0].1.live_bytes as f64;
+    println!("\nLive heap trend: {}/s ({} over {}s)",
+        format_bytes(slope), format_bytes(growth), span);
+    if growth > (1024.0 * 1024.0_f64).max(start * 0.1) {
+        println!("  LIKELY LEAK: the heap keeps growing under a steady load");
+    } else {
+        println!("  Stable: no sign of a leak");
+    }
+}
+
+fn display_connections(keepalive: bool, port: u16, time_wait: Option<(usize, usize)>) {
+    println!("\nConnections ({}):", if keepalive { "keep-alive" } else { "one per request" });
+    let Some((before, peak)) = time_wait else {
+        println!("  TIME_WAIT: not available (no /proc/net/tcp)");
+        return;
+    };
+    println!("  TIME_WAIT to port {}: {} at peak ({} before the test)", port, peak, before);
+    if let Some(ports) = ephemeral_ports() {
+        println!("  Local port range: {} ports, {:.1}% tied up in TIME_WAIT",
+            ports, peak as f64 / ports as f64 * 100.0);
+    }
+}
+
+fn display_comparison(with: &Summary, without: &Summary) {
+    println!("\n{}", "=".repeat(50));
+    println!("KEEP-ALIVE vs ONE CONNECTION PER REQUEST");
+    println!("{}", "=".repeat(50));
+    println!("  {:<16} {:>14} {:>14}", "", "keep-alive", "per request");
+    println!("  {:<16} {:>14.1} {:>14.1}", "Requests/sec", with.rps, without.rps);
+    println!("  {:<16} {:>14} {:>14}", "p50", format_duration(with.p50), format_duration(without.p50));
+    println!("  {:<16} {:>14} {:>14}", "p99", format_duration(with.p99), format_duration(without.p99));
+    println!("  {:<16} {:>14} {:>14}", "Failed", with.failed, without.failed);
+    // The rise during the run: the first run's sockets are still there
+    let added = |s: &Summary| s.time_wait
+        .map(|(before, peak)| format!("+{}", peak.saturating_sub(before)))
+        .unwrap_or_else(|| "-".to_string());
+    println!("  {:<16} {:>14} {:>14}", "TIME_WAIT", added(with), added(without));
+
+    if without.rps > 0.0 {
+        println!("\nKeep-alive served {:.1}x the requests per second.", with.rps / without.rps);
+    }
+    // Every request without keep-alive pays a TCP handshake (one extra
+    // round trip) and leaves a socket in TIME_WAIT: at a few thousand
+    // connections a second the local port range runs out, and connect()
+    // fails with EADDRNOTAVAIL.
+    println!("Without it every request pays a TCP handshake and leaves a local port");
+    println!("in TIME_WAIT for 60s; enough of those and connect() runs out of ports.");
+    println!("{}", "=".repeat(50));
+}
+
 async fn display_results(stats: &Stats, total_duration: Duration) {
     let (successful, failed) = stats.get_counts();
     let total = successful + failed;
@@ -185,36 +425,60 @@ async fn display_results(stats: &Stats, total_duration: Duration) {
     println!("\n{}", "=".repeat(50));
 }
 
-#[tokio::main]
-async fn main() {
-    let args = Args::parse();
-
+async fn run(args: &Args, keepalive: bool) -> Summary {
     println!("{}", "=".repeat(50));
     println!("LOAD TEST CONFIGURATION");
     println!("{}", "=".repeat(50));
     println!("URL:         {}", args.url);
     println!("Concurrency: {} workers", args.concurrency);
     println!("Duration:    {} seconds", args.duration);
+    println!("Keep-alive:  {}", if keepalive { "on" } else { "off" });
+    if let Some(url) = &args.memory_url {
+        println!("Memory:      {}", url);
+    }
     println!("{}", "=".repeat(50));
     println!("\nRunning load test...\n");
 
-    // Create HTTP client with connection pooling
+    // Create HTTP client with connection pooling; with no idle connections
+    // allowed, each one is closed after its response
     let client = reqwest::Client::builder()
-        .pool_max_idle_per_host(args.concurrency)
+        .pool_max_idle_per_host(if keepalive { args.concurrency } else { 0 })
         .timeout(Duration::from_secs(30))
         .build()
         .expect("Failed to create HTTP client");
 
+    let port = target_port(&args.url).unwrap_or(80);
+    let time_wait_before = time_wait_count(port);
+
     let stats = Arc::new(Stats::new());
     let duration = Duration::from_secs(args.duration);
     let end_time = Instant::now() + duration;
 
-    // Progress indicator
+    // Progress indicator; also records one Tick per second
     let stats_clone = stats.clone();
+    let memory_client = client.clone();
+    let memory_url = args.memory_url.clone();
     let progress_handle = tokio::spawn(async move {
         let start = Instant::now();
+        let mut timeline = Vec::new();
         loop {
             tokio::time::sleep(Duration::from_secs(1)).await;
+
+            let mut window = stats_clone.take_window().await;
+            window.sort();
+            let memory = match &memory_url {
+                Some(url) => fetch_memory(&memory_client, url).await,
+                None => None,
+            };
+            timeline.push(Tick {
+                second: start.elapsed().as_secs(),
+                requests: window.len(),
+                p50: percentile(&window, 50.0),
+                p99: percentile(&window, 99.0),
+                memory,
+                time_wait: time_wait_count(port),
+            });
+
             if Instant::now() >= end_time {
                 break;
             }
@@ -226,6 +490,7 @@ async fn main() {
             std::io::stdout().flush().ok();
         }
         println!();
+        timeline
     });
 
     // Spawn workers
@@ -249,10 +514,49 @@ async fn main() {
     }
 
     // Wait for progress indicator to finish
-    let _ = progress_handle.await;
+    let timeline = progress_handle.await.unwrap_or_default();
 
     let total_duration = test_start.elapsed();
 
     // Display results
     display_results(&stats, total_duration).await;
+    if args.memory_url.is_some() {
+        display_timeline(&timeline);
+    }
+
+    let time_wait = time_wait_before.map(|before| {
+        let peak = timeline
+            .iter()
+            .filter_map(|tick| tick.time_wait)
+            .chain(time_wait_count(port))
+            .max()
+            .unwrap_or(before);
+        (before, peak)
+    });
+    display_connections(keepalive, port, time_wait);
+
+    let latencies = stats.latencies.lock().await;
+    Summary {
+        rps: stats.get_counts().0 as f64 / total_duration.as_secs_f64(),
+        p50: percentile(&latencies, 50.0),
+        p99: percentile(&latencies, 99.0),
+        failed: stats.get_counts().1,
+        time_wait,
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+
+    if !args.compare {
+        run(&args, !args.no_keepalive).await;
+        return;
+    }
+    // Keep-alive first: it leaves almost nothing in TIME_WAIT to skew the
+    // second run's count
+    let with = run(&args, true).await;
+    println!();
+    let without = run(&args, false).await;
+    display_comparison(&with, &without);
 }
@@ -0,0 +1,516 @@
+//! Benchmark regression gate
+//!
+//! Runs lab benchmarks, keeps the numbers as a JSON baseline, and on later
+//! runs exits non-zero when something got slower than the baseline allows:
+//!
+//! ```bash
+//! cargo build --release                       # bench_gate + target_server
+//! ./target/release/bench_gate                 # 1st run: writes bench_baseline.json
+//! # ... change some code ...
+//! ./target/release/bench_gate                 # compare, exit 1 on a regression
+//! ./target/release/bench_gate --bench http --threshold 10
+//! ./target/release/bench_gate --update        # accept the new numbers
+//! ```
+//!
+//! Benchmarks:
+//! - `parallel_sum`: chapter 1 lab 2, every `Time:` line
+//! - `locality`: chapter 2 lab 3, every `...sum: <duration>` line
+//! - `http`: this lab's target_server under concurrent load (req/s, p50, p99)
+//!
+//! Each benchmark runs `--runs` times and the median is kept, so one unlucky
+//! run does not fail the gate. A latency only counts as a regression when it
+//! is worse by more than `--threshold` percent AND by more than `--noise-us`:
+//! +40% on a 30µs thread spawn is scheduler noise, not a slowdown.
+//!
+//! Exit codes: 0 pass, 1 regression, 2 a benchmark or the baseline failed.
+
+use clap::{Parser, ValueEnum};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitCode, Stdio};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Connections kept busy by the http benchmark
+const CONCURRENCY: usize = 16;
+
+#[derive(Parser, Debug)]
+#[command(name = "bench_gate")]
+#[command(about = "Fail when lab benchmarks regress against a saved baseline")]
+struct Args {
+    /// Benchmarks to run (repeatable, default: all)
+    #[arg(long = "bench", value_enum)]
+    benches: Vec<Bench>,
+
+    /// Baseline file, created on the first run
+    #[arg(long, default_value = "bench_baseline.json")]
+    baseline: PathBuf,
+
+    /// Allowed regression in percent
+    #[arg(long, default_value = "15")]
+    threshold: f64,
+
+    /// Latency changes below this many microseconds are noise
+    #[arg(long, default_value = "100")]
+    noise_us: f64,
+
+    /// Runs per benchmark; the median is compared
+    #[arg(long, default_value = "3")]
+    runs: usize,
+
+    /// Seconds of load per run of the http benchmark
+    #[arg(long, default_value = "3")]
+    load_secs: u64,
+
+    /// Port for the http benchmark's target_server (metrics on port + 1)
+    #[arg(long, default_value = "3200")]
+    port: u16,
+
+    /// Save this run as the new baseline instead of failing
+    #[arg(long)]
+    update: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum Bench {
+    #[value(name = "parallel_sum")]
+    ParallelSum,
+    Locality,
+    Http,
+}
+
+impl Bench {
+    const ALL: [Bench; 3] = [Bench::ParallelSum, Bench::Locality, Bench::Http];
+
+    fn name(self) -> &'static str {
+        match self {
+            Bench::ParallelSum => "parallel_sum",
+            Bench::Locality => "locality",
+            Bench::Http => "http",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum Better {
+    Lower,
+    Higher,
+}
+
+/// One number from one benchmark; latencies are in microseconds
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct Metric {
+    bench: String,
+    name: String,
+    value: f64,
+    unit: String,
+    better: Better,
+}
+
+impl Metric {
+    fn latency(bench: Bench, name: &str, d: Duration) -> Self {
+        Self {
+            bench: bench.name().to_string(),
+            name: name.to_string(),
+            value: d.as_secs_f64() * 1e6,
+            unit: "us".to_string(),
+            better: Better::Lower,
+        }
+    }
+
+    fn key(&self) -> (String, String) {
+        (self.bench.clone(), self.name.clone())
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct Baseline {
+    metrics: Vec<Metric>,
+}
+
+// ============================================================
+// Lab benchmarks: run them, pick the durations out of stdout
+// ============================================================
+
+/// `335ns`, `87.347µs`, `7.6ms`, `1.2s` (Duration's Debug format)
+fn parse_duration(s: &str) -> Option<Duration> {
+    let split = s.find(|c: char| !(c.is_ascii_digit() || c == '.'))?;
+    let (number, unit) = s.split_at(split);
+    let value: f64 = number.parse().ok()?;
+    let secs = match unit {
+        "ns" => value / 1e9,
+        "µs" | "us" => value / 1e6,
+        "ms" => value / 1e3,
+        "s" => value,
+        _ => return None,
+    };
+    Some(Duration::from_secs_f64(secs))
+}
+
+/// Lines ending in a duration, labelled by the text before the first `|` or `:`
+///
+/// ```text
+/// Mutex (4 threads)  | Result: 5000000050000000 | Time: 79.873µs  -> "Mutex (4 threads)"
+/// Random sum:               77.410586ms                          -> "Random sum"
+/// ```
+fn timings(output: &str) -> Vec<(String, Duration)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let duration = parse_duration(line.split_whitespace().last()?)?;
+            let label = line.split(['|', ':']).next()?.trim();
+            (!label.is_empty()).then(|| (label.to_string(), duration))
+        })
+        .collect()
+}
+
+/// `cargo run --release` another lab of this repo and return its stdout
+fn run_lab(dir: &str) -> Result<String, String> {
+    let manifest = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../../..")
+        .join(dir)
+        .join("Cargo.toml");
+    let cargo = std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    let output = Command::new(cargo)
+        .args(["run", "--release", "--quiet", "--manifest-path"])
+        .arg(&manifest)
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| format!("cannot run cargo: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("{} exited with {}", dir, output.status));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn lab_metrics(bench: Bench, dir: &str) -> Result<Vec<Metric>, String> {
+    let found = timings(&run_lab(dir)?);
+    if found.is_empty() {
+        return Err(format!("{} printed no timings", dir));
+    }
+    Ok(found
+        .into_iter()
+        .map(|(name, d)| Metric::latency(bench, &name, d))
+        .collect())
+}
+
+// ============================================================
+// http: target_server under load
+// ============================================================
+
+/// Killed when dropped, so a failed run does not leave the port taken
+struct Server(Child);
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+fn start_target_server(port: u16) -> Result<Server, String> {
+    let exe = std::env::current_exe()
+        .map_err(|e| e.to_string())?
+        .with_file_name(format!("target_server{}", std::env::consts::EXE_SUFFIX));
+    if !exe.exists() {
+        return Err(format!(
+            "{} not found, build it first: cargo build --release",
+            exe.display()
+        ));
+    }
+    let server = Server(
+        Command::new(&exe)
+            .args(["--port", &port.to_string()])
+            .args(["--metrics-port", &port.saturating_add(1).to_string()])
+            .stdout(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("cannot start target_server: {}", e))?,
+    );
+
+    for _ in 0..50 {
+        if std::net::TcpStream::connect(("127.0.0.1", port)).is_ok() {
+            return Ok(server);
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    Err(format!("target_server did not listen on port {}", port))
+}
+
+/// One request on a fresh connection, the way the server expects it
+async fn request(port: u16) -> std::io::Result<Duration> {
+    let start = Instant::now();
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await?;
+    stream
+        .write_all(b"GET /items HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+    if !response.starts_with(b"HTTP/1.1 200") {
+        return Err(std::io::Error::other("unexpected response"));
+    }
+    Ok(start.elapsed())
+}
+
+async fn load(port: u16, duration: Duration) -> (Vec<Duration>, usize) {
+    let deadline = Instant::now() + duration;
+    let workers: Vec<_> = (0..CONCURRENCY)
+        .map(|_| {
+            tokio::spawn(async move {
+                let (mut latencies, mut errors) = (Vec::new(), 0);
+                while Instant::now() < deadline {
+                    match request(port).await {
+                        Ok(latency) => latencies.push(latency),
+                        Err(_) => errors += 1,
+                    }
+                }
+                (latencies, errors)
+            })
+        })
+        .collect();
+
+    let (mut latencies, mut errors) = (Vec::new(), 0);
+    for worker in workers {
+        let (l, e) = worker.await.unwrap_or_default();
+        latencies.extend(l);
+        errors += e;
+    }
+    (latencies, errors)
+}
+
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    let index = ((sorted.len() as f64) * p / 100.0) as usize;
+    sorted[index.min(sorted.len() - 1)]
+}
+
+fn http_metrics(port: u16, secs: u64) -> Result<Vec<Metric>, String> {
+    let _server = start_target_server(port)?;
+    let runtime = tokio::runtime::Runtime::new().map_err(|e| e.to_string())?;
+    let duration = Duration::from_secs(secs.max(1));
+    let (mut latencies, errors) = runtime.block_on(load(port, duration));
+    if latencies.is_empty() {
+        return Err(format!("every request failed ({} errors)", errors));
+    }
+    if errors > 0 {
+        eprintln!("http: {} requests failed", errors);
+    }
+    latencies.sort();
+
+    Ok(vec![
+        Metric {
+            bench: Bench::Http.name().to_string(),
+            name: "throughput".to_string(),
+            value: latencies.len() as f64 / duration.as_secs_f64(),
+            unit: "req/s".to_string(),
+            better: Better::Higher,
+        },
+        Metric::latency(Bench::Http, "p50", percentile(&latencies, 50.0)),
+        Metric::latency(Bench::Http, "p99", percentile(&latencies, 99.0)),
+    ])
+}
+
+fn run_once(bench: Bench, args: &Args) -> Result<Vec<Metric>, String> {
+    match bench {
+        Bench::ParallelSum => lab_metrics(
+            bench,
+            "chapter_01_foundation/01_rust_fundamentals/lab_02_parallel_sum",
+        ),
+        Bench::Locality => lab_metrics(bench, "chapter_02_os/02_memory/lab_03_locality"),
+        Bench::Http => http_metrics(args.port, args.load_secs),
+    }
+}
+
+/// Run a benchmark `runs` times and keep the median of every metric
+fn run_bench(bench: Bench, args: &Args) -> Result<Vec<Metric>, String> {
+    let mut samples: Vec<Metric> = Vec::new();
+    let mut values: BTreeMap<(String, String), Vec<f64>> = BTreeMap::new();
+    for run in 1..=args.runs.max(1) {
+        println!("Running {} ({}/{})...", bench.name(), run, args.runs.max(1));
+        for metric in run_once(bench, args).map_err(|e| format!("{}: {}", bench.name(), e))? {
+            let entry = values.entry(metric.key()).or_default();
+            if entry.is_empty() {
+                samples.push(metric.clone());
+            }
+            entry.push(metric.value);
+        }
+    }
+
+    for metric in &mut samples {
+        let runs = values.get_mut(&metric.key()).expect("collected above");
+        runs.sort_by(f64::total_cmp);
+        metric.value = runs[runs.len() / 2];
+    }
+    Ok(samples)
+}
+
+// ============================================================
+// Compare against the baseline
+// ============================================================
+
+#[derive(Debug, PartialEq)]
+enum Verdict {
+    Ok,
+    Improved,
+    Regressed,
+    New,
+}
+
+/// Percent change, positive = worse
+fn worse_by(baseline: &Metric, current: &Metric) -> f64 {
+    if baseline.value == 0.0 {
+        return 0.0;
+    }
+    let change = (current.value - baseline.value) / baseline.value * 100.0;
+    match current.better {
+        Better::Lower => change,
+        Better::Higher => -change,
+    }
+}
+
+fn verdict(baseline: Option<&Metric>, current: &Metric, args: &Args) -> Verdict {
+    let Some(baseline) = baseline else {
+        return Verdict::New;
+    };
+    let worse = worse_by(baseline, current);
+    let beyond_noise =
+        current.better == Better::Higher || (current.value - baseline.value).abs() > args.noise_us;
+    if worse > args.threshold && beyond_noise {
+        Verdict::Regressed
+    } else if worse < -args.threshold && beyond_noise {
+        Verdict::Improved
+    } else {
+        Verdict::Ok
+    }
+}
+
+fn format_value(metric: &Metric) -> String {
+    match metric.unit.as_str() {
+        "us" if metric.value >= 1e6 => format!("{:.2}s", metric.value / 1e6),
+        "us" if metric.value >= 1e3 => format!("{:.2}ms", metric.value / 1e3),
+        "us" => format!("{:.1}µs", metric.value),
+        unit => format!("{:.0} {}", metric.value, unit),
+    }
+}
+
+fn load_baseline(path: &Path) -> Result<Option<Baseline>, String> {
+    match std::fs::read_to_string(path) {
+        Ok(json) => serde_json::from_str(&json)
+            .map(Some)
+            .map_err(|e| format!("{} is not a baseline: {}", path.display(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("cannot read {}: {}", path.display(), e)),
+    }
+}
+
+fn save_baseline(path: &Path, baseline: &Baseline) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(baseline).expect("metrics always serialize");
+    std::fs::write(path, json + "\n").map_err(|e| format!("cannot write {}: {}", path.display(), e))
+}
+
+fn run(args: &Args) -> Result<bool, String> {
+    let benches = if args.benches.is_empty() {
+        Bench::ALL.to_vec()
+    } else {
+        args.benches.clone()
+    };
+
+    let mut current = Vec::new();
+    for bench in &benches {
+        current.extend(run_bench(*bench, args)?);
+    }
+
+    let Some(mut baseline) = load_baseline(&args.baseline)? else {
+        save_baseline(
+            &args.baseline,
+            &Baseline {
+                metrics: current.clone(),
+            },
+        )?;
+        println!();
+        println!(
+            "No baseline yet: saved {} metrics to {}. Run again to compare.",
+            current.len(),
+            args.baseline.display()
+        );
+        return Ok(true);
+    };
+
+    let previous: BTreeMap<_, _> = baseline.metrics.iter().map(|m| (m.key(), m)).collect();
+    println!();
+    println!(
+        "{:<14} {:<26} {:>12} {:>12} {:>9}",
+        "Benchmark", "Metric", "Baseline", "Current", "Change"
+    );
+    println!("{}", "=".repeat(78));
+
+    let mut regressions = 0;
+    for metric in &current {
+        let old = previous.get(&metric.key()).copied();
+        let verdict = verdict(old, metric, args);
+        let (base, change) = match old {
+            Some(old) if old.value != 0.0 => (
+                format_value(old),
+                format!("{:+.1}%", (metric.value - old.value) / old.value * 100.0),
+            ),
+            Some(old) => (format_value(old), "-".to_string()),
+            None => ("-".to_string(), "-".to_string()),
+        };
+        let flag = match verdict {
+            Verdict::Ok => "",
+            Verdict::Improved => "improved",
+            Verdict::Regressed => "REGRESSED",
+            Verdict::New => "new",
+        };
+        let line = format!(
+            "{:<14} {:<26} {:>12} {:>12} {:>9}  {}",
+            metric.bench,
+            metric.name,
+            base,
+            format_value(metric),
+            change,
+            flag
+        );
+        println!("{}", line.trim_end());
+        if verdict == Verdict::Regressed {
+            regressions += 1;
+        }
+    }
+    println!("{}", "=".repeat(78));
+
+    if args.update {
+        // Keep the numbers of benchmarks that were not run this time
+        let ran: Vec<&str> = benches.iter().map(|b| b.name()).collect();
+        baseline
+            .metrics
+            .retain(|m| !ran.contains(&m.bench.as_str()));
+        baseline.metrics.extend(current);
+        save_baseline(&args.baseline, &baseline)?;
+        println!("Baseline updated: {}", args.baseline.display());
+        return Ok(true);
+    }
+
+    if regressions > 0 {
+        println!(
+            "FAIL: {} metric(s) regressed more than {}%",
+            regressions, args.threshold
+        );
+        Ok(false)
+    } else {
+        println!("PASS: no metric regressed more than {}%", args.threshold);
+        Ok(true)
+    }
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    match run(&args) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(1),
+        Err(e) => {
+            eprintln!("bench_gate: {}", e);
+            ExitCode::from(2)
+        }
+    }
+}
@@ -13,6 +13,11 @@
 //!    - Requests per second (throughput)
 //!    - Latency percentiles (p50, p95, p99)
 //!    - Min/max/average latency
+//! 5. Memory (`--memory-url`): poll the target server's memory sidecar
+//!    every second, print it next to per-second latency, flag a growing heap
+//! 6. Keep-alive (`--no-keepalive`, `--compare`): open a new connection per
+//!    request instead of reusing them; `--compare` runs both ways and shows
+//!    throughput, latency and TIME_WAIT sockets side by side
 //!
 //! ## Usage
 //! ```bash
@@ -24,6 +29,16 @@
 //!   --url http://localhost:3000/items \
 //!   --concurrency 50 \
 //!   --duration 10
+//!
+//! # Leak hunting: /leak keeps 10 KB per request, the sidecar is on port 3001
+//! cargo run --bin load_tester -- \
+//!   --url http://localhost:3000/leak \
+//!   --memory-url http://localhost:3001/memory
+//!
+//! # What does connection reuse buy? Keep-alive, then one connection per request
+//! cargo run --bin load_tester -- \
+//!   --url http://localhost:3000/items \
+//!   --compare
 //! ```
 //!
 //! ## Hints
@@ -31,6 +46,13 @@
 //! - Use `Instant::now()` and `elapsed()` for timing
 //! - Use `AtomicU64` for thread-safe counters
 //! - Sort latencies to calculate percentiles
+//! - Memory: `client.get(url).send().await?.json::<MemorySample>().await`;
+//!   a least-squares slope over (second, live_bytes) tells growth per second
+//! - Keep-alive: `pool_max_idle_per_host(0)` turns reuse off. Read the body
+//!   (`resp.bytes().await`) either way: a connection only goes back to the
+//!   pool once its response has been read
+//! - TIME_WAIT: lines of `/proc/net/tcp` (and `tcp6`) with state `06`; the
+//!   third column is the remote `HEXIP:HEXPORT`
 //!
 //! ## Acceptance Criteria
 //! - [ ] CLI accepts url, concurrency, duration arguments
@@ -39,6 +61,8 @@
 //! - [ ] Displays throughput (req/sec)
 //! - [ ] Displays latency percentiles
 //! - [ ] Handles errors gracefully
+//! - [ ] With `--memory-url`, `/leak` is reported as a leak and `/items` is not
+//! - [ ] `--compare` shows keep-alive ahead, and TIME_WAIT piling up without it
 //!
 //! Check solution/main.rs after completing
 
@@ -63,6 +87,26 @@ struct Args {
     /// Test duration in seconds
     #[arg(short, long, default_value = "10")]
     duration: u64,
+
+    /// Memory sidecar of the target server, e.g. http://localhost:3001/memory
+    #[arg(short, long)]
+    memory_url: Option<String>,
+
+    /// A new connection for every request instead of reusing them
+    #[arg(long)]
+    no_keepalive: bool,
+
+    /// Run with keep-alive, then without, and compare the two
+    #[arg(long, conflicts_with = "no_keepalive")]
+    compare: bool,
+}
+
+/// What the target server's sidecar reports (GET /memory)
+#[derive(serde::Deserialize, Clone, Copy)]
+struct MemorySample {
+    rss_bytes: Option<u64>,
+    live_bytes: u64,
+    allocations: u64,
 }
 
 struct Stats {
@@ -107,6 +151,7 @@ async fn worker(
     // while Instant::now() < end_time {
     //     let start = Instant::now();
     //     let result = client.get(&url).send().await;
+    //     // (read the body too, or the connection is never reused)
     //     let latency = start.elapsed();
     //
     //     match result {
@@ -129,6 +174,28 @@ fn percentile(sorted: &[Duration], p: f64) -> Duration {
     todo!()
 }
 
+// TODO: Fetch one sample from the memory sidecar (None if it can't be reached)
+async fn fetch_memory(client: &reqwest::Client, url: &str) -> Option<MemorySample> {
+    todo!()
+}
+
+// TODO: Least-squares slope of (x, y) points, e.g. live heap bytes per second
+//
+// slope = sum((x - mean_x) * (y - mean_y)) / sum((x - mean_x)^2)
+fn trend(points: &[(f64, f64)]) -> f64 {
+    todo!()
+}
+
+// TODO: Count sockets in TIME_WAIT whose remote port is `port` (the client
+// side of connections we closed), from /proc/net/tcp and /proc/net/tcp6.
+// None if those files don't exist.
+//
+// Each line: sl local_address rem_address st ...
+//            0: 0100007F:A1B2 0100007F:0BB8 06 ...   (06 = TIME_WAIT)
+fn time_wait_count(port: u16) -> Option<usize> {
+    todo!()
+}
+
 // TODO: Implement results display
 fn display_results(stats: &Stats, total_duration: Duration) {
     // TODO: Calculate and print:
@@ -156,6 +223,8 @@ async fn main() {
     // 4. Spawn worker tasks
     // 5. Wait for all workers
     // 6. Display results
+    // 7. --compare: do it all twice, keep-alive on then off, and print the
+    //    two runs side by side (req/s, p50, p99, TIME_WAIT peak)
 
     todo!()
 }
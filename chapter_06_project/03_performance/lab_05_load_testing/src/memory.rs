@@ -0,0 +1,77 @@
+//! Memory self-reporting for the target server
+//!
+//! - A counting global allocator tracks allocations and live heap bytes
+//! - RSS comes from `/proc/self/status` (Linux only; `null` elsewhere)
+//! - Once a second the server takes a `Sample`; the sidecar endpoint
+//!   (`GET /memory` on `--metrics-port`) returns the latest one as JSON
+//!
+//! Live bytes that keep rising under a steady load are a leak; RSS rises
+//! too but lags behind, because the allocator keeps freed pages around.
+
+use serde::Serialize;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static LIVE_BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// The system allocator plus two counters
+pub struct CountingAlloc;
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            LIVE_BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        LIVE_BYTES.fetch_sub(layout.size() as u64, Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new = System.realloc(ptr, layout, new_size);
+        if !new.is_null() {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            LIVE_BYTES.fetch_add(new_size as u64, Ordering::Relaxed);
+            LIVE_BYTES.fetch_sub(layout.size() as u64, Ordering::Relaxed);
+        }
+        new
+    }
+}
+
+/// One reading, as served by the sidecar
+#[derive(Clone, Serialize)]
+pub struct Sample {
+    pub uptime_secs: u64,
+    /// Resident set size: pages of this process in RAM
+    pub rss_bytes: Option<u64>,
+    /// Heap bytes allocated and not yet freed
+    pub live_bytes: u64,
+    /// Allocations since start (a counter, never goes down)
+    pub allocations: u64,
+}
+
+impl Sample {
+    pub fn take(started: Instant) -> Self {
+        Self {
+            uptime_secs: started.elapsed().as_secs(),
+            rss_bytes: rss_bytes(),
+            live_bytes: LIVE_BYTES.load(Ordering::Relaxed),
+            allocations: ALLOCATIONS.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// `VmRSS:  12345 kB` from /proc/self/status
+fn rss_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
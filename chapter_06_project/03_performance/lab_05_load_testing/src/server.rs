@@ -1,42 +1,191 @@
 //! Simple target server for load testing
 //!
 //! Run with: cargo run --bin target_server
+//!
+//! Profile it while a load test runs, then press Ctrl-C to get a flamegraph:
+//!
+//! ```bash
+//! cargo run --release --bin target_server -- --profile
+//! cargo run --release --bin load_tester -- --url http://localhost:3000/items   # other terminal
+//! # Ctrl-C in the server terminal:
+//! # Flamegraph: flamegraph.svg (412 samples)
+//! ```
+//!
+//! Connections are kept alive (HTTP/1.1 default) until the client closes or
+//! sends `Connection: close`, so the load tester's `--no-keepalive` has
+//! something to compare against.
+//!
+//! It also reports its own memory once a second on a sidecar port
+//! (`--metrics-port`, default port + 1), see `src/memory.rs`:
+//!
+//! ```bash
+//! curl http://localhost:3001/memory
+//! # {"uptime_secs":42,"rss_bytes":6291456,"live_bytes":81920,"allocations":12873}
+//! ```
+
+mod memory;
+// path_from_args goes unused: clap parses --profile here
+#[path = "../../../../shared/profiling.rs"]
+#[allow(dead_code)]
+mod profiling;
 
+use clap::Parser;
+use memory::{CountingAlloc, Sample};
 use std::net::SocketAddr;
-use std::time::Duration;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpListener;
 
-const RESPONSE: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 47\r\n\r\n{\"items\":[{\"id\":1,\"name\":\"Widget\"}],\"count\":1}";
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let addr: SocketAddr = "0.0.0.0:3000".parse()?;
-    let listener = TcpListener::bind(addr).await?;
+/// What GET /leak holds on to per request
+const LEAK_BYTES: usize = 10 * 1024;
 
-    println!("Target server running on http://{}", addr);
-    println!("Endpoints:");
-    println!("  GET /items - Returns JSON (fast)");
-    println!("  GET /slow  - Returns JSON after 100ms delay");
-    println!();
+/// Memory that GET /leak never gives back
+static LEAKED: Mutex<Vec<Vec<u8>>> = Mutex::new(Vec::new());
 
+const RESPONSE: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 46\r\n\r\n{\"items\":[{\"id\":1,\"name\":\"Widget\"}],\"count\":1}";
+
+#[derive(Parser, Debug)]
+#[command(name = "target_server")]
+#[command(about = "Target server for load testing")]
+struct Args {
+    /// Port to listen on
+    #[arg(short, long, default_value = "3000")]
+    port: u16,
+
+    /// Port for the memory sidecar (default: port + 1)
+    #[arg(long)]
+    metrics_port: Option<u16>,
+
+    /// Sample CPU until Ctrl-C, then write a flamegraph SVG
+    #[arg(long, value_name = "PATH", num_args = 0..=1, default_missing_value = "flamegraph.svg")]
+    profile: Option<PathBuf>,
+}
+
+/// HTTP/1.1 keeps the connection unless told `Connection: close`;
+/// HTTP/1.0 closes it unless told `Connection: keep-alive`
+fn keep_alive(request: &str) -> bool {
+    let connection = request
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("connection"))
+        .map(|(_, value)| value.trim().to_ascii_lowercase());
+    match connection.as_deref() {
+        Some("close") => false,
+        Some("keep-alive") => true,
+        _ => !request
+            .lines()
+            .next()
+            .is_some_and(|line| line.trim_end().ends_with("HTTP/1.0")),
+    }
+}
+
+async fn serve(listener: TcpListener) -> std::io::Result<()> {
     loop {
         let (mut socket, _) = listener.accept().await?;
 
+        // Keep-alive: one request per read, until the client closes or
+        // asks us to
         tokio::spawn(async move {
             let mut buf = [0u8; 1024];
-            if let Ok(n) = socket.read(&mut buf).await {
-                if n > 0 {
-                    let request = String::from_utf8_lossy(&buf[..n]);
+            while let Ok(n) = socket.read(&mut buf).await {
+                if n == 0 {
+                    break;
+                }
+                let request = String::from_utf8_lossy(&buf[..n]);
 
-                    // Check for /slow endpoint
-                    if request.contains("GET /slow") {
-                        tokio::time::sleep(Duration::from_millis(100)).await;
-                    }
+                // Check for /slow endpoint
+                if request.contains("GET /slow") {
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+                // A bug on purpose: every call keeps 10 KB forever
+                if request.contains("GET /leak") {
+                    LEAKED.lock().unwrap().push(vec![1u8; LEAK_BYTES]);
+                }
 
-                    let _ = socket.write_all(RESPONSE).await;
+                if socket.write_all(RESPONSE).await.is_err() || !keep_alive(&request) {
+                    break;
                 }
             }
         });
     }
 }
+
+/// Take a sample every second; the sidecar serves the latest one
+async fn sample_memory(latest: Arc<Mutex<Sample>>, started: Instant) {
+    let mut ticks = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        ticks.tick().await;
+        *latest.lock().unwrap() = Sample::take(started);
+    }
+}
+
+/// GET /memory on the sidecar port: the latest sample as JSON
+async fn serve_memory(listener: TcpListener, latest: Arc<Mutex<Sample>>) -> std::io::Result<()> {
+    loop {
+        let (mut socket, _) = listener.accept().await?;
+        let body = serde_json::to_string(&*latest.lock().unwrap()).unwrap();
+
+        tokio::spawn(async move {
+            let mut buf = [0u8; 1024];
+            if let Ok(n) = socket.read(&mut buf).await {
+                let response = if buf[..n].starts_with(b"GET /memory ") {
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                } else {
+                    "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_string()
+                };
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+    let addr: SocketAddr = ([0, 0, 0, 0], args.port).into();
+    let listener = TcpListener::bind(addr).await?;
+    let metrics_port = args.metrics_port.unwrap_or(args.port.saturating_add(1));
+    let metrics_addr: SocketAddr = ([0, 0, 0, 0], metrics_port).into();
+    let metrics_listener = TcpListener::bind(metrics_addr).await?;
+
+    println!("Target server running on http://{}", addr);
+    println!("Endpoints:");
+    println!("  GET /items - Returns JSON (fast)");
+    println!("  GET /slow  - Returns JSON after 100ms delay");
+    println!("  GET /leak  - Returns JSON, keeps 10 KB forever (leak on purpose)");
+    println!(
+        "Memory: http://{}/memory (updated every second)",
+        metrics_addr
+    );
+    println!();
+
+    let started = Instant::now();
+    let latest = Arc::new(Mutex::new(Sample::take(started)));
+    tokio::spawn(sample_memory(latest.clone(), started));
+    tokio::spawn(async move {
+        if let Err(e) = serve_memory(metrics_listener, latest).await {
+            eprintln!("memory sidecar stopped: {}", e);
+        }
+    });
+
+    let Some(path) = args.profile else {
+        return Ok(serve(listener).await?);
+    };
+
+    let profiler = profiling::Profiler::start(path);
+    println!("Profiling, press Ctrl-C to stop and write the flamegraph");
+    tokio::select! {
+        result = serve(listener) => result?,
+        _ = tokio::signal::ctrl_c() => profiler.finish(),
+    }
+    Ok(())
+}
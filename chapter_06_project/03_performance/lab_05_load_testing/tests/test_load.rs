@@ -109,3 +109,356 @@ fn test_throughput_calculation() {
     let rps = successful as f64 / duration.as_secs_f64();
     assert_eq!(rps, 100.0);
 }
+
+#[cfg(unix)]
+#[test]
+fn test_target_server_profile_writes_flamegraph() {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::process::{Command, Stdio};
+    use std::time::Instant;
+
+    let path = std::env::temp_dir().join(format!("target_server_{}.svg", std::process::id()));
+    let server = Command::new(env!("CARGO_BIN_EXE_target_server"))
+        .args(["--port", "3101", "--profile"])
+        .arg(&path)
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start target_server");
+    std::thread::sleep(Duration::from_millis(500));
+
+    // Give the profiler some work to sample
+    let start = Instant::now();
+    let mut requests = 0;
+    while start.elapsed() < Duration::from_secs(2) {
+        let mut stream = TcpStream::connect("127.0.0.1:3101").expect("connect");
+        stream
+            .write_all(b"GET /items HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = [0u8; 256];
+        let n = stream.read(&mut response).unwrap();
+        assert!(response[..n].starts_with(b"HTTP/1.1 200"));
+        requests += 1;
+    }
+    assert!(requests > 0);
+
+    // Ctrl-C stops the server and writes the flamegraph
+    let status = Command::new("kill")
+        .args(["-INT", &server.id().to_string()])
+        .status()
+        .expect("Failed to run kill");
+    assert!(status.success());
+    let output = server.wait_with_output().expect("server did not exit");
+    assert!(output.status.success(), "Exit status: {}", output.status);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Flamegraph:"),
+        "No flamegraph reported: {}",
+        stdout
+    );
+    let svg = std::fs::read_to_string(&path).expect("flamegraph file should exist");
+    let _ = std::fs::remove_file(&path);
+    assert!(svg.contains("<svg"), "Flamegraph should be an SVG");
+    assert!(
+        svg.contains("target_server::serve"),
+        "Accept loop missing from flamegraph"
+    );
+}
+
+#[test]
+fn test_trend_slope() {
+    fn trend(points: &[(f64, f64)]) -> f64 {
+        let n = points.len() as f64;
+        if points.len() < 2 {
+            return 0.0;
+        }
+        let mean_x = points.iter().map(|p| p.0).sum::<f64>() / n;
+        let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n;
+        let covariance: f64 = points.iter().map(|p| (p.0 - mean_x) * (p.1 - mean_y)).sum();
+        let variance: f64 = points.iter().map(|p| (p.0 - mean_x).powi(2)).sum();
+        if variance == 0.0 {
+            0.0
+        } else {
+            covariance / variance
+        }
+    }
+
+    // A leak: +1 MB every second, with some noise
+    let leaking: Vec<(f64, f64)> = (0..10)
+        .map(|s| {
+            (
+                s as f64,
+                s as f64 * 1e6 + if s % 2 == 0 { 5e4 } else { -5e4 },
+            )
+        })
+        .collect();
+    assert!((trend(&leaking) - 1e6).abs() < 2e4);
+
+    // Flat heap: no growth
+    let flat: Vec<(f64, f64)> = (0..10).map(|s| (s as f64, 8e6)).collect();
+    assert_eq!(trend(&flat), 0.0);
+
+    // Too few points to say anything
+    assert_eq!(trend(&[(1.0, 5.0)]), 0.0);
+    assert_eq!(trend(&[]), 0.0);
+}
+
+#[test]
+fn test_target_server_reports_memory() {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::process::{Command, Stdio};
+
+    struct Server(std::process::Child);
+    impl Drop for Server {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+        }
+    }
+
+    fn get(port: u16, path: &str) -> String {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).expect("connect");
+        // One write: the server only reads the first packet
+        let request = format!(
+            "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+            path
+        );
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    fn memory(port: u16) -> serde_json::Value {
+        let response = get(port, "/memory");
+        assert!(
+            response.starts_with("HTTP/1.1 200"),
+            "Sidecar: {}",
+            response
+        );
+        let body = response.split_once("\r\n\r\n").unwrap().1;
+        serde_json::from_str(body).expect("sidecar should answer JSON")
+    }
+
+    let _server = Server(
+        Command::new(env!("CARGO_BIN_EXE_target_server"))
+            .args(["--port", "3103", "--metrics-port", "3104"])
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Failed to start target_server"),
+    );
+    std::thread::sleep(Duration::from_millis(1500));
+
+    let before = memory(3104);
+    assert!(before["allocations"].as_u64().unwrap() > 0);
+    if cfg!(target_os = "linux") {
+        assert!(before["rss_bytes"].as_u64().unwrap() > 0);
+    }
+
+    // 300 requests to /leak keep about 3 MB
+    for _ in 0..300 {
+        assert!(get(3103, "/leak").starts_with("HTTP/1.1 200"));
+    }
+    std::thread::sleep(Duration::from_millis(1500));
+
+    let after = memory(3104);
+    let grown = after["live_bytes"].as_u64().unwrap() - before["live_bytes"].as_u64().unwrap();
+    assert!(
+        grown >= 300 * 10 * 1024,
+        "Live heap grew by only {} bytes",
+        grown
+    );
+    assert!(after["uptime_secs"].as_u64() > before["uptime_secs"].as_u64());
+
+    assert!(get(3104, "/other").starts_with("HTTP/1.1 404"));
+}
+
+#[test]
+fn test_bench_gate_parses_lab_timings() {
+    fn parse_duration(s: &str) -> Option<Duration> {
+        let split = s.find(|c: char| !(c.is_ascii_digit() || c == '.'))?;
+        let (number, unit) = s.split_at(split);
+        let value: f64 = number.parse().ok()?;
+        let secs = match unit {
+            "ns" => value / 1e9,
+            "µs" | "us" => value / 1e6,
+            "ms" => value / 1e3,
+            "s" => value,
+            _ => return None,
+        };
+        Some(Duration::from_secs_f64(secs))
+    }
+
+    assert_eq!(parse_duration("335ns"), Some(Duration::from_nanos(335)));
+    assert_eq!(parse_duration("87.5µs"), Some(Duration::from_nanos(87_500)));
+    assert_eq!(parse_duration("7.25ms"), Some(Duration::from_micros(7_250)));
+    assert_eq!(parse_duration("2s"), Some(Duration::from_secs(2)));
+    // Result lines end in plain numbers, which are not timings
+    assert_eq!(parse_duration("5000000050000000"), None);
+    assert_eq!(parse_duration("MB)"), None);
+}
+
+#[test]
+fn test_bench_gate_fails_on_regression() {
+    use std::process::Command;
+
+    fn gate(baseline: &std::path::Path) -> (bool, Option<i32>, String) {
+        let output = Command::new(env!("CARGO_BIN_EXE_bench_gate"))
+            .args(["--bench", "http", "--runs", "1", "--load-secs", "1"])
+            .args(["--port", "3105", "--baseline"])
+            .arg(baseline)
+            .output()
+            .expect("Failed to run bench_gate");
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        (output.status.success(), output.status.code(), stdout)
+    }
+
+    /// Rewrite the baseline, scaling throughput and latencies
+    fn scale(baseline: &std::path::Path, throughput: f64, latency: f64) {
+        let json = std::fs::read_to_string(baseline).unwrap();
+        let mut saved: serde_json::Value = serde_json::from_str(&json).unwrap();
+        for metric in saved["metrics"].as_array_mut().unwrap() {
+            let factor = if metric["better"] == "higher" {
+                throughput
+            } else {
+                latency
+            };
+            metric["value"] = (metric["value"].as_f64().unwrap() * factor).into();
+        }
+        std::fs::write(baseline, saved.to_string()).unwrap();
+    }
+
+    let baseline = std::env::temp_dir().join(format!("bench_gate_{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&baseline);
+
+    // First run: nothing to compare, the numbers become the baseline
+    let (ok, _, stdout) = gate(&baseline);
+    assert!(ok, "First run should pass: {}", stdout);
+    assert!(stdout.contains("No baseline yet"), "{}", stdout);
+    let json = std::fs::read_to_string(&baseline).expect("baseline should be saved");
+    for name in ["throughput", "p50", "p99"] {
+        assert!(json.contains(name), "{} missing from baseline", name);
+    }
+
+    // Baseline 100x faster than this machine can go: a regression, exit 1
+    scale(&baseline, 100.0, 1.0);
+    let (ok, code, stdout) = gate(&baseline);
+    assert!(!ok && code == Some(1), "Should fail: {}", stdout);
+    assert!(
+        stdout.contains("REGRESSED") && stdout.contains("FAIL"),
+        "{}",
+        stdout
+    );
+
+    // Baseline far slower: improvements never fail the gate
+    scale(&baseline, 1e-4, 1e3);
+    let (ok, _, stdout) = gate(&baseline);
+    let _ = std::fs::remove_file(&baseline);
+    assert!(ok, "Should pass: {}", stdout);
+    assert!(
+        stdout.contains("improved") && stdout.contains("PASS"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn test_time_wait_count_parses_proc_net_tcp() {
+    fn time_wait_count(table: &str, port: u16) -> usize {
+        table
+            .lines()
+            .skip(1)
+            .map(|line| line.split_whitespace().collect::<Vec<_>>())
+            .filter(|fields| fields.len() > 3 && fields[3] == "06")
+            .filter(|fields| {
+                fields[2]
+                    .rsplit(':')
+                    .next()
+                    .and_then(|hex| u16::from_str_radix(hex, 16).ok())
+                    == Some(port)
+            })
+            .count()
+    }
+
+    // 0x0BB8 = 3000. Two client sockets in TIME_WAIT towards :3000, one
+    // established, one TIME_WAIT on the server side (local :3000), one
+    // towards another port
+    let table = "\
+  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 0100007F:C350 0100007F:0BB8 06 00000000:00000000 03:00001770 00000000     0        0 0
+   1: 0100007F:C351 0100007F:0BB8 06 00000000:00000000 03:00001770 00000000     0        0 0
+   2: 0100007F:C352 0100007F:0BB8 01 00000000:00000000 00:00000000 00000000  1000        0 1
+   3: 0100007F:0BB8 0100007F:C353 06 00000000:00000000 03:00001770 00000000     0        0 0
+   4: 0100007F:C354 0100007F:1F90 06 00000000:00000000 03:00001770 00000000     0        0 0
+";
+    assert_eq!(time_wait_count(table, 3000), 2);
+    assert_eq!(time_wait_count(table, 8080), 1);
+    assert_eq!(time_wait_count(table, 443), 0);
+}
+
+#[test]
+fn test_target_server_keeps_connections_alive() {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::process::{Command, Stdio};
+
+    struct Server(std::process::Child);
+    impl Drop for Server {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+        }
+    }
+
+    let _server = Server(
+        Command::new(env!("CARGO_BIN_EXE_target_server"))
+            .args(["--port", "3106", "--metrics-port", "3107"])
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Failed to start target_server"),
+    );
+    std::thread::sleep(Duration::from_millis(500));
+
+    let mut stream = TcpStream::connect("127.0.0.1:3106").expect("connect");
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    /// Send one request and read exactly one response off the stream
+    fn exchange(stream: &mut TcpStream, headers: &str) -> String {
+        let request = format!("GET /items HTTP/1.1\r\nHost: localhost\r\n{}\r\n", headers);
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = Vec::new();
+        let mut buf = [0u8; 256];
+        // Headers, then Content-Length bytes of body
+        loop {
+            let n = stream.read(&mut buf).expect("response on the same connection");
+            assert!(n > 0, "Server closed a keep-alive connection");
+            response.extend_from_slice(&buf[..n]);
+            let text = String::from_utf8_lossy(&response).to_string();
+            if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                let length: usize = head
+                    .lines()
+                    .find_map(|line| line.strip_prefix("Content-Length: "))
+                    .expect("Content-Length")
+                    .parse()
+                    .unwrap();
+                if body.len() >= length {
+                    assert_eq!(body.len(), length, "Body longer than Content-Length");
+                    return text;
+                }
+            }
+        }
+    }
+
+    // Several requests, one connection
+    for _ in 0..3 {
+        assert!(exchange(&mut stream, "").starts_with("HTTP/1.1 200"));
+    }
+
+    // Connection: close gets one more response, then EOF
+    assert!(exchange(&mut stream, "Connection: close\r\n").starts_with("HTTP/1.1 200"));
+    let mut rest = Vec::new();
+    stream.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+}
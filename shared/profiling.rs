@@ -0,0 +1,68 @@
+//! CPU profiling with pprof, shared by the labs with a `--profile [PATH]`
+//! flag: parallel_sum, locality and the load-testing target server
+//!
+//! `Profiler::start` samples every thread until `finish`, which writes a
+//! flamegraph SVG, `flamegraph.svg` by default. Open it in a browser: each box
+//! is a function, its width is the share of samples where it was on the stack.
+//!
+//! Each lab includes this file with `#[path]`, and its Cargo.toml sets
+//! `debug = true` under `[profile.release]`: debug info lets the flamegraph
+//! name inlined functions, and the optimized code is unchanged.
+
+use std::fs::File;
+use std::path::PathBuf;
+
+/// Samples per second
+const FREQUENCY: i32 = 999;
+
+pub struct Profiler {
+    guard: pprof::ProfilerGuard<'static>,
+    path: PathBuf,
+}
+
+impl Profiler {
+    pub fn start(path: PathBuf) -> Self {
+        let guard = pprof::ProfilerGuardBuilder::default()
+            .frequency(FREQUENCY)
+            // Frames from these libraries only add noise at the bottom of the stacks
+            .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+            .build()
+            .expect("failed to start profiler");
+        Self { guard, path }
+    }
+
+    /// Stop sampling and write the flamegraph
+    pub fn finish(self) {
+        let report = match self.guard.report().build() {
+            Ok(report) => report,
+            Err(e) => {
+                eprintln!("profile: failed to build report: {}", e);
+                return;
+            }
+        };
+
+        let samples: isize = report.data.values().sum();
+        if samples == 0 {
+            eprintln!("profile: no samples, the profiled run was too short");
+            return;
+        }
+
+        match File::create(&self.path).map(|file| report.flamegraph(file)) {
+            Ok(Ok(())) => println!("Flamegraph: {} ({} samples)", self.path.display(), samples),
+            Ok(Err(e)) => eprintln!("profile: failed to write flamegraph: {}", e),
+            Err(e) => eprintln!("profile: cannot create {}: {}", self.path.display(), e),
+        }
+    }
+}
+
+/// `--profile [PATH]` from the command line
+pub fn path_from_args() -> Option<PathBuf> {
+    let mut args = std::env::args().skip(1).peekable();
+    while let Some(arg) = args.next() {
+        if arg == "--profile" {
+            let path = args.next_if(|next| !next.starts_with("--"));
+            return Some(path.unwrap_or_else(|| "flamegraph.svg".to_string()).into());
+        }
+    }
+    None
+}
@@ -0,0 +1,55 @@
+//! Seeded random numbers for the labs that simulate a bad network: the UDP
+//! echo server's impairments, delivery_semantics' lossy channel and the
+//! quorum cluster
+//!
+//! The point is replay, not quality: the same seed gives the same drops,
+//! duplicates and delays, so a run that broke something can be repeated
+//! until it is understood. splitmix64 does that in a few lines without a
+//! crate, and its output is plenty random for a coin flip per packet.
+//!
+//! Each lab includes this file with `#[path]` and uses only some of the
+//! methods, so its `mod` line allows dead code.
+
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in [0, 1)
+    pub fn unit(&mut self) -> f64 {
+        // Top 53 bits -> uniform f64
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// True with probability `p`
+    pub fn chance(&mut self, p: f64) -> bool {
+        self.unit() < p
+    }
+
+    /// Uniform in 1..=max
+    pub fn between_1_and(&mut self, max: u64) -> u64 {
+        1 + self.next_u64() % max
+    }
+
+    /// Uniform in 0..n
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// Fisher-Yates
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.below(i + 1));
+        }
+    }
+}